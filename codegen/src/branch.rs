@@ -7,7 +7,7 @@
 ///
 use crate::context::*;
 use go_parser::ast::*;
-use go_parser::{AssignStmtKey, Map, Token};
+use go_parser::{AssignStmtKey, Map, Parser, Token};
 use go_types::ObjKey as TCObjKey;
 use go_vm::types::*;
 
@@ -329,8 +329,9 @@ impl SelectHelper {
         }
     }
 
+    /// The channel and position of a receive, which can be parenthesized, e.g. `case (<-ch):`
     pub fn unwrap_recv(e: &Expr) -> (&Expr, usize) {
-        match e {
+        match Parser::unparen(e) {
             Expr::Unary(ue) => {
                 assert_eq!(ue.op, Token::ARROW);
                 (&ue.expr, ue.op_pos)
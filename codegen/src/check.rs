@@ -0,0 +1,220 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Cross-checks the ValueTypes of the instructions against the operands they are
+//! emitted with, so that an instruction with the wrong type is reported where it's
+//! generated rather than misinterpreted by the VM far away.
+//! Only the operands of known types are checked: the locals with zero values,
+//! whose type never changes, and the constants.
+
+use super::consts::Consts;
+use super::context::{Addr, InterInst};
+use go_parser::Map;
+use go_vm::types::{Opcode, ValueType};
+
+pub(crate) struct EmitChecker {
+    /// The function being generated, for the diagnostics
+    name: String,
+    local_types: Map<usize, ValueType>,
+    errors: Vec<(Option<usize>, String)>,
+    /// Panic on the first mismatch instead of recording it
+    pub panic_on_error: bool,
+}
+
+impl EmitChecker {
+    pub fn new() -> EmitChecker {
+        EmitChecker {
+            name: "<anonymous>".to_owned(),
+            local_types: Map::new(),
+            errors: vec![],
+            panic_on_error: cfg!(debug_assertions),
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_owned();
+    }
+
+    pub fn add_local(&mut self, index: usize, typ: ValueType) {
+        self.local_types.insert(index, typ);
+    }
+
+    /// The mismatches found so far, with the positions of the instructions
+    pub fn take_errors(&mut self) -> Vec<(Option<usize>, String)> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn operand_type(&self, addr: Addr, consts: &Consts) -> Option<ValueType> {
+        match addr {
+            Addr::LocalVar(i) => self.local_types.get(&i).copied(),
+            Addr::Const(i) => consts.value_type(i),
+            _ => None,
+        }
+    }
+
+    /// The operands of `inst` that have to be of a type, and the type
+    fn expected(inst: &InterInst) -> Vec<(&'static str, Addr, ValueType)> {
+        let (t0, t1) = (inst.t0, inst.t1);
+        match inst.op0 {
+            Opcode::ADD
+            | Opcode::SUB
+            | Opcode::MUL
+            | Opcode::QUO
+            | Opcode::REM
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR
+            | Opcode::AND_NOT
+            | Opcode::ADD_CHECKED
+            | Opcode::SUB_CHECKED
+            | Opcode::MUL_CHECKED => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t0), ("s1", inst.s1, t0)]
+            }
+            Opcode::SHL | Opcode::SHR | Opcode::SHL_CHECKED => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t0), ("s1", inst.s1, t1)]
+            }
+            Opcode::ADD_ASSIGN
+            | Opcode::SUB_ASSIGN
+            | Opcode::MUL_ASSIGN
+            | Opcode::QUO_ASSIGN
+            | Opcode::REM_ASSIGN
+            | Opcode::AND_ASSIGN
+            | Opcode::OR_ASSIGN
+            | Opcode::XOR_ASSIGN
+            | Opcode::AND_NOT_ASSIGN => vec![("d", inst.d, t0), ("s0", inst.s0, t0)],
+            Opcode::SHL_ASSIGN | Opcode::SHR_ASSIGN => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t1)]
+            }
+            Opcode::INC | Opcode::DEC => vec![("d", inst.d, t0)],
+            Opcode::UNARY_SUB | Opcode::UNARY_XOR | Opcode::NOT => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t0)]
+            }
+            // only the comparisons of copyable values depend on the types
+            Opcode::EQL if t0.copyable() && t0 == t1 => {
+                vec![("s0", inst.s0, t0), ("s1", inst.s1, t1)]
+            }
+            Opcode::NEQ | Opcode::LSS | Opcode::GTR | Opcode::LEQ | Opcode::GEQ
+                if t0.copyable() =>
+            {
+                vec![("s0", inst.s0, t0), ("s1", inst.s1, t0)]
+            }
+            Opcode::CAST | Opcode::CAST_CHECKED if t1 != ValueType::Void => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t1)]
+            }
+            Opcode::CAST | Opcode::CAST_CHECKED => vec![("d", inst.d, t0)],
+            _ => vec![],
+        }
+    }
+
+    /// Checks an instruction about to be emitted at `pos`.
+    pub fn check(&mut self, inst: &InterInst, pos: Option<usize>, consts: &Consts) {
+        let mut mismatches: Vec<String> = Self::expected(inst)
+            .into_iter()
+            .filter_map(|(name, addr, want)| {
+                let got = self.operand_type(addr, consts)?;
+                (got != want).then(|| format!("{} is {:?}, expected {:?}", name, got, want))
+            })
+            .collect();
+        if inst.op0 == Opcode::DUPLICATE {
+            let d = self.operand_type(inst.d, consts);
+            let s0 = self.operand_type(inst.s0, consts);
+            if let (Some(d), Some(s0)) = (d, s0) {
+                if d != s0 {
+                    mismatches.push(format!("storing {:?} to {:?}", s0, d));
+                }
+            }
+        }
+        if mismatches.is_empty() {
+            return;
+        }
+        let msg = format!(
+            "internal compiler error in {}: {} {:?}/{:?}: {}",
+            self.name,
+            inst.op0,
+            inst.t0,
+            inst.t1,
+            mismatches.join(", ")
+        );
+        if self.panic_on_error {
+            panic!("{} (at offset {:?})", msg, pos);
+        }
+        self.errors.push((pos, msg));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::consts::Consts;
+    use super::super::context::{Addr, FuncCtx, VirtualAddr};
+    use go_vm::types::*;
+    use go_vm::*;
+
+    #[test]
+    fn test_mismatched_store() {
+        let consts = Consts::new();
+        let mut fctx = FuncCtx::new(FunctionKey::default(), None, &consts);
+        fctx.set_name("f");
+        fctx.checker.panic_on_error = false;
+        let int_var = fctx.add_local(None, Some((0 as isize).into()));
+        let str_var = fctx.add_local(None, Some(FfiCtx::new_string("")));
+        let one = fctx.add_comparable((1 as isize).into());
+        let s = fctx.add_comparable(FfiCtx::new_string("s"));
+
+        // well typed
+        fctx.emit_assign(VirtualAddr::Direct(int_var), one, None, Some(1));
+        fctx.emit_assign(
+            VirtualAddr::Direct(int_var),
+            one,
+            Some((Opcode::ADD, ValueType::Int, None)),
+            Some(2),
+        );
+        // an unknown operand is not checked
+        fctx.emit_assign(
+            VirtualAddr::Direct(int_var),
+            Addr::Regsiter(0),
+            None,
+            Some(3),
+        );
+        assert!(fctx.checker.take_errors().is_empty());
+
+        fctx.emit_assign(VirtualAddr::Direct(int_var), s, None, Some(4));
+        fctx.emit_assign(
+            VirtualAddr::Direct(str_var),
+            one,
+            Some((Opcode::ADD, ValueType::Int, None)),
+            Some(5),
+        );
+        assert_eq!(
+            fctx.checker.take_errors(),
+            vec![
+                (
+                    Some(4),
+                    "internal compiler error in f: DUPLICATE Void/Void: storing String to Int"
+                        .to_owned()
+                ),
+                (
+                    Some(5),
+                    "internal compiler error in f: ADD_ASSIGN Int/Void: d is String, expected Int"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "internal compiler error in f: INC Int/Void: d is String")]
+    fn test_mismatch_panics() {
+        let consts = Consts::new();
+        let mut fctx = FuncCtx::new(FunctionKey::default(), None, &consts);
+        fctx.set_name("f");
+        fctx.checker.panic_on_error = true;
+        let str_var = fctx.add_local(None, Some(FfiCtx::new_string("")));
+        fctx.emit_assign(
+            VirtualAddr::Direct(str_var),
+            Addr::Void,
+            Some((Opcode::INC, ValueType::Int, None)),
+            None,
+        );
+    }
+}
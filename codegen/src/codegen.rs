@@ -47,6 +47,11 @@ pub(crate) struct CodeGen<'a, 'c> {
 
     pkg_key: PackageKey,
     blank_ident: IdentKey,
+    checked_arithmetic: bool,
+    // positions and messages of the lint warnings, `None` if lints are disabled
+    warnings: Option<&'a mut Vec<(usize, &'static str)>>,
+    // positions and messages of the constructs that can't be compiled
+    errors: &'a mut Vec<(usize, String)>,
     func_ctx_stack: Vec<FuncCtx<'c>>,
     expr_ctx_stack: Vec<ExprCtx>,
     results: Vec<FuncCtx<'c>>,
@@ -66,6 +71,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         pkg_helper: &'a mut PkgHelper<'a>,
         pkg_key: PackageKey,
         blank_ident: IdentKey,
+        checked_arithmetic: bool,
+        warnings: Option<&'a mut Vec<(usize, &'static str)>>,
+        errors: &'a mut Vec<(usize, String)>,
     ) -> CodeGen<'a, 'c> {
         CodeGen {
             vmctx,
@@ -79,6 +87,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             pkg_helper,
             pkg_key,
             blank_ident,
+            checked_arithmetic,
+            warnings,
+            errors,
             func_ctx_stack: vec![],
             expr_ctx_stack: vec![],
             results: vec![],
@@ -125,13 +136,14 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         // 2. try upvalue
         let upvalue = self
             .func_ctx_stack
-            .iter()
+            .iter_mut()
             .skip(1) // skip package constructor
             .rev()
             .skip(1) // skip itself
             .find_map(|ctx| {
                 let index = ctx.entity_index(&okey).map(|x| *x);
                 if let Some(ind) = index {
+                    ctx.set_captured(ind.as_var_index());
                     let desc = ValueDesc::new(
                         ctx.f_key,
                         ind.as_var_index() as OpIndex,
@@ -228,7 +240,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     ) -> Option<usize> {
         let lhs = lhs_exprs
             .iter()
-            .map(|expr| match expr {
+            .map(|expr| match Parser::unparen(expr) {
                 Expr::Ident(ident) => {
                     let is_def = self.t.ident_is_def(ident);
                     let (vaddr, typ, pos) = self.add_local_or_resolve_ident(ident, is_def);
@@ -258,6 +270,11 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             let zero_addr = self.add_zero_val(typ);
                             VirtualAddr::MapEntry(obj_addr, ind_addr, zero_addr)
                         }
+                        // a pointer to an array
+                        ValueType::Pointer => {
+                            let array_addr = self.gen_load_pointer(obj_addr, Some(pos));
+                            VirtualAddr::ArrayEntry(array_addr, ind_addr)
+                        }
                         _ => unreachable!(),
                     };
                     (va, Some(typ), pos)
@@ -370,10 +387,21 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         p: usize,
     ) {
         let pos = Some(p);
-        let rhs_addr = match right {
-            Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
+        let (opcode, rhs_addr) = match right {
+            Some(e) => (
+                self.checked_opcode(opcode, typ),
+                self.load_mode_call(|g| g.gen_expr(e)),
+            ),
             // inc/dec
-            None => Addr::Void,
+            None => {
+                let checked = self.checked_opcode(opcode, typ);
+                if checked != opcode {
+                    let one = GosValue::from(1isize).cast_copyable(ValueType::Int, typ);
+                    (checked, func_ctx!(self).add_comparable(one))
+                } else {
+                    (opcode, Addr::Void)
+                }
+            }
         };
         func_ctx!(self).emit_assign(left.clone(), rhs_addr, Some((opcode, typ, r_type)), pos);
     }
@@ -418,7 +446,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                         let mode = ExprMode::Store(lhs[1].0.clone(), None);
                         ExprCtx::new(mode, cur_reg)
                     });
-                    match val0 {
+                    match Parser::unparen(val0) {
                         Expr::TypeAssert(tae) => {
                             self.gen_expr_type_assert(&tae.expr, &tae.typ, ok_ectx);
                         }
@@ -485,7 +513,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             }
             RightHandSide::Range(r) => {
                 // the range statement
-                let right_addr = self.load_mode_call(|g| g.gen_expr(r));
+                let mut right_addr = self.load_mode_call(|g| g.gen_expr(r));
+                if self.t.expr_value_type(r) == ValueType::Pointer {
+                    right_addr = self.gen_load_pointer(right_addr, Some(r.pos(self.ast_objs)));
+                }
                 let tkv = self.t.expr_range_tc_types(r);
                 let types = [
                     Some(self.t.tc_type_to_value_type(tkv[0])),
@@ -556,7 +587,11 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 Some(l) => {
                     for c in l.iter() {
                         let pos = Some(stmt.pos(&self.ast_objs));
-                        let addr = self.load_mode_call(|g| g.gen_expr(c));
+                        let addr = if tag_type == ValueType::Metadata {
+                            self.type_case_meta(c)
+                        } else {
+                            self.load_mode_call(|g| g.gen_expr(c))
+                        };
                         let fctx = func_ctx!(self);
                         helper.tags.add_case(i, fctx.next_code_index());
                         fctx.emit_inst(
@@ -592,11 +627,33 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             if let Some((val_src, iface_src, ref dsts, p)) = type_switch_local_vars {
                 // Specs: In clauses with a case listing exactly one type, the variable has that type; otherwise,
                 // the variable has the type of the expression in the TypeSwitchGuard.
-                let src = if default { iface_src } else { val_src };
-                fctx.emit_inst(
-                    InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], src, Addr::Void),
-                    p,
-                );
+                let single = cc.list.as_ref().filter(|l| l.len() == 1).map(|l| &l[0]);
+                let single_type = single.map(|c| {
+                    let t = self.t.expr_tc_type(c);
+                    self.t.obj_underlying_value_type(t)
+                });
+                let inst = match single_type {
+                    // the value is bound to the methods of the interface in the case
+                    Some(ValueType::Interface) => {
+                        let meta_addr = self.type_case_meta(single.unwrap());
+                        InterInst::with_op_t_index(
+                            Opcode::TYPE_ASSERT,
+                            None,
+                            Some(ValueType::FlagA),
+                            dsts[i],
+                            iface_src,
+                            meta_addr,
+                        )
+                    }
+                    // `case nil` is Void
+                    Some(t) if t != ValueType::Void => {
+                        InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], val_src, Addr::Void)
+                    }
+                    _ => {
+                        InterInst::with_op_index(Opcode::DUPLICATE, dsts[i], iface_src, Addr::Void)
+                    }
+                };
+                func_ctx!(self).emit_inst(inst, p);
             }
             for s in cc.body.iter() {
                 self.visit_stmt(s);
@@ -620,8 +677,15 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
+    /// The metadata of a type in a type switch case, `nil` is the type of nil interfaces.
+    fn type_case_meta(&mut self, typ: &Expr) -> Addr {
+        let m = self.t.tc_type_to_meta(self.t.expr_tc_type(typ), self.vmctx);
+        func_ctx!(self).add_comparable(FfiCtx::new_metadata(m))
+    }
+
     fn gen_func_def(
         &mut self,
+        name: &str,
         tc_type: TCTypeKey, // Meta,
         f_type_key: FuncTypeKey,
         recv: Option<FieldList>,
@@ -634,6 +698,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             .function_with_meta(Some(self.pkg_key), fmeta, FuncFlag::Default);
         let fkey = *f.as_function();
         let mut fctx = FuncCtx::new(fkey, Some(tc_type), self.consts);
+        fctx.set_name(name);
         if let Some(fl) = &typ.results {
             fctx.add_params(&fl, self.ast_objs, &self.t);
         }
@@ -660,21 +725,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     fn gen_builtin_call(
         &mut self,
         func_expr: &Expr,
-        params: &Vec<Expr>,
+        params: &[Expr],
         builtin: &Builtin,
         return_types: &[TCTypeKey],
         ellipsis: bool,
         pos: Option<usize>,
     ) {
-        let slice_op_types = |g: &mut CodeGen| {
-            let t0 = if ellipsis && g.t.expr_value_type(&params[1]) == ValueType::String {
-                ValueType::String
-            } else {
-                ValueType::Slice
-            };
-            let (_, t_elem) = g.t.sliceable_expr_value_types(&params[0], g.vmctx);
-            (t0, g.t.tc_type_to_value_type(t_elem))
-        };
         match builtin {
             Builtin::Make => {
                 let meta_addr = self.load_mode_call(|g| g.gen_expr(&params[0]));
@@ -745,8 +801,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             Builtin::Append => {
                 let ft = self.t.try_expr_tc_type(func_expr).unwrap();
                 let init_reg = expr_ctx!(self).cur_reg;
-                self.gen_call_params(ft, params, ellipsis);
-                let types = slice_op_types(self);
+                self.gen_call_params(ft, params, ellipsis, init_reg);
+                let types = self.slice_op_types(params, ellipsis);
                 self.cur_expr_emit_assign(return_types[0], pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::APPEND,
@@ -763,7 +819,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             Builtin::Copy => {
                 let addr0 = self.load_mode_call(|g| g.gen_expr(&params[0]));
                 let addr1 = self.load_mode_call(|g| g.gen_expr(&params[1]));
-                let types = slice_op_types(self);
+                let types = self.slice_op_types(params, ellipsis);
                 self.cur_expr_emit_assign(return_types[0], pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::COPY,
@@ -777,7 +833,19 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 });
             }
             Builtin::Delete | Builtin::Close | Builtin::Panic | Builtin::Assert => {
-                let addr0 = self.load_mode_call(|g| g.gen_expr(&params[0]));
+                let addr0 = match builtin {
+                    // the value of panic is an interface{}, with the type for recover
+                    Builtin::Panic => {
+                        let ft = self.t.expr_tc_type(func_expr);
+                        let iface_t = self.t.sig_params_tc_types(ft).0[0];
+                        let iface_addr = expr_ctx!(self).inc_cur_reg();
+                        self.store_mode_call(VirtualAddr::Direct(iface_addr), Some(iface_t), |g| {
+                            g.gen_expr(&params[0])
+                        });
+                        iface_addr
+                    }
+                    _ => self.load_mode_call(|g| g.gen_expr(&params[0])),
+                };
                 let addr1 = if params.len() > 1 {
                     self.load_mode_call(|g| g.gen_expr(&params[1]))
                 } else {
@@ -800,8 +868,89 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
-            _ => unimplemented!(),
+            _ => {
+                let construct = format!("the builtin {:?}", builtin).to_lowercase();
+                self.unsupported(func_expr.pos(self.ast_objs), &construct);
+            }
+        };
+    }
+
+    fn slice_op_types(&mut self, params: &[Expr], ellipsis: bool) -> (ValueType, ValueType) {
+        let t0 = if ellipsis && self.t.expr_value_type(&params[1]) == ValueType::String {
+            ValueType::String
+        } else {
+            ValueType::Slice
         };
+        let (_, t_elem) = self.t.sliceable_expr_value_types(&params[0], self.vmctx);
+        (t0, self.t.tc_type_to_value_type(t_elem))
+    }
+
+    /// Builtins can't be called with a call style, `go` and `defer` calls of them are made
+    /// through a generated function that does nothing but the builtin call, so that the
+    /// args are evaluated and saved at the statement like for any other function.
+    fn gen_builtin_wrapper(
+        &mut self,
+        func_expr: &Expr,
+        params: &[Expr],
+        builtin: &Builtin,
+        ellipsis: bool,
+        pos: Option<usize>,
+    ) -> Addr {
+        let ft = self.t.expr_tc_type(func_expr);
+        let ret_count = self.t.sig_returns_tc_types(ft).len();
+        let types = match builtin {
+            Builtin::Copy => Some(self.slice_op_types(params, ellipsis)),
+            _ => None,
+        };
+        let fmeta = self.t.tc_type_to_meta(ft, self.vmctx);
+        let f = self
+            .vmctx
+            .function_with_meta(Some(self.pkg_key), fmeta, FuncFlag::Default);
+        let fkey = *f.as_function();
+        let mut fctx = FuncCtx::new(fkey, Some(ft), self.consts);
+        fctx.set_name(&format!("<builtin {:?} wrapper>", builtin));
+        let args: Vec<Addr> = (0..ret_count + params.len())
+            .map(|_| fctx.add_local(None, None))
+            .skip(ret_count)
+            .collect();
+        let arg = |i: usize| args.get(i).copied().unwrap_or(Addr::Void);
+        match builtin {
+            Builtin::Delete | Builtin::Close | Builtin::Panic | Builtin::Assert => {
+                let op = match builtin {
+                    Builtin::Delete => Opcode::DELETE,
+                    Builtin::Close => Opcode::CLOSE,
+                    Builtin::Panic => Opcode::PANIC,
+                    Builtin::Assert => Opcode::ASSERT,
+                    _ => unreachable!(),
+                };
+                fctx.emit_inst(
+                    InterInst::with_op_index(op, Addr::Void, arg(0), arg(1)),
+                    pos,
+                );
+            }
+            Builtin::Copy => {
+                let (t0, t1) = types.unwrap();
+                let inst = InterInst::with_op_t_index(
+                    Opcode::COPY,
+                    Some(t0),
+                    Some(t1),
+                    Addr::LocalVar(0),
+                    arg(0),
+                    arg(1),
+                );
+                fctx.emit_inst(inst, pos);
+            }
+            // Specs: the return value of recover is nil if recover was not called
+            // directly by a deferred function, so a deferred recover() is a no-op
+            Builtin::Recover => {}
+            _ => {
+                let construct = format!("the builtin {:?}", builtin).to_lowercase();
+                self.unsupported(func_expr.pos(self.ast_objs), &construct);
+            }
+        }
+        fctx.emit_return(None, pos, self.vmctx.functions());
+        self.results.push(fctx);
+        func_ctx!(self).add_comparable(FfiCtx::new_function(fkey))
     }
 
     fn gen_conversion(&mut self, to: &Expr, from: &Expr, pos: Option<usize>) {
@@ -824,7 +973,6 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             - x is the predeclared identifier nil and T is a pointer, function, slice, map, channel, or interface type.
             - x is an untyped constant representable by a value of type T.
         */
-        let from_addr = self.load_mode_call(|g| g.gen_expr(from));
         let mut converted = false;
 
         let n_tc_to = self.t.expr_tc_type(to); // possibly named type
@@ -832,7 +980,18 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         let typ_to = self.t.tc_type_to_value_type(tc_to);
         let n_tc_from = self.t.expr_tc_type(from); // possibly named type
         let tc_from = self.t.underlying_tc(n_tc_from);
-        let typ_from = self.t.tc_type_to_value_type(tc_from);
+        let typ_from = match self.t.is_nil(from) {
+            true => ValueType::Void,
+            false => self.t.tc_type_to_value_type(tc_from),
+        };
+
+        let from_addr = if typ_from == ValueType::Void {
+            // nil takes the type it's converted to
+            let nil = self.typed_nil(n_tc_to);
+            func_ctx!(self).add_nil(nil)
+        } else {
+            self.load_mode_call(|g| g.gen_expr(from))
+        };
 
         if typ_from == ValueType::Void || identical_ignore_tags(tc_to, tc_from, self.tc_objs) {
             // just ignore conversion if it's nil or types are identical
@@ -882,8 +1041,16 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     };
                     let t2 = t_extra.map(|x| self.t.tc_type_to_value_type(x));
 
+                    let checked = self.checked_arithmetic
+                        && typ_to != typ_from
+                        && typ_to.integer()
+                        && typ_from.integer();
                     self.cur_expr_emit_assign(tc_to, pos, |f, d, p| {
-                        f.emit_cast(d, from_addr, Addr::Void, typ_to, Some(typ_from), t2, p);
+                        if checked {
+                            f.emit_cast_checked(d, from_addr, typ_to, typ_from, p);
+                        } else {
+                            f.emit_cast(d, from_addr, Addr::Void, typ_to, Some(typ_from), t2, p);
+                        }
                     });
                     converted = true;
                 }
@@ -902,7 +1069,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
     fn gen_expr_call(
         &mut self,
         func_expr: &Expr,
-        params: &Vec<Expr>,
+        params: &[Expr],
         ellipsis: bool,
         style: CallStyle,
     ) {
@@ -911,10 +1078,29 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
         match *self.t.expr_mode(func_expr) {
             // built in function
-            OperandMode::Builtin(builtin) => {
-                let return_types = self.t.sig_returns_tc_types(ft);
-                self.gen_builtin_call(func_expr, params, &builtin, &return_types, ellipsis, pos);
-            }
+            OperandMode::Builtin(builtin) => match style {
+                CallStyle::Default => {
+                    let return_types = self.t.sig_returns_tc_types(ft);
+                    self.gen_builtin_call(
+                        func_expr,
+                        params,
+                        &builtin,
+                        &return_types,
+                        ellipsis,
+                        pos,
+                    );
+                }
+                CallStyle::Async | CallStyle::Defer => {
+                    let func = self.gen_builtin_wrapper(func_expr, params, &builtin, ellipsis, pos);
+                    let next_sb = expr_ctx!(self).cur_reg;
+                    let ret_count = self.t.sig_returns_tc_types(ft).len();
+                    self.gen_call_params(ft, params, ellipsis, next_sb + ret_count);
+                    let func_addr = expr_ctx!(self).inc_cur_reg();
+                    let fctx = func_ctx!(self);
+                    fctx.emit_closure(func_addr, func, pos);
+                    fctx.emit_call(func_addr, next_sb, false, style, pos);
+                }
+            },
             // conversion
             OperandMode::TypeExpr => {
                 assert!(params.len() == 1);
@@ -925,12 +1111,25 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 let next_sb = expr_ctx!(self).cur_reg;
                 // make sure params are at the right place
                 let return_types = self.t.sig_returns_tc_types(ft);
-                let reg_usage =
-                    return_types.len() + if self.t.is_method(func_expr) { 1 } else { 0 };
-                expr_ctx!(self).cur_reg = next_sb + reg_usage;
-                self.gen_call_params(ft, params, ellipsis);
-                let func_addr = self.load_mode_call(|g| g.gen_expr(func_expr));
-                func_ctx!(self).emit_call(func_addr, next_sb, style, pos);
+                let is_method = self.t.is_method(func_expr);
+                let reg_usage = return_types.len() + if is_method { 1 } else { 0 };
+                let params_begin = next_sb + reg_usage;
+                // the calls in the function expression, or the receiver, go before the
+                // ones in the params, it's evaluated into the registers after the params,
+                // which are one more than the args when there is an empty variadic to pack.
+                // Otherwise, like in gc, the function is read after the params.
+                let func_addr = if has_call_or_recv(func_expr) {
+                    let param_count = self.t.sig_params_tc_types(ft).0.len();
+                    let arg_count = self.call_arg_count(params);
+                    expr_ctx!(self).cur_reg = params_begin + arg_count.max(param_count);
+                    let addr = self.load_mode_call(|g| g.gen_expr(func_expr));
+                    self.gen_call_params(ft, params, ellipsis, params_begin);
+                    addr
+                } else {
+                    self.gen_call_params(ft, params, ellipsis, params_begin);
+                    self.load_mode_call(|g| g.gen_expr(func_expr))
+                };
+                func_ctx!(self).emit_call(func_addr, next_sb, is_method, style, pos);
 
                 if !return_types.is_empty() {
                     // assgin the first return value
@@ -941,39 +1140,66 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
-    fn gen_call_params(&mut self, func: TCTypeKey, params: &Vec<Expr>, ellipsis: bool) {
+    /// Evaluates `params` into the registers from `begin` on, with the temporaries
+    /// of them after the registers in use, and leaves the registers after the params
+    /// free.
+    fn gen_call_params(&mut self, func: TCTypeKey, params: &[Expr], ellipsis: bool, begin: usize) {
         let (sig_params, variadic) = self.t.sig_params_tc_types(func);
         let need_pack = !ellipsis && variadic.is_some();
         let non_variadic_count = sig_params.len() - if need_pack { 1 } else { 0 };
+        let param_type = |i: usize| match i < non_variadic_count {
+            true => sig_params[i],
+            false => variadic.unwrap(),
+        };
 
-        let init_reg = expr_ctx!(self).cur_reg;
-        for (i, e) in params.iter().enumerate() {
-            let addr = expr_ctx!(self).inc_cur_reg();
-            let lhs_type = if i < non_variadic_count {
-                sig_params[i]
-            } else {
-                variadic.unwrap()
-            };
-            self.store_mode_call(VirtualAddr::Direct(addr), Some(lhs_type), |g| g.gen_expr(e));
+        let arg_count = self.call_arg_count(params);
+        let end = begin + arg_count;
+        let cur_reg = &mut expr_ctx!(self).cur_reg;
+        *cur_reg = (*cur_reg).max(end);
+        if arg_count != params.len() {
+            // f(g()), the results of g are evaluated after the registers in use
+            // and then moved to the params one by one
+            let e = &params[0];
+            let pos = Some(e.pos(self.ast_objs));
+            self.discard_mode_call(|g| g.gen_expr(e));
+            let results_begin = expr_ctx!(self).cur_reg;
+            let types = self.t.expr_tuple_tc_types(e);
+            for (i, t) in types.into_iter().enumerate() {
+                let va = VirtualAddr::Direct(Addr::Regsiter(begin + i));
+                self.store_mode_call(va, Some(param_type(i)), |g| {
+                    g.cur_expr_emit_direct_assign(t, Addr::Regsiter(results_begin + i), pos)
+                });
+            }
+        } else {
+            for (i, e) in params.iter().enumerate() {
+                let addr = Addr::Regsiter(begin + i);
+                self.store_mode_call(VirtualAddr::Direct(addr), Some(param_type(i)), |g| {
+                    g.gen_expr(e)
+                });
+            }
         }
+        expr_ctx!(self).cur_reg = end;
 
-        debug_assert!(params.len() >= non_variadic_count);
+        debug_assert!(arg_count >= non_variadic_count);
         if need_pack {
             if let Some(t) = variadic {
-                let variadic_count = params.len() - non_variadic_count;
-                let variadic_begin_reg = init_reg + non_variadic_count;
-                let pos =
-                    (!params.is_empty()).then(|| params[non_variadic_count].pos(&self.ast_objs));
+                let variadic_count = arg_count - non_variadic_count;
+                let variadic_begin_reg = begin + non_variadic_count;
+                let first = match arg_count == params.len() {
+                    true => params.get(non_variadic_count),
+                    false => params.first(),
+                };
+                let pos = first.map(|e| e.pos(self.ast_objs));
                 let t_elem = self.t.tc_type_to_value_type(t);
-                let begin = Addr::Regsiter(variadic_begin_reg);
-                let end = Addr::Regsiter(variadic_begin_reg + variadic_count);
+                let pack_begin = Addr::Regsiter(variadic_begin_reg);
+                let pack_end = Addr::Regsiter(variadic_begin_reg + variadic_count);
                 let inst = InterInst::with_op_t_index(
                     Opcode::PACK_VARIADIC,
                     Some(t_elem),
                     None,
-                    begin,
-                    begin,
-                    end,
+                    pack_begin,
+                    pack_begin,
+                    pack_end,
                 );
                 func_ctx!(self).emit_inst(inst, pos);
 
@@ -982,6 +1208,20 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         }
     }
 
+    /// The number of arguments a call passes, which is the number of results
+    /// when its only param is a call returning more than one.
+    fn call_arg_count(&self, params: &[Expr]) -> usize {
+        match params {
+            [e] => {
+                let t = self.t.expr_tc_type(e);
+                self.tc_objs.types[t]
+                    .try_as_tuple()
+                    .map_or(1, |tuple| tuple.vars().len())
+            }
+            _ => params.len(),
+        }
+    }
+
     fn gen_expr_recv(
         &mut self,
         channel: &Expr,
@@ -1116,16 +1356,23 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 );
             }
             None => {
+                // the static type of the value is for the message of the panic
+                let iface_tc_type = self.t.expr_tc_type(expr);
+                let iface_meta = self.t.tc_type_to_meta(iface_tc_type, self.vmctx);
+                let iface_addr = func_ctx!(self).add_comparable(FfiCtx::new_metadata(iface_meta));
                 self.cur_expr_emit_assign(val_tc_type, pos, |f, d, p| {
                     let inst = InterInst::with_op_t_index(
                         Opcode::TYPE_ASSERT,
                         None,
-                        Some(ValueType::FlagA),
+                        Some(ValueType::FlagC),
                         d,
                         val_addr,
                         meta_addr,
                     );
                     f.emit_inst(inst, p);
+                    let inst_ex =
+                        InterInst::with_op_index(Opcode::VOID, Addr::Void, iface_addr, Addr::Void);
+                    f.emit_inst(inst_ex, p);
                 });
             }
         }
@@ -1133,7 +1380,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
 
     fn gen_expr_ref(&mut self, expr: &Expr, ref_tc_type: TCTypeKey) {
         let pos = Some(expr.pos(&self.ast_objs));
-        match expr {
+        match Parser::unparen(expr) {
             Expr::Ident(ikey) => {
                 let va = self.resolve_any_ident(ikey, None);
                 match va {
@@ -1147,6 +1394,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                             ValueDesc::new(fctx.f_key, ind.as_var_index() as OpIndex, t, true);
                         // for package ctors, all locals are "closed"
                         if !fctx.is_ctor(self.vmctx.functions()) {
+                            fctx.set_captured(ind.as_var_index());
                             let uv_index = fctx.add_upvalue(&entity_key, desc);
                             self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
                                 let inst = InterInst::with_op_index(
@@ -1183,9 +1431,13 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 }
             }
             Expr::Index(iexpr) => {
-                let (t0, _) = self.t.sliceable_expr_value_types(&iexpr.expr, self.vmctx);
-                let t1 = self.t.expr_value_type(&iexpr.index);
-                let lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
+                let (t0, elem) = self.t.sliceable_expr_value_types(&iexpr.expr, self.vmctx);
+                // the element type picks the dispatcher the pointer reads and writes with
+                let t1 = self.t.tc_type_to_value_type(elem);
+                let mut lhs_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.expr));
+                if self.t.expr_value_type(&iexpr.expr) == ValueType::Pointer {
+                    lhs_addr = self.gen_load_pointer(lhs_addr, pos);
+                }
                 let index_addr = self.load_mode_call(|g| g.gen_expr(&iexpr.index));
                 let pos = Some(iexpr.index.pos(&self.ast_objs));
                 self.cur_expr_emit_assign(ref_tc_type, pos, |f, d, p| {
@@ -1235,10 +1487,10 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                     f.emit_inst(inst, p);
                 });
             }
-            _ => {
-                dbg!(&expr);
-                unimplemented!()
-            }
+            // &*p is p
+            Expr::Star(sexpr) => self.gen_expr(&sexpr.expr),
+            // the type checker only lets addressable operands and composite literals through
+            _ => unreachable!(),
         }
     }
 
@@ -1246,7 +1498,20 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         match expr {
             Expr::CompositeLit(clit) => {
                 let tc_type = self.t.expr_tc_type(expr);
-                self.gen_expr_composite_lit(clit, tc_type)
+                let underlying = self.tc_objs.types[tc_type].underlying_val(self.tc_objs);
+                match underlying.try_as_pointer() {
+                    // an elided &T in the elements of a literal of []*T and the like
+                    Some(detail) => {
+                        let base = detail.base();
+                        let pos = Some(clit.l_brace);
+                        let addr = self.load_mode_call(|g| g.gen_expr_composite_lit(clit, base));
+                        self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+                            let inst = InterInst::with_op_index(Opcode::REF, d, addr, Addr::Void);
+                            f.emit_inst(inst, p);
+                        });
+                    }
+                    None => self.gen_expr_composite_lit(clit, tc_type),
+                }
             }
             _ => self.gen_expr(expr),
         }
@@ -1270,11 +1535,17 @@ impl<'a, 'c> CodeGen<'a, 'c> {
                 for expr in clit.elts.iter() {
                     let (key, elem) = match expr {
                         Expr::KeyValue(kv) => {
-                            // the key is a constant
+                            // the key is a constant representable as an int, e.g. 1.0 or 'a'
                             let key_const = self.t.try_tc_const_value(kv.key.id()).unwrap();
-                            let (key_i64, ok) = key_const.int_as_i64();
-                            debug_assert!(ok);
-                            (key_i64 as i32, &kv.val)
+                            let (key_i64, _) = key_const.to_int().int_as_i64();
+                            match i32::try_from(key_i64) {
+                                Ok(key) => (key, &kv.val),
+                                Err(_) => {
+                                    let pos = kv.key.pos(self.ast_objs);
+                                    self.unsupported(pos, "an index over 2147483647 in a literal");
+                                    continue;
+                                }
+                            }
                         }
                         _ => (-1, expr),
                     };
@@ -1340,7 +1611,50 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
             f.emit_literal(d, reg_base, count, meta_addr, p);
         });
-        expr_ctx!(self).cur_reg = reg_base + 1; //reset register allocation
+        //reset register allocation, but keep the register a loaded literal is in,
+        //or the next value loaded overwrites it, like in `a, b := T{1}, T{2}`
+        let ectx = expr_ctx!(self);
+        ectx.cur_reg = match (&ectx.mode, ectx.load_addr) {
+            (ExprMode::Load, Addr::Regsiter(r)) => r + 1,
+            _ => reg_base + 1,
+        };
+    }
+
+    /// The operands of a chain of string `+`, like `a`, `b` and `c` of `a + b + c`,
+    /// a constant operand is a part of its own
+    fn concat_parts<'e>(&self, expr: &'e Expr, parts: &mut Vec<&'e Expr>) {
+        match expr {
+            Expr::Binary(b)
+                if b.op == Token::ADD
+                    && !matches!(self.t.try_expr_mode(expr), Some(OperandMode::Constant(_))) =>
+            {
+                self.concat_parts(&b.expr_a, parts);
+                self.concat_parts(&b.expr_b, parts);
+            }
+            Expr::Paren(p) => self.concat_parts(&p.expr, parts),
+            _ => parts.push(expr),
+        }
+    }
+
+    /// Joins the parts in one allocation, they are loaded to consecutive registers
+    fn gen_expr_concat(&mut self, parts: &[&Expr], tc_type: TCTypeKey, pos: Option<usize>) {
+        let reg_base = expr_ctx!(self).cur_reg;
+        for part in parts.iter() {
+            let reg = VirtualAddr::Direct(expr_ctx!(self).inc_cur_reg());
+            self.store_mode_call(reg, None, |g| g.gen_expr(part));
+        }
+        let count = parts.len();
+        self.cur_expr_emit_assign(tc_type, pos, |f, d, p| {
+            let inst = InterInst::with_op_t_index(
+                Opcode::CONCAT,
+                Some(ValueType::String),
+                None,
+                d,
+                Addr::Regsiter(reg_base),
+                Addr::Imm(count as OpIndex),
+            );
+            f.emit_inst(inst, p);
+        });
     }
 
     fn gen_load_pointer(&mut self, ptr: Addr, pos: Option<usize>) -> Addr {
@@ -1364,6 +1678,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         self.cur_expr_emit_direct_assign(tc_type, addr, pos);
     }
 
+    /// Constant expressions are emitted as constants, qualified ones like
+    /// `math.Pi` included, the members of other packages are only loaded for vars.
     fn gen_expr(&mut self, expr: &Expr) {
         if let Some(mode) = self.t.try_expr_mode(expr) {
             if let OperandMode::Constant(_) = mode {
@@ -1374,6 +1690,12 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         walk_expr(self, expr);
     }
 
+    /// Reports a construct that passed the type checker but can't be compiled.
+    fn unsupported(&mut self, pos: usize, construct: &str) {
+        self.errors
+            .push((pos, format!("{} is not supported", construct)));
+    }
+
     fn get_field_meta(&self, parent: &Meta, indices: &[usize]) -> Meta {
         match parent.mtype_unwraped(self.vmctx.metas()) {
             MetadataType::Struct(f) => f.get(indices, self.vmctx.metas()).meta,
@@ -1398,7 +1720,8 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             let ident = &self.ast_objs.idents[*n];
             let meta = self.t.obj_def_meta(*n, self.vmctx);
             let val = self.vmctx.ffi_ctx().zero_val(&meta);
-            self.vmctx.packages_mut()[pkey].add_member(ident.name.clone(), val);
+            let zero = self.vmctx.ffi_ctx().zero_val(&meta);
+            self.vmctx.packages_mut()[pkey].add_var_member(ident.name.clone(), val, zero);
         }
     }
 
@@ -1548,6 +1871,20 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         self.expr_ctx_stack.pop().unwrap()
     }
 
+    /// Returns the overflow checking version of an integer opcode if checked arithmetic is on
+    fn checked_opcode(&self, code: Opcode, t: ValueType) -> Opcode {
+        if !self.checked_arithmetic || !t.integer() {
+            return code;
+        }
+        match code {
+            Opcode::ADD | Opcode::INC => Opcode::ADD_CHECKED,
+            Opcode::SUB | Opcode::DEC => Opcode::SUB_CHECKED,
+            Opcode::MUL => Opcode::MUL_CHECKED,
+            Opcode::SHL => Opcode::SHL_CHECKED,
+            _ => code,
+        }
+    }
+
     fn cur_expr_emit_assign<F>(&mut self, rhs_type: TCTypeKey, pos: Option<Pos>, f: F)
     where
         F: FnOnce(&mut FuncCtx, Addr, Option<Pos>),
@@ -1562,16 +1899,7 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         let index = lhs.map(|x| self.cast_to_iface_index(x, rhs_type)).flatten();
         let src = if src == Addr::UntypedNil {
             let nil = match lhs {
-                Some(tct) => {
-                    let typ = self.t.tc_type_to_value_type(tct);
-                    match typ {
-                        ValueType::Slice => {
-                            let t_elem = self.t.slice_elem_type(tct);
-                            FfiCtx::new_nil_slice(t_elem)
-                        }
-                        _ => FfiCtx::new_nil(typ),
-                    }
-                }
+                Some(tct) => self.typed_nil(tct),
                 None => FfiCtx::new_nil(ValueType::Void),
             };
             func_ctx!(self).add_nil(nil)
@@ -1581,6 +1909,17 @@ impl<'a, 'c> CodeGen<'a, 'c> {
         expr_ctx!(self).direct_assign(func_ctx!(self), src, index, pos);
     }
 
+    fn typed_nil(&self, tct: TCTypeKey) -> GosValue {
+        let typ = self.t.tc_type_to_value_type(tct);
+        match typ {
+            ValueType::Slice => {
+                let t_elem = self.t.slice_elem_type(tct);
+                FfiCtx::new_nil_slice(t_elem)
+            }
+            _ => FfiCtx::new_nil(typ),
+        }
+    }
+
     pub fn gen_with_files(
         mut self,
         files: &Vec<ast::File>,
@@ -1598,8 +1937,9 @@ impl<'a, 'c> CodeGen<'a, 'c> {
             CodeGenVMCtx::new_closure_static(fkey, None, fmeta),
         );
         self.pkg_key = pkey;
-        self.func_ctx_stack
-            .push(FuncCtx::new(fkey, None, self.consts));
+        let mut fctx = FuncCtx::new(fkey, None, self.consts);
+        fctx.set_name(&format!("<init of {}>", self.tc_objs.pkgs[tcpkg].path()));
+        self.func_ctx_stack.push(fctx);
 
         let (names, vars) = self.pkg_helper.sort_var_decls(files, self.t.type_info());
         self.add_pkg_var_member(pkey, &names);
@@ -1661,7 +2001,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
     /// Add function as a const and then generate a closure of it
     fn visit_expr_func_lit(&mut self, this: &Expr, flit: &FuncLit) {
         let tc_type = self.t.expr_tc_type(this);
-        let (fkey, _) = self.gen_func_def(tc_type, flit.typ, None, &flit.body);
+        let (fkey, _) = self.gen_func_def("<func literal>", tc_type, flit.typ, None, &flit.body);
         let fctx = func_ctx!(self);
         let addr = fctx.add_comparable(FfiCtx::new_function(fkey));
         let pos = Some(flit.body.l_brace);
@@ -1690,6 +2030,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             });
             return;
         }
+        if self.t.is_method_expr(this.id()) {
+            self.unsupported(lhs_expr.pos(self.ast_objs), "a method expression");
+            return;
+        }
 
         let lhs_meta = self.t.node_meta(lhs_expr.id(), self.vmctx);
         //let lhs_type = lhs_meta.value_type(&self.objects.metas);
@@ -1800,7 +2144,10 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
         let (t0, tct_elem) = self.t.sliceable_expr_value_types(expr, self.vmctx);
         let pos = Some(expr.pos(&self.ast_objs));
 
-        let slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        let mut slice_array_addr = self.load_mode_call(|g| g.gen_expr(expr));
+        if self.t.expr_value_type(expr) == ValueType::Pointer {
+            slice_array_addr = self.gen_load_pointer(slice_array_addr, pos);
+        }
         let low_addr = match low {
             None => func_ctx!(self).add_comparable(0isize.into()),
             Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
@@ -1814,6 +2161,12 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
             Some(e) => self.load_mode_call(|g| g.gen_expr(e)),
         };
         let t_elem = self.t.tc_type_to_value_type(tct_elem);
+        // a slice can be resliced beyond the elements its array holds, up to its
+        // capacity, the rest of the array is then filled with this zero value
+        let zero_addr = match t0 {
+            ValueType::Slice => self.add_zero_val(tct_elem),
+            _ => Addr::Void,
+        };
         self.cur_expr_emit_assign(tct_elem, pos, |f, d, p| {
             let inst = InterInst::with_op_t_index(
                 Opcode::SLICE,
@@ -1823,7 +2176,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 slice_array_addr,
                 low_addr,
             );
-            let inst_ex = InterInst::with_op_index(Opcode::VOID, Addr::Void, high_addr, max_addr);
+            let inst_ex = InterInst::with_op_index(Opcode::VOID, zero_addr, high_addr, max_addr);
             f.emit_inst(inst, p);
             f.emit_inst(inst_ex, p);
         })
@@ -1888,6 +2241,14 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
 
     fn visit_expr_binary(&mut self, this: &Expr, left: &Expr, op: &Token, right: &Expr) {
         let typ = self.t.expr_tc_type(this);
+        if op == &Token::ADD && self.t.expr_value_type(this) == ValueType::String {
+            let mut parts = vec![];
+            self.concat_parts(this, &mut parts);
+            if parts.len() > 2 {
+                self.gen_expr_concat(&parts, typ, Some(left.pos(self.ast_objs)));
+                return;
+            }
+        }
         let left_addr = self.load_mode_call(|g| g.gen_expr(left));
         let t = self.t.expr_value_type(left);
         let code = match op {
@@ -1951,6 +2312,7 @@ impl<'a, 'c> ExprVisitor for CodeGen<'a, 'c> {
                 Opcode::SHL | Opcode::SHR | Opcode::EQL => Some(self.t.expr_value_type(right)),
                 _ => None,
             };
+            let code = self.checked_opcode(code, t);
             self.cur_expr_emit_assign(typ, pos, |f, d, p| {
                 let inst = InterInst::with_op_t_index(code, Some(t), t1, d, left_addr, right_addr);
                 f.emit_inst(inst, p);
@@ -2028,9 +2390,14 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         if decl.body.is_none() {
             return;
         }
+        // a method named _ can't be called, it's not in the method set of the receiver
+        if decl.recv.is_some() && self.ast_objs.idents[decl.name].name == "_" {
+            return;
+        }
         let tc_type = self.t.obj_def_tc_type(decl.name);
         let stmt = decl.body.as_ref().unwrap();
-        let (fkey, cls) = self.gen_func_def(tc_type, decl.typ, decl.recv.clone(), stmt);
+        let name = &self.ast_objs.idents[decl.name].name;
+        let (fkey, cls) = self.gen_func_def(name, tc_type, decl.typ, decl.recv.clone(), stmt);
         // this is a struct method
         if let Some(self_ident) = &decl.recv {
             let field = &self.ast_objs.fields[self_ident.list[0]];
@@ -2054,7 +2421,11 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         let offset = func_ctx!(self).next_code_index();
         let entity = self.t.object_def(stmt.label);
         let is_breakable = match &stmt.stmt {
-            Stmt::For(_) | Stmt::Range(_) | Stmt::Select(_) | Stmt::Switch(_) => true,
+            Stmt::For(_)
+            | Stmt::Range(_)
+            | Stmt::Select(_)
+            | Stmt::Switch(_)
+            | Stmt::TypeSwitch(_) => true,
             _ => false,
         };
         self.branch_helper.add_label(entity, offset, is_breakable);
@@ -2097,6 +2468,14 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
 
     fn visit_stmt_defer(&mut self, dstmt: &DeferStmt) {
         self.vmctx.functions_mut()[func_ctx!(self).f_key].flag = FuncFlag::HasDefer;
+        if func_ctx!(self).loop_depth > 0 {
+            if let Some(w) = &mut self.warnings {
+                w.push((
+                    dstmt.defer,
+                    "defer in a loop: the call runs when the function returns, not when the iteration ends",
+                ));
+            }
+        }
         match &dstmt.call {
             Expr::Call(call) => {
                 self.gen_expr_call(
@@ -2113,9 +2492,24 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     fn visit_stmt_return(&mut self, rstmt: &ReturnStmt) {
         if !rstmt.results.is_empty() {
             let types = self.t.sig_returns_tc_types(func_ctx!(self).tc_key.unwrap());
-            for (i, expr) in rstmt.results.iter().enumerate() {
-                let va = VirtualAddr::Direct(Addr::LocalVar(i));
-                self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+            if rstmt.results.len() < types.len() {
+                // return f(), with f returning all the results
+                let expr = &rstmt.results[0];
+                let pos = Some(expr.pos(&self.ast_objs));
+                self.discard_mode_call(|g| g.gen_expr(expr));
+                let results_begin = expr_ctx!(self).cur_reg;
+                let rhs_types = self.t.expr_tuple_tc_types(expr);
+                for (i, t) in rhs_types.into_iter().enumerate() {
+                    let va = VirtualAddr::Direct(Addr::LocalVar(i));
+                    self.store_mode_call(va, Some(types[i]), |g| {
+                        g.cur_expr_emit_direct_assign(t, Addr::Regsiter(results_begin + i), pos)
+                    });
+                }
+            } else {
+                for (i, expr) in rstmt.results.iter().enumerate() {
+                    let va = VirtualAddr::Direct(Addr::LocalVar(i));
+                    self.store_mode_call(va, Some(types[i]), |g| g.gen_expr(expr));
+                }
             }
         }
         func_ctx!(self).emit_return(None, Some(rstmt.ret), &self.vmctx.functions());
@@ -2213,6 +2607,8 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     }
 
     fn visit_stmt_type_switch(&mut self, tstmt: &TypeSwitchStmt) {
+        self.branch_helper.enter_block(false);
+
         if let Some(init) = &tstmt.init {
             self.visit_stmt(init);
         }
@@ -2267,6 +2663,8 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         };
 
         self.gen_switch_body(&*tstmt.body, tag_dst, ValueType::Metadata, local_var_info);
+
+        self.branch_helper.leave_block(func_ctx!(self), None);
     }
 
     fn visit_stmt_comm(&mut self, _cclause: &CommClause) {
@@ -2398,15 +2796,21 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
         } else {
             None
         };
+        let first_local = func_ctx!(self).local_count();
+        func_ctx!(self).loop_depth += 1;
         self.visit_stmt_block(&fstmt.body);
+        func_ctx!(self).loop_depth -= 1;
+        // the next iteration gets new vars for the ones of the body that are captured,
+        // "continue" jumps to where they are closed if there are any
+        let closing = func_ctx!(self).emit_close_locals(first_local, Some(fstmt.for_pos));
         let continue_marker = if let Some(post) = &fstmt.post {
             // "continue" jumps to post statements
             let m = func_ctx!(self).next_code_index();
             self.visit_stmt(post);
-            m
+            closing.unwrap_or(m)
         } else {
             // "continue" jumps to top directly if no post statements
-            top_marker
+            closing.unwrap_or(top_marker)
         };
 
         // jump to the top
@@ -2423,12 +2827,20 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             let offset = fctx.offset(m) - 1;
             fctx.inst_mut(m).d = Addr::Imm(offset);
         }
+        // for "break", so entering the loop again doesn't share the vars either
+        if closing.is_some() {
+            func_ctx!(self).emit_close_locals(first_local, Some(fstmt.for_pos));
+        }
 
         self.branch_helper
             .leave_block(func_ctx!(self), Some(continue_marker));
     }
 
     fn visit_stmt_range(&mut self, rstmt: &RangeStmt) {
+        if self.t.expr_value_type(&rstmt.expr) == ValueType::Channel {
+            self.unsupported(rstmt.for_pos, "range over a channel");
+            return;
+        }
         self.branch_helper.enter_block(true);
 
         let blank = Expr::Ident(self.blank_ident);
@@ -2440,7 +2852,12 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             .gen_assign(&rstmt.token, &lhs, RightHandSide::Range(&rstmt.expr))
             .unwrap();
 
+        let first_local = func_ctx!(self).local_count();
+        func_ctx!(self).loop_depth += 1;
         self.visit_stmt_block(&rstmt.body);
+        func_ctx!(self).loop_depth -= 1;
+        // like in visit_stmt_for, with "continue" jumping to where they are closed
+        let closing = func_ctx!(self).emit_close_locals(first_local, Some(rstmt.token_pos));
         // jump to the top
         let fctx = func_ctx!(self);
         let offset = -fctx.offset(marker) - 1;
@@ -2451,9 +2868,12 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
             InterInst::with_op_index(Opcode::JUMP, Addr::Imm(offset), Addr::Void, Addr::Void),
             Some(rstmt.token_pos),
         );
+        if closing.is_some() {
+            func_ctx!(self).emit_close_locals(first_local, Some(rstmt.token_pos));
+        }
 
         self.branch_helper
-            .leave_block(func_ctx!(self), Some(marker));
+            .leave_block(func_ctx!(self), Some(closing.unwrap_or(marker)));
     }
 
     fn visit_expr_stmt(&mut self, e: &Expr) {
@@ -2471,6 +2891,30 @@ impl<'a, 'c> StmtVisitor for CodeGen<'a, 'c> {
     }
 }
 
+/// Whether evaluating `expr` calls a function or receives from a channel, the
+/// operations Go evaluates in the lexical order
+fn has_call_or_recv(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(_) => true,
+        Expr::Unary(u) => u.op == Token::ARROW || has_call_or_recv(&u.expr),
+        Expr::Paren(p) => has_call_or_recv(&p.expr),
+        Expr::Selector(s) => has_call_or_recv(&s.expr),
+        Expr::Index(i) => has_call_or_recv(&i.expr) || has_call_or_recv(&i.index),
+        Expr::Slice(s) => {
+            has_call_or_recv(&s.expr)
+                || [&s.low, &s.high, &s.max]
+                    .iter()
+                    .any(|x| x.as_ref().is_some_and(has_call_or_recv))
+        }
+        Expr::TypeAssert(t) => has_call_or_recv(&t.expr),
+        Expr::Star(s) => has_call_or_recv(&s.expr),
+        Expr::Binary(b) => has_call_or_recv(&b.expr_a) || has_call_or_recv(&b.expr_b),
+        Expr::KeyValue(kv) => has_call_or_recv(&kv.key) || has_call_or_recv(&kv.val),
+        Expr::CompositeLit(c) => c.elts.iter().any(has_call_or_recv),
+        _ => false,
+    }
+}
+
 pub type IfaceSelector = Selector<(TCTypeKey, TCTypeKey)>;
 
 pub type StructSelector = Selector<Vec<OpIndex>>;
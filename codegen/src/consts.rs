@@ -55,6 +55,15 @@ impl Consts {
         self.add(Const::Method(obj_type, index))
     }
 
+    /// The type of a const, `None` for methods, which are resolved later
+    #[cfg(feature = "emit_check")]
+    pub fn value_type(&self, index: usize) -> Option<ValueType> {
+        match &self.consts.borrow()[index] {
+            Const::Nil(v) | Const::Comparable(v) | Const::ZeroVal(v, _) => Some(v.typ()),
+            Const::Method(..) => None,
+        }
+    }
+
     fn add(&self, c: Const) -> usize {
         let mut borrow = self.consts.borrow_mut();
         let index = borrow.len();
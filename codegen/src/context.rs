@@ -9,6 +9,7 @@ use go_parser::{AstObjects, IdentKey, Map, Pos};
 use go_types::{ObjKey as TCObjKey, TypeKey as TCTypeKey};
 use go_vm::types::*;
 use go_vm::*;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -46,24 +47,38 @@ impl Addr {
         inst_index: usize,
         labels: &Map<TCObjKey, usize>,
         cst_map: &Map<usize, usize>,
-    ) -> OpIndex {
+    ) -> Result<OpIndex, String> {
+        let local = |i: usize| {
+            if i <= MAX_OPERAND {
+                Ok(i as OpIndex)
+            } else {
+                Err(format!(
+                    "too many local variables in function (max {})",
+                    MAX_OPERAND
+                ))
+            }
+        };
         // Zero values are the first batch of consts
         match self {
-            Self::Const(i) => -(cst_map[&i] as OpIndex) - 1,
-            Self::LocalVar(i) => i as OpIndex,
-            Self::Regsiter(i) => (reg_base + i) as OpIndex,
+            Self::Const(i) => match cst_map[&i] {
+                i if i <= MAX_OPERAND => Ok(-(i as OpIndex) - 1),
+                _ => Err(format!("too many constants (max {})", MAX_OPERAND)),
+            },
+            Self::LocalVar(i) => local(i),
+            Self::Regsiter(i) => local(reg_base + i),
             Self::PkgMemberIndex(key, ident) => {
                 let pkg = &packages[key];
                 let id = &ast_objs.idents[ident];
-                *pkg.member_index(&id.name).unwrap()
+                Ok(*pkg.member_index(&id.name).unwrap())
             }
+            // the function is no longer than MAX_OPERAND
             Self::Label(key) => {
                 let label_offset = labels[&key];
-                (label_offset as OpIndex) - (inst_index as OpIndex) - 1
+                Ok((label_offset as OpIndex) - (inst_index as OpIndex) - 1)
             }
-            Self::Imm(i) => i,
+            Self::Imm(i) => Ok(i),
             Self::UntypedNil => unreachable!(),
-            Self::Void => std::i32::MAX,
+            Self::Void => Ok(i32::MAX),
         }
     }
 }
@@ -309,22 +324,22 @@ impl InterInst {
         inst_index: usize,
         labels: &Map<TCObjKey, usize>,
         cst_map: &Map<usize, usize>,
-    ) -> Instruction {
-        Instruction {
+    ) -> Result<Instruction, String> {
+        Ok(Instruction {
             op0: self.op0,
             op1: self.op1,
             t0: self.t0,
             t1: self.t1,
             d: self
                 .d
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
+                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map)?,
             s0: self
                 .s0
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
+                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map)?,
             s1: self
                 .s1
-                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map),
-        }
+                .into_index(reg_base, ast_objs, packages, inst_index, labels, cst_map)?,
+        })
     }
 }
 
@@ -360,11 +375,17 @@ pub(crate) struct FuncCtx<'c> {
     code: Vec<InterInst>,
     pos: Vec<Option<usize>>,
     pub up_ptrs: Vec<ValueDesc>,
+    // the number of the for statements the code being generated is in
+    pub loop_depth: usize,
     local_zeros: Vec<GosValue>,
 
     entities: Map<TCObjKey, Addr>,
     uv_entities: Map<TCObjKey, Addr>,
     local_alloc: usize,
+    // the locals closures or pointers refer to
+    captured: HashSet<usize>,
+    #[cfg(feature = "emit_check")]
+    pub(crate) checker: super::check::EmitChecker,
 }
 
 impl<'a> FuncCtx<'a> {
@@ -376,13 +397,32 @@ impl<'a> FuncCtx<'a> {
             code: vec![],
             pos: vec![],
             up_ptrs: vec![],
+            loop_depth: 0,
             local_zeros: vec![],
             entities: Map::new(),
             uv_entities: Map::new(),
             local_alloc: 0,
+            captured: HashSet::new(),
+            #[cfg(feature = "emit_check")]
+            checker: super::check::EmitChecker::new(),
         }
     }
 
+    /// Names the function in the diagnostics of the `emit_check` feature
+    pub fn set_name(&mut self, _name: &str) {
+        #[cfg(feature = "emit_check")]
+        self.checker.set_name(_name);
+    }
+
+    /// The instructions emitted with operands of the wrong types, with their positions,
+    /// always empty without the `emit_check` feature.
+    pub fn take_internal_errors(&mut self) -> Vec<(Option<usize>, String)> {
+        #[cfg(feature = "emit_check")]
+        return self.checker.take_errors();
+        #[cfg(not(feature = "emit_check"))]
+        vec![]
+    }
+
     pub fn is_ctor(&self, funcs: &FunctionObjs) -> bool {
         funcs[self.f_key].is_ctor()
     }
@@ -439,12 +479,25 @@ impl<'a> FuncCtx<'a> {
         };
         self.local_alloc += 1;
 
+        #[cfg(feature = "emit_check")]
+        if let Some(zero) = &zero_val {
+            self.checker.add_local(addr.as_var_index(), zero.typ());
+        }
         if let Some(zero) = zero_val {
             self.local_zeros.push(zero);
         }
         addr
     }
 
+    /// The number of the locals added so far, the index of the next one
+    pub fn local_count(&self) -> usize {
+        self.local_alloc
+    }
+
+    pub fn set_captured(&mut self, local: usize) {
+        self.captured.insert(local);
+    }
+
     pub(crate) fn add_upvalue(&mut self, entity: &TCObjKey, uv: ValueDesc) -> VirtualAddr {
         let addr = match self.uv_entities.get(entity) {
             Some(i) => *i,
@@ -501,6 +554,15 @@ impl<'a> FuncCtx<'a> {
             VirtualAddr::Direct(l) => {
                 direct = true;
                 match op_ex {
+                    // there are no in-place versions of the checked opcodes
+                    Some((
+                        op @ (Opcode::ADD_CHECKED
+                        | Opcode::SUB_CHECKED
+                        | Opcode::MUL_CHECKED
+                        | Opcode::SHL_CHECKED),
+                        t0,
+                        t1,
+                    )) => InterInst::with_op_t_index(op, Some(t0), t1, l, l, rhs),
                     Some((op, t0, t1)) => {
                         let ass_op = match op {
                             Opcode::ADD => Opcode::ADD_ASSIGN,         // +=
@@ -616,6 +678,25 @@ impl<'a> FuncCtx<'a> {
         self.emit_inst(inst, pos);
     }
 
+    pub fn emit_cast_checked(
+        &mut self,
+        d: Addr,
+        s0: Addr,
+        to_type: ValueType,
+        from_type: ValueType,
+        pos: Option<usize>,
+    ) {
+        let inst = InterInst::with_op_t_index(
+            Opcode::CAST_CHECKED,
+            Some(to_type),
+            Some(from_type),
+            d,
+            s0,
+            Addr::Void,
+        );
+        self.emit_inst(inst, pos);
+    }
+
     pub fn emit_cast_iface(&mut self, d: Addr, s: Addr, index: OpIndex, pos: Option<usize>) {
         self.emit_cast(
             d,
@@ -633,16 +714,38 @@ impl<'a> FuncCtx<'a> {
         self.emit_inst(inst, pos);
     }
 
+    /// Emits a CLOSE_UP_VALUE for each local from `first` on that closures or pointers
+    /// refer to, returns the index of the first one
+    pub fn emit_close_locals(&mut self, first: usize, pos: Option<usize>) -> Option<usize> {
+        let begin = self.next_code_index();
+        for i in first..self.local_alloc {
+            if self.captured.contains(&i) {
+                let inst = InterInst::with_op_index(
+                    Opcode::CLOSE_UP_VALUE,
+                    Addr::Void,
+                    Addr::LocalVar(i),
+                    Addr::Void,
+                );
+                self.emit_inst(inst, pos);
+            }
+        }
+        (self.next_code_index() > begin).then_some(begin)
+    }
+
     pub fn emit_jump(&mut self, offset: OpIndex, pos: Option<usize>) {
         let inst =
             InterInst::with_op_index(Opcode::JUMP, Addr::Imm(offset), Addr::Void, Addr::Void);
         self.emit_inst(inst, pos);
     }
 
+    /// `recv_slot` is whether the args are after a slot left for the receiver, which is
+    /// only known when a method is called directly, a method value called like any
+    /// other func value has its args where the receiver goes.
     pub fn emit_call(
         &mut self,
         cls: Addr,
         stack_base: usize,
+        recv_slot: bool,
         style: CallStyle,
         pos: Option<usize>,
     ) {
@@ -650,7 +753,7 @@ impl<'a> FuncCtx<'a> {
         let inst = InterInst::with_op_t_index(
             Opcode::CALL,
             Some(flag),
-            None,
+            recv_slot.then_some(ValueType::FlagA),
             cls,
             Addr::Regsiter(stack_base),
             Addr::Void,
@@ -723,7 +826,15 @@ impl<'a> FuncCtx<'a> {
         vmctx: &mut CodeGenVMCtx,
         labels: &Map<TCObjKey, usize>,
         cst_map: &Map<usize, usize>,
-    ) {
+    ) -> Result<(), String> {
+        // jump offsets, and the counts of the things with at least an instruction
+        // each, can't get larger than the function
+        if self.code.len() > MAX_OPERAND {
+            return Err(format!(
+                "function too long (max {} instructions)",
+                MAX_OPERAND
+            ));
+        }
         let code: Vec<Instruction> = self
             .code
             .into_iter()
@@ -731,7 +842,7 @@ impl<'a> FuncCtx<'a> {
             .map(|(i, x)| {
                 x.into_runtime_inst(self.local_alloc, asto, vmctx.packages(), i, labels, cst_map)
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         let func = &mut vmctx.functions_mut()[self.f_key];
         func.pos = self
             .pos
@@ -747,9 +858,17 @@ impl<'a> FuncCtx<'a> {
         func.max_write_index = Instruction::max_write_index(&code);
         func.local_zeros = self.local_zeros;
         func.code = code;
+        Ok(())
+    }
+
+    /// The position of the first instruction that has one
+    pub fn first_pos(&self) -> Option<usize> {
+        self.pos.iter().find_map(|&p| p)
     }
 
     pub fn emit_inst(&mut self, i: InterInst, pos: Option<usize>) {
+        #[cfg(feature = "emit_check")]
+        self.checker.check(&i, pos, self.consts);
         self.code.push(i);
         self.pos.push(pos);
     }
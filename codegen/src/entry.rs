@@ -16,7 +16,9 @@ use go_types::{
 };
 use go_vm::types::*;
 use go_vm::*;
+use std::collections::HashSet;
 use std::path::Path;
+use std::rc::Rc;
 use std::vec;
 
 pub fn parse_check_gen<S: SourceRead>(
@@ -24,49 +26,270 @@ pub fn parse_check_gen<S: SourceRead>(
     tconfig: &TraceConfig,
     reader: &S,
     debug_info: bool,
+    checked_arithmetic: bool,
+    soft_errors: Option<&ErrorList>,
+    warnings: Option<&ErrorList>,
 ) -> Result<Bytecode, ErrorList> {
+    parse_check_gen_entries(
+        &[path],
+        tconfig,
+        reader,
+        debug_info,
+        checked_arithmetic,
+        soft_errors,
+        warnings,
+    )
+    .map(|x| x.code)
+}
+
+/// The result of compiling more than one main package into a single Bytecode,
+/// the packages imported by more than one of them are only compiled once.
+pub struct MultiEntryCode {
+    pub code: Bytecode,
+    /// The entry functions and the main packages, in the order of the input paths
+    pub entries: Vec<(FunctionKey, PackageKey)>,
+    /// Maps import paths to all the packages in the Bytecode
+    pub packages: Map<String, PackageKey>,
+}
+
+/// With `checked_arithmetic`, integer overflows in the main packages and the
+/// locally imported packages panic instead of wrapping around. The std library
+/// relies on wrapping, it's always compiled without the checks.
+///
+/// With `soft_errors`, the soft errors of the type checker, e.g. unused variables
+/// and imports, don't stop the compilation, they are moved to it instead.
+///
+/// With `warnings`, the code of the same packages is also checked for `defer`
+/// statements inside loops, and a soft error is added to it for each of them.
+///
+/// Constructs the code generator can't compile are reported as errors at their
+/// positions, it never panics on a program that passed the type checker.
+pub fn parse_check_gen_entries<S: SourceRead>(
+    paths: &[&Path],
+    tconfig: &TraceConfig,
+    reader: &S,
+    debug_info: bool,
+    checked_arithmetic: bool,
+    soft_errors: Option<&ErrorList>,
+    warnings: Option<&ErrorList>,
+) -> Result<MultiEntryCode, ErrorList> {
     let mut fset = FileSet::new();
 
     let ast_objs = &mut AstObjects::new();
     let tc_objs = &mut TCObjects::new();
     let results = &mut Map::new();
     let pkgs = &mut Map::new();
-    let el = ErrorList::new();
+    let mut el = ErrorList::new();
 
-    let importer = &mut Importer::new(
-        &tconfig, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
-    );
-    let key = ImportKey::new(
-        path.to_str().unwrap(),
-        reader.working_dir().to_str().unwrap(),
-    );
-    let main_pkg = importer.import(&key);
+    let keys: Vec<ImportKey> = paths
+        .iter()
+        .map(|p| ImportKey::new(p.to_str().unwrap(), reader.working_dir().to_str().unwrap()))
+        .collect();
+    let mut main_pkgs = vec![];
+    {
+        let importer = &mut Importer::new(
+            &tconfig, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
+        );
+        for key in keys.iter() {
+            main_pkgs.push(importer.import(key));
+        }
+    }
     if el.len() > 0 {
-        Err(el)
-    } else {
-        let blank_ident = ast_objs.idents.insert(Ident::blank(0));
-        let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
-        Ok(gen_byte_code(
-            ast_objs,
-            tc_objs,
-            results,
-            main_pkg.unwrap(),
-            main_ident,
-            blank_ident,
-            debug_info.then_some(fset),
-        ))
+        match soft_errors {
+            Some(soft) if el.borrow().iter().all(|e| e.soft) => {
+                for e in el.borrow().iter() {
+                    soft.add(Some(e.pos.clone()), e.msg.clone(), true, e.by_parser);
+                    for n in e.notes.iter() {
+                        soft.add_note(n.pos.clone(), n.label.clone());
+                    }
+                }
+                el = ErrorList::new();
+            }
+            _ => return Err(el),
+        }
+    }
+    let main_pkgs: Vec<TCPackageKey> = main_pkgs.into_iter().map(|x| x.unwrap()).collect();
+    for (i, &pkg) in main_pkgs.iter().enumerate() {
+        check_entry(pkg, &fset, tc_objs, &results[&pkg], &el);
+        if let Some(j) = main_pkgs[..i].iter().position(|&x| x == pkg) {
+            el.add(
+                None,
+                format!(
+                    "entries {} and {} are the same package",
+                    keys[j].path, keys[i].path
+                ),
+                false,
+                false,
+            );
+        }
+    }
+    if el.len() > 0 {
+        return Err(el);
+    }
+    check_overlap(&keys, &main_pkgs, &fset, tc_objs, results, &el);
+    if el.len() > 0 {
+        return Err(el);
+    }
+
+    let blank_ident = ast_objs.idents.insert(Ident::blank(0));
+    let main_ident = ast_objs.idents.insert(Ident::with_str(0, "main"));
+    let exit_ident = ast_objs.idents.insert(Ident::with_str(0, "Run"));
+    let (code, entries, pkg_map) = gen_byte_code(
+        ast_objs,
+        tc_objs,
+        results,
+        &main_pkgs,
+        main_ident,
+        exit_ident,
+        blank_ident,
+        fset,
+        debug_info,
+        checked_arithmetic,
+        warnings,
+    )?;
+    let packages = pkgs
+        .iter()
+        .filter_map(|(path, tcpkg)| pkg_map.get(tcpkg).map(|&pkey| (path.clone(), pkey)))
+        .collect();
+    Ok(MultiEntryCode {
+        code,
+        entries,
+        packages,
+    })
+}
+
+/// Reports the violations of "the main package must have package name main and
+/// declare a function main that takes no arguments and returns no value", at the
+/// package clause or the declaration of main.
+fn check_entry(
+    pkg: TCPackageKey,
+    fset: &FileSet,
+    tc_objs: &TCObjects,
+    result: &TypeInfo,
+    el: &ErrorList,
+) {
+    let package = &tc_objs.pkgs[pkg];
+    let clause = result.ast_files.first().map(|f| f.package).unwrap_or(0);
+    if package.name().as_deref() != Some("main") {
+        el.add(
+            fset.position(clause),
+            "runnable program must be package main".to_owned(),
+            false,
+            false,
+        );
+        return;
+    }
+    let main = tc_objs.scopes[*package.scope()]
+        .lookup("main")
+        .map(|&okey| &tc_objs.lobjs[okey])
+        .filter(|obj| obj.entity_type().is_func());
+    match main {
+        None => el.add(
+            fset.position(clause),
+            "function main is undeclared in the main package".to_owned(),
+            false,
+            false,
+        ),
+        Some(obj) => {
+            let sig = obj
+                .typ()
+                .and_then(|t| tc_objs.types[t].try_as_signature())
+                .unwrap();
+            if sig.params_count(tc_objs) > 0 || sig.results_count(tc_objs) > 0 {
+                el.add(
+                    fset.position(obj.pos()),
+                    "function main must have no arguments and no return values".to_owned(),
+                    false,
+                    false,
+                );
+            }
+        }
     }
 }
 
+/// Reports the entries whose globals would not be their own: an entry that
+/// imports the package of another one, directly or not, and a file that is in
+/// the packages of two entries, e.g. "./p/a.gos" and "./p", which declares its
+/// names in both of them.
+fn check_overlap(
+    keys: &[ImportKey],
+    main_pkgs: &[TCPackageKey],
+    fset: &FileSet,
+    tc_objs: &TCObjects,
+    results: &Map<TCPackageKey, TypeInfo>,
+    el: &ErrorList,
+) {
+    let file_names = |pkg: TCPackageKey| -> Vec<(Rc<String>, usize)> {
+        results[&pkg]
+            .ast_files
+            .iter()
+            .filter_map(|f| fset.position(f.package).map(|p| (p.filename, f.package)))
+            .collect()
+    };
+    for (i, &pkg) in main_pkgs.iter().enumerate() {
+        let mut imported = HashSet::new();
+        let mut stack = tc_objs.pkgs[pkg].imports().clone();
+        while let Some(p) = stack.pop() {
+            if imported.insert(p) {
+                stack.extend(tc_objs.pkgs[p].imports().iter().copied());
+            }
+        }
+        for (j, other) in main_pkgs.iter().enumerate() {
+            if i != j && imported.contains(other) {
+                el.add(
+                    None,
+                    format!(
+                        "entry {} imports the package of entry {}",
+                        keys[i].path, keys[j].path
+                    ),
+                    false,
+                    false,
+                );
+            }
+        }
+        let files = file_names(pkg);
+        for (j, &other) in main_pkgs[..i].iter().enumerate() {
+            let other_files = file_names(other);
+            for (name, clause) in files.iter() {
+                if other_files.iter().any(|(n, _)| n == name) {
+                    el.add(
+                        fset.position(*clause),
+                        format!(
+                            "file {} is in both entries {} and {}, its names would be declared twice",
+                            name, keys[j].path, keys[i].path
+                        ),
+                        false,
+                        false,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The bytecode, the entry functions of the main packages with their packages, and
+/// the packages of the type checked ones
+type GenResult = (
+    Bytecode,
+    Vec<(FunctionKey, PackageKey)>,
+    Map<TCPackageKey, PackageKey>,
+);
+
+// the idents and the flags are each made or passed through by its one caller
+#[allow(clippy::too_many_arguments)]
 fn gen_byte_code(
     ast_objs: &AstObjects,
     tc_objs: &TCObjects,
     checker_result: &Map<TCPackageKey, TypeInfo>,
-    tc_main_pkg: TCPackageKey,
+    tc_main_pkgs: &[TCPackageKey],
     main_ident: IdentKey,
+    exit_ident: IdentKey,
     blank_ident: IdentKey,
-    fset: Option<FileSet>,
-) -> Bytecode {
+    fset: FileSet,
+    debug_info: bool,
+    checked_arithmetic: bool,
+    warnings: Option<&ErrorList>,
+) -> Result<GenResult, ErrorList> {
     let vm_objs = VMObjects::new();
     let mut vmctx = CodeGenVMCtx::new(vm_objs);
     let consts = Consts::new();
@@ -76,6 +299,8 @@ fn gen_byte_code(
     let mut type_cache: TypeCache = Map::new();
     let mut branch_helper = BranchHelper::new();
     let mut result_funcs = vec![];
+    let mut lints = vec![];
+    let mut errors = vec![];
 
     for (&tcpkg, _) in checker_result.iter() {
         let name = tc_objs.pkgs[tcpkg].name().clone().unwrap();
@@ -83,12 +308,25 @@ fn gen_byte_code(
         pkg_map.insert(tcpkg, pkey);
     }
 
-    let main_pkg = pkg_map[&tc_main_pkg];
-    let entry = gen_entry_func(&mut vmctx, &consts, main_pkg, main_ident);
-    let entry_key = entry.f_key;
-    result_funcs.push(entry);
+    // the std internal/exithook package runs the hooks added by other packages
+    // after main returns, it's only there if some package uses it
+    let exit_pkg = checker_result
+        .keys()
+        .find(|&&k| tc_objs.pkgs[k].path() == "internal/exithook")
+        .map(|k| (pkg_map[k], exit_ident));
+    let mut entries = vec![];
+    for tc_main_pkg in tc_main_pkgs.iter() {
+        let main_pkg = pkg_map[tc_main_pkg];
+        let entry = gen_entry_func(&mut vmctx, &consts, main_pkg, main_ident, exit_pkg);
+        entries.push((entry.f_key, main_pkg));
+        result_funcs.push(entry);
+    }
 
     for (tcpkg, ti) in checker_result.iter() {
+        let user_code =
+            tc_main_pkgs.contains(tcpkg) || is_local_import(tc_objs.pkgs[*tcpkg].path());
+        let checked = checked_arithmetic && user_code;
+        let lints = (warnings.is_some() && user_code).then_some(&mut lints);
         let mut pkg_helper = PkgHelper::new(ast_objs, tc_objs, &pkg_map);
         let cgen = CodeGen::new(
             &mut vmctx,
@@ -103,13 +341,41 @@ fn gen_byte_code(
             &mut pkg_helper,
             pkg_map[tcpkg],
             blank_ident,
+            checked,
+            lints,
+            &mut errors,
         );
         result_funcs.append(&mut cgen.gen_with_files(&ti.ast_files, *tcpkg));
     }
+    if !errors.is_empty() {
+        let el = ErrorList::new();
+        for (pos, msg) in errors.into_iter() {
+            el.add(fset.position(pos), msg, false, false);
+        }
+        return Err(el);
+    }
+
+    if let Some(el) = warnings {
+        for (pos, msg) in lints.into_iter() {
+            el.add(fset.position(pos), msg.to_owned(), true, false);
+        }
+    }
 
     let (consts, cst_map) = consts.get_runtime_consts(&mut vmctx);
-    for f in result_funcs.into_iter() {
-        f.into_runtime_func(ast_objs, &mut vmctx, branch_helper.labels(), &cst_map);
+    let el = ErrorList::new();
+    for mut f in result_funcs.into_iter() {
+        for (pos, msg) in f.take_internal_errors() {
+            el.add(pos.and_then(|p| fset.position(p)), msg, false, false);
+        }
+        let pos = f.first_pos();
+        if let Err(msg) =
+            f.into_runtime_func(ast_objs, &mut vmctx, branch_helper.labels(), &cst_map)
+        {
+            el.add(pos.and_then(|p| fset.position(p)), msg, false, false);
+        }
+    }
+    if el.len() > 0 {
+        return Err(el);
     }
 
     let dummy_ti = TypeInfo::new();
@@ -120,15 +386,21 @@ fn gen_byte_code(
         .map(|x| lookup.iface_binding_info(x, &mut vmctx))
         .collect();
 
-    Bytecode::new(
+    let (entry_key, main_pkg) = entries[0];
+    let code = Bytecode::new(
         vmctx.into_vmo(),
         consts,
         iface_binding,
         struct_selector.result(),
         entry_key,
         main_pkg,
-        fset,
-    )
+        debug_info.then_some(fset),
+    );
+    Ok((code, entries, pkg_map))
+}
+
+fn is_local_import(path: &str) -> bool {
+    path.starts_with('.') || Path::new(path).is_absolute()
 }
 
 // generate the entry function for Bytecode
@@ -137,16 +409,25 @@ fn gen_entry_func<'a, 'c>(
     consts: &'c Consts,
     pkg: PackageKey,
     main_ident: IdentKey,
+    exit: Option<(PackageKey, IdentKey)>,
 ) -> FuncCtx<'c> {
     let fmeta = vmctx.prim_meta().default_sig;
     let fobj = vmctx.function_with_meta(None, fmeta.clone(), FuncFlag::Default);
     let fkey = *fobj.as_function();
     let mut fctx = FuncCtx::new(fkey, None, consts);
+    fctx.set_name("<entry>");
     fctx.emit_import(pkg, None);
     let pkg_addr = fctx.add_package(pkg);
     let index = Addr::PkgMemberIndex(pkg, main_ident);
     fctx.emit_load_pkg(Addr::Regsiter(0), pkg_addr, index, None);
-    fctx.emit_call(Addr::Regsiter(0), 0, CallStyle::Default, None);
+    fctx.emit_call(Addr::Regsiter(0), 0, false, CallStyle::Default, None);
+    if let Some((exit_pkg, exit_ident)) = exit {
+        fctx.emit_import(exit_pkg, None);
+        let exit_addr = fctx.add_package(exit_pkg);
+        let index = Addr::PkgMemberIndex(exit_pkg, exit_ident);
+        fctx.emit_load_pkg(Addr::Regsiter(0), exit_addr, index, None);
+        fctx.emit_call(Addr::Regsiter(0), 0, false, CallStyle::Default, None);
+    }
     fctx.emit_return(None, None, vmctx.functions());
     fctx
 }
@@ -7,8 +7,12 @@
 //! # Feature
 //! - `async`: Channel and goroutine support
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
+//! - `emit_check`: Check the types of the instructions against their operands when
+//!   emitting them, panicking in debug builds and reporting errors in release builds
 
 mod branch;
+#[cfg(feature = "emit_check")]
+mod check;
 mod consts;
 mod context;
 //mod emit;
@@ -18,5 +22,5 @@ mod codegen;
 mod entry;
 mod types;
 
-pub use entry::parse_check_gen;
+pub use entry::{parse_check_gen, parse_check_gen_entries, MultiEntryCode};
 pub use go_types::{SourceRead, TraceConfig};
@@ -137,7 +137,13 @@ impl<'a> TypeLookup<'a> {
         vmctx: &mut CodeGenVMCtx,
     ) -> (ValueType, TCTypeKey) {
         let tc_type = self.expr_tc_type(&e);
-        let typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        let mut typ = self.tc_objs.types[tc_type].underlying().unwrap_or(tc_type);
+        // a pointer to an array is indexed and sliced like the array
+        if let Some(p) = self.tc_objs.types[typ].try_as_pointer() {
+            typ = self.tc_objs.types[p.base()]
+                .underlying()
+                .unwrap_or(p.base());
+        }
         let meta = self.tc_type_to_meta(typ, vmctx);
         let metas = &vmctx.metas();
         match &metas[meta.key] {
@@ -262,12 +268,44 @@ impl<'a> TypeLookup<'a> {
 
     pub fn tc_type_to_meta(&mut self, typ: TCTypeKey, vmctx: &mut CodeGenVMCtx) -> Meta {
         if !self.types_cache.contains_key(&typ) {
-            let val = self.tc_type_to_meta_impl(typ, vmctx);
+            let val = match self.identical_cached(typ) {
+                Some(meta) => meta,
+                None => self.tc_type_to_meta_impl(typ, vmctx),
+            };
             self.types_cache.insert(typ, val);
         }
         self.types_cache.get(&typ).unwrap().clone()
     }
 
+    /// The meta of a cached type identical to `typ`, if `typ` is a composite type
+    /// literal. Every spelling of such a type gets its own TCTypeKey, they share
+    /// a Meta so that the values in interfaces can be told apart by Meta key.
+    fn identical_cached(&self, typ: TCTypeKey) -> Option<Meta> {
+        let composite = |t: &Type| {
+            matches!(
+                t,
+                Type::Array(_)
+                    | Type::Slice(_)
+                    | Type::Map(_)
+                    | Type::Struct(_)
+                    | Type::Interface(_)
+                    | Type::Chan(_)
+            )
+        };
+        let this = &self.tc_objs.types[typ];
+        if !composite(this) {
+            return None;
+        }
+        self.types_cache
+            .iter()
+            .find(|(&k, _)| {
+                let other = &self.tc_objs.types[k];
+                std::mem::discriminant(this) == std::mem::discriminant(other)
+                    && go_types::typ::identical(typ, k, self.tc_objs)
+            })
+            .map(|(_, m)| *m)
+    }
+
     pub fn sig_params_tc_types(&self, func: TCTypeKey) -> (Vec<TCTypeKey>, Option<TCTypeKey>) {
         let typ = &self.tc_objs.types[func].underlying_val(self.tc_objs);
         let sig = typ.try_as_signature().unwrap();
@@ -313,6 +351,27 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// Whether the expression is the predeclared nil, which the checker types as what
+    /// it's converted to
+    pub fn is_nil(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Ident(ikey) => self
+                .ti
+                .uses
+                .get(ikey)
+                .is_some_and(|o| self.tc_objs.lobjs[*o].entity_type().is_nil()),
+            Expr::Paren(p) => self.is_nil(&p.expr),
+            _ => false,
+        }
+    }
+
+    pub fn is_method_expr(&self, id: NodeId) -> bool {
+        match self.ti.selections.get(&id) {
+            Some(sel) => matches!(sel.kind(), TCSelectionKind::MethodExpr),
+            None => false,
+        }
+    }
+
     // returns vm_type(metadata) for the tc_type
     pub fn basic_type_meta(&self, tkey: TCTypeKey, prim_meta: &PrimitiveMeta) -> Option<Meta> {
         self.tc_objs.types[tkey].try_as_basic().map(|x| {
@@ -324,7 +383,8 @@ impl<'a> TypeLookup<'a> {
                 BasicType::Int16 => prim_meta.mint16,
                 BasicType::Int32 | BasicType::Rune | BasicType::UntypedRune => prim_meta.mint32,
                 BasicType::Int64 => prim_meta.mint64,
-                BasicType::Uint | BasicType::Uintptr => prim_meta.muint,
+                BasicType::Uint => prim_meta.muint,
+                BasicType::Uintptr => prim_meta.muint_ptr,
                 BasicType::Uint8 | BasicType::Byte => prim_meta.muint8,
                 BasicType::Uint16 => prim_meta.muint16,
                 BasicType::Uint32 => prim_meta.muint32,
@@ -332,7 +392,7 @@ impl<'a> TypeLookup<'a> {
                 BasicType::Float32 => prim_meta.mfloat32,
                 BasicType::Float64 | BasicType::UntypedFloat => prim_meta.mfloat64,
                 BasicType::Complex64 => prim_meta.mcomplex64,
-                BasicType::Complex128 => prim_meta.mcomplex128,
+                BasicType::Complex128 | BasicType::UntypedComplex => prim_meta.mcomplex128,
                 BasicType::Str | BasicType::UntypedString => prim_meta.mstr,
                 BasicType::UnsafePointer => prim_meta.unsafe_ptr,
                 BasicType::UntypedNil => prim_meta.none,
@@ -385,7 +445,7 @@ impl<'a> TypeLookup<'a> {
         match &self.tc_objs.types[typ] {
             Type::Basic(_) => self.basic_type_meta(typ, vmctx.prim_meta()).unwrap(),
             Type::Array(detail) => {
-                let elem = self.tc_type_to_meta_impl(detail.elem(), vmctx);
+                let elem = self.tc_type_to_meta(detail.elem(), vmctx);
                 Meta::new_array(elem, detail.len().unwrap() as usize, vmctx.metas_mut())
             }
             Type::Slice(detail) => {
@@ -453,6 +513,15 @@ impl<'a> TypeLookup<'a> {
             Type::Named(detail) => {
                 // generate a Named with dummy underlying to avoid recursion
                 let md = Meta::new_named(vmctx.prim_meta().mint, vmctx.metas_mut());
+                if let Some(obj) = detail.obj() {
+                    let obj = &self.tc_objs.lobjs[*obj];
+                    let pkg = obj.pkg().and_then(|p| self.tc_objs.pkgs[p].name().as_ref());
+                    let name = match pkg {
+                        Some(pkg) => format!("{}.{}", pkg, obj.name()),
+                        None => obj.name().clone(),
+                    };
+                    vmctx.objects_mut().type_names.insert(md.key, name);
+                }
                 for key in detail.methods().iter() {
                     let mobj = &self.tc_objs.lobjs[*key];
                     md.add_method(
@@ -503,7 +572,7 @@ impl<'a> TypeLookup<'a> {
                 BasicType::Float32 => ValueType::Float32,
                 BasicType::Float64 | BasicType::UntypedFloat => ValueType::Float64,
                 BasicType::Complex64 => ValueType::Complex64,
-                BasicType::Complex128 => ValueType::Complex128,
+                BasicType::Complex128 | BasicType::UntypedComplex => ValueType::Complex128,
                 BasicType::Str | BasicType::UntypedString => ValueType::String,
                 BasicType::UnsafePointer => ValueType::UnsafePtr,
                 BasicType::UntypedNil => ValueType::Void,
@@ -548,7 +617,12 @@ impl<'a> TypeLookup<'a> {
 
     fn range_tc_types(&self, typ: TCTypeKey) -> [TCTypeKey; 3] {
         let t_int = self.tc_objs.universe().types()[&BasicType::Int];
-        let typ = self.tc_objs.types[typ].underlying().unwrap_or(typ);
+        let mut typ = self.tc_objs.types[typ].underlying().unwrap_or(typ);
+        // ranging over a pointer to an array ranges over the array
+        if let Type::Pointer(detail) = &self.tc_objs.types[typ] {
+            let base = detail.base();
+            typ = self.tc_objs.types[base].underlying().unwrap_or(base);
+        }
         match &self.tc_objs.types[typ] {
             Type::Basic(detail) => match detail.typ() {
                 BasicType::Str | BasicType::UntypedString => [typ, t_int, t_int],
@@ -607,6 +681,7 @@ impl<'a> TypeLookup<'a> {
                 meta,
                 name: field.name().clone(),
                 tag,
+                embedded,
                 embedded_indices: None,
             });
             if embedded {
@@ -627,6 +702,7 @@ impl<'a> TypeLookup<'a> {
                             meta: f.meta,
                             name: f.name.clone(),
                             tag: f.tag.clone(),
+                            embedded: f.embedded,
                             embedded_indices: Some(indices),
                         });
                     }
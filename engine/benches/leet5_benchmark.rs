@@ -5,29 +5,143 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
 extern crate go_engine as engine;
+#[path = "../tests/common/mod.rs"]
+mod common;
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
 #[cfg(feature = "go_std")]
-fn run(path: &str, trace: bool) -> Result<(), engine::ErrorList> {
+fn run(
+    path: &str,
+    trace: bool,
+    checked: bool,
+    max_interned_len: Option<usize>,
+    tiering_threshold: Option<u32>,
+) -> Result<(), engine::ErrorList> {
     let mut cfg = engine::Config::default();
     cfg.trace_parser = trace;
     cfg.trace_checker = trace;
+    cfg.checked_arithmetic = checked;
+    cfg.max_interned_len = max_interned_len;
+    cfg.tiering_threshold = tiering_threshold;
     let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
     engine::run(cfg, &sr, Path::new(path), None)
 }
 
 #[cfg(not(feature = "go_std"))]
-fn run(_path: &str, _trace: bool) -> Result<(), engine::ErrorList> {
+fn run(
+    _path: &str,
+    _trace: bool,
+    _checked: bool,
+    _max_interned_len: Option<usize>,
+    _tiering_threshold: Option<u32>,
+) -> Result<(), engine::ErrorList> {
     unimplemented!()
 }
 
-fn leetcode5() {
-    let errs = run("./tests/demo/leetcode5.gos", false);
+fn leetcode5(checked: bool) {
+    let errs = run("./tests/demo/leetcode5.gos", false, checked, None, None);
     assert!(errs.is_ok());
 }
 
+// 1M map lookups with string keys
+fn dispatch(max_interned_len: Option<usize>) {
+    let errs = run(
+        "./tests/demo/dispatch.gos",
+        false,
+        false,
+        max_interned_len,
+        None,
+    );
+    assert!(errs.is_ok());
+}
+
+// call-heavy scripts: recursion, and small functions/methods in a loop
+fn calls(path: &str) {
+    let errs = run(path, false, false, None, None);
+    assert!(errs.is_ok());
+}
+
+// 2M interface method calls, each call site always seeing the same type
+fn shapes(tiering_threshold: Option<u32>) {
+    let errs = run(
+        "./tests/demo/shapes.gos",
+        false,
+        false,
+        None,
+        tiering_threshold,
+    );
+    assert!(errs.is_ok());
+}
+
+// `n` appends to a string in a loop
+fn string_concat(n: usize) -> String {
+    format!(
+        r#"package main
+
+func main() {{
+    s := ""
+    for i := 0; i < {}; i++ {{
+        s += "part "
+    }}
+    assert(len(s) == {})
+}}
+"#,
+        n,
+        n * 5
+    )
+}
+
+fn run_source(source: &str) {
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Owned(source.to_owned()),
+    );
+    assert!(engine::run(engine::Config::default(), &sr, &path, None).is_ok());
+}
+
+fn compile(source: &str) {
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Owned(source.to_owned()),
+    );
+    let engine = engine::Engine::new();
+    assert!(engine.compile(&sr, &path, false, false, false).is_ok());
+}
+
 pub fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("leet5", |b| b.iter(|| leetcode5()));
+    c.bench_function("leet5", |b| b.iter(|| leetcode5(false)));
+    // the default one above should not be affected by the checked arithmetic support
+    c.bench_function("leet5_checked", |b| b.iter(|| leetcode5(true)));
+    c.bench_function("dispatch", |b| b.iter(|| dispatch(None)));
+    c.bench_function("dispatch_not_interned", |b| b.iter(|| dispatch(Some(0))));
+    c.bench_function("fib_recursive", |b| {
+        b.iter(|| calls("./tests/demo/fib_recursive.gos"))
+    });
+    c.bench_function("calls", |b| b.iter(|| calls("./tests/demo/calls.gos")));
+    // spawning many goroutines that are done in their first time slice
+    c.bench_function("fanout", |b| b.iter(|| calls("./tests/demo/fanout.gos")));
+    // the calls of the promoted functions skip the closures of the bound methods
+    c.bench_function("shapes", |b| b.iter(|| shapes(None)));
+    c.bench_function("shapes_tiered", |b| b.iter(|| shapes(Some(100))));
+    // the compile time of a function, per statement, doesn't grow with its size:
+    // the second should take about 4 times the first
+    let mut group = c.benchmark_group("compile_large_function");
+    group.sample_size(10);
+    for stmts in [25000, 100000] {
+        let (source, _) = common::large_function(stmts, stmts / 10);
+        group.bench_function(stmts.to_string(), |b| b.iter(|| compile(&source)));
+    }
+    group.finish();
+    // each += copies the bytes of the string once, so the time per append grows with
+    // the length: the second should take about 4 times the first, not 8
+    let mut group = c.benchmark_group("string_concat");
+    group.sample_size(10);
+    for n in [10000, 20000] {
+        let source = string_concat(n);
+        group.bench_function(n.to_string(), |b| b.iter(|| run_source(&source)));
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,38 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! goscript-server STD_DIR [SOCKET]
+//!
+//! Serves the requests of `go_engine::server` on stdin and stdout, or on the
+//! Unix socket at SOCKET. What the scripts print goes to stderr.
+
+use go_engine::server::{Server, ServerConfig};
+use go_engine::Engine;
+use std::path::PathBuf;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} STD_DIR [SOCKET]", args[0]);
+        std::process::exit(2);
+    }
+    let engine = Engine::new();
+    #[cfg(feature = "go_std")]
+    engine.set_std_io(None, Some(Box::new(std::io::stderr())), None);
+    let mut server = Server::new(engine, ServerConfig::new(PathBuf::from(&args[1])));
+    let result = match args.get(2) {
+        #[cfg(unix)]
+        Some(socket) => server.serve_unix(std::path::Path::new(socket)),
+        #[cfg(not(unix))]
+        Some(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "sockets are only supported on Unix",
+        )),
+        None => server.serve(std::io::stdin(), std::io::stdout()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
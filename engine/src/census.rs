@@ -0,0 +1,156 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The heap censuses of `Engine::heap_census`, for the hosts to find the objects
+//! a program keeps alive longer than it should, e.g. a cache nothing evicts from.
+
+use crate::engine::file_pos;
+use go_vm as vm;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The number of objects and the bytes they take, roughly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CensusCount {
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+impl CensusCount {
+    fn add(&mut self, objects: usize, bytes: usize) {
+        self.objects += objects;
+        self.bytes += bytes;
+    }
+}
+
+/// The objects a loaded program keeps alive, see `Engine::heap_census`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapCensus {
+    /// By class: "struct", "array", "slice", "map", "closure", "interface", "string",
+    /// "channel", "upvalue", or what `UnsafePtr::census_class` says for user data
+    pub classes: BTreeMap<&'static str, CensusCount>,
+    /// By the position of the expression that allocated them, as "file:line:column".
+    /// Only the structs, maps, closures and arrays of values allocated while
+    /// `Engine::set_alloc_sites` was on are here.
+    pub sites: BTreeMap<String, CensusCount>,
+}
+
+impl HeapCensus {
+    pub(crate) fn take(bc: &vm::Bytecode, sites: Option<&vm::AllocSites>) -> HeapCensus {
+        let mut census = HeapCensus::default();
+        for entry in vm::census(bc, sites) {
+            census
+                .classes
+                .entry(entry.class)
+                .or_default()
+                .add(entry.objects, entry.bytes);
+            if let Some(pos) = entry.site.and_then(|(fkey, pc)| file_pos(bc, fkey, pc)) {
+                census
+                    .sites
+                    .entry(pos.to_string())
+                    .or_default()
+                    .add(entry.objects, entry.bytes);
+            }
+        }
+        census
+    }
+
+    /// All the objects counted
+    pub fn total(&self) -> CensusCount {
+        let mut total = CensusCount::default();
+        for c in self.classes.values() {
+            total.add(c.objects, c.bytes);
+        }
+        total
+    }
+
+    /// What changed from `before` to `after`, only the classes and the positions
+    /// that did are in it
+    pub fn diff(before: &HeapCensus, after: &HeapCensus) -> CensusDiff {
+        CensusDiff {
+            classes: diff_maps(&before.classes, &after.classes),
+            sites: diff_maps(&before.sites, &after.sites),
+        }
+    }
+}
+
+/// A change of a `CensusCount`, positive for growth
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CensusDelta {
+    pub objects: isize,
+    pub bytes: isize,
+}
+
+/// The changes between two censuses, see `HeapCensus::diff`. Its `Display` lists
+/// the classes and the positions with the largest growth first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CensusDiff {
+    pub classes: BTreeMap<&'static str, CensusDelta>,
+    pub sites: BTreeMap<String, CensusDelta>,
+}
+
+impl CensusDiff {
+    /// If nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty() && self.sites.is_empty()
+    }
+
+    /// The positions that allocated more objects than were freed, the largest
+    /// growth in bytes first
+    pub fn growth(&self) -> Vec<(&str, CensusDelta)> {
+        let mut rows = by_growth(&self.sites);
+        rows.retain(|(_, d)| d.objects > 0 || d.bytes > 0);
+        rows
+    }
+}
+
+impl fmt::Display for CensusDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut write_section = |title: &str, rows: Vec<(&str, CensusDelta)>| {
+            if !rows.is_empty() {
+                writeln!(f, "{}:", title)?;
+            }
+            for (name, d) in rows {
+                writeln!(
+                    f,
+                    "  {:+} objects, {:+} bytes  {}",
+                    d.objects, d.bytes, name
+                )?;
+            }
+            Ok(())
+        };
+        write_section("classes", by_growth(&self.classes))?;
+        write_section("sites", by_growth(&self.sites))
+    }
+}
+
+fn diff_maps<K: Clone + Ord>(
+    before: &BTreeMap<K, CensusCount>,
+    after: &BTreeMap<K, CensusCount>,
+) -> BTreeMap<K, CensusDelta> {
+    let zero = CensusCount::default();
+    before
+        .keys()
+        .chain(after.keys())
+        .filter_map(|k| {
+            let (b, a) = (
+                before.get(k).unwrap_or(&zero),
+                after.get(k).unwrap_or(&zero),
+            );
+            let d = CensusDelta {
+                objects: a.objects as isize - b.objects as isize,
+                bytes: a.bytes as isize - b.bytes as isize,
+            };
+            (d != CensusDelta::default()).then(|| (k.clone(), d))
+        })
+        .collect()
+}
+
+/// All the rows, the largest growth in bytes first
+fn by_growth<K: AsRef<str>>(deltas: &BTreeMap<K, CensusDelta>) -> Vec<(&str, CensusDelta)> {
+    let mut rows: Vec<(&str, CensusDelta)> = deltas.iter().map(|(k, d)| (k.as_ref(), *d)).collect();
+    // stable, the ties stay sorted by name
+    rows.sort_by_key(|(_, d)| (-d.bytes, -d.objects));
+    rows
+}
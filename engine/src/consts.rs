@@ -0,0 +1,145 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The values of constant expressions as computed by the type checker, for tools
+//! like editors and documentation generators.
+
+use go_parser::{AstObjects, ErrorList, FilePos, FileSet, Map, Pos};
+use go_types::{
+    check::TypeInfo, ConstValue, Displayer, EntityType, ImportKey, Importer, SourceRead, TCObjects,
+    TraceConfig, TypeKey,
+};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConstKind {
+    Bool,
+    String,
+    Int,
+    Float,
+    Complex,
+}
+
+/// A constant expression, or the name of a declared constant, and its value.
+#[derive(Clone, Debug)]
+pub struct FoldedConst {
+    pub pos: FilePos,
+    pub end: FilePos,
+    pub kind: ConstKind,
+    /// The value formatted by the type checker, long strings are shortened
+    pub value: String,
+    /// The type of the expression, e.g. "untyped int" or "time.Duration"
+    pub typ: String,
+}
+
+/// The constants of a package, sorted by position.
+#[derive(Clone, Debug, Default)]
+pub struct ConstTable {
+    consts: Vec<FoldedConst>,
+}
+
+impl ConstTable {
+    pub fn iter(&self) -> impl Iterator<Item = &FoldedConst> {
+        self.consts.iter()
+    }
+
+    /// Returns the innermost constant expression that contains the char `offset`
+    /// of `file`, `None` if the expression at `offset` is not a constant. `file`
+    /// is the name used in `FilePos`, as in error messages.
+    pub fn at(&self, file: &str, offset: usize) -> Option<&FoldedConst> {
+        self.consts
+            .iter()
+            .filter(|c| {
+                c.pos.filename.as_str() == file && c.pos.offset <= offset && offset < c.end.offset
+            })
+            .min_by_key(|c| c.end.offset - c.pos.offset)
+    }
+
+    /// Like `at`, with a 1-based line and column.
+    pub fn at_line_col(&self, file: &str, line: usize, column: usize) -> Option<&FoldedConst> {
+        self.consts
+            .iter()
+            .filter(|c| {
+                c.pos.filename.as_str() == file
+                    && (c.pos.line, c.pos.column) <= (line, column)
+                    && (line, column) < (c.end.line, c.end.column)
+            })
+            .min_by_key(|c| c.end.offset - c.pos.offset)
+    }
+
+    pub(crate) fn collect<S: SourceRead>(reader: &S, path: &Path) -> Result<ConstTable, ErrorList> {
+        let config = TraceConfig {
+            trace_parser: false,
+            trace_checker: false,
+        };
+        let mut fset = FileSet::new();
+        let ast_objs = &mut AstObjects::new();
+        let tc_objs = &mut TCObjects::new();
+        let results = &mut Map::new();
+        let pkgs = &mut Map::new();
+        let el = ErrorList::new();
+
+        let key = ImportKey::new(
+            path.to_str().unwrap(),
+            reader.working_dir().to_str().unwrap(),
+        );
+        let pkg = Importer::new(
+            &config, reader, &mut fset, pkgs, results, ast_objs, tc_objs, &el, 0,
+        )
+        .import(&key);
+        if el.len() > 0 {
+            return Err(el);
+        }
+        let info: &TypeInfo = &results[&pkg.unwrap()];
+
+        let mut consts = vec![];
+        let mut add = |pos: Pos, end: Pos, val: &ConstValue, typ: Option<TypeKey>| {
+            let kind = match val {
+                ConstValue::Bool(_) => ConstKind::Bool,
+                ConstValue::Str(_) => ConstKind::String,
+                ConstValue::Int(_) => ConstKind::Int,
+                ConstValue::Rat(_) | ConstValue::Float(_) => ConstKind::Float,
+                ConstValue::Complex(_, _) => ConstKind::Complex,
+                ConstValue::Unknown => return,
+            };
+            let (pos, end) = match (fset.position(pos), fset.position(end)) {
+                (Some(p), Some(e)) => (p, e),
+                _ => return,
+            };
+            let typ = typ.map_or(String::new(), |t| {
+                format!("{}", Displayer::new(&t, None, Some(tc_objs)))
+            });
+            consts.push(FoldedConst {
+                pos,
+                end,
+                kind,
+                value: val.to_string(),
+                typ,
+            });
+        };
+        for (id, &(pos, end)) in info.const_spans.iter() {
+            if let Some(tv) = info.types.get(id) {
+                if let Some(val) = tv.get_const_val() {
+                    add(pos, end, val, Some(tv.typ));
+                }
+            }
+        }
+        // The names of declared constants, an iota constant without an expression
+        // of its own only has a value here.
+        for (&ikey, okey) in info.defs.iter() {
+            if let Some(okey) = okey {
+                let obj = &tc_objs.lobjs[*okey];
+                if let EntityType::Const(val) = obj.entity_type() {
+                    let ident = &ast_objs.idents[ikey];
+                    let end = ident.pos + ident.name.len();
+                    add(ident.pos, end, val, obj.typ());
+                }
+            }
+        }
+        consts.sort_by(|a, b| {
+            (a.pos.filename.as_str(), a.pos.offset).cmp(&(b.pos.filename.as_str(), b.pos.offset))
+        });
+        Ok(ConstTable { consts })
+    }
+}
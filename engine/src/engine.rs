@@ -2,13 +2,26 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::census::HeapCensus;
 use crate::ffi::Ffi;
+#[cfg(all(feature = "go_std", feature = "async"))]
+use crate::host::{BackPressure, HostEventSender};
+use crate::json;
+#[cfg(feature = "codegen")]
+use crate::package::{self, Package};
+#[cfg(feature = "codegen")]
+use crate::repl::{self, ReplResult};
 #[cfg(feature = "go_std")]
 use crate::std::os;
 #[cfg(feature = "serde_borsh")]
 use borsh::BorshSerialize;
+use go_parser::Map;
+use std::fmt::Write;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "codegen")]
 pub use {cg::SourceRead, types::ImportKey};
@@ -20,6 +33,298 @@ extern crate go_parser as parser;
 extern crate go_types as types;
 extern crate go_vm as vm;
 
+/// The path of the main package of `Engine::run_string`, its errors are reported
+/// in this file
+#[cfg(feature = "codegen")]
+const MAIN_PATH: &str = "main.gos";
+
+/// Information about an unrecovered panic that reached the engine boundary.
+pub struct PanicInfo {
+    pub kind: vm::PanicKind,
+    /// The value passed to `panic`, or the error message for runtime panics
+    pub value: vm::types::GosValue,
+    /// The id of the goroutine that panicked, 0 is the main goroutine
+    pub goroutine: usize,
+    /// Positions of the frames from the innermost one, `None` if no debug info is available
+    pub backtrace: Vec<Option<parser::FilePos>>,
+}
+
+impl PanicInfo {
+    pub fn new(data: &vm::PanicData, bc: &vm::Bytecode) -> PanicInfo {
+        let backtrace = data
+            .call_stack
+            .iter()
+            .map(|&(fkey, pc)| file_pos(bc, fkey, pc))
+            .collect();
+        PanicInfo {
+            kind: data.kind,
+            value: data.msg.clone(),
+            goroutine: data.goroutine,
+            backtrace,
+        }
+    }
+
+    /// Renders the panic as a (message, backtrace) pair according to `format`.
+    pub fn format(&self, format: &PanicFormat) -> (String, String) {
+        let msg = if format.redact_value {
+            "<value redacted>".to_owned()
+        } else {
+            format!("{}", self.value)
+        };
+        let mut stack = String::new();
+        if format.backtrace {
+            let limit = format.max_frames.unwrap_or(usize::MAX);
+            for pos in self.backtrace.iter().take(limit) {
+                match pos {
+                    Some(p) => writeln!(stack, "{}", p).unwrap(),
+                    None => stack.push_str("<no debug info available for current frame>\n"),
+                }
+            }
+            if self.backtrace.len() > limit {
+                writeln!(stack, "... {} more frames", self.backtrace.len() - limit).unwrap();
+            }
+        }
+        (msg, stack)
+    }
+}
+
+/// A goroutine still alive at the end of a run
+pub struct GoroutineInfo {
+    pub id: usize,
+    /// `None` if it's runnable rather than blocked
+    pub blocked_on: Option<vm::BlockReason>,
+    /// Where it's suspended, `None` if no debug info is available
+    pub position: Option<parser::FilePos>,
+    /// The go statement that started it, `None` for the main goroutine
+    /// or if no debug info is available
+    pub spawned_at: Option<parser::FilePos>,
+}
+
+impl GoroutineInfo {
+    pub fn new(data: &vm::GoroutineData, bc: &vm::Bytecode) -> GoroutineInfo {
+        GoroutineInfo {
+            id: data.id,
+            blocked_on: data.blocked_on,
+            position: file_pos(bc, data.position.0, data.position.1),
+            spawned_at: data
+                .spawned_at
+                .and_then(|(fkey, pc)| file_pos(bc, fkey, pc)),
+        }
+    }
+}
+
+impl std::fmt::Display for GoroutineInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.blocked_on {
+            Some(reason) => write!(f, "goroutine {} [{}]", self.id, reason)?,
+            None => write!(f, "goroutine {} [runnable]", self.id)?,
+        }
+        if let Some(p) = &self.position {
+            write!(f, " at {}", p)?;
+        }
+        if let Some(p) = &self.spawned_at {
+            write!(f, ", created at {}", p)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a run, see `Engine::run_bytecode_with_result`
+pub struct RunResult {
+    pub panic: Option<vm::PanicData>,
+    /// The goroutines still alive when the run ended, always empty if it ended with a panic
+    pub leaked_goroutines: Vec<GoroutineInfo>,
+    /// The `append`s and map assignments that made a slice or a map grow, sorted by
+    /// position, empty unless `Engine::set_growth_stats` is on
+    pub growth_sites: Vec<GrowthSite>,
+    /// What the cycle collection did during the run and at its end
+    pub gc_stats: vm::GcStats,
+}
+
+/// Where slices or maps outgrew their storage, and how often
+#[derive(Clone, Debug)]
+pub struct GrowthSite {
+    /// The position of the `append` or the map assignment
+    pub position: Option<parser::FilePos>,
+    /// How often the slice was copied to a new array because it didn't fit
+    /// in the capacity, `make` with a large enough capacity avoids it
+    pub slice_growths: usize,
+    /// How often the map had to rehash its entries, `make` with a size hint avoids it
+    pub map_rehashes: usize,
+}
+
+pub(crate) fn file_pos(
+    bc: &vm::Bytecode,
+    fkey: vm::types::FunctionKey,
+    pc: vm::types::OpIndex,
+) -> Option<parser::FilePos> {
+    let func = &bc.objects.functions[fkey];
+    func.pos[pc as usize].map(|p| match &bc.file_set {
+        Some(fs) => fs.position(p as usize).unwrap_or(parser::FilePos::null()),
+        None => parser::FilePos::null(),
+    })
+}
+
+/// The function of the package `pkg` of `bc` with the given name
+fn package_func(
+    bc: &vm::Bytecode,
+    pkg: vm::types::PackageKey,
+    name: &str,
+) -> Option<(vm::types::GosValue, vm::types::FunctionKey)> {
+    let pkg = &bc.objects.packages[pkg];
+    let f = pkg.member_index(name).map(|&i| pkg.member(i).clone())?;
+    match f.typ() {
+        vm::types::ValueType::Closure => match f.as_closure() {
+            Some((vm::types::ClosureObj::Gos(cls), _)) => {
+                let fkey = cls.func;
+                Some((f, fkey))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// What to do with a panic after the panic hook has seen it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicDisposition {
+    /// Report the panic as usual
+    Propagate,
+    /// Swallow the panic, nothing is reported
+    Suppress,
+}
+
+pub type PanicHook = Rc<dyn Fn(&PanicInfo) -> PanicDisposition>;
+
+/// Receives the compile time warnings, formatted as "position: message"
+pub type WarningHandler = Rc<dyn Fn(String)>;
+
+/// Controls the textual rendering of panics
+#[derive(Clone, Debug)]
+pub struct PanicFormat {
+    /// include the backtrace
+    pub backtrace: bool,
+    /// max number of frames in the backtrace, `None` for no limit
+    pub max_frames: Option<usize>,
+    /// hide the panic value, for hosts that show panics to end users
+    pub redact_value: bool,
+}
+
+impl Default for PanicFormat {
+    fn default() -> Self {
+        PanicFormat {
+            backtrace: true,
+            max_frames: None,
+            redact_value: false,
+        }
+    }
+}
+
+/// How the package level vars of a package shared by several modules are treated,
+/// see `Engine::compile_modules`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlobalsPolicy {
+    /// All the module instances share one set of vars, initialized only once
+    Shared,
+    /// Every module instance gets freshly initialized vars
+    PerInstance,
+}
+
+/// Entry scripts compiled together with the packages they share.
+/// Every run of a module is an instance of it, the vars of the module's own
+/// package are always re-initialized for a new instance, the vars of the shared
+/// packages are treated according to their `GlobalsPolicy`.
+pub struct Modules {
+    code: vm::Bytecode,
+    entries: Map<String, (vm::types::FunctionKey, vm::types::PackageKey)>,
+    packages: Map<String, vm::types::PackageKey>,
+    per_instance: Vec<vm::types::PackageKey>,
+}
+
+impl Modules {
+    pub fn bytecode(&self) -> &vm::Bytecode {
+        &self.code
+    }
+
+    /// Sets the globals policy of the package with the given import path,
+    /// the default policy is `GlobalsPolicy::Shared`.
+    pub fn set_globals_policy(
+        &mut self,
+        import_path: &str,
+        policy: GlobalsPolicy,
+    ) -> Result<(), String> {
+        let pkey = *self
+            .packages
+            .get(import_path)
+            .ok_or_else(|| format!("package not found: {}", import_path))?;
+        if self.entries.values().any(|(_, p)| *p == pkey) {
+            return Err(format!("{} is a module, not a shared package", import_path));
+        }
+        self.per_instance.retain(|&p| p != pkey);
+        if policy == GlobalsPolicy::PerInstance {
+            self.per_instance.push(pkey);
+        }
+        Ok(())
+    }
+
+    /// Returns the current value of a package level var or const
+    pub fn global(&self, import_path: &str, name: &str) -> Option<vm::types::GosValue> {
+        let pkg = &self.code.objects.packages[*self.packages.get(import_path)?];
+        pkg.member_index(name).map(|&i| pkg.member(i).clone())
+    }
+
+    /// Returns a copy of the modules with a deep copy of the current package vars,
+    /// so that the copy and the original can go on running independently.
+    /// See `Bytecode::fork` for what can't be copied.
+    pub fn fork(&self) -> Result<Modules, String> {
+        Ok(Modules {
+            code: self.code.fork().map_err(|e| e.as_str().to_owned())?,
+            entries: self.entries.clone(),
+            packages: self.packages.clone(),
+            per_instance: self.per_instance.clone(),
+        })
+    }
+}
+
+/// The package vars of an instance of a module, made by `Engine::instantiate_module`.
+/// They are the vars of the module's own package and of the shared packages with
+/// `GlobalsPolicy::PerInstance`, `Engine::call_module` puts them in place for a call.
+pub struct ModuleInstance {
+    module: String,
+    vars: Vec<(vm::types::PackageKey, Vec<vm::types::GosValue>)>,
+}
+
+impl ModuleInstance {
+    /// The name of the module this is an instance of
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// Returns the value of a package level var or const as seen by this instance,
+    /// the vars of the packages with `GlobalsPolicy::Shared` are the ones of `modules`.
+    pub fn global(
+        &self,
+        modules: &Modules,
+        import_path: &str,
+        name: &str,
+    ) -> Option<vm::types::GosValue> {
+        let pkey = *modules.packages.get(import_path)?;
+        let pkg = &modules.code.objects.packages[pkey];
+        let i = *pkg.member_index(name)?;
+        let own = self.vars.iter().find(|(p, _)| *p == pkey);
+        match own.zip(pkg.var_position(i)) {
+            Some(((_, vals), pos)) => Some(vals[pos].clone()),
+            None => Some(pkg.member(i).clone()),
+        }
+    }
+
+    fn swap_vars(&mut self, modules: &Modules) {
+        for (pkey, vals) in self.vars.iter_mut() {
+            modules.code.objects.packages[*pkey].swap_vars(vals);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Config {
     /// print debug info in parser
@@ -32,10 +337,218 @@ pub struct Config {
     pub std_out: Option<Box<dyn std::io::Write + Sync + Send>>,
     /// custom std err
     pub std_err: Option<Box<dyn std::io::Write + Sync + Send>>,
+    /// called once for every unrecovered panic, before it's reported
+    pub panic_hook: Option<PanicHook>,
+    /// how panics are rendered
+    pub panic_format: PanicFormat,
+    /// panic on integer overflow instead of wrapping around, see `Engine::set_checked_arithmetic`
+    pub checked_arithmetic: bool,
+    /// warn about defer statements inside loops, see `Engine::set_warn_defer_in_loop`
+    pub warn_defer_in_loop: bool,
+    /// which diagnostics stop the compilation, see `Strictness`
+    pub strictness: Strictness,
+    /// receives the warnings, they are printed to stderr if it's not set
+    pub warning_handler: Option<WarningHandler>,
+    /// how long other goroutines may run after main returns, see `Engine::set_goroutine_grace_period`
+    pub goroutine_grace_period: Option<Duration>,
+    /// treat goroutines left running at the end as an error
+    pub fail_on_goroutine_leak: bool,
+    /// see `Engine::set_max_interned_len`, the default is used if it's not set
+    pub max_interned_len: Option<usize>,
+    /// how host numbers are converted to narrower parameter types, see `NumericPolicy`
+    pub numeric_policy: NumericPolicy,
+    /// see `Engine::set_tiering_threshold`
+    pub tiering_threshold: Option<u32>,
+    /// see `Engine::set_map_order_seed`
+    pub map_order_seed: Option<u64>,
+}
+
+/// Which diagnostics stop the compilation. Whatever the level, the compiler reports
+/// the constructs it can't compile as errors instead of panicking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// The soft errors of the type checker, e.g. unused variables, imports and labels,
+    /// are reported as warnings and the program is compiled as far as possible.
+    /// Programs that Go rejects may run, and behave unexpectedly at runtime.
+    Lenient,
+    /// Every error of the type checker stops the compilation
+    #[default]
+    Standard,
+    /// Like `Standard`, and all the lints run, with their warnings reported as errors
+    Pedantic,
+}
+
+/// How a number passed in by the host is converted to the type of the parameter
+/// when it doesn't fit. NaN and infinities can't be converted to integers under
+/// any policy, and rounding a float to the precision of float32 is never an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumericPolicy {
+    /// Any lossy conversion is an error: out of range values, and fractions to integers
+    #[default]
+    Strict,
+    /// Out of range values are clamped to the range of the type, fractions are truncated
+    Saturate,
+    /// Like a Go conversion: integers wrap around, fractions are truncated,
+    /// and too large floats become infinities
+    GoConvert,
+}
+
+/// Overrides of the engine's settings for a single `Engine::call_json_with_options`,
+/// `call_module_with_options` or `eval_with_options`, e.g. to call an untrusted handler
+/// of a trusted program on a tighter budget.
+///
+/// The engine has no memory limit, sandbox profile or execution observer, so there
+/// is nothing of the kind to override. A call made from inside another one, by an
+/// FFI, is not limited by the options of the outer call, only by its own.
+#[derive(Clone, Default)]
+pub struct CallOptions {
+    /// Converts the arguments under this policy instead of the engine's
+    pub numeric_policy: Option<NumericPolicy>,
+    /// Limits the instructions of the call, and of the goroutines it starts. It can
+    /// only tighten the limit of the engine, the lower of the two applies.
+    pub instruction_limit: Option<u64>,
+    /// Sees the panics of the call instead of the panic hook of the engine
+    pub panic_hook: Option<PanicHook>,
+}
+
+impl std::fmt::Debug for CallOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallOptions")
+            .field("numeric_policy", &self.numeric_policy)
+            .field("instruction_limit", &self.instruction_limit)
+            .field("panic_hook", &self.panic_hook.is_some())
+            .finish()
+    }
+}
+
+/// An error of `Engine::call_json`
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallError {
+    /// No program has been loaded with `load_source` or `load_bytecode`
+    NotLoaded,
+    /// There is no function with the name in the main package
+    NotFound(String),
+    /// The arguments are not a JSON array
+    InvalidJson(String),
+    /// The number of the arguments doesn't match the function
+    ArgumentCount { expected: usize, got: usize },
+    /// An argument can't be converted to the type of the parameter
+    Argument {
+        index: usize,
+        expected: String,
+        reason: String,
+    },
+    /// A result can't be converted to JSON
+    Result { index: usize, reason: String },
+    /// The results left by the call don't agree with the signature of the function
+    Results(String),
+    /// The function panicked, with the message of the panic
+    Panic(String),
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::NotLoaded => f.write_str("no program loaded"),
+            CallError::NotFound(name) => write!(f, "function not found: {}", name),
+            CallError::InvalidJson(e) => write!(f, "invalid JSON arguments: {}", e),
+            CallError::ArgumentCount { expected, got } => {
+                write!(
+                    f,
+                    "wrong number of arguments: want {}, got {}",
+                    expected, got
+                )
+            }
+            CallError::Argument {
+                index,
+                expected,
+                reason,
+            } => write!(f, "argument {} ({}): {}", index, expected, reason),
+            CallError::Result { index, reason } => write!(f, "result {}: {}", index, reason),
+            CallError::Results(msg) => f.write_str(msg),
+            CallError::Panic(msg) => write!(f, "panic: {}", msg),
+        }
+    }
 }
 
+/// Why `Engine::call_program` failed
+pub(crate) enum CallFailure {
+    Error(CallError),
+    Panic(PanicInfo),
+}
+
+impl From<CallError> for CallFailure {
+    fn from(e: CallError) -> Self {
+        CallFailure::Error(e)
+    }
+}
+
+/// An error of `Engine::run_file` and `Engine::run_string`
+#[cfg(feature = "codegen")]
+#[derive(Clone, Debug)]
+pub enum EngineError {
+    /// The source doesn't parse, the errors are the syntax errors with their positions
+    Parse(parser::ErrorList),
+    /// The program doesn't type check, or uses a construct the code generator can't
+    /// compile
+    Compile(parser::ErrorList),
+    /// The program panicked, rendered according to the panic format of the engine
+    Panic { msg: String, call_stack: String },
+    /// The main goroutine deadlocked, or goroutines were left running with
+    /// `set_fail_on_goroutine_leak` on
+    Goroutines(parser::ErrorList),
+}
+
+#[cfg(feature = "codegen")]
+impl EngineError {
+    fn from_compile(el: parser::ErrorList) -> EngineError {
+        match el.borrow().iter().any(|e| e.by_parser) {
+            true => EngineError::Parse(el.clone()),
+            false => EngineError::Compile(el.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::Parse(el) | EngineError::Compile(el) | EngineError::Goroutines(el) => {
+                write!(f, "{}", el)
+            }
+            EngineError::Panic { msg, call_stack } => write!(f, "panic: {}\n{}", msg, call_stack),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+impl std::error::Error for EngineError {}
+
 pub struct Engine {
     ffi: vm::FfiFactory,
+    panic_hook: Option<PanicHook>,
+    panic_format: PanicFormat,
+    checked_arithmetic: bool,
+    warn_defer_in_loop: bool,
+    strictness: Strictness,
+    warning_handler: Option<WarningHandler>,
+    goroutine_grace_period: Option<Duration>,
+    fail_on_goroutine_leak: bool,
+    max_interned_len: usize,
+    numeric_policy: NumericPolicy,
+    growth_stats: bool,
+    interrupt: Option<Arc<AtomicBool>>,
+    instruction_limit: Option<u64>,
+    fiber_pool_size: Option<usize>,
+    gc_max_pause: Option<Duration>,
+    tiering_threshold: Option<u32>,
+    alloc_sites: Option<Rc<vm::AllocSites>>,
+    map_order_seed: Option<u64>,
+    repl_mode: bool,
+    #[cfg(all(feature = "go_std", feature = "async"))]
+    host_events: crate::host::HostEvents,
+    // the program loaded for `call_json`
+    program: Option<vm::Bytecode>,
 }
 
 impl Engine {
@@ -44,6 +557,26 @@ impl Engine {
         {
             Engine {
                 ffi: vm::FfiFactory::new(),
+                panic_hook: None,
+                panic_format: PanicFormat::default(),
+                checked_arithmetic: false,
+                warn_defer_in_loop: false,
+                strictness: Strictness::Standard,
+                warning_handler: None,
+                goroutine_grace_period: None,
+                fail_on_goroutine_leak: false,
+                max_interned_len: vm::DEFAULT_MAX_INTERNED_LEN,
+                numeric_policy: NumericPolicy::Strict,
+                growth_stats: false,
+                interrupt: None,
+                instruction_limit: None,
+                fiber_pool_size: None,
+                gc_max_pause: None,
+                tiering_threshold: None,
+                alloc_sites: None,
+                map_order_seed: None,
+                repl_mode: false,
+                program: None,
             }
         }
 
@@ -51,8 +584,32 @@ impl Engine {
         {
             let mut e = Engine {
                 ffi: vm::FfiFactory::new(),
+                panic_hook: None,
+                panic_format: PanicFormat::default(),
+                checked_arithmetic: false,
+                warn_defer_in_loop: false,
+                strictness: Strictness::Standard,
+                warning_handler: None,
+                goroutine_grace_period: None,
+                fail_on_goroutine_leak: false,
+                max_interned_len: vm::DEFAULT_MAX_INTERNED_LEN,
+                numeric_policy: NumericPolicy::Strict,
+                growth_stats: false,
+                interrupt: None,
+                instruction_limit: None,
+                fiber_pool_size: None,
+                gc_max_pause: None,
+                tiering_threshold: None,
+                alloc_sites: None,
+                map_order_seed: None,
+                repl_mode: false,
+                #[cfg(feature = "async")]
+                host_events: Default::default(),
+                program: None,
             };
             crate::std::register(&mut e.ffi);
+            #[cfg(feature = "async")]
+            e.register_host_ffi();
             e
         }
     }
@@ -62,6 +619,26 @@ impl Engine {
         {
             Engine {
                 ffi: vm::FfiFactory::with_user_data(data),
+                panic_hook: None,
+                panic_format: PanicFormat::default(),
+                checked_arithmetic: false,
+                warn_defer_in_loop: false,
+                strictness: Strictness::Standard,
+                warning_handler: None,
+                goroutine_grace_period: None,
+                fail_on_goroutine_leak: false,
+                max_interned_len: vm::DEFAULT_MAX_INTERNED_LEN,
+                numeric_policy: NumericPolicy::Strict,
+                growth_stats: false,
+                interrupt: None,
+                instruction_limit: None,
+                fiber_pool_size: None,
+                gc_max_pause: None,
+                tiering_threshold: None,
+                alloc_sites: None,
+                map_order_seed: None,
+                repl_mode: false,
+                program: None,
             }
         }
 
@@ -69,12 +646,75 @@ impl Engine {
         {
             let mut e = Engine {
                 ffi: vm::FfiFactory::with_user_data(data),
+                panic_hook: None,
+                panic_format: PanicFormat::default(),
+                checked_arithmetic: false,
+                warn_defer_in_loop: false,
+                strictness: Strictness::Standard,
+                warning_handler: None,
+                goroutine_grace_period: None,
+                fail_on_goroutine_leak: false,
+                max_interned_len: vm::DEFAULT_MAX_INTERNED_LEN,
+                numeric_policy: NumericPolicy::Strict,
+                growth_stats: false,
+                interrupt: None,
+                instruction_limit: None,
+                fiber_pool_size: None,
+                gc_max_pause: None,
+                tiering_threshold: None,
+                alloc_sites: None,
+                map_order_seed: None,
+                repl_mode: false,
+                #[cfg(feature = "async")]
+                host_events: Default::default(),
+                program: None,
             };
             crate::std::register(&mut e.ffi);
+            #[cfg(feature = "async")]
+            e.register_host_ffi();
             e
         }
     }
 
+    #[cfg(all(feature = "go_std", feature = "async"))]
+    fn register_host_ffi(&mut self) {
+        let ffi = crate::host::HostFfi {
+            events: self.host_events.clone(),
+        };
+        self.ffi.register("host.events", Rc::new(ffi));
+    }
+
+    /// Creates the events named `name`, which the script receives from the channel
+    /// returned by `host.Subscribe(name)`. Up to `capacity` of them are buffered,
+    /// `send` blocks when the buffer is full, until the script receives one.
+    #[cfg(all(feature = "go_std", feature = "async"))]
+    pub fn event_channel(&mut self, name: &str, capacity: usize) -> HostEventSender {
+        self.event_channel_with_policy(name, capacity, BackPressure::Block)
+    }
+
+    /// Like `event_channel`, with what to do when the buffer is full.
+    #[cfg(all(feature = "go_std", feature = "async"))]
+    pub fn event_channel_with_policy(
+        &mut self,
+        name: &str,
+        capacity: usize,
+        policy: BackPressure,
+    ) -> HostEventSender {
+        self.host_events.channel(name, capacity, policy)
+    }
+
+    /// Keeps a run going while goroutines wait for host events.
+    fn idle_hook(&self) -> Option<vm::IdleHook> {
+        #[cfg(all(feature = "go_std", feature = "async"))]
+        {
+            self.host_events.idle_hook()
+        }
+        #[cfg(not(all(feature = "go_std", feature = "async")))]
+        {
+            None
+        }
+    }
+
     #[cfg(feature = "go_std")]
     pub fn set_std_io(
         &self,
@@ -85,10 +725,151 @@ impl Engine {
         os::set_std_io(std_in, std_out, std_err);
     }
 
+    pub fn set_panic_hook(&mut self, hook: Option<PanicHook>) {
+        self.panic_hook = hook;
+    }
+
+    pub fn set_panic_format(&mut self, format: PanicFormat) {
+        self.panic_format = format;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn panic_format(&self) -> &PanicFormat {
+        &self.panic_format
+    }
+
+    /// When on, integer +, -, *, <<, ++, -- and conversions to narrower integer types
+    /// in the compiled code panic with "integer overflow" instead of wrapping around.
+    /// It's a compile time option, the default wrapping code is not affected.
+    pub fn set_checked_arithmetic(&mut self, on: bool) {
+        self.checked_arithmetic = on;
+    }
+
+    /// When on, a warning is reported for every defer statement inside a for loop
+    /// in the compiled code, the deferred calls pile up until the function returns
+    /// rather than run at the end of each iteration, which is rarely intended.
+    pub fn set_warn_defer_in_loop(&mut self, on: bool) {
+        self.warn_defer_in_loop = on;
+    }
+
+    /// Sets which diagnostics stop the compilation, `Strictness::Standard` by default.
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    pub fn set_warning_handler(&mut self, handler: Option<WarningHandler>) {
+        self.warning_handler = handler;
+    }
+
+    /// Limits how long the other goroutines may keep running after the main goroutine
+    /// returns, the ones not finished by then are reported as leaked.
+    /// By default they run until none of them can make progress.
+    pub fn set_goroutine_grace_period(&mut self, period: Option<Duration>) {
+        self.goroutine_grace_period = period;
+    }
+
+    /// When on, `run_source` fails if any goroutine is left running at the end,
+    /// a deadlock of the main goroutine is always an error.
+    pub fn set_fail_on_goroutine_leak(&mut self, on: bool) {
+        self.fail_on_goroutine_leak = on;
+    }
+
+    /// String constants not longer than `len` are interned when compiling, so that
+    /// map lookups and switches on them compare pointers and reuse cached hashes
+    /// instead of the bytes, see `vm::StringPool`. 0 turns it off.
+    pub fn set_max_interned_len(&mut self, len: usize) {
+        self.max_interned_len = len;
+    }
+
+    /// Sets the default `NumericPolicy` of `call_json`
+    pub fn set_numeric_policy(&mut self, policy: NumericPolicy) {
+        self.numeric_policy = policy;
+    }
+
+    /// When on, `run_bytecode_with_result` counts the `append`s that don't fit in the
+    /// capacity of their slice and the map assignments that make the map rehash,
+    /// per `append` or assignment, see `RunResult::growth_sites`.
+    pub fn set_growth_stats(&mut self, on: bool) {
+        self.growth_stats = on;
+    }
+
+    /// Runs stop with a `vm::PanicKind::Interrupted` panic when `flag` is set, from
+    /// any thread. It's up to the host to clear it before the next run.
+    pub fn set_interrupt_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.interrupt = flag;
+    }
+
+    /// Runs and calls stop with a `vm::PanicKind::InstructionLimit` panic after about
+    /// `limit` instructions, counted over all their goroutines at the end of every
+    /// time slice, so a run may overshoot by a slice per goroutine.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    /// How many stacks of finished goroutines are kept for new ones, so that spawning
+    /// many short lived goroutines doesn't allocate a stack for each of them.
+    /// `vm::DEFAULT_FIBER_POOL_SIZE` if it's not set, 0 turns the reuse off.
+    pub fn set_fiber_pool_size(&mut self, size: Option<usize>) {
+        self.fiber_pool_size = size;
+    }
+
+    /// How long the cycle collection may stop a goroutine at once, it runs in slices
+    /// at the points where goroutines yield. `vm::DEFAULT_GC_MAX_PAUSE` if it's not set.
+    pub fn set_gc_max_pause(&mut self, pause: Option<Duration>) {
+        self.gc_max_pause = pause;
+    }
+
+    /// Once a function has been called `threshold` times, it runs a copy of its code
+    /// specialized by what it has done so far: the interface method calls that have
+    /// always seen the same dynamic type don't allocate a closure for the method.
+    /// It's for long running programs, the calls of every function are counted.
+    pub fn set_tiering_threshold(&mut self, threshold: Option<u32>) {
+        self.tiering_threshold = threshold;
+    }
+
+    /// When on, the runs record where the structs, maps, closures and arrays of
+    /// values are allocated, for `heap_census` to count the objects by position. It has to be
+    /// turned on before the program is loaded, and slows the runs down.
+    pub fn set_alloc_sites(&mut self, on: bool) {
+        self.alloc_sites = on.then(|| Rc::new(vm::AllocSites::new()));
+    }
+
+    /// Shuffles the order in which `range` visits the entries of a map with `seed`.
+    /// Go leaves the order unspecified, a program that passes with a few seeds
+    /// doesn't depend on it by accident.
+    pub fn set_map_order_seed(&mut self, seed: Option<u64>) {
+        self.map_order_seed = seed;
+    }
+
+    /// When on, `eval` imports the std packages a snippet uses without importing
+    /// them, e.g. `strings` for `strings.ToUpper("a")`, and captures what it writes
+    /// to `os.Stdout` instead of printing it.
+    pub fn set_repl_mode(&mut self, on: bool) {
+        self.repl_mode = on;
+    }
+
+    /// The limit of the engine, tightened by `call_limit`
+    fn effective_instruction_limit(&self, call_limit: Option<u64>) -> Option<u64> {
+        match (self.instruction_limit, call_limit) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
     pub fn register_extension(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
         self.ffi.register(name, proto);
     }
 
+    /// Like `register_extension`, but returns an error instead of panicking when
+    /// the declarations of the FFI don't agree with its handlers.
+    pub fn try_register_extension(
+        &mut self,
+        name: &'static str,
+        proto: Rc<dyn Ffi>,
+    ) -> Result<(), vm::FfiError> {
+        self.ffi.try_register(name, proto)
+    }
+
     #[cfg(feature = "codegen")]
     pub fn compile<S: SourceRead>(
         &self,
@@ -102,7 +883,89 @@ impl Engine {
             trace_parser,
             trace_checker,
         };
-        cg::parse_check_gen(path, &cfg, reader, debug_info)
+        let soft_errors = (self.strictness == Strictness::Lenient).then(parser::ErrorList::new);
+        let warnings = self.lints_on().then(parser::ErrorList::new);
+        let result = cg::parse_check_gen(
+            path,
+            &cfg,
+            reader,
+            debug_info,
+            self.checked_arithmetic,
+            soft_errors.as_ref(),
+            warnings.as_ref(),
+        );
+        let mut code = self.apply_strictness(result, soft_errors, warnings)?;
+        code.intern_strings(self.max_interned_len);
+        Ok(code)
+    }
+
+    /// Compiles a main package made of several files, see `Package`. The packages
+    /// it imports are read with `reader`.
+    #[cfg(feature = "codegen")]
+    pub fn compile_package<S: SourceRead>(
+        &self,
+        reader: &S,
+        package: &Package,
+        debug_info: bool,
+        trace_parser: bool,
+        trace_checker: bool,
+    ) -> Result<vm::Bytecode, parser::ErrorList> {
+        package.validate()?;
+        let reader = package::PackageReader {
+            inner: reader,
+            package,
+        };
+        self.compile(
+            &reader,
+            Path::new(package::PACKAGE_DIR),
+            debug_info,
+            trace_parser,
+            trace_checker,
+        )
+    }
+
+    #[cfg(feature = "codegen")]
+    fn lints_on(&self) -> bool {
+        self.warn_defer_in_loop || self.strictness == Strictness::Pedantic
+    }
+
+    /// Reports the soft errors let through and the lint warnings, under
+    /// `Strictness::Pedantic` the warnings fail the compilation instead.
+    #[cfg(feature = "codegen")]
+    fn apply_strictness<T>(
+        &self,
+        result: Result<T, parser::ErrorList>,
+        soft_errors: Option<parser::ErrorList>,
+        warnings: Option<parser::ErrorList>,
+    ) -> Result<T, parser::ErrorList> {
+        self.report_warnings(soft_errors);
+        match warnings {
+            Some(w) if self.strictness == Strictness::Pedantic && w.len() > 0 => {
+                let el = result.err().unwrap_or_else(parser::ErrorList::new);
+                for e in w.borrow().iter() {
+                    el.add(Some(e.pos.clone()), e.msg.clone(), false, e.by_parser);
+                }
+                Err(el)
+            }
+            _ => {
+                self.report_warnings(warnings);
+                result
+            }
+        }
+    }
+
+    #[cfg(feature = "codegen")]
+    fn report_warnings(&self, warnings: Option<parser::ErrorList>) {
+        if let Some(warnings) = warnings {
+            warnings.sort();
+            for w in warnings.borrow().iter() {
+                let text = format!("{}: {}", w.pos, w.msg);
+                match &self.warning_handler {
+                    Some(handler) => handler(text),
+                    None => eprintln!("warning: {}", text),
+                }
+            }
+        }
     }
 
     #[cfg(all(feature = "codegen", feature = "serde_borsh"))]
@@ -118,8 +981,497 @@ impl Engine {
             .map(|code| code.try_to_vec().unwrap())
     }
 
+    /// Type checks the package at `path` and returns the values of its constant
+    /// expressions and declared constants, without generating any code.
+    #[cfg(feature = "codegen")]
+    pub fn const_table<S: SourceRead>(
+        &self,
+        reader: &S,
+        path: &Path,
+    ) -> Result<crate::ConstTable, parser::ErrorList> {
+        crate::ConstTable::collect(reader, path)
+    }
+
     pub fn run_bytecode(&self, bc: &vm::Bytecode) -> Option<vm::PanicData> {
-        vm::run(bc, &self.ffi)
+        let options = vm::RunOptions {
+            idle_hook: self.idle_hook(),
+            interrupt: self.interrupt.clone(),
+            instruction_limit: self.instruction_limit,
+            fiber_pool_size: self.fiber_pool_size,
+            gc_max_pause: self.gc_max_pause,
+            tiering_threshold: self.tiering_threshold,
+            alloc_sites: self.alloc_sites.clone(),
+            map_order_seed: self.map_order_seed,
+            ..Default::default()
+        };
+        vm::run_with(bc, &self.ffi, bc.entry, &options).panic
+    }
+
+    /// Like `run_bytecode`, but also reports the goroutines left running.
+    pub fn run_bytecode_with_result(&self, bc: &vm::Bytecode) -> RunResult {
+        let options = vm::RunOptions {
+            grace_period: self.goroutine_grace_period,
+            growth_stats: self.growth_stats,
+            idle_hook: self.idle_hook(),
+            interrupt: self.interrupt.clone(),
+            instruction_limit: self.instruction_limit,
+            fiber_pool_size: self.fiber_pool_size,
+            gc_max_pause: self.gc_max_pause,
+            tiering_threshold: self.tiering_threshold,
+            alloc_sites: self.alloc_sites.clone(),
+            map_order_seed: self.map_order_seed,
+            std_out: None,
+        };
+        let result = vm::run_with(bc, &self.ffi, bc.entry, &options);
+        let mut growth_sites: Vec<GrowthSite> = result
+            .growth_stats
+            .iter()
+            .map(|(&(fkey, pc), count)| GrowthSite {
+                position: file_pos(bc, fkey, pc),
+                slice_growths: count.slice_growths,
+                map_rehashes: count.map_rehashes,
+            })
+            .collect();
+        // the ones without a position go last
+        growth_sites.sort_by_key(|s| match &s.position {
+            Some(p) => (false, p.filename.clone(), p.line, p.column),
+            None => (true, Default::default(), 0, 0),
+        });
+        RunResult {
+            panic: result.panic,
+            leaked_goroutines: result
+                .leaked_goroutines
+                .iter()
+                .map(|g| GoroutineInfo::new(g, bc))
+                .collect(),
+            growth_sites,
+            gc_stats: result.gc_stats,
+        }
+    }
+
+    /// Compiles several entry scripts, each identified by a name, into one `Modules`.
+    /// The packages they import are compiled only once and shared by them.
+    #[cfg(feature = "codegen")]
+    pub fn compile_modules<S: SourceRead>(
+        &self,
+        reader: &S,
+        modules: &[(&str, &Path)],
+        debug_info: bool,
+        trace_parser: bool,
+        trace_checker: bool,
+    ) -> Result<Modules, parser::ErrorList> {
+        let el = parser::ErrorList::new();
+        for (i, (name, _)) in modules.iter().enumerate() {
+            if modules[..i].iter().any(|(n, _)| n == name) {
+                el.add(
+                    None,
+                    format!("duplicate module name: {}", name),
+                    false,
+                    false,
+                );
+            }
+        }
+        if el.len() > 0 {
+            return Err(el);
+        }
+        let cfg = types::TraceConfig {
+            trace_parser,
+            trace_checker,
+        };
+        let paths: Vec<&Path> = modules.iter().map(|(_, p)| *p).collect();
+        let soft_errors = (self.strictness == Strictness::Lenient).then(parser::ErrorList::new);
+        let warnings = self.lints_on().then(parser::ErrorList::new);
+        let result = cg::parse_check_gen_entries(
+            &paths,
+            &cfg,
+            reader,
+            debug_info,
+            self.checked_arithmetic,
+            soft_errors.as_ref(),
+            warnings.as_ref(),
+        );
+        let mut result = self.apply_strictness(result, soft_errors, warnings)?;
+        result.code.intern_strings(self.max_interned_len);
+        let entries = modules
+            .iter()
+            .zip(result.entries)
+            .map(|((name, _), entry)| (name.to_string(), entry))
+            .collect();
+        Ok(Modules {
+            code: result.code,
+            entries,
+            packages: result.packages,
+            per_instance: vec![],
+        })
+    }
+
+    /// Runs a new instance of the module with the given name.
+    pub fn run_module(
+        &self,
+        modules: &Modules,
+        name: &str,
+    ) -> Result<Option<vm::PanicData>, String> {
+        let (entry, main_pkg) = *modules
+            .entries
+            .get(name)
+            .ok_or_else(|| format!("module not found: {}", name))?;
+        let packages = &modules.code.objects.packages;
+        packages[main_pkg].reset();
+        for &pkey in modules.per_instance.iter() {
+            packages[pkey].reset();
+        }
+        let options = vm::RunOptions {
+            idle_hook: self.idle_hook(),
+            interrupt: self.interrupt.clone(),
+            instruction_limit: self.instruction_limit,
+            fiber_pool_size: self.fiber_pool_size,
+            gc_max_pause: self.gc_max_pause,
+            tiering_threshold: self.tiering_threshold,
+            alloc_sites: self.alloc_sites.clone(),
+            map_order_seed: self.map_order_seed,
+            ..Default::default()
+        };
+        Ok(vm::run_with(&modules.code, &self.ffi, entry, &options).panic)
+    }
+
+    /// Runs a new instance of the module with `run_module` and keeps its vars,
+    /// so that the functions of the module can be called on this instance with
+    /// `call_module`, while other instances are run and called in between.
+    /// A panic is returned as an error, formatted with the engine's `PanicFormat`.
+    pub fn instantiate_module(
+        &self,
+        modules: &Modules,
+        name: &str,
+    ) -> Result<ModuleInstance, String> {
+        if let Some(p) = self.run_module(modules, name)? {
+            let (msg, _) = PanicInfo::new(&p, &modules.code).format(&self.panic_format);
+            return Err(format!("panic: {}", msg));
+        }
+        let main_pkg = modules.entries[name].1;
+        let vars = std::iter::once(main_pkg)
+            .chain(modules.per_instance.iter().copied())
+            .map(|pkey| (pkey, modules.code.objects.packages[pkey].vars()))
+            .collect();
+        Ok(ModuleInstance {
+            module: name.to_owned(),
+            vars,
+        })
+    }
+
+    /// Calls the function of the instance's module with the given name like
+    /// `call_json`, with the vars of `instance`. The vars of the shared packages
+    /// with `GlobalsPolicy::Shared` are the same for all the instances.
+    pub fn call_module(
+        &self,
+        modules: &Modules,
+        instance: &mut ModuleInstance,
+        func: &str,
+        args_json: &str,
+    ) -> Result<String, CallError> {
+        self.call_module_with_options(modules, instance, func, args_json, &CallOptions::default())
+    }
+
+    /// `call_module` with the settings of the engine overridden by `options` for this call only
+    pub fn call_module_with_options(
+        &self,
+        modules: &Modules,
+        instance: &mut ModuleInstance,
+        func: &str,
+        args_json: &str,
+        options: &CallOptions,
+    ) -> Result<String, CallError> {
+        let (_, main_pkg) = *modules
+            .entries
+            .get(&instance.module)
+            .ok_or_else(|| CallError::NotFound(instance.module.clone()))?;
+        let args = match json::Json::parse(args_json).map_err(CallError::InvalidJson)? {
+            json::Json::Array(args) => args,
+            _ => return Err(CallError::InvalidJson("not an array".to_owned())),
+        };
+        instance.swap_vars(modules);
+        let result = self.call_package(&modules.code, main_pkg, func, &args, options);
+        instance.swap_vars(modules);
+        result.map_err(|failure| match failure {
+            CallFailure::Error(e) => e,
+            CallFailure::Panic(info) => CallError::Panic(info.format(&self.panic_format).0),
+        })
+    }
+
+    /// Runs the Bytecode and keeps it, so that its functions can be called with
+    /// `call_json` afterwards, with the package vars as the run left them.
+    /// It's not kept if the run panics.
+    pub fn load_bytecode(&mut self, bc: vm::Bytecode) -> Result<(), PanicInfo> {
+        match self.run_bytecode(&bc) {
+            Some(p) => Err(PanicInfo::new(&p, &bc)),
+            None => {
+                self.program = Some(bc);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles the program and loads it with `load_bytecode`
+    #[cfg(feature = "codegen")]
+    pub fn load_source<S: SourceRead>(
+        &mut self,
+        reader: &S,
+        path: &Path,
+    ) -> Result<(), parser::ErrorList> {
+        let code = self.compile(reader, path, true, false, false)?;
+        self.load_bytecode(code).map_err(|info| {
+            let el = parser::ErrorList::new();
+            let (msg, _) = info.format(&self.panic_format);
+            el.add(None, format!("panic: {}", msg), false, false);
+            el
+        })
+    }
+
+    /// Compiles and runs a snippet, an expression or statements, as a program of its
+    /// own, and returns the values of the expression with their types. The snippets
+    /// don't share any state, and the loaded program isn't affected. A panic is
+    /// returned as an error like in `load_source`, unless the panic hook suppresses it.
+    #[cfg(feature = "codegen")]
+    pub fn eval<S: SourceRead>(
+        &self,
+        reader: &S,
+        snippet: &str,
+    ) -> Result<ReplResult, parser::ErrorList> {
+        self.eval_with_options(reader, snippet, &CallOptions::default())
+    }
+
+    /// `eval` with the settings of the engine overridden by `options` while the snippet
+    /// runs, its package vars are initialized under the settings of the engine.
+    #[cfg(feature = "codegen")]
+    pub fn eval_with_options<S: SourceRead>(
+        &self,
+        reader: &S,
+        snippet: &str,
+        call_options: &CallOptions,
+    ) -> Result<ReplResult, parser::ErrorList> {
+        // the first form that compiles, or the errors of the first one
+        let mut first_errors = None;
+        let mut compiled = None;
+        for form in repl::forms(snippet) {
+            let (src, auto_imports) = repl::wrap(snippet, form, self.repl_mode);
+            let reader = repl::SnippetReader {
+                inner: reader,
+                path: repl::SNIPPET_PATH,
+                src,
+            };
+            match self.compile(&reader, Path::new(repl::SNIPPET_PATH), true, false, false) {
+                Ok(bc) => {
+                    compiled = Some((bc, auto_imports));
+                    break;
+                }
+                Err(el) => {
+                    first_errors.get_or_insert(el);
+                }
+            }
+        }
+        let (bc, auto_imports) = match compiled {
+            Some(c) => c,
+            None => return Err(repl::snippet_errors(first_errors.unwrap())),
+        };
+        // what the snippet writes to the std out, it's not shared with other runs
+        let captured = self
+            .repl_mode
+            .then(|| Rc::new(std::cell::RefCell::new(Vec::<u8>::new())));
+        let result = match self.run_bytecode(&bc) {
+            Some(p) => Err(p),
+            None => {
+                let (f, _) = package_func(&bc, bc.main_pkg, repl::EVAL_FUNC).unwrap();
+                let options = vm::RunOptions {
+                    grace_period: self.goroutine_grace_period,
+                    idle_hook: self.idle_hook(),
+                    interrupt: self.interrupt.clone(),
+                    instruction_limit: self
+                        .effective_instruction_limit(call_options.instruction_limit),
+                    fiber_pool_size: self.fiber_pool_size,
+                    gc_max_pause: self.gc_max_pause,
+                    tiering_threshold: self.tiering_threshold,
+                    alloc_sites: self.alloc_sites.clone(),
+                    map_order_seed: self.map_order_seed,
+                    std_out: captured.clone().map(|c| c as vm::StdOutSink),
+                    ..Default::default()
+                };
+                vm::call(&bc, &self.ffi, &f, vec![], &options)
+            }
+        };
+        let stdout_captured = captured
+            .map(|c| String::from_utf8_lossy(&c.borrow()).into_owned())
+            .unwrap_or_default();
+        let results = match result {
+            Ok(results) => results,
+            Err(p) => {
+                let info = PanicInfo::new(&p, &bc);
+                let hook = call_options.panic_hook.as_ref();
+                if self.panic_disposition(&info, hook) == PanicDisposition::Propagate {
+                    let el = parser::ErrorList::new();
+                    let (msg, _) = info.format(&self.panic_format);
+                    el.add(None, format!("panic: {}", msg), false, false);
+                    return Err(el);
+                }
+                // a suppressed panic leaves the snippet without values
+                vec![]
+            }
+        };
+        let (values, type_strings) = match results.first() {
+            Some(vals) => {
+                vm::FfiCtx::with_objects(&bc.objects, |ctx| repl::display_values(ctx, vals))
+            }
+            None => (vec![], vec![]),
+        };
+        Ok(ReplResult {
+            values,
+            type_strings,
+            auto_imports,
+            stdout_captured,
+        })
+    }
+
+    /// Counts the objects the loaded program keeps alive, the ones reachable from its
+    /// package vars, empty if no program is loaded. Diffing the censuses taken before
+    /// and after `call_json`s with `HeapCensus::diff` shows what the calls left behind.
+    pub fn heap_census(&self) -> HeapCensus {
+        match &self.program {
+            Some(bc) => HeapCensus::take(bc, self.alloc_sites.as_deref()),
+            None => HeapCensus::default(),
+        }
+    }
+
+    /// Calls the function of the main package of the loaded program with the given name.
+    /// The arguments are a JSON array with an element for every parameter, a variadic
+    /// parameter takes an array. They are converted to the parameter types, and the
+    /// results are returned as a JSON array, the way Go's `encoding/json` does,
+    /// e.g. a struct is an object of its exported fields named by their `json` tags.
+    /// A panic is passed to the panic hook, if the hook suppresses it the zero values
+    /// of the results are returned.
+    pub fn call_json(&mut self, func: &str, args_json: &str) -> Result<String, CallError> {
+        self.call_json_with_options(func, args_json, &CallOptions::default())
+    }
+
+    /// `call_json` with the arguments converted under `policy` instead of the engine's
+    pub fn call_json_with_policy(
+        &mut self,
+        func: &str,
+        args_json: &str,
+        policy: NumericPolicy,
+    ) -> Result<String, CallError> {
+        let options = CallOptions {
+            numeric_policy: Some(policy),
+            ..Default::default()
+        };
+        self.call_json_with_options(func, args_json, &options)
+    }
+
+    /// `call_json` with the settings of the engine overridden by `options` for this call only
+    pub fn call_json_with_options(
+        &mut self,
+        func: &str,
+        args_json: &str,
+        options: &CallOptions,
+    ) -> Result<String, CallError> {
+        let bc = self.program.as_ref().ok_or(CallError::NotLoaded)?;
+        let args = match json::Json::parse(args_json).map_err(CallError::InvalidJson)? {
+            json::Json::Array(args) => args,
+            _ => return Err(CallError::InvalidJson("not an array".to_owned())),
+        };
+        self.call_program(bc, func, &args, options)
+            .map_err(|failure| match failure {
+                CallFailure::Error(e) => e,
+                CallFailure::Panic(info) => CallError::Panic(info.format(&self.panic_format).0),
+            })
+    }
+
+    /// Calls a function of the main package of `bc`, which must have been run,
+    /// with arguments already parsed from JSON.
+    pub(crate) fn call_program(
+        &self,
+        bc: &vm::Bytecode,
+        func: &str,
+        args: &[json::Json],
+        call_options: &CallOptions,
+    ) -> Result<String, CallFailure> {
+        self.call_package(bc, bc.main_pkg, func, args, call_options)
+    }
+
+    /// `call_program` with a function of the package `pkg` instead of the main package
+    fn call_package(
+        &self,
+        bc: &vm::Bytecode,
+        pkg: vm::types::PackageKey,
+        func: &str,
+        args: &[json::Json],
+        call_options: &CallOptions,
+    ) -> Result<String, CallFailure> {
+        let policy = call_options.numeric_policy.unwrap_or(self.numeric_policy);
+        let objs = &bc.objects;
+        let (f, fkey) =
+            package_func(bc, pkg, func).ok_or_else(|| CallError::NotFound(func.to_owned()))?;
+        let sig = objs.metas[objs.functions[fkey].meta.key]
+            .as_signature()
+            .clone();
+        if args.len() != sig.params.len() {
+            return Err(CallError::ArgumentCount {
+                expected: sig.params.len(),
+                got: args.len(),
+            }
+            .into());
+        }
+        let args = vm::FfiCtx::with_objects(objs, |ctx| {
+            args.iter()
+                .zip(sig.params.iter())
+                .enumerate()
+                .map(|(index, (arg, meta))| {
+                    json::decode(ctx, arg, meta, policy).map_err(|reason| CallError::Argument {
+                        index,
+                        expected: json::type_string(meta, &objs.metas),
+                        reason,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        let options = vm::RunOptions {
+            grace_period: self.goroutine_grace_period,
+            idle_hook: self.idle_hook(),
+            interrupt: self.interrupt.clone(),
+            instruction_limit: self.effective_instruction_limit(call_options.instruction_limit),
+            fiber_pool_size: self.fiber_pool_size,
+            gc_max_pause: self.gc_max_pause,
+            tiering_threshold: self.tiering_threshold,
+            alloc_sites: self.alloc_sites.clone(),
+            map_order_seed: self.map_order_seed,
+            ..Default::default()
+        };
+        let results = match vm::call(bc, &self.ffi, &f, args, &options) {
+            Ok(results) => results,
+            Err(p) => {
+                let info = PanicInfo::new(&p, bc);
+                let hook = call_options.panic_hook.as_ref();
+                if self.panic_disposition(&info, hook) == PanicDisposition::Propagate {
+                    return Err(CallFailure::Panic(info));
+                }
+                // a suppressed panic returns the zero values of the results
+                vm::FfiCtx::with_objects(objs, |ctx| {
+                    sig.results.iter().map(|m| ctx.zero_val(m)).collect()
+                })
+            }
+        };
+        let results = vm::TupleDesc::results_of(&sig, &objs.metas)
+            .check(func, "the call", results)
+            .map_err(|e| CallError::Results(e.to_string()))?;
+        vm::FfiCtx::with_objects(objs, |ctx| {
+            let mut out = String::from("[");
+            for (index, (val, meta)) in results.iter().zip(sig.results.iter()).enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                json::encode(ctx, val, meta, &mut out, 0)
+                    .map_err(|reason| CallError::Result { index, reason })?;
+            }
+            out.push(']');
+            Ok(out)
+        })
     }
 
     #[cfg(feature = "codegen")]
@@ -131,28 +1483,137 @@ impl Engine {
         path: &Path,
         panic_handler: Option<Rc<dyn Fn(String, String)>>,
     ) -> Result<(), parser::ErrorList> {
-        self.compile(reader, path, true, trace_parser, trace_checker)
-            .map(|code| {
-                // let mut decoded;
-                // #[cfg(feature = "serde_borsh")]
-                // {
-                //     let encoded = code.try_to_vec().unwrap();
-                //     decoded = go_vm::Bytecode::try_from_slice(&encoded).unwrap();
-                // }
-                // #[cfg(not(feature = "serde_borsh"))]
-                // {
-                //     decoded = code;
-                // }
-                let pdata = vm::run(&code, &self.ffi);
-                if let Some(pdata) = pdata {
-                    let call_stack = vm::CallStackDisplay::new(&pdata, &code);
-                    if let Some(handler) = panic_handler {
-                        handler(format!("{}", pdata.msg), format!("{}", call_stack));
-                    } else {
-                        eprintln!("{}\n", pdata.msg);
-                        eprintln!("{}\n", call_stack);
-                    }
+        let code = self.compile(reader, path, true, trace_parser, trace_checker)?;
+        match self.run_code(&code) {
+            Err(EngineError::Panic { msg, call_stack }) => {
+                if let Some(handler) = panic_handler {
+                    handler(msg, call_stack);
+                } else {
+                    eprintln!("{}\n", msg);
+                    eprintln!("{}\n", call_stack);
                 }
-            })
+                Ok(())
+            }
+            Err(EngineError::Goroutines(el)) => Err(el),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compiles and runs the main package at `path`. The FFIs registered with
+    /// `register_extension` are the ones the program can create with `ffi`.
+    #[cfg(feature = "codegen")]
+    pub fn run_file<S: SourceRead>(&self, reader: &S, path: &Path) -> Result<(), EngineError> {
+        let code = self
+            .compile(reader, path, true, false, false)
+            .map_err(EngineError::from_compile)?;
+        self.run_code(&code)
+    }
+
+    /// Like `run_file`, with the main package in `source`, it can import the packages
+    /// of `reader`.
+    ///
+    /// ```
+    /// use go_engine::ffi::*;
+    /// use go_engine::{Engine, EngineError, SourceReader};
+    /// use std::path::PathBuf;
+    /// use std::rc::Rc;
+    ///
+    /// #[derive(Ffi)]
+    /// pub struct MathxFfi;
+    ///
+    /// #[ffi_impl]
+    /// impl MathxFfi {
+    ///     #[go_sig = "func(a, b int) int"]
+    ///     fn ffi_max(a: isize, b: isize) -> isize {
+    ///         a.max(b)
+    ///     }
+    /// }
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.register_extension("mathx", Rc::new(MathxFfi));
+    /// let reader = SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    /// let source = r#"
+    /// package main
+    ///
+    /// type mathx interface {
+    ///     max(a, b int) int
+    /// }
+    ///
+    /// func main() {
+    ///     m := ffi(mathx, "mathx")
+    ///     assert(m.max(3, 7) == 7)
+    ///     panic("done")
+    /// }
+    /// "#;
+    /// match engine.run_string(&reader, source) {
+    ///     Err(EngineError::Panic { msg, .. }) => assert_eq!(msg, "done"),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[cfg(feature = "codegen")]
+    pub fn run_string<S: SourceRead>(&self, reader: &S, source: &str) -> Result<(), EngineError> {
+        let reader = repl::SnippetReader {
+            inner: reader,
+            path: MAIN_PATH,
+            src: source.to_owned(),
+        };
+        self.run_file(&reader, Path::new(MAIN_PATH))
+    }
+
+    /// Passes an unrecovered panic to `call_hook`, or to the panic hook of the engine
+    /// without it, if there is one
+    fn panic_disposition(
+        &self,
+        info: &PanicInfo,
+        call_hook: Option<&PanicHook>,
+    ) -> PanicDisposition {
+        match call_hook.or(self.panic_hook.as_ref()) {
+            Some(hook) => hook(info),
+            None => PanicDisposition::Propagate,
+        }
+    }
+
+    /// Runs the compiled program, the panic is reported unless the panic hook
+    /// suppresses it.
+    #[cfg(feature = "codegen")]
+    fn run_code(&self, code: &vm::Bytecode) -> Result<(), EngineError> {
+        // let mut decoded;
+        // #[cfg(feature = "serde_borsh")]
+        // {
+        //     let encoded = code.try_to_vec().unwrap();
+        //     decoded = go_vm::Bytecode::try_from_slice(&encoded).unwrap();
+        // }
+        // #[cfg(not(feature = "serde_borsh"))]
+        // {
+        //     decoded = code;
+        // }
+        let result = self.run_bytecode_with_result(code);
+        if let Some(pdata) = result.panic {
+            let info = PanicInfo::new(&pdata, code);
+            if self.panic_disposition(&info, None) == PanicDisposition::Propagate {
+                let (msg, call_stack) = info.format(&self.panic_format);
+                return Err(EngineError::Panic { msg, call_stack });
+            }
+        }
+        // the main goroutine is only left behind when it's deadlocked
+        let deadlock = result.leaked_goroutines.iter().any(|g| g.id == 0);
+        if deadlock || (self.fail_on_goroutine_leak && !result.leaked_goroutines.is_empty()) {
+            let el = parser::ErrorList::new();
+            if deadlock {
+                let main = &result.leaked_goroutines[0];
+                el.add(
+                    main.position.clone(),
+                    "all goroutines are asleep - deadlock!".to_owned(),
+                    false,
+                    false,
+                );
+            }
+            let what = if deadlock { "deadlocked" } else { "leaked" };
+            for g in result.leaked_goroutines.iter() {
+                el.add(g.position.clone(), format!("{} {}", what, g), false, false);
+            }
+            return Err(EngineError::Goroutines(el));
+        }
+        Ok(())
     }
 }
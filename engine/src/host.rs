@@ -0,0 +1,388 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Events sent by the host program to the script, which receives them from the
+//! channel returned by `host.Subscribe`, like os/signal delivers signals.
+
+use go_parser::Map;
+use go_vm::gc::GcContainer;
+use go_vm::types::{GosValue, Meta, RuntimeResult, ValueType};
+use go_vm::{Ffi, FfiCtx, HostChannel, TryRecvError};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The value of `host.Event.Payload`
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventPayload {
+    /// nil
+    None,
+    Bool(bool),
+    /// a Go int
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl From<bool> for EventPayload {
+    fn from(b: bool) -> Self {
+        EventPayload::Bool(b)
+    }
+}
+
+impl From<i64> for EventPayload {
+    fn from(i: i64) -> Self {
+        EventPayload::Int(i)
+    }
+}
+
+impl From<f64> for EventPayload {
+    fn from(f: f64) -> Self {
+        EventPayload::Float(f)
+    }
+}
+
+impl From<String> for EventPayload {
+    fn from(s: String) -> Self {
+        EventPayload::String(s)
+    }
+}
+
+impl From<&str> for EventPayload {
+    fn from(s: &str) -> Self {
+        EventPayload::String(s.to_owned())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HostEvent {
+    pub name: String,
+    pub payload: EventPayload,
+    pub time: SystemTime,
+}
+
+impl HostEvent {
+    /// An event that happens now.
+    pub fn new(name: &str, payload: impl Into<EventPayload>) -> HostEvent {
+        HostEvent {
+            name: name.to_owned(),
+            payload: payload.into(),
+            time: SystemTime::now(),
+        }
+    }
+}
+
+/// What `HostEventSender::send` does when the buffer of the subscription is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackPressure {
+    /// Wait until the script receives an event, or the subscription goes away.
+    #[default]
+    Block,
+    /// Make room by discarding the oldest buffered event.
+    DropOldest,
+    /// Discard the event being sent.
+    DropNewest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Receiver {
+    NotYet,
+    Subscribed,
+    Gone,
+}
+
+struct Queue {
+    events: VecDeque<HostEvent>,
+    capacity: usize,
+    policy: BackPressure,
+    sender_alive: bool,
+    receiver: Receiver,
+    dropped: usize,
+}
+
+/// Bumped whenever anything happens to any subscription, so that the VM can
+/// sleep while all the goroutines wait for the host.
+#[derive(Default)]
+pub(crate) struct Signal {
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Signal {
+    fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    fn wait_past(&self, generation: u64) -> u64 {
+        let mut g = self.generation.lock().unwrap();
+        while *g == generation {
+            g = self.changed.wait(g).unwrap();
+        }
+        *g
+    }
+}
+
+pub(crate) struct Subscription {
+    queue: Mutex<Queue>,
+    // for the senders blocked on a full buffer
+    taken: Condvar,
+    signal: Arc<Signal>,
+}
+
+impl Subscription {
+    /// Whether a goroutine could still receive something from it.
+    fn is_pending(&self) -> bool {
+        let q = self.queue.lock().unwrap();
+        q.receiver == Receiver::Subscribed && (q.sender_alive || !q.events.is_empty())
+    }
+
+    fn set_receiver(&self, receiver: Receiver) {
+        self.queue.lock().unwrap().receiver = receiver;
+        self.taken.notify_all();
+        self.signal.notify();
+    }
+}
+
+struct SenderInner {
+    sub: Arc<Subscription>,
+}
+
+impl Drop for SenderInner {
+    fn drop(&mut self) {
+        self.sub.queue.lock().unwrap().sender_alive = false;
+        self.sub.taken.notify_all();
+        self.sub.signal.notify();
+    }
+}
+
+/// Sends events to a subscription, from any thread. The channel of the script
+/// is closed once the buffered events are received and all the clones of the
+/// sender are dropped.
+#[derive(Clone)]
+pub struct HostEventSender {
+    inner: Arc<SenderInner>,
+}
+
+impl HostEventSender {
+    /// Buffers `event` for the script, following the `BackPressure` policy of
+    /// the subscription when the buffer is full. With `BackPressure::Block` it
+    /// must not be called on the thread running the script.
+    pub fn send(&self, event: HostEvent) {
+        let sub = &self.inner.sub;
+        let mut q = sub.queue.lock().unwrap();
+        if q.receiver == Receiver::Gone {
+            q.dropped += 1;
+            return;
+        }
+        while q.events.len() >= q.capacity {
+            match q.policy {
+                BackPressure::Block => {
+                    q = sub.taken.wait(q).unwrap();
+                    if q.receiver == Receiver::Gone {
+                        q.dropped += 1;
+                        return;
+                    }
+                }
+                BackPressure::DropOldest => {
+                    q.events.pop_front();
+                    q.dropped += 1;
+                }
+                BackPressure::DropNewest => {
+                    q.dropped += 1;
+                    return;
+                }
+            }
+        }
+        q.events.push_back(event);
+        drop(q);
+        sub.signal.notify();
+    }
+
+    /// How many events were discarded, by the policy or because the script's
+    /// channel is gone.
+    pub fn dropped(&self) -> usize {
+        self.inner.sub.queue.lock().unwrap().dropped
+    }
+}
+
+/// The metas of the types the payload can have in the script
+#[derive(Clone)]
+struct PayloadMetas {
+    mbool: Meta,
+    mint: Meta,
+    mfloat64: Meta,
+    mstr: Meta,
+}
+
+/// The script side of a subscription
+struct EventChannel {
+    sub: Arc<Subscription>,
+    metas: PayloadMetas,
+}
+
+impl EventChannel {
+    fn event_value(&self, event: HostEvent, gcc: &GcContainer) -> GosValue {
+        let m = &self.metas;
+        let payload = match event.payload {
+            EventPayload::None => FfiCtx::new_nil(ValueType::Interface),
+            EventPayload::Bool(b) => FfiCtx::empty_interface_of(b.into(), m.mbool),
+            EventPayload::Int(i) => FfiCtx::empty_interface_of((i as isize).into(), m.mint),
+            EventPayload::Float(f) => FfiCtx::empty_interface_of(f.into(), m.mfloat64),
+            EventPayload::String(s) => FfiCtx::empty_interface_of(FfiCtx::new_string(&s), m.mstr),
+        };
+        let nanos = event
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as i64);
+        FfiCtx::new_struct_in(
+            vec![FfiCtx::new_string(&event.name), payload, nanos.into()],
+            gcc,
+        )
+    }
+}
+
+impl std::fmt::Debug for EventChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "host event channel")
+    }
+}
+
+impl HostChannel for EventChannel {
+    fn try_recv(&self, gcc: &GcContainer) -> Result<GosValue, TryRecvError> {
+        let mut q = self.sub.queue.lock().unwrap();
+        match q.events.pop_front() {
+            Some(event) => {
+                drop(q);
+                self.sub.taken.notify_all();
+                Ok(self.event_value(event, gcc))
+            }
+            None if q.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Closed),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sub.queue.lock().unwrap().events.len()
+    }
+
+    fn cap(&self) -> usize {
+        self.sub.queue.lock().unwrap().capacity
+    }
+}
+
+impl Drop for EventChannel {
+    fn drop(&mut self) {
+        self.sub.set_receiver(Receiver::Gone);
+    }
+}
+
+/// The subscriptions the host has created, shared by the engine and the FFI
+#[derive(Clone, Default)]
+pub(crate) struct HostEvents {
+    subs: Rc<RefCell<Map<String, Arc<Subscription>>>>,
+    signal: Arc<Signal>,
+}
+
+impl HostEvents {
+    pub(crate) fn channel(
+        &self,
+        name: &str,
+        capacity: usize,
+        policy: BackPressure,
+    ) -> HostEventSender {
+        let sub = Arc::new(Subscription {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                capacity: capacity.max(1),
+                policy,
+                sender_alive: true,
+                receiver: Receiver::NotYet,
+                dropped: 0,
+            }),
+            taken: Condvar::new(),
+            signal: self.signal.clone(),
+        });
+        self.subs.borrow_mut().insert(name.to_owned(), sub.clone());
+        HostEventSender {
+            inner: Arc::new(SenderInner { sub }),
+        }
+    }
+
+    /// The `RunOptions::idle_hook` of the runs, `None` without subscriptions.
+    /// It sleeps until something happens to a subscription a goroutine could be
+    /// waiting on, and gives up when there is no such subscription.
+    pub(crate) fn idle_hook(&self) -> Option<go_vm::IdleHook> {
+        if self.subs.borrow().is_empty() {
+            return None;
+        }
+        let events = self.clone();
+        let seen = Cell::new(self.signal.generation());
+        Some(Rc::new(move || {
+            let now = events.signal.generation();
+            if now != seen.get() {
+                seen.set(now);
+                return true;
+            }
+            if !events.subs.borrow().values().any(|s| s.is_pending()) {
+                return false;
+            }
+            seen.set(events.signal.wait_past(now));
+            true
+        }))
+    }
+
+    fn subscribe(&self, ctx: &mut FfiCtx, params: Vec<GosValue>) -> RuntimeResult<GosValue> {
+        let name = params[0].as_string().as_str().to_owned();
+        let sub = match self.subs.borrow().get(&name) {
+            Some(sub) => sub.clone(),
+            None => return Err(format!("host.Subscribe: no host events named {}", name).into()),
+        };
+        if sub.queue.lock().unwrap().receiver != Receiver::NotYet {
+            return Err(format!("host.Subscribe: {} already subscribed", name).into());
+        }
+        sub.set_receiver(Receiver::Subscribed);
+        let pm = &ctx.vm_objs.prim_meta;
+        let chan = EventChannel {
+            sub,
+            metas: PayloadMetas {
+                mbool: pm.mbool,
+                mint: pm.mint,
+                mfloat64: pm.mfloat64,
+                mstr: pm.mstr,
+            },
+        };
+        Ok(FfiCtx::new_host_channel(Rc::new(chan), params[1].clone()))
+    }
+}
+
+/// The FFI behind the `host` package
+pub(crate) struct HostFfi {
+    pub(crate) events: HostEvents,
+}
+
+impl Ffi for HostFfi {
+    fn call(&self, ctx: &mut FfiCtx, params: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+        match ctx.func_name {
+            "subscribe" => Ok(vec![self.events.subscribe(ctx, params)?]),
+            _ => Err(format!("host: unknown function {}", ctx.func_name).into()),
+        }
+    }
+
+    fn async_call(
+        &self,
+        ctx: &mut FfiCtx,
+        _: Vec<GosValue>,
+    ) -> std::pin::Pin<Box<dyn futures_lite::Future<Output = RuntimeResult<Vec<GosValue>>> + '_>>
+    {
+        let err = format!("host: unknown function {}", ctx.func_name);
+        Box::pin(async move { Err(err.into()) })
+    }
+}
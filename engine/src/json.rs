@@ -0,0 +1,953 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Conversion between JSON and GosValues for `Engine::call_json`.
+//!
+//! The mapping is driven by the metadata of the values and follows Go's
+//! `encoding/json`: structs are objects of their exported fields named by the
+//! `json` tags, []byte is a base64 string, map keys are sorted, etc.
+//! Unlike `encoding/json`, embedded structs are not flattened, and JSON arrays
+//! and objects can't be decoded into non-empty interfaces.
+
+use crate::engine::NumericPolicy;
+use go_parser::Map;
+use go_vm::types::{GosValue, InterfaceObj, Meta, MetadataObjs, MetadataType, ValueType};
+use go_vm::FfiCtx;
+use std::fmt::Write;
+
+// deep enough for any sane data, and to catch cycles
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// The text of the number, it's converted when the type is known
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn parse(src: &str) -> Result<Json, String> {
+        let mut p = Parser {
+            src: src.as_bytes(),
+            pos: 0,
+        };
+        let val = p.value(0)?;
+        p.skip_space();
+        match p.peek() {
+            Some(c) => Err(p.unexpected(c)),
+            None => Ok(val),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "bool",
+            Json::Number(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn unexpected(&self, c: u8) -> String {
+        format!(
+            "invalid character {:?} at offset {}",
+            c as char,
+            self.pos + 1
+        )
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| "unexpected end of JSON input".to_owned())?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        match self.peek() {
+            Some(x) if x == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(x) => Err(self.unexpected(x)),
+            None => Err("unexpected end of JSON input".to_owned()),
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err("exceeded max depth".to_owned());
+        }
+        self.skip_space();
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_space();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_space();
+                    let key = self.string()?;
+                    self.skip_space();
+                    self.expect(b':')?;
+                    let val = self.value(depth + 1)?;
+                    members.push((key, val));
+                    self.skip_space();
+                    match self.next()? {
+                        b',' => continue,
+                        b'}' => return Ok(Json::Object(members)),
+                        c => {
+                            self.pos -= 1;
+                            return Err(self.unexpected(c));
+                        }
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elems = vec![];
+                self.skip_space();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(elems));
+                }
+                loop {
+                    elems.push(self.value(depth + 1)?);
+                    self.skip_space();
+                    match self.next()? {
+                        b',' => continue,
+                        b']' => return Ok(Json::Array(elems)),
+                        c => {
+                            self.pos -= 1;
+                            return Err(self.unexpected(c));
+                        }
+                    }
+                }
+            }
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(c) => Err(self.unexpected(c)),
+            None => Err("unexpected end of JSON input".to_owned()),
+        }
+    }
+
+    fn literal(&mut self, text: &str, val: Json) -> Result<Json, String> {
+        for &c in text.as_bytes() {
+            self.expect(c)?;
+        }
+        Ok(val)
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let begin = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.next()? {
+            b'0' => {}
+            b'1'..=b'9' => self.digits(),
+            c => {
+                self.pos -= 1;
+                return Err(self.unexpected(c));
+            }
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.first_digit()?;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.first_digit()?;
+        }
+        let text = std::str::from_utf8(&self.src[begin..self.pos]).unwrap();
+        Ok(Json::Number(text.to_owned()))
+    }
+
+    fn first_digit(&mut self) -> Result<(), String> {
+        match self.next()? {
+            b'0'..=b'9' => {
+                self.digits();
+                Ok(())
+            }
+            c => {
+                self.pos -= 1;
+                Err(self.unexpected(c))
+            }
+        }
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => match self.next()? {
+                    b'"' => bytes.push(b'"'),
+                    b'\\' => bytes.push(b'\\'),
+                    b'/' => bytes.push(b'/'),
+                    b'b' => bytes.push(8),
+                    b'f' => bytes.push(12),
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'u' => {
+                        let mut c = self.hex4()?;
+                        if (0xd800..0xdc00).contains(&c) && self.src[self.pos..].starts_with(b"\\u")
+                        {
+                            let save = self.pos;
+                            self.pos += 2;
+                            let low = self.hex4()?;
+                            if (0xdc00..0xe000).contains(&low) {
+                                c = 0x10000 + ((c - 0xd800) << 10) + (low - 0xdc00);
+                            } else {
+                                self.pos = save;
+                            }
+                        }
+                        let ch = char::from_u32(c).unwrap_or('\u{fffd}');
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                    }
+                    c => {
+                        self.pos -= 1;
+                        return Err(format!("{} in string escape code", self.unexpected(c)));
+                    }
+                },
+                c if c < 0x20 => {
+                    self.pos -= 1;
+                    return Err(format!("{} in string literal", self.unexpected(c)));
+                }
+                c => bytes.push(c),
+            }
+        }
+        // the input is a str, and the escapes are valid UTF-8
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut v = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let d = (c as char).to_digit(16).ok_or_else(|| {
+                self.pos -= 1;
+                format!("{} in \\u hexadecimal character escape", self.unexpected(c))
+            })?;
+            v = v * 16 + d;
+        }
+        Ok(v)
+    }
+}
+
+/// Describes the type of `meta` the Go way, as far as it can be told from the metadata,
+/// the names of the named types are not known at runtime.
+pub(crate) fn type_string(meta: &Meta, metas: &MetadataObjs) -> String {
+    let mut s = String::new();
+    write_type(&mut s, meta, metas, 0);
+    s
+}
+
+fn write_type(s: &mut String, meta: &Meta, metas: &MetadataObjs, depth: usize) {
+    for _ in 0..meta.ptr_depth {
+        s.push('*');
+    }
+    if depth > 2 {
+        s.push_str("...");
+        return;
+    }
+    match meta.mtype_unwraped(metas) {
+        MetadataType::Bool => s.push_str("bool"),
+        MetadataType::Int => s.push_str("int"),
+        MetadataType::Int8 => s.push_str("int8"),
+        MetadataType::Int16 => s.push_str("int16"),
+        MetadataType::Int32 => s.push_str("int32"),
+        MetadataType::Int64 => s.push_str("int64"),
+        MetadataType::Uint => s.push_str("uint"),
+        MetadataType::UintPtr => s.push_str("uintptr"),
+        MetadataType::Uint8 => s.push_str("uint8"),
+        MetadataType::Uint16 => s.push_str("uint16"),
+        MetadataType::Uint32 => s.push_str("uint32"),
+        MetadataType::Uint64 => s.push_str("uint64"),
+        MetadataType::Float32 => s.push_str("float32"),
+        MetadataType::Float64 => s.push_str("float64"),
+        MetadataType::Complex64 => s.push_str("complex64"),
+        MetadataType::Complex128 => s.push_str("complex128"),
+        MetadataType::UnsafePtr => s.push_str("unsafe.Pointer"),
+        MetadataType::Str => s.push_str("string"),
+        MetadataType::Array(m, n) => {
+            write!(s, "[{}]", n).unwrap();
+            write_type(s, m, metas, depth + 1);
+        }
+        MetadataType::Slice(m) => {
+            s.push_str("[]");
+            write_type(s, m, metas, depth + 1);
+        }
+        MetadataType::Map(k, v) => {
+            s.push_str("map[");
+            write_type(s, k, metas, depth + 1);
+            s.push(']');
+            write_type(s, v, metas, depth + 1);
+        }
+        MetadataType::Struct(fields) => {
+            s.push_str("struct{");
+            let real = fields
+                .infos()
+                .iter()
+                .filter(|f| f.embedded_indices.is_none());
+            for (i, f) in real.enumerate() {
+                if i > 0 {
+                    s.push_str("; ");
+                }
+                write!(s, "{} ", f.name).unwrap();
+                write_type(s, &f.meta, metas, depth + 1);
+            }
+            s.push('}');
+        }
+        MetadataType::Interface(fields) => match fields.infos().is_empty() {
+            true => s.push_str("interface{}"),
+            false => s.push_str("interface{...}"),
+        },
+        MetadataType::Signature(_) => s.push_str("func(...)"),
+        MetadataType::Channel(_, m) => {
+            s.push_str("chan ");
+            write_type(s, m, metas, depth + 1);
+        }
+        MetadataType::Named(_, _) | MetadataType::None => s.push('?'),
+    }
+}
+
+/// Makes a value of the type of `meta` from `json`, the numbers that don't fit
+/// are converted according to `policy`
+pub(crate) fn decode(
+    ctx: &FfiCtx,
+    json: &Json,
+    meta: &Meta,
+    policy: NumericPolicy,
+) -> Result<GosValue, String> {
+    let metas = &ctx.vm_objs.metas;
+    let mismatch = || {
+        format!(
+            "cannot use JSON {} as {}",
+            json.kind(),
+            type_string(meta, metas)
+        )
+    };
+    if meta.ptr_depth > 0 {
+        return match json {
+            Json::Null => Ok(FfiCtx::new_nil(ValueType::Pointer)),
+            _ => Ok(FfiCtx::new_pointer(decode(
+                ctx,
+                json,
+                &meta.unptr_to(),
+                policy,
+            )?)),
+        };
+    }
+    let mtype = meta.mtype_unwraped(metas);
+    if let Json::Null = json {
+        return match mtype {
+            MetadataType::Slice(_)
+            | MetadataType::Map(_, _)
+            | MetadataType::Interface(_)
+            | MetadataType::Signature(_)
+            | MetadataType::Channel(_, _)
+            | MetadataType::UnsafePtr => Ok(ctx.zero_val(meta)),
+            _ => Err(mismatch()),
+        };
+    }
+    match (mtype, json) {
+        (MetadataType::Bool, Json::Bool(b)) => Ok((*b).into()),
+        (MetadataType::Int, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as isize).into()),
+        (MetadataType::Int8, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as i8).into()),
+        (MetadataType::Int16, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as i16).into()),
+        (MetadataType::Int32, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as i32).into()),
+        (MetadataType::Int64, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as i64).into()),
+        (MetadataType::Uint, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as usize).into()),
+        (MetadataType::UintPtr, Json::Number(n)) => {
+            Ok(FfiCtx::new_uint_ptr(int(n, policy, meta, metas)? as usize))
+        }
+        (MetadataType::Uint8, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as u8).into()),
+        (MetadataType::Uint16, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as u16).into()),
+        (MetadataType::Uint32, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as u32).into()),
+        (MetadataType::Uint64, Json::Number(n)) => Ok((int(n, policy, meta, metas)? as u64).into()),
+        (MetadataType::Float32, Json::Number(n)) => {
+            Ok((float(n, policy, f32::MAX as f64, meta, metas)? as f32).into())
+        }
+        (MetadataType::Float64, Json::Number(n)) => {
+            Ok(float(n, policy, f64::MAX, meta, metas)?.into())
+        }
+        (MetadataType::Str, Json::String(s)) => Ok(FfiCtx::new_string(s)),
+        (MetadataType::Slice(m), Json::String(s)) if is_byte(m, metas) => {
+            let bytes = base64_decode(s)?;
+            let vals = bytes.into_iter().map(GosValue::from).collect();
+            Ok(ctx.new_slice(vals, ValueType::Uint8))
+        }
+        (MetadataType::Slice(m), Json::Array(elems)) => {
+            let vals = elems
+                .iter()
+                .enumerate()
+                .map(|(i, e)| decode(ctx, e, m, policy).map_err(|e| format!("[{}]: {}", i, e)))
+                .collect::<Result<Vec<GosValue>, String>>()?;
+            Ok(ctx.new_slice(vals, m.value_type(metas)))
+        }
+        (MetadataType::Array(m, n), Json::Array(elems)) => {
+            // like encoding/json, the extra elements are dropped, the missing ones are zero
+            let mut vals = Vec::with_capacity(*n);
+            for i in 0..*n {
+                vals.push(match elems.get(i) {
+                    Some(e) => decode(ctx, e, m, policy).map_err(|e| format!("[{}]: {}", i, e))?,
+                    None => ctx.zero_val(m),
+                });
+            }
+            Ok(ctx.new_array(vals, m.value_type(metas)))
+        }
+        (MetadataType::Map(k, v), Json::Object(members)) => {
+            let mut map = Map::new();
+            for (key, val) in members.iter() {
+                let key_val = decode_key(ctx, key, k, policy)?;
+                let val = decode(ctx, val, v, policy).map_err(|e| format!("[{:?}]: {}", key, e))?;
+                map.insert(key_val, val);
+            }
+            Ok(ctx.new_map(map))
+        }
+        (MetadataType::Struct(fields), Json::Object(members)) => {
+            let val = ctx.zero_val(meta);
+            for (key, json) in members.iter() {
+                // an exact match first, and then a case-insensitive one like encoding/json
+                let index = struct_fields(meta, metas)
+                    .find(|(_, name, _)| name == key)
+                    .or_else(|| {
+                        struct_fields(meta, metas)
+                            .find(|(_, name, _)| name.eq_ignore_ascii_case(key))
+                    })
+                    .map(|(i, _, _)| i);
+                // unknown keys are ignored
+                if let Some(i) = index {
+                    let fmeta = &fields.infos()[i].meta;
+                    let fval =
+                        decode(ctx, json, fmeta, policy).map_err(|e| format!("{}: {}", key, e))?;
+                    val.as_struct().0.borrow_fields_mut()[i] = fval;
+                }
+            }
+            Ok(val)
+        }
+        (MetadataType::Interface(fields), _) if fields.infos().is_empty() => {
+            let prim = &ctx.vm_objs.prim_meta;
+            let (val, m) = match json {
+                Json::Bool(b) => ((*b).into(), prim.mbool),
+                Json::Number(n) => (n.parse::<f64>().unwrap().into(), prim.mfloat64),
+                Json::String(s) => (FfiCtx::new_string(s), prim.mstr),
+                _ => return Err(mismatch()),
+            };
+            Ok(ctx.new_empty_interface(val, m))
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+/// Converts the number `n` to the integer type of `meta`, the result is in the
+/// range of the type
+fn int(n: &str, policy: NumericPolicy, meta: &Meta, metas: &MetadataObjs) -> Result<i128, String> {
+    let (bits, signed) = match meta.mtype_unwraped(metas) {
+        MetadataType::Int8 => (8, true),
+        MetadataType::Int16 => (16, true),
+        MetadataType::Int32 => (32, true),
+        MetadataType::Int | MetadataType::Int64 => (64, true),
+        MetadataType::Uint8 => (8, false),
+        MetadataType::Uint16 => (16, false),
+        MetadataType::Uint32 => (32, false),
+        _ => (64, false),
+    };
+    let (min, max) = match signed {
+        true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+        false => (0, (1i128 << bits) - 1),
+    };
+    let is_int_text = !n.contains(['.', 'e', 'E']);
+    let val = match n.parse::<i128>() {
+        Ok(i) if is_int_text => i,
+        _ => {
+            let f: f64 = n.parse().unwrap_or(f64::NAN);
+            if !f.is_finite() {
+                return Err(format!(
+                    "cannot convert number {} to {}",
+                    n,
+                    type_string(meta, metas)
+                ));
+            }
+            if f.fract() != 0.0 && policy == NumericPolicy::Strict {
+                return Err(format!(
+                    "number {} is not an integer, cannot use it as {}",
+                    n,
+                    type_string(meta, metas)
+                ));
+            }
+            let f = f.trunc();
+            if f.abs() < 1e38 {
+                f as i128
+            } else if policy == NumericPolicy::GoConvert {
+                // exact, the result fits in an i128 and wraps like the other values
+                (f % 2f64.powi(bits)) as i128
+            } else if f < 0.0 {
+                i128::MIN
+            } else {
+                i128::MAX
+            }
+        }
+    };
+    if val >= min && val <= max {
+        return Ok(val);
+    }
+    match policy {
+        NumericPolicy::Strict => Err(format!(
+            "number {} overflows {}",
+            n,
+            type_string(meta, metas)
+        )),
+        NumericPolicy::Saturate => Ok(val.clamp(min, max)),
+        NumericPolicy::GoConvert => {
+            let wrapped = val.rem_euclid(1i128 << bits);
+            match signed && wrapped > max {
+                true => Ok(wrapped - (1i128 << bits)),
+                false => Ok(wrapped),
+            }
+        }
+    }
+}
+
+/// Converts the number `n` to a float not larger than `max` in magnitude,
+/// unless it's an infinity under `NumericPolicy::GoConvert`
+fn float(
+    n: &str,
+    policy: NumericPolicy,
+    max: f64,
+    meta: &Meta,
+    metas: &MetadataObjs,
+) -> Result<f64, String> {
+    let f: f64 = n.parse().unwrap_or(f64::NAN);
+    if f.is_nan() {
+        return Err(format!(
+            "cannot convert number {} to {}",
+            n,
+            type_string(meta, metas)
+        ));
+    }
+    if f.abs() <= max {
+        return Ok(f);
+    }
+    match policy {
+        NumericPolicy::Strict => Err(format!(
+            "number {} overflows {}",
+            n,
+            type_string(meta, metas)
+        )),
+        NumericPolicy::Saturate => Ok(max.copysign(f)),
+        NumericPolicy::GoConvert => Ok(f64::INFINITY.copysign(f)),
+    }
+}
+
+fn decode_key(
+    ctx: &FfiCtx,
+    key: &str,
+    meta: &Meta,
+    policy: NumericPolicy,
+) -> Result<GosValue, String> {
+    let metas = &ctx.vm_objs.metas;
+    let bad = || {
+        format!(
+            "cannot use {:?} as map key of type {}",
+            key,
+            type_string(meta, metas)
+        )
+    };
+    if meta.ptr_depth > 0 {
+        return Err(bad());
+    }
+    match meta.mtype_unwraped(metas) {
+        MetadataType::Str => Ok(FfiCtx::new_string(key)),
+        MetadataType::Int
+        | MetadataType::Int8
+        | MetadataType::Int16
+        | MetadataType::Int32
+        | MetadataType::Int64
+        | MetadataType::Uint
+        | MetadataType::Uint8
+        | MetadataType::Uint16
+        | MetadataType::Uint32
+        | MetadataType::Uint64 => {
+            let valid = key.strip_prefix('-').unwrap_or(key);
+            if valid.is_empty() || !valid.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(bad());
+            }
+            decode(ctx, &Json::Number(key.to_owned()), meta, policy)
+        }
+        _ => Err(bad()),
+    }
+}
+
+fn is_byte(meta: &Meta, metas: &MetadataObjs) -> bool {
+    meta.ptr_depth == 0 && matches!(meta.mtype_unwraped(metas), MetadataType::Uint8)
+}
+
+/// The encoded fields of a struct: (index, JSON name, omitempty)
+fn struct_fields<'a>(
+    meta: &Meta,
+    metas: &'a MetadataObjs,
+) -> impl Iterator<Item = (usize, String, bool)> + 'a {
+    let fields = metas[meta.underlying(metas).key].as_struct();
+    fields
+        .infos()
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.embedded_indices.is_none() && f.exported())
+        .filter_map(|(i, f)| {
+            let tag = f.lookup_tag("json");
+            let mut opts = tag.as_deref().unwrap_or("").split(',');
+            let name = opts.next().unwrap();
+            if name == "-" && tag.as_deref() == Some("-") {
+                return None;
+            }
+            let omitempty = opts.any(|o| o == "omitempty");
+            let name = match name.is_empty() {
+                true => f.name.clone(),
+                false => name.to_owned(),
+            };
+            Some((i, name, omitempty))
+        })
+}
+
+/// Appends `val`, a value of the type of `meta`, to `out` as JSON
+pub(crate) fn encode(
+    ctx: &FfiCtx,
+    val: &GosValue,
+    meta: &Meta,
+    out: &mut String,
+    depth: usize,
+) -> Result<(), String> {
+    let metas = &ctx.vm_objs.metas;
+    if depth > MAX_DEPTH {
+        return Err("encountered a cycle".to_owned());
+    }
+    if meta.ptr_depth > 0 {
+        if val.is_nil() {
+            out.push_str("null");
+            return Ok(());
+        }
+        let pointee = ctx.deref_pointer(val).map_err(|e| e.as_str().to_owned())?;
+        return encode(ctx, &pointee, &meta.unptr_to(), out, depth + 1);
+    }
+    match meta.mtype_unwraped(metas) {
+        MetadataType::Bool => out.push_str(if *val.as_bool() { "true" } else { "false" }),
+        MetadataType::Int => write!(out, "{}", val.as_int()).unwrap(),
+        MetadataType::Int8 => write!(out, "{}", val.as_int8()).unwrap(),
+        MetadataType::Int16 => write!(out, "{}", val.as_int16()).unwrap(),
+        MetadataType::Int32 => write!(out, "{}", val.as_int32()).unwrap(),
+        MetadataType::Int64 => write!(out, "{}", val.as_int64()).unwrap(),
+        MetadataType::Uint => write!(out, "{}", val.as_uint()).unwrap(),
+        MetadataType::UintPtr => write!(out, "{}", val.as_uint_ptr()).unwrap(),
+        MetadataType::Uint8 => write!(out, "{}", val.as_uint8()).unwrap(),
+        MetadataType::Uint16 => write!(out, "{}", val.as_uint16()).unwrap(),
+        MetadataType::Uint32 => write!(out, "{}", val.as_uint32()).unwrap(),
+        MetadataType::Uint64 => write!(out, "{}", val.as_uint64()).unwrap(),
+        MetadataType::Float32 => write_float(out, val.as_float32().into_inner() as f64, true)?,
+        MetadataType::Float64 => write_float(out, val.as_float64().into_inner(), false)?,
+        MetadataType::Str => write_string(out, &val.as_string().as_str()),
+        MetadataType::Slice(m) => match FfiCtx::slice_elems(val) {
+            None => out.push_str("null"),
+            Some(elems) if is_byte(m, metas) => {
+                let bytes: Vec<u8> = elems.iter().map(|x| *x.as_uint8()).collect();
+                write_string(out, &base64_encode(&bytes));
+            }
+            Some(elems) => encode_elems(ctx, &elems, m, out, depth)?,
+        },
+        MetadataType::Array(m, _) => encode_elems(ctx, &FfiCtx::array_elems(val), m, out, depth)?,
+        MetadataType::Map(k, v) => match val.as_map() {
+            None => out.push_str("null"),
+            Some(map) => {
+                let mut members = vec![];
+                for (key, val) in map.0.borrow_data().iter() {
+                    let key = match k.mtype_unwraped(metas) {
+                        MetadataType::Str if k.ptr_depth == 0 => {
+                            key.as_string().as_str().to_string()
+                        }
+                        MetadataType::Int
+                        | MetadataType::Int8
+                        | MetadataType::Int16
+                        | MetadataType::Int32
+                        | MetadataType::Int64
+                        | MetadataType::Uint
+                        | MetadataType::Uint8
+                        | MetadataType::Uint16
+                        | MetadataType::Uint32
+                        | MetadataType::Uint64
+                            if k.ptr_depth == 0 =>
+                        {
+                            key.to_string()
+                        }
+                        _ => return Err(format!("unsupported type: {}", type_string(meta, metas))),
+                    };
+                    members.push((key, val.clone()));
+                }
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+                out.push('{');
+                for (i, (key, val)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(out, key);
+                    out.push(':');
+                    encode(ctx, val, v, out, depth + 1)?;
+                }
+                out.push('}');
+            }
+        },
+        MetadataType::Struct(fields) => {
+            let vals = val.as_struct().0.borrow_fields().clone();
+            out.push('{');
+            let mut first = true;
+            for (i, name, omitempty) in struct_fields(meta, metas) {
+                let fmeta = &fields.infos()[i].meta;
+                if omitempty && is_empty(&vals[i], fmeta, metas) {
+                    continue;
+                }
+                if !first {
+                    out.push(',');
+                }
+                first = false;
+                write_string(out, &name);
+                out.push(':');
+                encode(ctx, &vals[i], fmeta, out, depth + 1)?;
+            }
+            out.push('}');
+        }
+        MetadataType::Interface(_) => match val.as_interface() {
+            None => out.push_str("null"),
+            Some(InterfaceObj::Gos(v, Some((m, _)))) => encode(ctx, v, m, out, depth + 1)?,
+            Some(_) => return Err("unsupported interface value".to_owned()),
+        },
+        _ => return Err(format!("unsupported type: {}", type_string(meta, metas))),
+    }
+    Ok(())
+}
+
+fn encode_elems(
+    ctx: &FfiCtx,
+    elems: &[GosValue],
+    meta: &Meta,
+    out: &mut String,
+    depth: usize,
+) -> Result<(), String> {
+    out.push('[');
+    for (i, e) in elems.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        encode(ctx, e, meta, out, depth + 1)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+// the values omitted by omitempty
+fn is_empty(val: &GosValue, meta: &Meta, metas: &MetadataObjs) -> bool {
+    if meta.ptr_depth > 0 {
+        return val.is_nil();
+    }
+    match meta.mtype_unwraped(metas) {
+        MetadataType::Bool => !*val.as_bool(),
+        MetadataType::Int
+        | MetadataType::Int8
+        | MetadataType::Int16
+        | MetadataType::Int32
+        | MetadataType::Int64
+        | MetadataType::Uint
+        | MetadataType::UintPtr
+        | MetadataType::Uint8
+        | MetadataType::Uint16
+        | MetadataType::Uint32
+        | MetadataType::Uint64
+        | MetadataType::Float32
+        | MetadataType::Float64 => val.to_string() == "0",
+        MetadataType::Str
+        | MetadataType::Array(_, _)
+        | MetadataType::Slice(_)
+        | MetadataType::Map(_, _) => val.is_nil() || val.len() == 0,
+        MetadataType::Interface(_) => val.is_nil(),
+        _ => false,
+    }
+}
+
+/// Formats a float like encoding/json does
+fn write_float(out: &mut String, f: f64, bits32: bool) -> Result<(), String> {
+    if f.is_nan() || f.is_infinite() {
+        return Err(format!("unsupported value: {}", f));
+    }
+    let abs = f.abs();
+    let exp = abs != 0.0
+        && if bits32 {
+            !(1e-6..1e21).contains(&(abs as f32))
+        } else {
+            !(1e-6..1e21).contains(&abs)
+        };
+    let s = match (exp, bits32) {
+        (false, false) => format!("{}", f),
+        (false, true) => format!("{}", f as f32),
+        (true, false) => format!("{:e}", f),
+        (true, true) => format!("{:e}", f as f32),
+    };
+    // 1e21 is 1e+21 in Go
+    match s.find('e') {
+        Some(i) if !s[i + 1..].starts_with('-') => {
+            out.push_str(&s[..=i]);
+            out.push('+');
+            out.push_str(&s[i + 1..]);
+        }
+        _ => out.push_str(&s),
+    }
+    Ok(())
+}
+
+/// Writes a JSON string, escaping the characters that encoding/json escapes
+pub(crate) fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let bad = || format!("illegal base64 data {:?}", s);
+    let data = s.trim_end_matches('=').as_bytes();
+    if !s.len().is_multiple_of(4) || s.len() - data.len() > 2 {
+        return Err(bad());
+    }
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let d = BASE64.iter().position(|&x| x == c).ok_or_else(bad)?;
+            n |= (d as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use go_vm::types::VMObjects;
+
+    #[test]
+    fn test_numeric_policy() {
+        let objs = VMObjects::new();
+        FfiCtx::with_objects(&objs, |ctx| {
+            let prim = &ctx.vm_objs.prim_meta;
+            let num = |n: &str| Json::Number(n.to_owned());
+            let policies = [
+                NumericPolicy::Strict,
+                NumericPolicy::Saturate,
+                NumericPolicy::GoConvert,
+            ];
+            for policy in policies {
+                for n in ["NaN", "inf", "-inf"] {
+                    assert!(decode(ctx, &num(n), &prim.mint64, policy).is_err());
+                    assert!(decode(ctx, &num(n), &prim.muint8, policy).is_err());
+                }
+                assert!(decode(ctx, &num("NaN"), &prim.mfloat64, policy).is_err());
+            }
+
+            let uint8 =
+                |n: &str, policy| decode(ctx, &num(n), &prim.muint8, policy).map(|v| *v.as_uint8());
+            assert!(uint8("300", NumericPolicy::Strict).is_err());
+            assert_eq!(uint8("300", NumericPolicy::Saturate), Ok(255));
+            assert_eq!(uint8("300", NumericPolicy::GoConvert), Ok(44));
+            assert_eq!(uint8("-1", NumericPolicy::Saturate), Ok(0));
+            assert_eq!(uint8("-1", NumericPolicy::GoConvert), Ok(255));
+            assert!(uint8("2.5", NumericPolicy::Strict).is_err());
+            assert_eq!(uint8("2.5", NumericPolicy::Saturate), Ok(2));
+            assert_eq!(uint8("2e2", NumericPolicy::Strict), Ok(200));
+            assert_eq!(uint8("1e40", NumericPolicy::Saturate), Ok(255));
+            assert_eq!(uint8("1e40", NumericPolicy::GoConvert), Ok(0));
+
+            let float32 = |n: &str, policy| {
+                decode(ctx, &num(n), &prim.mfloat32, policy).map(|v| v.as_float32().into_inner())
+            };
+            assert!(float32("1e39", NumericPolicy::Strict).is_err());
+            assert_eq!(float32("-1e39", NumericPolicy::Saturate), Ok(f32::MIN));
+            assert_eq!(float32("1e39", NumericPolicy::GoConvert), Ok(f32::INFINITY));
+        });
+    }
+}
@@ -37,13 +37,35 @@
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
 //! - `serde_borsh`: Serde support for bytecode using Borsh
 //! - `wasm`: Enable wasm support
+//! - `server`: Serve compile and call requests from other processes, see `server`
+//! - `stack_check`: Panic on the first instruction that reads a value of the wrong type, for
+//!   debugging the code generator
 //!
 
 mod engine;
 
+mod census;
+
+#[cfg(feature = "codegen")]
+mod consts;
+
+mod json;
+
+#[cfg(feature = "codegen")]
+mod package;
+
+#[cfg(feature = "codegen")]
+mod repl;
+
 #[cfg(feature = "go_std")]
 mod std;
 
+#[cfg(all(feature = "go_std", feature = "async"))]
+mod host;
+
+#[cfg(feature = "server")]
+pub mod server;
+
 mod vfs;
 
 mod source;
@@ -51,12 +73,17 @@ mod source;
 #[macro_use]
 pub mod ffi;
 
-#[cfg(feature = "go_std")]
-#[macro_use]
-extern crate lazy_static;
-
+pub use census::{CensusCount, CensusDelta, CensusDiff, HeapCensus};
+#[cfg(feature = "codegen")]
+pub use consts::{ConstKind, ConstTable, FoldedConst};
 pub use engine::*;
 pub use go_parser::{ErrorList, FileSet};
+#[cfg(all(feature = "go_std", feature = "async"))]
+pub use host::{BackPressure, EventPayload, HostEvent, HostEventSender};
+#[cfg(feature = "codegen")]
+pub use package::Package;
+#[cfg(feature = "codegen")]
+pub use repl::ReplResult;
 pub use source::*;
 
 pub use crate::vfs::{compound::CompoundFs, vfs_map::VfsMap, VirtualFs};
@@ -0,0 +1,126 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A main package made of several source files given as strings.
+//!
+//! The files are compiled as one unit, the way the files of a directory are: they
+//! must all have the same package clause, their top-level declarations share the
+//! package scope, so that a file refers to what another one declares, and the
+//! package-level variables are initialized in dependency order across the files.
+//! Redeclarations across the files and initialization cycles are reported by the
+//! type checker.
+
+use crate::engine::{ImportKey, SourceRead};
+use go_parser as parser;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The path of the directory the files of the package are in
+pub(crate) const PACKAGE_DIR: &str = "<package>";
+
+/// Built with `file`, compiled with `Engine::compile_package`
+#[derive(Default)]
+pub struct Package {
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Package {
+    pub fn new() -> Package {
+        Package::default()
+    }
+
+    /// Adds a source file, the name is the one its positions are reported with,
+    /// it ends with ".gos" or ".go".
+    pub fn file(mut self, name: &str, src: impl Into<String>) -> Package {
+        self.files.push((PathBuf::from(name), src.into()));
+        self
+    }
+
+    /// The errors of the file list, that would make some files left out silently
+    pub(crate) fn validate(&self) -> Result<(), parser::ErrorList> {
+        let el = parser::ErrorList::new();
+        if self.files.is_empty() {
+            el.add(None, "package has no files".to_owned(), false, false);
+        }
+        for (i, (name, _)) in self.files.iter().enumerate() {
+            let ext = name.extension().and_then(|x| x.to_str());
+            let stem = name.file_stem().and_then(|x| x.to_str()).unwrap_or("");
+            if !matches!(ext, Some("gos") | Some("go")) || stem.ends_with("_test") {
+                el.add(
+                    None,
+                    format!("not a source file name: {}", name.display()),
+                    false,
+                    false,
+                );
+            }
+            if self.files[..i].iter().any(|(n, _)| n == name) {
+                el.add(
+                    None,
+                    format!("duplicate file name: {}", name.display()),
+                    false,
+                    false,
+                );
+            }
+        }
+        match el.len() {
+            0 => Ok(()),
+            _ => Err(el),
+        }
+    }
+
+    fn get(&self, path: &Path) -> Option<&String> {
+        self.files.iter().find(|(n, _)| n == path).map(|(_, s)| s)
+    }
+}
+
+/// Reads the package as the directory `PACKAGE_DIR`, and the rest from `inner`
+pub(crate) struct PackageReader<'a, S> {
+    pub inner: &'a S,
+    pub package: &'a Package,
+}
+
+impl<'a, S> PackageReader<'a, S> {
+    fn is_dir_path(&self, path: &Path) -> bool {
+        path == Path::new(PACKAGE_DIR)
+    }
+}
+
+impl<'a, S: SourceRead> SourceRead for PackageReader<'a, S> {
+    fn working_dir(&self) -> &Path {
+        self.inner.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&Path> {
+        self.inner.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        match self.package.get(path) {
+            Some(src) => Ok(src.clone()),
+            None => self.inner.read_file(path),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        match self.is_dir_path(path) {
+            true => Ok(self.package.files.iter().map(|(n, _)| n.clone()).collect()),
+            false => self.inner.read_dir(path),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.package.get(path).is_some() || self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.is_dir_path(path) || self.inner.is_dir(path)
+    }
+
+    fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
+        match self.is_dir_path(Path::new(&key.path)) {
+            true => Ok((PathBuf::from(PACKAGE_DIR), key.path.clone())),
+            false => self.inner.canonicalize_import(key),
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The snippets of `Engine::eval`.
+//!
+//! A snippet is compiled as the body of a function of a program of its own: an
+//! expression is returned as a slice of interfaces, so that the values keep their
+//! types, anything else is run as statements and returns nothing. In REPL mode the
+//! std packages in `AUTO_IMPORTS` the snippet refers to are imported for it.
+
+use crate::engine::{ImportKey, SourceRead};
+use crate::json::{type_string, write_string};
+use go_parser as parser;
+use go_vm::types::{GosValue, InterfaceObj, Meta, MetadataType};
+use go_vm::FfiCtx;
+use std::fmt::{self, Write};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The path of the file the snippet is compiled in, its line 1 is the header
+pub(crate) const SNIPPET_PATH: &str = "repl.gos";
+
+/// The function of the snippet
+pub(crate) const EVAL_FUNC: &str = "__repl_eval";
+
+/// The std packages imported when a snippet refers to them in REPL mode, by name
+const AUTO_IMPORTS: &[(&str, &str)] = &[
+    ("bytes", "bytes"),
+    ("errors", "errors"),
+    ("fmt", "fmt"),
+    ("math", "math"),
+    ("sort", "sort"),
+    ("strconv", "strconv"),
+    ("strings", "strings"),
+    ("time", "time"),
+    ("unicode", "unicode"),
+    ("utf8", "unicode/utf8"),
+];
+
+// the most results of a call a snippet can be
+const MAX_RESULTS: usize = 8;
+
+// deep enough for any value worth reading
+const MAX_DEPTH: usize = 16;
+
+/// What `Engine::eval` made of a snippet
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplResult {
+    /// The values of an expression the way `%#v` prints them, empty for statements
+    pub values: Vec<String>,
+    /// The types of `values`, as far as they can be told at runtime, the names of
+    /// the named types are not known
+    pub type_strings: Vec<String>,
+    /// The import paths of the packages imported for the snippet
+    pub auto_imports: Vec<String>,
+    /// What the snippet wrote to `os.Stdout`, empty unless in REPL mode
+    pub stdout_captured: String,
+}
+
+impl fmt::Display for ReplResult {
+    /// A line of "value : type" for every value
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (v, t) in self.values.iter().zip(self.type_strings.iter()) {
+            writeln!(f, "{} : {}", v, t)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a snippet is compiled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Form {
+    /// Statements, nothing is returned
+    Stmts,
+    /// An expression of one value, or of none
+    Expr,
+    /// A call of a function with the given number of results, passing them on to
+    /// a variadic function is not supported
+    Results(usize),
+}
+
+/// The forms a snippet can be compiled in, in the order to try them
+pub(crate) fn forms(snippet: &str) -> Vec<Form> {
+    match parser::parse_expr(snippet) {
+        Ok((parser::ast::Expr::Call(_), _)) => std::iter::once(Form::Expr)
+            .chain((2..=MAX_RESULTS).map(Form::Results))
+            .collect(),
+        Ok(_) => vec![Form::Expr],
+        Err(_) => vec![Form::Stmts],
+    }
+}
+
+/// Makes the source of the program of `snippet`, and the import paths of the
+/// packages imported for it if `auto_import` is on. The snippet starts on line 2.
+pub(crate) fn wrap(snippet: &str, form: Form, auto_import: bool) -> (String, Vec<String>) {
+    let vars: Vec<String> = match form {
+        Form::Results(n) => (0..n).map(|i| format!("__v{}", i)).collect(),
+        _ => vec![],
+    };
+    let (open, snippet, close) = match form {
+        Form::Stmts => (String::new(), snippet, "\n\treturn nil\n".to_owned()),
+        // the comma keeps the semicolon out of the arguments
+        Form::Expr => (
+            " return __repl_values(".to_owned(),
+            snippet.trim_end(),
+            ",\n\t)\n".to_owned(),
+        ),
+        Form::Results(_) => (
+            format!(" {} :=", vars.join(", ")),
+            snippet,
+            format!("\n\treturn []interface{{}}{{{}}}\n", vars.join(", ")),
+        ),
+    };
+    let src = |imports: &[String]| {
+        let header: Vec<String> = imports
+            .iter()
+            .map(|p| format!("import {:?}; ", p))
+            .collect();
+        format!(
+            "package main; {}func {}() []interface{{}} {{{}\n{}{}}}\n\n\
+            func __repl_values(v ...interface{{}}) []interface{{}} {{ return v }}\n\n\
+            func main() {{}}\n",
+            header.concat(),
+            EVAL_FUNC,
+            open,
+            snippet,
+            close
+        )
+    };
+    let imports = match auto_import {
+        true => unresolved_packages(&src(&[])),
+        false => vec![],
+    };
+    (src(&imports), imports)
+}
+
+/// The import paths of the packages in `AUTO_IMPORTS` the program refers to
+fn unresolved_packages(src: &str) -> Vec<String> {
+    let mut fs = parser::FileSet::new();
+    let o = &mut parser::AstObjects::new();
+    let el = parser::ErrorList::new();
+    let (_, file) = parser::parse_file(o, &mut fs, &el, SNIPPET_PATH, src, false);
+    let names: Vec<&str> = file
+        .iter()
+        .flat_map(|f| f.unresolved.iter())
+        .map(|&i| o.idents[i].name.as_str())
+        .collect();
+    AUTO_IMPORTS
+        .iter()
+        .filter(|(name, _)| names.contains(name))
+        .map(|(_, path)| path.to_string())
+        .collect()
+}
+
+/// Moves the errors in the snippet to the lines of the snippet itself
+pub(crate) fn snippet_errors(el: parser::ErrorList) -> parser::ErrorList {
+    let moved = |p: &parser::FilePos| {
+        let mut p = p.clone();
+        if p.filename.as_str() == SNIPPET_PATH && p.line > 1 {
+            p.line -= 1;
+        }
+        p
+    };
+    let result = parser::ErrorList::new();
+    for e in el.borrow().iter() {
+        result.add(Some(moved(&e.pos)), e.msg.clone(), e.soft, e.by_parser);
+        for n in e.notes.iter() {
+            result.add_note(moved(&n.pos), n.label.clone());
+        }
+    }
+    result
+}
+
+/// Reads the snippet's program from `path`, and everything else from `inner`
+pub(crate) struct SnippetReader<'a, S> {
+    pub inner: &'a S,
+    pub path: &'static str,
+    pub src: String,
+}
+
+impl<'a, S> SnippetReader<'a, S> {
+    fn is_snippet(&self, path: &Path) -> bool {
+        path == Path::new(self.path)
+    }
+}
+
+impl<'a, S: SourceRead> SourceRead for SnippetReader<'a, S> {
+    fn working_dir(&self) -> &Path {
+        self.inner.working_dir()
+    }
+
+    fn base_dir(&self) -> Option<&Path> {
+        self.inner.base_dir()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        match self.is_snippet(path) {
+            true => Ok(self.src.clone()),
+            false => self.inner.read_file(path),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.read_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.is_snippet(path) || self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        !self.is_snippet(path) && self.inner.is_dir(path)
+    }
+
+    fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
+        match self.is_snippet(Path::new(&key.path)) {
+            true => Ok((PathBuf::from(self.path), key.path.clone())),
+            false => self.inner.canonicalize_import(key),
+        }
+    }
+}
+
+/// The values returned by `EVAL_FUNC` and their types
+pub(crate) fn display_values(ctx: &FfiCtx, values: &GosValue) -> (Vec<String>, Vec<String>) {
+    let metas = &ctx.vm_objs.metas;
+    FfiCtx::slice_elems(values)
+        .unwrap_or_default()
+        .iter()
+        .map(|v| match v.as_interface() {
+            Some(InterfaceObj::Gos(v, Some((m, _)))) => {
+                let mut out = String::new();
+                write_value(ctx, v, m, &mut out, 0);
+                (out, type_string(m, metas))
+            }
+            Some(InterfaceObj::Ffi(_)) => ("<ffi>".to_owned(), "?".to_owned()),
+            _ => ("<nil>".to_owned(), "<nil>".to_owned()),
+        })
+        .unzip()
+}
+
+/// Appends `val`, a value of the type of `meta`, to `out` the way `%#v` does
+fn write_value(ctx: &FfiCtx, val: &GosValue, meta: &Meta, out: &mut String, depth: usize) {
+    let metas = &ctx.vm_objs.metas;
+    let typ = || type_string(meta, metas);
+    if depth > MAX_DEPTH {
+        out.push_str("...");
+        return;
+    }
+    if meta.ptr_depth > 0 {
+        if val.is_nil() {
+            write!(out, "({})(nil)", typ()).unwrap();
+            return;
+        }
+        let pointee = meta.unptr_to();
+        let composite = matches!(
+            pointee.mtype_unwraped(metas),
+            MetadataType::Struct(_)
+                | MetadataType::Array(_, _)
+                | MetadataType::Slice(_)
+                | MetadataType::Map(_, _)
+        );
+        match ctx.deref_pointer(val) {
+            Ok(v) if meta.ptr_depth == 1 && composite => {
+                out.push('&');
+                write_value(ctx, &v, &pointee, out, depth + 1);
+            }
+            _ => write!(out, "({})({:p})", typ(), val.as_addr()).unwrap(),
+        }
+        return;
+    }
+    match meta.mtype_unwraped(metas) {
+        MetadataType::Bool
+        | MetadataType::Int
+        | MetadataType::Int8
+        | MetadataType::Int16
+        | MetadataType::Int32
+        | MetadataType::Int64 => write!(out, "{}", val).unwrap(),
+        MetadataType::Uint => write!(out, "{:#x}", val.as_uint()).unwrap(),
+        MetadataType::UintPtr => write!(out, "{:#x}", val.as_uint_ptr()).unwrap(),
+        MetadataType::Uint8 => write!(out, "{:#x}", val.as_uint8()).unwrap(),
+        MetadataType::Uint16 => write!(out, "{:#x}", val.as_uint16()).unwrap(),
+        MetadataType::Uint32 => write!(out, "{:#x}", val.as_uint32()).unwrap(),
+        MetadataType::Uint64 => write!(out, "{:#x}", val.as_uint64()).unwrap(),
+        MetadataType::Float32 => write_float(out, val.as_float32().into_inner() as f64),
+        MetadataType::Float64 => write_float(out, val.as_float64().into_inner()),
+        MetadataType::Complex64 => {
+            let c = val.as_complex64();
+            write_complex(out, c.r.into_inner() as f64, c.i.into_inner() as f64)
+        }
+        MetadataType::Complex128 => {
+            let c = val.as_complex128();
+            write_complex(out, c.r.into_inner(), c.i.into_inner())
+        }
+        MetadataType::Str => write_string(out, &val.as_string().as_str()),
+        MetadataType::Slice(m) => match FfiCtx::slice_elems(val) {
+            None => write!(out, "{}(nil)", typ()).unwrap(),
+            Some(elems) => write_elems(ctx, &typ(), &elems, m, out, depth),
+        },
+        MetadataType::Array(m, _) => {
+            write_elems(ctx, &typ(), &FfiCtx::array_elems(val), m, out, depth)
+        }
+        MetadataType::Map(k, v) => match val.as_map() {
+            None => write!(out, "{}(nil)", typ()).unwrap(),
+            Some(map) => {
+                let mut members: Vec<(String, String)> = map
+                    .0
+                    .borrow_data()
+                    .iter()
+                    .map(|(key, val)| {
+                        let (mut ks, mut vs) = (String::new(), String::new());
+                        write_value(ctx, key, k, &mut ks, depth + 1);
+                        write_value(ctx, val, v, &mut vs, depth + 1);
+                        (ks, vs)
+                    })
+                    .collect();
+                members.sort();
+                out.push_str(&typ());
+                out.push('{');
+                for (i, (key, val)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    write!(out, "{}:{}", key, val).unwrap();
+                }
+                out.push('}');
+            }
+        },
+        MetadataType::Struct(fields) => {
+            let vals = val.as_struct().0.borrow_fields().clone();
+            out.push_str(&typ());
+            out.push('{');
+            let declared = fields
+                .infos()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.embedded_indices.is_none());
+            for (n, (i, f)) in declared.enumerate() {
+                if n > 0 {
+                    out.push_str(", ");
+                }
+                write!(out, "{}:", f.name).unwrap();
+                write_value(ctx, &vals[i], &f.meta, out, depth + 1);
+            }
+            out.push('}');
+        }
+        MetadataType::Interface(_) => match val.as_interface() {
+            Some(InterfaceObj::Gos(v, Some((m, _)))) => write_value(ctx, v, m, out, depth + 1),
+            Some(InterfaceObj::Ffi(_)) => out.push_str("<ffi>"),
+            _ => write!(out, "{}(nil)", typ()).unwrap(),
+        },
+        MetadataType::Signature(_) | MetadataType::Channel(_, _) | MetadataType::UnsafePtr => {
+            match val.is_nil() {
+                true => write!(out, "({})(nil)", typ()).unwrap(),
+                false => write!(out, "({})({:p})", typ(), val.as_addr()).unwrap(),
+            }
+        }
+        MetadataType::Named(_, _) | MetadataType::None => out.push('?'),
+    }
+}
+
+fn write_elems(
+    ctx: &FfiCtx,
+    typ: &str,
+    elems: &[GosValue],
+    meta: &Meta,
+    out: &mut String,
+    depth: usize,
+) {
+    out.push_str(typ);
+    out.push('{');
+    for (i, e) in elems.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_value(ctx, e, meta, out, depth + 1);
+    }
+    out.push('}');
+}
+
+/// Formats a float like `%v` does, with an exponent for the very large and small ones
+fn write_float(out: &mut String, f: f64) {
+    let exp = f.abs().log10();
+    if f == 0.0 || !f.is_finite() || (-4.0..21.0).contains(&exp) {
+        match f {
+            f if f.is_nan() => out.push_str("NaN"),
+            f if f.is_infinite() => out.push_str(if f > 0.0 { "+Inf" } else { "-Inf" }),
+            f => write!(out, "{}", f).unwrap(),
+        }
+    } else {
+        // Rust writes "1e21" and "1e-7", Go "1e+21" and "1e-07"
+        let s = format!("{:e}", f);
+        let (mantissa, exp) = s.split_once('e').unwrap();
+        let (sign, digits) = match exp.strip_prefix('-') {
+            Some(d) => ('-', d),
+            None => ('+', exp),
+        };
+        write!(out, "{}e{}{:0>2}", mantissa, sign, digits).unwrap();
+    }
+}
+
+fn write_complex(out: &mut String, r: f64, i: f64) {
+    out.push('(');
+    write_float(out, r);
+    if i >= 0.0 || i.is_nan() {
+        out.push('+');
+    }
+    write_float(out, i);
+    out.push_str("i)");
+}
@@ -0,0 +1,621 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Compiles and runs scripts for other processes, over a stream of requests.
+//!
+//! Every message is a frame: a 4-byte big-endian length followed by that many
+//! bytes of a UTF-8 JSON object. All the objects have the protocol version `"v"`,
+//! the `"id"` the client chose for the request, and a `"type"`. The requests are:
+//! - `compile`: `"sources"` is an object from file paths to their contents and
+//!   `"main"` the path of the main file, "main.gos" by default. The program is
+//!   run to init its packages, and answered with `compiled` and its `"program"` id,
+//!   or `diagnostics`, each with its `"pos"`, `"message"` and the `"related"`
+//!   positions and messages if it has notes.
+//! - `call`: calls the function `"func"` of the main package of `"program"` with
+//!   the JSON array `"args"`, like `Engine::call_json`, answered with `result`
+//!   and its `"results"`.
+//! - `interrupt`: stops the request `"target"`, running or not yet started,
+//!   which is then answered with `interrupted`. It's answered with `ok` right away,
+//!   before the target.
+//! - `drop`: forgets `"program"`, answered with `ok`.
+//!
+//! `compile` and `call` may have `"limits"`, for now `{"timeout_ms": n}`, over
+//! which they are interrupted. Their responses have the `"stats"` of the run,
+//! and they can also be a `panic` with the `"message"` and `"backtrace"`.
+//! A request that can't be served is answered with `error` and its `"message"`,
+//! the id is null if the request couldn't be parsed.
+//! Requests are served in order, except `interrupt`.
+
+use crate::engine::{CallFailure, CallOptions, Engine, NumericPolicy, PanicInfo};
+use crate::json::{self, Json};
+use crate::SourceReader;
+use go_parser::Map;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The `"v"` of the messages
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// The default of `ServerConfig::max_frame_len`
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 << 20;
+
+/// Why a frame couldn't be read
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    /// The stream ended in the middle of a frame
+    Truncated {
+        expected: usize,
+        got: usize,
+    },
+    /// The frame is longer than allowed, it has been skipped
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "{}", e),
+            FrameError::Truncated { expected, got } => {
+                write!(f, "truncated frame: want {} bytes, got {}", expected, got)
+            }
+            FrameError::TooLarge { len, max } => {
+                write!(f, "frame too large: {} bytes, the limit is {}", len, max)
+            }
+        }
+    }
+}
+
+/// Reads `buf.len()` bytes, returns how many were read before the stream ended.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Reads the payload of a frame, `None` if the stream ends before it.
+/// The payload of a frame longer than `max_len` is skipped, so that the
+/// next one can be read.
+pub fn read_frame<R: Read>(r: &mut R, max_len: usize) -> Result<Option<Vec<u8>>, FrameError> {
+    let mut header = [0; 4];
+    match read_full(r, &mut header).map_err(FrameError::Io)? {
+        0 => return Ok(None),
+        4 => {}
+        got => return Err(FrameError::Truncated { expected: 4, got }),
+    }
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max_len {
+        let skipped = io::copy(&mut r.take(len as u64), &mut io::sink()).map_err(FrameError::Io)?;
+        return Err(match skipped as usize {
+            got if got < len => FrameError::Truncated { expected: len, got },
+            _ => FrameError::TooLarge { len, max: max_len },
+        });
+    }
+    let mut payload = vec![0; len];
+    match read_full(r, &mut payload).map_err(FrameError::Io)? {
+        got if got < len => Err(FrameError::Truncated { expected: len, got }),
+        _ => Ok(Some(payload)),
+    }
+}
+
+pub fn write_frame<W: Write>(w: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(payload)?;
+    w.flush()
+}
+
+pub struct ServerConfig {
+    /// Where the standard library is read from
+    pub std_dir: PathBuf,
+    /// Longer request frames are answered with an error
+    pub max_frame_len: usize,
+    /// The timeout of the requests without one in their limits
+    pub default_timeout: Option<Duration>,
+}
+
+impl ServerConfig {
+    pub fn new(std_dir: PathBuf) -> ServerConfig {
+        ServerConfig {
+            std_dir,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            default_timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Limits {
+    timeout: Option<Duration>,
+}
+
+enum Request {
+    Compile {
+        id: u64,
+        sources: Vec<(String, String)>,
+        main: String,
+        limits: Limits,
+    },
+    Call {
+        id: u64,
+        program: u64,
+        func: String,
+        args: Vec<Json>,
+        limits: Limits,
+    },
+    Interrupt {
+        id: u64,
+        target: u64,
+    },
+    Drop {
+        id: u64,
+        program: u64,
+    },
+}
+
+fn field<'a>(obj: &'a [(String, Json)], name: &str) -> Option<&'a Json> {
+    obj.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+}
+
+fn as_u64(val: &Json) -> Option<u64> {
+    match val {
+        Json::Number(n) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses a request, the error has the id if it could be found.
+fn parse_request(payload: &[u8]) -> Result<Request, (Option<u64>, String)> {
+    let text = std::str::from_utf8(payload).map_err(|e| (None, e.to_string()))?;
+    let obj = match Json::parse(text).map_err(|e| (None, e))? {
+        Json::Object(obj) => obj,
+        _ => return Err((None, "request is not an object".to_owned())),
+    };
+    let id = field(&obj, "id")
+        .and_then(as_u64)
+        .ok_or((None, "missing request id".to_owned()))?;
+    let err = |msg: &str| (Some(id), msg.to_owned());
+    match field(&obj, "v").and_then(as_u64) {
+        Some(PROTOCOL_VERSION) => {}
+        _ => return Err(err("unsupported protocol version")),
+    }
+    let u64_field = |name: &str| {
+        field(&obj, name)
+            .and_then(as_u64)
+            .ok_or_else(|| err(&format!("missing {}", name)))
+    };
+    let limits = match field(&obj, "limits") {
+        None => Limits::default(),
+        Some(Json::Object(limits)) => Limits {
+            timeout: match field(limits, "timeout_ms") {
+                None => None,
+                Some(ms) => Some(Duration::from_millis(
+                    as_u64(ms).ok_or_else(|| err("invalid timeout_ms"))?,
+                )),
+            },
+        },
+        Some(_) => return Err(err("limits is not an object")),
+    };
+    match field(&obj, "type") {
+        Some(Json::String(t)) if t == "compile" => {
+            let sources = match field(&obj, "sources") {
+                Some(Json::Object(files)) => files
+                    .iter()
+                    .map(|(path, src)| match src {
+                        Json::String(src) => Ok((path.clone(), src.clone())),
+                        _ => Err(err("source is not a string")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => return Err(err("missing sources")),
+            };
+            let main = match field(&obj, "main") {
+                None => "main.gos".to_owned(),
+                Some(Json::String(main)) => main.clone(),
+                Some(_) => return Err(err("main is not a string")),
+            };
+            Ok(Request::Compile {
+                id,
+                sources,
+                main,
+                limits,
+            })
+        }
+        Some(Json::String(t)) if t == "call" => Ok(Request::Call {
+            id,
+            program: u64_field("program")?,
+            func: match field(&obj, "func") {
+                Some(Json::String(func)) => func.clone(),
+                _ => return Err(err("missing func")),
+            },
+            args: match field(&obj, "args") {
+                None => vec![],
+                Some(Json::Array(args)) => args.clone(),
+                Some(_) => return Err(err("args is not an array")),
+            },
+            limits,
+        }),
+        Some(Json::String(t)) if t == "interrupt" => Ok(Request::Interrupt {
+            id,
+            target: u64_field("target")?,
+        }),
+        Some(Json::String(t)) if t == "drop" => Ok(Request::Drop {
+            id,
+            program: u64_field("program")?,
+        }),
+        _ => Err(err("unknown request type")),
+    }
+}
+
+/// Starts a response object, the caller adds the fields and closes it.
+fn response(id: Option<u64>, typ: &str) -> String {
+    let mut out = format!("{{\"v\":{},\"id\":", PROTOCOL_VERSION);
+    match id {
+        Some(id) => write!(out, "{}", id).unwrap(),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"type\":");
+    json::write_string(&mut out, typ);
+    out
+}
+
+fn error_response(id: Option<u64>, msg: &str) -> String {
+    let mut out = response(id, "error");
+    out.push_str(",\"message\":");
+    json::write_string(&mut out, msg);
+    out.push('}');
+    out
+}
+
+fn write_stats(out: &mut String, elapsed: Duration) {
+    write!(out, ",\"stats\":{{\"elapsed_us\":{}}}", elapsed.as_micros()).unwrap();
+}
+
+/// What the request being served is, shared with the thread reading requests
+/// to interrupt it.
+#[derive(Default)]
+struct Running {
+    id: Option<u64>,
+    // interrupted before they started
+    cancelled: HashSet<u64>,
+}
+
+/// Why a request was interrupted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stop {
+    Request,
+    Timeout,
+}
+
+/// Serves the requests read from a stream with an `Engine`.
+/// The compiled programs are kept until they are dropped, across streams.
+pub struct Server {
+    engine: Engine,
+    config: ServerConfig,
+    programs: Map<u64, go_vm::Bytecode>,
+    next_program: u64,
+    interrupt: Arc<AtomicBool>,
+    running: Arc<Mutex<Running>>,
+}
+
+impl Server {
+    pub fn new(mut engine: Engine, config: ServerConfig) -> Server {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        engine.set_interrupt_flag(Some(interrupt.clone()));
+        Server {
+            engine,
+            config,
+            programs: Map::new(),
+            next_program: 1,
+            interrupt,
+            running: Default::default(),
+        }
+    }
+
+    /// Serves the requests from `input` until it ends, the responses are written
+    /// to `output`. The requests are read on another thread, so that they can be
+    /// interrupted while running.
+    pub fn serve<R: Read + Send, W: Write + Send>(
+        &mut self,
+        mut input: R,
+        output: W,
+    ) -> io::Result<()> {
+        let output = Mutex::new(output);
+        let (tx, rx) = mpsc::channel();
+        let max_frame_len = self.config.max_frame_len;
+        let (interrupt, running) = (self.interrupt.clone(), self.running.clone());
+        let send = &|msg: &str| write_frame(&mut *output.lock().unwrap(), msg.as_bytes());
+        thread::scope(|s| {
+            let reader = s.spawn(move || -> io::Result<()> {
+                loop {
+                    let payload = match read_frame(&mut input, max_frame_len) {
+                        Ok(Some(payload)) => payload,
+                        Ok(None) => return Ok(()),
+                        Err(FrameError::Io(e)) => return Err(e),
+                        Err(e @ FrameError::TooLarge { .. }) => {
+                            send(&error_response(None, &e.to_string()))?;
+                            continue;
+                        }
+                        Err(e @ FrameError::Truncated { .. }) => {
+                            // there is no telling where the next frame starts
+                            return send(&error_response(None, &e.to_string()));
+                        }
+                    };
+                    match parse_request(&payload) {
+                        Ok(Request::Interrupt { id, target }) => {
+                            let mut r = running.lock().unwrap();
+                            if r.id == Some(target) {
+                                interrupt.store(true, Ordering::Relaxed);
+                            } else {
+                                r.cancelled.insert(target);
+                            }
+                            // answered before the target can be
+                            send(&(response(Some(id), "ok") + "}"))?;
+                            drop(r);
+                        }
+                        Ok(req) => {
+                            if tx.send(req).is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Err((id, msg)) => send(&error_response(id, &msg))?,
+                    }
+                }
+            });
+            for req in rx.iter() {
+                let resp = self.handle(req);
+                send(&resp)?;
+            }
+            reader.join().unwrap()
+        })
+    }
+
+    /// Serves the connections to a Unix socket at `path`, one at a time.
+    #[cfg(unix)]
+    pub fn serve_unix(&mut self, path: &Path) -> io::Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            self.serve(stream.try_clone()?, stream)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, req: Request) -> String {
+        match req {
+            Request::Compile {
+                id,
+                sources,
+                main,
+                limits,
+            } => self.compile(id, sources, &main, limits),
+            Request::Call {
+                id,
+                program,
+                func,
+                args,
+                limits,
+            } => self.call(id, program, &func, &args, limits),
+            Request::Drop { id, program } => match self.programs.remove(&program) {
+                Some(_) => response(Some(id), "ok") + "}",
+                None => error_response(Some(id), "no such program"),
+            },
+            Request::Interrupt { .. } => unreachable!(),
+        }
+    }
+
+    fn compile(
+        &mut self,
+        id: u64,
+        sources: Vec<(String, String)>,
+        main: &str,
+        limits: Limits,
+    ) -> String {
+        let sources = sources
+            .into_iter()
+            .map(|(path, src)| (PathBuf::from(path), Cow::Owned(src)))
+            .collect();
+        let reader = SourceReader::fs_lib_and_strings(self.config.std_dir.clone(), sources);
+        let start = Instant::now();
+        let bc = match self
+            .engine
+            .compile(&reader, &Path::new(".").join(main), true, false, false)
+        {
+            Ok(bc) => bc,
+            Err(el) => {
+                el.sort();
+                let mut out = response(Some(id), "diagnostics");
+                out.push_str(",\"diagnostics\":[");
+                for (i, e) in el.borrow().iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push_str("{\"pos\":");
+                    json::write_string(&mut out, &e.pos.to_string());
+                    out.push_str(",\"message\":");
+                    json::write_string(&mut out, &e.msg);
+                    if !e.notes.is_empty() {
+                        out.push_str(",\"related\":[");
+                        for (j, n) in e.notes.iter().enumerate() {
+                            if j > 0 {
+                                out.push(',');
+                            }
+                            out.push_str("{\"pos\":");
+                            json::write_string(&mut out, &n.pos.to_string());
+                            out.push_str(",\"message\":");
+                            json::write_string(&mut out, &n.label);
+                            out.push('}');
+                        }
+                        out.push(']');
+                    }
+                    out.push('}');
+                }
+                out.push_str("]}");
+                return out;
+            }
+        };
+        // the packages are initialized like `Engine::load_bytecode` does
+        let (panic, stop) = match self.limited(id, limits, || self.engine.run_bytecode(&bc)) {
+            Ok(result) => result,
+            Err(stop) => return interrupted_response(id, stop, None, start.elapsed()),
+        };
+        match panic {
+            Some(p) => {
+                let info = PanicInfo::new(&p, &bc);
+                self.panic_response(id, &info, stop, start.elapsed())
+            }
+            None => {
+                let program = self.next_program;
+                self.next_program += 1;
+                self.programs.insert(program, bc);
+                let mut out = response(Some(id), "compiled");
+                write!(out, ",\"program\":{}", program).unwrap();
+                write_stats(&mut out, start.elapsed());
+                out.push('}');
+                out
+            }
+        }
+    }
+
+    fn call(&self, id: u64, program: u64, func: &str, args: &[Json], limits: Limits) -> String {
+        let bc = match self.programs.get(&program) {
+            Some(bc) => bc,
+            None => return error_response(Some(id), "no such program"),
+        };
+        let start = Instant::now();
+        let options = CallOptions {
+            numeric_policy: Some(NumericPolicy::Strict),
+            ..Default::default()
+        };
+        let (result, stop) = match self.limited(id, limits, || {
+            self.engine.call_program(bc, func, args, &options)
+        }) {
+            Ok(result) => result,
+            Err(stop) => return interrupted_response(id, stop, None, start.elapsed()),
+        };
+        match result {
+            Ok(results) => {
+                let mut out = response(Some(id), "result");
+                out.push_str(",\"results\":");
+                out.push_str(&results);
+                write_stats(&mut out, start.elapsed());
+                out.push('}');
+                out
+            }
+            Err(CallFailure::Panic(info)) => self.panic_response(id, &info, stop, start.elapsed()),
+            Err(CallFailure::Error(e)) => error_response(Some(id), &e.to_string()),
+        }
+    }
+
+    /// Runs `f` as the request `id`, so that it can be interrupted, and stops it after
+    /// the timeout. Returns why it was stopped, if it was, along with the result.
+    /// It's not run at all if it was interrupted before it started.
+    fn limited<T>(
+        &self,
+        id: u64,
+        limits: Limits,
+        f: impl FnOnce() -> T,
+    ) -> Result<(T, Option<Stop>), Stop> {
+        {
+            let mut r = self.running.lock().unwrap();
+            if r.cancelled.remove(&id) {
+                return Err(Stop::Request);
+            }
+            r.id = Some(id);
+            self.interrupt.store(false, Ordering::Relaxed);
+        }
+        let timeout = limits.timeout.or(self.config.default_timeout);
+        let (result, timed_out) = match timeout {
+            None => (f(), false),
+            Some(timeout) => thread::scope(|s| {
+                let (done, wait) = mpsc::channel::<()>();
+                let interrupt = &self.interrupt;
+                let watchdog = s.spawn(move || match wait.recv_timeout(timeout) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        interrupt.store(true, Ordering::Relaxed);
+                        true
+                    }
+                    _ => false,
+                });
+                let result = f();
+                drop(done);
+                (result, watchdog.join().unwrap())
+            }),
+        };
+        let interrupted = self.interrupt.load(Ordering::Relaxed);
+        self.running.lock().unwrap().id = None;
+        let stop = match (timed_out, interrupted) {
+            (true, _) => Some(Stop::Timeout),
+            (false, true) => Some(Stop::Request),
+            _ => None,
+        };
+        Ok((result, stop))
+    }
+
+    /// A `panic` response, or an `interrupted` one if the panic is the interruption.
+    fn panic_response(
+        &self,
+        id: u64,
+        info: &PanicInfo,
+        stop: Option<Stop>,
+        elapsed: Duration,
+    ) -> String {
+        let (msg, backtrace) = info.format(self.engine.panic_format());
+        if let (go_vm::PanicKind::Interrupted, Some(stop)) = (info.kind, stop) {
+            return interrupted_response(id, stop, Some(&backtrace), elapsed);
+        }
+        let mut out = response(Some(id), "panic");
+        out.push_str(",\"message\":");
+        json::write_string(&mut out, &msg);
+        write!(out, ",\"goroutine\":{}", info.goroutine).unwrap();
+        write_backtrace(&mut out, &backtrace);
+        write_stats(&mut out, elapsed);
+        out.push('}');
+        out
+    }
+}
+
+fn interrupted_response(id: u64, stop: Stop, backtrace: Option<&str>, elapsed: Duration) -> String {
+    let mut out = response(Some(id), "interrupted");
+    out.push_str(",\"reason\":");
+    json::write_string(
+        &mut out,
+        match stop {
+            Stop::Request => "request",
+            Stop::Timeout => "timeout",
+        },
+    );
+    write_backtrace(&mut out, backtrace.unwrap_or(""));
+    write_stats(&mut out, elapsed);
+    out.push('}');
+    out
+}
+
+fn write_backtrace(out: &mut String, backtrace: &str) {
+    out.push_str(",\"backtrace\":[");
+    for (i, line) in backtrace.lines().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json::write_string(out, line);
+    }
+    out.push(']');
+}
@@ -7,7 +7,7 @@ use crate::vfs::VirtualFs;
 use crate::ErrorList;
 use go_parser::Map;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
 
 pub fn run(
@@ -16,7 +16,21 @@ pub fn run(
     path: &Path,
     panic_handler: Option<Rc<dyn Fn(String, String)>>,
 ) -> Result<(), ErrorList> {
-    let engine = Engine::new();
+    let mut engine = Engine::new();
+    engine.set_panic_hook(config.panic_hook);
+    engine.set_panic_format(config.panic_format);
+    engine.set_checked_arithmetic(config.checked_arithmetic);
+    engine.set_warn_defer_in_loop(config.warn_defer_in_loop);
+    engine.set_strictness(config.strictness);
+    engine.set_warning_handler(config.warning_handler);
+    engine.set_goroutine_grace_period(config.goroutine_grace_period);
+    engine.set_fail_on_goroutine_leak(config.fail_on_goroutine_leak);
+    if let Some(len) = config.max_interned_len {
+        engine.set_max_interned_len(len);
+    }
+    engine.set_numeric_policy(config.numeric_policy);
+    engine.set_tiering_threshold(config.tiering_threshold);
+    engine.set_map_order_seed(config.map_order_seed);
     #[cfg(feature = "go_std")]
     engine.set_std_io(config.std_in, config.std_out, config.std_err);
     engine.run_source(
@@ -28,9 +42,75 @@ pub fn run(
     )
 }
 
+/// Where a non-local import path is found by `ImportResolver`
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResolvedImport {
+    /// A package of the std library, whose natives are implemented by the engine
+    Std(PathBuf),
+    /// A package of Goscript source under one of the search roots
+    Source(PathBuf),
+}
+
+/// Finds the packages of non-local import paths, like "fmt" or "util/strs": in the
+/// std library first, then in the search roots in order, the way Go looks in GOROOT
+/// and then GOPATH. The first directory of the path with any file in it is the package.
+pub struct ImportResolver {
+    std_dir: Option<PathBuf>,
+    roots: Vec<PathBuf>,
+}
+
+impl ImportResolver {
+    pub fn new(std_dir: Option<PathBuf>, roots: Vec<PathBuf>) -> ImportResolver {
+        ImportResolver { std_dir, roots }
+    }
+
+    pub fn std_dir(&self) -> Option<&Path> {
+        self.std_dir.as_deref()
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Returns an error listing the locations searched if the package isn't found
+    pub fn resolve(&self, vfs: &dyn VirtualFs, path: &str) -> io::Result<ResolvedImport> {
+        let std = self
+            .std_dir
+            .iter()
+            .map(|d| ResolvedImport::Std(d.join(path)));
+        let roots = self
+            .roots
+            .iter()
+            .map(|d| ResolvedImport::Source(d.join(path)));
+        let mut searched = vec![];
+        for candidate in std.chain(roots) {
+            let dir = match &candidate {
+                ResolvedImport::Std(p) | ResolvedImport::Source(p) => p,
+            };
+            // a file is the main package given by its path
+            if vfs.is_file(dir) || vfs.read_dir(dir).is_ok_and(|files| !files.is_empty()) {
+                return Ok(candidate);
+            }
+            searched.push(match &candidate {
+                ResolvedImport::Std(_) => format!("{} (std)", dir.display()),
+                ResolvedImport::Source(_) => dir.display().to_string(),
+            });
+        }
+        let msg = match searched.is_empty() {
+            true => format!("base dir required for path: {}", path),
+            false => format!(
+                "cannot find package \"{}\" in any of: {}",
+                path,
+                searched.join(", ")
+            ),
+        };
+        Err(io::Error::new(io::ErrorKind::NotFound, msg))
+    }
+}
+
 pub struct SourceReader {
-    /// base directory for non-local imports(library files)
-    base_dir: Option<PathBuf>,
+    /// finds the non-local imports, in the library files and the search roots
+    resolver: ImportResolver,
     /// working directory
     working_dir: PathBuf,
     /// The virtual file system from which to read files.
@@ -44,12 +124,26 @@ impl SourceReader {
         vfs: Box<dyn VirtualFs>,
     ) -> SourceReader {
         SourceReader {
-            base_dir,
+            resolver: ImportResolver::new(base_dir, vec![]),
             working_dir,
             vfs,
         }
     }
 
+    /// Sets the directories searched for the non-local imports not in the std library,
+    /// in order. A relative one is relative to the working directory.
+    pub fn set_search_roots(&mut self, roots: Vec<PathBuf>) {
+        let roots = roots
+            .into_iter()
+            .map(|r| self.working_dir.join(r))
+            .collect();
+        self.resolver.roots = roots;
+    }
+
+    pub fn resolver(&self) -> &ImportResolver {
+        &self.resolver
+    }
+
     /// Create a SourceReader that reads from local file system.
     #[cfg(feature = "read_fs")]
     pub fn local_fs(base_dir: PathBuf, working_dir: PathBuf) -> SourceReader {
@@ -63,30 +157,41 @@ impl SourceReader {
         source: std::borrow::Cow<'static, str>,
     ) -> (SourceReader, PathBuf) {
         let temp_file_name = "temp_file.gos";
-        let vfs_map_name = "vfs_map";
-        let vfs_fs_name = "vfs_fs";
         (
-            SourceReader::new(
-                Some(Path::new(vfs_fs_name).join(base_dir)),
-                PathBuf::from(format!("{}/", vfs_map_name)),
-                Box::new(crate::CompoundFs::new(Map::from([
-                    (
-                        vfs_fs_name.to_owned(),
-                        Box::new(crate::VfsFs {}) as Box<dyn VirtualFs>,
-                    ),
-                    (
-                        vfs_map_name.to_owned(),
-                        Box::new(crate::VfsMap::new(Map::from([(
-                            PathBuf::from(temp_file_name),
-                            source,
-                        )]))),
-                    ),
-                ]))),
+            SourceReader::fs_lib_and_strings(
+                base_dir,
+                Map::from([(PathBuf::from(temp_file_name), source)]),
             ),
             PathBuf::from(format!("./{}", temp_file_name)),
         )
     }
 
+    /// Create a SourceReader that reads library files from local file system and
+    /// user's source code from a map of relative paths to strings.
+    /// The files in the map can be imported with paths like "./dir".
+    #[cfg(feature = "read_fs")]
+    pub fn fs_lib_and_strings(
+        base_dir: PathBuf,
+        sources: Map<PathBuf, std::borrow::Cow<'static, str>>,
+    ) -> SourceReader {
+        let vfs_map_name = "vfs_map";
+        let vfs_fs_name = "vfs_fs";
+        SourceReader::new(
+            Some(Path::new(vfs_fs_name).join(base_dir)),
+            PathBuf::from(format!("{}/", vfs_map_name)),
+            Box::new(crate::CompoundFs::new(Map::from([
+                (
+                    vfs_fs_name.to_owned(),
+                    Box::new(crate::VfsFs {}) as Box<dyn VirtualFs>,
+                ),
+                (
+                    vfs_map_name.to_owned(),
+                    Box::new(crate::VfsMap::new(sources)),
+                ),
+            ]))),
+        )
+    }
+
     /// Creates a SourceReader that reads from a zip archive and a string.
     /// Returns the SourceReader and the path of the virtual file that contains the string.
     #[cfg(feature = "read_zip")]
@@ -155,7 +260,7 @@ impl SourceRead for SourceReader {
     }
 
     fn base_dir(&self) -> Option<&Path> {
-        self.base_dir.as_ref().map(|x| x.as_path())
+        self.resolver.std_dir()
     }
 
     fn read_file(&self, path: &Path) -> io::Result<String> {
@@ -174,12 +279,16 @@ impl SourceRead for SourceReader {
         self.vfs.is_dir(path)
     }
 
+    /// A local import is identified by where it resolves to rather than how it's
+    /// spelled, so that "./lib" imported from the working dir and "../lib" imported
+    /// from a sub dir are the same package, and its init runs only once.
     fn canonicalize_import(&self, key: &ImportKey) -> io::Result<(PathBuf, String)> {
         let mut import_path = key.path.clone();
         let path = if self.vfs.is_local(&key.path) {
+            let rel = self.vfs.strip_prefix(Path::new(&key.dir)).join(&key.path);
+            import_path = normalize_local_path(&rel);
             let mut wd = self.working_dir().to_owned();
-            wd.push(self.vfs.strip_prefix(Path::new(&key.dir)));
-            wd.push(&key.path);
+            wd.push(rel);
             if let Some(base) = &self.base_dir() {
                 if let Ok(rel) = wd.as_path().strip_prefix(base) {
                     import_path = rel.to_string_lossy().to_string()
@@ -187,18 +296,40 @@ impl SourceRead for SourceReader {
             }
             wd
         } else {
-            if let Some(base) = &self.base_dir() {
-                let mut p = PathBuf::new();
-                p.push(base);
-                p.push(&key.path);
-                p
-            } else {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("base dir required for path: {}", key.path),
-                ));
+            match self.resolver.resolve(self.vfs.as_ref(), &key.path)? {
+                ResolvedImport::Std(p) | ResolvedImport::Source(p) => p,
             }
         };
         self.vfs.canonicalize_path(&path).map(|p| (p, import_path))
     }
 }
+
+/// Removes the "." and ".." components of a path relative to the working dir,
+/// the result starts with "./" unless it's absolute or goes above the working dir.
+fn normalize_local_path(path: &Path) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut root = String::new();
+    for c in path.components() {
+        match c {
+            Component::Prefix(p) => root.push_str(&p.as_os_str().to_string_lossy()),
+            Component::RootDir => root.push('/'),
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(last) if last != ".." => {
+                    parts.pop();
+                }
+                _ if !root.is_empty() => {}
+                _ => parts.push("..".to_owned()),
+            },
+            Component::Normal(n) => parts.push(n.to_string_lossy().to_string()),
+        }
+    }
+    let joined = parts.join("/");
+    if !root.is_empty() {
+        root + &joined
+    } else if parts.first().is_some_and(|x| x == "..") {
+        joined
+    } else {
+        format!("./{}", joined)
+    }
+}
@@ -26,4 +26,20 @@ impl BitsFfi {
     fn ffi_f64_from_bits(u: u64) -> f64 {
         f64::from_be_bytes(u.to_be_bytes())
     }
+
+    fn ffi_sqrt(x: f64) -> f64 {
+        x.sqrt()
+    }
+
+    fn ffi_floor(x: f64) -> f64 {
+        x.floor()
+    }
+
+    fn ffi_ceil(x: f64) -> f64 {
+        x.ceil()
+    }
+
+    fn ffi_trunc(x: f64) -> f64 {
+        x.trunc()
+    }
 }
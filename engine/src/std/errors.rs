@@ -0,0 +1,84 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::*;
+
+#[derive(Ffi)]
+pub struct ErrorsFfi;
+
+#[ffi_impl(rename = "errors")]
+impl ErrorsFfi {
+    /// Panics like Go's errors.As if target is not a pointer to an interface or
+    /// to a type implementing error
+    fn ffi_check_as_target(
+        ctx: &FfiCtx,
+        target: GosValue,
+        error_type: GosValue,
+    ) -> RuntimeResult<()> {
+        let (_, meta) = as_target(&target)?;
+        let elem = meta.unptr_to();
+        let (_, err_meta) = iface_value(&error_type).unwrap();
+        let metas = &ctx.vm_objs.metas;
+        let implements = elem.value_type(metas) == ValueType::Interface
+            || err_meta
+                .unptr_to()
+                .iface_bindings_or_missing(&elem, ctx.vm_objs)
+                .is_ok();
+        match implements {
+            true => Ok(()),
+            false => Err("errors: *target must be interface or implement error"
+                .to_owned()
+                .into()),
+        }
+    }
+
+    /// Sets *target to err, if err's dynamic type is assignable to the type of *target
+    fn ffi_assign_to(ctx: &mut FfiCtx, err: GosValue, target: GosValue) -> RuntimeResult<bool> {
+        let (ptr, meta) = as_target(&target)?;
+        let elem = meta.unptr_to();
+        let (val, val_meta) = match iface_value(&err) {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let metas = &ctx.vm_objs.metas;
+        let val = if val_meta.identical(&elem, metas) {
+            ctx.copy_value(val)
+        } else if elem.value_type(metas) == ValueType::Interface
+            && elem
+                .iface_bindings_or_missing(&val_meta, ctx.vm_objs)
+                .is_ok()
+        {
+            ctx.copy_to_interface(val, (&elem, val_meta))
+        } else {
+            return Ok(false);
+        };
+        ptr.as_non_nil_pointer()?
+            .set_pointee(&val, ctx.stack, &ctx.vm_objs.packages, ctx.gcc)?;
+        Ok(true)
+    }
+
+    /// Whether the dynamic type of v is comparable, an interface holding any other
+    /// type panics when compared
+    fn ffi_comparable(ctx: &FfiCtx, v: GosValue) -> bool {
+        iface_value(&v).is_none_or(|(_, meta)| meta.comparable(&ctx.vm_objs.metas))
+    }
+}
+
+/// The dynamic value and type of an interface, `None` if it's nil
+fn iface_value(iface: &GosValue) -> Option<(&GosValue, Meta)> {
+    match iface.as_interface()? as &InterfaceObj {
+        InterfaceObj::Gos(v, Some((m, _))) => Some((v, *m)),
+        _ => None,
+    }
+}
+
+/// The pointer in target and its type, if it's a non-nil pointer
+fn as_target(target: &GosValue) -> RuntimeResult<(&GosValue, Meta)> {
+    match iface_value(target) {
+        Some((ptr, meta)) if meta.ptr_depth > 0 && !ptr.is_nil() => Ok((ptr, meta)),
+        _ => Err("errors: target must be a non-nil pointer".to_owned().into()),
+    }
+}
@@ -5,23 +5,39 @@
 /// Go 1.12
 ///
 mod bits;
+mod errors;
 mod fmt2;
 mod io;
 pub(crate) mod os;
+mod rand;
 mod reflect;
 #[cfg(feature = "async")]
+mod runtime;
+mod strconv;
+mod strings;
+#[cfg(feature = "async")]
 mod sync;
+mod time;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
 
 pub(crate) fn register(factory: &mut go_vm::FfiFactory) {
     fmt2::Fmt2Ffi::register(factory);
     bits::BitsFfi::register(factory);
+    errors::ErrorsFfi::register(factory);
+    #[cfg(feature = "async")]
+    runtime::RuntimeFfi::register(factory);
     #[cfg(feature = "async")]
     sync::MutexFfi::register(factory);
     #[cfg(feature = "async")]
     sync::RWMutexFfi::register(factory);
+    #[cfg(feature = "async")]
+    sync::WaitGroupFfi::register(factory);
+    rand::RandFfi::register(factory);
     reflect::ReflectFfi::register(factory);
     io::IoFfi::register(factory);
     os::FileFfi::register(factory);
+    strings::StringsFfi::register(factory);
+    strconv::StrconvFfi::register(factory);
+    time::TimeFfi::register(factory);
 }
@@ -10,7 +10,6 @@ use std::fs;
 use std::io;
 use std::io::prelude::*;
 use std::rc::Rc;
-use std::sync::Arc;
 use std::sync::Mutex;
 
 // Flags to OpenFile
@@ -22,9 +21,20 @@ const O_CREATE: usize = 0x00040;
 const O_EXCL: usize = 0x00080;
 const O_TRUNC: usize = 0x00200;
 
-lazy_static! {
-    static ref STD_IO_API: Arc<Mutex<StdIoApi>> = Arc::new(Mutex::new(StdIoApi::default()));
-}
+// Error kinds reported to Go, see errorKind in os/error.gos
+const ERR_OTHER: isize = 1;
+const ERR_INVALID: isize = 2;
+const ERR_PERMISSION: isize = 3;
+const ERR_EXIST: isize = 4;
+const ERR_NOT_EXIST: isize = 5;
+const ERR_CLOSED: isize = 6;
+
+// Process wide, shared by all the engines
+static STD_IO_API: Mutex<StdIoApi> = Mutex::new(StdIoApi {
+    std_in: None,
+    std_out: None,
+    std_err: None,
+});
 
 pub fn set_std_io(
     std_in: Option<Box<dyn std::io::Read + Sync + Send>>,
@@ -58,23 +68,46 @@ impl FileFfi {
         }
     }
 
-    fn ffi_open(path: GosValue, flags: isize) -> (GosValue, isize, GosValue) {
+    fn ffi_temp_dir() -> GosValue {
+        FfiCtx::new_string(&std::env::temp_dir().to_string_lossy())
+    }
+
+    fn ffi_remove(path: GosValue) -> (isize, GosValue) {
+        let path = path.as_string().as_str();
+        let r = match fs::metadata(&*path) {
+            Ok(m) if m.is_dir() => fs::remove_dir(&*path),
+            _ => fs::remove_file(&*path),
+        };
+        let (_, kind, msg) = FileFfi::result_to_go(r, |_| ());
+        (kind, msg)
+    }
+
+    fn ffi_open(path: GosValue, flags: isize, perm: u32) -> (GosValue, isize, GosValue) {
         let path = path.as_string().as_str();
         let flags = flags as usize;
         let mut options = fs::OpenOptions::new();
-        match flags & O_RDWR {
+        match flags & (O_WRONLY | O_RDWR) {
             O_RDONLY => options.read(true),
             O_WRONLY => options.write(true),
             O_RDWR => options.read(true).write(true),
-            _ => unreachable!(),
+            _ => {
+                let e = io::Error::from(io::ErrorKind::InvalidInput);
+                return FileFfi::result_to_go(Err::<fs::File, _>(e), |_| {
+                    FfiCtx::new_nil(ValueType::UnsafePtr)
+                });
+            }
         };
         options.append((flags & O_APPEND) != 0);
-        options.append((flags & O_TRUNC) != 0);
+        options.truncate((flags & O_TRUNC) != 0);
         match (((flags & O_CREATE) != 0), ((flags & O_EXCL) != 0)) {
             (true, false) => options.create(true),
             (true, true) => options.create_new(true),
             _ => &options,
         };
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, perm);
+        #[cfg(not(unix))]
+        let _ = perm;
         let r = options.open(&*path);
         FileFfi::result_to_go(r, |opt| match opt {
             Some(f) => VirtualFile::with_sys_file(f).into_val(),
@@ -84,18 +117,30 @@ impl FileFfi {
 
     fn ffi_read(fp: GosValue, buffer: GosValue) -> RuntimeResult<(isize, isize, GosValue)> {
         let file = fp.as_non_nil_unsafe_ptr()?.downcast_ref::<VirtualFile>()?;
-        let slice = &buffer.as_non_nil_slice::<Elem8>()?.0;
-        let mut buf = slice.as_raw_slice_mut();
-        let r = file.read(&mut buf);
+        // a nil buffer reads nothing, but still reports errors such as a closed file
+        let r = match buffer.as_slice::<Elem8>() {
+            Some(slice) => file.read(&mut slice.0.as_raw_slice_mut()),
+            None => file.read(&mut []),
+        };
         Ok(FileFfi::result_to_go(r, |opt| opt.unwrap_or(0) as isize))
     }
 
-    fn ffi_write(fp: GosValue, buffer: GosValue) -> RuntimeResult<(isize, isize, GosValue)> {
+    fn ffi_write(
+        ctx: &FfiCtx,
+        fp: GosValue,
+        buffer: GosValue,
+    ) -> RuntimeResult<(isize, isize, GosValue)> {
         let file = fp.as_non_nil_unsafe_ptr()?.downcast_ref::<VirtualFile>()?;
-        let slice = &buffer.as_non_nil_slice::<Elem8>()?.0;
-        let buf = slice.as_raw_slice();
-        let r = file.write(&buf);
-        Ok(FileFfi::result_to_go(r, |opt| opt.unwrap_or(0) as isize))
+        Ok(match buffer.as_slice::<Elem8>() {
+            Some(slice) => file.write_all(ctx.std_out(), &slice.0.as_raw_slice()),
+            None => file.write_all(ctx.std_out(), &[]),
+        })
+    }
+
+    fn ffi_close(fp: GosValue) -> RuntimeResult<(isize, GosValue)> {
+        let file = fp.as_non_nil_unsafe_ptr()?.downcast_ref::<VirtualFile>()?;
+        let (_, kind, msg) = FileFfi::result_to_go(file.close(), |_| ());
+        Ok((kind, msg))
     }
 
     fn ffi_seek(fp: GosValue, offset: i64, whence: isize) -> RuntimeResult<(i64, isize, GosValue)> {
@@ -116,15 +161,43 @@ impl FileFfi {
     {
         match result {
             Ok(i) => (f(Some(i)), 0, FfiCtx::new_string("")),
-            Err(e) => (
-                f(None),
-                e.kind() as isize,
-                FfiCtx::new_string(&e.to_string()),
-            ),
+            Err(e) => (f(None), error_kind(&e), FfiCtx::new_string(&error_msg(&e))),
         }
     }
 }
 
+fn error_kind(e: &io::Error) -> isize {
+    match e.kind() {
+        io::ErrorKind::InvalidInput => ERR_INVALID,
+        io::ErrorKind::PermissionDenied => ERR_PERMISSION,
+        io::ErrorKind::AlreadyExists => ERR_EXIST,
+        io::ErrorKind::NotFound => ERR_NOT_EXIST,
+        _ if e.to_string() == CLOSED_MSG => ERR_CLOSED,
+        _ => ERR_OTHER,
+    }
+}
+
+/// The message of an OS error spelled the way Go does, e.g. "no such file or directory"
+/// rather than "No such file or directory (os error 2)".
+fn error_msg(e: &io::Error) -> String {
+    let msg = e.to_string();
+    let msg = match (e.raw_os_error(), msg.rfind(" (os error ")) {
+        (Some(_), Some(i)) => &msg[..i],
+        _ => &msg,
+    };
+    let mut chars = msg.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+const CLOSED_MSG: &str = "file already closed";
+
+fn closed_err() -> io::Error {
+    io::Error::other(CLOSED_MSG)
+}
+
 pub enum StdIo {
     StdIn,
     StdOut,
@@ -133,7 +206,10 @@ pub enum StdIo {
 
 impl StdIo {
     fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut api = STD_IO_API.lock().unwrap();
+        self.read_with(&mut STD_IO_API.lock().unwrap(), buf)
+    }
+
+    fn read_with(&self, api: &mut StdIoApi, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             Self::StdIn => match &mut api.std_in {
                 Some(r) => r.read(buf),
@@ -151,7 +227,10 @@ impl StdIo {
     }
 
     fn write(&self, buf: &[u8]) -> io::Result<usize> {
-        let mut api = STD_IO_API.lock().unwrap();
+        self.write_with(&mut STD_IO_API.lock().unwrap(), buf)
+    }
+
+    fn write_with(&self, api: &mut StdIoApi, buf: &[u8]) -> io::Result<usize> {
         match self {
             Self::StdOut => match &mut api.std_out {
                 Some(r) => r.write(buf),
@@ -187,13 +266,13 @@ impl StdIo {
 
 #[derive(UnsafePtr)]
 pub enum VirtualFile {
-    File(Rc<RefCell<fs::File>>),
+    File(Rc<RefCell<Option<fs::File>>>),
     StdIo(StdIo),
 }
 
 impl VirtualFile {
     fn with_sys_file(f: fs::File) -> VirtualFile {
-        VirtualFile::File(Rc::new(RefCell::new(f)))
+        VirtualFile::File(Rc::new(RefCell::new(Some(f))))
     }
 
     fn with_std_io(io: StdIo) -> VirtualFile {
@@ -202,21 +281,23 @@ impl VirtualFile {
 
     fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
-            Self::File(f) => f.borrow_mut().read(buf),
+            Self::File(f) => f.borrow_mut().as_mut().ok_or_else(closed_err)?.read(buf),
             Self::StdIo(io) => io.read(buf),
         }
     }
 
-    fn write(&self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            Self::File(f) => f.borrow_mut().write(buf),
-            Self::StdIo(io) => io.write(buf),
+    /// Writes to the file, the std out goes to `std_out` if the run has one
+    fn write(&self, std_out: Option<&StdOutSink>, buf: &[u8]) -> io::Result<usize> {
+        match (self, std_out) {
+            (Self::File(f), _) => f.borrow_mut().as_mut().ok_or_else(closed_err)?.write(buf),
+            (Self::StdIo(StdIo::StdOut), Some(out)) => out.borrow_mut().write(buf),
+            (Self::StdIo(io), _) => io.write(buf),
         }
     }
 
     fn seek(&self, pos: io::SeekFrom) -> io::Result<u64> {
         match self {
-            Self::File(f) => f.borrow_mut().seek(pos),
+            Self::File(f) => f.borrow_mut().as_mut().ok_or_else(closed_err)?.seek(pos),
             Self::StdIo(_) => Err(io::Error::new(
                 io::ErrorKind::Unsupported,
                 "seek from std io",
@@ -224,6 +305,33 @@ impl VirtualFile {
         }
     }
 
+    /// Keeps writing until everything is written or there is an error like Go does,
+    /// returns what `ffi_write` returns.
+    fn write_all(&self, std_out: Option<&StdOutSink>, buf: &[u8]) -> (isize, isize, GosValue) {
+        let mut n = 0;
+        while n < buf.len() {
+            let e = match self.write(std_out, &buf[n..]) {
+                Ok(0) => io::Error::from(io::ErrorKind::WriteZero),
+                Ok(m) => {
+                    n += m;
+                    continue;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+            return FileFfi::result_to_go(Err::<(), _>(e), |_| n as isize);
+        }
+        (n as isize, 0, FfiCtx::new_string(""))
+    }
+
+    /// Closes a file by dropping it, closing a std io is a no-op
+    fn close(&self) -> io::Result<()> {
+        match self {
+            Self::File(f) => f.borrow_mut().take().map(drop).ok_or_else(closed_err),
+            Self::StdIo(_) => Ok(()),
+        }
+    }
+
     fn into_val(self) -> GosValue {
         FfiCtx::new_unsafe_ptr(Rc::new(self))
     }
@@ -7,6 +7,8 @@ use crate::ffi::*;
 use go_vm::types::*;
 use std::any::Any;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::rc::Rc;
 
@@ -16,17 +18,14 @@ macro_rules! err_wrong_type {
     };
 }
 
-macro_rules! err_index_oor {
-    () => {
-        Err("reflect: index out of range".to_owned().into())
-    };
-}
-
-macro_rules! err_set_val_type {
-    () => {
-        Err(RuntimeError::new(
-            "reflect: set value with wrong type".to_owned(),
-        ))
+/// The panic of Go's reflect.Value when a method doesn't apply to its kind
+macro_rules! err_call {
+    ($method:expr, $typ:expr) => {
+        Err(RuntimeError::new(format!(
+            "reflect: call of reflect.Value.{} on {} Value",
+            $method,
+            GosKind::of($typ).name()
+        )))
     };
 }
 
@@ -36,8 +35,8 @@ fn wrap_std_val(v: GosValue, m: Option<Meta>) -> GosValue {
 }
 
 #[inline]
-fn wrap_ptr_std_val(p: Box<PointerObj>, m: Option<Meta>) -> GosValue {
-    FfiCtx::new_unsafe_ptr(Rc::new(StdValue::Pointer(p, m, None)))
+fn wrap_ptr_std_val(p: Box<PointerObj>, m: Option<Meta>, access: Access) -> GosValue {
+    FfiCtx::new_unsafe_ptr(Rc::new(StdValue::Pointer(p, m, access)))
 }
 
 #[inline]
@@ -45,6 +44,20 @@ fn val_to_std_val(val: &GosValue) -> RuntimeResult<&StdValue> {
     val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdValue>()
 }
 
+/// Like `val_to_std_val`, but the zero Value panics the way it does in Go
+#[inline]
+fn val_to_std_val_for<'a>(val: &'a GosValue, method: &str) -> RuntimeResult<&'a StdValue> {
+    match val.is_nil() {
+        true => Err(format!("reflect: call of reflect.Value.{} on zero Value", method).into()),
+        false => val_to_std_val(val),
+    }
+}
+
+#[inline]
+fn val_to_std_type(val: &GosValue) -> RuntimeResult<&StdType> {
+    val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdType>()
+}
+
 #[inline]
 fn val_to_map_iter(val: &GosValue) -> RuntimeResult<&StdMapIter> {
     val.as_non_nil_unsafe_ptr()?.downcast_ref::<StdMapIter>()
@@ -85,127 +98,281 @@ enum GosKind {
     UnsafePtr,
 }
 
+impl GosKind {
+    fn of(t: ValueType) -> GosKind {
+        match t {
+            ValueType::Bool => GosKind::Bool,
+            ValueType::Int => GosKind::Int,
+            ValueType::Int8 => GosKind::Int8,
+            ValueType::Int16 => GosKind::Int16,
+            ValueType::Int32 => GosKind::Int32,
+            ValueType::Int64 => GosKind::Int64,
+            ValueType::Uint => GosKind::Uint,
+            ValueType::UintPtr => GosKind::UintPtr,
+            ValueType::Uint8 => GosKind::Uint8,
+            ValueType::Uint16 => GosKind::Uint16,
+            ValueType::Uint32 => GosKind::Uint32,
+            ValueType::Uint64 => GosKind::Uint64,
+            ValueType::Float32 => GosKind::Float32,
+            ValueType::Float64 => GosKind::Float64,
+            ValueType::Complex64 => GosKind::Complex64,
+            ValueType::Complex128 => GosKind::Complex128,
+            ValueType::Array => GosKind::Array,
+            ValueType::Channel => GosKind::Chan,
+            ValueType::Closure => GosKind::Func,
+            ValueType::Interface => GosKind::Interface,
+            ValueType::Map => GosKind::Map,
+            ValueType::Pointer => GosKind::Ptr,
+            ValueType::UnsafePtr => GosKind::UnsafePtr,
+            ValueType::Slice => GosKind::Slice,
+            ValueType::String => GosKind::String,
+            ValueType::Struct => GosKind::Struct,
+            _ => GosKind::Invalid,
+        }
+    }
+
+    /// The name Go's reflect.Kind has for it
+    fn name(&self) -> &'static str {
+        match self {
+            GosKind::Invalid => "invalid",
+            GosKind::Bool => "bool",
+            GosKind::Int => "int",
+            GosKind::Int8 => "int8",
+            GosKind::Int16 => "int16",
+            GosKind::Int32 => "int32",
+            GosKind::Int64 => "int64",
+            GosKind::Uint => "uint",
+            GosKind::Uint8 => "uint8",
+            GosKind::Uint16 => "uint16",
+            GosKind::Uint32 => "uint32",
+            GosKind::Uint64 => "uint64",
+            GosKind::UintPtr => "uintptr",
+            GosKind::Float32 => "float32",
+            GosKind::Float64 => "float64",
+            GosKind::Complex64 => "complex64",
+            GosKind::Complex128 => "complex128",
+            GosKind::Array => "array",
+            GosKind::Chan => "chan",
+            GosKind::Func => "func",
+            GosKind::Interface => "interface",
+            GosKind::Map => "map",
+            GosKind::Ptr => "ptr",
+            GosKind::Slice => "slice",
+            GosKind::String => "string",
+            GosKind::Struct => "struct",
+            GosKind::UnsafePtr => "unsafe.Pointer",
+        }
+    }
+}
+
 #[derive(Ffi)]
 pub struct ReflectFfi;
 
 #[ffi_impl]
 impl ReflectFfi {
+    #[go_sig = "func(i interface{}) unsafe.Pointer"]
     fn ffi_value_of(iface: GosValue) -> RuntimeResult<GosValue> {
         StdValue::value_from_iface(&iface)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) (unsafe.Pointer, uint)"]
     fn ffi_type_of(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<(GosValue, usize)> {
         let v = val_to_std_val(&p)?;
         Ok(StdType::type_of(v, ctx))
     }
 
+    #[go_sig = "func(p unsafe.Pointer) bool"]
     fn ffi_bool_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<bool> {
-        val_to_std_val(&p)?.bool_val(ctx)
+        val_to_std_val_for(&p, "Bool")?.bool_val(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) string"]
+    fn ffi_string_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val_for(&p, "String")?.string_val(ctx)
+    }
+
+    #[go_sig = "func(p unsafe.Pointer) int64"]
     fn ffi_int_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<i64> {
-        val_to_std_val(&p)?.int_val(ctx)
+        val_to_std_val_for(&p, "Int")?.int_val(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) uint64"]
     fn ffi_uint_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<u64> {
-        val_to_std_val(&p)?.uint_val(ctx)
+        val_to_std_val_for(&p, "Uint")?.uint_val(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) float64"]
     fn ffi_float_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<f64> {
-        val_to_std_val(&p)?.float_val(ctx)
+        val_to_std_val_for(&p, "Float")?.float_val(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) []byte"]
     fn ffi_bytes_val(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
-        val_to_std_val(&p)?.bytes_val(ctx)
+        val_to_std_val_for(&p, "Bytes")?.bytes_val(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) unsafe.Pointer"]
     fn ffi_elem(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
         val_to_std_val(&p)?.elem(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) int"]
     fn ffi_num_field(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
-        val_to_std_val(&p)?.num_field(ctx)
+        val_to_std_val_for(&p, "NumField")?.num_field(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, i int) unsafe.Pointer"]
     fn ffi_field(ctx: &FfiCtx, p: GosValue, i: isize) -> RuntimeResult<GosValue> {
-        val_to_std_val(&p)?.field(ctx, i)
+        val_to_std_val_for(&p, "Field")?.field(ctx, i)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, i int) unsafe.Pointer"]
     fn ffi_index(ctx: &FfiCtx, p: GosValue, i: isize) -> RuntimeResult<GosValue> {
-        val_to_std_val(&p)?.index(ctx, i)
+        val_to_std_val_for(&p, "Index")?.index(ctx, i)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) bool"]
     fn ffi_is_nil(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<bool> {
         Ok(val_to_std_val(&p)?.val(ctx)?.is_nil())
     }
 
+    #[go_sig = "func(p unsafe.Pointer) uintptr"]
+    fn ffi_pointer(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val_for(&p, "Pointer")?
+            .pointer(ctx)
+            .map(FfiCtx::new_uint_ptr)
+    }
+
+    #[go_sig = "func(p unsafe.Pointer) int"]
     fn ffi_len(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<isize> {
-        val_to_std_val(&p)?.len(ctx)
+        val_to_std_val_for(&p, "Len")?.len(ctx)
     }
 
+    #[go_sig = "func(p unsafe.Pointer) unsafe.Pointer"]
     fn ffi_map_range_init(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
-        StdMapIter::map_range(ctx, val_to_std_val(&p)?)
+        StdMapIter::map_range(ctx, val_to_std_val_for(&p, "MapRange")?)
+    }
+
+    #[go_sig = "func(p unsafe.Pointer) []unsafe.Pointer"]
+    fn ffi_map_keys(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val_for(&p, "MapKeys")?.map_keys(ctx)
+    }
+
+    #[go_sig = "func(t unsafe.Pointer) string"]
+    fn ffi_type_string(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<GosValue> {
+        let typ = val_to_std_type(&t)?;
+        Ok(FfiCtx::new_string(&typ.meta.type_string(ctx.vm_objs)))
+    }
+
+    #[go_sig = "func(t unsafe.Pointer) (unsafe.Pointer, uint)"]
+    fn ffi_type_elem(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<(GosValue, usize)> {
+        val_to_std_type(&t)?.elem(ctx)
+    }
+
+    #[go_sig = "func(t unsafe.Pointer) int"]
+    fn ffi_type_num_field(ctx: &FfiCtx, t: GosValue) -> RuntimeResult<isize> {
+        val_to_std_type(&t)?.num_field(ctx)
     }
 
+    #[go_sig = "func(t unsafe.Pointer, i int) (string, unsafe.Pointer, uint, bool)"]
+    fn ffi_type_field(
+        ctx: &FfiCtx,
+        t: GosValue,
+        i: isize,
+    ) -> RuntimeResult<(GosValue, GosValue, usize, bool)> {
+        val_to_std_type(&t)?.field(ctx, i)
+    }
+
+    #[go_sig = "func(p unsafe.Pointer) bool"]
     fn ffi_map_range_next(p: GosValue) -> RuntimeResult<GosValue> {
         Ok(val_to_map_iter(&p)?.next())
     }
 
+    #[go_sig = "func(p unsafe.Pointer) unsafe.Pointer"]
     fn ffi_map_range_key(p: GosValue) -> RuntimeResult<GosValue> {
         val_to_map_iter(&p)?.key()
     }
 
+    #[go_sig = "func(p unsafe.Pointer) unsafe.Pointer"]
     fn ffi_map_range_value(p: GosValue) -> RuntimeResult<GosValue> {
         val_to_map_iter(&p)?.value()
     }
 
+    #[go_sig = "func(p unsafe.Pointer) bool"]
     fn ffi_can_addr(p: GosValue) -> RuntimeResult<bool> {
         Ok(val_to_std_val(&p)?.can_addr())
     }
 
+    #[go_sig = "func(p unsafe.Pointer) bool"]
     fn ffi_can_set(p: GosValue) -> RuntimeResult<bool> {
         Ok(val_to_std_val(&p)?.can_set())
     }
 
+    #[go_sig = "func(p unsafe.Pointer) bool"]
+    fn ffi_can_interface(p: GosValue) -> RuntimeResult<bool> {
+        Ok(val_to_std_val(&p)?.can_interface())
+    }
+
+    #[go_sig = "func(p unsafe.Pointer) interface{}"]
+    fn ffi_to_interface(ctx: &FfiCtx, p: GosValue) -> RuntimeResult<GosValue> {
+        val_to_std_val(&p)?.interface(ctx)
+    }
+
+    #[go_sig = "func(p unsafe.Pointer, v unsafe.Pointer)"]
     fn ffi_set(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
-        val_to_std_val(&p)?.set(ctx, val_to_std_val(&val)?.val(ctx)?)
+        val_to_std_val_for(&p, "Set")?.set(ctx, val_to_std_val_for(&val, "Set")?)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, b bool)"]
     fn ffi_set_bool(ctx: &mut FfiCtx, p: GosValue, val: bool) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set_bool(ctx, val)
     }
 
-    fn ffi_set_string(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
-        val_to_std_val(&p)?.set_string(ctx, val)
+    #[go_sig = "func(p unsafe.Pointer, x string)"]
+    fn ffi_set_string(ctx: &mut FfiCtx, args: FfiArgs) -> RuntimeResult<()> {
+        let val = args.get_typed(1, ValueType::String)?.clone();
+        val_to_std_val(args.get(0)?)?.set_string(ctx, val)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, x int64)"]
     fn ffi_set_int(ctx: &mut FfiCtx, p: GosValue, val: i64) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set_int(ctx, val)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, x uint64)"]
     fn ffi_set_uint(ctx: &mut FfiCtx, p: GosValue, val: u64) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set_uint(ctx, val)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, x float64)"]
     fn ffi_set_float(ctx: &mut FfiCtx, p: GosValue, val: f64) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set_float(ctx, val)
     }
 
-    fn ffi_set_complex(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
-        val_to_std_val(&p)?.set_complex(ctx, val)
+    #[go_sig = "func(p unsafe.Pointer, x complex128)"]
+    fn ffi_set_complex(ctx: &mut FfiCtx, args: FfiArgs) -> RuntimeResult<()> {
+        let val = args.get_complex128(1)?;
+        val_to_std_val(args.get(0)?)?.set_complex(ctx, val)
     }
 
-    fn ffi_set_bytes(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
-        val_to_std_val(&p)?.set_bytes(ctx, val)
+    #[go_sig = "func(p unsafe.Pointer, x []byte)"]
+    fn ffi_set_bytes(ctx: &mut FfiCtx, args: FfiArgs) -> RuntimeResult<()> {
+        let val = args.get_typed(1, ValueType::Slice)?.clone();
+        val_to_std_val(args.get(0)?)?.set_bytes(ctx, val)
     }
 
+    #[go_sig = "func(p unsafe.Pointer, x unsafe.Pointer)"]
     fn ffi_set_pointer(ctx: &mut FfiCtx, p: GosValue, val: GosValue) -> RuntimeResult<()> {
         val_to_std_val(&p)?.set_pointer(ctx, val)
     }
 
-    fn ffi_swap(iface: GosValue, i: isize, j: isize) -> RuntimeResult<()> {
-        let iface = iface.as_interface().unwrap();
-        match iface.underlying_value() {
+    #[go_sig = "func(slice interface{}, i int, j int)"]
+    fn ffi_swap(args: FfiArgs) -> RuntimeResult<()> {
+        let (i, j) = (args.get_int(1)?, args.get_int(2)?);
+        match args
+            .get_interface(0)?
+            .and_then(|iface| iface.underlying_value())
+        {
             Some(obj) => {
                 if obj.typ() == ValueType::Slice {
                     obj.slice_swap(i as usize, j as usize)
@@ -218,10 +385,19 @@ impl ReflectFfi {
     }
 }
 
+/// How a value in a location was reached, which decides whether it can be set,
+/// like the flagAddr and flagRO of Go's reflect
+#[derive(Clone, Copy, Debug)]
+struct Access {
+    addressable: bool,
+    /// Not obtained through an unexported struct field
+    exported: bool,
+}
+
 #[derive(Clone, Debug, UnsafePtr)]
 enum StdValue {
     Value(GosValue, Option<Meta>),
-    Pointer(Box<PointerObj>, Option<Meta>, Option<bool>),
+    Pointer(Box<PointerObj>, Option<Meta>, Access),
 }
 
 impl StdValue {
@@ -233,7 +409,11 @@ impl StdValue {
         if v.typ() != ValueType::Interface {
             return Err("reflect: not an interface".to_owned().into());
         }
-        let iface = v.as_interface().unwrap();
+        // a nil interface has no Value
+        let iface = match v.as_interface() {
+            Some(iface) => iface,
+            None => return Ok(FfiCtx::new_nil(ValueType::UnsafePtr)),
+        };
         match &iface as &InterfaceObj {
             InterfaceObj::Gos(v, m) => Ok(wrap_std_val(v.clone(), m.as_ref().map(|x| x.0))),
             // todo: should we return something else?
@@ -254,12 +434,35 @@ impl StdValue {
         }
     }
 
-    fn settable_meta(&self) -> RuntimeResult<&Meta> {
+    fn access(&self) -> Access {
         match self {
-            Self::Pointer(_, m, _) => m
+            Self::Value(_, _) => Access {
+                addressable: false,
+                exported: true,
+            },
+            Self::Pointer(_, _, access) => *access,
+        }
+    }
+
+    /// The type of the value if `method` can set it, panics like Go's otherwise
+    fn settable_meta(&self, method: &str) -> RuntimeResult<&Meta> {
+        let access = self.access();
+        if !access.addressable {
+            Err(format!(
+                "reflect: reflect.Value.{} using unaddressable value",
+                method
+            )
+            .into())
+        } else if !access.exported {
+            Err(format!(
+                "reflect: reflect.Value.{} using value obtained using unexported field",
+                method
+            )
+            .into())
+        } else {
+            self.meta()
                 .as_ref()
-                .ok_or("reflect: type info missing".to_owned().into()),
-            Self::Value(_, _) => Err("reflect: value not settable".to_owned().into()),
+                .ok_or("reflect: type info missing".to_owned().into())
         }
     }
 
@@ -267,6 +470,14 @@ impl StdValue {
         let val = self.val(ctx)?;
         match val.typ() {
             ValueType::Bool => Ok(val.as_()),
+            _ => err_call!("Bool", val.typ()),
+        }
+    }
+
+    fn string_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let val = self.val(ctx)?;
+        match val.typ() {
+            ValueType::String => Ok(val),
             _ => err_wrong_type!(),
         }
     }
@@ -279,7 +490,7 @@ impl StdValue {
             ValueType::Int16 => Ok(*val.as_int16() as i64),
             ValueType::Int32 => Ok(*val.as_int32() as i64),
             ValueType::Int64 => Ok(*val.as_int64()),
-            _ => err_wrong_type!(),
+            _ => err_call!("Int", val.typ()),
         }
     }
 
@@ -287,11 +498,12 @@ impl StdValue {
         let val = self.val(ctx)?;
         match val.typ() {
             ValueType::Uint => Ok(*val.as_uint() as u64),
+            ValueType::UintPtr => Ok(*val.as_uint_ptr() as u64),
             ValueType::Uint8 => Ok(*val.as_uint8() as u64),
             ValueType::Uint16 => Ok(*val.as_uint16() as u64),
             ValueType::Uint32 => Ok(*val.as_uint32() as u64),
             ValueType::Uint64 => Ok(*val.as_uint64()),
-            _ => err_wrong_type!(),
+            _ => err_call!("Uint", val.typ()),
         }
     }
 
@@ -300,17 +512,22 @@ impl StdValue {
         match val.typ() {
             ValueType::Float32 => Ok((Into::<f32>::into(*val.as_float32()) as f64).into()),
             ValueType::Float64 => Ok(*val.as_float64()),
-            _ => err_wrong_type!(),
+            _ => err_call!("Float", val.typ()),
         }
         .map(|x| x.into_inner())
     }
 
     fn bytes_val(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         let val = self.val(ctx)?;
-        if val.typ() != ValueType::Slice || val.t_elem() != ValueType::Uint8 {
-            return err_wrong_type!();
+        match val.typ() {
+            ValueType::Slice if val.t_elem() == ValueType::Uint8 => Ok(val),
+            // a copy, like []byte(s)
+            ValueType::String => {
+                let bytes = val.as_string().as_str().bytes().map(|b| b.into()).collect();
+                Ok(ctx.new_slice(bytes, ValueType::Uint8))
+            }
+            _ => err_call!("Bytes", val.typ()),
         }
-        Ok(val)
     }
 
     fn elem(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
@@ -318,9 +535,16 @@ impl StdValue {
         match val.typ() {
             ValueType::Interface => StdValue::value_from_iface(&val),
             ValueType::Pointer => {
-                let p = val.as_non_nil_pointer()?;
+                let p = match val.as_pointer() {
+                    Some(p) => p,
+                    None => return Ok(FfiCtx::new_nil(ValueType::UnsafePtr)),
+                };
                 let meta = self.meta().map(|x| x.unptr_to());
-                Ok(wrap_ptr_std_val(Box::new(p.clone()), meta))
+                let access = Access {
+                    addressable: true,
+                    exported: self.access().exported,
+                };
+                Ok(wrap_ptr_std_val(Box::new(p.clone()), meta, access))
             }
             _ => err_wrong_type!(),
         }
@@ -329,7 +553,7 @@ impl StdValue {
     fn num_field(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
         let val = self.val(ctx)?;
         if val.typ() != ValueType::Struct {
-            err_wrong_type!()
+            err_call!("NumField", val.typ())
         } else {
             Ok((val.as_struct().0.borrow_fields().len() as isize).into())
         }
@@ -342,21 +566,21 @@ impl StdValue {
             ValueType::Struct => {
                 let fields = &val.as_struct().0.borrow_fields();
                 if fields.len() <= i {
-                    err_index_oor!()
+                    Err("reflect: Field index out of range".to_owned().into())
                 } else {
                     let p = Box::new(PointerObj::StructField(val.clone(), i as i32));
                     let metas = &ctx.vm_objs.metas;
                     let fields = &metas[self.meta().unwrap().underlying(metas).key]
                         .as_struct()
                         .infos();
-                    Ok(FfiCtx::new_unsafe_ptr(Rc::new(StdValue::Pointer(
-                        p,
-                        Some(fields[i].meta),
-                        Some(fields[i].exported()),
-                    ))))
+                    let access = Access {
+                        addressable: self.access().addressable,
+                        exported: self.access().exported && fields[i].exported(),
+                    };
+                    Ok(wrap_ptr_std_val(p, Some(fields[i].meta), access))
                 }
             }
-            _ => err_wrong_type!(),
+            _ => err_call!("Field", val.typ()),
         }
     }
 
@@ -367,6 +591,14 @@ impl StdValue {
         let t = container.typ();
         match t {
             ValueType::Array | ValueType::Slice => {
+                if ival < 0 || iusize >= container.len() {
+                    let what = if t == ValueType::Array {
+                        "array"
+                    } else {
+                        "slice"
+                    };
+                    return Err(format!("reflect: {} index out of range", what).into());
+                }
                 let metas = &ctx.vm_objs.metas;
                 let elem_meta = match &metas[self.meta().unwrap().underlying(metas).key] {
                     MetadataType::Array(m, _) | MetadataType::Slice(m) => m,
@@ -378,7 +610,12 @@ impl StdValue {
                     t,
                     elem_meta.value_type(metas),
                 )?);
-                Ok(wrap_ptr_std_val(p, Some(elem_meta.clone())))
+                // the elements of a slice are addressable, those of an array if it is
+                let access = Access {
+                    addressable: t == ValueType::Slice || self.access().addressable,
+                    exported: self.access().exported,
+                };
+                Ok(wrap_ptr_std_val(p, Some(*elem_meta), access))
             }
             // specs: a[x] is the non-constant byte value at index x and the type of a[x] is byte
             ValueType::String => match container.as_string().len() > iusize {
@@ -386,128 +623,239 @@ impl StdValue {
                     container.as_string().index_elem_u8(iusize).into(),
                     Some(ctx.vm_objs.prim_meta.mint8),
                 )),
-                false => err_index_oor!(),
+                false => Err("reflect: string index out of range".to_owned().into()),
             },
-            _ => err_wrong_type!(),
+            _ => err_call!("Index", container.typ()),
         }
     }
 
     fn len(&self, ctx: &FfiCtx) -> RuntimeResult<isize> {
         let val = self.val(ctx)?;
-        Ok(val.len() as isize)
+        match val.typ() {
+            ValueType::Array
+            | ValueType::Slice
+            | ValueType::String
+            | ValueType::Map
+            | ValueType::Channel => Ok(val.len() as isize),
+            _ => err_call!("Len", val.typ()),
+        }
     }
 
-    fn can_addr(&self) -> bool {
-        match self {
-            Self::Value(_, _) => false,
-            Self::Pointer(_, _, _) => true,
+    /// Identifies what the value refers to, 0 for nil
+    fn pointer(&self, ctx: &FfiCtx) -> RuntimeResult<usize> {
+        let val = self.val(ctx)?;
+        match val.typ() {
+            // a pointer is not an address, but equal pointers hash the same
+            ValueType::Pointer => Ok(val.as_pointer().map_or(0, |p| {
+                let mut hasher = DefaultHasher::new();
+                p.hash(&mut hasher);
+                hasher.finish() as usize
+            })),
+            ValueType::Map
+            | ValueType::Slice
+            | ValueType::Closure
+            | ValueType::Channel
+            | ValueType::UnsafePtr => match val.is_nil() {
+                true => Ok(0),
+                false => Ok(val.as_addr() as usize),
+            },
+            ValueType::UintPtr => Ok(*val.as_uint_ptr()),
+            t => err_call!("Pointer", t),
         }
     }
 
-    fn can_set(&self) -> bool {
-        match self {
-            Self::Value(_, _) => false,
-            Self::Pointer(p, _, exported) => match p as &PointerObj {
-                PointerObj::SliceMember(_, _) => true,
-                PointerObj::StructField(_, _) => exported.unwrap(),
-                PointerObj::UpVal(_) => true,
-                _ => false,
-            },
+    fn map_keys(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        let val = self.val(ctx)?;
+        if val.typ() != ValueType::Map {
+            return err_call!("MapKeys", val.typ());
         }
+        let metas = &ctx.vm_objs.metas;
+        let key_meta = match &metas[self.meta().unwrap().underlying(metas).key] {
+            MetadataType::Map(k, _) => *k,
+            _ => unreachable!(),
+        };
+        let keys = match val.as_map() {
+            Some(m) => {
+                m.0.borrow_data()
+                    .keys()
+                    .map(|k| wrap_std_val(k.clone(), Some(key_meta)))
+                    .collect()
+            }
+            None => vec![],
+        };
+        Ok(ctx.new_slice(keys, ValueType::UnsafePtr))
+    }
+
+    fn can_addr(&self) -> bool {
+        self.access().addressable
+    }
+
+    fn can_interface(&self) -> bool {
+        self.access().exported
     }
 
-    fn set(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
-        if !self.can_set() {
-            return Err("reflect: value is not settable".to_owned().into());
+    fn interface(&self, ctx: &FfiCtx) -> RuntimeResult<GosValue> {
+        if !self.can_interface() {
+            return Err(
+                "reflect: cannot return value obtained from unexported field"
+                    .to_owned()
+                    .into(),
+            );
+        }
+        let val = self.val(ctx)?;
+        if val.typ() == ValueType::Interface {
+            return Ok(val);
         }
+        match self.meta() {
+            Some(m) => Ok(ctx.copy_to_empty_interface(&val, *m)),
+            None => Err("reflect: type info missing".to_owned().into()),
+        }
+    }
+
+    fn can_set(&self) -> bool {
+        let access = self.access();
+        access.addressable && access.exported
+    }
+
+    /// Writes `val` to the location of the value, once checked by the setters
+    fn store(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
         match self {
             Self::Pointer(p, _, _) => {
                 p.set_pointee(&val, ctx.stack, &ctx.vm_objs.packages, &ctx.gcc)
             }
-            _ => unreachable!(),
+            Self::Value(_, _) => unreachable!(),
+        }
+    }
+
+    fn set(&self, ctx: &mut FfiCtx, x: &StdValue) -> RuntimeResult<()> {
+        let meta = *self.settable_meta("Set")?;
+        if !x.can_interface() {
+            return Err(
+                "reflect: reflect.Value.Set using value obtained using unexported field"
+                    .to_owned()
+                    .into(),
+            );
         }
+        let x_meta = x
+            .meta()
+            .ok_or_else(|| RuntimeError::new("reflect: type info missing".to_owned()))?;
+        let metas = &ctx.vm_objs.metas;
+        let val = x.val(ctx)?;
+        let val = if x_meta.identical(&meta, metas) {
+            val
+        } else if meta.value_type(metas) == ValueType::Interface
+            && meta.iface_bindings_or_missing(&x_meta, ctx.vm_objs).is_ok()
+        {
+            ctx.copy_to_interface(&val, (&meta, x_meta))
+        } else {
+            return Err(format!(
+                "reflect.Set: value of type {} is not assignable to type {}",
+                x_meta.type_string(ctx.vm_objs),
+                meta.type_string(ctx.vm_objs)
+            )
+            .into());
+        };
+        self.store(ctx, val)
     }
 
     fn set_bool(&self, ctx: &mut FfiCtx, val: bool) -> RuntimeResult<()> {
-        match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
-            ValueType::Bool => self.set(ctx, val.into()),
-            _ => err_set_val_type!(),
+        match self
+            .settable_meta("SetBool")?
+            .value_type(&ctx.vm_objs.metas)
+        {
+            ValueType::Bool => self.store(ctx, val.into()),
+            t => err_call!("SetBool", t),
         }
     }
 
     fn set_string(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
-        match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
-            ValueType::String => self.set(ctx, val),
-            _ => err_set_val_type!(),
+        match self
+            .settable_meta("SetString")?
+            .value_type(&ctx.vm_objs.metas)
+        {
+            ValueType::String => self.store(ctx, val),
+            t => err_call!("SetString", t),
         }
     }
 
     fn set_int(&self, ctx: &mut FfiCtx, ival: i64) -> RuntimeResult<()> {
-        let val = match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
+        let val = match self.settable_meta("SetInt")?.value_type(&ctx.vm_objs.metas) {
             ValueType::Int => Ok((ival as isize).into()),
             ValueType::Int8 => Ok((ival as i8).into()),
             ValueType::Int16 => Ok((ival as i16).into()),
             ValueType::Int32 => Ok((ival as i32).into()),
-            ValueType::Int64 => Ok((ival as i64).into()),
-            _ => err_set_val_type!(),
+            ValueType::Int64 => Ok(ival.into()),
+            t => err_call!("SetInt", t),
         }?;
-        self.set(ctx, val)
+        self.store(ctx, val)
     }
 
     fn set_uint(&self, ctx: &mut FfiCtx, ival: u64) -> RuntimeResult<()> {
-        let val = match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
+        let val = match self
+            .settable_meta("SetUint")?
+            .value_type(&ctx.vm_objs.metas)
+        {
             ValueType::Uint => Ok((ival as usize).into()),
+            ValueType::UintPtr => Ok(FfiCtx::new_uint_ptr(ival as usize)),
             ValueType::Uint8 => Ok((ival as u8).into()),
             ValueType::Uint16 => Ok((ival as u16).into()),
             ValueType::Uint32 => Ok((ival as u32).into()),
-            ValueType::Uint64 => Ok((ival as u64).into()),
-            _ => err_set_val_type!(),
+            ValueType::Uint64 => Ok(ival.into()),
+            t => err_call!("SetUint", t),
         }?;
-        self.set(ctx, val)
+        self.store(ctx, val)
     }
 
     fn set_float(&self, ctx: &mut FfiCtx, fval: f64) -> RuntimeResult<()> {
-        let val = match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
+        let val = match self
+            .settable_meta("SetFloat")?
+            .value_type(&ctx.vm_objs.metas)
+        {
             ValueType::Float32 => Ok((fval as f32).into()),
             ValueType::Float64 => Ok(fval.into()),
-            _ => err_set_val_type!(),
+            t => err_call!("SetFloat", t),
         }?;
-        self.set(ctx, val)
+        self.store(ctx, val)
     }
 
-    fn set_complex(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
-        let c = val.as_complex128();
-        let val = match self.settable_meta()?.value_type(&ctx.vm_objs.metas) {
+    fn set_complex(&self, ctx: &mut FfiCtx, c: Complex128) -> RuntimeResult<()> {
+        let val = match self
+            .settable_meta("SetComplex")?
+            .value_type(&ctx.vm_objs.metas)
+        {
             ValueType::Complex64 => Ok(FfiCtx::new_complex64(
                 c.r.into_inner() as f32,
                 c.i.into_inner() as f32,
             )),
             ValueType::Complex128 => Ok(FfiCtx::new_complex128(c.r.into_inner(), c.i.into_inner())),
-            _ => err_set_val_type!(),
+            t => err_call!("SetComplex", t),
         }?;
-        self.set(ctx, val)
+        self.store(ctx, val)
     }
 
     fn set_bytes(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
         let metas = &ctx.vm_objs.metas;
-        let meta = self.settable_meta()?;
-        if meta.value_type(metas) != ValueType::Slice {
-            err_wrong_type!()
-        } else {
-            let elem_meta = metas[meta.key].as_slice();
-            if elem_meta.value_type(metas) != ValueType::Uint8 {
-                err_wrong_type!()
-            } else {
-                self.set(ctx, val)
+        let meta = self.settable_meta("SetBytes")?;
+        match meta.value_type(metas) {
+            ValueType::Slice
+                if metas[meta.underlying(metas).key]
+                    .as_slice()
+                    .value_type(metas)
+                    == ValueType::Uint8 =>
+            {
+                self.store(ctx, val)
             }
+            t => err_call!("SetBytes", t),
         }
     }
 
     fn set_pointer(&self, ctx: &mut FfiCtx, val: GosValue) -> RuntimeResult<()> {
-        if self.settable_meta()? != &ctx.vm_objs.prim_meta.unsafe_ptr {
-            err_wrong_type!()
-        } else {
-            self.set(ctx, val)
+        match self
+            .settable_meta("SetPointer")?
+            .value_type(&ctx.vm_objs.metas)
+        {
+            ValueType::UnsafePtr => self.store(ctx, val),
+            t => err_call!("SetPointer", t),
         }
     }
 }
@@ -543,47 +891,71 @@ impl StdType {
     }
 
     fn type_of(val: &StdValue, ctx: &FfiCtx) -> (GosValue, usize) {
-        let m = val.meta().unwrap().clone();
+        StdType::wrap(val.meta().unwrap(), ctx)
+    }
+
+    fn wrap(m: Meta, ctx: &FfiCtx) -> (GosValue, usize) {
         let typ = StdType::new(m, &ctx.vm_objs.metas);
-        let kind = match m
-            .underlying(&ctx.vm_objs.metas)
-            .value_type(&ctx.vm_objs.metas)
-        {
-            ValueType::Bool => GosKind::Bool,
-            ValueType::Int => GosKind::Int,
-            ValueType::Int8 => GosKind::Int8,
-            ValueType::Int16 => GosKind::Int16,
-            ValueType::Int32 => GosKind::Int32,
-            ValueType::Int64 => GosKind::Int64,
-            ValueType::Uint => GosKind::Uint,
-            ValueType::UintPtr => GosKind::UintPtr,
-            ValueType::Uint8 => GosKind::Uint8,
-            ValueType::Uint16 => GosKind::Uint16,
-            ValueType::Uint32 => GosKind::Uint32,
-            ValueType::Uint64 => GosKind::Uint64,
-            ValueType::Float32 => GosKind::Float32,
-            ValueType::Float64 => GosKind::Float64,
-            ValueType::Complex64 => GosKind::Complex64,
-            ValueType::Complex128 => GosKind::Complex128,
-            ValueType::Array => GosKind::Array,
-            ValueType::Channel => GosKind::Chan,
-            ValueType::Closure => GosKind::Func,
-            ValueType::Interface => GosKind::Interface,
-            ValueType::Map => GosKind::Map,
-            ValueType::Pointer => GosKind::Ptr,
-            ValueType::UnsafePtr => GosKind::UnsafePtr,
-            ValueType::Slice => GosKind::Slice,
-            ValueType::String => GosKind::String,
-            ValueType::Struct => GosKind::Struct,
-            _ => GosKind::Invalid,
-        };
+        let kind = GosKind::of(m.value_type(&ctx.vm_objs.metas));
         (FfiCtx::new_unsafe_ptr(Rc::new(typ)), (kind as usize))
     }
+
+    fn elem(&self, ctx: &FfiCtx) -> RuntimeResult<(GosValue, usize)> {
+        let metas = &ctx.vm_objs.metas;
+        let elem = match self.meta.ptr_depth {
+            0 => match &metas[self.meta.underlying(metas).key] {
+                MetadataType::Array(m, _)
+                | MetadataType::Slice(m)
+                | MetadataType::Map(_, m)
+                | MetadataType::Channel(_, m) => *m,
+                _ => {
+                    let t = self.meta.type_string(ctx.vm_objs);
+                    return Err(format!("reflect: Elem of invalid type {}", t).into());
+                }
+            },
+            _ => self.meta.unptr_to(),
+        };
+        Ok(StdType::wrap(elem, ctx))
+    }
+
+    /// The declared fields, without the ones promoted from embedded fields
+    fn struct_fields<'a>(
+        &self,
+        ctx: &'a FfiCtx,
+        method: &str,
+    ) -> RuntimeResult<Vec<&'a FieldInfo>> {
+        let metas = &ctx.vm_objs.metas;
+        match &metas[self.meta.underlying(metas).key] {
+            MetadataType::Struct(f) if self.meta.ptr_depth == 0 => Ok(f
+                .infos()
+                .iter()
+                .filter(|x| x.embedded_indices.is_none())
+                .collect()),
+            _ => Err(format!("reflect: {} of non-struct type", method).into()),
+        }
+    }
+
+    fn num_field(&self, ctx: &FfiCtx) -> RuntimeResult<isize> {
+        Ok(self.struct_fields(ctx, "NumField")?.len() as isize)
+    }
+
+    /// The name, type, kind and whether it's embedded of the field at `i`
+    fn field(&self, ctx: &FfiCtx, i: isize) -> RuntimeResult<(GosValue, GosValue, usize, bool)> {
+        let fields = self.struct_fields(ctx, "Field")?;
+        match usize::try_from(i).ok().and_then(|i| fields.get(i)) {
+            Some(f) => {
+                let (typ, kind) = StdType::wrap(f.meta, ctx);
+                Ok((FfiCtx::new_string(&f.name), typ, kind, f.embedded))
+            }
+            None => Err("reflect: Field index out of range".to_owned().into()),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 struct StdMapIterInner {
-    iter: GosMapIter<'static>,
+    /// None for a nil map
+    iter: Option<GosMapIter<'static>>,
     item: Option<(GosValue, GosValue)>,
 }
 
@@ -591,7 +963,7 @@ struct StdMapIterInner {
 struct StdMapIter {
     inner: RefCell<StdMapIterInner>,
     key_meta: Meta,
-    //val_meta: Meta,
+    val_meta: Meta,
 }
 
 impl UnsafePtr for StdMapIter {
@@ -603,21 +975,32 @@ impl UnsafePtr for StdMapIter {
 impl StdMapIter {
     fn map_range(ctx: &FfiCtx, v: &StdValue) -> RuntimeResult<GosValue> {
         let val = v.val(ctx)?;
-        let mref = val.as_map().unwrap().0.borrow_data();
-        let iter: GosMapIter<'static> = unsafe { mem::transmute(mref.iter()) };
+        if val.typ() != ValueType::Map {
+            return err_call!("MapRange", val.typ());
+        }
+        let iter = val.as_map().map(|m| {
+            let mref = m.0.borrow_data();
+            let iter: GosMapIter<'static> = unsafe { mem::transmute(mref.iter()) };
+            iter
+        });
         let metas = &ctx.vm_objs.metas;
         let map_meta = metas[v.meta().unwrap().underlying(metas).key].as_map();
-        let (key_meta, _) = (map_meta.0.clone(), map_meta.1.clone());
+        let (key_meta, val_meta) = (*map_meta.0, *map_meta.1);
         let smi = StdMapIter {
             inner: RefCell::new(StdMapIterInner { iter, item: None }),
             key_meta,
+            val_meta,
         };
         Ok(FfiCtx::new_unsafe_ptr(Rc::new(smi)))
     }
 
     fn next(&self) -> GosValue {
         let mut inner = self.inner.borrow_mut();
-        inner.item = inner.iter.next().map(|x| (x.0.clone(), x.1.clone()));
+        inner.item = inner
+            .iter
+            .as_mut()
+            .and_then(|it| it.next())
+            .map(|x| (x.0.clone(), x.1.clone()));
         inner.item.is_some().into()
     }
 
@@ -638,6 +1021,6 @@ impl StdMapIter {
                 .to_owned()
                 .into()),
         }
-        .map(|x| wrap_std_val(x, Some(self.key_meta)))
+        .map(|x| wrap_std_val(x, Some(self.val_meta)))
     }
 }
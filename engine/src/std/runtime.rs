@@ -0,0 +1,19 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use futures_lite::future;
+use go_vm::types::{GosValue, RuntimeResult};
+
+#[derive(Ffi)]
+pub struct RuntimeFfi;
+
+#[ffi_impl(rename = "runtime")]
+impl RuntimeFfi {
+    async fn ffi_async_gosched() -> RuntimeResult<Vec<GosValue>> {
+        future::yield_now().await;
+        Ok(vec![])
+    }
+}
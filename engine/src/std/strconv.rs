@@ -0,0 +1,473 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use super::strings::{str_bytes, Runes};
+use crate::ffi::*;
+use go_vm::types::GosValue;
+
+/// The error codes of the parsing functions, the Go side turns them into `*NumError`s
+const OK: isize = 0;
+const SYNTAX: isize = 1;
+const RANGE: isize = 2;
+
+#[derive(Ffi)]
+pub struct StrconvFfi;
+
+#[ffi_impl(rename = "strconv")]
+impl StrconvFfi {
+    fn ffi_parse_bool(s: GosValue) -> (bool, isize) {
+        match &*str_bytes(&s) {
+            b"1" | b"t" | b"T" | b"true" | b"TRUE" | b"True" => (true, OK),
+            b"0" | b"f" | b"F" | b"false" | b"FALSE" | b"False" => (false, OK),
+            _ => (false, SYNTAX),
+        }
+    }
+
+    fn ffi_parse_uint(s: GosValue, base: isize, bit_size: isize) -> (u64, isize) {
+        parse_uint(&str_bytes(&s), base as u32, bit_size as u32)
+    }
+
+    fn ffi_parse_int(s: GosValue, base: isize, bit_size: isize) -> (i64, isize) {
+        let bytes = str_bytes(&s);
+        let (neg, digits) = match bytes.first() {
+            Some(b'+') => (false, &bytes[1..]),
+            Some(b'-') => (true, &bytes[1..]),
+            _ => (false, &bytes[..]),
+        };
+        let (un, code) = parse_uint(digits, base as u32, bit_size as u32);
+        if code == SYNTAX {
+            return (0, SYNTAX);
+        }
+        let bit_size = if bit_size == 0 { 64 } else { bit_size };
+        let cutoff = 1u64 << (bit_size - 1);
+        if !neg && un >= cutoff {
+            ((cutoff - 1) as i64, RANGE)
+        } else if neg && un > cutoff {
+            (cutoff.wrapping_neg() as i64, RANGE)
+        } else if neg {
+            (un.wrapping_neg() as i64, OK)
+        } else {
+            (un as i64, OK)
+        }
+    }
+
+    fn ffi_parse_float(s: GosValue, bit_size: isize) -> (f64, isize) {
+        let bytes = str_bytes(&s);
+        let s = match std::str::from_utf8(&bytes) {
+            Ok(s) => s,
+            Err(_) => return (0.0, SYNTAX),
+        };
+        if let Some(f) = special(s) {
+            return (f, OK);
+        }
+        if matches!(s.as_bytes().first(), Some(b'+' | b'-')) && s[1..].eq_ignore_ascii_case("nan") {
+            // Rust takes a signed NaN, Go doesn't
+            return (0.0, SYNTAX);
+        }
+        let parsed = match bit_size {
+            32 => s.parse::<f32>().map(f64::from),
+            _ => s.parse::<f64>(),
+        };
+        match parsed {
+            Ok(f) if f.is_infinite() => (f, RANGE),
+            Ok(f) => (f, OK),
+            Err(_) => (0.0, SYNTAX),
+        }
+    }
+
+    fn ffi_format_int(i: i64, base: isize) -> GosValue {
+        let s = format_bits(i.unsigned_abs(), base as u64, i < 0);
+        FfiCtx::new_string(&s)
+    }
+
+    fn ffi_format_uint(u: u64, base: isize) -> GosValue {
+        FfiCtx::new_string(&format_bits(u, base as u64, false))
+    }
+
+    fn ffi_format_float(f: f64, fmt: u8, prec: isize, bit_size: isize) -> GosValue {
+        FfiCtx::new_string(&format_float(f, fmt, prec, bit_size))
+    }
+
+    fn ffi_quote(s: GosValue) -> GosValue {
+        FfiCtx::new_string(&quote(&str_bytes(&s)))
+    }
+
+    fn ffi_unquote(s: GosValue) -> (GosValue, bool) {
+        match unquote(&str_bytes(&s)) {
+            Some(bytes) => (FfiCtx::new_string_from_bytes(&bytes), true),
+            None => (FfiCtx::new_string(""), false),
+        }
+    }
+}
+
+/// Go's `ParseUint` after the base and bit size have been checked, a `base`
+/// of 0 is implied by the prefix of `s`.
+fn parse_uint(s: &[u8], base: u32, bit_size: u32) -> (u64, isize) {
+    if s.is_empty() {
+        return (0, SYNTAX);
+    }
+    let (base, s) = match base {
+        0 if s.len() > 1 && s[0] == b'0' && (s[1] == b'x' || s[1] == b'X') => {
+            if s.len() < 3 {
+                return (0, SYNTAX);
+            }
+            (16, &s[2..])
+        }
+        0 if s[0] == b'0' => (8, &s[1..]),
+        0 => (10, s),
+        _ => (base, s),
+    };
+    let max = match bit_size {
+        0 | 64 => u64::MAX,
+        n => (1 << n) - 1,
+    };
+    let mut n: u64 = 0;
+    for &c in s {
+        let d = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'z' => c - b'a' + 10,
+            b'A'..=b'Z' => c - b'A' + 10,
+            _ => return (0, SYNTAX),
+        };
+        if d as u32 >= base {
+            return (0, SYNTAX);
+        }
+        match n
+            .checked_mul(base as u64)
+            .and_then(|n| n.checked_add(d as u64))
+        {
+            Some(n1) if n1 <= max => n = n1,
+            _ => return (max, RANGE),
+        }
+    }
+    (n, OK)
+}
+
+/// The infinities and NaN as Go spells them, case insensitive
+fn special(s: &str) -> Option<f64> {
+    match s.to_ascii_lowercase().as_str() {
+        "+inf" | "+infinity" | "inf" | "infinity" => Some(f64::INFINITY),
+        "-inf" | "-infinity" => Some(f64::NEG_INFINITY),
+        "nan" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+fn format_bits(mut u: u64, base: u64, neg: bool) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut buf = vec![];
+    loop {
+        buf.push(DIGITS[(u % base) as usize]);
+        u /= base;
+        if u == 0 {
+            break;
+        }
+    }
+    if neg {
+        buf.push(b'-');
+    }
+    buf.reverse();
+    String::from_utf8(buf).unwrap()
+}
+
+/// The decimal digits of a float, without leading or trailing zeros,
+/// and the position of the decimal point, like Go's `decimalSlice`
+struct Digits {
+    d: Vec<u8>,
+    dp: isize,
+}
+
+impl Digits {
+    /// The shortest digits that read back as `f`, or `f` rounded to `sig`
+    /// significant digits
+    fn new(f: f64, bit_size: isize, sig: Option<usize>) -> Digits {
+        let e = match (sig, bit_size) {
+            (Some(n), _) => format!("{:.*e}", n.max(1) - 1, f),
+            (None, 32) => format!("{:e}", f as f32),
+            (None, _) => format!("{:e}", f),
+        };
+        let (mantissa, exp) = e.split_once('e').unwrap();
+        let mut d: Vec<u8> = mantissa.bytes().filter(|&c| c != b'.').collect();
+        while d.last() == Some(&b'0') {
+            d.pop();
+        }
+        let dp = if d.is_empty() {
+            0
+        } else {
+            exp.parse::<isize>().unwrap() + 1
+        };
+        Digits { d, dp }
+    }
+
+    fn nd(&self) -> isize {
+        self.d.len() as isize
+    }
+}
+
+/// Go's `FormatFloat` for the formats 'e', 'E', 'f', 'g' and 'G'
+fn format_float(f: f64, fmt: u8, prec: isize, bit_size: isize) -> String {
+    let f = if bit_size == 32 { f as f32 as f64 } else { f };
+    if f.is_nan() {
+        return "NaN".to_owned();
+    } else if f.is_infinite() {
+        return if f > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    let neg = f.is_sign_negative();
+    let abs = f.abs();
+    let shortest = prec < 0;
+    let mut buf = String::new();
+    match fmt {
+        b'e' | b'E' => {
+            let digs = Digits::new(abs, bit_size, (!shortest).then(|| prec as usize + 1));
+            let prec = if shortest {
+                (digs.nd() - 1).max(0)
+            } else {
+                prec
+            };
+            fmt_e(&mut buf, neg, &digs, prec, fmt as char);
+        }
+        b'f' if shortest => {
+            let digs = Digits::new(abs, bit_size, None);
+            fmt_f(&mut buf, neg, &digs, (digs.nd() - digs.dp).max(0));
+        }
+        b'f' => {
+            if neg {
+                buf.push('-');
+            }
+            buf.push_str(&format!("{:.*}", prec as usize, abs));
+        }
+        b'g' | b'G' => {
+            let prec = if prec == 0 { 1 } else { prec };
+            let digs = Digits::new(abs, bit_size, (!shortest).then_some(prec as usize));
+            let mut prec = if shortest { digs.nd() } else { prec };
+            let mut eprec = prec;
+            if eprec > digs.nd() && digs.nd() >= digs.dp {
+                eprec = digs.nd();
+            }
+            // %e is used if the exponent from the conversion is less than -4 or
+            // greater than or equal to the precision. If precision was the
+            // shortest possible, use precision 6 for this decision.
+            if shortest {
+                eprec = 6;
+            }
+            let exp = digs.dp - 1;
+            if exp < -4 || exp >= eprec {
+                if prec > digs.nd() {
+                    prec = digs.nd();
+                }
+                let fmt = if fmt == b'g' { 'e' } else { 'E' };
+                fmt_e(&mut buf, neg, &digs, prec - 1, fmt);
+            } else {
+                if prec > digs.dp {
+                    prec = digs.nd();
+                }
+                fmt_f(&mut buf, neg, &digs, (prec - digs.dp).max(0));
+            }
+        }
+        _ => {
+            buf.push('%');
+            buf.push(fmt as char);
+        }
+    }
+    buf
+}
+
+/// %e: -d.ddddde±dd
+fn fmt_e(buf: &mut String, neg: bool, digs: &Digits, prec: isize, fmt: char) {
+    if neg {
+        buf.push('-');
+    }
+    buf.push(digs.d.first().map_or('0', |&c| c as char));
+    if prec > 0 {
+        buf.push('.');
+        for i in 1..=prec {
+            buf.push(digs.d.get(i as usize).map_or('0', |&c| c as char));
+        }
+    }
+    buf.push(fmt);
+    let exp = if digs.d.is_empty() { 0 } else { digs.dp - 1 };
+    buf.push(if exp < 0 { '-' } else { '+' });
+    buf.push_str(&format!("{:02}", exp.abs()));
+}
+
+/// %f: -ddddddd.ddddd
+fn fmt_f(buf: &mut String, neg: bool, digs: &Digits, prec: isize) {
+    if neg {
+        buf.push('-');
+    }
+    if digs.dp > 0 {
+        for i in 0..digs.dp {
+            buf.push(digs.d.get(i as usize).map_or('0', |&c| c as char));
+        }
+    } else {
+        buf.push('0');
+    }
+    if prec > 0 {
+        buf.push('.');
+        for i in 1..=prec {
+            let j = digs.dp + i - 1;
+            let c = if 0 <= j && j < digs.nd() {
+                digs.d[j as usize] as char
+            } else {
+                '0'
+            };
+            buf.push(c);
+        }
+    }
+}
+
+/// Go's `IsPrint`: letters, marks, numbers, punctuation, symbols and the
+/// ASCII space
+fn is_print(c: char) -> bool {
+    if c.is_ascii() {
+        return (' '..='~').contains(&c);
+    }
+    // `str::escape_debug` leaves alone the printable characters, and marks
+    // unless they start the string
+    let s = format!("a{}", c);
+    s.escape_debug().eq(s.chars())
+}
+
+/// Go's `Quote`, an invalid byte is written as \x..
+fn quote(bytes: &[u8]) -> String {
+    let mut buf = String::with_capacity(bytes.len() + 2);
+    buf.push('"');
+    for (i, c, _) in Runes::new(bytes) {
+        let c = match c {
+            Some(c) => c,
+            None => {
+                buf.push_str(&format!("\\x{:02x}", bytes[i]));
+                continue;
+            }
+        };
+        match c {
+            '"' | '\\' => {
+                buf.push('\\');
+                buf.push(c);
+            }
+            c if is_print(c) => buf.push(c),
+            '\x07' => buf.push_str("\\a"),
+            '\x08' => buf.push_str("\\b"),
+            '\x0c' => buf.push_str("\\f"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\x0b' => buf.push_str("\\v"),
+            c if c < ' ' || c == '\x7f' => buf.push_str(&format!("\\x{:02x}", c as u32)),
+            c if (c as u32) < 0x10000 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// Go's `Unquote`, `None` for invalid syntax
+fn unquote(s: &[u8]) -> Option<Vec<u8>> {
+    let n = s.len();
+    if n < 2 || s[0] != s[n - 1] {
+        return None;
+    }
+    let quote = s[0];
+    let s = &s[1..n - 1];
+    match quote {
+        b'`' if s.contains(&b'`') => None,
+        b'`' => Some(s.iter().copied().filter(|&c| c != b'\r').collect()),
+        b'"' | b'\'' if s.contains(&b'\n') => None,
+        b'"' | b'\'' => {
+            let mut buf = Vec::with_capacity(s.len());
+            let mut rest = s;
+            while !rest.is_empty() {
+                rest = unquote_char(rest, quote, &mut buf)?;
+                if quote == b'\'' && !rest.is_empty() {
+                    // single-quoted must be single character
+                    return None;
+                }
+            }
+            if quote == b'\'' && buf.is_empty() {
+                return None;
+            }
+            Some(buf)
+        }
+        _ => None,
+    }
+}
+
+/// Decodes the first character or escape sequence of `s` into `buf`,
+/// returning the rest of `s`
+fn unquote_char<'a>(s: &'a [u8], quote: u8, buf: &mut Vec<u8>) -> Option<&'a [u8]> {
+    let c = s[0];
+    if c == quote {
+        return None;
+    }
+    if c != b'\\' {
+        let (_, r, w) = Runes::new(s).next().unwrap();
+        match r {
+            Some(_) => buf.extend_from_slice(&s[..w]),
+            // an invalid byte is decoded as U+FFFD
+            None => buf.extend_from_slice("\u{FFFD}".as_bytes()),
+        }
+        return Some(&s[w..]);
+    }
+    let c = *s.get(1)?;
+    let s = &s[2..];
+    let simple = match c {
+        b'a' => Some(b'\x07'),
+        b'b' => Some(b'\x08'),
+        b'f' => Some(b'\x0c'),
+        b'n' => Some(b'\n'),
+        b'r' => Some(b'\r'),
+        b't' => Some(b'\t'),
+        b'v' => Some(b'\x0b'),
+        b'\\' => Some(b'\\'),
+        b'\'' | b'"' if c == quote => Some(c),
+        _ => None,
+    };
+    if let Some(b) = simple {
+        buf.push(b);
+        return Some(s);
+    }
+    match c {
+        b'x' | b'u' | b'U' => {
+            let n = match c {
+                b'x' => 2,
+                b'u' => 4,
+                _ => 8,
+            };
+            let hex = std::str::from_utf8(s.get(..n)?).ok()?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let v = u32::from_str_radix(hex, 16).ok()?;
+            if c == b'x' {
+                // single-byte string, possibly not UTF-8
+                buf.push(v as u8);
+            } else {
+                if v > char::MAX as u32 {
+                    return None;
+                }
+                let r = char::from_u32(v).unwrap_or(char::REPLACEMENT_CHARACTER);
+                buf.extend_from_slice(r.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            Some(&s[n..])
+        }
+        b'0'..=b'7' => {
+            let oct = s.get(..2)?;
+            let mut v = (c - b'0') as u32;
+            for &d in oct {
+                if !(b'0'..=b'7').contains(&d) {
+                    return None;
+                }
+                v = v << 3 | (d - b'0') as u32;
+            }
+            if v > 255 {
+                return None;
+            }
+            buf.push(v as u8);
+            Some(&s[2..])
+        }
+        _ => None,
+    }
+}
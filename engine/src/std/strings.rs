@@ -0,0 +1,265 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use go_vm::types::{Elem8, GosElem, GosValue, UnsafePtr, ValueType};
+use std::any::Any;
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+#[derive(Ffi)]
+pub struct StringsFfi;
+
+#[ffi_impl(rename = "strings")]
+impl StringsFfi {
+    fn ffi_index(s: GosValue, substr: GosValue) -> isize {
+        index(&str_bytes(&s), &str_bytes(&substr)).map_or(-1, |i| i as isize)
+    }
+
+    fn ffi_has_prefix(s: GosValue, prefix: GosValue) -> bool {
+        str_bytes(&s).starts_with(&str_bytes(&prefix))
+    }
+
+    fn ffi_has_suffix(s: GosValue, suffix: GosValue) -> bool {
+        str_bytes(&s).ends_with(&str_bytes(&suffix))
+    }
+
+    fn ffi_split(ctx: &mut FfiCtx, s: GosValue, sep: GosValue) -> GosValue {
+        let (s, sep) = (str_bytes(&s), str_bytes(&sep));
+        let parts: Vec<GosValue> = if sep.is_empty() {
+            Runes::new(&s)
+                .map(|(i, _, w)| FfiCtx::new_string_from_bytes(&s[i..i + w]))
+                .collect()
+        } else {
+            let mut parts = vec![];
+            let mut rest: &[u8] = &s;
+            while let Some(i) = index(rest, &sep) {
+                parts.push(FfiCtx::new_string_from_bytes(&rest[..i]));
+                rest = &rest[i + sep.len()..];
+            }
+            parts.push(FfiCtx::new_string_from_bytes(rest));
+            parts
+        };
+        ctx.new_slice(parts, ValueType::String)
+    }
+
+    fn ffi_join(elems: GosValue, sep: GosValue) -> GosValue {
+        let sep = str_bytes(&sep);
+        let mut buf = vec![];
+        if let Some(elems) = elems.as_slice::<GosElem>() {
+            for (i, e) in elems.0.as_rust_slice().iter().enumerate() {
+                if i > 0 {
+                    buf.extend_from_slice(&sep);
+                }
+                buf.extend_from_slice(&str_bytes(&e.borrow()));
+            }
+        }
+        FfiCtx::new_string_from_bytes(&buf)
+    }
+
+    fn ffi_replace(s: GosValue, old: GosValue, new: GosValue, n: isize) -> GosValue {
+        let (bytes, old_b, new_b) = (str_bytes(&s), str_bytes(&old), str_bytes(&new));
+        let mut n = if n < 0 { usize::MAX } else { n as usize };
+        let mut buf = Vec::with_capacity(bytes.len());
+        let mut rest: &[u8] = &bytes;
+        if old_b.is_empty() {
+            // matches at the beginning and after each UTF-8 sequence
+            for (i, _, w) in Runes::new(&bytes) {
+                if n == 0 {
+                    break;
+                }
+                buf.extend_from_slice(&new_b);
+                buf.extend_from_slice(&bytes[i..i + w]);
+                rest = &bytes[i + w..];
+                n -= 1;
+            }
+            if n > 0 {
+                buf.extend_from_slice(&new_b);
+            }
+        } else {
+            while n > 0 {
+                match index(rest, &old_b) {
+                    Some(i) => {
+                        buf.extend_from_slice(&rest[..i]);
+                        buf.extend_from_slice(&new_b);
+                        rest = &rest[i + old_b.len()..];
+                        n -= 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+        buf.extend_from_slice(rest);
+        FfiCtx::new_string_from_bytes(&buf)
+    }
+
+    fn ffi_repeat(s: GosValue, count: isize) -> GosValue {
+        FfiCtx::new_string_from_bytes(&str_bytes(&s).repeat(count as usize))
+    }
+
+    fn ffi_to_upper(s: GosValue) -> GosValue {
+        map_runes(&str_bytes(&s), to_upper)
+    }
+
+    fn ffi_to_lower(s: GosValue) -> GosValue {
+        map_runes(&str_bytes(&s), to_lower)
+    }
+
+    fn ffi_trim_space(s: GosValue) -> GosValue {
+        let bytes = str_bytes(&s);
+        let mut start = bytes.len();
+        let mut end = 0;
+        for (i, c, w) in Runes::new(&bytes) {
+            if !c.is_some_and(char::is_whitespace) {
+                start = start.min(i);
+                end = i + w;
+            }
+        }
+        FfiCtx::new_string_from_bytes(if start < end { &bytes[start..end] } else { &[] })
+    }
+
+    fn ffi_new_builder() -> GosValue {
+        FfiCtx::new_unsafe_ptr(Rc::new(Builder::default()))
+    }
+
+    fn ffi_builder_write_string(b: GosValue, s: GosValue) {
+        Builder::of(&b)
+            .buf
+            .borrow_mut()
+            .extend_from_slice(&str_bytes(&s));
+    }
+
+    fn ffi_builder_write_bytes(b: GosValue, p: GosValue) {
+        if let Some(p) = p.as_slice::<Elem8>() {
+            let mut buf = Builder::of(&b).buf.borrow_mut();
+            buf.extend_from_slice(&p.0.as_raw_slice::<u8>());
+        }
+    }
+
+    fn ffi_builder_string(b: GosValue) -> GosValue {
+        FfiCtx::new_string_from_bytes(&Builder::of(&b).buf.borrow())
+    }
+
+    fn ffi_builder_len(b: GosValue) -> isize {
+        Builder::of(&b).buf.borrow().len() as isize
+    }
+
+    fn ffi_builder_cap(b: GosValue) -> isize {
+        Builder::of(&b).buf.borrow().capacity() as isize
+    }
+
+    fn ffi_builder_grow(b: GosValue, n: isize) {
+        Builder::of(&b).buf.borrow_mut().reserve(n as usize);
+    }
+}
+
+/// The buffer behind a `strings.Builder`
+#[derive(Default)]
+struct Builder {
+    buf: RefCell<Vec<u8>>,
+}
+
+impl Builder {
+    fn of(val: &GosValue) -> &Builder {
+        val.as_unsafe_ptr()
+            .unwrap()
+            .downcast_ref::<Builder>()
+            .unwrap()
+    }
+}
+
+impl UnsafePtr for Builder {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_for_fork(&self) -> Option<Rc<dyn UnsafePtr>> {
+        Some(Rc::new(Builder {
+            buf: RefCell::new(self.buf.borrow().clone()),
+        }))
+    }
+}
+
+/// Iterates over the runes of a string that isn't necessarily valid UTF-8,
+/// yielding `(offset, rune, width)` with a `None` rune for each invalid byte,
+/// like ranging over a string in Go.
+pub(crate) struct Runes<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Runes<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Runes<'a> {
+        Runes { bytes, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for Runes<'a> {
+    type Item = (usize, Option<char>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.bytes[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let head = &rest[..rest.len().min(4)];
+        let valid = match std::str::from_utf8(head) {
+            Ok(s) => s,
+            Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+        };
+        let offset = self.offset;
+        let (c, w) = match valid.chars().next() {
+            Some(c) => (Some(c), c.len_utf8()),
+            None => (None, 1),
+        };
+        self.offset += w;
+        Some((offset, c, w))
+    }
+}
+
+/// The bytes of a string, which may not be valid UTF-8
+pub(crate) fn str_bytes(s: &GosValue) -> Ref<'_, [u8]> {
+    s.as_string().as_bytes()
+}
+
+fn index(s: &[u8], sub: &[u8]) -> Option<usize> {
+    match sub.len() {
+        0 => Some(0),
+        n if n > s.len() => None,
+        n => s.windows(n).position(|w| w == sub),
+    }
+}
+
+/// Maps every rune with `f`, an invalid byte becomes U+FFFD as in Go's strings.Map
+fn map_runes(bytes: &[u8], f: fn(char) -> char) -> GosValue {
+    let mut s = String::with_capacity(bytes.len());
+    for (_, c, _) in Runes::new(bytes) {
+        s.push(f(c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+    }
+    FfiCtx::new_string(&s)
+}
+
+/// Go's `unicode.ToUpper`, which uses the one-to-one simple case mapping,
+/// so a rune like 'ß' without one is left alone rather than becoming "SS".
+fn to_upper(c: char) -> char {
+    let mut up = c.to_uppercase();
+    match (up.next(), up.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+/// Go's `unicode.ToLower`, see `to_upper`
+fn to_lower(c: char) -> char {
+    if c == '\u{130}' {
+        // the only rune whose simple lowercase mapping differs from the full one
+        return 'i';
+    }
+    let mut low = c.to_lowercase();
+    match (low.next(), low.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
@@ -7,16 +7,17 @@
 ///
 extern crate self as go_engine;
 use crate::ffi::*;
-use futures_lite::future;
 use futures_lite::future::Future;
 use go_vm::types::{GosValue, RuntimeResult, UnsafePtr};
 use std::any::Any;
 use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
 use std::vec;
 
-macro_rules! create_mutex {
+macro_rules! create_handle {
     ($arg0:expr, $ctx:expr, $typ:tt) => {{
         let pp = $arg0.as_pointer().unwrap();
         let p = pp.deref(&$ctx.stack, &$ctx.vm_objs.packages)?;
@@ -35,6 +36,14 @@ macro_rules! create_mutex {
     }};
 }
 
+/// The handle is only created by locking, so a nil one was never locked
+fn held_handle<T: Any + Clone>(arg: &GosValue, unlocked: &str) -> RuntimeResult<T> {
+    match arg.as_unsafe_ptr() {
+        Some(p) => p.downcast_ref::<T>().cloned(),
+        None => Err(unlocked.to_owned().into()),
+    }
+}
+
 #[derive(Ffi)]
 pub struct MutexFfi;
 
@@ -46,56 +55,66 @@ impl MutexFfi {
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
         // It'd probably be cleaner if we use interface{} instead of pointer as
         // the argument, but let's leave it like this to serve as an example.
-        match MutexFfi::create_mutex(&lock, ctx) {
+        match MutexFfi::create_handle(&lock, ctx) {
             Ok(mutex) => Box::pin((|| async move { mutex.lock().await })()),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
     async fn ffi_async_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
-        let ud = lock.as_unsafe_ptr();
-        let mutex = ud.unwrap().downcast_ref::<Mutex>().unwrap().clone();
+        let mutex: Mutex = held_handle(&lock, "sync: unlock of unlocked mutex")?;
         mutex.unlock().await
     }
 
-    fn create_mutex(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<Mutex> {
-        create_mutex!(arg, ctx, Mutex)
+    fn create_handle(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<Mutex> {
+        create_handle!(arg, ctx, Mutex)
     }
 }
 
 #[derive(Clone)]
 struct Mutex {
     locked: Rc<Cell<bool>>,
+    waiters: Rc<WaitList>,
 }
 
 impl UnsafePtr for Mutex {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn clone_for_fork(&self) -> Option<Rc<dyn UnsafePtr>> {
+        Some(Rc::new(Mutex {
+            locked: Rc::new(Cell::new(self.locked.get())),
+            waiters: Rc::new(WaitList::default()),
+        }))
+    }
 }
 
 impl Mutex {
     fn new() -> Mutex {
         Mutex {
             locked: Rc::new(Cell::new(false)),
+            waiters: Rc::new(WaitList::default()),
         }
     }
 
     async fn lock(self) -> RuntimeResult<Vec<GosValue>> {
-        //dbg!("lock called");
-        while self.locked.get() {
-            future::yield_now().await;
+        if self.locked.get() {
+            // the goroutine unlocking it hands the mutex over
+            self.waiters.park().await;
+        } else {
+            self.locked.set(true);
         }
-        self.locked.set(true);
         Ok(vec![])
     }
 
     async fn unlock(self) -> RuntimeResult<Vec<GosValue>> {
-        //dbg!("unlock called");
         if !self.locked.get() {
             Err("sync: unlock of unlocked mutex".to_owned().into())
         } else {
-            self.locked.set(false);
+            if !self.waiters.wake_one() {
+                self.locked.set(false);
+            }
             Ok(vec![])
         }
     }
@@ -110,15 +129,14 @@ impl RWMutexFfi {
         ctx: &mut FfiCtx,
         lock: GosValue,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
-        match RWMutexFfi::create_mutex(&lock, ctx) {
+        match RWMutexFfi::create_handle(&lock, ctx) {
             Ok(m) => Box::pin((|| async move { m.r_lock().await })()),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
     async fn ffi_async_r_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
-        let ud = lock.as_unsafe_ptr();
-        let mutex = ud.unwrap().downcast_ref::<RWMutex>().unwrap().clone();
+        let mutex: RWMutex = held_handle(&lock, "sync: RUnlock of unlocked RWMutex")?;
         mutex.r_unlock().await
     }
 
@@ -126,135 +144,250 @@ impl RWMutexFfi {
         ctx: &mut FfiCtx,
         lock: GosValue,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
-        match RWMutexFfi::create_mutex(&lock, ctx) {
+        match RWMutexFfi::create_handle(&lock, ctx) {
             Ok(m) => Box::pin((|| async move { m.w_lock().await })()),
             Err(e) => Box::pin(async move { Err(e) }),
         }
     }
 
     async fn ffi_async_w_unlock(lock: GosValue) -> RuntimeResult<Vec<GosValue>> {
-        let ud = lock.as_unsafe_ptr();
-        let mutex = ud.unwrap().downcast_ref::<RWMutex>().unwrap().clone();
+        let mutex: RWMutex = held_handle(&lock, "sync: Unlock of unlocked RWMutex")?;
         mutex.w_unlock().await
     }
 
-    fn create_mutex(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<RWMutex> {
-        create_mutex!(arg, ctx, RWMutex)
+    fn create_handle(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<RWMutex> {
+        create_handle!(arg, ctx, RWMutex)
     }
 }
 
+#[derive(Clone, Default)]
 pub struct RWMutexData {
-    num_writers_waiting: isize,
     writer_active: bool,
     num_readers_active: isize,
 }
 
-impl RWMutexData {
-    fn new() -> RWMutexData {
-        RWMutexData {
-            num_writers_waiting: 0,
-            writer_active: false,
-            num_readers_active: 0,
+/// Readers wait while a writer is waiting, so that writers don't starve,
+/// and the readers waiting when a writer unlocks go before the next writer.
+#[derive(Clone)]
+pub struct RWMutex {
+    data: Rc<RefCell<RWMutexData>>,
+    readers: Rc<WaitList>,
+    writers: Rc<WaitList>,
+}
+
+impl UnsafePtr for RWMutex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_for_fork(&self) -> Option<Rc<dyn UnsafePtr>> {
+        Some(Rc::new(RWMutex {
+            data: Rc::new(RefCell::new(self.data.borrow().clone())),
+            readers: Rc::new(WaitList::default()),
+            writers: Rc::new(WaitList::default()),
+        }))
+    }
+}
+
+impl RWMutex {
+    fn new() -> RWMutex {
+        RWMutex {
+            data: Rc::new(RefCell::new(RWMutexData::default())),
+            readers: Rc::new(WaitList::default()),
+            writers: Rc::new(WaitList::default()),
         }
     }
 
-    fn can_read(&self) -> bool {
-        !self.writer_active && self.num_writers_waiting == 0
+    async fn r_lock(self) -> RuntimeResult<Vec<GosValue>> {
+        let can_read = {
+            let d = self.data.borrow();
+            !d.writer_active && self.writers.is_empty()
+        };
+        if can_read {
+            self.data.borrow_mut().num_readers_active += 1;
+        } else {
+            // counted as active by the writer that wakes it up
+            self.readers.park().await;
+        }
+        Ok(vec![])
     }
 
-    fn can_write(&self) -> bool {
-        !self.writer_active && self.num_readers_active == 0
+    async fn r_unlock(self) -> RuntimeResult<Vec<GosValue>> {
+        let mut d = self.data.borrow_mut();
+        if d.num_readers_active <= 0 {
+            return Err("sync: RUnlock of unlocked RWMutex".to_owned().into());
+        }
+        d.num_readers_active -= 1;
+        if d.num_readers_active == 0 && self.writers.wake_one() {
+            d.writer_active = true;
+        }
+        Ok(vec![])
     }
 
-    fn inc_reader_num(&mut self) -> isize {
-        self.num_readers_active += 1;
-        self.num_readers_active
+    async fn w_lock(self) -> RuntimeResult<Vec<GosValue>> {
+        let can_write = {
+            let d = self.data.borrow();
+            !d.writer_active && d.num_readers_active == 0
+        };
+        if can_write {
+            self.data.borrow_mut().writer_active = true;
+        } else {
+            self.writers.park().await;
+        }
+        Ok(vec![])
     }
 
-    fn dec_reader_num(&mut self) -> isize {
-        self.num_readers_active -= 1;
-        self.num_readers_active
+    async fn w_unlock(self) -> RuntimeResult<Vec<GosValue>> {
+        let mut d = self.data.borrow_mut();
+        if !d.writer_active {
+            return Err("sync: Unlock of unlocked RWMutex".to_owned().into());
+        }
+        d.writer_active = false;
+        let readers = self.readers.wake_all();
+        if readers > 0 {
+            d.num_readers_active += readers as isize;
+        } else if self.writers.wake_one() {
+            d.writer_active = true;
+        }
+        Ok(vec![])
     }
+}
 
-    fn inc_writer_num(&mut self) -> isize {
-        self.num_writers_waiting += 1;
-        self.num_writers_waiting
+#[derive(Ffi)]
+pub struct WaitGroupFfi;
+
+#[ffi_impl(rename = "sync.wait_group")]
+impl WaitGroupFfi {
+    fn ffi_add(ctx: &mut FfiCtx, wg: GosValue, delta: isize) -> RuntimeResult<()> {
+        let wg = WaitGroupFfi::create_wait_group(&wg, ctx)?;
+        let counter = wg.counter.get() + delta;
+        if counter < 0 {
+            return Err("sync: negative WaitGroup counter".to_owned().into());
+        }
+        wg.counter.set(counter);
+        if counter == 0 {
+            wg.waiters.wake_all();
+        }
+        Ok(())
     }
 
-    fn dec_writer_num(&mut self) -> isize {
-        self.num_writers_waiting -= 1;
-        self.num_writers_waiting
+    fn ffi_async_wait(
+        ctx: &mut FfiCtx,
+        wg: GosValue,
+    ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>>>> {
+        match WaitGroupFfi::create_wait_group(&wg, ctx) {
+            Ok(wg) => Box::pin(async move { wg.wait().await }),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
     }
 
-    fn set_writer_active(&mut self, val: bool) -> bool {
-        let old_val = self.writer_active;
-        self.writer_active = val;
-        old_val
+    fn create_wait_group(arg: &GosValue, ctx: &mut FfiCtx) -> RuntimeResult<WaitGroup> {
+        create_handle!(arg, ctx, WaitGroup)
     }
 }
 
 #[derive(Clone)]
-pub struct RWMutex {
-    data: Rc<RefCell<RWMutexData>>,
+struct WaitGroup {
+    counter: Rc<Cell<isize>>,
+    waiters: Rc<WaitList>,
 }
 
-impl UnsafePtr for RWMutex {
+impl UnsafePtr for WaitGroup {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn clone_for_fork(&self) -> Option<Rc<dyn UnsafePtr>> {
+        Some(Rc::new(WaitGroup {
+            counter: Rc::new(Cell::new(self.counter.get())),
+            waiters: Rc::new(WaitList::default()),
+        }))
+    }
 }
 
-impl RWMutex {
-    fn new() -> RWMutex {
-        RWMutex {
-            data: Rc::new(RefCell::new(RWMutexData::new())),
+impl WaitGroup {
+    fn new() -> WaitGroup {
+        WaitGroup {
+            counter: Rc::new(Cell::new(0)),
+            waiters: Rc::new(WaitList::default()),
         }
     }
 
-    async fn r_lock(self) -> RuntimeResult<Vec<GosValue>> {
-        loop {
-            let can_read = self.data.borrow().can_read();
-            if !can_read {
-                future::yield_now().await;
-            } else {
-                break;
-            }
+    async fn wait(self) -> RuntimeResult<Vec<GosValue>> {
+        if self.counter.get() > 0 {
+            self.waiters.park().await;
         }
-        self.data.borrow_mut().inc_reader_num();
         Ok(vec![])
     }
+}
 
-    async fn r_unlock(self) -> RuntimeResult<Vec<GosValue>> {
-        let num = self.data.borrow_mut().dec_reader_num();
-        if num < 0 {
-            Err("sync: unmatched rUnlock call".to_owned().into())
-        } else {
-            Ok(vec![])
-        }
+/// The goroutines parked on a lock, in the order they came. A parked goroutine
+/// isn't polled by the executor until it's woken up, so when all of them are
+/// parked the VM stops and reports the deadlock.
+#[derive(Default)]
+struct WaitList {
+    waiters: RefCell<VecDeque<Rc<Waiter>>>,
+}
+
+#[derive(Default)]
+struct Waiter {
+    woken: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl WaitList {
+    fn park(&self) -> Park {
+        let waiter = Rc::new(Waiter::default());
+        self.waiters.borrow_mut().push_back(waiter.clone());
+        Park(waiter)
     }
 
-    async fn w_lock(self) -> RuntimeResult<Vec<GosValue>> {
-        self.data.borrow_mut().inc_writer_num();
-        loop {
-            let can_write = self.data.borrow().can_write();
-            if !can_write {
-                future::yield_now().await;
-            } else {
-                break;
+    fn is_empty(&self) -> bool {
+        self.waiters.borrow().is_empty()
+    }
+
+    /// Returns false if no one was waiting
+    fn wake_one(&self) -> bool {
+        let waiter = self.waiters.borrow_mut().pop_front();
+        match waiter {
+            Some(w) => {
+                w.wake();
+                true
             }
+            None => false,
         }
-        let mut d = self.data.borrow_mut();
-        d.dec_writer_num();
-        d.set_writer_active(true);
-        Ok(vec![])
     }
 
-    async fn w_unlock(self) -> RuntimeResult<Vec<GosValue>> {
-        let was_active = self.data.borrow_mut().set_writer_active(false);
-        if !was_active {
-            Err("sync: unmatched wUnlock call".to_owned().into())
+    /// Returns how many were waiting
+    fn wake_all(&self) -> usize {
+        let waiters: Vec<Rc<Waiter>> = self.waiters.borrow_mut().drain(..).collect();
+        for w in waiters.iter() {
+            w.wake();
+        }
+        waiters.len()
+    }
+}
+
+impl Waiter {
+    fn wake(&self) {
+        self.woken.set(true);
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Park(Rc<Waiter>);
+
+impl Future for Park {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0.woken.get() {
+            Poll::Ready(())
         } else {
-            Ok(vec![])
+            *self.0.waker.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
         }
     }
 }
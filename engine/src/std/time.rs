@@ -0,0 +1,55 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+extern crate self as go_engine;
+use crate::ffi::*;
+use futures_lite::future;
+use go_vm::types::{GosValue, RuntimeResult};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Per thread, the monotonic clock reads as the time since the first read
+thread_local! {
+    static START: Instant = Instant::now();
+}
+
+#[derive(Ffi)]
+pub struct TimeFfi;
+
+#[ffi_impl(rename = "time")]
+impl TimeFfi {
+    /// The wall clock as seconds and nanoseconds since the Unix epoch, and the monotonic clock
+    fn ffi_now() -> (i64, i32, i64) {
+        let (sec, nsec) = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i32),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_nanos() {
+                    0 => (-(d.as_secs() as i64), 0),
+                    n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n as i32),
+                }
+            }
+        };
+        (sec, nsec, runtime_nano())
+    }
+
+    fn ffi_runtime_nano() -> i64 {
+        runtime_nano()
+    }
+
+    /// Keeps yielding to the other goroutines until the time is up, so that a
+    /// sleeping goroutine doesn't block the others like blocking the thread would.
+    async fn ffi_async_sleep(d: i64) -> RuntimeResult<Vec<GosValue>> {
+        if d > 0 {
+            let deadline = Instant::now() + Duration::from_nanos(d as u64);
+            while Instant::now() < deadline {
+                future::yield_now().await;
+            }
+        }
+        Ok(vec![])
+    }
+}
+
+fn runtime_nano() -> i64 {
+    START.with(|start| start.elapsed().as_nanos() as i64)
+}
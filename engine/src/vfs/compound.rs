@@ -85,7 +85,8 @@ impl VirtualFs for CompoundFs {
 
     fn strip_prefix<'a>(&'a self, path: &'a Path) -> &'a Path {
         let path_str = path.to_str().unwrap();
-        let (_, suffix) = path_str.split_once('/').unwrap_or(("", path_str));
+        // a path without '/' is the name of a sub fs
+        let (_, suffix) = path_str.split_once('/').unwrap_or((path_str, ""));
         Path::new(suffix)
     }
 
@@ -11,7 +11,8 @@ pub struct VfsFs {}
 
 impl VirtualFs for VfsFs {
     fn read_file(&self, path: &Path) -> io::Result<String> {
-        fs::read_to_string(path)
+        // the scanner reports the bytes that are not UTF-8 where they are
+        Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
     }
 
     fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
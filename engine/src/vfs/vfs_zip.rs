@@ -40,9 +40,10 @@ impl VirtualFs for VfsZip {
         let mut borrow = self.archive.borrow_mut();
         let re = borrow.by_name(path.to_str().unwrap());
         let mut file = VfsZip::convert_err(re)?;
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        Ok(buf)
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        // the scanner reports the bytes that are not UTF-8 where they are
+        Ok(String::from_utf8_lossy(&buf).into_owned())
     }
 
     fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
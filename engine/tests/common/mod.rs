@@ -0,0 +1,32 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The sources generated for both the tests and the benchmarks.
+
+use std::fmt::Write;
+
+/// A function of `stmts` statements, with a label every `stmts / labels` of them and a
+/// goto to the next one, like the state machines generated for scripts. Returns the
+/// source and the result of the function.
+pub fn large_function(stmts: usize, labels: usize) -> (String, i64) {
+    let every = stmts / labels;
+    let mut a: i64 = 1;
+    let mut source = String::from("package main\n\nfunc big(a int) int {\n");
+    source.push_str("    if a < 0 {\n        goto L0\n    }\n");
+    for i in 0..stmts {
+        if i % every == 0 {
+            let k = i / every;
+            writeln!(source, "L{}:\n    a = a + {}", k, i % 7).unwrap();
+            a += (i % 7) as i64;
+            if k + 1 < labels {
+                writeln!(source, "    if a < 0 {{\n        goto L{}\n    }}", k + 1).unwrap();
+            }
+        } else {
+            writeln!(source, "    a = a ^ {}", i % 13).unwrap();
+            a ^= (i % 13) as i64;
+        }
+    }
+    source.push_str("    return a\n}\n\nfunc main() {\n    big(1)\n}\n");
+    (source, a)
+}
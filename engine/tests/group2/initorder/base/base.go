@@ -0,0 +1,14 @@
+package base
+
+var Log []string
+
+var Inits int
+
+func Record(name string) {
+	Log = append(Log, name)
+}
+
+func init() {
+	Inits++
+	Record("base")
+}
@@ -0,0 +1,12 @@
+package left
+
+import "../base"
+
+var Value = base.Inits * 10
+
+func init() {
+	// base is fully initialized before the vars of left
+	if Value == 10 {
+		base.Record("left")
+	}
+}
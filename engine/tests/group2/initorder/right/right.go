@@ -0,0 +1,12 @@
+package right
+
+import (
+	_ "../base"
+	b "../base"
+)
+
+func init() {
+	b.Record("right")
+}
+
+const Name = "right"
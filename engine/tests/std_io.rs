@@ -0,0 +1,300 @@
+//! The tests that set the std io of the engine. It's process wide, so they
+//! are kept apart from the other tests, which run in parallel and reset it,
+//! and they take turns with each other.
+#![cfg(feature = "go_std")]
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard};
+
+extern crate go_engine as engine;
+
+static STD_IO: Mutex<()> = Mutex::new(());
+
+fn lock_std_io() -> MutexGuard<'static, ()> {
+    STD_IO.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run_string_with_config(
+    source: &str,
+    cfg: engine::Config,
+) -> (Result<(), engine::ErrorList>, Option<(String, String)>) {
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Owned(source.to_owned()),
+    );
+    let reported = Rc::new(std::cell::RefCell::new(None));
+    let reported2 = reported.clone();
+    let ph: Option<Rc<dyn Fn(String, String)>> =
+        Some(Rc::new(move |msg: String, stack: String| {
+            *reported2.borrow_mut() = Some((msg, stack));
+        }));
+    let result = engine::run(cfg, &sr, &path, ph);
+    let reported = reported.borrow_mut().take();
+    (result, reported)
+}
+
+/// Produces `total` bytes of lowercase lines without holding them in memory
+struct LineSource {
+    remaining: usize,
+    pos: usize,
+    max_read: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl io::Read for LineSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        const LINE: &[u8] = b"the quick brown fox jumps over the lazy dog\n";
+        let ordering = std::sync::atomic::Ordering::Relaxed;
+        self.max_read.fetch_max(buf.len(), ordering);
+        let n = buf.len().min(self.remaining);
+        for b in buf[..n].iter_mut() {
+            *b = LINE[self.pos];
+            self.pos = (self.pos + 1) % LINE.len();
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// Counts the bytes written and remembers the last few of them
+#[derive(Clone, Default)]
+struct TailSink(std::sync::Arc<std::sync::Mutex<(usize, Vec<u8>, bool)>>);
+
+impl Write for TailSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        data.0 += buf.len();
+        data.1.extend_from_slice(buf);
+        let keep = data.1.len().saturating_sub(64);
+        data.1.drain(..keep);
+        data.2 |= buf.iter().any(|b| b.is_ascii_lowercase());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_bufio_filter() {
+    let _io = lock_std_io();
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+        "strings"
+    )
+
+    func main() {
+        s := bufio.NewScanner(os.Stdin)
+        w := bufio.NewWriter(os.Stdout)
+        for s.Scan() {
+            w.WriteString(strings.ToUpper(s.Text()))
+            w.WriteByte('\n')
+        }
+        assert(s.Err() == nil)
+        // not flushed, it's done when main returns
+    }
+    "#;
+    // whole lines, a debug build of the vm is too slow for a bigger stream
+    let total = 44 * 6000;
+    let max_read = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let sink = TailSink::default();
+    let cfg = engine::Config {
+        std_in: Some(Box::new(LineSource {
+            remaining: total,
+            pos: 0,
+            max_read: max_read.clone(),
+        })),
+        std_out: Some(Box::new(sink.clone())),
+        ..Default::default()
+    };
+    let (result, reported) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert!(reported.is_none(), "{:?}", reported);
+    let data = sink.0.lock().unwrap();
+    assert_eq!(data.0, total);
+    assert!(!data.2);
+    assert!(data.1.ends_with(b"LAZY DOG\n"));
+    // the input is read in small chunks rather than all at once
+    assert!(max_read.load(std::sync::atomic::Ordering::Relaxed) <= 4096);
+}
+
+#[test]
+fn test_bufio_too_long() {
+    let _io = lock_std_io();
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+    )
+
+    func main() {
+        s := bufio.NewScanner(os.Stdin)
+        s.Buffer(make([]byte, 16), 32)
+        assert(s.Scan())
+        assert(s.Text() == "the quick brown fox jumps over the lazy dog")
+    }
+    "#;
+    let cfg = engine::Config {
+        std_in: Some(Box::new(LineSource {
+            remaining: 100,
+            pos: 0,
+            max_read: Default::default(),
+        })),
+        ..Default::default()
+    };
+    let (_, reported) = run_string_with_config(source, cfg);
+    assert!(reported.is_some());
+
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+    )
+
+    func main() {
+        s := bufio.NewScanner(os.Stdin)
+        s.Buffer(make([]byte, 16), 32)
+        assert(!s.Scan())
+        assert(s.Err() == bufio.ErrTooLong)
+    }
+    "#;
+    let cfg = engine::Config {
+        std_in: Some(Box::new(LineSource {
+            remaining: 100,
+            pos: 0,
+            max_read: Default::default(),
+        })),
+        ..Default::default()
+    };
+    let (result, reported) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert!(reported.is_none(), "{:?}", reported);
+}
+
+#[test]
+fn test_bufio_flush_on_exit() {
+    let _io = lock_std_io();
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+    )
+
+    func main() {
+        w := bufio.NewWriter(os.Stdout)
+        w.WriteString("buffered")
+        assert(w.Buffered() == 8)
+    }
+    "#;
+    let sink = TailSink::default();
+    let cfg = engine::Config {
+        std_out: Some(Box::new(sink.clone())),
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert_eq!(sink.0.lock().unwrap().1, b"buffered");
+
+    // nothing is flushed if main panics
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+    )
+
+    func main() {
+        w := bufio.NewWriter(os.Stdout)
+        w.WriteString("buffered")
+        panic("exit")
+    }
+    "#;
+    let sink = TailSink::default();
+    let cfg = engine::Config {
+        std_out: Some(Box::new(sink.clone())),
+        ..Default::default()
+    };
+    let (_, reported) = run_string_with_config(source, cfg);
+    assert!(reported.is_some());
+    assert_eq!(sink.0.lock().unwrap().0, 0);
+
+    // only what's left in the buffers is flushed, and only once
+    let source = r#"
+    package main
+
+    import (
+        "bufio"
+        "os"
+    )
+
+    func main() {
+        for i := 0; i < 100; i++ {
+            w := bufio.NewWriter(os.Stdout)
+            w.WriteString("x")
+            w.Flush()
+        }
+        w := bufio.NewWriter(os.Stdout)
+        w.WriteString("a")
+        w.Flush()
+        w.WriteString("b")
+        w2 := bufio.NewWriter(os.Stdout)
+        w2.WriteString("c")
+        w2.Reset(os.Stdout)
+    }
+    "#;
+    let sink = TailSink::default();
+    let cfg = engine::Config {
+        std_out: Some(Box::new(sink.clone())),
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    let data = sink.0.lock().unwrap();
+    assert_eq!(data.0, 102);
+    assert!(data.1.ends_with(b"xxab"));
+}
+
+#[test]
+fn test_repl_capture_is_per_run() {
+    let _io = lock_std_io();
+    let (sr, _) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(""));
+    let sink = TailSink::default();
+    let mut engine = engine::Engine::new();
+    engine.set_std_io(None, Some(Box::new(sink.clone())), None);
+    engine.set_repl_mode(true);
+    let r = engine.eval(&sr, "fmt.Println(\"hi\")").unwrap();
+    assert_eq!(r.stdout_captured, "hi\n");
+    // the std out shared by the engines neither sees the output nor gets replaced
+    assert_eq!(sink.0.lock().unwrap().0, 0);
+    let source = r#"
+    package main
+
+    import "fmt"
+
+    func main() {
+        fmt.Print("out")
+    }
+    "#;
+    let cfg = engine::Config {
+        std_out: Some(Box::new(sink.clone())),
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert_eq!(sink.0.lock().unwrap().1, b"out");
+}
@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
+mod common;
+
 use std::borrow::Cow;
+use std::fmt::Write as _;
 #[cfg(feature = "read_zip")]
 use std::fs;
 use std::io;
@@ -127,50 +130,3027 @@ fn run_string(_source: &str, _trace: bool) -> Result<(), engine::ErrorList> {
     unimplemented!()
 }
 
-#[cfg(feature = "read_zip")]
-fn run_zip_and_string(
-    file: &str,
-    source: Cow<'static, str>,
-    trace: bool,
-) -> Result<(), engine::ErrorList> {
-    let zip = fs::read(Path::new(file)).unwrap();
+#[cfg(feature = "read_zip")]
+fn run_zip_and_string(
+    file: &str,
+    source: Cow<'static, str>,
+    trace: bool,
+) -> Result<(), engine::ErrorList> {
+    let zip = fs::read(Path::new(file)).unwrap();
+
+    let mut cfg = engine::Config::default();
+    cfg.trace_parser = trace;
+    cfg.trace_checker = trace;
+    let (sr, path) = engine::SourceReader::zip_lib_and_string(
+        std::borrow::Cow::Owned(zip),
+        PathBuf::from("std/"),
+        source,
+    );
+    let ph: Option<Rc<dyn Fn(String, String)>> =
+        Some(Rc::new(move |msg: String, stack: String| {
+            eprintln!("{}\n", msg);
+            eprintln!("{}\n", stack);
+            panic!("test panicked");
+        }));
+    let result = engine::run(cfg, &sr, &path, ph);
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    result
+}
+
+#[test]
+fn test_source() {
+    let source = r#"
+    package main
+    import (
+        "fmt"
+    )
+    func main() {
+        fmt.Println("hello from raw source")
+    }
+    "#;
+    let result = run_string(Cow::Borrowed(source), false);
+    dbg!(&result);
+    assert!(result.is_ok());
+}
+
+#[cfg(feature = "go_std")]
+fn run_string_with_panic(
+    source: &'static str,
+    hook: Option<engine::PanicHook>,
+    format: engine::PanicFormat,
+) -> (Result<(), engine::ErrorList>, Option<(String, String)>) {
+    let cfg = engine::Config {
+        panic_hook: hook,
+        panic_format: format,
+        ..Default::default()
+    };
+    run_string_with_config(source, cfg)
+}
+
+#[cfg(feature = "go_std")]
+fn run_string_with_config(
+    source: &str,
+    cfg: engine::Config,
+) -> (Result<(), engine::ErrorList>, Option<(String, String)>) {
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Owned(source.to_owned()),
+    );
+    let reported = Rc::new(std::cell::RefCell::new(None));
+    let reported2 = reported.clone();
+    let ph: Option<Rc<dyn Fn(String, String)>> =
+        Some(Rc::new(move |msg: String, stack: String| {
+            *reported2.borrow_mut() = Some((msg, stack));
+        }));
+    let result = engine::run(cfg, &sr, &path, ph);
+    let reported = reported.borrow_mut().take();
+    (result, reported)
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_hook() {
+    let source = r#"
+    package main
+
+    func main() {
+        s := []int{1, 2, 3}
+        i := 5
+        _ = s[i]
+    }
+    "#;
+    let seen = Rc::new(std::cell::RefCell::new(vec![]));
+    let seen2 = seen.clone();
+    let hook: engine::PanicHook = Rc::new(move |info: &engine::PanicInfo| {
+        seen2.borrow_mut().push((
+            info.kind,
+            format!("{}", info.value),
+            info.goroutine,
+            info.backtrace.clone(),
+        ));
+        engine::PanicDisposition::Propagate
+    });
+    let (result, reported) =
+        run_string_with_panic(source, Some(hook), engine::PanicFormat::default());
+    assert!(result.is_ok());
+    let seen = seen.borrow();
+    assert_eq!(seen.len(), 1);
+    let (kind, value, goroutine, backtrace) = &seen[0];
+    assert_eq!(*kind, go_vm::PanicKind::Runtime);
+    assert!(value.contains("out of range"));
+    assert_eq!(*goroutine, 0);
+    assert_eq!(backtrace[0].as_ref().unwrap().line, 7);
+    let (msg, _) = reported.unwrap();
+    assert!(msg.contains("out of range"));
+
+    let hook: engine::PanicHook =
+        Rc::new(|_: &engine::PanicInfo| engine::PanicDisposition::Suppress);
+    let (result, reported) =
+        run_string_with_panic(source, Some(hook), engine::PanicFormat::default());
+    assert!(result.is_ok());
+    assert!(reported.is_none());
+}
+
+/// A panic hook that expects the panic "failed", and counts the panics it has seen.
+/// It suppresses them while the flag is set.
+#[cfg(feature = "go_std")]
+fn counting_panic_hook() -> (
+    engine::PanicHook,
+    Rc<std::cell::Cell<usize>>,
+    Rc<std::cell::Cell<bool>>,
+) {
+    let seen = Rc::new(std::cell::Cell::new(0));
+    let suppress = Rc::new(std::cell::Cell::new(false));
+    let (seen2, suppress2) = (seen.clone(), suppress.clone());
+    let hook: engine::PanicHook = Rc::new(move |info: &engine::PanicInfo| {
+        assert!(format!("{}", info.value).contains("failed"));
+        seen2.set(seen2.get() + 1);
+        match suppress2.get() {
+            true => engine::PanicDisposition::Suppress,
+            false => engine::PanicDisposition::Propagate,
+        }
+    });
+    (hook, seen, suppress)
+}
+
+#[cfg(feature = "go_std")]
+const FAILING_CALL: &str = r#"
+    package main
+
+    type Pair struct {
+        A int
+        B string
+    }
+
+    func Fail(n int) (int, string, *Pair, Pair) {
+        if n > 0 {
+            panic("failed")
+        }
+        return n, "ok", nil, Pair{}
+    }
+
+    func main() {}
+    "#;
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_hook_call_json() {
+    let (hook, seen, suppress) = counting_panic_hook();
+    let (sr, path) = engine::SourceReader::fs_lib_and_string(
+        PathBuf::from("../std/"),
+        Cow::Borrowed(FAILING_CALL),
+    );
+    let mut engine = engine::Engine::new();
+    engine.set_panic_hook(Some(hook));
+    engine.load_source(&sr, &path).unwrap();
+    let err = engine.call_json("Fail", "[1]").unwrap_err();
+    assert!(err.to_string().contains("failed"), "{}", err);
+    assert_eq!(seen.get(), 1);
+    // a suppressed panic returns the zero values
+    suppress.set(true);
+    assert_eq!(
+        engine.call_json("Fail", "[1]").unwrap(),
+        r#"[0,"",null,{"A":0,"B":""}]"#
+    );
+    assert_eq!(seen.get(), 2);
+    assert_eq!(
+        engine.call_json("Fail", "[0]").unwrap(),
+        r#"[0,"ok",null,{"A":0,"B":""}]"#
+    );
+    assert_eq!(seen.get(), 2);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_hook_call_module() {
+    let (hook, seen, suppress) = counting_panic_hook();
+    let (mut engine, modules) =
+        compile_modules(&[("m.gos", FAILING_CALL)], &[("m", "./m.gos")]).unwrap();
+    engine.set_panic_hook(Some(hook));
+    let mut m = engine.instantiate_module(&modules, "m").unwrap();
+    let err = engine
+        .call_module(&modules, &mut m, "Fail", "[1]")
+        .unwrap_err();
+    assert!(err.to_string().contains("failed"), "{}", err);
+    assert_eq!(seen.get(), 1);
+    suppress.set(true);
+    assert_eq!(
+        engine.call_module(&modules, &mut m, "Fail", "[1]").unwrap(),
+        r#"[0,"",null,{"A":0,"B":""}]"#
+    );
+    assert_eq!(seen.get(), 2);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_hook_eval() {
+    let (hook, seen, suppress) = counting_panic_hook();
+    let (sr, _) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(""));
+    let mut engine = engine::Engine::new();
+    engine.set_panic_hook(Some(hook));
+    let snippet = r#"if true { panic("failed") }"#;
+    let err = engine.eval(&sr, snippet).unwrap_err();
+    assert!(err.to_string().contains("panic: failed"), "{}", err);
+    assert_eq!(seen.get(), 1);
+    suppress.set(true);
+    let r = engine.eval(&sr, snippet).unwrap();
+    assert!(r.values.is_empty());
+    assert_eq!(seen.get(), 2);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_panic_format() {
+    let source = r#"
+    package main
+
+    func f(n int) {
+        if n == 0 {
+            panic("deep")
+        }
+        f(n - 1)
+    }
+
+    func main() {
+        f(10)
+    }
+    "#;
+    let format = engine::PanicFormat {
+        backtrace: true,
+        max_frames: Some(3),
+        redact_value: true,
+    };
+    let (_, reported) = run_string_with_panic(source, None, format);
+    let (msg, stack) = reported.unwrap();
+    assert_eq!(msg, "<value redacted>");
+    let lines: Vec<&str> = stack.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[3], "... 10 more frames");
+
+    let format = engine::PanicFormat {
+        backtrace: false,
+        ..Default::default()
+    };
+    let (_, reported) = run_string_with_panic(source, None, format);
+    let (msg, stack) = reported.unwrap();
+    assert!(msg.contains("deep"));
+    assert!(stack.is_empty());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_checked_arithmetic() {
+    let cases = [
+        (
+            "var a int8 = 127\n a = a + 1",
+            "-128",
+            "integer overflow: int8 addition of 127 + 1",
+        ),
+        (
+            "var a uint8\n a--",
+            "255",
+            "integer overflow: uint8 subtraction of 0 - 1",
+        ),
+        (
+            "var a int16 = 300\n a *= 200",
+            "-5536",
+            "integer overflow: int16 multiplication of 300 * 200",
+        ),
+        (
+            "var a int32 = 1 << 30\n a = a << 2",
+            "0",
+            "integer overflow: int32 left shift of 1073741824 << 2",
+        ),
+        (
+            "s := struct{ v int64 }{9223372036854775807}\n s.v++\n a := s.v",
+            "-9223372036854775808",
+            "integer overflow: int64 addition of 9223372036854775807 + 1",
+        ),
+        (
+            "x := 300\n a := uint8(x)",
+            "44",
+            "integer overflow: conversion of int 300 to uint8",
+        ),
+    ];
+    for (stmts, wrapped, msg) in cases {
+        let source = format!(
+            "package main\n\nfunc main() {{\n {}\n assert(a == {})\n}}\n",
+            stmts, wrapped
+        );
+        let (result, reported) = run_string_with_config(&source, engine::Config::default());
+        assert!(result.is_ok());
+        assert!(reported.is_none(), "{:?}", reported);
+
+        let cfg = engine::Config {
+            checked_arithmetic: true,
+            ..Default::default()
+        };
+        let (result, reported) = run_string_with_config(&source, cfg);
+        assert!(result.is_ok());
+        let (value, stack) = reported.unwrap();
+        assert!(value.contains(msg), "{}", value);
+        // the overflow is always on the second statement
+        assert!(stack.lines().next().unwrap().contains(":5:"), "{}", stack);
+    }
+
+    let source = r#"
+    package main
+
+    func add(a, b uint16) (r uint16, err interface{}) {
+        defer func() { err = recover() }()
+        return a + b, nil
+    }
+
+    func main() {
+        r, err := add(1, 2)
+        assert(r == 3 && err == nil)
+        _, err = add(65535, 1)
+        assert(err != nil)
+    }
+    "#;
+    let cfg = engine::Config {
+        checked_arithmetic: true,
+        ..Default::default()
+    };
+    let (result, reported) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert!(reported.is_none(), "{:?}", reported);
+
+    let source = "package main\n\nconst c int8 = 127 + 1\n\nfunc main() {}\n";
+    let cfg = engine::Config {
+        checked_arithmetic: true,
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_err());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_warn_defer_in_loop() {
+    let source = r#"
+    package main
+
+    func g(string) {}
+
+    func f(names []string) {
+        defer g("done")
+        for _, n := range names {
+            defer g(n)
+            func() {
+                defer g("inner")
+            }()
+        }
+        for i := 0; ; i++ {
+            if i > 2 {
+                break
+            }
+            defer func() {}()
+        }
+    }
+
+    func main() {
+        f(nil)
+    }
+    "#;
+    let warnings = Rc::new(std::cell::RefCell::new(vec![]));
+    let warnings2 = warnings.clone();
+    let cfg = engine::Config {
+        warn_defer_in_loop: true,
+        warning_handler: Some(Rc::new(move |w: String| warnings2.borrow_mut().push(w))),
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    let warnings = warnings.borrow();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(
+        warnings[0].contains(":9:13: defer in a loop"),
+        "{}",
+        warnings[0]
+    );
+    assert!(
+        warnings[1].contains(":18:13: defer in a loop"),
+        "{}",
+        warnings[1]
+    );
+
+    let warnings = Rc::new(std::cell::RefCell::new(vec![]));
+    let warnings2 = warnings.clone();
+    let cfg = engine::Config {
+        warning_handler: Some(Rc::new(move |w: String| warnings2.borrow_mut().push(w))),
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    assert!(result.is_ok());
+    assert!(warnings.borrow().is_empty());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_strictness() {
+    let unused = "package main\n\nfunc main() {\n\tx := 1\n}\n";
+    let deferred = "package main\n\nfunc main() {\n\tfor i := 0; i < 2; i++ {\n\t\tdefer func() {}()\n\t}\n}\n";
+    let run = |source: &str, strictness: engine::Strictness| {
+        let warnings = Rc::new(std::cell::RefCell::new(vec![]));
+        let warnings2 = warnings.clone();
+        let cfg = engine::Config {
+            strictness,
+            warning_handler: Some(Rc::new(move |w: String| warnings2.borrow_mut().push(w))),
+            ..Default::default()
+        };
+        let (result, _) = run_string_with_config(source, cfg);
+        let warnings = warnings.borrow().clone();
+        (result, warnings)
+    };
+
+    let (result, warnings) = run(unused, engine::Strictness::Lenient);
+    assert!(result.is_ok());
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(
+        warnings[0].contains("declared but not used: x"),
+        "{}",
+        warnings[0]
+    );
+    let (result, _) = run(unused, engine::Strictness::Standard);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("declared but not used: x"), "{}", err);
+
+    let (result, warnings) = run(deferred, engine::Strictness::Standard);
+    assert!(result.is_ok());
+    assert!(warnings.is_empty());
+    let (result, warnings) = run(deferred, engine::Strictness::Pedantic);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains(":5:3: defer in a loop"), "{}", err);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_exit_hooks() {
+    let source = r#"
+    package main
+
+    import "internal/exithook"
+
+    var ran []int
+
+    func main() {
+        exithook.Add(exithook.Hook{F: func() {
+            assert(len(ran) == 2 && ran[0] == 2 && ran[1] == 1)
+            panic("all the hooks ran")
+        }})
+        exithook.Add(exithook.Hook{F: func() { ran = append(ran, 1) }})
+        exithook.Add(exithook.Hook{F: func() {
+            ran = append(ran, 2)
+        }})
+        assert(len(ran) == 0)
+    }
+    "#;
+    let (_, reported) = run_string_with_config(source, engine::Config::default());
+    let (value, _) = reported.unwrap();
+    assert!(value.contains("all the hooks ran"), "{}", value);
+
+    // they don't run if main panics
+    let source = r#"
+    package main
+
+    import "internal/exithook"
+
+    func main() {
+        exithook.Add(exithook.Hook{F: func() { panic("hook") }})
+        panic("main")
+    }
+    "#;
+    let (_, reported) = run_string_with_config(source, engine::Config::default());
+    let (value, _) = reported.unwrap();
+    assert!(value.contains("main"), "{}", value);
+    assert!(!value.contains("hook"), "{}", value);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_goroutine_leaks() {
+    let source = r#"
+    package main
+
+    func worker(ch chan int) {
+        <-ch
+    }
+
+    func main() {
+        ch := make(chan int)
+        go worker(ch)
+        finished := make(chan int, 1)
+        go func() {
+            for i := 0; i < 10000; i++ {
+            }
+            finished <- 1
+        }()
+    }
+    "#;
+    let run = |grace: Option<std::time::Duration>| {
+        let (sr, path) = engine::SourceReader::fs_lib_and_string(
+            PathBuf::from("../std/"),
+            Cow::Borrowed(source),
+        );
+        let mut engine = engine::Engine::new();
+        engine.set_goroutine_grace_period(grace);
+        let code = engine.compile(&sr, &path, true, false, false).unwrap();
+        engine.run_bytecode_with_result(&code)
+    };
+
+    let result = run(None);
+    assert!(result.panic.is_none());
+    assert_eq!(result.leaked_goroutines.len(), 1);
+    let leaked = &result.leaked_goroutines[0];
+    assert_eq!(leaked.id, 1);
+    assert_eq!(leaked.blocked_on, Some(go_vm::BlockReason::Recv));
+    assert_eq!(leaked.position.as_ref().unwrap().line, 5);
+    assert_eq!(leaked.spawned_at.as_ref().unwrap().line, 10);
+
+    // main returns before the others get to run
+    let result = run(Some(std::time::Duration::ZERO));
+    assert_eq!(result.leaked_goroutines.len(), 2);
+    assert_eq!(result.leaked_goroutines[1].blocked_on, None);
+    assert_eq!(
+        result.leaked_goroutines[1]
+            .spawned_at
+            .as_ref()
+            .unwrap()
+            .line,
+        12
+    );
+
+    let result = run(Some(std::time::Duration::from_secs(10)));
+    assert_eq!(result.leaked_goroutines.len(), 1);
+
+    let cfg = engine::Config {
+        fail_on_goroutine_leak: true,
+        ..Default::default()
+    };
+    let (result, _) = run_string_with_config(source, cfg);
+    let el = result.unwrap_err();
+    let errs = el.borrow();
+    assert_eq!(errs.len(), 1);
+    assert!(
+        errs[0].msg.starts_with("leaked goroutine 1 [chan receive]"),
+        "{}",
+        errs[0].msg
+    );
+    assert_eq!(errs[0].pos.line, 5);
+
+    let deadlock = "package main\nfunc main() {\n    ch := make(chan int)\n    ch <- 1\n}\n";
+    let (result, _) = run_string_with_config(deadlock, engine::Config::default());
+    let el = result.unwrap_err();
+    let errs = el.borrow();
+    assert_eq!(errs[0].msg, "all goroutines are asleep - deadlock!");
+    assert_eq!(errs[0].pos.line, 4);
+    assert!(errs[1]
+        .msg
+        .starts_with("deadlocked goroutine 0 [chan send]"));
+
+    // the others are listed too, and a deadlock is found with them all blocked
+    let deadlock = r#"
+    package main
+
+    func main() {
+        ch, quit := make(chan int), make(chan int)
+        for i := 0; i < 3; i++ {
+            go func() { ch <- <-quit }()
+        }
+        <-ch
+    }
+    "#;
+    let (result, _) = run_string_with_config(deadlock, engine::Config::default());
+    let el = result.unwrap_err();
+    let errs = el.borrow();
+    assert_eq!(errs.len(), 5, "{}", el);
+    assert_eq!(errs[0].msg, "all goroutines are asleep - deadlock!");
+    assert!(errs[1]
+        .msg
+        .starts_with("deadlocked goroutine 0 [chan receive]"));
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_growth_stats() {
+    let source = r#"
+    package main
+
+    func main() {
+        hinted := make([]int, 0, 1000)
+        for i := 0; i < 1000; i++ {
+            hinted = append(hinted, i)
+        }
+        var grown []int
+        for i := 0; i < 1000; i++ {
+            grown = append(grown, i)
+        }
+        m := make(map[int]int, 1000)
+        for i := 0; i < 1000; i++ {
+            m[i] = i
+        }
+        n := map[int]int{}
+        for i := 0; i < 1000; i++ {
+            n[i] = i
+        }
+        assert(len(hinted) == 1000 && len(grown) == 1000 && len(m) == 1000 && len(n) == 1000)
+    }
+    "#;
+    let run = |on: bool| {
+        let (sr, path) = engine::SourceReader::fs_lib_and_string(
+            PathBuf::from("../std/"),
+            Cow::Borrowed(source),
+        );
+        let mut engine = engine::Engine::new();
+        engine.set_growth_stats(on);
+        let code = engine.compile(&sr, &path, true, false, false).unwrap();
+        engine.run_bytecode_with_result(&code)
+    };
+
+    let result = run(true);
+    assert!(result.panic.is_none());
+    let lines: Vec<usize> = result
+        .growth_sites
+        .iter()
+        .map(|s| s.position.as_ref().unwrap().line)
+        .collect();
+    // the appends and the map with a large enough capacity never grow
+    #[cfg(not(feature = "btree_map"))]
+    assert_eq!(lines, [11, 19]);
+    #[cfg(feature = "btree_map")]
+    assert_eq!(lines, [11]);
+    // 1, 2, 4, ..., 512, 832, 1232
+    let grown = &result.growth_sites[0];
+    assert_eq!((grown.slice_growths, grown.map_rehashes), (12, 0));
+    #[cfg(not(feature = "btree_map"))]
+    {
+        let rehashed = &result.growth_sites[1];
+        assert_eq!(rehashed.slice_growths, 0);
+        assert!((5..=20).contains(&rehashed.map_rehashes));
+    }
+
+    assert!(run(false).growth_sites.is_empty());
+}
+
+/// Runs a loop making cyclic garbage of every kind for a few seconds
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn run_gc_soak(max_pause: std::time::Duration) -> go_vm::GcStats {
+    let source = r#"
+    package main
+
+    import "runtime"
+
+    type node struct {
+        next *node
+        data []int
+    }
+
+    func main() {
+        keep := &node{}
+        keep.next = keep
+        total := 0
+        for i := 0; i < 100000; i++ {
+            a := &node{data: []int{i}}
+            b := &node{next: a}
+            a.next = b
+            var f func(int) int
+            f = func(n int) int {
+                if n == 0 {
+                    return 0
+                }
+                return f(n-1) + 1
+            }
+            total += f(1)
+            m := map[int]interface{}{}
+            m[i] = m
+            if i%1000 == 0 {
+                runtime.Gosched()
+            }
+        }
+        assert(total == 100000)
+        assert(keep.next == keep)
+    }
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.set_gc_max_pause(Some(max_pause));
+    let code = engine.compile(&sr, &path, true, false, false).unwrap();
+    let result = engine.run_bytecode_with_result(&code);
+    assert!(result.panic.is_none());
+    result.gc_stats
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_gc_soak() {
+    let stats = run_gc_soak(std::time::Duration::from_millis(1));
+    assert!(
+        stats.cycles > 10 && stats.slices > stats.cycles,
+        "{:?}",
+        stats
+    );
+    // the work of a slice is bounded whatever the clock says
+    assert!(
+        stats.max_slice_objects > 0 && stats.max_slice_objects <= go_vm::SLICE_OBJECTS,
+        "{:?}",
+        stats
+    );
+    // 6 objects in cycles per iteration: the nodes, the map and its interface,
+    // the closure and its up value
+    assert!(stats.freed >= 600000, "{:?}", stats);
+    // collected as the loop goes, it makes 400000 tracked objects in total
+    assert!(stats.max_tracked < 100000, "{:?}", stats);
+}
+
+/// Timing sensitive, a busy machine may stop a slice for longer than the budget.
+/// Meant to be run in release mode: `cargo test --release -- --ignored test_gc_max_pause`
+#[test]
+#[ignore]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_gc_max_pause() {
+    let budget = std::time::Duration::from_millis(1);
+    let stats = run_gc_soak(budget);
+    assert!(stats.max_pause <= budget, "{:?}", stats);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_large_operands() {
+    // 40k locals fit in the operands
+    let mut source = String::from("package main\n\nfunc main() {\n    v0 := 0\n");
+    for i in 1..40000 {
+        writeln!(source, "    v{} := v{} + 1", i, i - 1).unwrap();
+    }
+    source.push_str("    assert(v39999 == 39999 && v0 == 0)\n}\n");
+    assert!(run_string(Cow::Owned(source), false).is_ok());
+
+    // a 300-argument call
+    let params: Vec<String> = (0..300).map(|i| format!("a{} int", i)).collect();
+    let elems: Vec<String> = (0..300).map(|i| format!("a{}", i)).collect();
+    let args: Vec<String> = (0..300).map(|i| i.to_string()).collect();
+    let source = format!(
+        r#"package main
+
+func f({}) int {{
+    sum := 0
+    for i, a := range []int{{{}}} {{
+        assert(a == i)
+        sum += a
+    }}
+    return sum
+}}
+
+func main() {{
+    assert(f({}) == 44850)
+}}
+"#,
+        params.join(", "),
+        elems.join(", "),
+        args.join(", ")
+    );
+    assert!(run_string(Cow::Owned(source), false).is_ok());
+
+    // the loop jumps back, and the break forward, over more than 32767 instructions
+    let mut source =
+        String::from("package main\n\nfunc main() {\n    x := 1\n    for i := 0; i < 3; i++ {\n");
+    source.push_str("        if i == 5 {\n            break\n        }\n");
+    let mut x: i64 = 1;
+    for _ in 0..3 {
+        for i in 0..10000 {
+            x = (x * 3 + i % 7) % 1000003;
+        }
+    }
+    for i in 0..10000 {
+        writeln!(source, "        x = (x*3 + {}) % 1000003", i % 7).unwrap();
+    }
+    write!(source, "    }}\n    assert(x == {})\n}}\n", x).unwrap();
+    assert!(run_string(Cow::Owned(source), false).is_ok());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_large_function() {
+    let compile = |stmts: usize| {
+        let (source, result) = common::large_function(stmts, stmts / 10);
+        let source = source.replace("    big(1)", &format!("    assert(big(1) == {})", result));
+        let (sr, path) =
+            engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Owned(source));
+        let engine = engine::Engine::new();
+        let start = std::time::Instant::now();
+        let code = engine.compile(&sr, &path, true, false, false).unwrap();
+        let elapsed = start.elapsed();
+        assert!(engine.run_bytecode(&code).is_none());
+        let big = code
+            .objects
+            .functions
+            .vec()
+            .iter()
+            .max_by_key(|f| f.code.len())
+            .unwrap();
+        (elapsed, big.code.len(), big.pos.len())
+    };
+    // the best of two, to be less at the mercy of the machine
+    let best = |stmts: usize| {
+        let (t0, len, pos_len) = compile(stmts);
+        let (t1, _, _) = compile(stmts);
+        (t0.min(t1), len, pos_len)
+    };
+    let (small, small_len, _) = best(25000);
+    let (large, large_len, pos_len) = best(100000);
+
+    // the code, and the table of the positions, grow with the statements
+    assert_eq!(pos_len, large_len);
+    assert!(large_len < 100000 * 4, "{} instructions", large_len);
+    assert!(large_len < small_len * 5, "{} and {}", small_len, large_len);
+    // 4 times the statements, quadratic would be 16 times the time
+    assert!(
+        large < small * 10,
+        "{:?} for 25k statements, {:?} for 100k",
+        small,
+        large
+    );
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "stack_check"))]
+fn test_stack_check() {
+    use go_vm::types::{FunctionKey, Opcode, ValueType};
+    let source = r#"package main
+
+func main() {
+    a := 1
+    b := a + 2
+    assert(b == 3)
+}
+"#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let engine = engine::Engine::new();
+    let mut code = engine.compile(&sr, &path, true, false, false).unwrap();
+    assert!(engine.run_bytecode(&code).is_none());
+
+    // break the addition the way a codegen bug would, it adds floats now
+    let (func, pc) = code
+        .objects
+        .functions
+        .vec()
+        .iter()
+        .enumerate()
+        .find_map(|(i, f)| {
+            let pc = f
+                .code
+                .iter()
+                .position(|inst| inst.op0 == Opcode::ADD && inst.t0 == ValueType::Int)?;
+            Some((i, pc))
+        })
+        .unwrap();
+    let func = FunctionKey::from(func);
+    code.objects.functions[func].code[pc].t0 = ValueType::Float64;
+    let panic = engine.run_bytecode(&code).unwrap();
+    assert_eq!(panic.kind, go_vm::PanicKind::StackCheck);
+    let msg = panic.msg.to_string();
+    let expected = format!(
+        "stack type mismatch in function {:?} at pc {} (vfs_map/temp_file.gos:5:10): \
+         ADD Float64/Void: s0 is Int, expected Float64, last written at pc {} \
+         (vfs_map/temp_file.gos:4:10); s1 is Int, expected Float64, a constant",
+        func,
+        pc,
+        pc - 1,
+    );
+    assert_eq!(msg, expected);
+    // the backtrace starts at the addition
+    let info = engine::PanicInfo::new(&panic, &code);
+    let top = info.backtrace[0].as_ref().unwrap();
+    assert_eq!((top.line, top.column), (5, 10));
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "emit_check"))]
+fn test_emit_check_corpus() {
+    // every instruction of the corpus is emitted with operands of its types
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let engine = engine::Engine::new();
+    let mut paths: Vec<PathBuf> = std::fs::read_dir("./tests/group1")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == "gos"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        if let Err(el) = engine.compile(&sr, &path, false, false, false) {
+            assert!(
+                !el.to_string().contains("internal compiler error"),
+                "{}: {}",
+                path.display(),
+                el
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_fuzz_corpus() {
+    // the programs compile, or fail with a diagnostic, the compiler doesn't panic on them
+    let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let engine = engine::Engine::new();
+    let mut paths: Vec<PathBuf> = std::fs::read_dir("./tests/fuzz")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == "gos"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        if let Err(el) = engine.compile(&sr, &path, false, false, false) {
+            assert!(
+                !el.to_string().contains("internal compiler error"),
+                "{}: {}",
+                path.display(),
+                el
+            );
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_codegen_diagnostics() {
+    // the constructs codegen can't compile are reported at their positions
+    let cases = [
+        (
+            "package main\n\nfunc main() {\n\tfor range make(chan int) {\n\t}\n}\n",
+            ":4:2: range over a channel is not supported",
+        ),
+        (
+            "package main\n\ntype T struct{}\n\nfunc (T) M() {}\n\nfunc main() {\n\tf := T.M\n\t_ = f\n}\n",
+            ":8:7: a method expression is not supported",
+        ),
+        (
+            "package main\n\nfunc main() {\n\ts := []byte{2, 1 << 31: 1}\n\t_ = s\n}\n",
+            ":4:17: an index over 2147483647 in a literal is not supported",
+        ),
+    ];
+    for (source, expected) in cases {
+        let (result, _) = run_string_with_config(source, engine::Config::default());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains(expected), "{}", err);
+    }
+}
+
+#[test]
+#[cfg(all(feature = "go_std", feature = "async"))]
+fn test_host_events() {
+    use engine::{BackPressure, HostEvent};
+
+    let compile = |engine: &mut engine::Engine, source: &'static str| {
+        let (sr, path) = engine::SourceReader::fs_lib_and_string(
+            PathBuf::from("../std/"),
+            Cow::Borrowed(source),
+        );
+        engine.compile(&sr, &path, true, false, false).unwrap()
+    };
+
+    let source = r#"
+    package main
+
+    import "host"
+
+    func main() {
+        done := make(chan int)
+        go func() {
+            count, sum := 0, 0
+            ticks := host.Subscribe("ticks")
+            for {
+                e, ok := <-ticks
+                if !ok {
+                    break
+                }
+                assert(e.Name == "tick" && e.Time > 0)
+                sum += e.Payload.(int)
+                count++
+            }
+            assert(sum == 4950)
+            done <- count
+        }()
+        assert(<-done == 100)
+    }
+    "#;
+    let mut engine = engine::Engine::new();
+    let sender = engine.event_channel("ticks", 8);
+    let code = compile(&mut engine, source);
+    let host = std::thread::spawn(move || {
+        for i in 0..100 {
+            sender.send(HostEvent::new("tick", i as i64));
+        }
+        sender.dropped()
+    });
+    let result = engine.run_bytecode_with_result(&code);
+    assert!(result.panic.is_none());
+    assert!(result.leaked_goroutines.is_empty());
+    assert_eq!(host.join().unwrap(), 0);
+
+    // the script only gets to the events once the host has sent them all
+    let source = r#"
+    package main
+
+    import "host"
+
+    func main() {
+        events := host.Subscribe("config")
+        assert(len(events) == 4 && cap(events) == 4)
+        names := ""
+        for e, ok := <-events; ok; e, ok = <-events {
+            names += e.Name + e.Payload.(string)
+        }
+        assert(names == "v96v97v98v99")
+    }
+    "#;
+    let mut engine = engine::Engine::new();
+    let sender = engine.event_channel_with_policy("config", 4, BackPressure::DropOldest);
+    let code = compile(&mut engine, source);
+    for i in 0..100 {
+        sender.send(HostEvent::new("v", i.to_string()));
+    }
+    assert_eq!(sender.dropped(), 96);
+    drop(sender);
+    assert!(engine.run_bytecode(&code).is_none());
+
+    let source = r#"
+    package main
+
+    import "host"
+
+    func main() {
+        host.Subscribe("nothing")
+    }
+    "#;
+    let mut engine = engine::Engine::new();
+    let code = compile(&mut engine, source);
+    assert!(engine.run_bytecode(&code).is_some());
+}
+
+/// A server on another thread, talked to over a socket pair
+#[cfg(all(feature = "server", unix))]
+struct Loopback {
+    conn: std::os::unix::net::UnixStream,
+    server: std::thread::JoinHandle<io::Result<()>>,
+}
+
+#[cfg(all(feature = "server", unix))]
+impl Loopback {
+    fn start(max_frame_len: usize) -> Loopback {
+        let (conn, theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut config = engine::server::ServerConfig::new(PathBuf::from("../std/"));
+            config.max_frame_len = max_frame_len;
+            let mut server = engine::server::Server::new(engine::Engine::new(), config);
+            server.serve(theirs.try_clone()?, theirs)
+        });
+        Loopback { conn, server }
+    }
+
+    fn send(&mut self, request: &str) {
+        engine::server::write_frame(&mut self.conn, request.as_bytes()).unwrap();
+    }
+
+    fn recv(&mut self) -> String {
+        let frame = engine::server::read_frame(&mut self.conn, usize::MAX).unwrap();
+        String::from_utf8(frame.expect("the server hung up")).unwrap()
+    }
+
+    /// Closes the requests and waits for the server to answer them.
+    fn finish(self) {
+        self.conn.shutdown(std::net::Shutdown::Write).unwrap();
+        self.server.join().unwrap().unwrap();
+    }
+}
+
+#[cfg(all(feature = "server", unix))]
+const SERVER_SOURCE: &str = r#"{
+    "main.gos": "package main\n\nvar calls int\n\nfunc main() {}\n\nfunc Add(a, b int) int {\n\tcalls++\n\treturn a + b\n}\n\nfunc Calls() int { return calls }\n\nfunc Fail(msg string) {\n\tpanic(msg)\n}\n\nfunc Spin() {\n\tfor {\n\t}\n}\n"
+}"#;
+
+#[cfg(all(feature = "server", unix))]
+#[test]
+fn test_server_compile_call() {
+    let mut lb = Loopback::start(engine::server::DEFAULT_MAX_FRAME_LEN);
+    lb.send(&format!(
+        r#"{{"v":1,"id":1,"type":"compile","sources":{}}}"#,
+        SERVER_SOURCE
+    ));
+    let resp = lb.recv();
+    assert!(resp.starts_with(r#"{"v":1,"id":1,"type":"compiled","program":1,"stats":"#));
+
+    lb.send(r#"{"v":1,"id":2,"type":"call","program":1,"func":"Add","args":[1,2]}"#);
+    assert!(lb
+        .recv()
+        .starts_with(r#"{"v":1,"id":2,"type":"result","results":[3],"stats":{"elapsed_us":"#));
+    // the package vars are kept between the calls
+    lb.send(r#"{"v":1,"id":3,"type":"call","program":1,"func":"Add","args":[3,4]}"#);
+    assert!(lb.recv().contains(r#""results":[7]"#));
+    lb.send(r#"{"v":1,"id":4,"type":"call","program":1,"func":"Calls"}"#);
+    assert!(lb.recv().contains(r#""results":[2]"#));
+
+    lb.send(r#"{"v":1,"id":5,"type":"call","program":1,"func":"Fail","args":["boom"]}"#);
+    let resp = lb.recv();
+    assert!(resp.starts_with(r#"{"v":1,"id":5,"type":"panic","message":"boom","goroutine":0"#));
+    assert!(resp.contains(r#""backtrace":["vfs_map/main.gos:15:2""#));
+
+    lb.send(r#"{"v":1,"id":6,"type":"call","program":1,"func":"Add","args":[1]}"#);
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":6,"type":"error","message":"wrong number of arguments: want 2, got 1"}"#
+    );
+    lb.send(r#"{"v":1,"id":7,"type":"compile","sources":{"main.gos":"package main\n\nfunc main() {\n\tx := 1\n}\n"}}"#);
+    let resp = lb.recv();
+    assert!(resp.starts_with(r#"{"v":1,"id":7,"type":"diagnostics","diagnostics":[{"pos":"vfs_map/main.gos:4:2","message":"#));
+    lb.send(r#"{"v":1,"id":8,"type":"drop","program":1}"#);
+    assert_eq!(lb.recv(), r#"{"v":1,"id":8,"type":"ok"}"#);
+    lb.send(r#"{"v":1,"id":9,"type":"call","program":1,"func":"Calls"}"#);
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":9,"type":"error","message":"no such program"}"#
+    );
+    lb.finish();
+}
+
+#[cfg(all(feature = "server", unix))]
+#[test]
+fn test_server_malformed_frames() {
+    let mut lb = Loopback::start(1024);
+    lb.send("not json");
+    assert!(lb
+        .recv()
+        .starts_with(r#"{"v":1,"id":null,"type":"error","message":"#));
+    lb.send(r#"{"v":2,"id":1,"type":"drop","program":1}"#);
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":1,"type":"error","message":"unsupported protocol version"}"#
+    );
+    lb.send(r#"{"v":1,"id":2,"type":"call","func":"Add"}"#);
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":2,"type":"error","message":"missing program"}"#
+    );
+    // too large frames are skipped, the ones after them are still served
+    lb.send(&" ".repeat(2000));
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":null,"type":"error","message":"frame too large: 2000 bytes, the limit is 1024"}"#
+    );
+    lb.send(r#"{"v":1,"id":3,"type":"drop","program":1}"#);
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":3,"type":"error","message":"no such program"}"#
+    );
+    // the stream ends in the middle of a frame
+    lb.conn.write_all(&100u32.to_be_bytes()).unwrap();
+    lb.conn.write_all(b"{\"v\":1").unwrap();
+    lb.conn.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(
+        lb.recv(),
+        r#"{"v":1,"id":null,"type":"error","message":"truncated frame: want 100 bytes, got 6"}"#
+    );
+    lb.server.join().unwrap().unwrap();
+}
+
+#[cfg(all(feature = "server", unix))]
+#[test]
+fn test_server_interrupt() {
+    let mut lb = Loopback::start(engine::server::DEFAULT_MAX_FRAME_LEN);
+    lb.send(&format!(
+        r#"{{"v":1,"id":1,"type":"compile","sources":{}}}"#,
+        SERVER_SOURCE
+    ));
+    assert!(lb.recv().contains(r#""type":"compiled""#));
+
+    // interrupted while running
+    lb.send(r#"{"v":1,"id":2,"type":"call","program":1,"func":"Spin"}"#);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    lb.send(r#"{"v":1,"id":3,"type":"interrupt","target":2}"#);
+    assert_eq!(lb.recv(), r#"{"v":1,"id":3,"type":"ok"}"#);
+    let resp = lb.recv();
+    assert!(resp.starts_with(r#"{"v":1,"id":2,"type":"interrupted","reason":"request""#));
+
+    // the next calls are not affected
+    lb.send(r#"{"v":1,"id":4,"type":"call","program":1,"func":"Add","args":[1,2]}"#);
+    assert!(lb.recv().contains(r#""results":[3]"#));
+
+    // interrupted before it starts, behind a call that times out
+    lb.send(
+        r#"{"v":1,"id":5,"type":"call","program":1,"func":"Spin","limits":{"timeout_ms":200}}"#,
+    );
+    lb.send(r#"{"v":1,"id":6,"type":"call","program":1,"func":"Add","args":[1,2]}"#);
+    lb.send(r#"{"v":1,"id":7,"type":"interrupt","target":6}"#);
+    assert_eq!(lb.recv(), r#"{"v":1,"id":7,"type":"ok"}"#);
+    assert!(lb.recv().starts_with(
+        r#"{"v":1,"id":5,"type":"interrupted","reason":"timeout","backtrace":["vfs_map/main.gos:"#
+    ));
+    assert!(lb
+        .recv()
+        .starts_with(r#"{"v":1,"id":6,"type":"interrupted","reason":"request","backtrace":[]"#));
+    lb.finish();
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_call_json() {
+    let source = r#"
+    package main
+
+    import "errors"
+
+    type Config struct {
+        Name    string `json:"name"`
+        Port    int
+        Debug   bool           `json:",omitempty"`
+        Limits  map[string]int `json:"limits,omitempty"`
+        Secret  string         `json:"-"`
+        private int
+    }
+
+    var calls int
+
+    func Describe(c Config, tags []string, n int) *Config {
+        calls++
+        c.Name = c.Name + ":" + tags[n]
+        c.Port += len(tags)
+        c.Secret = "hidden"
+        return &c
+    }
+
+    func Divide(a, b int) (int, int, error) {
+        if b == 0 {
+            return 0, 0, errors.New("division by zero")
+        }
+        return a / b, a % b, nil
+    }
+
+    func Stats(xs []float64, raw []byte) (map[string]float64, []byte, [2]bool) {
+        sum := 0.0
+        for _, x := range xs {
+            sum += x
+        }
+        return map[string]float64{"sum": sum, "count": float64(len(xs))}, raw[1:], [2]bool{true, false}
+    }
+
+    func Calls() int {
+        return calls
+    }
+
+    func Fail() {
+        panic("no way")
+    }
+
+    func main() {
+        calls = 100
+    }
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    assert_eq!(
+        engine.call_json("Calls", "[]"),
+        Err(engine::CallError::NotLoaded)
+    );
+    engine.load_source(&sr, &path).unwrap();
+
+    let result = engine.call_json(
+        "Describe",
+        r#"[{"name": "svc", "port": 80, "limits": {"a": 1}, "Unknown": 1}, ["x", "y\u00e9"], 1]"#,
+    );
+    assert_eq!(
+        result.unwrap(),
+        r#"[{"name":"svc:y\u00e9","Port":82,"limits":{"a":1}}]"#.replace("\\u00e9", "\u{e9}")
+    );
+    // the package vars are kept between the calls
+    assert_eq!(engine.call_json("Calls", "[]").unwrap(), "[101]");
+
+    let err = engine
+        .call_json("Describe", r#"[{"name": "svc"}, ["x"], "0"]"#)
+        .unwrap_err();
+    match &err {
+        engine::CallError::Argument {
+            index, expected, ..
+        } => {
+            assert_eq!(*index, 2);
+            assert_eq!(expected, "int");
+        }
+        _ => panic!("{}", err),
+    }
+    assert_eq!(
+        err.to_string(),
+        "argument 2 (int): cannot use JSON string as int"
+    );
+    let err = engine
+        .call_json("Describe", r#"[{"Port": "80"}, [], 0]"#)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "argument 0 (struct{Name string; Port int; Debug bool; Limits map[string]int; Secret string; private int}): Port: cannot use JSON string as int"
+    );
+    assert!(matches!(
+        engine.call_json("Describe", "[{}, [], 1.5]"),
+        Err(engine::CallError::Argument { index: 2, .. })
+    ));
+    assert!(matches!(
+        engine.call_json("Describe", "[{}, []]"),
+        Err(engine::CallError::ArgumentCount {
+            expected: 3,
+            got: 2
+        })
+    ));
+    assert!(matches!(
+        engine.call_json("Describe", "[{}, [], 0"),
+        Err(engine::CallError::InvalidJson(_))
+    ));
+    assert_eq!(
+        engine.call_json("Nope", "[]"),
+        Err(engine::CallError::NotFound("Nope".to_owned()))
+    );
+
+    assert_eq!(engine.call_json("Divide", "[7, 2]").unwrap(), "[3,1,null]");
+    assert_eq!(engine.call_json("Divide", "[7, 0]").unwrap(), "[0,0,{}]");
+    assert!(matches!(
+        engine.call_json("Divide", "[1e100, 1]"),
+        Err(engine::CallError::Argument { index: 0, .. })
+    ));
+    assert_eq!(
+        engine
+            .call_json("Stats", r#"[[0.5, 1e21, 2], "AQID"]"#)
+            .unwrap(),
+        r#"[{"count":3,"sum":1e+21},"AgM=",[true,false]]"#
+    );
+    assert_eq!(
+        engine.call_json("Fail", "[]"),
+        Err(engine::CallError::Panic("no way".to_owned()))
+    );
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_call_json_closure() {
+    let source = r#"
+    package main
+
+    func Sum(n int) int {
+        done := make(chan int)
+        go func() {
+            sum := 0
+            for i := 0; i < n; i++ {
+                sum += i
+            }
+            done <- sum
+        }()
+        return <-done
+    }
+
+    func worker(n int, out chan int) {
+        done := make(chan int)
+        go func() { done <- n * 2 }()
+        out <- <-done
+    }
+
+    func main() {
+        out := make(chan int)
+        go worker(21, out)
+        assert(<-out == 42)
+    }
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.load_source(&sr, &path).unwrap();
+    assert_eq!(engine.call_json("Sum", "[10]").unwrap(), "[45]");
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_call_json_numeric_policy() {
+    let source = r#"
+    package main
+
+    func Id(id int64, ratio float32) (int64, float32) {
+        return id, ratio
+    }
+
+    func main() {}
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.load_source(&sr, &path).unwrap();
+
+    // u64::MAX as an int64 id
+    let args = "[18446744073709551615, 0.5]";
+    let err = engine.call_json("Id", args).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "argument 0 (int64): number 18446744073709551615 overflows int64"
+    );
+    assert_eq!(
+        engine
+            .call_json_with_policy("Id", args, engine::NumericPolicy::Saturate)
+            .unwrap(),
+        "[9223372036854775807,0.5]"
+    );
+    engine.set_numeric_policy(engine::NumericPolicy::GoConvert);
+    assert_eq!(engine.call_json("Id", args).unwrap(), "[-1,0.5]");
+    // the per call policy overrides the engine's
+    assert!(engine
+        .call_json_with_policy("Id", args, engine::NumericPolicy::Strict)
+        .is_err());
+
+    for policy in [
+        engine::NumericPolicy::Strict,
+        engine::NumericPolicy::Saturate,
+        engine::NumericPolicy::GoConvert,
+    ] {
+        // 1e400 is an infinity, which isn't an integer under any policy
+        assert!(engine
+            .call_json_with_policy("Id", "[1e400, 0]", policy)
+            .is_err());
+    }
+    let result = engine.call_json_with_policy("Id", "[7.9, 1e39]", engine::NumericPolicy::Saturate);
+    assert_eq!(result.unwrap(), "[7,3.4028235e+38]");
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_call_json_instruction_limit() {
+    let source = r#"
+    package main
+
+    func Spin(n int) int {
+        sum := 0
+        for i := 0; i < n; i++ {
+            sum += i
+        }
+        return sum
+    }
+
+    func SpinAsync(n int) int {
+        done := make(chan int)
+        go func() { done <- Spin(n) }()
+        return <-done
+    }
+
+    func main() {}
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.set_instruction_limit(Some(100_000_000));
+    engine.load_source(&sr, &path).unwrap();
+    let untrusted = engine::CallOptions {
+        instruction_limit: Some(10_000),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        engine.call_json("Spin", "[100000]").unwrap(),
+        "[4999950000]"
+    );
+    let err = engine
+        .call_json_with_options("Spin", "[100000]", &untrusted)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("instruction limit exceeded"),
+        "{}",
+        err
+    );
+    // the goroutines started by the call share its budget
+    assert_eq!(
+        engine.call_json("SpinAsync", "[100000]").unwrap(),
+        "[4999950000]"
+    );
+    let err = engine
+        .call_json_with_options("SpinAsync", "[100000]", &untrusted)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("instruction limit exceeded"),
+        "{}",
+        err
+    );
+    // within the budget
+    assert_eq!(
+        engine
+            .call_json_with_options("Spin", "[10]", &untrusted)
+            .unwrap(),
+        "[45]"
+    );
+    // the limit of a call can't widen the engine's
+    engine.set_instruction_limit(Some(10_000));
+    let generous = engine::CallOptions {
+        instruction_limit: Some(100_000_000),
+        ..Default::default()
+    };
+    assert!(engine
+        .call_json_with_options("Spin", "[100000]", &generous)
+        .is_err());
+    engine.set_instruction_limit(None);
+    assert!(engine
+        .call_json_with_options("Spin", "[100000]", &generous)
+        .is_ok());
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_call_options() {
+    let source = r#"
+    package main
+
+    func Spin(n int) int {
+        sum := 0
+        for i := 0; i < n; i++ {
+            sum += i
+        }
+        return sum
+    }
+
+    func Fail() int {
+        panic("failed")
+    }
+
+    func main() {}
+    "#;
+    let (engine_hook, engine_seen, _) = counting_panic_hook();
+    let (call_hook, call_seen, suppress) = counting_panic_hook();
+    suppress.set(true);
+    let hooked = engine::CallOptions {
+        panic_hook: Some(call_hook),
+        ..Default::default()
+    };
+    let untrusted = engine::CallOptions {
+        instruction_limit: Some(10_000),
+        ..Default::default()
+    };
+
+    // the hook of the call replaces the one of the engine
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.set_panic_hook(Some(engine_hook));
+    engine.load_source(&sr, &path).unwrap();
+    assert!(engine.call_json("Fail", "[]").is_err());
+    assert_eq!((engine_seen.get(), call_seen.get()), (1, 0));
+    assert_eq!(
+        engine
+            .call_json_with_options("Fail", "[]", &hooked)
+            .unwrap(),
+        "[0]"
+    );
+    assert_eq!((engine_seen.get(), call_seen.get()), (1, 1));
+
+    // call_module_with_options
+    let (mut engine, modules) = compile_modules(&[("m.gos", source)], &[("m", "./m.gos")]).unwrap();
+    engine.set_instruction_limit(Some(100_000_000));
+    let mut m = engine.instantiate_module(&modules, "m").unwrap();
+    assert_eq!(
+        engine
+            .call_module(&modules, &mut m, "Spin", "[100000]")
+            .unwrap(),
+        "[4999950000]"
+    );
+    let err = engine
+        .call_module_with_options(&modules, &mut m, "Spin", "[100000]", &untrusted)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("instruction limit exceeded"),
+        "{}",
+        err
+    );
+
+    // eval_with_options
+    let snippet = "func() int { s := 0; for i := 0; i < 100000; i++ { s += i }; return s }()";
+    let r = engine.eval(&sr, snippet).unwrap();
+    assert_eq!(r.values, ["4999950000"]);
+    let err = engine
+        .eval_with_options(&sr, snippet, &untrusted)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("instruction limit exceeded"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_heap_census() {
+    let source = r#"
+    package main
+
+    type Entry struct {
+        Key  string
+        Hits []int
+    }
+
+    var cache = map[string]*Entry{}
+
+    func Cached(key string) int {
+        e, ok := cache[key]
+        if !ok {
+            e = &Entry{Key: key, Hits: []int{1}}
+            cache[key] = e
+        }
+        return len(e.Key)
+    }
+
+    func Pure(n int) int {
+        xs := make([]int, n)
+        m := map[int]*Entry{}
+        for i := range xs {
+            xs[i] = i
+            m[i] = &Entry{Hits: xs[:i]}
+        }
+        return len(xs) + len(m)
+    }
+
+    func main() {}
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    assert_eq!(engine.heap_census(), engine::HeapCensus::default());
+    engine.set_alloc_sites(true);
+    engine.load_source(&sr, &path).unwrap();
+
+    // nothing run in between
+    let before = engine.heap_census();
+    let diff = engine::HeapCensus::diff(&before, &engine.heap_census());
+    assert!(diff.is_empty(), "{}", diff);
+
+    // the temporaries of a call are gone after it
+    engine.call_json("Pure", "[100]").unwrap();
+    let diff = engine::HeapCensus::diff(&before, &engine.heap_census());
+    assert!(diff.is_empty(), "{}", diff);
+
+    // the cache keeps an entry per key, attributed to the line that makes it
+    for key in ["a", "b", "c", "a"] {
+        engine
+            .call_json("Cached", &format!("[\"{}\"]", key))
+            .unwrap();
+    }
+    let after = engine.heap_census();
+    let diff = engine::HeapCensus::diff(&before, &after);
+    assert_eq!(diff.classes["struct"].objects, 3, "{}", diff);
+    assert!(diff.classes["map"].bytes > 0, "{}", diff);
+    let line = source
+        .lines()
+        .position(|l| l.contains("e = &Entry"))
+        .unwrap()
+        + 1;
+    let growth = diff.growth();
+    let (site, delta) = growth[0];
+    assert!(site.contains(&format!(":{}:", line)), "{}", diff);
+    // the entries, the array of their Hits is not tracked by the collector
+    assert_eq!(delta.objects, 3, "{}", diff);
+    assert!(diff.to_string().contains(site));
+    assert_eq!(
+        after.classes["struct"].objects,
+        before.classes.get("struct").map_or(0, |c| c.objects) + 3
+    );
+
+    let diff = engine::HeapCensus::diff(&after, &engine.heap_census());
+    assert!(diff.is_empty(), "{}", diff);
+}
+
+#[test]
+fn test_repl_eval() {
+    let (sr, _) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(""));
+    let mut engine = engine::Engine::new();
+    let eval = |engine: &engine::Engine, snippet: &str| {
+        let r = engine.eval(&sr, snippet);
+        r.map_err(|el| el.to_string())
+    };
+
+    // no auto-import unless in REPL mode
+    let err = eval(&engine, r#"strings.ToUpper("go")"#).unwrap_err();
+    assert!(err.starts_with("repl.gos:1:1: "), "{}", err);
+    engine.set_repl_mode(true);
+    let r = eval(&engine, r#"strings.ToUpper("go")"#).unwrap();
+    assert_eq!(r.values, [r#""GO""#]);
+    assert_eq!(r.type_strings, ["string"]);
+    assert_eq!(r.auto_imports, ["strings"]);
+
+    let displayed = |snippet: &str| eval(&engine, snippet).unwrap().to_string();
+    assert_eq!(displayed("[3]int{1, 2, 3}"), "[3]int{1, 2, 3} : [3]int\n");
+    assert_eq!(
+        displayed(r#"map[string]int{"b": 2, "a": 1}"#),
+        "map[string]int{\"a\":1, \"b\":2} : map[string]int\n"
+    );
+    assert_eq!(displayed("[]string(nil)"), "[]string(nil) : []string\n");
+    assert_eq!(
+        displayed("&struct{ X int; Y []float64 }{1, []float64{0.5, 2}}"),
+        "&struct{X int; Y []float64}{X:1, Y:[]float64{0.5, 2}} : *struct{X int; Y []float64}\n"
+    );
+    assert_eq!(
+        displayed("strconv.Atoi(\"12\")"),
+        "12 : int\n<nil> : <nil>\n"
+    );
+    assert_eq!(displayed("uint8(10)"), "0xa : uint8\n");
+    assert_eq!(displayed("1e21 + 1i"), "(1e+21+1i) : complex128\n");
+    assert_eq!(displayed("nil"), "<nil> : <nil>\n");
+
+    // the output is kept apart from the values
+    let r = eval(&engine, "fmt.Println(\"hi\")\nfmt.Print(math.Sqrt(4))").unwrap();
+    assert_eq!(r.values, Vec::<String>::new());
+    assert_eq!(r.auto_imports, ["fmt", "math"]);
+    assert_eq!(r.stdout_captured, "hi\n2");
+
+    // the errors are at the lines of the snippet
+    let err = eval(&engine, "x := 1\ny := \"a\" + x").unwrap_err();
+    assert!(err.starts_with("repl.gos:2:6: "), "{}", err);
+    let err = eval(&engine, "[]int{}[1]").unwrap_err();
+    assert!(
+        err.contains("panic: index out of range [1] with length 0"),
+        "{}",
+        err
+    );
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_const_table() {
+    let source = r#"package main
+
+const (
+    KB = 1 << (10 * (iota + 1))
+    MB
+    GB
+)
+
+const MaxRetries = 1 << 3
+
+type Level int
+
+const (
+    Debug Level = iota
+    Info
+)
+
+var buf [MaxRetries * 2]byte
+
+func main() {
+    n := len(buf)
+    switch n {
+    case MaxRetries + 8:
+        println("full", n, Info)
+    }
+}
+"#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let engine = engine::Engine::new();
+    let table = engine.const_table(&sr, &path).unwrap();
+    // the names are the ones used in error messages
+    let first = table.iter().next().unwrap();
+    let file = first.pos.filename.clone();
+    assert!(file.ends_with("temp_file.gos"));
+    let at = |text: &str| {
+        let offset = source.find(text).unwrap();
+        table.at(&file, offset)
+    };
+
+    // iota constants, the implicit ones have no expression of their own
+    let mb = at("MB").unwrap();
+    assert_eq!(
+        (mb.value.as_str(), mb.kind),
+        ("1048576", engine::ConstKind::Int)
+    );
+    assert_eq!(mb.typ, "untyped int");
+    assert_eq!(at("GB").unwrap().value, "1073741824");
+    let info = at("Info").unwrap();
+    assert_eq!(info.value, "1");
+    assert!(info.typ.ends_with(".Level"));
+
+    // the innermost expression wins
+    assert_eq!(at("1 << 3").unwrap().value, "1");
+    let shift = at("<< 3").unwrap();
+    assert_eq!(shift.value, "8");
+    assert_eq!(shift.end.offset - shift.pos.offset, "1 << 3".len());
+    assert_eq!(at("3\n").unwrap().value, "3");
+    // the array bound and the case label
+    assert_eq!(at("MaxRetries * 2").unwrap().value, "8");
+    assert_eq!(at("* 2").unwrap().value, "16");
+    assert_eq!(at("+ 8").unwrap().value, "16");
+    let line = source[..source.find("+ 8").unwrap()].lines().count();
+    assert_eq!(table.at_line_col(&file, line, 21).unwrap().value, "16");
+    // len of an array is a constant too
+    assert_eq!(at("len(buf)").unwrap().value, "16");
+
+    // not constants
+    assert!(at("buf [").is_none());
+    assert!(at("n := ").is_none());
+    assert!(at("n {").is_none());
+    assert!(at("n, Info").is_none());
+    assert!(at("main()").is_none());
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_upvalue_capture() {
+    // only the variables a closure uses or whose address is taken get up-values
+    let source = r#"
+    package main
+
+    var kept []func() int
+
+    func Hot(n int) int {
+        total := 0
+        for i := 0; i < n; i++ {
+            x := i * 2
+            total += x
+        }
+        kept = append(kept, func() int { return total })
+        return total
+    }
+
+    func Shared() int {
+        var fs []func() int
+        for i := 0; i < 3; i++ {
+            fs = append(fs, func() int { return i })
+        }
+        sum := 0
+        for _, f := range fs {
+            sum += f()
+        }
+        return sum
+    }
+
+    func Counter() int {
+        inc, get := counter()
+        inc()
+        inc()
+        p := &get
+        return (*p)()
+    }
+
+    func counter() (func(), func() int) {
+        n := 0
+        return func() { n++ }, func() int { return n }
+    }
+
+    func main() {}
+    "#;
+    let (sr, path) =
+        engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Borrowed(source));
+    let mut engine = engine::Engine::new();
+    engine.load_source(&sr, &path).unwrap();
+    let before = engine.heap_census();
+    assert_eq!(engine.call_json("Hot", "[1000]").unwrap(), "[999000]");
+    let diff = engine::HeapCensus::diff(&before, &engine.heap_census());
+    // the closure kept and its one up-value, none for the loop
+    assert_eq!(diff.classes["closure"].objects, 1, "{}", diff);
+    assert_eq!(diff.classes["upvalue"].objects, 1, "{}", diff);
+
+    // the loop variable is one variable for all the iterations
+    assert_eq!(engine.call_json("Shared", "[]").unwrap(), "[9]");
+    // and the closures of a call still share a variable after it returns
+    assert_eq!(engine.call_json("Counter", "[]").unwrap(), "[2]");
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_const_folding() {
+    // the constants are folded by the checker, the code only loads them
+    let source = r#"package main
+
+type Flag uint8
+
+const (
+    Read Flag = 1 << iota
+    Write
+    Exec
+    All = Read | Write | Exec
+)
+
+const prefix = "user"
+const key = prefix + ":" + "name"
+
+func main() {
+    assert(Exec == 4)
+    assert(All == 7)
+    assert(All&^Write == 5)
+    m := map[string]int{key: 1}
+    assert(m["user:name"] == 1)
+}
+"#;
+    assert!(run_string(Cow::Borrowed(source), false).is_ok());
+
+    let source = r#"package main
+
+const (
+    Small int8 = 1 << 7
+    Ratio = 10 / 0
+)
+
+func main() {
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "4:18: 1 << 7 (untyped int constant 128) overflows int8",
+            "5:18: invalid operation: division by zero"
+        ]
+    );
+}
+
+mod checked_ffi {
+    use engine::ffi::*;
+
+    #[derive(Ffi)]
+    pub struct TextFfi;
+
+    #[ffi_impl]
+    impl TextFfi {
+        #[go_sig = "func(s string, n int) (string, int)"]
+        fn ffi_repeat(s: GosValue, n: isize) -> (GosValue, isize) {
+            let s = s.as_string().as_str().repeat(n as usize);
+            let len = s.len() as isize;
+            (FfiCtx::new_string(&s), len)
+        }
+
+        #[go_sig = "func() int"]
+        fn ffi_wrong_type() -> GosValue {
+            FfiCtx::new_string("not an int")
+        }
+
+        #[go_sig = "func(s string, b []byte) (int, string)"]
+        fn ffi_find(args: FfiArgs) -> RuntimeResult<FfiResult> {
+            let (s, b) = (args.get_str(0)?, args.get_slice_of_u8(1)?);
+            let found = (0..=b.len().saturating_sub(s.len()))
+                .find(|&i| b[i..].starts_with(s.as_bytes()))
+                .map(|i| i as isize)
+                .ok_or("not found");
+            Ok(FfiResult::new().push_result(found, 0isize.into()))
+        }
+    }
+
+    /// Declares no signatures, the arguments are only checked by the handlers
+    #[derive(Ffi)]
+    pub struct UncheckedFfi;
+
+    #[ffi_impl]
+    impl UncheckedFfi {
+        fn ffi_add(a: isize, b: isize) -> isize {
+            a + b
+        }
+
+        fn ffi_greet(args: FfiArgs) -> RuntimeResult<FfiResult> {
+            args.expect_len(2)?;
+            let name = args.get_str(0)?;
+            let times = args.get_int(1)?;
+            Ok(FfiResult::new().push(FfiCtx::new_string(
+                &format!("hi {}", name).repeat(times as usize),
+            )))
+        }
+    }
+
+    #[derive(Ffi)]
+    pub struct BadParamFfi;
+
+    #[ffi_impl]
+    impl BadParamFfi {
+        #[go_sig = "func(n int) int"]
+        fn ffi_double(n: i64) -> i64 {
+            n * 2
+        }
+    }
+
+    #[derive(Ffi)]
+    pub struct BadArityFfi;
+
+    #[ffi_impl]
+    impl BadArityFfi {
+        #[go_sig = "func(n int) (int, error)"]
+        fn ffi_half(n: isize) -> isize {
+            n / 2
+        }
+    }
+
+    /// Implemented by hand, the declared results can't be checked against it
+    /// at registration
+    pub struct ShortFfi;
+
+    impl Ffi for ShortFfi {
+        fn call(&self, _: &mut FfiCtx, _: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>> {
+            Ok(vec![1isize.into()])
+        }
+
+        #[cfg(feature = "async")]
+        fn async_call(
+            &self,
+            _: &mut FfiCtx,
+            _: Vec<GosValue>,
+        ) -> std::pin::Pin<Box<dyn futures_lite::Future<Output = RuntimeResult<Vec<GosValue>>> + '_>>
+        {
+            unimplemented!()
+        }
+
+        fn decls(&self) -> Vec<FfiFuncDecl> {
+            vec![FfiFuncDecl {
+                name: "pair",
+                signature: "func() (int, int)",
+                params: vec![],
+                results: None,
+            }]
+        }
+    }
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_ffi_decls() {
+    use checked_ffi::*;
+
+    let mut engine = engine::Engine::new();
+    let err = engine
+        .try_register_extension("bad_param", Rc::new(BadParamFfi))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ffi function 'bad_param.double': parameter 0 is Int in the signature, Int64 in the handler"
+    );
+    let err = engine
+        .try_register_extension("bad_arity", Rc::new(BadArityFfi))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ffi function 'bad_arity.half': 2 results in the signature, 1 in the handler"
+    );
+    engine
+        .try_register_extension("text", Rc::new(TextFfi))
+        .unwrap();
+    engine
+        .try_register_extension("short", Rc::new(ShortFfi))
+        .unwrap();
+    assert!(engine
+        .try_register_extension("text", Rc::new(TextFfi))
+        .is_err());
+
+    let run = |engine: &engine::Engine, body: &str| {
+        let source = format!(
+            r#"
+    package main
+
+    type text interface {{
+        repeat(s string, n int) (string, int)
+        wrong_type() int
+    }}
+
+    type short interface {{
+        pair() (int, int)
+    }}
+
+    func main() {{
+        {}
+    }}
+    "#,
+            body
+        );
+        let (sr, path) =
+            engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Owned(source));
+        let code = engine.compile(&sr, &path, true, false, false).unwrap();
+        engine
+            .run_bytecode_with_result(&code)
+            .panic
+            .map(|p| format!("{}", p.msg))
+    };
+
+    // correct handlers work as before
+    let ok = r#"
+        t := ffi(text, "text")
+        s, n := t.repeat("ab", 3)
+        assert(s == "ababab")
+        assert(n == 6)
+    "#;
+    assert_eq!(run(&engine, ok), None);
+
+    // the results are checked at call time
+    let wrong = r#"
+        t := ffi(text, "text")
+        assert(t.wrong_type() == 0)
+    "#;
+    assert_eq!(
+        run(&engine, wrong).unwrap(),
+        "function wrong_type returns Int as result 0, handler provided String"
+    );
+    let short = r#"
+        s := ffi(short, "short")
+        a, b := s.pair()
+        assert(a == b)
+    "#;
+    assert_eq!(
+        run(&engine, short).unwrap(),
+        "function pair returns 2 values, handler provided 1"
+    );
+    // the panic is recoverable and leaves the stack of the caller intact
+    let recovered = r#"
+        s := ffi(short, "short")
+        x, y := 1, "y"
+        func() {
+            defer func() {
+                assert(recover() != nil)
+            }()
+            s.pair()
+        }()
+        assert(x == 1 && y == "y")
+        t := ffi(text, "text")
+        r, n := t.repeat("ab", 2)
+        assert(r == "abab" && n == 4)
+    "#;
+    assert_eq!(run(&engine, recovered), None);
+    assert_eq!(run(&engine, ok), None);
+
+    // and the Go interface when the FFI is created
+    let mismatch = r#"
+        type text2 interface {
+            repeat(s string, n int64) (string, int)
+        }
+        t := ffi(text2, "text")
+        t.repeat("", 0)
+    "#;
+    assert_eq!(
+        run(&engine, mismatch).unwrap(),
+        "ffi function 'text.repeat': parameter 1 is Int in the signature, Int64 in the Go code"
+    );
+    let undeclared = r#"
+        type text3 interface {
+            repeat(s string, n int) (string, int)
+            reverse(s string) string
+        }
+        t := ffi(text3, "text")
+        t.reverse("")
+    "#;
+    assert_eq!(
+        run(&engine, undeclared).unwrap(),
+        "ffi function 'text.reverse': not declared by the FFI"
+    );
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_ffi_args() {
+    use checked_ffi::*;
+
+    let mut engine = engine::Engine::new();
+    engine.register_extension("text", Rc::new(TextFfi));
+    engine.register_extension("unchecked", Rc::new(UncheckedFfi));
+    let run = |body: &str| {
+        let source = format!(
+            r#"
+    package main
+
+    type text interface {{
+        find(s string, b []byte) (int, string)
+    }}
+
+    type unchecked interface {{
+        add(a int) int
+        greet(name string, times int) string
+    }}
+
+    type unchecked2 interface {{
+        greet(name int, times int) string
+    }}
+
+    func main() {{
+        {}
+    }}
+    "#,
+            body
+        );
+        let (sr, path) =
+            engine::SourceReader::fs_lib_and_string(PathBuf::from("../std/"), Cow::Owned(source));
+        let code = engine.compile(&sr, &path, true, false, false).unwrap();
+        engine
+            .run_bytecode_with_result(&code)
+            .panic
+            .map(|p| format!("{}", p.msg))
+    };
+
+    let ok = r#"
+        t := ffi(text, "text")
+        i, err := t.find("c", []byte("abc"))
+        assert(i == 2 && err == "")
+        i, err = t.find("x", []byte("abc"))
+        assert(i == 0 && err == "not found")
+        i, err = t.find("", nil)
+        assert(i == 0 && err == "")
+        u := ffi(unchecked, "unchecked")
+        assert(u.greet("go", 2) == "hi gohi go")
+    "#;
+    assert_eq!(run(ok), None);
+
+    // a wrong argument count is a panic in the Go code
+    let arity = r#"
+        u := ffi(unchecked, "unchecked")
+        u.add(1)
+    "#;
+    assert_eq!(
+        run(arity).unwrap(),
+        "ffi function 'add': takes 2 arguments, got 1"
+    );
+    let recovered = r#"
+        u := ffi(unchecked, "unchecked")
+        n := 1
+        func() {
+            defer func() {
+                assert(recover() != nil)
+                n++
+            }()
+            u.add(n)
+        }()
+        assert(n == 2)
+    "#;
+    assert_eq!(run(recovered), None);
+
+    // so is a wrong argument type read by the handler
+    let wrong_type = r#"
+        u := ffi(unchecked2, "unchecked")
+        u.greet(1, 2)
+    "#;
+    assert_eq!(
+        run(wrong_type).unwrap(),
+        "ffi function 'greet': argument 0 is Int, expected String"
+    );
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_shadowed_error() {
+    let main = r#"
+    package main
+
+    import "./shadow"
+
+    type real struct{}
+
+    func (real) Error() string { return "real" }
+
+    func main() {
+        var err error = real{}
+        assert(shadow.Describe(err) == "error real")
+        assert(shadow.Describe(shadow.Local()) == "local error local")
+    }
+    "#;
+    let shadow = r#"
+    package shadow
+
+    // the predeclared error is shadowed in this package only
+    type error struct {
+        msg string
+    }
+
+    func (e error) Msg() string { return e.msg }
+
+    func Local() interface{} { return error{"local"} }
+
+    func Describe(v interface{}) string {
+        switch e := v.(type) {
+        case error:
+            return "local error " + e.Msg()
+        case interface{ Error() string }:
+            return "error " + e.Error()
+        }
+        return "other"
+    }
+    "#;
+    let sources = [
+        (PathBuf::from("main.gos"), Cow::Borrowed(main)),
+        (PathBuf::from("shadow/shadow.go"), Cow::Borrowed(shadow)),
+    ]
+    .into_iter()
+    .collect();
+    let sr = engine::SourceReader::fs_lib_and_strings(PathBuf::from("../std/"), sources);
+    let engine = engine::Engine::new();
+    let code = engine
+        .compile(&sr, Path::new("./main.gos"), true, false, false)
+        .unwrap();
+    assert!(engine.run_bytecode(&code).is_none());
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_cross_package_consts() {
+    let main = r#"
+    package main
+
+    import "./config"
+
+    func main() {
+        var table [config.MaxItems * 2]int
+        total := 0
+        for i := 0; i < 1000; i++ {
+            total += config.MaxItems
+            total += config.Limit
+        }
+        table[0] = total
+        assert(len(table) == config.MaxItems*2)
+        assert(config.Scale*2 == 5.0)
+        assert(config.Name + "!" == "cfg!")
+    }
+    "#;
+    let config = "package config\n\nconst MaxItems = 16\nconst Scale = 2.5\nconst Name = \"cfg\"\n\nvar Limit = 3\n";
+    let compile = |max_items: &str| {
+        let config = config.replace("16", max_items);
+        let sources = [
+            (PathBuf::from("main.gos"), Cow::Borrowed(main)),
+            (PathBuf::from("config/config.go"), Cow::Owned(config)),
+        ]
+        .into_iter()
+        .collect();
+        let sr = engine::SourceReader::fs_lib_and_strings(PathBuf::from("../std/"), sources);
+        let engine = engine::Engine::new();
+        let code = engine
+            .compile(&sr, Path::new("./main.gos"), true, false, false)
+            .unwrap();
+        assert!(engine.run_bytecode(&code).is_none());
+        code
+    };
+    // the opcodes of the functions of the main package, the package constructor
+    // loads the imported packages to init them
+    let main_code = |code: &go_vm::Bytecode| {
+        let mut ops = vec![];
+        for f in code.objects.functions.iter() {
+            if f.package == code.main_pkg && f.flag != go_vm::types::FuncFlag::PkgCtor {
+                ops.extend(f.code.iter().map(|i| i.op0));
+            }
+        }
+        ops
+    };
+
+    let code = compile("16");
+    let ops = main_code(&code);
+    // only the var is loaded from the other package
+    assert_eq!(
+        ops.iter()
+            .filter(|&&op| op == go_vm::types::Opcode::LOAD_PKG)
+            .count(),
+        1
+    );
+    assert!(code
+        .consts
+        .iter()
+        .any(|c| c.typ() == go_vm::types::ValueType::Int && *c.as_int() == 16));
+    assert!(!code
+        .consts
+        .iter()
+        .any(|c| c.typ() == go_vm::types::ValueType::Int && *c.as_int() == 20));
+
+    let code = compile("20");
+    assert_eq!(main_code(&code), ops);
+    assert!(code
+        .consts
+        .iter()
+        .any(|c| c.typ() == go_vm::types::ValueType::Int && *c.as_int() == 20));
+
+    // the constants are still members of their package
+    let (_, modules) = compile_modules(
+        &[("main.gos", main), ("config/config.go", config)],
+        &[("main", "./main.gos")],
+    )
+    .unwrap();
+    assert_eq!(
+        *modules.global("./config", "MaxItems").unwrap().as_int(),
+        16
+    );
+    assert_eq!(
+        modules.global("./config", "Name").unwrap().to_string(),
+        "cfg"
+    );
+}
+
+#[cfg(feature = "go_std")]
+fn compile_modules(
+    files: &[(&str, &'static str)],
+    modules: &[(&str, &str)],
+) -> Result<(engine::Engine, engine::Modules), engine::ErrorList> {
+    let sources = files
+        .iter()
+        .map(|(p, s)| (PathBuf::from(p), Cow::Borrowed(*s)))
+        .collect();
+    let sr = engine::SourceReader::fs_lib_and_strings(PathBuf::from("../std/"), sources);
+    let engine = engine::Engine::new();
+    let modules: Vec<(&str, &Path)> = modules.iter().map(|(n, p)| (*n, Path::new(p))).collect();
+    let result = engine.compile_modules(&sr, &modules, true, false, false);
+    if let Err(el) = &result {
+        el.sort();
+        eprint!("{}", el);
+    }
+    result.map(|m| (engine, m))
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_package_errors() {
+    let compile = |files: &[(&str, &'static str)]| {
+        compile_modules(files, &[("main", "./main.gos")])
+            .err()
+            .map(|el| el.to_string())
+            .unwrap_or_default()
+    };
+    let main = "package main\nfunc main() {}\n";
+
+    // all the files of a package agree on its name
+    let err = compile(&[
+        (
+            "main.gos",
+            "package main\nimport \"./lib\"\nfunc main() { lib.F() }\n",
+        ),
+        ("lib/a.go", "package lib\nfunc F() {}\n"),
+        ("lib/b.go", "package other\nfunc G() {}\n"),
+    ]);
+    assert!(
+        err.contains("lib/b.go:1:1: found packages lib (vfs_map/lib/a.go) and other (vfs_map/lib/b.go) in ./lib"),
+        "{}",
+        err
+    );
+
+    // the entry is package main with a func main()
+    let err = compile(&[("main.gos", "package lib\nfunc main() {}\n")]);
+    assert!(
+        err.contains("main.gos:1:1: runnable program must be package main"),
+        "{}",
+        err
+    );
+    let err = compile(&[("main.gos", "package main\nfunc Main() {}\n")]);
+    assert!(
+        err.contains("main.gos:1:1: function main is undeclared in the main package"),
+        "{}",
+        err
+    );
+    let err = compile(&[("main.gos", "package main\nfunc main() int { return 0 }\n")]);
+    assert!(
+        err.contains("main.gos:2:6: function main must have no arguments and no return values"),
+        "{}",
+        err
+    );
+    let err = compile(&[("main.gos", "package main\nfunc main(n int) {}\n")]);
+    assert!(
+        err.contains("main.gos:2:6: function main must have no arguments and no return values"),
+        "{}",
+        err
+    );
+
+    // the import paths
+    for (path, msg) in [
+        ("", "empty string"),
+        ("fmt\\\\x", "invalid character: \\\\"),
+        ("fmt\u{7}", "invalid character: \\u{7}"),
+        (".", ". segment"),
+        ("..", ".. segment"),
+        ("./lib/..", ".. segment"),
+        ("lib/./x", ". segment"),
+    ] {
+        let source = format!("package main\nimport \"{}\"\nfunc main() {{}}\n", path);
+        let source: &'static str = Box::leak(source.into_boxed_str());
+        let err = compile(&[("main.gos", source), ("lib/a.go", "package lib\n")]);
+        let want = format!("main.gos:2:8: invalid import path ({})", msg);
+        assert!(err.contains(&want), "{}: {}", want, err);
+    }
+
+    // an imported package binds under its declared name, not the last element of its path
+    let files = [
+        (
+            "main.gos",
+            "package main\nimport \"./lib\"\nfunc main() { assert(util.Twice(2) == 4) }\n",
+        ),
+        (
+            "lib/a.go",
+            "package util\nfunc Twice(n int) int { return n * 2 }\n",
+        ),
+    ];
+    let (engine, modules) = compile_modules(&files, &[("main", "./main.gos")]).unwrap();
+    assert!(engine.run_module(&modules, "main").unwrap().is_none());
+    let err = compile(&[
+        (
+            "main.gos",
+            "package main\nimport \"./lib\"\nfunc main() { lib.Twice(2) }\n",
+        ),
+        (
+            "lib/a.go",
+            "package util\nfunc Twice(n int) int { return n * 2 }\n",
+        ),
+    ]);
+    assert!(err.contains("undeclared name: lib"), "{}", err);
+    assert!(compile(&[("main.gos", main)]).is_empty());
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_import_resolution() {
+    let compile = |files: &[(&str, &'static str)]| {
+        let sources = files
+            .iter()
+            .map(|(p, s)| (PathBuf::from(p), Cow::Borrowed(*s)))
+            .collect();
+        let mut sr = engine::SourceReader::fs_lib_and_strings(PathBuf::from("../std/"), sources);
+        sr.set_search_roots(vec![PathBuf::from("lib"), PathBuf::from("vendor")]);
+        let engine = engine::Engine::new();
+        let result = engine.compile(&sr, Path::new("./main.gos"), true, false, false);
+        if let Err(el) = &result {
+            el.sort();
+        }
+        result.map(|code| engine.run_bytecode(&code).is_none())
+    };
+    let errors = |files: &[(&str, &'static str)]| {
+        compile(files)
+            .err()
+            .map(|el| el.to_string())
+            .unwrap_or_default()
+    };
+
+    // a diamond, the package both sides import is compiled and initialized once,
+    // the roots are searched in order
+    let shared = "package shared\nvar Inits int\nvar Items []string\nfunc init() { Inits++ }\n";
+    let files = [
+        (
+            "main.gos",
+            r#"package main
+import (
+    "left"
+    r "right"
+    "shared"
+    "strings"
+)
+func main() {
+    left.Add()
+    r.Add()
+    assert(shared.Inits == 1)
+    assert(strings.Join(shared.Items, ",") == "left,right")
+}
+"#,
+        ),
+        (
+            "lib/left/left.go",
+            "package left\nimport \"shared\"\nfunc Add() { shared.Items = append(shared.Items, \"left\") }\n",
+        ),
+        (
+            "vendor/right/right.go",
+            "package right\nimport \"shared\"\nfunc Add() { shared.Items = append(shared.Items, \"right\") }\n",
+        ),
+        ("lib/shared/shared.go", shared),
+        ("vendor/shared/shared.go", "package shared\nvar Inits = 100\n"),
+    ];
+    assert!(compile(&files).unwrap());
+
+    // a blank import runs the package's init only
+    let files = [
+        (
+            "main.gos",
+            "package main\nimport (\n    _ \"plugin\"\n    \"registry\"\n)\nfunc main() { assert(registry.Names[0] == \"plugin\") }\n",
+        ),
+        (
+            "lib/plugin/plugin.go",
+            "package plugin\nimport \"registry\"\nfunc init() { registry.Register(\"plugin\") }\n",
+        ),
+        (
+            "lib/registry/registry.go",
+            "package registry\nvar Names []string\nfunc Register(name string) { Names = append(Names, name) }\n",
+        ),
+    ];
+    assert!(compile(&files).unwrap());
+
+    // import cycles, with the whole chain
+    let err = errors(&[
+        (
+            "main.gos",
+            "package main\nimport \"a\"\nfunc main() { a.A() }\n",
+        ),
+        (
+            "lib/a/a.go",
+            "package a\nimport \"b\"\nfunc A() { b.B() }\n",
+        ),
+        (
+            "lib/b/b.go",
+            "package b\nimport \"c\"\nfunc B() { c.C() }\n",
+        ),
+        (
+            "lib/c/c.go",
+            "package c\nimport \"a\"\nfunc C() { a.A() }\n",
+        ),
+    ]);
+    assert!(
+        err.contains("lib/c/c.go:2:8: import cycle not allowed: a -> b -> c -> a"),
+        "{}",
+        err
+    );
+    let err = errors(&[
+        (
+            "main.gos",
+            "package main\nimport \"a\"\nfunc main() { a.A() }\n",
+        ),
+        ("lib/a/a.go", "package a\nimport \"a\"\nfunc A() {}\n"),
+    ]);
+    assert!(err.contains("import cycle not allowed: a -> a"), "{}", err);
+
+    // the locations searched
+    let err = errors(&[(
+        "main.gos",
+        "package main\nimport \"nowhere/pkg\"\nfunc main() { pkg.F() }\n",
+    )]);
+    assert!(
+        err.contains("cannot find package \"nowhere/pkg\" in any of: vfs_fs/../std/nowhere/pkg (std), vfs_map/lib/nowhere/pkg, vfs_map/vendor/nowhere/pkg"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_package() {
+    let compile = |package: &engine::Package| {
+        let sr = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+        let engine = engine::Engine::new();
+        let result = engine.compile_package(&sr, package, true, false, false);
+        if let Err(el) = &result {
+            el.sort();
+        }
+        result.map(|code| (engine, code))
+    };
+    let errors = |package: &engine::Package| {
+        compile(package)
+            .err()
+            .map(|el| el.to_string())
+            .unwrap_or_default()
+    };
+
+    // a file uses the types, funcs and vars of another one, the vars are
+    // initialized in dependency order across the files
+    let package = engine::Package::new()
+        .file(
+            "main.gos",
+            r#"package main
+
+import "fmt"
+
+var total = sum(points) + offset
+
+func main() {
+    p := point{1, 2}
+    assert(p.String() == "(1, 2)")
+    assert(fmt.Sprint(p) == "(1, 2)")
+    assert(total == 13)
+    assert(order == "acb")
+}
+"#,
+        )
+        .file(
+            "point.gos",
+            r#"package main
+
+import "fmt"
+
+type point struct {
+    x, y int
+}
+
+func (p point) String() string {
+    return fmt.Sprintf("(%d, %d)", p.x, p.y)
+}
+
+var points = []point{{1, 2}, {3, 4}}
+
+var offset = record("a", 3)
+
+var order string
+
+func record(s string, n int) int {
+    order += s
+    return n
+}
+
+func sum(ps []point) int {
+    n := 0
+    for _, p := range ps {
+        n += p.x + p.y
+    }
+    return n
+}
+
+var first = record("b", 0) + second
+
+var second = record("c", 0)
+"#,
+        );
+    let (engine, code) = compile(&package).unwrap();
+    assert!(engine.run_bytecode(&code).is_none());
+
+    // redeclarations across the files, with both positions
+    let err = errors(
+        &engine::Package::new()
+            .file("a.gos", "package main\n\nfunc main() {}\n\nvar x = 1\n")
+            .file("b.gos", "package main\n\nfunc x() {}\n"),
+    );
+    assert!(
+        err.contains("b.gos:3:6: x redeclared in this block"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("a.gos:5:5: note: other declaration of x"),
+        "{}",
+        err
+    );
+
+    // initialization cycles across the files
+    let err = errors(
+        &engine::Package::new()
+            .file("a.gos", "package main\n\nvar a = b + 1\n\nfunc main() {}\n")
+            .file(
+                "b.gos",
+                "package main\n\nvar b = f()\n\nfunc f() int { return a }\n",
+            ),
+    );
+    assert!(err.contains("initialization cycle for a"), "{}", err);
+
+    // all the files have the same package clause
+    let err = errors(
+        &engine::Package::new()
+            .file("a.gos", "package main\n\nfunc main() {}\n")
+            .file("b.gos", "package other\n"),
+    );
+    assert!(
+        err.contains("b.gos:1:1: found packages main (a.gos) and other (b.gos)"),
+        "{}",
+        err
+    );
+
+    // the files that would be left out
+    let err = errors(&engine::Package::new());
+    assert!(err.contains("package has no files"), "{}", err);
+    let err = errors(
+        &engine::Package::new()
+            .file("a.gos", "package main\n\nfunc main() {}\n")
+            .file("a.gos", "package main\n")
+            .file("b.txt", "package main\n")
+            .file("b_test.go", "package main\n"),
+    );
+    assert!(err.contains("duplicate file name: a.gos"), "{}", err);
+    assert!(err.contains("not a source file name: b.txt"), "{}", err);
+    assert!(err.contains("not a source file name: b_test.go"), "{}", err);
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_modules() {
+    let registry = r#"
+    package registry
+
+    var Count int
+
+    func Register() int {
+        Count++
+        return Count
+    }
+    "#;
+    let plugin = r#"
+    package main
+
+    import "./registry"
+
+    var Mine int
+
+    func main() {
+        Mine = registry.Register()
+    }
+    "#;
+    let files = [
+        ("registry/registry.gos", registry),
+        ("a.gos", plugin),
+        ("b.gos", plugin),
+    ];
+    let count = |m: &engine::Modules| *m.global("./registry", "Count").unwrap().as_int();
+
+    let (engine, modules) = compile_modules(&files, &[("a", "./a.gos"), ("b", "./b.gos")]).unwrap();
+    assert!(engine.run_module(&modules, "a").unwrap().is_none());
+    assert!(engine.run_module(&modules, "b").unwrap().is_none());
+    assert!(engine.run_module(&modules, "a").unwrap().is_none());
+    assert_eq!(count(&modules), 3);
+    assert!(engine.run_module(&modules, "c").is_err());
+
+    let (engine, mut modules) =
+        compile_modules(&files, &[("a", "./a.gos"), ("b", "./b.gos")]).unwrap();
+    modules
+        .set_globals_policy("./registry", engine::GlobalsPolicy::PerInstance)
+        .unwrap();
+    assert!(engine.run_module(&modules, "a").unwrap().is_none());
+    assert!(engine.run_module(&modules, "b").unwrap().is_none());
+    assert_eq!(count(&modules), 1);
+    assert!(modules
+        .set_globals_policy("nonexistent", engine::GlobalsPolicy::Shared)
+        .is_err());
+
+    assert!(compile_modules(&files, &[("a", "./a.gos"), ("a", "./b.gos")]).is_err());
+    assert!(compile_modules(&files, &[("a", "./a.gos"), ("b", "./a.gos")]).is_err());
+
+    // the globals of a module can't be declared or imported by another one
+    let main = "package main\n\nvar Got int\n\nfunc main() {}\n";
+    let other = "package main\n\nvar Other int\n";
+    let imports = "package main\n\nimport a \"./a\"\n\nfunc main() { a.Got++ }\n";
+    let files = [("p/a.gos", main), ("p/b.gos", other)];
+    let err = compile_modules(&files, &[("a", "./p/a.gos"), ("b", "./p")]).err();
+    assert!(err
+        .unwrap()
+        .to_string()
+        .contains("is in both entries ./p/a.gos and ./p"));
+    let files = [("a/main.gos", main), ("b.gos", imports)];
+    let err = compile_modules(&files, &[("a", "./a"), ("b", "./b.gos")]).err();
+    assert!(err
+        .unwrap()
+        .to_string()
+        .contains("entry ./b.gos imports the package of entry ./a"));
+}
+
+#[test]
+#[cfg(feature = "go_std")]
+fn test_module_instances() {
+    let registry = r#"
+    package registry
+
+    var Count int
+
+    func Register() int {
+        Count++
+        return Count
+    }
+    "#;
+    let plugin = r#"
+    package main
+
+    import "./registry"
+
+    var Mine int
+
+    func main() {
+        Mine = registry.Register()
+    }
+
+    func Get() int {
+        return Mine
+    }
+
+    func Bump(n int) int {
+        Mine += n
+        return Mine
+    }
+
+    func Register() int {
+        return registry.Register()
+    }
+    "#;
+    let files = [
+        ("registry/registry.gos", registry),
+        ("a.gos", plugin),
+        ("b.gos", plugin),
+    ];
 
-    let mut cfg = engine::Config::default();
-    cfg.trace_parser = trace;
-    cfg.trace_checker = trace;
-    let (sr, path) = engine::SourceReader::zip_lib_and_string(
-        std::borrow::Cow::Owned(zip),
-        PathBuf::from("std/"),
-        source,
+    let (engine, modules) = compile_modules(&files, &[("a", "./a.gos"), ("b", "./b.gos")]).unwrap();
+    let mut a1 = engine.instantiate_module(&modules, "a").unwrap();
+    let mut b1 = engine.instantiate_module(&modules, "b").unwrap();
+    let mut a2 = engine.instantiate_module(&modules, "a").unwrap();
+    assert_eq!(a1.module(), "a");
+    let call = |inst: &mut engine::ModuleInstance, func: &str, args: &str| {
+        engine.call_module(&modules, inst, func, args)
+    };
+    assert_eq!(call(&mut a1, "Get", "[]").unwrap(), "[1]");
+    assert_eq!(call(&mut b1, "Get", "[]").unwrap(), "[2]");
+    assert_eq!(call(&mut a2, "Get", "[]").unwrap(), "[3]");
+    assert_eq!(call(&mut a1, "Bump", "[10]").unwrap(), "[11]");
+    assert_eq!(call(&mut a2, "Get", "[]").unwrap(), "[3]");
+    assert_eq!(call(&mut a1, "Get", "[]").unwrap(), "[11]");
+    let mine =
+        |inst: &engine::ModuleInstance| *inst.global(&modules, "./a.gos", "Mine").unwrap().as_int();
+    assert_eq!((mine(&a1), mine(&a2)), (11, 3));
+    // the shared registry counts on for all the instances
+    assert_eq!(call(&mut b1, "Register", "[]").unwrap(), "[4]");
+    assert_eq!(
+        call(&mut b1, "Missing", "[]"),
+        Err(engine::CallError::NotFound("Missing".to_owned()))
     );
-    let ph: Option<Rc<dyn Fn(String, String)>> =
-        Some(Rc::new(move |msg: String, stack: String| {
-            eprintln!("{}\n", msg);
-            eprintln!("{}\n", stack);
-            panic!("test panicked");
-        }));
-    let result = engine::run(cfg, &sr, &path, ph);
-    if let Err(el) = &result {
-        el.sort();
-        eprint!("{}", el);
-    }
-    result
+
+    let (engine, mut modules) =
+        compile_modules(&files, &[("a", "./a.gos"), ("b", "./b.gos")]).unwrap();
+    modules
+        .set_globals_policy("./registry", engine::GlobalsPolicy::PerInstance)
+        .unwrap();
+    let mut a1 = engine.instantiate_module(&modules, "a").unwrap();
+    let mut b1 = engine.instantiate_module(&modules, "b").unwrap();
+    let call = |inst: &mut engine::ModuleInstance, func: &str| {
+        engine.call_module(&modules, inst, func, "[]").unwrap()
+    };
+    assert_eq!(call(&mut a1, "Register"), "[2]");
+    assert_eq!(call(&mut a1, "Register"), "[3]");
+    assert_eq!(call(&mut b1, "Register"), "[2]");
+    assert_eq!(call(&mut a1, "Get"), "[1]");
+    assert!(engine.instantiate_module(&modules, "c").is_err());
 }
 
 #[test]
-fn test_source() {
-    let source = r#"
-    package main
+#[cfg(feature = "go_std")]
+fn test_fork() {
+    let state = r#"
+    package state
+
     import (
-        "fmt"
+        "sync"
+        "unsafe"
     )
-    func main() {
-        fmt.Println("hello from raw source")
+
+    type Node struct {
+        Next *Node
+        V    int
+    }
+
+    var base = []int{1, 2, 3}
+    var a = base[0:2]
+    var b = base[1:3]
+    var m = map[string]int{}
+    var m2 = m
+    var n = &Node{V: 1}
+    var x = 0
+    var p = unsafe.Pointer(&x)
+    var mu sync.Mutex
+
+    var Last int
+    var Same bool
+
+    func init() {
+        n.Next = n
+    }
+
+    func Bump(k int) {
+        mu.Lock()
+        defer mu.Unlock()
+        a[1] += k
+        m["k"] += k
+        n.Next.V += k
+        Last = b[0]
+        Same = m2["k"] == m["k"] && n.Next == n && n.V == Last-1 && p != nil
     }
     "#;
-    let result = run_string(Cow::Borrowed(source), false);
-    dbg!(&result);
-    assert!(result.is_ok());
+    let files = [
+        ("state/state.gos", state),
+        (
+            "one.gos",
+            "package main\nimport \"./state\"\nfunc main() {\n    state.Bump(1)\n}\n",
+        ),
+        (
+            "ten.gos",
+            "package main\nimport \"./state\"\nfunc main() {\n    state.Bump(10)\n}\n",
+        ),
+    ];
+    let last = |m: &engine::Modules| *m.global("./state", "Last").unwrap().as_int();
+    let same = |m: &engine::Modules| *m.global("./state", "Same").unwrap().as_bool();
+
+    let (engine, modules) =
+        compile_modules(&files, &[("one", "./one.gos"), ("ten", "./ten.gos")]).unwrap();
+    assert!(engine.run_module(&modules, "one").unwrap().is_none());
+    assert_eq!(last(&modules), 3);
+
+    let forked = modules.fork().unwrap();
+    assert!(engine.run_module(&forked, "ten").unwrap().is_none());
+    assert!(engine.run_module(&modules, "one").unwrap().is_none());
+    assert_eq!(last(&forked), 13);
+    assert!(same(&forked));
+    assert_eq!(last(&modules), 4);
+    assert!(same(&modules));
+
+    let (engine, modules) = compile_modules(
+        &[
+            (
+                "state/state.gos",
+                "package state\nimport \"reflect\"\nvar T = reflect.TypeOf(1)\n",
+            ),
+            (
+                "main.gos",
+                "package main\nimport \"./state\"\nfunc main() {\n    _ = state.T\n}\n",
+            ),
+        ],
+        &[("main", "./main.gos")],
+    )
+    .unwrap();
+    assert!(engine.run_module(&modules, "main").unwrap().is_none());
+    let err = modules.fork().err().unwrap();
+    assert!(err.contains("StdType"), "{}", err);
 }
 
 #[test]
@@ -258,6 +3238,12 @@ fn test_local() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_init_order() {
+    let result = run("./tests/group2/init_order.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_g1case1() {
     let result = run("./tests/group1/case1.gos", true);
@@ -288,6 +3274,12 @@ fn test_closure4() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_closure5() {
+    let result = run("./tests/group1/closure5.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_func1() {
     let result = run("./tests/group1/func1.gos", true);
@@ -318,6 +3310,70 @@ fn test_map1() {
     assert!(result.is_ok());
 }
 
+#[test]
+#[cfg(feature = "go_std")]
+fn test_map_order_seed() {
+    let source = r#"
+    package main
+
+    import "strconv"
+
+    func main() {
+        m := map[int]bool{}
+        for i := 0; i < 32; i++ {
+            m[i] = true
+        }
+        order := ""
+        for k := range m {
+            order += strconv.Itoa(k) + ","
+        }
+        panic(order)
+    }
+    "#;
+    let order = |seed: Option<u64>| {
+        let cfg = engine::Config {
+            map_order_seed: seed,
+            ..Default::default()
+        };
+        let (_, reported) = run_string_with_config(source, cfg);
+        reported.unwrap().0
+    };
+    // the same seed gives the same order, different ones shuffle it differently
+    assert_eq!(order(Some(1)), order(Some(1)));
+    assert_ne!(order(Some(1)), order(Some(2)));
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_intern() {
+    let source = std::fs::read_to_string("./tests/group1/intern.gos").unwrap();
+    // the results don't depend on whether the strings are interned
+    for len in [None, Some(0), Some(usize::MAX)] {
+        let cfg = engine::Config {
+            max_interned_len: len,
+            ..Default::default()
+        };
+        let (result, panic) = run_string_with_config(&source, cfg);
+        assert!(result.is_ok(), "{:?}: {:?}", len, panic);
+    }
+}
+
+#[cfg(feature = "go_std")]
+#[test]
+fn test_tiering() {
+    let source = std::fs::read_to_string("./tests/group1/tiering.gos").unwrap();
+    // the call sites see other types after the functions are promoted
+    for threshold in [None, Some(1), Some(5)] {
+        let cfg = engine::Config {
+            tiering_threshold: threshold,
+            ..Default::default()
+        };
+        let (result, panic) = run_string_with_config(&source, cfg);
+        assert!(result.is_ok(), "{:?}: {:?}", threshold, panic);
+        assert!(panic.is_none(), "{:?}: {:?}", threshold, panic);
+    }
+}
+
 #[test]
 fn test_pkg() {
     let result = run("./tests/group1/pkg.gos", true);
@@ -366,12 +3422,119 @@ fn test_interface2() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_method_value() {
+    let result = run("./tests/group1/method_value.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_method_set() {
+    let result = run("./tests/group1/method_set.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_method_set_errors() {
+    let source = r#"package main
+
+type C struct{ n int }
+
+func (c *C) Inc() { c.n++ }
+
+type Incer interface{ Inc() }
+
+func main() {
+    var c C
+    var i Incer = c
+    C{}.Inc()
+    _ = i
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}", e.pos.line, e.pos.column))
+        .collect();
+    assert_eq!(errs, ["11:19", "12:9"]);
+    assert!(el.borrow()[0]
+        .msg
+        .contains("method Inc has pointer receiver"));
+    assert!(el.borrow()[1].msg.contains("Inc is not in method set"));
+}
+
+#[test]
+fn test_embedded() {
+    let result = run("./tests/group1/embedded.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_embedded_ambiguous() {
+    let source = r#"package main
+
+type X struct{ v int }
+
+func (X) M() {}
+
+type Y struct{ v int }
+
+func (Y) M() {}
+
+type Z struct {
+    X
+    Y
+}
+
+type W struct {
+    Z
+    v string
+}
+
+func main() {
+    var z Z
+    _ = z.v
+    var w W
+    _ = w.v
+    w.M()
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "23:11: ambiguous selector z.v (z.X.v and z.Y.v)",
+            "26:7: ambiguous selector w.M (w.Z.X.M and w.Z.Y.M)"
+        ]
+    );
+}
+
+#[test]
+fn test_equality() {
+    let result = run("./tests/group1/equality.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_initorder() {
     let result = run("./tests/group1/initorder.gos", true);
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_evalorder() {
+    let result = run("./tests/group1/evalorder.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_switch() {
     let result = run("./tests/group1/switch.gos", true);
@@ -408,6 +3571,95 @@ fn test_conversion() {
     assert!(result.is_ok());
 }
 
+#[test]
+#[cfg(feature = "go_std")]
+fn test_type_errors() {
+    // all the errors are reported, and the program isn't run
+    let source = r#"package main
+
+import "strconv"
+
+func pair() (int, string) { return 1, "a" }
+
+func half(f float64) float64 { return f / 2 }
+
+func main() {
+    panic("ran")
+    var s string = "a"
+    n := 1
+    _ = s + n
+    var i int = 2.5
+    var b byte = 300
+    _ = half(1, 2)
+    _ = half("x")
+    _ = strconv.Itoa(half(1))
+    x, y, z := pair()
+    _, _, _, _, _ = i, b, x, y, z
+}
+
+func count() int {
+    return 1, 2
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "13:9: invalid operation: mismatched types string and int",
+            "14:17: 2.5 (untyped float constant 2.5) truncated to int",
+            "15:18: 300 (untyped int constant 300) overflows byte",
+            "16:17: too many arguments",
+            "17:14: cannot convert \"x\" (untyped string constant x) to float64",
+            "18:22: cannot use half(1) (value of type float64) as int value in argument to strconv.Itoa",
+            "19:16: assignment mismatch: 3 variables but pair returns 2 values",
+            "24:5: wrong number of return values (want 1, got 2)",
+        ]
+    );
+}
+
+#[test]
+fn test_intarith() {
+    let result = run("./tests/group1/intarith.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_strings() {
+    let result = run("./tests/group1/strings.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_string_concat_in_loop() {
+    // the time it takes is measured by the string_concat benchmark
+    let source = r#"
+package main
+
+func main() {
+    s := ""
+    for i := 0; i < 10000; i++ {
+        s += "part "
+    }
+    assert(len(s) == 50000)
+    assert(s[49995:] == "part ")
+}
+"#;
+    let result = run_string(Cow::Borrowed(source), false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_untyped() {
+    let result = run("./tests/group1/untyped.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_async() {
     let result = run("./tests/group1/async.gos", true);
@@ -420,6 +3672,18 @@ fn test_channel() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_goroutine() {
+    let result = run("./tests/group1/goroutine.gos", true);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_goroutine_reuse() {
+    let result = run("./tests/group1/goroutine_reuse.gos", true);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_defer() {
     let result = run("./tests/group1/defer.gos", true);
@@ -432,6 +3696,104 @@ fn test_goto() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_label() {
+    let result = run("./tests/group1/label.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_label_errors() {
+    let source = r#"package main
+
+func main() {
+    goto L
+    x := 1
+L:
+    _ = x
+unused:
+    for {
+        break nope
+    }
+    for {
+        continue L
+    }
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "4:10: goto L jumps over variable declaration at line 5",
+            "8:1: label unused defined and not used",
+            "10:15: label nope undefined",
+            "10:15: invalid break label nope",
+            "13:18: invalid continue label L",
+        ]
+    );
+}
+
+#[test]
+fn test_unused_errors() {
+    let source = r#"package main
+
+import (
+    "strings"
+    str "strconv"
+    _ "sort"
+    . "math"
+    "bytes"
+)
+
+func main() {
+    var a int
+    b := 1
+    b = 2
+    c, d := 1, 2
+    _ = d
+    e := 1
+    func() {
+        _ = e + 1
+    }()
+    f := 1
+    func() {
+        f = 2
+    }()
+    var _ int
+    _, g := 1, 2
+    _ = g
+    h := []int{}
+    h[0] = 1
+    var buf bytes.Buffer
+    _ = buf
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "4:5: imported and not used: \"strings\"",
+            "5:5: imported and not used: \"strconv\" as str",
+            "12:9: declared but not used: a",
+            "13:5: declared but not used: b",
+            "15:5: declared but not used: c",
+            "21:5: declared but not used: f",
+        ]
+    );
+}
+
 #[test]
 fn test_recover() {
     let result = run("./tests/group1/recover.gos", true);
@@ -478,6 +3840,24 @@ fn test_fibonacci() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_calls() {
+    time_test!();
+
+    let result = run("./tests/demo/fib_recursive.gos", false);
+    assert!(result.is_ok());
+    let result = run("./tests/demo/calls.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_fanout() {
+    time_test!();
+
+    let result = run("./tests/demo/fanout.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_issue8() {
     time_test!();
@@ -534,6 +3914,22 @@ fn test_std_time() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_std_errors() {
+    time_test!();
+
+    let result = run("./tests/std/errors.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_std_sync() {
+    time_test!();
+
+    let result = run("./tests/std/sync.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_std_sort() {
     time_test!();
@@ -574,6 +3970,22 @@ fn test_std_fmt() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_std_fmt_methods() {
+    time_test!();
+
+    let result = run("./tests/std/fmt_methods.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_std_os() {
+    time_test!();
+
+    let result = run("./tests/std/os.gos", false);
+    assert!(result.is_ok());
+}
+
 #[test]
 fn test_map_perf() {
     time_test!();
@@ -610,3 +4022,122 @@ fn test_std_temp() {
     let result = run("./tests/std/temp.gos", false);
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_variadic() {
+    let result = run("./tests/group1/variadic.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_variadic_errors() {
+    let source = r#"package main
+
+func f(a int, b int) {}
+func g(a int, xs ...int) {}
+
+func main() {
+    s := []int{1}
+    f(s...)
+    g(1, 2, s...)
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "8:8: cannot use ... in call to non-variadic f",
+            "9:14: can only use ... with matching parameter",
+        ]
+    );
+}
+
+#[test]
+fn test_multi_result() {
+    let result = run("./tests/group1/multi_result.gos", false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_multi_result_errors() {
+    let source = r#"package main
+
+func two() (int, string) { return 1, "a" }
+func three() (int, int, int) { return 1, 2, 3 }
+
+func main() {
+    a, b := three()
+    var c, d int
+    c, d = three()
+    x := two()
+    _, _ = 1, 2, 3
+    m := map[int]int{}
+    p, q, r := m[1]
+}
+"#;
+    let el = run_string(Cow::Borrowed(source), false).unwrap_err();
+    el.sort();
+    let errs: Vec<String> = el
+        .borrow()
+        .iter()
+        .filter(|e| e.msg.starts_with("assignment mismatch"))
+        .map(|e| format!("{}:{}: {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            "7:13: assignment mismatch: 2 variables but three returns 3 values",
+            "9:12: assignment mismatch: 2 variables but three returns 3 values",
+            "10:10: assignment mismatch: 1 variable but two returns 2 values",
+            "11:12: assignment mismatch: 2 variables but 3 values",
+            "13:16: assignment mismatch: 3 variables but 1 value",
+        ]
+    );
+}
+
+#[test]
+fn test_run_string() {
+    let engine = engine::Engine::new();
+    let reader = engine::SourceReader::local_fs(PathBuf::from("../std/"), PathBuf::from("./"));
+    let run = |source: &str| engine.run_string(&reader, source);
+
+    assert!(run("package main\n\nfunc main() {\n    assert(1+1 == 2)\n}\n").is_ok());
+
+    match run("package main\n\nfunc main() {\n    x := \n}\n") {
+        Err(engine::EngineError::Parse(el)) => {
+            let e = &el.borrow()[0];
+            assert_eq!((e.pos.filename.as_str(), e.pos.line), ("main.gos", 5));
+        }
+        other => panic!("{:?}", other),
+    }
+    match run("package main\n\nfunc main() {\n    var s string = 1\n    _ = s\n}\n") {
+        Err(engine::EngineError::Compile(el)) => assert_eq!(el.borrow()[0].pos.line, 4),
+        other => panic!("{:?}", other),
+    }
+    match run("package main\n\nfunc main() {\n    panic(\"oops\")\n}\n") {
+        Err(engine::EngineError::Panic { msg, call_stack }) => {
+            assert_eq!(msg, "oops");
+            assert!(call_stack.starts_with("main.gos:4:5"), "{}", call_stack);
+        }
+        other => panic!("{:?}", other),
+    }
+    match run("package main\n\nfunc main() {\n    ch := make(chan int)\n    ch <- 1\n}\n") {
+        Err(engine::EngineError::Goroutines(el)) => {
+            assert_eq!(el.borrow()[0].msg, "all goroutines are asleep - deadlock!")
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // the packages of the reader can be imported
+    let source = "package main\n\nimport \"strings\"\n\nfunc main() {\n    assert(strings.ToUpper(\"a\") == \"A\")\n}\n";
+    assert!(run(source).is_ok());
+    assert!(engine
+        .run_file(&reader, Path::new("./tests/group1/func1.gos"))
+        .is_ok());
+}
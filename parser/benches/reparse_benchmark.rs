@@ -0,0 +1,60 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+extern crate go_parser as fe;
+
+// A 5000 line file of 500 functions
+fn source() -> String {
+    let mut src = "package main\n\nimport \"fmt\"\n".to_owned();
+    for i in 0..500 {
+        src.push_str(&format!(
+            r#"
+func f{0}(xs []int, n int) int {{
+	total := 0
+	for i, x := range xs {{
+		if i%n == 0 {{
+			total += x * {0}
+		}}
+	}}
+	fmt.Println(total)
+	return total
+}}
+"#,
+            i
+        ));
+    }
+    src
+}
+
+// Types a char into a function body and deletes it again.
+fn type_and_undo(parsed: &mut Option<fe::ParsedFile>, at: usize) {
+    let p = parsed.take().unwrap();
+    let p = p.reparse(&[fe::TextEdit::new(at..at, "1")]);
+    *parsed = Some(p.reparse(&[fe::TextEdit::new(at..at + 1, "")]));
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let src = source();
+    c.bench_function("parse_5k_lines", |b| {
+        b.iter(|| fe::ParsedFile::parse("./main.go", &src))
+    });
+
+    // the functions after the edited one are moved
+    let first = src.find("total := 0").unwrap() + 9;
+    let mut parsed = Some(fe::ParsedFile::parse("./main.go", &src));
+    c.bench_function("reparse_first_decl", |b| {
+        b.iter(|| type_and_undo(&mut parsed, first))
+    });
+
+    let last = src.rfind("total := 0").unwrap() + 9;
+    let mut parsed = Some(fe::ParsedFile::parse("./main.go", &src));
+    c.bench_function("reparse_last_decl", |b| {
+        b.iter(|| type_and_undo(&mut parsed, last))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -13,6 +13,8 @@ use super::objects::*;
 use super::position;
 use super::scope;
 use super::token;
+#[cfg(feature = "serde_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 use std::hash::Hash;
 use std::rc::Rc;
 
@@ -103,6 +105,107 @@ pub enum Decl {
     Func(FuncDeclKey),
 }
 
+// The enums are written as the index of the variant and the node, the derive
+// macros would have the nodes bound on themselves.
+#[cfg(feature = "serde_borsh")]
+macro_rules! impl_borsh_for_node_enum {
+    ($name:ident { $($variant:ident),* }) => {
+        impl BorshSerialize for $name {
+            fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+                let mut i = 0u8;
+                $(
+                    if let $name::$variant(node) = self {
+                        i.serialize(writer)?;
+                        return node.serialize(writer);
+                    }
+                    i += 1;
+                )*
+                let _ = i;
+                unreachable!()
+            }
+        }
+
+        impl BorshDeserialize for $name {
+            fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+                let tag = u8::deserialize_reader(reader)?;
+                let mut i = 0u8;
+                $(
+                    if tag == i {
+                        return Ok($name::$variant(BorshDeserialize::deserialize_reader(reader)?));
+                    }
+                    i += 1;
+                )*
+                let _ = i;
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid {} variant {}", stringify!($name), tag),
+                ))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde_borsh")]
+impl_borsh_for_node_enum!(Expr {
+    Bad,
+    Ident,
+    Ellipsis,
+    BasicLit,
+    FuncLit,
+    CompositeLit,
+    Paren,
+    Selector,
+    Index,
+    Slice,
+    TypeAssert,
+    Call,
+    Star,
+    Unary,
+    Binary,
+    KeyValue,
+    Array,
+    Struct,
+    Func,
+    Interface,
+    Map,
+    Chan
+});
+
+#[cfg(feature = "serde_borsh")]
+impl_borsh_for_node_enum!(Stmt {
+    Bad,
+    Decl,
+    Empty,
+    Labeled,
+    Expr,
+    Send,
+    IncDec,
+    Assign,
+    Go,
+    Defer,
+    Return,
+    Branch,
+    Block,
+    If,
+    Case,
+    Switch,
+    TypeSwitch,
+    Comm,
+    Select,
+    For,
+    Range
+});
+
+#[cfg(feature = "serde_borsh")]
+impl_borsh_for_node_enum!(Spec {
+    Import,
+    Value,
+    Type
+});
+
+#[cfg(feature = "serde_borsh")]
+impl_borsh_for_node_enum!(Decl { Bad, Gen, Func });
+
 impl Expr {
     pub fn new_bad(from: position::Pos, to: position::Pos) -> Expr {
         Expr::Bad(Rc::new(BadExpr { from: from, to: to }))
@@ -213,7 +316,7 @@ impl Node for Expr {
                 Some(expr) => expr.end(objs),
                 None => e.pos + 3,
             },
-            Expr::BasicLit(e) => e.pos + e.token.get_literal().len(),
+            Expr::BasicLit(e) => e.end(),
             Expr::FuncLit(e) => e.body.end(),
             Expr::CompositeLit(e) => e.r_brace + 1,
             Expr::Paren(e) => e.r_paren + 1,
@@ -335,7 +438,11 @@ impl Node for Stmt {
             Stmt::IncDec(s) => s.token_pos + 2,
             Stmt::Assign(s) => {
                 let assign = &objs.a_stmts[*s];
-                assign.rhs[assign.rhs.len() - 1].end(objs)
+                match assign.rhs.last() {
+                    Some(e) => e.end(objs),
+                    // the rhs is missing after a syntax error
+                    None => assign.token_pos + assign.token.text().len(),
+                }
             }
             Stmt::Go(s) => s.call.end(objs),
             Stmt::Defer(s) => s.call.end(objs),
@@ -423,7 +530,7 @@ impl Node for Spec {
         match &self {
             Spec::Import(s) => match s.end_pos {
                 Some(p) => p,
-                None => s.path.pos,
+                None => s.path.end(),
             },
             Spec::Value(s) => {
                 let n = s.values.len();
@@ -461,9 +568,10 @@ impl Node for Decl {
     fn end(&self, objs: &AstObjects) -> position::Pos {
         match &self {
             Decl::Bad(d) => d.to,
-            Decl::Gen(d) => match &d.r_paren {
-                Some(p) => p + 1,
-                None => objs.specs[d.specs[0]].end(objs),
+            Decl::Gen(d) => match (&d.r_paren, d.specs.first()) {
+                (Some(p), _) => p + 1,
+                (None, Some(s)) => objs.specs[*s].end(objs),
+                (None, None) => d.token_pos + d.token.text().len(),
             },
             Decl::Func(d) => {
                 let fd = &objs.fdecls[*d];
@@ -484,6 +592,7 @@ impl Node for Decl {
     }
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug)]
 pub struct File {
     pub package: position::Pos,
@@ -492,6 +601,8 @@ pub struct File {
     pub scope: ScopeKey,
     pub imports: Vec<SpecKey>, //ImportSpec
     pub unresolved: Vec<IdentKey>,
+    pub doc: Option<CommentGroupKey>,
+    pub comments: Vec<CommentGroupKey>, // all the comments, only if collected
 }
 
 impl Node for File {
@@ -513,6 +624,119 @@ impl Node for File {
     }
 }
 
+/// The version of what `File::serialize_with` writes, to be bumped when the
+/// nodes change
+#[cfg(feature = "serde_borsh")]
+pub const AST_CACHE_VERSION: u32 = 1;
+
+#[cfg(feature = "serde_borsh")]
+impl File {
+    /// Writes the file and the arenas its nodes are in, for a cache of parsed
+    /// files. The positions are the ones of the `FileSet` it was parsed with.
+    pub fn serialize_with(&self, objs: &AstObjects) -> std::io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        AST_CACHE_VERSION.serialize(&mut buf)?;
+        env!("CARGO_PKG_VERSION").serialize(&mut buf)?;
+        self.serialize(&mut buf)?;
+        objs.serialize(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Reads what `serialize_with` wrote, a cache written by another version
+    /// of the parser is an `InvalidData` error
+    pub fn deserialize_with(mut bytes: &[u8]) -> std::io::Result<(File, AstObjects)> {
+        let reader = &mut bytes;
+        let version = u32::deserialize_reader(reader)?;
+        let crate_version = String::deserialize_reader(reader)?;
+        if version != AST_CACHE_VERSION || crate_version != env!("CARGO_PKG_VERSION") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "stale AST cache: version {} of go-parser {}",
+                    version, crate_version
+                ),
+            ));
+        }
+        let file = File::deserialize_reader(reader)?;
+        let objs = AstObjects::deserialize_reader(reader)?;
+        if !reader.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "trailing bytes after the AST",
+            ));
+        }
+        Ok((file, objs))
+    }
+}
+
+// A Comment node represents a single //-style or /*-style comment.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Debug)]
+pub struct Comment {
+    pub slash: position::Pos,
+    pub text: String, // excluding the '\n' of the //-style comments
+}
+
+impl Comment {
+    pub fn end(&self) -> position::Pos {
+        self.slash + self.text.chars().count()
+    }
+}
+
+// A CommentGroup represents a sequence of comments
+// with no other tokens and no empty lines between.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Debug)]
+pub struct CommentGroup {
+    pub list: Vec<Comment>, // len(list) > 0
+}
+
+impl CommentGroup {
+    pub fn pos(&self) -> position::Pos {
+        self.list[0].slash
+    }
+
+    pub fn end(&self) -> position::Pos {
+        self.list[self.list.len() - 1].end()
+    }
+
+    /// Returns the text of the comment.
+    /// Comment markers (//, /*, and */), the first space of a line comment, and
+    /// leading and trailing empty lines are removed. Multiple empty lines are
+    /// reduced to one, and trailing space on lines is trimmed. Unless the result
+    /// is empty, it is newline-terminated.
+    pub fn text(&self) -> String {
+        let mut lines: Vec<&str> = vec![];
+        for c in self.list.iter() {
+            // remove comment markers, the parser has given us exactly the comment
+            // text, so we don't need to check the prefix or the suffix
+            let text = match c.text.strip_prefix("//") {
+                Some(t) => t.strip_prefix(' ').unwrap_or(t),
+                None => &c.text[2..c.text.len() - 2],
+            };
+            lines.extend(text.split('\n').map(|l| l.trim_end()));
+        }
+
+        // remove the leading blank lines, and collapse the others, the trailing
+        // blank lines are gone this way too
+        let mut result = String::new();
+        let mut blank = false;
+        for line in lines {
+            if line.is_empty() {
+                blank = !result.is_empty();
+            } else {
+                if blank {
+                    result.push('\n');
+                    blank = false;
+                }
+                result.push_str(line);
+                result.push('\n');
+            }
+        }
+        result
+    }
+}
+
 // pub struct Package {
 //     name: String,
 //     scope: ScopeKey,
@@ -523,12 +747,14 @@ impl Node for File {
 // A BadExpr node is a placeholder for expressions containing
 // syntax errors for which no correct expression nodes can be
 // created.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BadExpr {
     pub from: position::Pos,
     pub to: position::Pos,
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub enum IdentEntity {
     NoEntity,
@@ -550,6 +776,7 @@ pub fn is_exported(s: &str) -> bool {
 }
 
 // An Ident node represents an identifier.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub struct Ident {
     pub pos: position::Pos,
@@ -575,7 +802,7 @@ impl Ident {
     }
 
     pub fn end(&self) -> position::Pos {
-        self.pos + self.name.len()
+        self.pos + self.name.chars().count()
     }
 
     pub fn entity_obj<'a>(&self, objs: &'a AstObjects) -> Option<&'a scope::Entity> {
@@ -596,28 +823,38 @@ impl Ident {
 
 // An Ellipsis node stands for the "..." type in a
 // parameter list or the "..." length in an array type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct Ellipsis {
     pub pos: position::Pos,
     pub elt: Option<Expr>, // ellipsis element type (parameter lists only)
 }
 
 // A BasicLit node represents a literal of basic type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BasicLit {
     pub pos: position::Pos,
     pub token: token::Token,
 }
 
+impl BasicLit {
+    pub fn end(&self) -> position::Pos {
+        self.pos + self.token.get_literal().chars().count()
+    }
+}
+
 // A FuncLit node represents a function literal.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct FuncLit {
     pub typ: FuncTypeKey,
     pub body: Rc<BlockStmt>,
 }
 
 // A CompositeLit node represents a composite literal.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct CompositeLit {
     pub typ: Option<Expr>,
     pub l_brace: position::Pos,
@@ -627,14 +864,16 @@ pub struct CompositeLit {
 }
 
 // A ParenExpr node represents a parenthesized expression.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ParenExpr {
     pub l_paren: position::Pos,
     pub expr: Expr,
     pub r_paren: position::Pos,
 }
 // A SelectorExpr node represents an expression followed by a selector.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct SelectorExpr {
     pub expr: Expr,
     pub sel: IdentKey,
@@ -647,7 +886,8 @@ impl SelectorExpr {
 }
 
 // An IndexExpr node represents an expression followed by an index.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct IndexExpr {
     pub expr: Expr,
     pub l_brack: position::Pos,
@@ -656,7 +896,8 @@ pub struct IndexExpr {
 }
 
 // An SliceExpr node represents an expression followed by slice indices.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct SliceExpr {
     pub expr: Expr,
     pub l_brack: position::Pos,
@@ -669,7 +910,8 @@ pub struct SliceExpr {
 
 // A TypeAssertExpr node represents an expression followed by a
 // type assertion.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct TypeAssertExpr {
     pub expr: Expr,
     pub l_paren: position::Pos,
@@ -678,7 +920,8 @@ pub struct TypeAssertExpr {
 }
 
 // A CallExpr node represents an expression followed by an argument list.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct CallExpr {
     pub func: Expr,
     pub l_paren: position::Pos,
@@ -695,7 +938,8 @@ impl CallExpr {
 
 // A StarExpr node represents an expression of the form "*" Expression.
 // Semantically it could be a unary "*" expression, or a pointer type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct StarExpr {
     pub star: position::Pos,
     pub expr: Expr,
@@ -703,7 +947,8 @@ pub struct StarExpr {
 
 // A UnaryExpr node represents a unary expression.
 // Unary "*" expressions are represented via StarExpr nodes.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct UnaryExpr {
     pub op_pos: position::Pos,
     pub op: token::Token,
@@ -711,7 +956,8 @@ pub struct UnaryExpr {
 }
 
 // A BinaryExpr node represents a binary expression.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BinaryExpr {
     pub expr_a: Expr,
     pub op_pos: position::Pos,
@@ -721,7 +967,8 @@ pub struct BinaryExpr {
 
 // A KeyValueExpr node represents (key : value) pairs
 // in composite literals.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct KeyValueExpr {
     pub key: Expr,
     pub colon: position::Pos,
@@ -729,7 +976,8 @@ pub struct KeyValueExpr {
 }
 
 // An ArrayType node represents an array or slice type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ArrayType {
     pub l_brack: position::Pos,
     pub len: Option<Expr>, // Ellipsis node for [...]T array types, None for slice types
@@ -737,7 +985,8 @@ pub struct ArrayType {
 }
 
 // A StructType node represents a struct type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct StructType {
     pub struct_pos: position::Pos,
     pub fields: FieldList,
@@ -747,6 +996,7 @@ pub struct StructType {
 // Pointer types are represented via StarExpr nodes.
 
 // A FuncType node represents a function type.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Clone, Debug)]
 pub struct FuncType {
     pub func: Option<position::Pos>,
@@ -791,6 +1041,7 @@ impl Node for FuncTypeKey {
 }
 
 // An InterfaceType node represents an interface type.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Clone, Debug)]
 pub struct InterfaceType {
     pub interface: position::Pos,
@@ -799,7 +1050,8 @@ pub struct InterfaceType {
 }
 
 // A MapType node represents a map type.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct MapType {
     pub map: position::Pos,
     pub key: Expr,
@@ -807,6 +1059,7 @@ pub struct MapType {
 }
 
 // A ChanType node represents a channel type.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub enum ChanDir {
     Send = 1,
@@ -814,6 +1067,7 @@ pub enum ChanDir {
     SendRecv = 3,
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Clone, Debug)]
 pub struct ChanType {
     pub begin: position::Pos,
@@ -823,31 +1077,41 @@ pub struct ChanType {
 }
 
 // An ImportSpec node represents a single package import.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ImportSpec {
+    pub doc: Option<CommentGroupKey>,
     pub name: Option<IdentKey>,
     pub path: BasicLit,
+    pub comment: Option<CommentGroupKey>,
     pub end_pos: Option<position::Pos>,
 }
 
 // A ValueSpec node represents a constant or variable declaration
 // (ConstSpec or VarSpec production).
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ValueSpec {
+    pub doc: Option<CommentGroupKey>,
     pub names: Vec<IdentKey>,
     pub typ: Option<Expr>,
     pub values: Vec<Expr>,
+    pub comment: Option<CommentGroupKey>,
 }
 
 // A TypeSpec node represents a type declaration (TypeSpec production).
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct TypeSpec {
+    pub doc: Option<CommentGroupKey>,
     pub name: IdentKey,
     pub assign: position::Pos,
     pub typ: Expr,
+    pub comment: Option<CommentGroupKey>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BadDecl {
     pub from: position::Pos,
     pub to: position::Pos,
@@ -863,8 +1127,10 @@ pub struct BadDecl {
 //	Token::CONST   ValueSpec
 //	Token::TYPE    TypeSpec
 //	Token::VAR     ValueSpec
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct GenDecl {
+    pub doc: Option<CommentGroupKey>,
     pub token_pos: position::Pos,
     pub token: token::Token,
     pub l_paran: Option<position::Pos>,
@@ -873,8 +1139,10 @@ pub struct GenDecl {
 }
 
 // A FuncDecl node represents a function declaration.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug)]
 pub struct FuncDecl {
+    pub doc: Option<CommentGroupKey>,
     pub recv: Option<FieldList>,
     pub name: IdentKey,
     pub typ: FuncTypeKey,
@@ -887,19 +1155,22 @@ impl FuncDecl {
     }
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BadStmt {
     pub from: position::Pos,
     pub to: position::Pos,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct EmptyStmt {
     pub semi: position::Pos,
     pub implicit: bool,
 }
 
 // A LabeledStmt node represents a labeled statement.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug)]
 pub struct LabeledStmt {
     pub label: IdentKey,
@@ -928,7 +1199,8 @@ impl LabeledStmt {
 }
 
 // A SendStmt node represents a send statement.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct SendStmt {
     pub chan: Expr,
     pub arrow: position::Pos,
@@ -936,7 +1208,8 @@ pub struct SendStmt {
 }
 
 // An IncDecStmt node represents an increment or decrement statement.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct IncDecStmt {
     pub expr: Expr,
     pub token_pos: position::Pos,
@@ -945,6 +1218,7 @@ pub struct IncDecStmt {
 
 // An AssignStmt node represents an assignment or
 // a short variable declaration.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug)]
 pub struct AssignStmt {
     pub lhs: Vec<Expr>,
@@ -975,18 +1249,21 @@ impl AssignStmt {
     }
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct GoStmt {
     pub go: position::Pos,
     pub call: Expr,
 }
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct DeferStmt {
     pub defer: position::Pos,
     pub call: Expr,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ReturnStmt {
     pub ret: position::Pos,
     pub results: Vec<Expr>,
@@ -994,14 +1271,16 @@ pub struct ReturnStmt {
 
 // A BranchStmt node represents a break, continue, goto,
 // or fallthrough statement.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BranchStmt {
     pub token_pos: position::Pos,
     pub token: token::Token,
     pub label: Option<IdentKey>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct BlockStmt {
     pub l_brace: position::Pos,
     pub list: Vec<Stmt>,
@@ -1026,7 +1305,8 @@ impl BlockStmt {
     }
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct IfStmt {
     pub if_pos: position::Pos,
     pub init: Option<Stmt>,
@@ -1036,7 +1316,8 @@ pub struct IfStmt {
 }
 
 // A CaseClause represents a case of an expression or type switch statement.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct CaseClause {
     pub case: position::Pos,
     pub list: Option<Vec<Expr>>,
@@ -1044,7 +1325,8 @@ pub struct CaseClause {
     pub body: Vec<Stmt>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct SwitchStmt {
     pub switch: position::Pos,
     pub init: Option<Stmt>,
@@ -1052,7 +1334,8 @@ pub struct SwitchStmt {
     pub body: Rc<BlockStmt>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct TypeSwitchStmt {
     pub switch: position::Pos,
     pub init: Option<Stmt>,
@@ -1061,7 +1344,8 @@ pub struct TypeSwitchStmt {
 }
 
 // A CommClause node represents a case of a select statement.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct CommClause {
     //communication
     pub case: position::Pos,
@@ -1070,13 +1354,15 @@ pub struct CommClause {
     pub body: Vec<Stmt>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct SelectStmt {
     pub select: position::Pos,
     pub body: Rc<BlockStmt>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct ForStmt {
     pub for_pos: position::Pos,
     pub init: Option<Stmt>,
@@ -1085,7 +1371,8 @@ pub struct ForStmt {
     pub body: Rc<BlockStmt>,
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
+#[derive(Clone, Debug)]
 pub struct RangeStmt {
     pub for_pos: position::Pos,
     pub key: Option<Expr>,
@@ -1096,11 +1383,14 @@ pub struct RangeStmt {
     pub body: Rc<BlockStmt>,
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug)]
 pub struct Field {
+    pub doc: Option<CommentGroupKey>,
     pub names: Vec<IdentKey>,
     pub typ: Expr,
     pub tag: Option<Expr>,
+    pub comment: Option<CommentGroupKey>,
 }
 
 impl Node for FieldKey {
@@ -1126,6 +1416,7 @@ impl Node for FieldKey {
     }
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Clone, Debug)]
 pub struct FieldList {
     pub openning: Option<position::Pos>,
@@ -1146,17 +1437,23 @@ impl FieldList {
         }
     }
 
+    /// The position of the opening parenthesis or brace, or of the first field,
+    /// 0 if there are neither
     pub fn pos(&self, objs: &AstObjects) -> position::Pos {
-        match self.openning {
-            Some(o) => o,
-            None => self.list[0].pos(objs),
+        match (self.openning, self.list.first()) {
+            (Some(o), _) => o,
+            (None, Some(f)) => f.pos(objs),
+            (None, None) => 0,
         }
     }
 
+    /// The position right after the closing parenthesis or brace, or after the last
+    /// field, 0 if there are neither
     pub fn end(&self, objs: &AstObjects) -> position::Pos {
-        match self.closing {
-            Some(c) => c,
-            None => self.list[self.list.len() - 1].pos(objs),
+        match (self.closing, self.list.last()) {
+            (Some(c), _) => c + 1,
+            (None, Some(f)) => f.end(objs),
+            (None, None) => 0,
         }
     }
 }
@@ -9,38 +9,68 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use super::map::Map;
 use super::position::{File, FilePos, Pos};
-use std::cell::{Ref, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::fmt;
 use std::rc::Rc;
 
+/// The error the parser adds when it gives up on a file, see `ParserConfig::max_errors`
+pub const TOO_MANY_ERRORS: &str = "too many errors";
+
+/// Another position an error is about, e.g. the other declaration of what's
+/// redeclared, with a short label. Clients of the language server get them as
+/// the related information of the diagnostic.
+#[derive(Clone, Debug)]
+pub struct Note {
+    pub pos: FilePos,
+    pub label: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct Error {
     pub pos: FilePos,
     pub msg: String,
     pub soft: bool,
     pub by_parser: bool, // reported by parser (not type checker)
-    order: usize,        // display order
+    pub notes: Vec<Note>,
 }
 
+impl Error {
+    fn sort_key(&self) -> (&str, usize, usize) {
+        (&self.pos.filename, self.pos.line, self.pos.column)
+    }
+}
+
+/// `file:line:col: msg`, with the notes on the lines after it, indented
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let p = if self.by_parser { "[Parser]" } else { "[TC]" };
-        write!(f, "{} {}  {}\n", p, self.pos, self.msg)?;
+        writeln!(f, "{}: {}", self.pos, self.msg)?;
+        for n in self.notes.iter() {
+            writeln!(f, "\t{}: note: {}", n.pos, n.label)?;
+        }
         Ok(())
     }
 }
 
 impl std::error::Error for Error {}
 
+/// The file name, the offset and the message of an error
+type ErrorKey = (Rc<String>, usize, String);
+
 #[derive(Clone, Debug)]
 pub struct ErrorList {
     errors: Rc<RefCell<Vec<Error>>>,
+    // the index of every error by its position and message, for finding the
+    // same error reported again
+    index: Rc<RefCell<Map<ErrorKey, usize>>>,
+    // the error the notes are added to
+    last: Rc<Cell<Option<usize>>>,
 }
 
+/// The errors in their current order, see `Error`'s Display
 impl fmt::Display for ErrorList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Result: {} errors\n", self.errors.borrow().len())?;
         for e in self.errors.borrow().iter() {
             e.fmt(f)?;
         }
@@ -54,43 +84,96 @@ impl ErrorList {
     pub fn new() -> ErrorList {
         ErrorList {
             errors: Rc::new(RefCell::new(vec![])),
+            index: Rc::new(RefCell::new(Map::new())),
+            last: Rc::new(Cell::new(None)),
         }
     }
 
+    /// Adds an error, unless the same message is already reported at the position,
+    /// which error recovery tends to do. A message starting with a tab is a note on
+    /// the error added before it.
     pub fn add(&self, p: Option<FilePos>, msg: String, soft: bool, by_parser: bool) {
         let fp = p.unwrap_or(FilePos::null());
-        let order = if msg.starts_with('\t') {
-            self.errors
-                .borrow()
-                .iter()
-                .rev()
-                .find(|x| !x.msg.starts_with('\t'))
-                .unwrap()
-                .pos
-                .offset
-        } else {
-            fp.offset
-        };
-        self.errors.borrow_mut().push(Error {
+        if let (Some(label), Some(_)) = (msg.strip_prefix('\t'), self.last.get()) {
+            self.add_note(fp, label.to_owned());
+            return;
+        }
+        let mut errors = self.errors.borrow_mut();
+        let key = (fp.filename.clone(), fp.offset, msg);
+        if let Some(&i) = self.index.borrow().get(&key) {
+            let e = &mut errors[i];
+            e.soft &= soft;
+            e.by_parser &= by_parser;
+            self.last.set(Some(i));
+            return;
+        }
+        errors.push(Error {
             pos: fp,
-            msg: msg,
+            msg: key.2.clone(),
             soft: soft,
             by_parser: by_parser,
-            order: order,
+            notes: vec![],
         });
+        self.index.borrow_mut().insert(key, errors.len() - 1);
+        self.last.set(Some(errors.len() - 1));
+    }
+
+    /// Adds a note to the error added last, unless it has the same one.
+    pub fn add_note(&self, p: FilePos, label: String) {
+        if let Some(i) = self.last.get() {
+            let notes = &mut self.errors.borrow_mut()[i].notes;
+            if notes.iter().all(|n| n.pos != p || n.label != label) {
+                notes.push(Note { pos: p, label });
+            }
+        }
     }
 
     pub fn len(&self) -> usize {
         self.errors.borrow().len()
     }
 
+    /// Orders the errors by file, then by position in the file. The errors at the
+    /// same position keep the order they were added in.
     pub fn sort(&self) {
-        self.errors.borrow_mut().sort_by_key(|e| e.order);
+        self.errors
+            .borrow_mut()
+            .sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        self.reindex();
+    }
+
+    /// Sorts the errors and keeps only the first one on each line, the others
+    /// tend to be caused by it. `TOO_MANY_ERRORS` is always kept.
+    pub fn remove_multiples(&self) {
+        self.sort();
+        let mut last_line = None;
+        self.errors.borrow_mut().retain(|e| {
+            let line = (e.pos.filename.clone(), e.pos.line);
+            let keep = last_line.as_ref() != Some(&line) || e.msg == TOO_MANY_ERRORS;
+            last_line = Some(line);
+            keep
+        });
+        self.reindex();
+    }
+
+    /// Copies of the errors in their current order. The list isn't borrowed
+    /// between the items, more errors may be added while iterating.
+    pub fn iter(&self) -> impl Iterator<Item = Error> + '_ {
+        (0..).map_while(move |i| self.errors.borrow().get(i).cloned())
     }
 
     pub fn borrow(&self) -> Ref<Vec<Error>> {
         self.errors.borrow()
     }
+
+    fn reindex(&self) {
+        let errors = self.errors.borrow();
+        let mut index = self.index.borrow_mut();
+        index.clear();
+        for (i, e) in errors.iter().enumerate() {
+            index.insert((e.pos.filename.clone(), e.pos.offset, e.msg.clone()), i);
+        }
+        self.last.set(None);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -124,4 +207,9 @@ impl<'a> FilePosErrors<'a> {
     pub fn parser_add_str(&self, pos: Pos, s: &str) {
         self.parser_add(pos, s.to_string());
     }
+
+    pub fn add_note(&self, pos: Pos, label: &str) {
+        let p = self.file.position(pos);
+        self.elist.add_note(p, label.to_owned());
+    }
 }
@@ -0,0 +1,902 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Incremental parsing for editors: after a change to a file, only the top level
+//! declarations touched by the change are parsed again, the others are taken from
+//! the previous parse with their positions moved.
+//!
+//! A declaration is reused if the text from its first token to the end of the token
+//! after it is unchanged, it had no errors, and the parser arrives at its first token
+//! between two declarations. The identifiers it resolved at package level are
+//! resolved again against the new package scope, so the result is the same as that
+//! of `parse_file`.
+
+use super::ast::*;
+use super::errors::ErrorList;
+use super::objects::*;
+use super::parser::Parser;
+use super::position::{FileSet, Pos};
+use super::token::Token;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A change to a source file, the bytes in `range` are replaced by `text`.
+#[derive(Clone, Debug)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: &str) -> TextEdit {
+        TextEdit {
+            range,
+            text: text.to_owned(),
+        }
+    }
+}
+
+// An identifier in a top level declaration whose resolution reached the package scope
+pub(crate) struct FreeIdent {
+    pub ident: IdentKey,
+    pub collect: bool,  // collected as unresolved if not found
+    pub resolved: bool, // found when it was parsed
+}
+
+// What's needed to reuse a top level declaration, offsets are in chars if not noted
+struct DeclInfo {
+    start: usize,
+    start_byte: usize,
+    // the token after the declaration, and the byte offset the scanner reached
+    // after scanning it
+    next: usize,
+    next_byte: usize,
+    end_byte: usize,
+    reusable: bool,
+    import: bool,
+    free: Vec<FreeIdent>,
+    entities: Vec<EntityKey>,
+}
+
+/// A source file parsed by `ParsedFile::parse`, which can be parsed again after
+/// changes with `reparse`.
+///
+/// # Usage:
+/// ```
+/// use go_parser::{ParsedFile, TextEdit};
+///
+/// let src = "package main\n\nfunc a() int { return 1 }\n\nfunc b() int { return a() }\n";
+/// let parsed = ParsedFile::parse("./main.go", src);
+/// // change the body of a
+/// let at = src.find('1').unwrap();
+/// let parsed = parsed.reparse(&[TextEdit::new(at..at + 1, "2")]);
+/// assert_eq!(parsed.reused_decls(), 1);
+/// ```
+pub struct ParsedFile {
+    name: String,
+    src: String,
+    objects: AstObjects,
+    fset: FileSet,
+    errors: ErrorList,
+    file: Option<File>,
+    decls: Vec<DeclInfo>,
+    // the number of identifiers after the last full parse, to bound the garbage
+    // left in the arenas by the replaced declarations
+    full_idents: usize,
+    reused: usize,
+}
+
+impl ParsedFile {
+    pub fn parse(name: &str, src: &str) -> ParsedFile {
+        let mut fset = FileSet::new();
+        fset.add_file(name.to_owned(), None, src.chars().count());
+        let mut objects = AstObjects::new();
+        let errors = ErrorList::new();
+        let (file, decls, _) =
+            parse_decls(&mut objects, &mut fset, &errors, src, None, &mut []).unwrap();
+        ParsedFile {
+            name: name.to_owned(),
+            src: src.to_owned(),
+            full_idents: objects.idents.vec().len(),
+            objects,
+            fset,
+            errors,
+            file,
+            decls,
+            reused: 0,
+        }
+    }
+
+    /// Applies the edits to the source and parses it, reusing the unchanged top
+    /// level declarations. The ranges of the edits are byte ranges of the current
+    /// source and must not overlap.
+    pub fn reparse(self, edits: &[TextEdit]) -> ParsedFile {
+        let mut edits: Vec<&TextEdit> = edits.iter().collect();
+        edits.sort_by_key(|e| e.range.start);
+        let mut last = 0;
+        for e in edits.iter() {
+            let r = &e.range;
+            if r.start < last
+                || r.start > r.end
+                || !self.src.is_char_boundary(r.start)
+                || !self.src.is_char_boundary(r.end)
+            {
+                panic!("invalid text edit {:?}", r);
+            }
+            last = r.end;
+        }
+
+        let mut src = String::with_capacity(self.src.len());
+        let mut last = 0;
+        for e in edits.iter() {
+            src.push_str(&self.src[last..e.range.start]);
+            src.push_str(&e.text);
+            last = e.range.end;
+        }
+        src.push_str(&self.src[last..]);
+
+        let garbage = self.objects.idents.vec().len() > self.full_idents * 2 + 1024;
+        let pkg_scope = match &self.file {
+            Some(f) if !garbage => f.scope,
+            _ => return ParsedFile::parse(&self.name, &src),
+        };
+
+        let changes: Vec<Change> = edits.iter().map(|e| self.change(e)).collect();
+        let mut fset = FileSet::new();
+        let size = self.fset.iter().next().unwrap().size() as isize
+            + changes.iter().map(|c| c.delta).sum::<isize>();
+        let file = fset.add_file(self.name.clone(), None, size as usize);
+        file.set_lines(self.new_lines(&changes, &edits, size as usize));
+
+        let ParsedFile {
+            name,
+            objects: mut objs,
+            file: old_file,
+            decls: old_infos,
+            full_idents,
+            ..
+        } = self;
+        let mut old: Vec<OldDecl> = old_file
+            .unwrap()
+            .decls
+            .into_iter()
+            .zip(old_infos)
+            .map(|(decl, info)| {
+                let shift = shift_of(&changes, &info);
+                OldDecl {
+                    decl: Some(decl),
+                    info,
+                    shift,
+                }
+            })
+            .collect();
+
+        let errors = ErrorList::new();
+        match parse_decls(
+            &mut objs,
+            &mut fset,
+            &errors,
+            &src,
+            Some(pkg_scope),
+            &mut old,
+        ) {
+            Some((file @ Some(_), decls, reused)) => ParsedFile {
+                name,
+                src,
+                objects: objs,
+                fset,
+                errors,
+                file,
+                decls,
+                full_idents,
+                reused,
+            },
+            // the package clause has errors, in which case the rest is not scanned,
+            // or a reused declaration conflicts with a new one
+            _ => ParsedFile::parse(&name, &src),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+
+    pub fn file(&self) -> Option<&File> {
+        self.file.as_ref()
+    }
+
+    pub fn objects(&self) -> &AstObjects {
+        &self.objects
+    }
+
+    pub fn fset(&self) -> &FileSet {
+        &self.fset
+    }
+
+    pub fn errors(&self) -> &ErrorList {
+        &self.errors
+    }
+
+    /// The number of top level declarations taken from the previous parse.
+    pub fn reused_decls(&self) -> usize {
+        self.reused
+    }
+
+    // Returns the char offset of a byte offset of the source.
+    fn char_offset(&self, byte: usize) -> usize {
+        // count from the closest declaration before it
+        let i = self.decls.partition_point(|d| d.start_byte <= byte);
+        let (start, start_byte) = match i {
+            0 => (0, 0),
+            _ => (self.decls[i - 1].start, self.decls[i - 1].start_byte),
+        };
+        start + self.src[start_byte..byte].chars().count()
+    }
+
+    fn change(&self, edit: &TextEdit) -> Change {
+        let start = self.char_offset(edit.range.start);
+        let end = self.char_offset(edit.range.end);
+        Change {
+            start_byte: edit.range.start,
+            end_byte: edit.range.end,
+            start,
+            end,
+            delta: edit.text.chars().count() as isize - (end - start) as isize,
+            byte_delta: edit.text.len() as isize - edit.range.len() as isize,
+        }
+    }
+
+    // Returns the line table of the source after the changes.
+    fn new_lines(&self, changes: &[Change], edits: &[&TextEdit], size: usize) -> Vec<usize> {
+        let file = self.fset.iter().next().unwrap();
+        let mut old = file.lines().to_vec();
+        // the line after a '\n' at the end is not in the table
+        if self.src.ends_with('\n') && file.size() > 0 {
+            old.push(file.size());
+        }
+        let mut lines = Vec::with_capacity(old.len());
+        let (mut i, mut delta) = (0, 0);
+        for (c, e) in changes.iter().zip(edits.iter()) {
+            while i < old.len() && old[i] <= c.start {
+                lines.push(offset(old[i], delta));
+                i += 1;
+            }
+            // the lines starting after a replaced '\n'
+            while i < old.len() && old[i] <= c.end {
+                i += 1;
+            }
+            let at = offset(c.start, delta);
+            for (j, ch) in e.text.chars().enumerate() {
+                if ch == '\n' {
+                    lines.push(at + j + 1);
+                }
+            }
+            delta += c.delta;
+        }
+        lines.extend(old[i..].iter().map(|&l| offset(l, delta)));
+        // like File::add_line, a '\n' at the end doesn't start a line
+        while lines.len() > 1 && *lines.last().unwrap() >= size {
+            lines.pop();
+        }
+        lines
+    }
+}
+
+// An edit in offsets of the source before it
+struct Change {
+    start_byte: usize,
+    end_byte: usize,
+    start: usize,
+    end: usize,
+    delta: isize,
+    byte_delta: isize,
+}
+
+// Returns how far a declaration moves, `None` if its text is changed.
+fn shift_of(changes: &[Change], info: &DeclInfo) -> Option<(isize, isize)> {
+    let (mut delta, mut byte_delta) = (0, 0);
+    for c in changes.iter() {
+        // an insertion right before the declaration doesn't touch it
+        if c.end_byte <= info.start_byte {
+            delta += c.delta;
+            byte_delta += c.byte_delta;
+        } else if c.start_byte <= info.end_byte {
+            return None;
+        }
+    }
+    Some((delta, byte_delta))
+}
+
+fn offset(o: usize, delta: isize) -> usize {
+    (o as isize + delta) as usize
+}
+
+struct OldDecl {
+    decl: Option<Decl>,
+    info: DeclInfo,
+    shift: Option<(isize, isize)>,
+}
+
+enum Reuse {
+    Done(Decl, DeclInfo),
+    // the declaration can't be moved, it's parsed again
+    Failed,
+    // it declares a name the declarations before it now declare
+    Conflict,
+}
+
+impl OldDecl {
+    // Returns where the declaration starts in the new source if it can be reused.
+    fn start(&self) -> Option<usize> {
+        match self.shift {
+            Some((delta, _)) if self.info.reusable && self.decl.is_some() => {
+                Some(offset(self.info.start, delta))
+            }
+            _ => None,
+        }
+    }
+
+    fn reuse(&mut self, p: &mut Parser) -> Reuse {
+        let (delta, byte_delta) = self.shift.unwrap();
+        let mut decl = self.decl.take().unwrap();
+        if delta != 0 && !Shift::run(p.objects(), &mut decl, delta) {
+            return Reuse::Failed;
+        }
+
+        let scope = p.pkg_scope();
+        let objs = p.objects();
+        let info = &mut self.info;
+        for e in info.entities.iter() {
+            if objs.scopes[scope]
+                .look_up(&objs.entities[*e].name)
+                .is_some()
+            {
+                return Reuse::Conflict;
+            }
+        }
+        // resolve the names not declared by the declaration itself as the parser
+        // would have, given the declarations before it
+        let mut unresolved = vec![];
+        for f in info.free.iter_mut() {
+            let ident = &mut objs.idents[f.ident];
+            if let IdentEntity::Entity(e) = ident.entity {
+                if f.resolved && info.entities.contains(&e) {
+                    continue;
+                }
+            }
+            match objs.scopes[scope].look_up(&ident.name) {
+                Some(e) => {
+                    ident.entity = IdentEntity::Entity(*e);
+                    f.resolved = true;
+                }
+                None => {
+                    f.resolved = false;
+                    ident.entity = if f.collect {
+                        unresolved.push(f.ident);
+                        IdentEntity::Sentinel
+                    } else {
+                        IdentEntity::NoEntity
+                    };
+                }
+            }
+        }
+        for e in info.entities.iter() {
+            let name = objs.entities[*e].name.clone();
+            objs.scopes[scope].insert(name, *e);
+        }
+
+        let imports = match &decl {
+            Decl::Gen(g) if g.token == Token::IMPORT => g.specs.clone(),
+            _ => vec![],
+        };
+        let info = DeclInfo {
+            start: offset(info.start, delta),
+            start_byte: offset(info.start_byte, byte_delta),
+            next: offset(info.next, delta),
+            next_byte: offset(info.next_byte, byte_delta),
+            end_byte: offset(info.end_byte, byte_delta),
+            reusable: true,
+            import: info.import,
+            free: std::mem::take(&mut info.free),
+            entities: std::mem::take(&mut info.entities),
+        };
+        p.skip_decl(&imports, &unresolved, info.next, info.next_byte);
+        Reuse::Done(decl, info)
+    }
+}
+
+// Parses the top level declarations one by one, taking the ones in `old` that
+// can be reused. Returns `None` if a reused declaration conflicts with another.
+fn parse_decls(
+    objects: &mut AstObjects,
+    fset: &mut FileSet,
+    el: &ErrorList,
+    src: &str,
+    pkg_scope: Option<ScopeKey>,
+    old: &mut [OldDecl],
+) -> Option<(Option<File>, Vec<DeclInfo>, usize)> {
+    let f = fset.recent_file().unwrap();
+    let base = f.base();
+    let mut p = Parser::new(objects, f, el, src, false);
+    let (pos, ident) = match p.begin_incremental(pkg_scope) {
+        Some(clause) => clause,
+        None => return Some((None, vec![], 0)),
+    };
+
+    let (mut decls, mut infos, mut reused) = (vec![], vec![], 0);
+    let (mut importing, mut k) = (true, 0);
+    loop {
+        let (token, at) = p.token();
+        if *token == Token::EOF {
+            break;
+        }
+        importing = importing && *token == Token::IMPORT;
+        let at = at - base;
+        while k < old.len() && !matches!(old[k].start(), Some(s) if s >= at) {
+            k += 1;
+        }
+        if k < old.len()
+            && old[k].start() == Some(at)
+            && old[k].info.import == importing
+            && p.at_decl_boundary()
+        {
+            match old[k].reuse(&mut p) {
+                Reuse::Done(decl, info) => {
+                    decls.push(decl);
+                    infos.push(info);
+                    reused += 1;
+                    k += 1;
+                    continue;
+                }
+                Reuse::Failed => {}
+                Reuse::Conflict => return None,
+            }
+        }
+
+        let boundary = p.at_decl_boundary();
+        let errors = p.error_count();
+        let (_, _, start_byte) = p.scanner_offsets();
+        let (decl, free, entities) = p.parse_top_decl(importing);
+        let (_, end_byte, next_byte) = p.scanner_offsets();
+        infos.push(DeclInfo {
+            start: at,
+            start_byte,
+            next: p.token().1 - base,
+            next_byte,
+            end_byte,
+            reusable: boundary && errors == p.error_count() && p.at_decl_boundary(),
+            import: importing,
+            free,
+            entities,
+        });
+        decls.push(decl);
+    }
+    Some((Some(p.end_incremental(pos, ident, decls)), infos, reused))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NodeKey {
+    Field(FieldKey),
+    FuncType(FuncTypeKey),
+    Spec(SpecKey),
+    FuncDecl(FuncDeclKey),
+    Assign(AssignStmtKey),
+    Labeled(LabeledStmtKey),
+}
+
+// Moves all the positions in a declaration by `delta` chars. The nodes in the
+// arenas are visited after the tree they are in, the identifiers last.
+struct Shift {
+    delta: isize,
+    keys: Vec<NodeKey>,
+    seen: HashSet<NodeKey>,
+    idents: Vec<IdentKey>,
+}
+
+macro_rules! unique {
+    ($rc:expr) => {
+        match Rc::get_mut($rc) {
+            Some(node) => node,
+            None => return false,
+        }
+    };
+}
+
+impl Shift {
+    // Fails if a node is shared, the declaration is then partly moved.
+    fn run(objs: &mut AstObjects, decl: &mut Decl, delta: isize) -> bool {
+        let mut s = Shift {
+            delta,
+            keys: vec![],
+            seen: HashSet::new(),
+            idents: vec![],
+        };
+        if !s.decl(decl) {
+            return false;
+        }
+        while let Some(key) = s.keys.pop() {
+            let ok = match key {
+                NodeKey::Field(k) => {
+                    let f = &mut objs.fields[k];
+                    s.add_idents(&f.names);
+                    s.expr(&mut f.typ) && s.opt_expr(&mut f.tag)
+                }
+                NodeKey::FuncType(k) => {
+                    let t = &mut objs.ftypes[k];
+                    s.opt_pos(&mut t.func);
+                    s.field_list(&mut t.params);
+                    if let Some(r) = &mut t.results {
+                        s.field_list(r);
+                    }
+                    true
+                }
+                NodeKey::Spec(k) => s.spec(&mut objs.specs[k]),
+                NodeKey::FuncDecl(k) => {
+                    let f = &mut objs.fdecls[k];
+                    if let Some(r) = &mut f.recv {
+                        s.field_list(r);
+                    }
+                    s.idents.push(f.name);
+                    s.push(NodeKey::FuncType(f.typ));
+                    match &mut f.body {
+                        Some(b) => s.block(b),
+                        None => true,
+                    }
+                }
+                NodeKey::Assign(k) => {
+                    let a = &mut objs.a_stmts[k];
+                    s.pos(&mut a.token_pos);
+                    s.exprs(&mut a.lhs) && s.exprs(&mut a.rhs)
+                }
+                NodeKey::Labeled(k) => {
+                    let l = &mut objs.l_stmts[k];
+                    s.idents.push(l.label);
+                    s.pos(&mut l.colon);
+                    s.stmt(&mut l.stmt)
+                }
+            };
+            if !ok {
+                return false;
+            }
+        }
+        s.idents.sort_unstable();
+        s.idents.dedup();
+        for i in s.idents.iter() {
+            s.pos(&mut objs.idents[*i].pos);
+        }
+        true
+    }
+
+    fn push(&mut self, key: NodeKey) {
+        if self.seen.insert(key) {
+            self.keys.push(key);
+        }
+    }
+
+    fn add_idents(&mut self, idents: &[IdentKey]) {
+        self.idents.extend_from_slice(idents);
+    }
+
+    // a zero position is a missing one
+    fn pos(&self, p: &mut Pos) {
+        if *p != 0 {
+            *p = offset(*p, self.delta);
+        }
+    }
+
+    fn opt_pos(&self, p: &mut Option<Pos>) {
+        if let Some(p) = p {
+            self.pos(p);
+        }
+    }
+
+    fn field_list(&mut self, l: &mut FieldList) {
+        self.opt_pos(&mut l.openning);
+        for f in l.list.iter() {
+            self.push(NodeKey::Field(*f));
+        }
+        self.opt_pos(&mut l.closing);
+    }
+
+    fn exprs(&mut self, l: &mut [Expr]) -> bool {
+        l.iter_mut().all(|e| self.expr(e))
+    }
+
+    fn opt_expr(&mut self, e: &mut Option<Expr>) -> bool {
+        match e {
+            Some(e) => self.expr(e),
+            None => true,
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) -> bool {
+        match e {
+            Expr::Bad(b) => {
+                let b = unique!(b);
+                self.pos(&mut b.from);
+                self.pos(&mut b.to);
+            }
+            Expr::Ident(i) => self.idents.push(*i),
+            Expr::Ellipsis(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.pos);
+                return self.opt_expr(&mut x.elt);
+            }
+            Expr::BasicLit(x) => self.pos(&mut unique!(x).pos),
+            Expr::FuncLit(x) => {
+                let x = unique!(x);
+                self.push(NodeKey::FuncType(x.typ));
+                return self.block(&mut x.body);
+            }
+            Expr::CompositeLit(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_brace);
+                self.pos(&mut x.r_brace);
+                return self.opt_expr(&mut x.typ) && self.exprs(&mut x.elts);
+            }
+            Expr::Paren(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_paren);
+                self.pos(&mut x.r_paren);
+                return self.expr(&mut x.expr);
+            }
+            Expr::Selector(x) => {
+                let x = unique!(x);
+                self.idents.push(x.sel);
+                return self.expr(&mut x.expr);
+            }
+            Expr::Index(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_brack);
+                self.pos(&mut x.r_brack);
+                return self.expr(&mut x.expr) && self.expr(&mut x.index);
+            }
+            Expr::Slice(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_brack);
+                self.pos(&mut x.r_brack);
+                return self.expr(&mut x.expr)
+                    && self.opt_expr(&mut x.low)
+                    && self.opt_expr(&mut x.high)
+                    && self.opt_expr(&mut x.max);
+            }
+            Expr::TypeAssert(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_paren);
+                self.pos(&mut x.r_paren);
+                return self.expr(&mut x.expr) && self.opt_expr(&mut x.typ);
+            }
+            Expr::Call(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_paren);
+                self.opt_pos(&mut x.ellipsis);
+                self.pos(&mut x.r_paren);
+                return self.expr(&mut x.func) && self.exprs(&mut x.args);
+            }
+            Expr::Star(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.star);
+                return self.expr(&mut x.expr);
+            }
+            Expr::Unary(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.op_pos);
+                return self.expr(&mut x.expr);
+            }
+            Expr::Binary(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.op_pos);
+                return self.expr(&mut x.expr_a) && self.expr(&mut x.expr_b);
+            }
+            Expr::KeyValue(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.colon);
+                return self.expr(&mut x.key) && self.expr(&mut x.val);
+            }
+            Expr::Array(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.l_brack);
+                return self.opt_expr(&mut x.len) && self.expr(&mut x.elt);
+            }
+            Expr::Struct(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.struct_pos);
+                self.field_list(&mut x.fields);
+            }
+            Expr::Func(k) => self.push(NodeKey::FuncType(*k)),
+            Expr::Interface(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.interface);
+                self.field_list(&mut x.methods);
+            }
+            Expr::Map(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.map);
+                return self.expr(&mut x.key) && self.expr(&mut x.val);
+            }
+            Expr::Chan(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.begin);
+                self.pos(&mut x.arrow);
+                return self.expr(&mut x.val);
+            }
+        }
+        true
+    }
+
+    fn stmts(&mut self, l: &mut [Stmt]) -> bool {
+        l.iter_mut().all(|s| self.stmt(s))
+    }
+
+    fn opt_stmt(&mut self, s: &mut Option<Stmt>) -> bool {
+        match s {
+            Some(s) => self.stmt(s),
+            None => true,
+        }
+    }
+
+    fn block(&mut self, b: &mut Rc<BlockStmt>) -> bool {
+        let b = unique!(b);
+        self.pos(&mut b.l_brace);
+        self.pos(&mut b.r_brace);
+        self.stmts(&mut b.list)
+    }
+
+    fn stmt(&mut self, s: &mut Stmt) -> bool {
+        match s {
+            Stmt::Bad(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.from);
+                self.pos(&mut x.to);
+            }
+            Stmt::Decl(x) => return self.decl(unique!(x)),
+            Stmt::Empty(x) => self.pos(&mut unique!(x).semi),
+            Stmt::Labeled(k) => self.push(NodeKey::Labeled(*k)),
+            Stmt::Expr(x) => return self.expr(x),
+            Stmt::Send(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.arrow);
+                return self.expr(&mut x.chan) && self.expr(&mut x.val);
+            }
+            Stmt::IncDec(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.token_pos);
+                return self.expr(&mut x.expr);
+            }
+            Stmt::Assign(k) => self.push(NodeKey::Assign(*k)),
+            Stmt::Go(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.go);
+                return self.expr(&mut x.call);
+            }
+            Stmt::Defer(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.defer);
+                return self.expr(&mut x.call);
+            }
+            Stmt::Return(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.ret);
+                return self.exprs(&mut x.results);
+            }
+            Stmt::Branch(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.token_pos);
+                if let Some(l) = x.label {
+                    self.idents.push(l);
+                }
+            }
+            Stmt::Block(x) => return self.block(x),
+            Stmt::If(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.if_pos);
+                return self.opt_stmt(&mut x.init)
+                    && self.expr(&mut x.cond)
+                    && self.block(&mut x.body)
+                    && self.opt_stmt(&mut x.els);
+            }
+            Stmt::Case(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.case);
+                self.pos(&mut x.colon);
+                if let Some(l) = &mut x.list {
+                    if !self.exprs(l) {
+                        return false;
+                    }
+                }
+                return self.stmts(&mut x.body);
+            }
+            Stmt::Switch(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.switch);
+                return self.opt_stmt(&mut x.init)
+                    && self.opt_expr(&mut x.tag)
+                    && self.block(&mut x.body);
+            }
+            Stmt::TypeSwitch(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.switch);
+                return self.opt_stmt(&mut x.init)
+                    && self.stmt(&mut x.assign)
+                    && self.block(&mut x.body);
+            }
+            Stmt::Comm(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.case);
+                self.pos(&mut x.colon);
+                return self.opt_stmt(&mut x.comm) && self.stmts(&mut x.body);
+            }
+            Stmt::Select(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.select);
+                return self.block(&mut x.body);
+            }
+            Stmt::For(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.for_pos);
+                return self.opt_stmt(&mut x.init)
+                    && self.opt_expr(&mut x.cond)
+                    && self.opt_stmt(&mut x.post)
+                    && self.block(&mut x.body);
+            }
+            Stmt::Range(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.for_pos);
+                self.pos(&mut x.token_pos);
+                return self.opt_expr(&mut x.key)
+                    && self.opt_expr(&mut x.val)
+                    && self.expr(&mut x.expr)
+                    && self.block(&mut x.body);
+            }
+        }
+        true
+    }
+
+    fn spec(&mut self, s: &mut Spec) -> bool {
+        match s {
+            Spec::Import(x) => {
+                let x = unique!(x);
+                if let Some(n) = x.name {
+                    self.idents.push(n);
+                }
+                self.pos(&mut x.path.pos);
+                self.opt_pos(&mut x.end_pos);
+            }
+            Spec::Value(x) => {
+                let x = unique!(x);
+                self.add_idents(&x.names);
+                return self.opt_expr(&mut x.typ) && self.exprs(&mut x.values);
+            }
+            Spec::Type(x) => {
+                let x = unique!(x);
+                self.idents.push(x.name);
+                self.pos(&mut x.assign);
+                return self.expr(&mut x.typ);
+            }
+        }
+        true
+    }
+
+    fn decl(&mut self, d: &mut Decl) -> bool {
+        match d {
+            Decl::Bad(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.from);
+                self.pos(&mut x.to);
+            }
+            Decl::Gen(x) => {
+                let x = unique!(x);
+                self.pos(&mut x.token_pos);
+                self.opt_pos(&mut x.l_paran);
+                self.opt_pos(&mut x.r_paren);
+                for s in x.specs.iter() {
+                    self.push(NodeKey::Spec(*s));
+                }
+            }
+            Decl::Func(k) => self.push(NodeKey::FuncDecl(*k)),
+        }
+        true
+    }
+}
@@ -20,9 +20,11 @@
 //!
 //! # Feature
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
+//! - `serde_borsh`: Serde support for the AST using Borsh, see `ast::File::serialize_with`
 //!
 
 mod errors;
+mod incremental;
 mod map;
 mod objects;
 mod parser;
@@ -31,13 +33,16 @@ mod scanner;
 mod token;
 
 pub mod ast;
+pub mod printer;
 pub mod scope;
 pub mod visitor;
+pub mod walk;
 
 pub use errors::*;
+pub use incremental::{ParsedFile, TextEdit};
 pub use map::{Map, MapIter};
 pub use objects::*;
-pub use parser::Parser;
+pub use parser::{Parser, ParserConfig};
 pub use position::*;
 pub use token::*;
 
@@ -48,9 +53,40 @@ pub fn parse_file<'a>(
     name: &str,
     src: &'a str,
     trace: bool,
+) -> (parser::Parser<'a>, Option<ast::File>) {
+    let config = ParserConfig {
+        trace,
+        ..ParserConfig::default()
+    };
+    parse_file_with_config(o, fs, el, name, src, config)
+}
+
+/// Like `parse_file`, with the comments collected if `config` says so
+pub fn parse_file_with_config<'a>(
+    o: &'a mut AstObjects,
+    fs: &'a mut FileSet,
+    el: &'a ErrorList,
+    name: &str,
+    src: &'a str,
+    config: ParserConfig,
 ) -> (parser::Parser<'a>, Option<ast::File>) {
     let f = fs.add_file(name.to_string(), None, src.chars().count());
-    let mut p = parser::Parser::new(o, f, el, src, trace);
+    let mut p = parser::Parser::with_config(o, f, el, src, config);
     let file = p.parse_file();
     (p, file)
 }
+
+/// Parses `src` as a single expression, or a type, on its own. The returned
+/// objects are the ones the keys in the expression refer to.
+pub fn parse_expr(src: &str) -> Result<(ast::Expr, AstObjects), ErrorList> {
+    let mut o = AstObjects::new();
+    let mut fs = FileSet::new();
+    let el = ErrorList::new();
+    let f = fs.add_file("expr".to_owned(), None, src.chars().count());
+    let expr = Parser::new(&mut o, f, &el, src, false).parse_single_expr();
+    if el.len() > 0 {
+        el.sort();
+        return Err(el);
+    }
+    Ok((expr, o))
+}
@@ -17,7 +17,7 @@ pub trait PiggyVecKey {
 
 /// A vec that you can only insert into, so that the index can be used as a key
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PiggyVec<K, V>
 where
     K: PiggyVecKey + From<usize>,
@@ -177,10 +177,47 @@ piggy_key_type! {
     pub struct FuncTypeKey;
     pub struct IdentKey;
     pub struct FieldKey;
+    pub struct CommentGroupKey;
     pub struct EntityKey;
     pub struct ScopeKey;
 }
 
+#[cfg(feature = "serde_borsh")]
+macro_rules! impl_borsh_for_key {
+    ($($key:ident),*) => {
+        $(
+            impl BorshSerialize for $key {
+                fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+                    self.as_usize().serialize(writer)
+                }
+            }
+
+            impl BorshDeserialize for $key {
+                fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+                    let i: usize = usize::deserialize_reader(reader)?;
+                    Ok(i.into())
+                }
+            }
+        )*
+    };
+}
+
+// The links between the nodes, the entities and the scopes are these keys, so
+// they are written as the indices into the arenas.
+#[cfg(feature = "serde_borsh")]
+impl_borsh_for_key!(
+    LabeledStmtKey,
+    AssignStmtKey,
+    SpecKey,
+    FuncDeclKey,
+    FuncTypeKey,
+    IdentKey,
+    FieldKey,
+    CommentGroupKey,
+    EntityKey,
+    ScopeKey
+);
+
 pub type LabeledStmts = PiggyVec<LabeledStmtKey, ast::LabeledStmt>;
 pub type AssignStmts = PiggyVec<AssignStmtKey, ast::AssignStmt>;
 pub type Specs = PiggyVec<SpecKey, ast::Spec>;
@@ -188,9 +225,11 @@ pub type FuncDecls = PiggyVec<FuncDeclKey, ast::FuncDecl>;
 pub type FuncTypes = PiggyVec<FuncTypeKey, ast::FuncType>;
 pub type Idents = PiggyVec<IdentKey, ast::Ident>;
 pub type Fields = PiggyVec<FieldKey, ast::Field>;
+pub type CommentGroups = PiggyVec<CommentGroupKey, ast::CommentGroup>;
 pub type Entitys = PiggyVec<EntityKey, scope::Entity>;
 pub type Scopes = PiggyVec<ScopeKey, scope::Scope>;
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 pub struct AstObjects {
     pub l_stmts: LabeledStmts,
     pub a_stmts: AssignStmts,
@@ -199,6 +238,7 @@ pub struct AstObjects {
     pub ftypes: FuncTypes,
     pub idents: Idents,
     pub fields: Fields,
+    pub comment_groups: CommentGroups,
     pub entities: Entitys,
     pub scopes: Scopes,
 }
@@ -214,6 +254,7 @@ impl AstObjects {
             ftypes: PiggyVec::with_capacity(CAP),
             idents: PiggyVec::with_capacity(CAP),
             fields: PiggyVec::with_capacity(CAP),
+            comment_groups: PiggyVec::with_capacity(CAP),
             entities: PiggyVec::with_capacity(CAP),
             scopes: PiggyVec::with_capacity(CAP),
         }
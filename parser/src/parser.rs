@@ -10,14 +10,19 @@
 // license that can be found in the LICENSE file.
 
 use super::ast::*;
-use super::errors::{ErrorList, FilePosErrors};
+use super::errors::{ErrorList, FilePosErrors, TOO_MANY_ERRORS};
+use super::incremental::FreeIdent;
 use super::objects::*;
 use super::position;
 use super::scanner;
 use super::scope::*;
 use super::token::{Token, LOWEST_PREC};
+use std::cell::Cell;
 use std::rc::Rc;
 
+// The max number of errors reported in a composite literal
+const MAX_LITERAL_ERRORS: usize = 3;
+
 macro_rules! new_scope {
     ($owner:ident, $outer:expr) => {
         $owner.objects.scopes.insert(Scope::new($outer))
@@ -36,14 +41,32 @@ macro_rules! new_ident {
 
 macro_rules! new_field {
     ($owner:ident, $names:expr, $typ:expr, $tag:expr) => {
+        new_field!($owner, None, $names, $typ, $tag, None)
+    };
+    ($owner:ident, $doc:expr, $names:expr, $typ:expr, $tag:expr, $comment:expr) => {
         $owner.objects.fields.insert(Field {
+            doc: $doc,
             names: $names,
             typ: $typ,
             tag: $tag,
+            comment: $comment,
         })
     };
 }
 
+/// The options of a Parser
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParserConfig {
+    /// Print a trace of the parsed productions
+    pub trace: bool,
+    /// Collect the comments and attach the doc comments to the declarations,
+    /// they are skipped otherwise
+    pub comments: bool,
+    /// Give up on the file once it has this many errors, with a `TOO_MANY_ERRORS`
+    /// error, instead of reporting the ones the first errors cause further on
+    pub max_errors: Option<usize>,
+}
+
 // Parsing modes for parseSimpleStmt.
 #[derive(PartialEq, Eq)]
 enum ParseSimpleMode {
@@ -56,6 +79,11 @@ pub struct Parser<'a> {
     objects: &'a mut AstObjects,
     scanner: scanner::Scanner<'a>,
     errors: &'a ErrorList,
+    // the errors in the list before this file, and whether it has given up on the
+    // file because of too many errors
+    errors_before: usize,
+    max_errors: Option<usize>,
+    bailed_out: Cell<bool>,
 
     trace: bool,
     indent: isize,
@@ -63,12 +91,26 @@ pub struct Parser<'a> {
     pos: position::Pos,
     token: Token,
 
+    // comments
+    parse_comments: bool,
+    comments: Vec<CommentGroupKey>,
+    lead_comment: Option<CommentGroupKey>, // last lead comment
+    line_comment: Option<CommentGroupKey>, // last line comment
+    file_doc: Option<CommentGroupKey>,     // the comment of the package clause
+
     sync_pos: position::Pos,
     sync_count: isize,
 
     expr_level: isize,
     in_rhs: bool,
 
+    // nesting depth of the composite literals being parsed, and the errors
+    // reported in the outermost one
+    lit_depth: usize,
+    lit_errors: Cell<usize>,
+    // a composite literal was cut off by EOF or a top level declaration
+    lit_unclosed: bool,
+
     pkg_scope: Option<ScopeKey>,
     top_scope: Option<ScopeKey>,
     unresolved: Vec<IdentKey>,
@@ -76,6 +118,12 @@ pub struct Parser<'a> {
 
     label_scope: Option<ScopeKey>,
     target_stack: Vec<Vec<IdentKey>>,
+
+    // what the current top level declaration resolves to and declares at
+    // package level, only recorded for incremental parsing
+    incremental: bool,
+    free_idents: Vec<FreeIdent>,
+    pkg_entities: Vec<EntityKey>,
 }
 
 impl<'a> Parser<'a> {
@@ -85,26 +133,54 @@ impl<'a> Parser<'a> {
         el: &'a ErrorList,
         src: &'a str,
         trace: bool,
+    ) -> Parser<'a> {
+        let config = ParserConfig {
+            trace,
+            ..ParserConfig::default()
+        };
+        Parser::with_config(objs, file, el, src, config)
+    }
+
+    pub fn with_config(
+        objs: &'a mut AstObjects,
+        file: &'a mut position::File,
+        el: &'a ErrorList,
+        src: &'a str,
+        config: ParserConfig,
     ) -> Parser<'a> {
         let s = scanner::Scanner::new(file, src, el);
         let mut p = Parser {
             objects: objs,
             scanner: s,
             errors: el,
-            trace: trace,
+            errors_before: el.len(),
+            max_errors: config.max_errors,
+            bailed_out: Cell::new(false),
+            trace: config.trace,
             indent: 0,
             pos: 0,
             token: Token::NONE,
+            parse_comments: config.comments,
+            comments: vec![],
+            lead_comment: None,
+            line_comment: None,
+            file_doc: None,
             sync_pos: 0,
             sync_count: 0,
             expr_level: 0,
             in_rhs: false,
+            lit_depth: 0,
+            lit_errors: Cell::new(0),
+            lit_unclosed: false,
             pkg_scope: None,
             top_scope: None,
             unresolved: vec![],
             imports: vec![],
             label_scope: None,
             target_stack: vec![],
+            incremental: false,
+            free_idents: vec![],
+            pkg_entities: vec![],
         };
         p.next(); // get the first token ready
         p
@@ -144,8 +220,8 @@ impl<'a> Parser<'a> {
                             ident.entity = IdentEntity::Entity(*e);
                         }
                         None => {
-                            let s = format!("label {} undefined", ident.name);
-                            self.error(self.pos, s);
+                            let (pos, s) = (ident.pos, format!("label {} undefined", ident.name));
+                            self.error(pos, s);
                         }
                     }
                 }
@@ -193,18 +269,18 @@ impl<'a> Parser<'a> {
             mut_ident.entity = IdentEntity::Entity(entity);
             let ident = &self.objects.idents[*id];
             if ident.name != "_" {
+                if self.incremental && Some(*scope_ind) == self.pkg_scope {
+                    self.pkg_entities.push(entity);
+                }
                 let scope = &mut self.objects.scopes[*scope_ind];
                 match scope.insert(ident.name.clone(), entity) {
                     Some(prev_decl) => {
                         let p = self.objects.entities[prev_decl].pos(&self.objects);
                         self.error(
                             ident.pos,
-                            format!(
-                                "{} redeclared in this block\n\tprevious declaration at {}",
-                                ident.name,
-                                self.file().position(p)
-                            ),
+                            format!("{} redeclared in this block", ident.name),
                         );
+                        self.note(p, "previous declaration");
                     }
                     _ => {}
                 }
@@ -283,6 +359,13 @@ impl<'a> Parser<'a> {
                         let scope = &self.objects.scopes[sidx];
                         if let Some(entity) = scope.look_up(&ident.name) {
                             ident.entity = IdentEntity::Entity(*entity);
+                            if self.incremental && s == self.pkg_scope {
+                                self.free_idents.push(FreeIdent {
+                                    ident: *i,
+                                    collect: collect_unresolved,
+                                    resolved: true,
+                                });
+                            }
                             return;
                         }
                         s = scope.outer;
@@ -292,6 +375,13 @@ impl<'a> Parser<'a> {
                     }
                 }
             }
+            if self.incremental {
+                self.free_idents.push(FreeIdent {
+                    ident: *i,
+                    collect: collect_unresolved,
+                    resolved: false,
+                });
+            }
             // all local scopes are known, so any unresolved identifier
             // must be found either in the file scope, package scope
             // (perhaps in another file), or universe scope --- collect
@@ -339,12 +429,17 @@ impl<'a> Parser<'a> {
         self.print_trace(self.pos, ")");
     }
 
-    fn next(&mut self) {
-        // Get next token and skip comments
+    // Advance to the next token, the comments are skipped unless collected.
+    fn next0(&mut self) {
+        // the rest of the file is skipped after giving up on it
+        if self.bailed_out.get() {
+            self.token = Token::EOF;
+            return;
+        }
         loop {
             let (token, pos) = self.scanner.scan();
             match token {
-                Token::COMMENT(_) => {
+                Token::COMMENT(_) if !self.parse_comments => {
                     // Skip comment
                     self.print_trace(pos, &format!("{}", token));
                 }
@@ -358,19 +453,144 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Consume a comment and return it and the line on which it ends.
+    fn consume_comment(&mut self) -> (Comment, usize) {
+        let text: String = match &self.token {
+            Token::COMMENT(lit) => {
+                let lit: &String = lit.as_ref();
+                lit.strip_suffix('\n').unwrap_or(lit).to_owned()
+            }
+            _ => unreachable!(),
+        };
+        // /*-style comments may end on a different line than where they start.
+        // Scan the comment for '\n' chars and adjust endline accordingly.
+        let endline = self.file().line(self.pos) + text.matches('\n').count();
+        let comment = Comment {
+            slash: self.pos,
+            text,
+        };
+        self.next0();
+        (comment, endline)
+    }
+
+    // Consume a group of adjacent comments, add it to the parser's
+    // comments list, and return it together with the line at which
+    // the last comment in the group ends. A non-comment token or n
+    // empty lines terminate a comment group.
+    fn consume_comment_group(&mut self, n: usize) -> (CommentGroupKey, usize) {
+        let mut list = vec![];
+        let mut endline = self.file().line(self.pos);
+        while matches!(self.token, Token::COMMENT(_)) && self.file().line(self.pos) <= endline + n {
+            let (comment, end) = self.consume_comment();
+            list.push(comment);
+            endline = end;
+        }
+
+        // add comment group to the comments list
+        let group = self.objects.comment_groups.insert(CommentGroup { list });
+        self.comments.push(group);
+        (group, endline)
+    }
+
+    // Advance to the next non-comment token. In the process, collect
+    // any comment groups encountered, and remember the last lead and
+    // line comments.
+    //
+    // A lead comment is a comment group that starts and ends in a
+    // line without any other tokens and that is followed by a non-comment
+    // token on the line immediately after the comment group.
+    //
+    // A line comment is a comment group that follows a non-comment
+    // token on the same line, and that has no tokens after it on the line
+    // where it ends.
+    //
+    // Lead and line comments may be considered documentation that is
+    // stored in the AST.
+    fn next(&mut self) {
+        self.lead_comment = None;
+        self.line_comment = None;
+        let prev_line = match self.token {
+            Token::NONE => 0,
+            _ => self.file().line(self.pos),
+        };
+        self.next0();
+
+        if let Token::COMMENT(_) = self.token {
+            if self.file().line(self.pos) == prev_line {
+                // The comment is on same line as the previous token; it
+                // cannot be a lead comment but may be a line comment.
+                let (comment, endline) = self.consume_comment_group(0);
+                if self.file().line(self.pos) != endline || self.token == Token::EOF {
+                    // The next token is on a different line, thus
+                    // the last comment group is a line comment.
+                    self.line_comment = Some(comment);
+                }
+            }
+
+            // consume successor comments, if any
+            let mut last = None;
+            while let Token::COMMENT(_) = self.token {
+                last = Some(self.consume_comment_group(1));
+            }
+
+            if let Some((comment, endline)) = last {
+                if endline + 1 == self.file().line(self.pos) {
+                    // The next token is following on the line immediately after the
+                    // comment group, thus the last comment group is a lead comment.
+                    self.lead_comment = Some(comment);
+                }
+            }
+        }
+    }
+
     fn error_str(&self, pos: position::Pos, s: &str) {
-        FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, s);
+        self.error(pos, s.to_owned());
     }
 
     fn error(&self, pos: position::Pos, msg: String) {
+        if self.bailed_out.get() {
+            return;
+        }
+        if let Some(max) = self.max_errors {
+            if self.errors.len().saturating_sub(self.errors_before) >= max {
+                FilePosErrors::new(self.file(), self.errors).parser_add_str(pos, TOO_MANY_ERRORS);
+                self.bailed_out.set(true);
+                return;
+            }
+        }
+        // one mistake in a composite literal tends to cause more, only the first
+        // few errors in a literal are reported
+        if self.lit_depth > 0 {
+            let count = self.lit_errors.get() + 1;
+            self.lit_errors.set(count);
+            if count > MAX_LITERAL_ERRORS {
+                if count == MAX_LITERAL_ERRORS + 1 {
+                    FilePosErrors::new(self.file(), self.errors)
+                        .parser_add_str(pos, "... (further errors in this literal suppressed)");
+                }
+                return;
+            }
+        }
         FilePosErrors::new(self.file(), self.errors).parser_add(pos, msg);
     }
 
+    /// Adds a note to the error reported last, unless that error was suppressed
+    fn note(&self, pos: position::Pos, label: &str) {
+        if self.bailed_out.get() {
+            return;
+        }
+        if self.lit_depth == 0 || self.lit_errors.get() <= MAX_LITERAL_ERRORS {
+            FilePosErrors::new(self.file(), self.errors).add_note(pos, label);
+        }
+    }
+
     fn error_expected(&self, pos: position::Pos, msg: &str) {
         let mut mstr = "expected ".to_owned();
         mstr.push_str(msg);
         if pos == self.pos {
             match &self.token {
+                // the scanner has reported the illegal encoding
+                Token::ILLEGAL(c) if c.as_str() == "\u{FFFD}" => return,
                 Token::SEMICOLON(real) => {
                     if !*real.as_bool() {
                         mstr.push_str(", found newline");
@@ -408,18 +628,23 @@ impl<'a> Parser<'a> {
     }
 
     fn expect_semi(&mut self) {
+        // the missing '}' of a composite literal is the only error reported
+        if self.lit_unclosed && (self.token == Token::EOF || self.at_top_level_decl()) {
+            self.lit_unclosed = false;
+            return;
+        }
         // semicolon is optional before a closing ')' or '}'
         match self.token {
             Token::RPAREN | Token::RBRACE => {}
             Token::SEMICOLON(_) => {
                 self.next();
             }
+            Token::COMMA => {
+                // permit a ',' instead of a ';' but complain
+                self.error_expected(self.pos, "';'");
+                self.next();
+            }
             _ => {
-                if let Token::COMMA = self.token {
-                    // permit a ',' instead of a ';' but complain
-                    self.error_expected(self.pos, "';'");
-                    self.next();
-                }
                 self.error_expected(self.pos, "';'");
                 self.advance(Token::is_stmt_start);
             }
@@ -444,6 +669,19 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // at_top_level_decl reports whether the current token starts a top level
+    // declaration, i.e. it's a declaration keyword at the beginning of a line.
+    // It's used to keep an unterminated composite literal from swallowing the
+    // declarations after it.
+    fn at_top_level_decl(&self) -> bool {
+        match self.token {
+            Token::FUNC | Token::TYPE | Token::VAR | Token::CONST | Token::IMPORT => {
+                self.file().position(self.pos).column == 1
+            }
+            _ => false,
+        }
+    }
+
     // advance consumes tokens until the current token p.tok
     // is in the 'to' set, or token.EOF. For error recovery.
     fn advance(&mut self, to: fn(&Token) -> bool) {
@@ -668,6 +906,8 @@ impl<'a> Parser<'a> {
     fn parse_field_decl(&mut self, scope: ScopeKey) -> FieldKey {
         self.trace_begin("FieldDecl");
 
+        let doc = self.lead_comment;
+
         // 1st FieldDecl
         // A type name used as an anonymous field looks like a field identifier.
         let mut list = vec![];
@@ -680,6 +920,8 @@ impl<'a> Parser<'a> {
         }
 
         let mut idents = vec![];
+        // the types in parentheses are resolved already
+        let mut parenthesized = false;
         let typ = match self.try_var_type(false) {
             Some(t) => {
                 idents = self.make_ident_list(&mut list);
@@ -687,18 +929,26 @@ impl<'a> Parser<'a> {
             }
             // ["*"] TypeName (AnonymousField)
             None => {
-                let first = &list[0]; // we always have at least one element
                 if list.len() > 1 {
                     self.error_expected(self.pos, "type");
                     Expr::new_bad(self.pos, self.pos)
-                } else if !Parser::is_type_name(Parser::deref(first)) {
-                    self.error_expected(self.pos, "anonymous field");
-                    Expr::new_bad(
-                        first.pos(&self.objects),
-                        self.safe_pos(first.end(&self.objects)),
-                    )
                 } else {
-                    list.into_iter().nth(0).unwrap()
+                    let first = list.pop().unwrap(); // we always have at least one element
+                    let (typ, paren) = Parser::unparen_embedded(&first);
+                    if !Parser::is_type_name(Parser::deref(&typ)) {
+                        self.error_expected(first.pos(self.objects), "anonymous field");
+                        Expr::new_bad(
+                            first.pos(&self.objects),
+                            self.safe_pos(first.end(&self.objects)),
+                        )
+                    } else {
+                        // (T), (*T) and *(T) are not allowed, go on with the type
+                        if let Some(pos) = paren {
+                            self.error_str(pos, "cannot parenthesize embedded type");
+                            parenthesized = true;
+                        }
+                        typ
+                    }
                 }
             }
         };
@@ -713,10 +963,10 @@ impl<'a> Parser<'a> {
             None
         };
 
-        self.expect_semi();
+        self.expect_semi(); // call before accessing self.line_comment
 
-        let to_resolve = typ.clone_ident();
-        let field = new_field!(self, idents, typ, tag);
+        let to_resolve = typ.clone_ident().filter(|_| !parenthesized);
+        let field = new_field!(self, doc, idents, typ, tag, self.line_comment);
         self.declare(
             DeclObj::Field(field),
             EntityData::NoData,
@@ -732,19 +982,30 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_struct_type(&mut self) -> Expr {
-        self.trace_begin("FieldDecl");
+        self.trace_begin("StructType");
 
         let stru = self.expect(&Token::STRUCT);
         let lbrace = self.expect(&Token::LBRACE);
         let scope = new_scope!(self, None);
         let mut list = vec![];
+        let mut incomplete = false;
         loop {
             match &self.token {
                 Token::IDENT(_) | Token::MUL | Token::LPAREN => {
                     list.push(self.parse_field_decl(scope));
                 }
+                Token::RBRACE | Token::EOF => break,
+                // an unclosed struct stops at the next declaration
+                _ if self.at_top_level_decl() => break,
                 _ => {
-                    break;
+                    self.error_expected(self.pos, "field name or embedded type");
+                    incomplete = true;
+                    if !matches!(self.token, Token::SEMICOLON(_)) {
+                        self.advance(|t| matches!(t, Token::SEMICOLON(_) | Token::RBRACE));
+                    }
+                    if let Token::SEMICOLON(_) = self.token {
+                        self.next();
+                    }
                 }
             }
         }
@@ -754,7 +1015,7 @@ impl<'a> Parser<'a> {
         Expr::Struct(Rc::new(StructType {
             struct_pos: stru,
             fields: FieldList::new(Some(lbrace), list, Some(rbrace)),
-            incomplete: false,
+            incomplete,
         }))
     }
 
@@ -931,6 +1192,7 @@ impl<'a> Parser<'a> {
     fn parse_method_spec(&mut self, scope: ScopeKey) -> FieldKey {
         self.trace_begin("MethodSpec");
 
+        let doc = self.lead_comment;
         let mut idents = vec![];
         let mut typ = self.parse_type_name();
         let ident = typ.try_as_ident();
@@ -943,8 +1205,8 @@ impl<'a> Parser<'a> {
             // embedded interface
             self.resolve(&typ);
         }
-        self.expect_semi();
-        let field = new_field!(self, idents, typ, None);
+        self.expect_semi(); // call before accessing self.line_comment
+        let field = new_field!(self, doc, idents, typ, None, self.line_comment);
         self.declare(
             DeclObj::Field(field),
             EntityData::NoData,
@@ -1133,7 +1395,13 @@ impl<'a> Parser<'a> {
             Expr::Func(typ_key)
         } else {
             self.expr_level += 1;
+            // the errors in the body are not counted as the errors of
+            // the composite literal it may be in
+            let lit_depth = std::mem::replace(&mut self.lit_depth, 0);
+            let lit_errors = self.lit_errors.get();
             let body = self.parse_body(scope);
+            self.lit_depth = lit_depth;
+            self.lit_errors.set(lit_errors);
             self.expr_level -= 1;
             Expr::FuncLit(Rc::new(FuncLit {
                 typ: typ_key,
@@ -1304,11 +1572,20 @@ impl<'a> Parser<'a> {
         self.expr_level += 1;
         let mut list = vec![];
         let mut ellipsis: Option<position::Pos> = None;
-        while self.token != Token::RPAREN && self.token != Token::EOF && ellipsis.is_none() {
+        let mut misplaced = false;
+        while self.token != Token::RPAREN && self.token != Token::EOF {
+            // the arguments after a '...' are parsed all the same, so that a
+            // misplaced one doesn't derail the rest of the statement
+            if let (Some(pos), false) = (ellipsis, misplaced) {
+                self.error_str(pos, "can only use ... with final argument in list");
+                misplaced = true;
+            }
             //// builtins may expect a type: make(some_type)
             list.push(self.parse_rhs_or_type());
             if self.token == Token::ELLIPSIS {
-                ellipsis = Some(self.pos);
+                if ellipsis.is_none() {
+                    ellipsis = Some(self.pos);
+                }
                 self.next();
             }
             if !self.at_comma("argument list", &Token::RPAREN) {
@@ -1392,20 +1669,79 @@ impl<'a> Parser<'a> {
         ret
     }
 
-    fn parse_element_list(&mut self) -> Vec<Expr> {
+    // parse_element_list parses the elements up to the closing '}', which is
+    // not consumed. It returns false if the literal is cut off by EOF or a top
+    // level declaration, the error is reported at the opening brace.
+    fn parse_element_list(&mut self, lbrace: position::Pos) -> (Vec<Expr>, bool) {
         self.trace_begin("ElementList");
 
         let mut list = vec![];
-        while self.token != Token::RBRACE && self.token != Token::EOF {
-            list.push(self.parse_element());
-            if !self.at_comma("composite literal", &Token::RBRACE) {
-                break;
+        let closed = loop {
+            if self.token == Token::RBRACE {
+                break true;
+            }
+            if self.token == Token::EOF || self.at_top_level_decl() {
+                // only the innermost one of nested literals is reported, and none
+                // after giving up on the file, the EOF isn't real then
+                if !self.lit_unclosed && !self.bailed_out.get() {
+                    self.lit_unclosed = true;
+                    FilePosErrors::new(self.file(), self.errors)
+                        .parser_add_str(lbrace, "missing '}' in composite literal");
+                }
+                break false;
             }
-            self.next();
-        }
+            if self.token.is_expr_start() {
+                list.push(self.parse_element());
+            } else {
+                let msg = format!("unexpected {} in composite literal", self.token);
+                self.error(self.pos, msg);
+                self.skip_to_element_end();
+            }
+            match &self.token {
+                Token::COMMA => self.next(),
+                Token::RBRACE | Token::EOF => {}
+                Token::SEMICOLON(real) if !*real.as_bool() => {
+                    // the next line is most likely the next element
+                    self.error_str(self.pos, "missing ',' before newline in composite literal");
+                    self.next();
+                }
+                _ if self.at_top_level_decl() => {}
+                t if t.is_expr_start() => {
+                    // parse the next element from here
+                    self.error_str(self.pos, "missing ',' in composite literal");
+                }
+                _ => {
+                    let msg = format!("unexpected {} in composite literal", self.token);
+                    self.error(self.pos, msg);
+                    self.skip_to_element_end();
+                    if self.token == Token::COMMA {
+                        self.next();
+                    }
+                }
+            }
+        };
 
         self.trace_end();
-        list
+        (list, closed)
+    }
+
+    // skip_to_element_end consumes tokens up to the next ',' or '}' of the
+    // current composite literal, counting the braces in between so that the '}'
+    // of a nested literal or function body doesn't end it. It also stops at EOF
+    // and top level declarations.
+    fn skip_to_element_end(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.token {
+                Token::EOF => break,
+                Token::COMMA | Token::RBRACE if depth == 0 => break,
+                Token::LBRACE => depth += 1,
+                Token::RBRACE => depth -= 1,
+                _ if self.at_top_level_decl() => break,
+                _ => {}
+            }
+            self.next();
+        }
     }
 
     fn parse_literal_value(&mut self, typ: Option<Expr>) -> Expr {
@@ -1413,13 +1749,21 @@ impl<'a> Parser<'a> {
 
         let lbrace = self.expect(&Token::LBRACE);
         self.expr_level += 1;
-        let elts = if self.token != Token::RBRACE {
-            self.parse_element_list()
+        if self.lit_depth == 0 {
+            self.lit_errors.set(0);
+        }
+        self.lit_depth += 1;
+        let (elts, closed) = if self.token != Token::RBRACE {
+            self.parse_element_list(lbrace)
         } else {
-            vec![]
+            (vec![], true)
         };
+        self.lit_depth -= 1;
         self.expr_level -= 1;
-        let rbrace = self.expect_closing(&Token::RBRACE, "composite literal");
+        let rbrace = match closed {
+            true => self.expect_closing(&Token::RBRACE, "composite literal"),
+            false => self.pos,
+        };
 
         self.trace_end();
         Expr::CompositeLit(Rc::new(CompositeLit {
@@ -1427,7 +1771,7 @@ impl<'a> Parser<'a> {
             l_brace: lbrace,
             elts: elts,
             r_brace: rbrace,
-            incomplete: false,
+            incomplete: !closed,
         }))
     }
 
@@ -1501,6 +1845,25 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // The type of an embedded field without the parentheses around it or around
+    // its base type, and the position of the parenthesis removed first.
+    fn unparen_embedded(x: &Expr) -> (Expr, Option<position::Pos>) {
+        match x {
+            Expr::Paren(p) => (Parser::unparen_embedded(&p.expr).0, Some(p.l_paren)),
+            Expr::Star(s) => match &s.expr {
+                Expr::Paren(p) => (
+                    Expr::Star(Rc::new(StarExpr {
+                        star: s.star,
+                        expr: Parser::unparen(&p.expr).clone(),
+                    })),
+                    Some(p.l_paren),
+                ),
+                _ => (x.clone(), None),
+            },
+            _ => (x.clone(), None),
+        }
+    }
+
     pub fn unparen(x: &Expr) -> &Expr {
         if let Expr::Paren(p) = x {
             Parser::unparen(&p.expr)
@@ -1921,6 +2284,7 @@ impl<'a> Parser<'a> {
             Token::SEMICOLON(_) | Token::RBRACE => vec![],
             _ => self.parse_rhs_list(),
         };
+        self.expect_semi();
 
         self.trace_end();
         Stmt::Return(Rc::new(ReturnStmt {
@@ -2184,8 +2548,22 @@ impl<'a> Parser<'a> {
         let type_switch = self.is_type_switch_guard(&s2);
         let lbrace = self.expect(&Token::LBRACE);
         let mut list = vec![];
+        let mut first_default = None;
         while self.token == Token::CASE || self.token == Token::DEFAULT {
             let clause = self.parse_case_clause(type_switch);
+            if clause.list.is_none() {
+                match first_default {
+                    Some(first) => {
+                        let p = self.file().position(first);
+                        let msg = format!(
+                            "multiple defaults in switch (first at {}:{})",
+                            p.line, p.column
+                        );
+                        self.error(clause.case, msg);
+                    }
+                    None => first_default = Some(clause.case),
+                }
+            }
             list.push(Stmt::Case(Rc::new(clause)));
         }
         let rbrace = self.expect(&Token::RBRACE);
@@ -2249,8 +2627,13 @@ impl<'a> Parser<'a> {
                     }
                     let pos = self.pos;
                     self.next();
-                    let rhs = self.parse_rhs();
-                    let ass = Stmt::new_assign(&mut self.objects, lhs, pos, tk.clone(), vec![rhs]);
+                    let mut rhs = self.parse_rhs_list();
+                    if rhs.len() > 1 {
+                        self.error_expected(rhs[1].pos(self.objects), "1 expression");
+                        rhs.truncate(1);
+                    }
+                    self.check_recv(&rhs[0]);
+                    let ass = Stmt::new_assign(self.objects, lhs, pos, tk.clone(), rhs);
                     if tk == Token::DEFINE {
                         self.short_var_decl(&ass);
                     }
@@ -2260,7 +2643,9 @@ impl<'a> Parser<'a> {
                         self.error_expected(lhs[0].pos(&self.objects), "1 expression");
                         // continue with first expression
                     }
-                    Some(Stmt::Expr(Box::new(lhs.into_iter().nth(0).unwrap())))
+                    let x = lhs.into_iter().nth(0).unwrap();
+                    self.check_recv(&x);
+                    Some(Stmt::Expr(Box::new(x)))
                 }
             }
         } else {
@@ -2280,6 +2665,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // checkRecv checks that x is a receive operation, the only
+    // expression a comm clause can have besides a send.
+    fn check_recv(&self, x: &Expr) {
+        match Parser::unparen(x) {
+            Expr::Unary(u) if u.op == Token::ARROW => {}
+            _ => self.error_expected(x.pos(self.objects), "receive operation"),
+        }
+    }
+
     fn parse_select_stmt(&mut self) -> Stmt {
         self.trace_begin("SelectStmt");
 
@@ -2358,6 +2752,9 @@ impl<'a> Parser<'a> {
 
         let ret = if is_range {
             if let Stmt::Assign(idx) = s2.unwrap() {
+                // the range expression is moved out of the assignment, which
+                // is not part of the tree, so that no node is shared
+                let rhs = std::mem::take(&mut self.objects.a_stmts[idx].rhs);
                 let ass = &self.objects.a_stmts[idx];
                 let (key, val) = match ass.lhs.len() {
                     0 => (None, None),
@@ -2375,14 +2772,18 @@ impl<'a> Parser<'a> {
                 };
                 // parseSimpleStmt returned a right-hand side that
                 // is a single unary expression of the form "range x"
-                if let Expr::Unary(unary) = ass.rhs[0].clone() {
+                if let Some(Expr::Unary(unary)) = rhs.into_iter().next() {
+                    let expr = match Rc::try_unwrap(unary) {
+                        Ok(unary) => unary.expr,
+                        Err(unary) => unary.expr.clone(),
+                    };
                     Stmt::Range(Rc::new(RangeStmt {
                         for_pos: pos,
                         key: key,
                         val: val,
                         token_pos: ass.token_pos,
                         token: ass.token.clone(),
-                        expr: unary.expr.clone(),
+                        expr,
                         body: Rc::new(body),
                     }))
                 } else {
@@ -2478,7 +2879,7 @@ impl<'a> Parser<'a> {
             .is_none()
     }
 
-    fn parse_import_spec(&mut self, _: &Token, _: isize) -> SpecKey {
+    fn parse_import_spec(&mut self, doc: Option<CommentGroupKey>, _: &Token, _: isize) -> SpecKey {
         self.trace_begin("ImportSpec");
 
         let ident = match self.token {
@@ -2509,13 +2910,15 @@ impl<'a> Parser<'a> {
                 token
             }
         };
-        self.expect_semi();
+        self.expect_semi(); // call before accessing self.line_comment
         let index = self.objects.specs.insert(Spec::Import(Rc::new(ImportSpec {
+            doc,
             name: ident,
             path: BasicLit {
                 pos: pos,
                 token: path_token,
             },
+            comment: self.line_comment,
             end_pos: None,
         })));
         self.imports.push(index);
@@ -2526,6 +2929,7 @@ impl<'a> Parser<'a> {
 
     fn parse_value_spec<'p, 'k>(
         self_: &'p mut Parser<'a>,
+        doc: Option<CommentGroupKey>,
         keyword: &'k Token,
         iota: isize,
     ) -> SpecKey {
@@ -2541,7 +2945,7 @@ impl<'a> Parser<'a> {
             }
             _ => vec![],
         };
-        self_.expect_semi();
+        self_.expect_semi(); // call before accessing self_.line_comment
 
         match keyword {
             Token::VAR => {
@@ -2562,9 +2966,11 @@ impl<'a> Parser<'a> {
         // the end of the innermost containing block.
         // (Global identifiers are resolved in a separate phase after parsing.)
         let spec = self_.objects.specs.insert(Spec::Value(Rc::new(ValueSpec {
+            doc,
             names: idents,
             typ,
             values,
+            comment: self_.line_comment,
         })));
         let kind = if let Token::VAR = keyword {
             EntityKind::Var
@@ -2582,7 +2988,7 @@ impl<'a> Parser<'a> {
         spec
     }
 
-    fn parse_type_spec(&mut self, _: &Token, _: isize) -> SpecKey {
+    fn parse_type_spec(&mut self, doc: Option<CommentGroupKey>, _: &Token, _: isize) -> SpecKey {
         self.trace_begin("TypeSpec");
 
         let ident = self.parse_ident();
@@ -2592,9 +2998,11 @@ impl<'a> Parser<'a> {
         // (Global identifiers are resolved in a separate phase after parsing.)
         let placeholder = Expr::new_bad(0, 0);
         let spec_val = Spec::Type(Rc::new(TypeSpec {
+            doc,
             name: ident,
             assign: 0,
             typ: placeholder,
+            comment: None,
         }));
         let index = self.objects.specs.insert(spec_val);
         let scope = self.top_scope.unwrap();
@@ -2605,8 +3013,9 @@ impl<'a> Parser<'a> {
             &scope,
         );
         let assign = if self.token == Token::ASSIGN {
+            let pos = self.pos;
             self.next();
-            self.pos
+            pos
         } else {
             0
         };
@@ -2618,7 +3027,10 @@ impl<'a> Parser<'a> {
         };
         spec.assign = assign;
         spec.typ = typ;
-        self.expect_semi();
+        self.expect_semi(); // call before accessing self.line_comment
+        if let Spec::Type(ts) = &mut self.objects.specs[index] {
+            Rc::get_mut(ts).unwrap().comment = self.line_comment;
+        }
 
         self.trace_end();
         index
@@ -2627,10 +3039,11 @@ impl<'a> Parser<'a> {
     fn parse_gen_decl(
         &mut self,
         keyword: &Token,
-        f: fn(&mut Parser<'a>, &Token, isize) -> SpecKey,
+        f: fn(&mut Parser<'a>, Option<CommentGroupKey>, &Token, isize) -> SpecKey,
     ) -> Decl {
         self.trace_begin(&format!("GenDecl({})", keyword.text()));
 
+        let doc = self.lead_comment;
         let pos = self.expect(keyword);
         let (lparen, rparen, list) = if self.token == Token::LPAREN {
             let lp = self.pos;
@@ -2638,18 +3051,19 @@ impl<'a> Parser<'a> {
             let mut list = vec![];
             let mut iota = 0;
             while self.token != Token::RPAREN && self.token != Token::EOF {
-                list.push(f(self, keyword, iota));
+                list.push(f(self, self.lead_comment, keyword, iota));
                 iota += 1;
             }
             let rp = self.expect(&Token::RPAREN);
             self.expect_semi();
             (Some(lp), Some(rp), list)
         } else {
-            (None, None, vec![f(self, keyword, 0)])
+            (None, None, vec![f(self, None, keyword, 0)])
         };
 
         self.trace_end();
         Decl::Gen(Rc::new(GenDecl {
+            doc,
             token_pos: pos,
             token: keyword.clone(),
             l_paran: lparen,
@@ -2661,10 +3075,21 @@ impl<'a> Parser<'a> {
     fn parse_func_decl(&mut self) -> Decl {
         self.trace_begin("FunctionDecl");
 
+        let doc = self.lead_comment;
         let pos = self.expect(&Token::FUNC);
         let scope = new_scope!(self, self.top_scope);
         let recv = if self.token == Token::LPAREN {
-            Some(self.parse_parameters(scope, false))
+            let rpos = self.pos;
+            let recv = self.parse_parameters(scope, false);
+            let count: usize = recv
+                .list
+                .iter()
+                .map(|f| self.objects.fields[*f].names.len().max(1))
+                .sum();
+            if count > 1 {
+                self.error_str(rpos, "method has multiple receivers");
+            }
+            Some(recv)
         } else {
             None
         };
@@ -2684,6 +3109,7 @@ impl<'a> Parser<'a> {
             results: results,
         });
         let decl = self.objects.fdecls.insert(FuncDecl {
+            doc,
             recv: recv,
             name: ident,
             typ: typ,
@@ -2748,7 +3174,52 @@ impl<'a> Parser<'a> {
     pub fn parse_file(&mut self) -> Option<File> {
         self.trace_begin("File");
 
+        let file = self.parse_package_clause(None).map(|(pos, ident)| {
+            let mut decls = vec![];
+            // import decls
+            while self.token == Token::IMPORT {
+                decls.push(self.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec));
+            }
+            // rest of package body
+            while self.token != Token::EOF {
+                decls.push(self.parse_decl(Token::is_decl_start))
+            }
+            self.finish_file(pos, ident, decls)
+        });
+
+        self.trace_end();
+        file
+    }
+
+    /// Parses the source as a single expression, which may also be a type like
+    /// `map[string]int`. Nothing may follow it but the end of the source.
+    pub fn parse_single_expr(&mut self) -> Expr {
+        self.trace_begin("SingleExpr");
+        self.open_scope();
+        self.pkg_scope = self.top_scope;
+        let x = self.parse_rhs_or_type();
+        self.close_scope();
+        // the semicolon inserted at the end of the line
+        if let Token::SEMICOLON(real) = &self.token {
+            if !*real.as_bool() {
+                self.next();
+            }
+        }
+        if self.token != Token::EOF {
+            self.error_expected(self.pos, "EOF");
+        }
+        self.trace_end();
+        x
+    }
+
+    // Parses the package clause and opens the package scope, `pkg_scope` is
+    // cleared and reused if given.
+    fn parse_package_clause(
+        &mut self,
+        pkg_scope: Option<ScopeKey>,
+    ) -> Option<(position::Pos, IdentKey)> {
         let err_count = self.errors.len();
+        self.file_doc = self.lead_comment;
         let pos = self.expect(&Token::PACKAGE);
         // Go spec: The package clause is not a declaration;
         // the package name does not appear in any scope.
@@ -2761,52 +3232,132 @@ impl<'a> Parser<'a> {
         // Don't bother parsing the rest if we had errors parsing the package clause.
         // Likely not a Go source file at all.
         if self.errors.len() > err_count {
-            self.trace_end();
             return None;
         }
 
-        self.open_scope();
-        self.pkg_scope = self.top_scope;
-        let mut decls = vec![];
-        // import decls
-        while self.token == Token::IMPORT {
-            decls.push(self.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec));
-        }
-        // rest of package body
-        while self.token != Token::EOF {
-            decls.push(self.parse_decl(Token::is_decl_start))
+        match pkg_scope {
+            Some(key) => {
+                self.objects.scopes[key] = Scope::new(None);
+                self.top_scope = Some(key);
+            }
+            None => self.open_scope(),
         }
+        self.pkg_scope = self.top_scope;
+        Some((pos, ident))
+    }
+
+    fn finish_file(&mut self, pos: position::Pos, ident: IdentKey, decls: Vec<Decl>) -> File {
         self.close_scope();
         assert!(self.top_scope.is_none(), "unbalanced scopes");
         assert!(self.label_scope.is_none(), "unbalanced label scopes");
 
-        // resolve global identifiers within the same file
+        // resolve global identifiers within the same file, the ones left
+        // are declared in other files of the package or in the universe
         self.unresolved = self
             .unresolved
             .to_owned()
             .into_iter()
-            .filter_map(|x| {
-                let ident = &mut self.objects.idents[x];
+            .filter(|x| {
+                let ident = &mut self.objects.idents[*x];
                 let scope = &self.objects.scopes[self.pkg_scope.unwrap()];
-                let entity = scope.look_up(&ident.name);
-                if let Some(en) = entity {
-                    ident.entity = IdentEntity::Entity(*en);
-                    Some(x)
-                } else {
-                    None
+                match scope.look_up(&ident.name) {
+                    Some(en) => {
+                        ident.entity = IdentEntity::Entity(*en);
+                        false
+                    }
+                    None => true,
                 }
             })
             .collect();
 
-        self.trace_end();
-        Some(File {
+        File {
             package: pos,
             name: ident,
             decls: decls,
             scope: self.pkg_scope.unwrap(),
             imports: self.imports.clone(),
             unresolved: self.unresolved.clone(),
-        })
+            doc: self.file_doc,
+            comments: self.comments.clone(),
+        }
+    }
+
+    // ----------------------------------------------------------------------------
+    // Incremental parsing support, the top level declarations are parsed one at
+    // a time by the caller, which may skip the ones it has from a previous parse.
+
+    pub(crate) fn begin_incremental(
+        &mut self,
+        pkg_scope: Option<ScopeKey>,
+    ) -> Option<(position::Pos, IdentKey)> {
+        self.incremental = true;
+        self.parse_package_clause(pkg_scope)
+    }
+
+    pub(crate) fn end_incremental(
+        &mut self,
+        pos: position::Pos,
+        ident: IdentKey,
+        decls: Vec<Decl>,
+    ) -> File {
+        self.finish_file(pos, ident, decls)
+    }
+
+    pub(crate) fn token(&self) -> (&Token, position::Pos) {
+        (&self.token, self.pos)
+    }
+
+    pub(crate) fn objects(&mut self) -> &mut AstObjects {
+        self.objects
+    }
+
+    pub(crate) fn pkg_scope(&self) -> ScopeKey {
+        self.pkg_scope.unwrap()
+    }
+
+    pub(crate) fn scanner_offsets(&self) -> (usize, usize, usize) {
+        self.scanner.offsets()
+    }
+
+    pub(crate) fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    // The parser is in the state it's in between two top level declarations
+    // after parsing the ones before without errors.
+    pub(crate) fn at_decl_boundary(&self) -> bool {
+        !self.lit_unclosed && self.top_scope == self.pkg_scope
+    }
+
+    // Parses the top level declaration at the current token, returns what it
+    // resolved to and declared at package level.
+    pub(crate) fn parse_top_decl(
+        &mut self,
+        import: bool,
+    ) -> (Decl, Vec<FreeIdent>, Vec<EntityKey>) {
+        let decl = if import {
+            self.parse_gen_decl(&Token::IMPORT, Parser::parse_import_spec)
+        } else {
+            self.parse_decl(Token::is_decl_start)
+        };
+        let free = std::mem::take(&mut self.free_idents);
+        let entities = std::mem::take(&mut self.pkg_entities);
+        (decl, free, entities)
+    }
+
+    // Adds a top level declaration from a previous parse, the parser continues
+    // with the token at the given offsets.
+    pub(crate) fn skip_decl(
+        &mut self,
+        imports: &[SpecKey],
+        unresolved: &[IdentKey],
+        offset: usize,
+        byte_offset: usize,
+    ) {
+        self.imports.extend_from_slice(imports);
+        self.unresolved.extend_from_slice(unresolved);
+        self.scanner.seek(offset, byte_offset);
+        self.next();
     }
 }
 
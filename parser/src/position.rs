@@ -21,7 +21,7 @@ use std::rc::Rc;
 
 pub type Pos = usize;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FilePos {
     pub filename: Rc<String>,
     pub offset: usize, // offset in utf8 char
@@ -63,7 +63,7 @@ impl fmt::Display for FilePos {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct File {
     name: Rc<String>,
     base: usize,
@@ -97,6 +97,10 @@ impl File {
         self.lines.len()
     }
 
+    pub(crate) fn lines(&self) -> &[usize] {
+        &self.lines
+    }
+
     pub fn add_line(&mut self, offset: usize) {
         let i = self.line_count();
         if (i == 0 || self.lines[i - 1] < offset) && offset < self.size {
@@ -127,8 +131,8 @@ impl File {
 
     pub fn set_lines(&mut self, lines: Vec<usize>) -> bool {
         let size = self.size;
-        for (i, &offset) in self.lines.iter().enumerate() {
-            if (i == 0 && size <= offset) || offset < lines[i - 1] {
+        for (i, &offset) in lines.iter().enumerate() {
+            if (i > 0 && offset <= lines[i - 1]) || size <= offset {
                 return false;
             }
         }
@@ -167,29 +171,28 @@ impl File {
         self.base() + offset
     }
 
+    /// The line number of `p`, 0 for an invalid position
+    pub fn line(&self, p: Pos) -> usize {
+        if p < self.base || p > self.base + self.size {
+            return 0;
+        }
+        let offset = p - self.base;
+        self.lines.partition_point(|&line| line <= offset)
+    }
+
+    /// The file, 1-based line and column of `p`, found by a binary search of the
+    /// line table the scanner fills with `add_line`
     pub fn position(&self, p: Pos) -> FilePos {
         if p < self.base || p > self.base + self.size {
             panic!("illegal Pos value");
         }
-
-        let line_count = self.line_count();
         let offset = p - self.base;
-        let line = match self
-            .lines
-            .iter()
-            .enumerate()
-            .find(|&(_, &line)| line > offset)
-        {
-            Some((i, _)) => i,
-            None => line_count,
-        };
-        let column = offset - self.lines[line - 1] + 1;
-
+        let line = self.line(p);
         FilePos {
             filename: self.name.clone(),
             line: line,
             offset: offset,
-            column: column,
+            column: offset - self.lines[line - 1] + 1,
         }
     }
 }
@@ -224,7 +227,7 @@ impl BorshDeserialize for File {
 }
 
 #[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct FileSet {
     base: usize,
     files: Vec<File>,
@@ -246,13 +249,10 @@ impl FileSet {
         FileSetIter { fs: self, cur: 0 }
     }
 
+    /// The file `p` is in, the files are sorted by base
     pub fn file(&self, p: Pos) -> Option<&File> {
-        for f in self.files.iter() {
-            if f.base <= p && f.base + f.size >= p {
-                return Some(f.borrow());
-            }
-        }
-        None
+        let i = self.files.partition_point(|f| f.base <= p);
+        self.files[..i].last().filter(|f| p <= f.base + f.size)
     }
 
     pub fn position(&self, p: Pos) -> Option<FilePos> {
@@ -285,7 +285,7 @@ impl FileSet {
         let mut f = File::new(name);
         f.base = real_base;
         f.size = size;
-        let set_base = self.base + size + 1; // +1 because EOF also has a position
+        let set_base = real_base + size + 1; // +1 because EOF also has a position
         if set_base < self.base {
             panic!("token.Pos offset overflow (> 2G of source code in file set)");
         }
@@ -348,4 +348,38 @@ mod test {
         }
         print!("\nfile at 100: {:?}", fs.file(100))
     }
+
+    #[test]
+    fn test_file_set_positions() {
+        let mut fs = FileSet::new();
+        // "ab\ncd\n\nef", the EOF has a position too
+        let a = fs.add_file("a.go".to_owned(), None, 9);
+        for offset in [3, 6, 7] {
+            a.add_line(offset);
+        }
+        // lines are added in order only
+        a.add_line(5);
+        assert_eq!(a.line_count(), 4);
+        let b = fs.add_file("b.go".to_owned(), Some(20), 4);
+        b.add_line(2);
+        assert_eq!(fs.base(), 25);
+        let c = fs.add_file("c.go".to_owned(), None, 0);
+        assert_eq!(c.base(), 25);
+
+        let at = |p: Pos| fs.position(p).map(|p| p.to_string());
+        assert_eq!(at(0).unwrap(), "a.go:1:1");
+        assert_eq!(at(2).unwrap(), "a.go:1:3");
+        assert_eq!(at(3).unwrap(), "a.go:2:1");
+        assert_eq!(at(6).unwrap(), "a.go:3:1");
+        assert_eq!(at(8).unwrap(), "a.go:4:2");
+        assert_eq!(at(9).unwrap(), "a.go:4:3");
+        // between the files
+        assert_eq!(at(10), None);
+        assert_eq!(at(20).unwrap(), "b.go:1:1");
+        assert_eq!(at(23).unwrap(), "b.go:2:2");
+        assert_eq!(at(24).unwrap(), "b.go:2:3");
+        assert_eq!(at(25).unwrap(), "c.go:1:1");
+        assert_eq!(at(26), None);
+        assert_eq!(fs.file(21).unwrap().line(23), 2);
+    }
 }
@@ -0,0 +1,608 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Prints the AST back to Go source.
+//!
+//! The output parses to the same AST, but it's not formatted like gofmt does, and
+//! the comments are dropped. Parentheses are added where the precedence of the
+//! operators or the association of `<-` with `chan` needs them, an AST from the
+//! parser already has them as `ParenExpr`s.
+
+use super::ast::*;
+use super::objects::*;
+use super::token::LOWEST_PREC;
+
+/// Prints the file as Go source
+pub fn print_file(file: &File, objs: &AstObjects) -> String {
+    let mut p = Printer::new(objs);
+    p.file(file);
+    p.out
+}
+
+/// Prints the expression as Go source
+pub fn print_expr(expr: &Expr, objs: &AstObjects) -> String {
+    let mut p = Printer::new(objs);
+    p.expr(expr);
+    p.out
+}
+
+/// Prints the statement as Go source, a block is printed on several lines
+pub fn print_stmt(stmt: &Stmt, objs: &AstObjects) -> String {
+    let mut p = Printer::new(objs);
+    p.stmt(stmt);
+    p.out
+}
+
+/// Prints the declaration as Go source
+pub fn print_decl(decl: &Decl, objs: &AstObjects) -> String {
+    let mut p = Printer::new(objs);
+    p.decl(decl);
+    p.out
+}
+
+// the precedence of the unary expressions and the operands
+const UNARY_PREC: usize = 6;
+
+struct Printer<'a> {
+    objs: &'a AstObjects,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(objs: &'a AstObjects) -> Printer<'a> {
+        Printer {
+            objs,
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push('\t');
+        }
+    }
+
+    fn ident(&mut self, i: IdentKey) {
+        let objs = self.objs;
+        self.push(&objs.idents[i].name);
+    }
+
+    fn idents(&mut self, is: &[IdentKey]) {
+        for (n, &i) in is.iter().enumerate() {
+            if n > 0 {
+                self.push(", ");
+            }
+            self.ident(i);
+        }
+    }
+
+    fn exprs(&mut self, es: &[Expr]) {
+        for (n, e) in es.iter().enumerate() {
+            if n > 0 {
+                self.push(", ");
+            }
+            self.expr(e);
+        }
+    }
+
+    fn file(&mut self, file: &File) {
+        self.push("package ");
+        self.ident(file.name);
+        self.push("\n");
+        for d in file.decls.iter() {
+            self.push("\n");
+            self.decl(d);
+            self.push("\n");
+        }
+    }
+
+    fn decl(&mut self, d: &Decl) {
+        match d {
+            Decl::Bad(_) => self.push("/* bad declaration */"),
+            Decl::Gen(g) => {
+                self.push(g.token.text());
+                self.push(" ");
+                match g.l_paran {
+                    Some(_) => {
+                        self.push("(");
+                        self.indent += 1;
+                        for &s in g.specs.iter() {
+                            self.newline();
+                            self.spec(s);
+                        }
+                        self.indent -= 1;
+                        self.newline();
+                        self.push(")");
+                    }
+                    None => {
+                        for &s in g.specs.iter() {
+                            self.spec(s);
+                        }
+                    }
+                }
+            }
+            Decl::Func(key) => {
+                let objs = self.objs;
+                let fd = &objs.fdecls[*key];
+                self.push("func ");
+                if let Some(recv) = &fd.recv {
+                    self.params(recv);
+                    self.push(" ");
+                }
+                self.ident(fd.name);
+                self.signature(fd.typ);
+                if let Some(body) = &fd.body {
+                    self.push(" ");
+                    self.block(body);
+                }
+            }
+        }
+    }
+
+    fn spec(&mut self, key: SpecKey) {
+        let objs = self.objs;
+        match &objs.specs[key] {
+            Spec::Import(s) => {
+                if let Some(name) = s.name {
+                    self.ident(name);
+                    self.push(" ");
+                }
+                self.push(s.path.token.get_literal());
+            }
+            Spec::Value(s) => {
+                self.idents(&s.names);
+                if let Some(t) = &s.typ {
+                    self.push(" ");
+                    self.expr(t);
+                }
+                if !s.values.is_empty() {
+                    self.push(" = ");
+                    self.exprs(&s.values);
+                }
+            }
+            Spec::Type(s) => {
+                self.ident(s.name);
+                self.push(if s.assign > 0 { " = " } else { " " });
+                self.expr(&s.typ);
+            }
+        }
+    }
+
+    /// A parameter or result list in parentheses
+    fn params(&mut self, fl: &FieldList) {
+        let objs = self.objs;
+        self.push("(");
+        for (n, &f) in fl.list.iter().enumerate() {
+            if n > 0 {
+                self.push(", ");
+            }
+            let f = &objs.fields[f];
+            if !f.names.is_empty() {
+                self.idents(&f.names);
+                self.push(" ");
+            }
+            self.expr(&f.typ);
+        }
+        self.push(")");
+    }
+
+    /// The parameters and the results of a function type, without the `func`
+    fn signature(&mut self, key: FuncTypeKey) {
+        let objs = self.objs;
+        let ft = &objs.ftypes[key];
+        self.params(&ft.params);
+        if let Some(results) = &ft.results {
+            self.push(" ");
+            match &results.list[..] {
+                [f] if objs.fields[*f].names.is_empty() => self.expr(&objs.fields[*f].typ),
+                _ => self.params(results),
+            }
+        }
+    }
+
+    /// The fields of a struct or the methods of an interface, one on a line
+    fn members(&mut self, fl: &FieldList, methods: bool) {
+        let objs = self.objs;
+        if fl.list.is_empty() {
+            self.push("{}");
+            return;
+        }
+        self.push(" {");
+        self.indent += 1;
+        for &f in fl.list.iter() {
+            self.newline();
+            let f = &objs.fields[f];
+            match (&f.typ, methods && !f.names.is_empty()) {
+                (Expr::Func(ft), true) => {
+                    self.idents(&f.names);
+                    self.signature(*ft);
+                }
+                _ => {
+                    if !f.names.is_empty() {
+                        self.idents(&f.names);
+                        self.push(" ");
+                    }
+                    self.expr(&f.typ);
+                }
+            }
+            if let Some(tag) = &f.tag {
+                self.push(" ");
+                self.expr(tag);
+            }
+        }
+        self.indent -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        self.expr_prec(e, LOWEST_PREC);
+    }
+
+    /// Prints `e` in parentheses if it binds more loosely than `prec`
+    fn expr_prec(&mut self, e: &Expr, prec: usize) {
+        let own = match e {
+            Expr::Binary(b) => b.op.precedence(),
+            Expr::Unary(_) | Expr::Star(_) => UNARY_PREC,
+            // a receive-only channel type would take a `<-` in front of it
+            // and the `chan` before it, see `chan_type`
+            _ => UNARY_PREC + 1,
+        };
+        if own < prec {
+            self.push("(");
+            self.expr_unparen(e);
+            self.push(")");
+        } else {
+            self.expr_unparen(e);
+        }
+    }
+
+    fn expr_unparen(&mut self, e: &Expr) {
+        match e {
+            Expr::Bad(_) => self.push("BadExpr"),
+            Expr::Ident(i) => self.ident(*i),
+            Expr::Ellipsis(e) => {
+                self.push("...");
+                if let Some(elt) = &e.elt {
+                    self.expr(elt);
+                }
+            }
+            Expr::BasicLit(l) => self.push(l.token.get_literal()),
+            Expr::FuncLit(f) => {
+                self.push("func");
+                self.signature(f.typ);
+                self.push(" ");
+                self.block(&f.body);
+            }
+            Expr::CompositeLit(c) => {
+                if let Some(t) = &c.typ {
+                    self.expr_prec(t, UNARY_PREC + 1);
+                }
+                self.push("{");
+                self.exprs(&c.elts);
+                self.push("}");
+            }
+            Expr::Paren(p) => {
+                self.push("(");
+                self.expr(&p.expr);
+                self.push(")");
+            }
+            Expr::Selector(s) => {
+                self.expr_prec(&s.expr, UNARY_PREC + 1);
+                self.push(".");
+                self.ident(s.sel);
+            }
+            Expr::Index(i) => {
+                self.expr_prec(&i.expr, UNARY_PREC + 1);
+                self.push("[");
+                self.expr(&i.index);
+                self.push("]");
+            }
+            Expr::Slice(s) => {
+                self.expr_prec(&s.expr, UNARY_PREC + 1);
+                self.push("[");
+                let bounds = [&s.low, &s.high, &s.max];
+                let n = if s.slice3 { 3 } else { 2 };
+                for (i, b) in bounds[..n].iter().enumerate() {
+                    if i > 0 {
+                        self.push(":");
+                    }
+                    if let Some(b) = b {
+                        self.expr(b);
+                    }
+                }
+                self.push("]");
+            }
+            Expr::TypeAssert(t) => {
+                self.expr_prec(&t.expr, UNARY_PREC + 1);
+                self.push(".(");
+                match &t.typ {
+                    Some(typ) => self.expr(typ),
+                    None => self.push("type"),
+                }
+                self.push(")");
+            }
+            Expr::Call(c) => {
+                self.expr_prec(&c.func, UNARY_PREC + 1);
+                self.push("(");
+                self.exprs(&c.args);
+                if c.ellipsis.is_some() {
+                    self.push("...");
+                }
+                self.push(")");
+            }
+            Expr::Star(s) => {
+                self.push("*");
+                self.expr_prec(&s.expr, UNARY_PREC);
+            }
+            Expr::Unary(u) => {
+                let op = u.op.text();
+                self.push(op);
+                let start = self.out.len();
+                self.expr_prec(&u.expr, UNARY_PREC);
+                // "- -x" is not "--x", and "& ^x" is not "&^x"
+                let fused = matches!(
+                    (op, self.out[start..].chars().next()),
+                    ("-", Some('-')) | ("+", Some('+')) | ("&", Some('&' | '^'))
+                );
+                if fused {
+                    self.out.insert(start, ' ');
+                }
+            }
+            Expr::Binary(b) => {
+                let prec = b.op.precedence();
+                self.expr_prec(&b.expr_a, prec);
+                self.push(" ");
+                self.push(b.op.text());
+                self.push(" ");
+                // the operators associate to the left
+                self.expr_prec(&b.expr_b, prec + 1);
+            }
+            Expr::KeyValue(kv) => {
+                self.expr(&kv.key);
+                self.push(": ");
+                self.expr(&kv.val);
+            }
+            Expr::Array(a) => {
+                self.push("[");
+                if let Some(len) = &a.len {
+                    self.expr(len);
+                }
+                self.push("]");
+                self.expr(&a.elt);
+            }
+            Expr::Struct(s) => {
+                self.push("struct");
+                self.members(&s.fields, false);
+            }
+            Expr::Func(ft) => {
+                self.push("func");
+                self.signature(*ft);
+            }
+            Expr::Interface(i) => {
+                self.push("interface");
+                self.members(&i.methods, true);
+            }
+            Expr::Map(m) => {
+                self.push("map[");
+                self.expr(&m.key);
+                self.push("]");
+                self.expr(&m.val);
+            }
+            Expr::Chan(c) => self.chan_type(c),
+        }
+    }
+
+    fn chan_type(&mut self, c: &ChanType) {
+        self.push(match c.dir {
+            ChanDir::SendRecv => "chan ",
+            ChanDir::Send => "chan<- ",
+            ChanDir::Recv => "<-chan ",
+        });
+        // "chan <-chan T" is parsed as "chan<- chan T"
+        match (&c.dir, &c.val) {
+            (ChanDir::SendRecv, Expr::Chan(v)) if v.dir == ChanDir::Recv => {
+                self.push("(");
+                self.expr(&c.val);
+                self.push(")");
+            }
+            _ => self.expr(&c.val),
+        }
+    }
+
+    fn block(&mut self, b: &BlockStmt) {
+        self.push("{");
+        self.indent += 1;
+        self.stmt_list(&b.list);
+        self.indent -= 1;
+        if !b.list.is_empty() {
+            self.newline();
+        }
+        self.push("}");
+    }
+
+    /// The body of a switch or a select, the clauses are not indented
+    fn clauses(&mut self, b: &BlockStmt) {
+        self.push("{");
+        self.stmt_list(&b.list);
+        if !b.list.is_empty() {
+            self.newline();
+        }
+        self.push("}");
+    }
+
+    fn stmt_list(&mut self, list: &[Stmt]) {
+        for s in list.iter() {
+            self.newline();
+            self.stmt(s);
+        }
+    }
+
+    /// The init statement of an if or a switch
+    fn init(&mut self, init: &Option<Stmt>) {
+        if let Some(s) = init {
+            self.stmt(s);
+            self.push("; ");
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        let objs = self.objs;
+        match s {
+            Stmt::Bad(_) => self.push("/* bad statement */"),
+            Stmt::Decl(d) => self.decl(d),
+            Stmt::Empty(_) => self.push(";"),
+            Stmt::Labeled(key) => {
+                let ls = &objs.l_stmts[*key];
+                self.ident(ls.label);
+                self.push(":");
+                self.newline();
+                self.stmt(&ls.stmt);
+            }
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Send(s) => {
+                self.expr(&s.chan);
+                self.push(" <- ");
+                self.expr(&s.val);
+            }
+            Stmt::IncDec(s) => {
+                self.expr(&s.expr);
+                self.push(s.token.text());
+            }
+            Stmt::Assign(key) => {
+                let a = &objs.a_stmts[*key];
+                self.exprs(&a.lhs);
+                self.push(" ");
+                self.push(a.token.text());
+                self.push(" ");
+                self.exprs(&a.rhs);
+            }
+            Stmt::Go(s) => {
+                self.push("go ");
+                self.expr(&s.call);
+            }
+            Stmt::Defer(s) => {
+                self.push("defer ");
+                self.expr(&s.call);
+            }
+            Stmt::Return(s) => {
+                self.push("return");
+                if !s.results.is_empty() {
+                    self.push(" ");
+                    self.exprs(&s.results);
+                }
+            }
+            Stmt::Branch(s) => {
+                self.push(s.token.text());
+                if let Some(l) = s.label {
+                    self.push(" ");
+                    self.ident(l);
+                }
+            }
+            Stmt::Block(b) => self.block(b),
+            Stmt::If(s) => {
+                self.push("if ");
+                self.init(&s.init);
+                self.expr(&s.cond);
+                self.push(" ");
+                self.block(&s.body);
+                if let Some(els) = &s.els {
+                    self.push(" else ");
+                    self.stmt(els);
+                }
+            }
+            Stmt::Case(c) => {
+                match &c.list {
+                    Some(list) => {
+                        self.push("case ");
+                        self.exprs(list);
+                        self.push(":");
+                    }
+                    None => self.push("default:"),
+                }
+                self.indent += 1;
+                self.stmt_list(&c.body);
+                self.indent -= 1;
+            }
+            Stmt::Switch(s) => {
+                self.push("switch ");
+                self.init(&s.init);
+                if let Some(tag) = &s.tag {
+                    self.expr(tag);
+                    self.push(" ");
+                }
+                self.clauses(&s.body);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.push("switch ");
+                self.init(&s.init);
+                self.stmt(&s.assign);
+                self.push(" ");
+                self.clauses(&s.body);
+            }
+            Stmt::Comm(c) => {
+                match &c.comm {
+                    Some(comm) => {
+                        self.push("case ");
+                        self.stmt(comm);
+                        self.push(":");
+                    }
+                    None => self.push("default:"),
+                }
+                self.indent += 1;
+                self.stmt_list(&c.body);
+                self.indent -= 1;
+            }
+            Stmt::Select(s) => {
+                self.push("select ");
+                self.clauses(&s.body);
+            }
+            Stmt::For(s) => {
+                self.push("for ");
+                if s.init.is_some() || s.post.is_some() {
+                    self.init(&s.init);
+                    if s.init.is_none() {
+                        self.push("; ");
+                    }
+                    if let Some(cond) = &s.cond {
+                        self.expr(cond);
+                    }
+                    self.push("; ");
+                    if let Some(post) = &s.post {
+                        self.stmt(post);
+                        self.push(" ");
+                    }
+                } else if let Some(cond) = &s.cond {
+                    self.expr(cond);
+                    self.push(" ");
+                }
+                self.block(&s.body);
+            }
+            Stmt::Range(s) => {
+                self.push("for ");
+                if let Some(key) = &s.key {
+                    self.expr(key);
+                    if let Some(val) = &s.val {
+                        self.push(", ");
+                        self.expr(val);
+                    }
+                    self.push(" ");
+                    self.push(s.token.text());
+                    self.push(" ");
+                }
+                self.push("range ");
+                self.expr(&s.expr);
+                self.push(" ");
+                self.block(&s.body);
+            }
+        }
+    }
+}
@@ -15,12 +15,18 @@ use super::token::Token;
 use std::iter::Peekable;
 use std::str::Chars;
 
+/// The scanner of a source in a `&str`. A source read with `String::from_utf8_lossy`
+/// has U+FFFD for the bytes that are not UTF-8, each of them is reported as an
+/// illegal encoding and the scanning goes on.
 pub struct Scanner<'a> {
     file: &'a mut position::File, // source file handle
+    source: &'a str,              // the whole source
     src: Peekable<Chars<'a>>,     // source
     errors: &'a errors::ErrorList,
 
     offset: usize,      // character offset
+    byte_offset: usize, // byte offset of the character offset
+    token_byte: usize,  // byte offset of the last token
     line_offset: usize, // current line offset
     semi1: bool,        // insert semicolon if current char is \n
     semi2: bool,        // insert semicolon if followed by \n
@@ -34,9 +40,12 @@ impl<'a> Scanner<'a> {
     ) -> Scanner<'a> {
         Scanner {
             file: file,
+            source: src,
             src: src.chars().peekable(),
             errors: err,
             offset: 0,
+            byte_offset: 0,
+            token_byte: 0,
             line_offset: 0,
             semi1: false,
             semi2: false,
@@ -44,7 +53,11 @@ impl<'a> Scanner<'a> {
     }
 
     fn error(&self, msg: &str) {
-        errors::FilePosErrors::new(self.file, self.errors).add_str(self.offset, msg, false);
+        self.error_at(self.offset, msg);
+    }
+
+    fn error_at(&self, offset: usize, msg: &str) {
+        errors::FilePosErrors::new(self.file, self.errors).add_str(offset, msg, false);
     }
 
     // Read the next Unicode char
@@ -54,6 +67,7 @@ impl<'a> Scanner<'a> {
         self.semi2 = false;
         self.skip_whitespace();
         let pos = self.file().pos(self.offset);
+        self.token_byte = self.byte_offset;
         let token = match self.peek_char() {
             Some(&ch) if is_letter(ch) => {
                 let t = self.scan_identifier();
@@ -212,7 +226,7 @@ impl<'a> Scanner<'a> {
         let mut s = String::new();
         loop {
             match self.peek_char() {
-                Some(&ch) if is_letter(ch) || is_decimal(ch) => {
+                Some(&ch) if is_letter(ch) || is_digit(ch) => {
                     self.advance_and_push(&mut s, ch);
                 }
                 _ => break,
@@ -221,84 +235,103 @@ impl<'a> Scanner<'a> {
         Token::ident_token(s)
     }
 
+    // Scans a number literal the way the Go scanner does: the literal is kept
+    // as it's written, an invalid one is reported and still returned.
     fn scan_number(&mut self, ch: char) -> Token {
-        let mut tok = self.scan_number_without_i(ch);
-        // Handles the 'i' at the end
-        match self.peek_char() {
-            Some('i') => match tok {
-                Token::INT(mut lit) | Token::FLOAT(mut lit) => {
-                    self.advance_and_push(lit.as_mut(), 'i');
-                    tok = Token::IMAG(lit);
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-        tok
-    }
+        let offs = self.offset;
+        let mut lit = String::new();
+        let mut float = false;
+        let mut base = 10;
+        let mut prefix = None;
+        let mut digsep = 0; // bit 0: a digit, bit 1: a '_'
+        let mut invalid = None; // the first digit not in the base and its offset
 
-    fn scan_number_without_i(&mut self, ch: char) -> Token {
-        let mut literal = String::new();
-        if ch == '.' {
-            //.34
-            self.scan_fraction_and_finish(literal)
-        } else if ch == '0' {
-            self.advance_and_push(&mut literal, '0');
-            match self.peek_char() {
-                // hexadecimal int
-                Some('x') | Some('X') => self.prefixed_int(literal, IntPrefix::Hex),
-                // octal int (explicit)
-                Some('o') | Some('O') => self.prefixed_int(literal, IntPrefix::Octal(false)),
-                // binary int
-                Some('b') | Some('B') => self.prefixed_int(literal, IntPrefix::Binary),
-                // octal int (bare)
-                Some(ch) if is_decimal(*ch) => self.prefixed_int(literal, IntPrefix::Octal(true)),
-                _ => self.decimal_int_or_float(literal),
+        // the integer part
+        if ch != '.' {
+            if ch == '0' {
+                self.advance_and_push(&mut lit, '0');
+                match self.peek_char().map(|c| c.to_ascii_lowercase()) {
+                    Some(c @ ('x' | 'o' | 'b')) => {
+                        base = match c {
+                            'x' => 16,
+                            'o' => 8,
+                            _ => 2,
+                        };
+                        prefix = Some(c);
+                        let c = *self.peek_char().unwrap();
+                        self.advance_and_push(&mut lit, c);
+                    }
+                    _ => {
+                        base = 8;
+                        prefix = Some('0');
+                        digsep = 1; // the leading 0
+                    }
+                }
+            }
+            digsep |= self.scan_digits(&mut lit, base, &mut invalid);
+            if self.peek_char() == Some(&'.') && matches!(prefix, Some('o' | 'b')) {
+                self.error(&format!("invalid radix point in {}", lit_name(prefix)));
             }
-        } else {
-            self.decimal_int_or_float(literal)
         }
-    }
 
-    fn prefixed_int(&mut self, mut literal: String, prefix: IntPrefix) -> Token {
-        if prefix.is_bare() {
-            literal.push(prefix.char());
-        } else {
-            self.advance_and_push(&mut literal, prefix.char());
+        // the fractional part
+        if self.peek_char() == Some(&'.') {
+            float = true;
+            self.advance_and_push(&mut lit, '.');
+            digsep |= self.scan_digits(&mut lit, base, &mut invalid);
+        }
+        if digsep & 1 == 0 {
+            self.error_at(offs, &format!("{} has no digits", lit_name(prefix)));
         }
 
-        let result = self.scan_digits(&mut literal, prefix.is_valid(), true);
-        match result {
-            Ok(count) => {
-                if count == 0 {
-                    self.error(prefix.err_msg());
-                    return Token::ILLEGAL(literal.into());
+        // the exponent
+        match self.peek_char().map(|c| c.to_ascii_lowercase()) {
+            Some(e @ ('e' | 'p')) => {
+                if e == 'e' && !matches!(prefix, None | Some('0')) {
+                    self.error("'e' exponent requires decimal mantissa");
+                } else if e == 'p' && prefix != Some('x') {
+                    self.error("'p' exponent requires hexadecimal mantissa");
+                }
+                let c = *self.peek_char().unwrap();
+                self.advance_and_push(&mut lit, c);
+                float = true;
+                if let Some(&c @ ('+' | '-')) = self.peek_char() {
+                    self.advance_and_push(&mut lit, c);
+                }
+                let ds = self.scan_digits(&mut lit, 10, &mut None);
+                digsep |= ds;
+                if ds & 1 == 0 {
+                    self.error("exponent has no digits");
                 }
             }
-            Err(e) => {
-                self.error(e);
-                return Token::ILLEGAL(literal.into());
+            _ => {
+                if prefix == Some('x') && float {
+                    self.error("hexadecimal mantissa requires a 'p' exponent");
+                }
             }
         }
-        if let Some(c) = self.peek_char() {
-            if matches!(prefix, IntPrefix::Binary | IntPrefix::Octal(_)) && is_decimal(*c) {
-                self.error(prefix.err_msg());
-                return Token::ILLEGAL(literal.into());
+
+        let imag = self.peek_char() == Some(&'i');
+        if imag {
+            self.advance_and_push(&mut lit, 'i');
+        }
+        // 09i and 09.5 are decimal
+        if let (false, false, Some((offset, c))) = (float, imag, invalid) {
+            self.error_at(
+                offset,
+                &format!("invalid digit {:?} in {}", c, lit_name(prefix)),
+            );
+        }
+        if digsep & 2 != 0 {
+            if let Some(i) = invalid_sep(&lit) {
+                self.error_at(offs + i, "'_' must separate successive digits");
             }
         }
-        Token::INT(literal.into())
-    }
 
-    fn decimal_int_or_float(&mut self, mut literal: String) -> Token {
-        // decimal int or float 3 / 3.14
-        if let Err(e) = self.scan_digits(&mut literal, is_decimal, true) {
-            self.error(e);
-            return Token::ILLEGAL(literal.into());
-        }
-        match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(literal),
-            Some('.') => self.scan_fraction_and_finish(literal),
-            _ => Token::INT(literal.into()),
+        match (imag, float) {
+            (true, _) => Token::IMAG(lit.into()),
+            (false, true) => Token::FLOAT(lit.into()),
+            (false, false) => Token::INT(lit.into()),
         }
     }
 
@@ -309,24 +342,40 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan_char(&mut self) -> Token {
+        let offs = self.offset;
         let mut lit = String::new();
-        if let Some(unquoted) = self.scan_string_char_lit(&mut lit, '\'') {
-            Token::CHAR((lit, unquoted.chars().next().unwrap()).into())
-        } else {
-            Token::ILLEGAL(lit.into())
+        match self.scan_string_char_lit(&mut lit, '\'') {
+            Some((unquoted, valid)) => {
+                let mut chars = unquoted.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Token::CHAR((lit, ch).into()),
+                    // the escape sequence is reported already
+                    _ if !valid => Token::CHAR((lit, char::REPLACEMENT_CHARACTER).into()),
+                    (None, _) => {
+                        self.error_at(offs, "empty rune literal or unescaped ' in rune literal");
+                        Token::ILLEGAL(lit.into())
+                    }
+                    (Some(_), Some(_)) => {
+                        self.error_at(offs, "illegal rune literal");
+                        Token::ILLEGAL(lit.into())
+                    }
+                }
+            }
+            None => Token::ILLEGAL(lit.into()),
         }
     }
 
     fn scan_string(&mut self) -> Token {
         let mut lit = String::new();
-        if let Some(unquoted) = self.scan_string_char_lit(&mut lit, '"') {
-            Token::STRING((lit, unquoted).into())
-        } else {
-            Token::ILLEGAL(lit.into())
+        match self.scan_string_char_lit(&mut lit, '"') {
+            Some((unquoted, _)) => Token::STRING((lit, unquoted).into()),
+            None => Token::ILLEGAL(lit.into()),
         }
     }
 
+    // no escape sequences in raw strings, the carriage returns are dropped
     fn scan_raw_string(&mut self) -> Token {
+        let offs = self.offset;
         let mut lit = self.read_char().unwrap().to_string();
         let mut unquoted = String::with_capacity(lit.len());
         loop {
@@ -344,7 +393,7 @@ impl<'a> Scanner<'a> {
                     unquoted.push(ch);
                 }
                 None => {
-                    self.error("raw string literal not terminated");
+                    self.error_at(offs, "raw string literal not terminated");
                     break;
                 }
             };
@@ -397,9 +446,14 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn scan_string_char_lit(&mut self, lit: &mut String, quote: char) -> Option<String> {
+    /// Scans the literal into `lit` and returns its value and if all its escape
+    /// sequences are valid, the invalid ones are left out of the value.
+    /// Returns `None` if it's not terminated.
+    fn scan_string_char_lit(&mut self, lit: &mut String, quote: char) -> Option<(String, bool)> {
+        let offs = self.offset;
         lit.push(self.read_char().unwrap());
         let mut unquoted = String::with_capacity(lit.len());
+        let mut valid = true;
         loop {
             match self.peek_char() {
                 Some(&ch) if ch == quote => {
@@ -407,28 +461,31 @@ impl<'a> Scanner<'a> {
                     break;
                 }
                 Some('\n') | None => {
-                    self.error("string/char literal not terminated");
+                    let msg = match quote {
+                        '"' => "string literal not terminated",
+                        _ => "rune literal not terminated",
+                    };
+                    self.error_at(offs, msg);
                     return None;
                 }
-                Some('\\') => {
-                    let result = self.scan_escape(lit, quote);
-                    if result.is_none() {
-                        return None;
-                    } else {
-                        unquoted.push(result.unwrap());
-                    }
-                }
+                Some('\\') => match self.scan_escape(lit, quote) {
+                    Some(ch) => unquoted.push(ch),
+                    None => valid = false,
+                },
                 Some(&ch) => {
                     self.advance_and_push(lit, ch);
                     unquoted.push(ch);
                 }
             }
         }
-        Some(unquoted)
+        Some((unquoted, valid))
     }
 
+    /// Scans an escape sequence, the backslash included. On an error the
+    /// character that's not part of it is left for the literal.
     fn scan_escape(&mut self, lit: &mut String, quote: char) -> Option<char> {
         lit.push(self.read_char().unwrap());
+        let offs = self.offset;
 
         let mut n: isize;
         let base: u32;
@@ -495,12 +552,12 @@ impl<'a> Scanner<'a> {
                     max = std::char::MAX as u32;
                 }
                 _ => {
-                    self.error("unknown escape sequence");
+                    self.error_at(offs, "unknown escape sequence");
                     return None;
                 }
             },
             None => {
-                self.error("escape sequence not terminated");
+                self.error_at(offs, "escape sequence not terminated");
                 return None;
             }
         }
@@ -511,7 +568,10 @@ impl<'a> Scanner<'a> {
                 Some(&ch) => {
                     let d = digit_val(ch);
                     if d >= base {
-                        self.error("illegal character in escape sequence");
+                        self.error(&format!(
+                            "illegal character {} in escape sequence",
+                            describe_char(ch)
+                        ));
                         return None;
                     }
                     self.advance_and_push(lit, ch);
@@ -524,15 +584,12 @@ impl<'a> Scanner<'a> {
             }
             n -= 1;
         }
-        if x <= max {
-            let result = std::char::from_u32(x);
-            if result.is_none() {
-                self.error("escape sequence is invalid Unicode code point");
-            }
-            result
-        } else {
-            None
+        // the surrogate halves are not code points either
+        let result = std::char::from_u32(x).filter(|_| x <= max);
+        if result.is_none() {
+            self.error_at(offs, "escape sequence is invalid Unicode code point");
         }
+        result
     }
 
     fn scan_switch2<'b>(&mut self, t1: &'b Token, t2: &'b Token) -> &'b Token {
@@ -595,82 +652,34 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn scan_fraction_and_finish(&mut self, mut lit: String) -> Token {
-        self.advance_and_push(&mut lit, '.');
-        if let Err(e) = self.scan_digits(&mut lit, is_decimal, false) {
-            self.error(e);
-            return Token::ILLEGAL(lit.into());
-        }
-        match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(lit),
-            _ => Token::FLOAT(lit.into()),
-        }
-    }
-
-    fn scan_exponent_and_finish(&mut self, mut lit: String) -> Token {
-        self.advance_and_push(&mut lit, 'e');
-        match self.peek_char() {
-            Some(&ch) if ch == '+' || ch == '-' => {
-                self.advance_and_push(&mut lit, ch);
-            }
-            _ => {}
-        }
-        match self.scan_digits(&mut lit, is_decimal, false) {
-            Ok(count) => {
-                if count == 0 {
-                    self.error("illegal floating-point exponent");
-                    Token::ILLEGAL(lit.into())
-                } else {
-                    Token::FLOAT(lit.into())
-                }
-            }
-            Err(e) => {
-                self.error(e);
-                Token::ILLEGAL(lit.into())
-            }
-        }
-    }
-
-    fn scan_digits<'s, 'r>(
-        &'s mut self,
-        digits: &mut String,
-        ch_valid: fn(char) -> bool,
-        allow_pre_underscore: bool,
-    ) -> Result<usize, &'r str> {
-        let msg = "_ must separate successive digits";
-        if !allow_pre_underscore {
-            if let Some(ch) = self.peek_char() {
-                if *ch == '_' {
-                    return Err(msg);
-                }
-            }
-        }
-        let mut count = 0;
-        let mut previous_is_underscore = false;
-        loop {
-            match self.peek_char() {
-                Some(&ch) if ch_valid(ch) => {
-                    self.advance_and_push(digits, ch);
-                    count += 1;
-                    previous_is_underscore = false;
-                }
-                Some('_') => {
-                    if !previous_is_underscore {
-                        // accept '_' but do not increase count
-                        self.advance_and_push(digits, '_');
-                        previous_is_underscore = true;
-                    } else {
-                        return Err(msg);
-                    }
+    // Scans the digits and the '_'s of a number, the decimal digits are taken for
+    // any base up to 10 and the first one too large is recorded in `invalid`.
+    // Returns bit 0 set if there's a digit and bit 1 set if there's a '_'.
+    fn scan_digits(
+        &mut self,
+        lit: &mut String,
+        base: u32,
+        invalid: &mut Option<(usize, char)>,
+    ) -> u32 {
+        let mut digsep = 0;
+        while let Some(&ch) = self.peek_char() {
+            let valid = match base {
+                16 => ch.is_ascii_hexdigit(),
+                _ => is_decimal(ch),
+            };
+            if ch == '_' {
+                digsep |= 2;
+            } else if valid {
+                digsep |= 1;
+                if digit_val(ch) >= base && invalid.is_none() {
+                    *invalid = Some((self.offset, ch));
                 }
-                _ => break,
+            } else {
+                break;
             }
+            self.advance_and_push(lit, ch);
         }
-        if !previous_is_underscore {
-            Ok(count)
-        } else {
-            return Err(msg);
-        }
+        digsep
     }
 
     fn skip_whitespace(&mut self) {
@@ -691,8 +700,11 @@ impl<'a> Scanner<'a> {
                 if ch == '\n' {
                     self.line_offset = self.offset;
                     self.file.add_line(self.offset + 1);
+                } else if ch == char::REPLACEMENT_CHARACTER {
+                    self.error_at(self.offset, "illegal UTF-8 encoding");
                 }
                 self.offset += 1;
+                self.byte_offset += ch.len_utf8();
             }
             None => {}
         }
@@ -736,7 +748,7 @@ impl<'a> Scanner<'a> {
                 loop {
                     //skip whitespaces
                     match iter.peek() {
-                        Some(' ') | Some('\t') | Some('r') => {
+                        Some(' ') | Some('\t') | Some('\r') => {
                             iter.next();
                         }
                         _ => break,
@@ -760,6 +772,32 @@ impl<'a> Scanner<'a> {
     pub fn file(&self) -> &position::File {
         self.file
     }
+
+    /// Returns the char and byte offsets scanning has reached, and the byte
+    /// offset of the last token.
+    pub fn offsets(&self) -> (usize, usize, usize) {
+        (self.offset, self.byte_offset, self.token_byte)
+    }
+
+    /// Continues scanning at the char `offset`, which is at `byte_offset` of the
+    /// source. The offset must be that of a token no semicolon is inserted before.
+    pub fn seek(&mut self, offset: usize, byte_offset: usize) {
+        self.src = self.source[byte_offset..].chars().peekable();
+        self.offset = offset;
+        self.byte_offset = byte_offset;
+        self.line_offset = offset;
+        self.semi1 = false;
+        self.semi2 = false;
+    }
+}
+
+/// Like Go's %#U, e.g. U+0067 'g'
+fn describe_char(ch: char) -> String {
+    if ch.is_control() {
+        format!("U+{:04X}", ch as u32)
+    } else {
+        format!("U+{:04X} '{}'", ch as u32, ch)
+    }
 }
 
 fn digit_val(ch: char) -> u32 {
@@ -771,60 +809,64 @@ fn digit_val(ch: char) -> u32 {
     }
 }
 
+// The letters of the Go spec are the Unicode category L, Rust's alphabetic is
+// a bit wider, it has the letter numbers and some marks too
 fn is_letter(ch: char) -> bool {
     ch.is_alphabetic() || ch == '_'
 }
 
-fn is_decimal(ch: char) -> bool {
-    ch >= '0' && ch <= '9'
-}
-
-fn is_octal(ch: char) -> bool {
-    ch >= '0' && ch <= '7'
-}
-
-fn is_binary(ch: char) -> bool {
-    ch == '0' || ch == '1'
-}
-
-fn is_hex(ch: char) -> bool {
-    (ch >= '0' && ch <= '9') || (ch.to_ascii_lowercase() >= 'a' && ch.to_ascii_lowercase() <= 'f')
+// A digit in an identifier, the Go spec has the Unicode category Nd, Rust's
+// numeric has the other numbers too
+fn is_digit(ch: char) -> bool {
+    is_decimal(ch) || (!ch.is_ascii() && ch.is_numeric())
 }
 
-enum IntPrefix {
-    Binary,
-    Octal(bool),
-    Hex,
+fn is_decimal(ch: char) -> bool {
+    ch >= '0' && ch <= '9'
 }
 
-impl IntPrefix {
-    fn is_valid(&self) -> fn(char) -> bool {
-        match self {
-            Self::Binary => is_binary,
-            Self::Octal(_) => is_octal,
-            Self::Hex => is_hex,
-        }
+fn lit_name(prefix: Option<char>) -> &'static str {
+    match prefix {
+        Some('x') => "hexadecimal literal",
+        Some('o') | Some('0') => "octal literal",
+        Some('b') => "binary literal",
+        _ => "decimal literal",
     }
+}
 
-    fn char(&self) -> char {
-        match self {
-            Self::Binary => 'b',
-            Self::Octal(_) => 'o',
-            Self::Hex => 'x',
+// The offset of the first '_' in the number literal that doesn't separate two
+// digits, or a prefix and a digit
+fn invalid_sep(lit: &str) -> Option<usize> {
+    let bytes = lit.as_bytes();
+    let mut x1 = b' '; // the prefix char, only 'x' matters
+    let mut d = b'.'; // the previous char: '_', '0' for a digit, '.' for the rest
+    let mut i = 0;
+    // a prefix counts as a digit
+    if bytes.len() >= 2 && bytes[0] == b'0' {
+        x1 = bytes[1].to_ascii_lowercase();
+        if x1 == b'x' || x1 == b'o' || x1 == b'b' {
+            d = b'0';
+            i = 2;
         }
     }
-
-    fn is_bare(&self) -> bool {
-        matches!(self, Self::Octal(true))
-    }
-
-    fn err_msg(&self) -> &str {
-        match self {
-            Self::Binary => "illegal binary number",
-            Self::Octal(_) => "illegal octal number",
-            Self::Hex => "illegal hexadecimal number",
+    while i < bytes.len() {
+        let p = d;
+        d = bytes[i];
+        if d == b'_' {
+            if p != b'0' {
+                return Some(i);
+            }
+        } else if d.is_ascii_digit() || x1 == b'x' && d.is_ascii_hexdigit() {
+            d = b'0';
+        } else {
+            if p == b'_' {
+                return Some(i - 1);
+            }
+            d = b'.';
         }
+        i += 1;
     }
+    (d == b'_').then(|| bytes.len() - 1)
 }
 
 #[cfg(test)]
@@ -877,4 +919,271 @@ mod test {
         }
         print!("\n<- {} ->\n", err);
     }
+
+    /// The tokens of `src` but the EOF, and the errors
+    fn scan_all(src: &str) -> (Vec<Token>, Vec<String>) {
+        let mut fs = FileSet::new();
+        let f = fs.add_file("a.go".to_owned(), None, src.chars().count());
+        let err = errors::ErrorList::new();
+        let mut scanner = Scanner::new(f, src, &err);
+        let mut tokens = vec![];
+        loop {
+            match scanner.scan().0 {
+                Token::EOF => break,
+                Token::SEMICOLON(_) => {}
+                t => tokens.push(t),
+            }
+        }
+        (
+            tokens,
+            err.iter()
+                .map(|e| format!("{}: {}", e.pos, e.msg))
+                .collect(),
+        )
+    }
+
+    fn unquoted(t: &Token) -> String {
+        match t {
+            Token::STRING(d) => d.as_str_str().1.clone(),
+            Token::CHAR(d) => d.as_str_char().1.to_string(),
+            _ => panic!("not a literal: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_escapes() {
+        let valid = [
+            (r#""a\tb\nc""#, "a\tb\nc"),
+            (r#""\a\b\f\r\v\\\"""#, "\u{7}\u{8}\u{c}\r\u{b}\\\""),
+            (r#""\x41\x7a""#, "Az"),
+            (r#""\101\000""#, "A\0"),
+            (r#""\u00e9\U0001F600""#, "\u{e9}\u{1F600}"),
+            (r"'\''", "'"),
+            (r"'\x41'", "A"),
+            (r"'\377'", "\u{ff}"),
+            (r"'\u12e4'", "\u{12e4}"),
+            ("`a\\n\r\nb`", "a\\n\nb"),
+        ];
+        for (src, want) in valid {
+            let (tokens, errs) = scan_all(src);
+            assert!(errs.is_empty(), "{}: {:?}", src, errs);
+            assert_eq!(tokens.len(), 1, "{}", src);
+            assert_eq!(unquoted(&tokens[0]), want, "{}", src);
+        }
+        // the payload is the literal as written, but for the carriage returns of raw strings
+        let (tokens, _) = scan_all("`x\r\ny`");
+        assert_eq!(tokens[0].get_literal(), "`x\ny`");
+        let (tokens, _) = scan_all(r#""\x41""#);
+        assert_eq!(tokens[0].get_literal(), r#""\x41""#);
+
+        let invalid = [
+            (r#"x := "a\qb""#, "a.go:1:9: unknown escape sequence"),
+            (r#"'\"'"#, "a.go:1:3: unknown escape sequence"),
+            (
+                r#""\x4g""#,
+                "a.go:1:5: illegal character U+0067 'g' in escape sequence",
+            ),
+            (
+                r#""\u12""#,
+                "a.go:1:6: illegal character U+0022 '\"' in escape sequence",
+            ),
+            (
+                r#""\08""#,
+                "a.go:1:4: illegal character U+0038 '8' in escape sequence",
+            ),
+            (
+                r#""\400""#,
+                "a.go:1:3: escape sequence is invalid Unicode code point",
+            ),
+            (
+                r#""\uD800""#,
+                "a.go:1:3: escape sequence is invalid Unicode code point",
+            ),
+            (
+                r#""\U00110000""#,
+                "a.go:1:3: escape sequence is invalid Unicode code point",
+            ),
+            ("\"ab\n", "a.go:1:1: string literal not terminated"),
+            ("'a", "a.go:1:1: rune literal not terminated"),
+            ("'ab'", "a.go:1:1: illegal rune literal"),
+            (
+                "''",
+                "a.go:1:1: empty rune literal or unescaped ' in rune literal",
+            ),
+            ("x `ab", "a.go:1:3: raw string literal not terminated"),
+        ];
+        for (src, want) in invalid {
+            let (_, errs) = scan_all(src);
+            assert_eq!(errs, [want], "{}", src);
+        }
+        // the literal goes on after a bad escape sequence
+        let (tokens, errs) = scan_all(r#""\q\x4g\u00e9" + 1"#);
+        assert_eq!(errs.len(), 2, "{:?}", errs);
+        assert_eq!(tokens.len(), 3, "{:?}", tokens);
+        assert_eq!(unquoted(&tokens[0]), "qg\u{e9}");
+    }
+
+    /// The tokens of `src` but the EOF, the inserted semicolons as "<;>"
+    fn with_semis(src: &str) -> String {
+        let mut fs = FileSet::new();
+        let f = fs.add_file("a.go".to_owned(), None, src.chars().count());
+        let err = errors::ErrorList::new();
+        let mut scanner = Scanner::new(f, src, &err);
+        let mut tokens = vec![];
+        loop {
+            match scanner.scan().0 {
+                Token::EOF => break,
+                Token::SEMICOLON(real) if !*real.as_bool() => tokens.push("<;>".to_owned()),
+                Token::COMMENT(c) => tokens.push(c.as_str().trim_end().to_owned()),
+                t => tokens.push(t.to_string()),
+            }
+        }
+        assert_eq!(err.len(), 0, "{}", err);
+        tokens.join(" ")
+    }
+
+    #[test]
+    fn test_semicolon_insertion() {
+        // a line ending with one of these ends the statement
+        for last in [
+            "x",
+            "1",
+            "1.5",
+            "2i",
+            "'a'",
+            "\"s\"",
+            "`r`",
+            "x++",
+            "x--",
+            "f()",
+            "a[0]",
+            "T{}",
+            "return",
+            "break",
+            "continue",
+            "fallthrough",
+        ] {
+            let want = format!("{} <;>", with_semis(last).trim_end_matches(" <;>"));
+            assert_eq!(with_semis(&format!("{}\n", last)), want, "{}", last);
+            assert_eq!(with_semis(&format!("{}\r\n", last)), want, "{}", last);
+            // or a comment, which is left for after the semicolon
+            let comment = with_semis(&format!("{} // c\n", last));
+            assert_eq!(comment, format!("{} // c", want), "{}", last);
+            let comment = with_semis(&format!("{} /* c */\r\n", last));
+            assert_eq!(comment, format!("{} /* c */", want), "{}", last);
+        }
+        // and these don't
+        for last in [
+            "x +", "x,", "f(", "a[", "T{", "x =", "if", "go", "defer", "*", "...",
+        ] {
+            let tokens = with_semis(&format!("{}\n", last));
+            assert!(!tokens.contains("<;>"), "{}", tokens);
+        }
+        // a comment with a newline is a newline
+        assert_eq!(with_semis("x++ /* a\nb */ y"), "x ++ <;> /* a\nb */ y <;>");
+        assert_eq!(with_semis("x /* a */ r\n"), "x /* a */ r <;>");
+        assert_eq!(with_semis("x++ /* a */"), "x ++ <;> /* a */");
+        // not before a '}' on the same line, the parser doesn't need one there
+        assert_eq!(with_semis("if x { return }\n"), "if x { return } <;>");
+        assert_eq!(
+            with_semis("func() { x++ }()\n"),
+            "func ( ) { x ++ } ( ) <;>"
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        let valid = [
+            ("0", "INT"),
+            ("123", "INT"),
+            ("1_000_000", "INT"),
+            ("0777", "INT"),
+            ("0o777", "INT"),
+            ("0O7_7", "INT"),
+            ("0b1010", "INT"),
+            ("0B_1", "INT"),
+            ("0xdead_BEEF", "INT"),
+            ("0X_f", "INT"),
+            ("1.5", "FLOAT"),
+            ("1.", "FLOAT"),
+            (".25", "FLOAT"),
+            ("09.5", "FLOAT"),
+            ("1e10", "FLOAT"),
+            ("1_0.2_5E-1_0", "FLOAT"),
+            ("0x1.8p3", "FLOAT"),
+            ("0x.8p-1", "FLOAT"),
+            ("0x1P+2", "FLOAT"),
+            ("0x_1p0", "FLOAT"),
+            ("1i", "IMAG"),
+            ("09i", "IMAG"),
+            ("0b101i", "IMAG"),
+            ("0o7i", "IMAG"),
+            ("0x1p2i", "IMAG"),
+            ("1.5e3i", "IMAG"),
+        ];
+        for (src, kind) in valid {
+            let (tokens, errs) = scan_all(src);
+            assert!(errs.is_empty(), "{}: {:?}", src, errs);
+            assert_eq!(tokens.len(), 1, "{}", src);
+            assert_eq!(
+                format!("{:?}", tokens[0]).split(' ').next(),
+                Some(kind),
+                "{}",
+                src
+            );
+            // the literal is kept as written
+            assert_eq!(tokens[0].get_literal(), src);
+        }
+
+        let invalid = [
+            ("0x", "a.go:1:1: hexadecimal literal has no digits"),
+            ("0b", "a.go:1:1: binary literal has no digits"),
+            ("0o_", "a.go:1:1: octal literal has no digits"),
+            ("0b102", "a.go:1:5: invalid digit '2' in binary literal"),
+            ("0o78", "a.go:1:4: invalid digit '8' in octal literal"),
+            ("078", "a.go:1:3: invalid digit '8' in octal literal"),
+            ("1__0", "a.go:1:3: '_' must separate successive digits"),
+            ("1_", "a.go:1:2: '_' must separate successive digits"),
+            ("0_x1", "a.go:1:2: '_' must separate successive digits"),
+            ("1_.5", "a.go:1:2: '_' must separate successive digits"),
+            ("1e", "a.go:1:3: exponent has no digits"),
+            (
+                "0x1.8",
+                "a.go:1:6: hexadecimal mantissa requires a 'p' exponent",
+            ),
+            (
+                "1p3",
+                "a.go:1:2: 'p' exponent requires hexadecimal mantissa",
+            ),
+            ("0b1e3", "a.go:1:4: 'e' exponent requires decimal mantissa"),
+            ("0o1.2", "a.go:1:4: invalid radix point in octal literal"),
+        ];
+        for (src, want) in invalid {
+            let (tokens, errs) = scan_all(src);
+            assert_eq!(errs.first().map(|e| e.as_str()), Some(want), "{}", src);
+            // the scanning goes on after the literal
+            assert!(!tokens.is_empty(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_unicode() {
+        let (tokens, errs) = scan_all("π x١ 日本語_2 ½");
+        assert!(errs.is_empty(), "{:?}", errs);
+        let names: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+        assert_eq!(names, ["π", "x١", "日本語_2", "ILLEGAL"]);
+        assert!(matches!(tokens[3], Token::ILLEGAL(_)));
+
+        let src = String::from_utf8_lossy(b"a\xffb \"c\xfe\"");
+        let (tokens, errs) = scan_all(&src);
+        assert!(matches!(tokens[1], Token::ILLEGAL(_)));
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(
+            errs,
+            [
+                "a.go:1:2: illegal UTF-8 encoding",
+                "a.go:1:7: illegal UTF-8 encoding"
+            ]
+        );
+    }
 }
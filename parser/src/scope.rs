@@ -9,12 +9,15 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use super::ast::Node;
+use super::ast::{File, IdentEntity, Node};
 use super::map::Map;
 use super::objects::*;
 use super::position;
+#[cfg(feature = "serde_borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
 use std::fmt;
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub enum EntityKind {
     Bad,
@@ -40,6 +43,7 @@ impl EntityKind {
     }
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub enum DeclObj {
     Field(FieldKey),
@@ -50,6 +54,7 @@ pub enum DeclObj {
     NoDecl,
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub enum EntityData {
     PkgScope(ScopeKey),
@@ -59,6 +64,7 @@ pub enum EntityData {
 
 // An Entity describes a named language entity such as a package,
 // constant, type, variable, function (incl. methods), or label.
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Debug, Clone)]
 pub struct Entity {
     pub kind: EntityKind,
@@ -93,6 +99,7 @@ impl Entity {
     }
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 pub struct Scope {
     pub outer: Option<ScopeKey>,
     pub entities: Map<String, EntityKey>,
@@ -133,5 +140,100 @@ impl Scope {
     }
 }
 
+/// The identifiers predeclared in the universe scope, and what they denote.
+/// The builtins of package unsafe are not here, they are selected from it.
+pub const PREDECLARED: &[(&str, EntityKind)] = &[
+    // types
+    ("bool", EntityKind::Typ),
+    ("byte", EntityKind::Typ),
+    ("complex64", EntityKind::Typ),
+    ("complex128", EntityKind::Typ),
+    ("error", EntityKind::Typ),
+    ("float32", EntityKind::Typ),
+    ("float64", EntityKind::Typ),
+    ("int", EntityKind::Typ),
+    ("int8", EntityKind::Typ),
+    ("int16", EntityKind::Typ),
+    ("int32", EntityKind::Typ),
+    ("int64", EntityKind::Typ),
+    ("rune", EntityKind::Typ),
+    ("string", EntityKind::Typ),
+    ("uint", EntityKind::Typ),
+    ("uint8", EntityKind::Typ),
+    ("uint16", EntityKind::Typ),
+    ("uint32", EntityKind::Typ),
+    ("uint64", EntityKind::Typ),
+    ("uintptr", EntityKind::Typ),
+    // constants
+    ("true", EntityKind::Con),
+    ("false", EntityKind::Con),
+    ("iota", EntityKind::Con),
+    ("nil", EntityKind::Con),
+    // functions
+    ("append", EntityKind::Fun),
+    ("cap", EntityKind::Fun),
+    ("close", EntityKind::Fun),
+    ("complex", EntityKind::Fun),
+    ("copy", EntityKind::Fun),
+    ("delete", EntityKind::Fun),
+    ("imag", EntityKind::Fun),
+    ("len", EntityKind::Fun),
+    ("make", EntityKind::Fun),
+    ("new", EntityKind::Fun),
+    ("panic", EntityKind::Fun),
+    ("print", EntityKind::Fun),
+    ("println", EntityKind::Fun),
+    ("real", EntityKind::Fun),
+    ("recover", EntityKind::Fun),
+    // goscript extensions
+    ("assert", EntityKind::Fun),
+    ("trace", EntityKind::Fun),
+    ("ffi", EntityKind::Fun),
+];
+
+/// Makes the universe scope, with an entity for each of `PREDECLARED`
+pub fn new_universe(objs: &mut AstObjects) -> ScopeKey {
+    let mut scope = Scope::new(None);
+    for (name, kind) in PREDECLARED.iter() {
+        let entity = Entity::with_no_data(kind.clone(), name.to_string(), DeclObj::NoDecl);
+        scope.insert(name.to_string(), objs.entities.insert(entity));
+    }
+    objs.scopes.insert(scope)
+}
+
+/// Resolves the identifiers each file of a package left unresolved, to the top level
+/// declarations of the other files or to the predeclared ones in `universe`. Names
+/// declared at the top level shadow the predeclared ones, like locals shadow both.
+///
+/// The identifiers left in `File::unresolved` are the undeclared ones and the names
+/// of imported packages. Redeclarations across files are left to the type checker.
+pub fn resolve_package(objs: &mut AstObjects, files: &mut [File], universe: ScopeKey) -> ScopeKey {
+    let mut pkg_scope = Scope::new(Some(universe));
+    for f in files.iter() {
+        for (name, entity) in objs.scopes[f.scope].entities.iter() {
+            if pkg_scope.look_up(name).is_none() {
+                pkg_scope.insert(name.clone(), *entity);
+            }
+        }
+    }
+    let pkg_scope = objs.scopes.insert(pkg_scope);
+    for f in files.iter_mut() {
+        f.unresolved.retain(|i| {
+            let ident = &mut objs.idents[*i];
+            let mut s = Some(pkg_scope);
+            while let Some(skey) = s {
+                let scope = &objs.scopes[skey];
+                if let Some(entity) = scope.look_up(&ident.name) {
+                    ident.entity = IdentEntity::Entity(*entity);
+                    return false;
+                }
+                s = scope.outer;
+            }
+            true
+        });
+    }
+    pkg_scope
+}
+
 #[cfg(test)]
 mod test {}
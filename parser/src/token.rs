@@ -10,6 +10,8 @@
 // license that can be found in the LICENSE file.
 
 #![allow(non_camel_case_types)]
+#[cfg(feature = "serde_borsh")]
+use borsh::{maybestd::io::Result, maybestd::io::Write, BorshDeserialize, BorshSerialize};
 use std::fmt;
 
 pub(crate) const LOWEST_PREC: usize = 0; // non-operators
@@ -17,6 +19,7 @@ pub(crate) const LOWEST_PREC: usize = 0; // non-operators
 //pub(crate) const UNARY_PREC: usize = 6;
 //pub(crate) const HIGHEST_PREC: usize = 7;
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub enum Token {
     // Special tokens
@@ -334,6 +337,33 @@ impl Token {
         }
     }
 
+    pub fn is_expr_start(&self) -> bool {
+        matches!(
+            self,
+            Token::IDENT(_)
+                | Token::INT(_)
+                | Token::FLOAT(_)
+                | Token::IMAG(_)
+                | Token::CHAR(_)
+                | Token::STRING(_)
+                | Token::ADD
+                | Token::SUB
+                | Token::MUL
+                | Token::AND
+                | Token::XOR
+                | Token::NOT
+                | Token::ARROW
+                | Token::LPAREN
+                | Token::LBRACK
+                | Token::LBRACE
+                | Token::CHAN
+                | Token::FUNC
+                | Token::INTERFACE
+                | Token::MAP
+                | Token::STRUCT
+        )
+    }
+
     pub fn is_expr_end(&self) -> bool {
         match self {
             Token::COMMA => true,
@@ -387,9 +417,50 @@ enum RawTokenData {
     StrChar(String, char),
 }
 
+#[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub struct TokenData(Box<RawTokenData>);
 
+// Borsh has no chars, they are written as u32s
+#[cfg(feature = "serde_borsh")]
+impl BorshSerialize for RawTokenData {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            RawTokenData::Bool(b) => (0u8, b).serialize(writer),
+            RawTokenData::Str(s) => (1u8, s).serialize(writer),
+            RawTokenData::StrStr(s0, s1) => (2u8, s0, s1).serialize(writer),
+            RawTokenData::StrChar(s, c) => (3u8, s, *c as u32).serialize(writer),
+        }
+    }
+}
+
+#[cfg(feature = "serde_borsh")]
+impl BorshDeserialize for RawTokenData {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        Ok(match u8::deserialize_reader(reader)? {
+            0 => RawTokenData::Bool(bool::deserialize_reader(reader)?),
+            1 => RawTokenData::Str(String::deserialize_reader(reader)?),
+            2 => RawTokenData::StrStr(
+                String::deserialize_reader(reader)?,
+                String::deserialize_reader(reader)?,
+            ),
+            3 => {
+                let s = String::deserialize_reader(reader)?;
+                let c = char::from_u32(u32::deserialize_reader(reader)?).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid char")
+                })?;
+                RawTokenData::StrChar(s, c)
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "invalid token data",
+                ))
+            }
+        })
+    }
+}
+
 impl From<bool> for TokenData {
     fn from(b: bool) -> Self {
         TokenData(Box::new(RawTokenData::Bool(b)))
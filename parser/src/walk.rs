@@ -0,0 +1,285 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A depth-first walk over the AST, like `ast.Inspect` of Go.
+//!
+//! Unlike the visitors of `visitor`, which only dispatch on the kind of a node,
+//! the walk goes into the children of the nodes, the ones in the arenas of
+//! `AstObjects` too, in the order they appear in the source.
+
+use super::ast::*;
+use super::objects::*;
+
+/// What to do after a node is visited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Walk {
+    /// Go on with the children of the node
+    Continue,
+    /// Go on with the next node after the children of this one
+    SkipChildren,
+    /// End the walk
+    Stop,
+}
+
+/// The callbacks of a walk, all of them go on with the children by default
+pub trait Visitor {
+    fn visit_expr(&mut self, _expr: &Expr, _objs: &AstObjects) -> Walk {
+        Walk::Continue
+    }
+
+    fn visit_stmt(&mut self, _stmt: &Stmt, _objs: &AstObjects) -> Walk {
+        Walk::Continue
+    }
+
+    fn visit_decl(&mut self, _decl: &Decl, _objs: &AstObjects) -> Walk {
+        Walk::Continue
+    }
+
+    /// Every identifier, the ones of the `Expr::Ident`s after their `visit_expr`, and
+    /// the ones that name things: the package, declarations, fields and labels
+    fn visit_ident(&mut self, _ident: IdentKey, _objs: &AstObjects) -> Walk {
+        Walk::Continue
+    }
+}
+
+// Err if the walk is stopped
+type WalkResult = Result<(), ()>;
+
+/// Walks the package name and the declarations of the file, returns false if
+/// the visitor stopped the walk
+pub fn walk_file<V: Visitor + ?Sized>(v: &mut V, file: &File, objs: &AstObjects) -> bool {
+    let result = ident(v, file.name, objs)
+        .and_then(|_| file.decls.iter().try_for_each(|d| decl(v, d, objs)));
+    result.is_ok()
+}
+
+/// Walks the expression and everything in it, returns false if the visitor
+/// stopped the walk
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, e: &Expr, objs: &AstObjects) -> bool {
+    expr(v, e, objs).is_ok()
+}
+
+/// Walks the statement and everything in it, returns false if the visitor
+/// stopped the walk
+pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, s: &Stmt, objs: &AstObjects) -> bool {
+    stmt(v, s, objs).is_ok()
+}
+
+/// Walks the declaration and everything in it, returns false if the visitor
+/// stopped the walk
+pub fn walk_decl<V: Visitor + ?Sized>(v: &mut V, d: &Decl, objs: &AstObjects) -> bool {
+    decl(v, d, objs).is_ok()
+}
+
+// Ok(true) if the children are to be walked
+fn enter(w: Walk) -> Result<bool, ()> {
+    match w {
+        Walk::Continue => Ok(true),
+        Walk::SkipChildren => Ok(false),
+        Walk::Stop => Err(()),
+    }
+}
+
+fn ident<V: Visitor + ?Sized>(v: &mut V, i: IdentKey, objs: &AstObjects) -> WalkResult {
+    enter(v.visit_ident(i, objs)).map(|_| ())
+}
+
+fn idents<V: Visitor + ?Sized>(v: &mut V, is: &[IdentKey], objs: &AstObjects) -> WalkResult {
+    is.iter().try_for_each(|&i| ident(v, i, objs))
+}
+
+fn exprs<V: Visitor + ?Sized>(v: &mut V, es: &[Expr], objs: &AstObjects) -> WalkResult {
+    es.iter().try_for_each(|e| expr(v, e, objs))
+}
+
+fn opt_expr<V: Visitor + ?Sized>(v: &mut V, e: &Option<Expr>, objs: &AstObjects) -> WalkResult {
+    e.iter().try_for_each(|e| expr(v, e, objs))
+}
+
+fn stmts<V: Visitor + ?Sized>(v: &mut V, ss: &[Stmt], objs: &AstObjects) -> WalkResult {
+    ss.iter().try_for_each(|s| stmt(v, s, objs))
+}
+
+fn opt_stmt<V: Visitor + ?Sized>(v: &mut V, s: &Option<Stmt>, objs: &AstObjects) -> WalkResult {
+    s.iter().try_for_each(|s| stmt(v, s, objs))
+}
+
+fn block<V: Visitor + ?Sized>(v: &mut V, b: &BlockStmt, objs: &AstObjects) -> WalkResult {
+    stmts(v, &b.list, objs)
+}
+
+fn fields<V: Visitor + ?Sized>(v: &mut V, fl: &FieldList, objs: &AstObjects) -> WalkResult {
+    fl.list.iter().try_for_each(|&f| {
+        let f = &objs.fields[f];
+        idents(v, &f.names, objs)?;
+        expr(v, &f.typ, objs)?;
+        opt_expr(v, &f.tag, objs)
+    })
+}
+
+fn func_type<V: Visitor + ?Sized>(v: &mut V, ft: FuncTypeKey, objs: &AstObjects) -> WalkResult {
+    let ft = &objs.ftypes[ft];
+    fields(v, &ft.params, objs)?;
+    ft.results.iter().try_for_each(|r| fields(v, r, objs))
+}
+
+fn expr<V: Visitor + ?Sized>(v: &mut V, e: &Expr, objs: &AstObjects) -> WalkResult {
+    if !enter(v.visit_expr(e, objs))? {
+        return Ok(());
+    }
+    match e {
+        Expr::Bad(_) | Expr::BasicLit(_) => Ok(()),
+        Expr::Ident(i) => ident(v, *i, objs),
+        Expr::Ellipsis(e) => opt_expr(v, &e.elt, objs),
+        Expr::FuncLit(e) => {
+            func_type(v, e.typ, objs)?;
+            block(v, &e.body, objs)
+        }
+        Expr::CompositeLit(e) => {
+            opt_expr(v, &e.typ, objs)?;
+            exprs(v, &e.elts, objs)
+        }
+        Expr::Paren(e) => expr(v, &e.expr, objs),
+        Expr::Selector(e) => {
+            expr(v, &e.expr, objs)?;
+            ident(v, e.sel, objs)
+        }
+        Expr::Index(e) => {
+            expr(v, &e.expr, objs)?;
+            expr(v, &e.index, objs)
+        }
+        Expr::Slice(e) => {
+            expr(v, &e.expr, objs)?;
+            opt_expr(v, &e.low, objs)?;
+            opt_expr(v, &e.high, objs)?;
+            opt_expr(v, &e.max, objs)
+        }
+        Expr::TypeAssert(e) => {
+            expr(v, &e.expr, objs)?;
+            opt_expr(v, &e.typ, objs)
+        }
+        Expr::Call(e) => {
+            expr(v, &e.func, objs)?;
+            exprs(v, &e.args, objs)
+        }
+        Expr::Star(e) => expr(v, &e.expr, objs),
+        Expr::Unary(e) => expr(v, &e.expr, objs),
+        Expr::Binary(e) => {
+            expr(v, &e.expr_a, objs)?;
+            expr(v, &e.expr_b, objs)
+        }
+        Expr::KeyValue(e) => {
+            expr(v, &e.key, objs)?;
+            expr(v, &e.val, objs)
+        }
+        Expr::Array(e) => {
+            opt_expr(v, &e.len, objs)?;
+            expr(v, &e.elt, objs)
+        }
+        Expr::Struct(e) => fields(v, &e.fields, objs),
+        Expr::Func(ft) => func_type(v, *ft, objs),
+        Expr::Interface(e) => fields(v, &e.methods, objs),
+        Expr::Map(e) => {
+            expr(v, &e.key, objs)?;
+            expr(v, &e.val, objs)
+        }
+        Expr::Chan(e) => expr(v, &e.val, objs),
+    }
+}
+
+fn stmt<V: Visitor + ?Sized>(v: &mut V, s: &Stmt, objs: &AstObjects) -> WalkResult {
+    if !enter(v.visit_stmt(s, objs))? {
+        return Ok(());
+    }
+    match s {
+        Stmt::Bad(_) | Stmt::Empty(_) => Ok(()),
+        Stmt::Decl(d) => decl(v, d, objs),
+        Stmt::Labeled(key) => {
+            let ls = &objs.l_stmts[*key];
+            ident(v, ls.label, objs)?;
+            stmt(v, &ls.stmt, objs)
+        }
+        Stmt::Expr(e) => expr(v, e, objs),
+        Stmt::Send(s) => {
+            expr(v, &s.chan, objs)?;
+            expr(v, &s.val, objs)
+        }
+        Stmt::IncDec(s) => expr(v, &s.expr, objs),
+        Stmt::Assign(key) => {
+            let a = &objs.a_stmts[*key];
+            exprs(v, &a.lhs, objs)?;
+            exprs(v, &a.rhs, objs)
+        }
+        Stmt::Go(s) => expr(v, &s.call, objs),
+        Stmt::Defer(s) => expr(v, &s.call, objs),
+        Stmt::Return(s) => exprs(v, &s.results, objs),
+        Stmt::Branch(s) => s.label.iter().try_for_each(|&l| ident(v, l, objs)),
+        Stmt::Block(b) => block(v, b, objs),
+        Stmt::If(s) => {
+            opt_stmt(v, &s.init, objs)?;
+            expr(v, &s.cond, objs)?;
+            block(v, &s.body, objs)?;
+            opt_stmt(v, &s.els, objs)
+        }
+        Stmt::Case(s) => {
+            s.list.iter().try_for_each(|l| exprs(v, l, objs))?;
+            stmts(v, &s.body, objs)
+        }
+        Stmt::Switch(s) => {
+            opt_stmt(v, &s.init, objs)?;
+            opt_expr(v, &s.tag, objs)?;
+            block(v, &s.body, objs)
+        }
+        Stmt::TypeSwitch(s) => {
+            opt_stmt(v, &s.init, objs)?;
+            stmt(v, &s.assign, objs)?;
+            block(v, &s.body, objs)
+        }
+        Stmt::Comm(s) => {
+            opt_stmt(v, &s.comm, objs)?;
+            stmts(v, &s.body, objs)
+        }
+        Stmt::Select(s) => block(v, &s.body, objs),
+        Stmt::For(s) => {
+            opt_stmt(v, &s.init, objs)?;
+            opt_expr(v, &s.cond, objs)?;
+            opt_stmt(v, &s.post, objs)?;
+            block(v, &s.body, objs)
+        }
+        Stmt::Range(s) => {
+            opt_expr(v, &s.key, objs)?;
+            opt_expr(v, &s.val, objs)?;
+            expr(v, &s.expr, objs)?;
+            block(v, &s.body, objs)
+        }
+    }
+}
+
+fn decl<V: Visitor + ?Sized>(v: &mut V, d: &Decl, objs: &AstObjects) -> WalkResult {
+    if !enter(v.visit_decl(d, objs))? {
+        return Ok(());
+    }
+    match d {
+        Decl::Bad(_) => Ok(()),
+        Decl::Gen(g) => g.specs.iter().try_for_each(|&s| match &objs.specs[s] {
+            Spec::Import(s) => s.name.iter().try_for_each(|&n| ident(v, n, objs)),
+            Spec::Value(s) => {
+                idents(v, &s.names, objs)?;
+                opt_expr(v, &s.typ, objs)?;
+                exprs(v, &s.values, objs)
+            }
+            Spec::Type(s) => {
+                ident(v, s.name, objs)?;
+                expr(v, &s.typ, objs)
+            }
+        }),
+        Decl::Func(key) => {
+            let fd = &objs.fdecls[*key];
+            fd.recv.iter().try_for_each(|r| fields(v, r, objs))?;
+            ident(v, fd.name, objs)?;
+            func_type(v, fd.typ, objs)?;
+            fd.body.iter().try_for_each(|b| block(v, b, objs))
+        }
+    }
+}
@@ -57,3 +57,2200 @@ fn test_issue3() {
     let (p, _) = fe::parse_file(o, &mut fs, el, "/a", "`", false);
     print!("{}", p.get_errors());
 }
+
+fn stmt_kind(s: &fe::ast::Stmt) -> &'static str {
+    use fe::ast::Stmt;
+    match s {
+        Stmt::Bad(_) => "Bad",
+        Stmt::Decl(_) => "Decl",
+        Stmt::Empty(_) => "Empty",
+        Stmt::Labeled(_) => "Labeled",
+        Stmt::Expr(_) => "Expr",
+        Stmt::Send(_) => "Send",
+        Stmt::IncDec(_) => "IncDec",
+        Stmt::Assign(_) => "Assign",
+        Stmt::Go(_) => "Go",
+        Stmt::Defer(_) => "Defer",
+        Stmt::Return(_) => "Return",
+        Stmt::Branch(_) => "Branch",
+        Stmt::Block(_) => "Block",
+        Stmt::If(_) => "If",
+        Stmt::Case(_) => "Case",
+        Stmt::Switch(_) => "Switch",
+        Stmt::TypeSwitch(_) => "TypeSwitch",
+        Stmt::Comm(_) => "Comm",
+        Stmt::Select(_) => "Select",
+        Stmt::For(_) => "For",
+        Stmt::Range(_) => "Range",
+    }
+}
+
+#[test]
+fn test_parse_file() {
+    let src = r#"package main
+
+import (
+	"fmt"
+	m "math"
+)
+import "os"
+
+var a = b + undefinedX
+
+func f() T { return g(a) }
+
+func g(x int) T { return T(len(fmt.Sprint(m.Pi, os.Args))) }
+
+type T int
+
+var b = 1
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+    assert_eq!(o.idents[f.name].name, "main");
+    assert_eq!(f.decls.len(), 7);
+    assert_eq!(f.imports.len(), 3);
+    // the forward references to the package members are resolved at the end,
+    // what's left is for the type checker
+    let mut unresolved: Vec<&str> = f
+        .unresolved
+        .iter()
+        .map(|i| o.idents[*i].name.as_str())
+        .collect();
+    unresolved.sort();
+    unresolved.dedup();
+    assert_eq!(
+        unresolved,
+        vec!["fmt", "int", "len", "m", "os", "undefinedX"]
+    );
+    let members = o
+        .idents
+        .vec()
+        .iter()
+        .filter(|i| ["b", "g", "T"].contains(&i.name.as_str()));
+    for ident in members {
+        assert!(
+            matches!(ident.entity, fe::ast::IdentEntity::Entity(_)),
+            "{} is not resolved",
+            ident.name
+        );
+    }
+
+    let (errors, decls) = parse_errors("package _\n\nvar a = 1\n");
+    assert_eq!(errors, vec!["1:10 invalid package name _"]);
+    assert_eq!(decls, 0);
+}
+
+#[test]
+fn test_resolve_package() {
+    use fe::ast::IdentEntity;
+    use fe::scope::{DeclObj, EntityKind};
+
+    let uses: Vec<String> = fe::scope::PREDECLARED
+        .iter()
+        .map(|(name, _)| format!("\t_ = {}\n", name))
+        .collect();
+    let a = format!("package p\n\nfunc f() {{\n{}}}\n", uses.concat());
+    // new is shadowed in a local scope, len at the package level of another file
+    let b = r#"package p
+
+import "fmt"
+
+func g() {
+	new := 1
+	_ = new
+	_ = len
+	_ = fmt.Sprint
+}
+
+func len() {}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let mut files = vec![];
+    for (name, src) in [("/a.go", a.as_str()), ("/b.go", b)] {
+        let (p, f) = fe::parse_file(o, &mut fs, el, name, src, false);
+        assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+        files.push(f.unwrap());
+    }
+    let universe = fe::scope::new_universe(o);
+    fe::scope::resolve_package(o, &mut files, universe);
+
+    // the names of imported packages are left for the type checker
+    let unresolved: Vec<&str> = files
+        .iter()
+        .flat_map(|f| f.unresolved.iter())
+        .map(|i| o.idents[*i].name.as_str())
+        .collect();
+    assert_eq!(unresolved, vec!["fmt"]);
+
+    let entity = |i: &fe::ast::Ident| match i.entity {
+        IdentEntity::Entity(e) => &o.entities[e],
+        _ => panic!("{} is not resolved", i.name),
+    };
+    let uses: Vec<&fe::ast::Ident> = o.idents.vec().iter().filter(|i| i.pos > 0).collect();
+    for (name, kind) in fe::scope::PREDECLARED.iter() {
+        let ident = uses.iter().find(|i| i.name == *name).unwrap();
+        let e = entity(ident);
+        assert_eq!(e.kind.kind_text(), kind.kind_text(), "{}", name);
+        if *name == "len" {
+            // the package function
+            assert!(matches!(e.decl, DeclObj::FuncDecl(_)));
+        } else if *name == "new" {
+            assert!(matches!(e.decl, DeclObj::NoDecl));
+        }
+    }
+    let news: Vec<&EntityKind> = uses
+        .iter()
+        .filter(|i| i.name == "new")
+        .map(|i| &entity(i).kind)
+        .collect();
+    assert_eq!(news.len(), 3);
+    assert!(matches!(news[1], EntityKind::Var));
+    assert!(matches!(news[2], EntityKind::Var));
+}
+
+#[test]
+fn test_stmt_kinds() {
+    let src = r#"package main
+
+func f(c chan int, x interface{}) int {
+	const k = 1
+	var v int
+	;
+loop:
+	for i := 0; i < 3; i++ {
+		if i == 1 {
+			continue loop
+		}
+		break
+	}
+	f(c, x)
+	c <- v
+	v++
+	v = k
+	go f(c, x)
+	defer f(c, x)
+	{
+	}
+	if v > 0 {
+	} else {
+	}
+	switch v {
+	case 1:
+		fallthrough
+	default:
+	}
+	switch x.(type) {
+	}
+	select {
+	case <-c:
+	}
+	for range c {
+	}
+	goto end
+end:
+	return v
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let body = match &f.unwrap().decls[0] {
+        fe::ast::Decl::Func(key) => o.fdecls[*key].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let kinds: Vec<&str> = body.list.iter().map(stmt_kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "Decl",
+            "Decl",
+            "Empty",
+            "Labeled",
+            "Expr",
+            "Send",
+            "IncDec",
+            "Assign",
+            "Go",
+            "Defer",
+            "Block",
+            "If",
+            "Switch",
+            "TypeSwitch",
+            "Select",
+            "Range",
+            "Branch",
+            "Labeled",
+        ]
+    );
+    let labeled: Vec<&str> = body
+        .list
+        .iter()
+        .filter_map(|s| match s {
+            fe::ast::Stmt::Labeled(key) => Some(stmt_kind(&o.l_stmts[*key].stmt)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(labeled, vec!["For", "Return"]);
+}
+
+#[test]
+fn test_for_stmt() {
+    use fe::ast::{Decl, Stmt};
+    let src = r#"package main
+
+func f(xs []int) {
+	for {
+	}
+	for len(xs) > 0 {
+	}
+	for i := 0; i < 3; i++ {
+	}
+	for ; ; {
+	}
+	for range xs {
+	}
+	for k := range xs {
+	}
+	for k, v := range xs {
+	}
+	_ = k
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let body = match &f.unwrap().decls[0] {
+        Decl::Func(key) => o.fdecls[*key].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let forms: Vec<(bool, bool, bool)> = body.list[..4]
+        .iter()
+        .map(|s| match s {
+            Stmt::For(f) => (f.init.is_some(), f.cond.is_some(), f.post.is_some()),
+            _ => panic!("not a for statement"),
+        })
+        .collect();
+    assert_eq!(
+        forms,
+        vec![
+            (false, false, false),
+            (false, true, false),
+            (true, true, true),
+            (false, false, false)
+        ]
+    );
+    let ranges: Vec<(bool, bool)> = body.list[4..7]
+        .iter()
+        .map(|s| match s {
+            Stmt::Range(r) => (r.key.is_some(), r.val.is_some()),
+            _ => panic!("not a range statement"),
+        })
+        .collect();
+    assert_eq!(ranges, vec![(false, false), (true, false), (true, true)]);
+    // the variables of the clauses are scoped to the statement
+    let k = match &body.list[7] {
+        Stmt::Assign(key) => match &o.a_stmts[*key].rhs[0] {
+            fe::ast::Expr::Ident(i) => &o.idents[*i],
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    assert_eq!(k.name, "k");
+    assert!(!matches!(k.entity, fe::ast::IdentEntity::Entity(_)));
+
+    let (errors, _) =
+        parse_errors("package main\nfunc f(xs []int) {\n\tfor a, b, c := range xs {\n\t}\n}\n");
+    assert_eq!(errors, vec!["3:6 expected at most 2 expressions"]);
+}
+
+#[test]
+fn test_switch_stmt() {
+    use fe::ast::{Decl, Stmt};
+    let src = r#"package main
+
+func f(x interface{}, n int) {
+	switch m := n * 2; m {
+	case 1, 2:
+	case 3:
+		fallthrough
+	default:
+	}
+	switch {
+	case n > 0:
+	}
+	switch v := x.(type) {
+	case int, string:
+		_ = v
+	case nil:
+	}
+	switch x.(type) {
+	}
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let body = match &f.unwrap().decls[0] {
+        Decl::Func(key) => o.fdecls[*key].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let clauses = |list: &Vec<Stmt>| -> Vec<Option<usize>> {
+        list.iter()
+            .map(|s| match s {
+                Stmt::Case(c) => c.list.as_ref().map(|l| l.len()),
+                _ => panic!("not a case clause"),
+            })
+            .collect()
+    };
+    match &body.list[0] {
+        Stmt::Switch(s) => {
+            assert!(matches!(s.init, Some(Stmt::Assign(_))));
+            assert!(s.tag.is_some());
+            assert_eq!(clauses(&s.body.list), vec![Some(2), Some(1), None]);
+        }
+        _ => panic!("not an expression switch"),
+    }
+    match &body.list[1] {
+        Stmt::Switch(s) => {
+            assert!(s.init.is_none() && s.tag.is_none());
+            assert_eq!(clauses(&s.body.list), vec![Some(1)]);
+        }
+        _ => panic!("not an expression switch"),
+    }
+    match &body.list[2] {
+        Stmt::TypeSwitch(s) => {
+            assert!(matches!(s.assign, Stmt::Assign(_)));
+            assert_eq!(clauses(&s.body.list), vec![Some(2), Some(1)]);
+        }
+        _ => panic!("not a type switch"),
+    }
+    match &body.list[3] {
+        Stmt::TypeSwitch(s) => assert!(matches!(s.assign, Stmt::Expr(_))),
+        _ => panic!("not a type switch"),
+    }
+
+    let (errors, _) = parse_errors(
+        "package main\nfunc f(x int) {\n\tswitch x {\n\tdefault:\n\tcase 1:\n\tdefault:\n\tdefault:\n\t}\n}\n",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "6:2 multiple defaults in switch (first at 4:2)",
+            "7:2 multiple defaults in switch (first at 4:2)"
+        ]
+    );
+}
+
+#[test]
+fn test_select_stmt() {
+    use fe::ast::{Decl, Stmt};
+    let src = r#"package main
+
+func f(a chan int, b chan int) {
+	var w int
+	select {
+	case a <- 1:
+	case v := <-a:
+		_ = v
+	case v, ok := <-b:
+		_, _ = v, ok
+	case w = <-a:
+	case <-b:
+	default:
+	}
+	select {}
+	_ = v
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let body = match &f.unwrap().decls[0] {
+        Decl::Func(key) => o.fdecls[*key].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    let clauses = |s: &Stmt| -> Vec<&'static str> {
+        let list = match s {
+            Stmt::Select(s) => &s.body.list,
+            _ => panic!("not a select statement"),
+        };
+        list.iter()
+            .map(|s| match s {
+                Stmt::Comm(c) => match &c.comm {
+                    Some(Stmt::Send(_)) => "send",
+                    Some(Stmt::Assign(key)) => match o.a_stmts[*key].lhs.len() {
+                        1 => "recv",
+                        _ => "recv ok",
+                    },
+                    Some(Stmt::Expr(_)) => "expr",
+                    None => "default",
+                    _ => panic!("unexpected comm"),
+                },
+                _ => panic!("not a comm clause"),
+            })
+            .collect()
+    };
+    assert_eq!(
+        clauses(&body.list[1]),
+        vec!["send", "recv", "recv ok", "recv", "expr", "default"]
+    );
+    assert!(clauses(&body.list[2]).is_empty());
+    // the variables of the clauses are scoped to the clause
+    let v = match &body.list[3] {
+        Stmt::Assign(key) => match &o.a_stmts[*key].rhs[0] {
+            fe::ast::Expr::Ident(i) => &o.idents[*i],
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    assert_eq!(v.name, "v");
+    assert!(!matches!(v.entity, fe::ast::IdentEntity::Entity(_)));
+
+    let (errors, _) = parse_errors(
+        "package main\nfunc f(a chan int) {\n\tselect {\n\tcase x, y, z := <-a:\n\tcase x := 1:\n\tcase x := <-a, <-a:\n\tcase a:\n\t}\n}\n",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "4:7 expected 1 or 2 expressions",
+            "5:12 expected receive operation",
+            "6:17 expected 1 expression",
+            "7:7 expected receive operation"
+        ]
+    );
+}
+
+#[test]
+fn test_gen_decl() {
+    use fe::ast::{Decl, Expr, IdentEntity, Spec};
+    use fe::scope::EntityData;
+    let src = r#"package main
+
+const (
+	A = iota
+	B
+	_
+	C = "c"
+	D
+)
+
+const E int = 1
+
+var (
+	x, y int
+	z    = 1.5
+	w    float32 = 2
+)
+
+type T struct{}
+
+type (
+	U = T
+	V map[string]T
+)
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+    let decls: Vec<_> = f
+        .decls
+        .iter()
+        .map(|d| match d {
+            Decl::Gen(g) => g.clone(),
+            _ => panic!("not a gen decl"),
+        })
+        .collect();
+    assert_eq!(decls.len(), 5);
+    let grouped: Vec<(bool, usize)> = decls
+        .iter()
+        .map(|g| (g.l_paran.is_some() && g.r_paren.is_some(), g.specs.len()))
+        .collect();
+    assert_eq!(
+        grouped,
+        vec![(true, 5), (false, 1), (true, 3), (false, 1), (true, 2)]
+    );
+
+    // the names of a spec, the kind they are declared as and the data of the entity,
+    // the count of the types and the values
+    type ValueSpec<'a> = (Vec<&'a str>, Vec<(&'a str, isize)>, usize, usize);
+    let value_specs = |decl: usize| -> Vec<ValueSpec> {
+        decls[decl]
+            .specs
+            .iter()
+            .map(|s| match &o.specs[*s] {
+                Spec::Value(v) => {
+                    let names = v.names.iter().map(|i| o.idents[*i].name.as_str());
+                    let entities = v.names.iter().filter_map(|i| match o.idents[*i].entity {
+                        IdentEntity::Entity(e) => match o.entities[e].data {
+                            EntityData::ConIota(iota) => {
+                                Some((o.entities[e].kind.kind_text(), iota))
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                    (
+                        names.collect(),
+                        entities.collect(),
+                        v.typ.iter().count(),
+                        v.values.len(),
+                    )
+                }
+                _ => panic!("not a value spec"),
+            })
+            .collect()
+    };
+    // the specs without values take the ones of the previous spec in the
+    // type checker, only the iota is recorded
+    assert_eq!(
+        value_specs(0),
+        vec![
+            (vec!["A"], vec![("const", 0)], 0, 1),
+            (vec!["B"], vec![("const", 1)], 0, 0),
+            (vec!["_"], vec![("const", 2)], 0, 0),
+            (vec!["C"], vec![("const", 3)], 0, 1),
+            (vec!["D"], vec![("const", 4)], 0, 0),
+        ]
+    );
+    assert_eq!(value_specs(1), vec![(vec!["E"], vec![("const", 0)], 1, 1)]);
+    assert_eq!(
+        value_specs(2),
+        vec![
+            (vec!["x", "y"], vec![("var", 0), ("var", 0)], 1, 0),
+            (vec!["z"], vec![("var", 1)], 0, 1),
+            (vec!["w"], vec![("var", 2)], 1, 1),
+        ]
+    );
+
+    // a definition has no assign position, an alias has the one of '='
+    type TypeSpec<'a> = (&'a str, &'a str, Option<(usize, usize)>, bool);
+    let type_specs: Vec<TypeSpec> = [3, 4]
+        .iter()
+        .flat_map(|&i| decls[i].specs.iter())
+        .map(|s| match &o.specs[*s] {
+            Spec::Type(t) => {
+                let ident = &o.idents[t.name];
+                let kind = match ident.entity {
+                    IdentEntity::Entity(e) => o.entities[e].kind.kind_text(),
+                    _ => "",
+                };
+                let assign = (t.assign > 0).then(|| {
+                    let p = fs.position(t.assign).unwrap();
+                    (p.line, p.column)
+                });
+                (
+                    ident.name.as_str(),
+                    kind,
+                    assign,
+                    matches!(t.typ, Expr::Ident(_)),
+                )
+            }
+            _ => panic!("not a type spec"),
+        })
+        .collect();
+    assert_eq!(
+        type_specs,
+        vec![
+            ("T", "type", None, false),
+            ("U", "type", Some((22, 4)), true),
+            ("V", "type", None, false),
+        ]
+    );
+
+    let (errors, decls) = parse_errors(
+        "package main\nconst (\n\ta\n\tb = 1\n\tc int\n\td\n)\nconst e\nvar f\nvar (\n\tg int\n\th\n)\n",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "3:2 missing constant value",
+            "5:2 missing constant value",
+            "8:7 missing constant value",
+            "9:5 missing variable type or initialization",
+            "12:2 missing variable type or initialization"
+        ]
+    );
+    assert_eq!(decls, 4);
+}
+
+fn parse_errors(src: &str) -> (Vec<String>, usize) {
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    let errors = p
+        .get_errors()
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{} {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    (errors, f.map_or(0, |f| f.decls.len()))
+}
+
+#[test]
+fn test_positions_across_files() {
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &fe::ErrorList::new();
+    let a = "package a\n\nvar x = 1 +\n";
+    let b = "package b\n\n// \u{e9}t\u{e9}\nfunc f() {\n\tx := [1\n}\n";
+    fe::parse_file(o, &mut fs, el, "/a.go", a, false);
+    fe::parse_file(o, &mut fs, el, "/b.go", b, false);
+    // the first error in each file, the EOF is at the end of the last line
+    let mut errors: Vec<String> = el.borrow().iter().map(|e| e.to_string()).collect();
+    errors.dedup_by(|e, prev| e[..5] == prev[..5]);
+    assert_eq!(
+        errors,
+        [
+            "/a.go:3:13: expected operand, found EOF\n",
+            "/b.go:5:9: expected ']', found newline\n",
+        ],
+        "{}",
+        el
+    );
+    assert_eq!(fs.position(fs.base() - 1).unwrap().to_string(), "/b.go:6:3");
+}
+
+#[test]
+fn test_node_spans() {
+    use fe::ast::{Decl, Expr, Node, Spec, Stmt};
+    let src = r#"package main
+
+import "fmt"
+
+type T struct {
+	Näme string `json:"näme"`
+}
+
+var (
+	x, y = 1, "héllo"
+)
+
+func f(a []int, m map[string]int) (int, error) {
+	b := a[1:2]
+	v, ok := interface{}(a).(int)
+	s := T{Näme: "é"}.Näme
+	for i := range a {
+		b[i]++
+	}
+	fmt.Println(-x+2*(y[0]), func() {}, &s, *f, m["k"])
+	return 0, nil
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &fe::ErrorList::new();
+    let f = fe::parse_file(o, &mut fs, el, "/a.go", src, false)
+        .1
+        .unwrap();
+    assert_eq!(el.len(), 0, "{}", el);
+    let base = fs.iter().next().unwrap().base();
+    let chars: Vec<char> = src.chars().collect();
+    let text =
+        |pos: usize, end: usize| -> String { chars[pos - base..end - base].iter().collect() };
+    let node = |n: &dyn Node| text(n.pos(o), n.end(o));
+
+    let spec = |d: &Decl| match d {
+        Decl::Gen(g) => g.specs[0],
+        _ => panic!("not a gen decl"),
+    };
+    assert_eq!(node(&f.decls[0]), r#"import "fmt""#);
+    let typ = spec(&f.decls[1]);
+    assert_eq!(
+        node(&o.specs[typ]),
+        "T struct {\n\tNäme string `json:\"näme\"`\n}"
+    );
+    let field = match &o.specs[typ] {
+        Spec::Type(t) => match &t.typ {
+            Expr::Struct(s) => s.fields.list[0],
+            _ => panic!("not a struct"),
+        },
+        _ => panic!("not a type spec"),
+    };
+    assert_eq!(node(&field), "Näme string `json:\"näme\"`");
+    assert_eq!(node(&f.decls[2]), "var (\n\tx, y = 1, \"héllo\"\n)");
+    assert_eq!(node(&o.specs[spec(&f.decls[2])]), "x, y = 1, \"héllo\"");
+
+    let fdecl = match &f.decls[3] {
+        Decl::Func(d) => &o.fdecls[*d],
+        _ => panic!("not a func decl"),
+    };
+    assert!(node(&f.decls[3]).starts_with("func f(") && node(&f.decls[3]).ends_with("nil\n}"));
+    assert_eq!(
+        node(&fdecl.typ),
+        "func f(a []int, m map[string]int) (int, error)"
+    );
+    let params = &o.ftypes[fdecl.typ].params;
+    assert_eq!(
+        text(params.pos(o), params.end(o)),
+        "(a []int, m map[string]int)"
+    );
+    let results = o.ftypes[fdecl.typ].results.as_ref().unwrap();
+    assert_eq!(text(results.pos(o), results.end(o)), "(int, error)");
+
+    let body = &fdecl.body.as_ref().unwrap().list;
+    let rhs = |s: &Stmt| match s {
+        Stmt::Assign(a) => o.a_stmts[*a].rhs[0].clone(),
+        _ => panic!("not an assignment"),
+    };
+    assert_eq!(node(&body[0]), "b := a[1:2]");
+    assert_eq!(node(&rhs(&body[1])), "interface{}(a).(int)");
+    assert_eq!(node(&rhs(&body[2])), "T{Näme: \"é\"}.Näme");
+    let range = match &body[3] {
+        Stmt::Range(r) => r,
+        _ => panic!("not a range"),
+    };
+    assert_eq!(node(&body[3]), "for i := range a {\n\t\tb[i]++\n\t}");
+    assert_eq!(
+        text(range.body.pos(), range.body.end()),
+        "{\n\t\tb[i]++\n\t}"
+    );
+    assert_eq!(node(&range.body.list[0]), "b[i]++");
+    let args = match &body[4] {
+        Stmt::Expr(e) => match &**e {
+            Expr::Call(c) => c.args.clone(),
+            _ => panic!("not a call"),
+        },
+        _ => panic!("not an expression"),
+    };
+    assert_eq!(
+        node(&body[4]),
+        "fmt.Println(-x+2*(y[0]), func() {}, &s, *f, m[\"k\"])"
+    );
+    let spans: Vec<String> = args.iter().map(|a| node(a)).collect();
+    assert_eq!(spans, ["-x+2*(y[0])", "func() {}", "&s", "*f", "m[\"k\"]"]);
+    assert_eq!(node(&body[5]), "return 0, nil");
+}
+
+#[test]
+fn test_walk() {
+    use fe::ast::{Expr, Stmt};
+    use fe::walk::{self, Visitor, Walk};
+
+    #[derive(Default)]
+    struct Collect {
+        idents: usize,
+        callees: Vec<String>,
+        // stops at the first return
+        stop_at_return: bool,
+        skip_func_lits: bool,
+    }
+
+    impl Visitor for Collect {
+        fn visit_expr(&mut self, expr: &Expr, objs: &fe::AstObjects) -> Walk {
+            match expr {
+                Expr::Call(call) => {
+                    let name = match &call.func {
+                        Expr::Ident(i) => objs.idents[*i].name.clone(),
+                        Expr::Selector(s) => match &s.expr {
+                            Expr::Ident(x) => {
+                                format!("{}.{}", objs.idents[*x].name, objs.idents[s.sel].name)
+                            }
+                            _ => format!("?.{}", objs.idents[s.sel].name),
+                        },
+                        Expr::FuncLit(_) => "func".to_owned(),
+                        _ => "?".to_owned(),
+                    };
+                    self.callees.push(name);
+                    Walk::Continue
+                }
+                Expr::FuncLit(_) if self.skip_func_lits => Walk::SkipChildren,
+                _ => Walk::Continue,
+            }
+        }
+
+        fn visit_stmt(&mut self, stmt: &Stmt, _: &fe::AstObjects) -> Walk {
+            match stmt {
+                Stmt::Return(_) if self.stop_at_return => Walk::Stop,
+                _ => Walk::Continue,
+            }
+        }
+
+        fn visit_ident(&mut self, _: fe::IdentKey, _: &fe::AstObjects) -> Walk {
+            self.idents += 1;
+            Walk::Continue
+        }
+    }
+
+    let src = r#"package main
+
+import f "fmt"
+
+type T struct{ A, B int }
+
+func (t *T) Sum() int { return t.A + t.B }
+
+func main() {
+	t := &T{A: 1}
+	defer func() { recover() }()
+	f.Println(t.Sum(), len(make([]int, 2)))
+L:
+	for i := range []int{1} {
+		go print(i)
+		break L
+	}
+}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &fe::ErrorList::new();
+    let file = fe::parse_file(o, &mut fs, el, "/a.go", src, false)
+        .1
+        .unwrap();
+    assert_eq!(el.len(), 0, "{}", el);
+
+    let mut all = Collect::default();
+    assert!(walk::walk_file(&mut all, &file, o));
+    // main f T A B int t T Sum int t A t B main t T A recover f Println t Sum len
+    // make int L i int print i L
+    assert_eq!(all.idents, 32);
+    assert_eq!(
+        all.callees,
+        [
+            "func",
+            "recover",
+            "f.Println",
+            "t.Sum",
+            "len",
+            "make",
+            "print"
+        ]
+    );
+
+    let mut skipping = Collect {
+        skip_func_lits: true,
+        ..Default::default()
+    };
+    walk::walk_file(&mut skipping, &file, o);
+    assert!(!skipping.callees.contains(&"recover".to_owned()));
+
+    let mut stopping = Collect {
+        stop_at_return: true,
+        ..Default::default()
+    };
+    assert!(!walk::walk_file(&mut stopping, &file, o));
+    // main f T A B int t T Sum int
+    assert_eq!(stopping.idents, 10);
+    assert!(stopping.callees.is_empty());
+}
+
+// The shape of a tree: the kinds of the nodes, the names, the literals and the
+// operators in the order of a walk, without the positions.
+fn shape(o: &fe::AstObjects, f: &fe::ast::File) -> Vec<String> {
+    use fe::ast::{Decl, Expr, Stmt};
+    use fe::walk::{self, Visitor, Walk};
+
+    fn kind<T: std::fmt::Debug>(node: &T) -> String {
+        let s = format!("{:?}", node);
+        s[..s.find('(').unwrap_or(s.len())].to_owned()
+    }
+
+    struct Shape(Vec<String>);
+
+    impl Visitor for Shape {
+        fn visit_expr(&mut self, expr: &Expr, _: &fe::AstObjects) -> Walk {
+            let node = match expr {
+                Expr::BasicLit(l) => l.token.get_literal().to_owned(),
+                Expr::Unary(u) => format!("Unary {}", u.op),
+                Expr::Binary(b) => format!("Binary {}", b.op),
+                Expr::Chan(c) => format!("Chan {:?}", c.dir),
+                Expr::Slice(s) => format!("Slice {}", s.slice3),
+                Expr::Call(c) => format!("Call {}", c.ellipsis.is_some()),
+                Expr::Array(a) => format!("Array {}", a.len.is_some()),
+                _ => kind(expr),
+            };
+            self.0.push(node);
+            Walk::Continue
+        }
+
+        fn visit_stmt(&mut self, stmt: &Stmt, o: &fe::AstObjects) -> Walk {
+            let node = match stmt {
+                Stmt::Assign(a) => format!("Assign {}", o.a_stmts[*a].token),
+                Stmt::IncDec(s) => format!("IncDec {}", s.token),
+                Stmt::Branch(s) => format!("Branch {}", s.token),
+                Stmt::Range(s) => format!("Range {}", s.token),
+                Stmt::Case(c) => format!("Case {}", c.list.is_some()),
+                Stmt::Comm(c) => format!("Comm {}", c.comm.is_some()),
+                _ => kind(stmt),
+            };
+            self.0.push(node);
+            Walk::Continue
+        }
+
+        fn visit_decl(&mut self, decl: &Decl, o: &fe::AstObjects) -> Walk {
+            let node = match decl {
+                Decl::Gen(g) => {
+                    let aliases = g.specs.iter().map(|s| match &o.specs[*s] {
+                        fe::ast::Spec::Type(t) => (t.assign > 0).to_string(),
+                        fe::ast::Spec::Import(i) => i.path.token.get_literal().to_owned(),
+                        fe::ast::Spec::Value(_) => "value".to_owned(),
+                    });
+                    format!("{} {:?}", g.token, aliases.collect::<Vec<_>>())
+                }
+                _ => kind(decl),
+            };
+            self.0.push(node);
+            Walk::Continue
+        }
+
+        fn visit_ident(&mut self, ident: fe::IdentKey, o: &fe::AstObjects) -> Walk {
+            self.0.push(o.idents[ident].name.clone());
+            Walk::Continue
+        }
+    }
+
+    let mut shape = Shape(vec![]);
+    walk::walk_file(&mut shape, f, o);
+    shape.0
+}
+
+#[test]
+fn test_printer_round_trip() {
+    let snippets = [
+        "import \"fmt\"\nimport (\n\tm \"math\"\n\t. \"sort\"\n\t_ \"strings\"\n)",
+        "const (\n\tA = iota\n\tB\n\tC, D = 1.5, 'x'\n)\nvar s, t string = \"a\", `b`",
+        "type T struct {\n\tA, B int `json:\"a\"`\n\t*U\n\tf func(int) (int, error)\n}",
+        "type I interface {\n\tM(a int, b ...string) bool\n\tJ\n}\ntype E interface{}",
+        "type A = map[string][]*[4]int\ntype B [...]struct{}",
+        "type C chan (<-chan int)\ntype D <-chan chan<- int\ntype F chan<- <-chan int",
+        "func f(c chan int) { c <- <-c; x, ok := <-c; _ = x == ok }",
+        "func f() func() func(int) int { return nil }",
+        "func (t *T) m(a, b int, c ...int) (r int, err error) { return a + b, nil }",
+        "func f() { x := a*(b+c) - d/e%f<<g | h&^i; y := !(a || b) && c != d }",
+        "func f() { x := - -a + -(-b) + ^^c; p := &*q; r := & ^s; t := *(*u).v }",
+        "func f() { a[1:2], b[:], c[1:2:3] = x.(T), y.(*U), z[i]; g(h...) }",
+        "func f() { x := T{1, 2}; y := []T{{A: 1}, {}}; z := &map[K]V{k: {}}; w := (*T)(nil) }",
+        "func f() { if x := g(); x > 0 { return } else if y { x++ } else { x-- } }",
+        "func f() { for { break }; for x < 10 { continue }; for i := 0; i < n; i++ {}; for ; ; x++ {} }",
+        "func f() { for k, v := range m {}; for k = range m {}; for range c {} }",
+        "func f() { switch x := y; x {\ncase 1, 2:\n\tfallthrough\ncase 3:\ndefault:\n\tg()\n} }",
+        "func f() { switch t := x.(type) {\ncase int, nil:\n\tuse(t)\n}; switch {} }",
+        "func f() { select {\ncase c <- 1:\ncase v, ok := <-d:\n\t_ = v\ncase <-e:\ndefault:\n} }",
+        "func f() {\nL:\n\tfor {\n\t\tgo g()\n\t\tdefer func() { recover() }()\n\t\tgoto L\n\t}\n\t;\n\tvar z, w = 1, 2\n}",
+        "func f() { if (T{}) == x {}; for _, x := range (T{}).s {}; x := func(a int) int { return a }(1) }",
+    ];
+    for snippet in snippets.iter() {
+        let src = format!("package p\n\n{}\n", snippet);
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = &fe::ErrorList::new();
+        let file = fe::parse_file(o, &mut fs, el, "/a.go", &src, false)
+            .1
+            .unwrap();
+        assert_eq!(el.len(), 0, "{}\n{}", src, el);
+
+        let printed = fe::printer::print_file(&file, o);
+        let mut fs2 = fe::FileSet::new();
+        let o2 = &mut fe::AstObjects::new();
+        let el2 = &fe::ErrorList::new();
+        let file2 = fe::parse_file(o2, &mut fs2, el2, "/b.go", &printed, false)
+            .1
+            .unwrap();
+        assert_eq!(el2.len(), 0, "{}\n{}", printed, el2);
+        assert_eq!(shape(o, &file), shape(o2, &file2), "{}\n{}", src, printed);
+        // printing is a fixed point after the first time
+        assert_eq!(fe::printer::print_file(&file2, o2), printed);
+    }
+
+    let src = "package p; type T struct{}; func f(x interface{}) (int, T) { switch x.(type) { case int: return 1, T{} }; return 0, T{} }";
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &fe::ErrorList::new();
+    let file = fe::parse_file(o, &mut fs, el, "/c.go", src, false)
+        .1
+        .unwrap();
+    assert_eq!(
+        fe::printer::print_file(&file, o),
+        "package p\n\ntype T struct{}\n\nfunc f(x interface{}) (int, T) {\n\tswitch x.(type) {\n\tcase int:\n\t\treturn 1, T{}\n\t}\n\treturn 0, T{}\n}\n"
+    );
+}
+
+#[test]
+fn test_printer_precedence() {
+    use fe::ast::{BinaryExpr, Expr, UnaryExpr};
+    use std::rc::Rc;
+
+    // the tree without its parentheses, they are printed where they are needed
+    fn unparen(e: &Expr) -> Expr {
+        match e {
+            Expr::Paren(p) => unparen(&p.expr),
+            Expr::Binary(b) => Expr::Binary(Rc::new(BinaryExpr {
+                expr_a: unparen(&b.expr_a),
+                op_pos: b.op_pos,
+                op: b.op.clone(),
+                expr_b: unparen(&b.expr_b),
+            })),
+            Expr::Unary(u) => Expr::Unary(Rc::new(UnaryExpr {
+                op_pos: u.op_pos,
+                op: u.op.clone(),
+                expr: unparen(&u.expr),
+            })),
+            _ => e.clone(),
+        }
+    }
+
+    let cases = [
+        ("(a + b) * c", "(a + b) * c"),
+        ("a + (b * c)", "a + b * c"),
+        ("(a - b) - c", "a - b - c"),
+        ("a - (b - c)", "a - (b - c)"),
+        ("a || (b && c)", "a || b && c"),
+        ("(a || b) && c", "(a || b) && c"),
+        ("(a == b) == (c < d)", "a == b == (c < d)"),
+        ("-(a + b)", "-(a + b)"),
+        ("-(-a)", "- -a"),
+        ("&(^a)", "& ^a"),
+        ("<-(<-c)", "<-<-c"),
+        ("!(a)", "!a"),
+    ];
+    for (src, want) in cases.iter() {
+        let (x, o) = fe::parse_expr(src).unwrap();
+        assert_eq!(fe::printer::print_expr(&unparen(&x), &o), *want, "{}", src);
+        // the parentheses of the source are kept
+        assert_eq!(fe::printer::print_expr(&x, &o), *src);
+    }
+}
+
+#[test]
+fn test_func_decl() {
+    use fe::ast::{Decl, Expr};
+    use fe::scope::DeclObj;
+    let src = r#"package main
+
+func f(a int) int { return a }
+
+func (t *T) m() {}
+
+func g()
+
+func init() {}
+
+func init() {}
+
+type T struct{}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+    let decls: Vec<_> = f
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Func(key) => Some(*key),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(decls.len(), 5);
+
+    // the name, whether there is a receiver and a body
+    let shapes: Vec<(&str, bool, bool)> = decls
+        .iter()
+        .map(|k| {
+            let d = &o.fdecls[*k];
+            (
+                o.idents[d.name].name.as_str(),
+                d.recv.is_some(),
+                d.body.is_some(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        shapes,
+        vec![
+            ("f", false, true),
+            ("m", true, true),
+            ("g", false, false),
+            ("init", false, true),
+            ("init", false, true),
+        ]
+    );
+
+    let recv = o.fdecls[decls[1]].recv.as_ref().unwrap();
+    assert_eq!(recv.list.len(), 1);
+    let field = &o.fields[recv.list[0]];
+    assert_eq!(field.names.len(), 1);
+    assert_eq!(o.idents[field.names[0]].name, "t");
+    assert!(matches!(&field.typ, Expr::Star(s) if matches!(s.expr, Expr::Ident(_))));
+
+    // functions are package members, methods and init functions are not
+    let scope = &o.scopes[f.scope];
+    for (name, decl) in [("f", decls[0]), ("g", decls[2])] {
+        let entity = &o.entities[*scope.look_up(&name.to_owned()).unwrap()];
+        assert_eq!(entity.kind.kind_text(), "func");
+        assert!(matches!(entity.decl, DeclObj::FuncDecl(k) if k == decl));
+    }
+    assert!(scope.look_up(&"m".to_owned()).is_none());
+    assert!(scope.look_up(&"init".to_owned()).is_none());
+
+    let (errors, decls) =
+        parse_errors("package main\nfunc (a, b T) m() {}\nfunc (T, *T) n() {}\nfunc () o() {}\n");
+    assert_eq!(
+        errors,
+        vec![
+            "2:6 method has multiple receivers",
+            "3:6 method has multiple receivers"
+        ]
+    );
+    assert_eq!(decls, 3);
+}
+
+#[test]
+fn test_error_notes() {
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let src = "package main\n\nfunc f() {\n\tx := 1\n\tvar x int\n\t_ = x\n}\n";
+    let (p, _) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    let errors = p.get_errors();
+    assert_eq!(errors.len(), 1);
+    {
+        let e = &errors.borrow()[0];
+        assert_eq!((e.pos.line, e.pos.column), (5, 6));
+        assert_eq!(e.notes.len(), 1);
+        let n = &e.notes[0];
+        assert_eq!((n.pos.line, n.pos.column), (4, 2));
+        assert_eq!(n.label, "previous declaration");
+    }
+    assert_eq!(
+        errors.to_string(),
+        "/a.go:5:6: x redeclared in this block\n\t/a.go:4:2: note: previous declaration\n"
+    );
+
+    // the same message at the same position is reported once, with its notes merged
+    let e0 = errors.borrow()[0].clone();
+    errors.add(Some(e0.pos.clone()), e0.msg.clone(), false, true);
+    errors.add_note(e0.notes[0].pos.clone(), "previous declaration".to_owned());
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors.borrow()[0].notes.len(), 1);
+    errors.add(
+        Some(e0.pos.clone()),
+        "another error".to_owned(),
+        false,
+        true,
+    );
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn test_error_list_order() {
+    let mut fs = fe::FileSet::new();
+    let el = fe::ErrorList::new();
+    let src = "package main\n\nvar a = 1\nvar b = 2\n";
+    let mut bases = vec![];
+    for name in ["/a.go", "/b.go"] {
+        bases.push(fs.base());
+        let f = fs.add_file(name.to_owned(), None, src.len());
+        f.set_lines(vec![0, 13, 14, 24]);
+    }
+    // the files share the list, their errors interleave
+    let add = |file: usize, offset: usize, msg: &str| {
+        let pos = fs.position(bases[file] + offset);
+        el.add(pos, msg.to_owned(), false, true)
+    };
+    add(0, 0, "a first");
+    add(1, 25, "b line 4");
+    add(0, 27, "a line 4, col 3");
+    add(1, 14, "b line 3");
+    add(0, 25, "a line 4");
+    add(0, 14, "a line 3");
+    add(0, 16, "a line 3, again");
+    el.sort();
+    let msgs = |el: &fe::ErrorList| el.iter().map(|e| e.msg).collect::<Vec<_>>();
+    assert_eq!(
+        msgs(&el),
+        [
+            "a first",
+            "a line 3",
+            "a line 3, again",
+            "a line 4",
+            "a line 4, col 3",
+            "b line 3",
+            "b line 4"
+        ]
+    );
+    assert_eq!(
+        el.to_string().lines().take(2).collect::<Vec<_>>(),
+        ["/a.go:1:1: a first", "/a.go:3:1: a line 3"]
+    );
+    // the same error again after sorting is still found
+    add(0, 14, "a line 3");
+    assert_eq!(el.len(), 7);
+
+    el.remove_multiples();
+    assert_eq!(
+        msgs(&el),
+        ["a first", "a line 3", "a line 4", "b line 3", "b line 4"]
+    );
+}
+
+#[test]
+fn test_too_many_errors() {
+    // 100 statements with a syntax error each
+    let mut src = "package main\n\nfunc main() {\n".to_owned();
+    for i in 0..100 {
+        src.push_str(&format!("\tx{} := [1]int{{)}}\n", i));
+    }
+    src.push_str("}\n");
+    let parse = |max_errors: Option<usize>| {
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = fe::ErrorList::new();
+        let config = fe::ParserConfig {
+            max_errors,
+            ..fe::ParserConfig::default()
+        };
+        fe::parse_file_with_config(o, &mut fs, &el, "/a.go", &src, config);
+        el
+    };
+
+    let el = parse(None);
+    assert!(el.len() >= 100, "{}", el);
+    assert!(el.iter().all(|e| e.msg != fe::TOO_MANY_ERRORS));
+
+    let el = parse(Some(10));
+    assert_eq!(el.len(), 11, "{}", el);
+    let last = el.iter().last().unwrap();
+    assert_eq!(last.msg, fe::TOO_MANY_ERRORS);
+    // it gave up in the first lines with errors
+    assert!(last.pos.line < 15, "{}", el);
+    el.remove_multiples();
+    assert_eq!(el.iter().last().unwrap().msg, fe::TOO_MANY_ERRORS);
+}
+
+#[test]
+fn test_call_args() {
+    use fe::ast::{Decl, Expr, Stmt};
+    let src = "package main\n\nfunc g() {\n\tf(a, b)\n\tf(a...)\n\tT(x)\n\tf()\n}\n";
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let body = match &f.unwrap().decls[0] {
+        Decl::Func(key) => o.fdecls[*key].body.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    // the number of args, the line and column of the parens and of the ellipsis
+    let col = |p| fs.position(p).map(|fp| (fp.line, fp.column));
+    let calls: Vec<_> = body
+        .list
+        .iter()
+        .map(|s| match s {
+            Stmt::Expr(e) => match e.as_ref() {
+                Expr::Call(c) => (
+                    c.args.len(),
+                    col(c.l_paren).unwrap(),
+                    col(c.r_paren).unwrap(),
+                    c.ellipsis.and_then(col),
+                ),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        calls,
+        vec![
+            (2, (4, 3), (4, 8), None),
+            (1, (5, 3), (5, 8), Some((5, 5))),
+            (1, (6, 3), (6, 5), None),
+            (0, (7, 3), (7, 4), None),
+        ]
+    );
+
+    // the arguments after a misplaced '...' are still read
+    let (errors, decls) = parse_errors(
+        "package main\nfunc g() {\n\tf(a..., b)\n\tf(a..., b..., c)\n\th(1)\n}\nfunc h() {}\n",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "3:5 can only use ... with final argument in list",
+            "4:5 can only use ... with final argument in list",
+        ]
+    );
+    assert_eq!(decls, 2);
+}
+
+#[test]
+fn test_struct_recovery() {
+    use fe::ast::{Decl, Expr, Spec};
+    let src = r#"package main
+
+type A struct{ 123 }
+
+type B struct {
+	a int
+	"x"
+	b, c string
+	;
+	d int
+}
+
+type C struct{ e int }
+
+func f() {}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    let errors: Vec<_> = p
+        .get_errors()
+        .borrow()
+        .iter()
+        .map(|e| format!("{}:{} {}", e.pos.line, e.pos.column, e.msg))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            "3:16 expected field name or embedded type, found INT",
+            "7:2 expected field name or embedded type, found STRING",
+            "9:2 expected field name or embedded type",
+        ]
+    );
+    let f = f.unwrap();
+    assert_eq!(f.decls.len(), 4);
+
+    // the number of fields, and whether some were skipped
+    let structs: Vec<(usize, bool)> = f
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Gen(g) => match &o.specs[g.specs[0]] {
+                Spec::Type(t) => match &t.typ {
+                    Expr::Struct(s) => Some((s.fields.list.len(), s.incomplete)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(structs, vec![(0, true), (3, true), (1, false)]);
+}
+
+#[test]
+fn test_embedded_fields() {
+    use fe::ast::{Decl, Expr, Spec};
+    let src = r#"package main
+
+import (
+	"bytes"
+	"io"
+)
+
+type W struct {
+	*bytes.Buffer
+	io.Writer
+	T
+	*T
+	n int
+}
+
+type R interface {
+	io.Reader
+	Name() string
+}
+
+type T struct{}
+"#;
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+
+    // the embedded types as written, with the number of names of the fields
+    let describe = |e: &Expr| match e {
+        Expr::Ident(i) => o.idents[*i].name.clone(),
+        Expr::Selector(s) => match &s.expr {
+            Expr::Ident(i) => format!("{}.{}", o.idents[*i].name, o.idents[s.sel].name),
+            _ => "?".to_owned(),
+        },
+        Expr::Star(s) => match &s.expr {
+            Expr::Ident(i) => format!("*{}", o.idents[*i].name),
+            Expr::Selector(s) => match &s.expr {
+                Expr::Ident(i) => format!("*{}.{}", o.idents[*i].name, o.idents[s.sel].name),
+                _ => "?".to_owned(),
+            },
+            _ => "?".to_owned(),
+        },
+        Expr::Func(_) => "func".to_owned(),
+        _ => "?".to_owned(),
+    };
+    let fields: Vec<Vec<(String, usize)>> = f
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Gen(g) => match &o.specs[g.specs[0]] {
+                Spec::Type(t) => match &t.typ {
+                    Expr::Struct(s) => Some(&s.fields),
+                    Expr::Interface(i) => Some(&i.methods),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|l| {
+            l.list
+                .iter()
+                .map(|f| (describe(&o.fields[*f].typ), o.fields[*f].names.len()))
+                .collect()
+        })
+        .collect();
+    let owned = |v: &[(&str, usize)]| -> Vec<(String, usize)> {
+        v.iter().map(|(s, n)| (s.to_string(), *n)).collect()
+    };
+    assert_eq!(
+        fields,
+        vec![
+            owned(&[
+                ("*bytes.Buffer", 0),
+                ("io.Writer", 0),
+                ("T", 0),
+                ("*T", 0),
+                ("int", 1)
+            ]),
+            owned(&[("io.Reader", 0), ("func", 1)]),
+            vec![],
+        ]
+    );
+    // the package names are left for the type checker to resolve to the imports
+    let unresolved: Vec<&str> = f
+        .unresolved
+        .iter()
+        .map(|i| o.idents[*i].name.as_str())
+        .filter(|n| ["bytes", "io"].contains(n))
+        .collect();
+    assert_eq!(unresolved, vec!["bytes", "io", "io"]);
+
+    let (errors, decls) = parse_errors(
+        "package main\ntype A struct {\n\t(T)\n\t(*T)\n\t*(pkg.T)\n\t**T\n}\ntype T int\n",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "3:2 cannot parenthesize embedded type",
+            "4:2 cannot parenthesize embedded type",
+            "5:3 cannot parenthesize embedded type",
+            "6:2 expected anonymous field",
+        ]
+    );
+    assert_eq!(decls, 2);
+}
+
+#[test]
+fn test_literal_recovery() {
+    let missing_comma = r#"package main
+
+var xs = []int{
+	1,
+	2,
+	3,
+	4
+	5,
+	6,
+	7,
+	8,
+	9,
+	10,
+}
+
+func f() int { return 1 }
+"#;
+    let (errors, decls) = parse_errors(missing_comma);
+    assert_eq!(
+        errors,
+        vec!["7:3 missing ',' before newline in composite literal"]
+    );
+    assert_eq!(decls, 2);
+
+    let (errors, decls) = parse_errors(
+        "package main\nvar m = map[string]int{\"a\": 1, \"b\": 2 \"c\": 3}\nfunc f() {}\n",
+    );
+    assert_eq!(errors, vec!["2:39 missing ',' in composite literal"]);
+    assert_eq!(decls, 2);
+
+    let unclosed = "package main\n\nvar xs = [][]int{\n\t{1, 2},\n\t{3,\n";
+    let (errors, _) = parse_errors(unclosed);
+    assert_eq!(errors, vec!["5:2 missing '}' in composite literal"]);
+
+    // the declarations after an unterminated literal are not swallowed
+    let (errors, decls) = parse_errors(
+        "package main\n\nvar xs = []int{\n\t1,\n\nfunc f() int { return 1 }\n\nfunc g() {}\n",
+    );
+    assert_eq!(errors, vec!["3:15 missing '}' in composite literal"]);
+    assert_eq!(decls, 3);
+
+    let (errors, _) = parse_errors("package main\nvar xs = []int{1, 2 3 4 5 6 7 8, ) 9}\n");
+    assert_eq!(
+        errors,
+        vec![
+            "2:21 missing ',' in composite literal",
+            "2:23 missing ',' in composite literal",
+            "2:25 missing ',' in composite literal",
+            "2:27 ... (further errors in this literal suppressed)",
+        ]
+    );
+}
+
+#[test]
+fn test_raw_string_tag() {
+    use fe::ast::{Decl, Expr, Spec};
+    let src = "package main\n\ntype T struct {\n\tName string `json:\"name\"\r\n\tdb:\"n\\x\"`\n}\n\nvar s = `a\\n\nb`\n";
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (p, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+    let tag = match &f.decls[0] {
+        Decl::Gen(g) => match &o.specs[g.specs[0]] {
+            Spec::Type(t) => match &t.typ {
+                Expr::Struct(s) => o.fields[s.fields.list[0]].tag.clone(),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    match tag {
+        Some(Expr::BasicLit(lit)) => {
+            // as written, but for the carriage return, no escape sequences
+            assert_eq!(lit.token.get_literal(), "`json:\"name\"\n\tdb:\"n\\x\"`");
+            assert_eq!(lit.token.get_literal().lines().count(), 2);
+        }
+        _ => panic!("the tag is not a literal"),
+    }
+    assert_eq!(f.decls.len(), 2);
+
+    let (errors, _) = parse_errors("package main\n\nvar s = \"a\\qb\"\nvar t = `unterminated\n");
+    assert_eq!(
+        errors,
+        vec![
+            "3:12 unknown escape sequence",
+            "4:9 raw string literal not terminated"
+        ]
+    );
+}
+
+#[test]
+fn test_one_liners() {
+    let stmts = [
+        "go f()",
+        "defer f()",
+        "x++",
+        "x--",
+        "a[0]++",
+        "p.x--",
+        "return",
+        "return x",
+        "return x, y",
+        "if x { return }",
+        "if x { return x } else { x++ }",
+        "for { break }",
+        "for i := 0; i < n; i++ { continue }",
+        "switch x { case 1: fallthrough; default: }",
+        "select { case c <- 1: x++; default: }",
+        "func() { x++ }()",
+        "go func() { c <- 1 }()",
+        "x = []int{1, 2}",
+        "_ = 'a'",
+        "_ = `raw`",
+        "_ = 2i",
+    ];
+    for stmt in stmts {
+        // on one line, on their own lines, in a block of one line, and at the end of the file
+        for src in [
+            format!("package main\nfunc f() {{ {} }}\n", stmt),
+            format!("package main\nfunc f() {{\n\t{}\n\t{}\n}}\n", stmt, stmt),
+            format!(
+                "package main\nfunc f() {{ {{ {} }}; {{ {} }} }}\n",
+                stmt, stmt
+            ),
+            format!("package main\nfunc f() {{ {} /* c */ }}", stmt),
+            format!("package main\r\nfunc f() {{\r\n\t{} // c\r\n}}\r\n", stmt),
+        ] {
+            let (errors, decls) = parse_errors(&src);
+            assert!(errors.is_empty(), "{:?}: {:?}", src, errors);
+            assert_eq!(decls, 1, "{:?}", src);
+        }
+    }
+
+    // a ',' instead of a ';' is reported once, the statements after it are parsed
+    let (errors, decls) = parse_errors("package main\nfunc f() { x++, y--; z++ }\nfunc g() {}\n");
+    assert_eq!(errors, vec!["2:15 expected ';', found ,"]);
+    assert_eq!(decls, 2);
+}
+
+#[test]
+fn test_parse_expr() {
+    use fe::ast::Expr;
+    let (x, o) = fe::parse_expr("a + b*2").unwrap();
+    match &x {
+        Expr::Binary(b) => {
+            assert_eq!(b.op, fe::Token::ADD);
+            assert!(matches!(&b.expr_a, Expr::Ident(i) if o.idents[*i].name == "a"));
+            assert!(matches!(&b.expr_b, Expr::Binary(m) if m.op == fe::Token::MUL));
+        }
+        _ => panic!("not a binary expression: {:?}", x),
+    }
+
+    let (x, o) = fe::parse_expr("map[string]int{\"a\": 1, \"b\": 2}\n").unwrap();
+    match &x {
+        Expr::CompositeLit(lit) => {
+            assert_eq!(lit.elts.len(), 2);
+            match lit.typ.as_ref().unwrap() {
+                Expr::Map(m) => {
+                    assert!(matches!(&m.key, Expr::Ident(i) if o.idents[*i].name == "string"))
+                }
+                t => panic!("not a map type: {:?}", t),
+            }
+        }
+        _ => panic!("not a composite literal: {:?}", x),
+    }
+    // a type is an expression too
+    assert!(matches!(
+        fe::parse_expr("map[string]int"),
+        Ok((Expr::Map(_), _))
+    ));
+
+    let errors = match fe::parse_expr("a + b c") {
+        Err(errors) => errors,
+        Ok((x, _)) => panic!("parsed with trailing garbage: {:?}", x),
+    };
+    let errors: Vec<String> = errors
+        .borrow()
+        .iter()
+        .map(|e| format!("{} {}", e.pos.column, e.msg))
+        .collect();
+    assert_eq!(errors, vec!["7 expected EOF, found IDENT"]);
+    assert!(fe::parse_expr("a +").is_err());
+}
+
+#[test]
+fn test_comments() {
+    use fe::ast::{Decl, Expr, Spec};
+    let src = r#"// Package main is a test.
+package main
+
+import (
+	// for Println
+	"fmt" // printing
+)
+
+// A Point is a point.
+//
+// It has two coordinates.
+type Point struct {
+	// X is the first one.
+	X int // the x
+	Y int /* the y */
+}
+
+/*
+   f does
+   nothing.
+*/
+func f() {
+	// not a doc comment
+
+	fmt.Println()
+}
+
+var (
+	// a is one
+	a = 1 // one
+	b = 2
+)
+
+// detached
+
+func g() {}
+"#;
+    let text =
+        |o: &fe::AstObjects, c: Option<fe::CommentGroupKey>| c.map(|k| o.comment_groups[k].text());
+    let some = |s: &str| Some(s.to_owned());
+
+    // the comments are skipped by default
+    let (errors, _) = parse_errors(src);
+    assert!(errors.is_empty());
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (_, f) = fe::parse_file(o, &mut fs, el, "/a.go", src, false);
+    let f = f.unwrap();
+    assert!(f.doc.is_none() && f.comments.is_empty());
+    assert_eq!(o.comment_groups.vec().len(), 0);
+
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let config = fe::ParserConfig {
+        comments: true,
+        ..fe::ParserConfig::default()
+    };
+    let (p, f) = fe::parse_file_with_config(o, &mut fs, el, "/a.go", src, config);
+    assert_eq!(p.get_errors().len(), 0, "{}", p.get_errors());
+    let f = f.unwrap();
+    assert_eq!(text(o, f.doc), some("Package main is a test.\n"));
+    assert_eq!(f.comments.len(), 12);
+
+    let gen = |i: usize| match &f.decls[i] {
+        Decl::Gen(g) => g.clone(),
+        _ => unreachable!(),
+    };
+    let import = gen(0);
+    assert_eq!(text(o, import.doc), None);
+    match &o.specs[import.specs[0]] {
+        Spec::Import(i) => {
+            assert_eq!(text(o, i.doc), some("for Println\n"));
+            assert_eq!(text(o, i.comment), some("printing\n"));
+        }
+        _ => unreachable!(),
+    }
+
+    let point = gen(1);
+    assert_eq!(
+        text(o, point.doc),
+        some("A Point is a point.\n\nIt has two coordinates.\n")
+    );
+    let fields = match &o.specs[point.specs[0]] {
+        Spec::Type(t) => match &t.typ {
+            Expr::Struct(s) => s.fields.list.clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    let x = &o.fields[fields[0]];
+    assert_eq!(text(o, x.doc), some("X is the first one.\n"));
+    assert_eq!(text(o, x.comment), some("the x\n"));
+    let y = &o.fields[fields[1]];
+    assert_eq!(text(o, y.doc), None);
+    assert_eq!(text(o, y.comment), some(" the y\n"));
+    // with the positions as they are in the source
+    let pos = |p: usize| {
+        let p = fs.position(p).unwrap();
+        (p.line, p.column)
+    };
+    let group = &o.comment_groups[y.comment.unwrap()];
+    assert_eq!(group.list[0].text, "/* the y */");
+    assert_eq!((pos(group.pos()), pos(group.end())), ((15, 8), (15, 19)));
+
+    let fdecl = |i: usize| match &f.decls[i] {
+        Decl::Func(k) => &o.fdecls[*k],
+        _ => unreachable!(),
+    };
+    assert_eq!(text(o, fdecl(2).doc), some("   f does\n   nothing.\n"));
+    let doc = fdecl(2).doc.unwrap();
+    assert_eq!(pos(o.comment_groups[doc].pos()), (18, 1));
+
+    let vars = gen(3);
+    assert_eq!(text(o, vars.doc), None);
+    let values: Vec<_> = vars
+        .specs
+        .iter()
+        .map(|s| match &o.specs[*s] {
+            Spec::Value(v) => (text(o, v.doc), text(o, v.comment)),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        values,
+        vec![(some("a is one\n"), some("one\n")), (None, None)]
+    );
+
+    // separated by a blank line, so not the doc of g, but still collected
+    assert_eq!(text(o, fdecl(4).doc), None);
+    assert_eq!(text(o, f.comments.last().copied()), some("detached\n"));
+}
+
+// Prints the tree with the nodes in the arenas in place of their keys, and the
+// entities as what they are and where they are declared.
+fn dump(o: &fe::AstObjects, f: &fe::ast::File) -> String {
+    let s = format!(
+        "{} {:?} {:?} {:?} {:?}",
+        f.package, f.name, f.decls, f.imports, f.unresolved
+    );
+    expand_keys(o, &s)
+}
+
+fn expand_keys(o: &fe::AstObjects, s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find("Key(") {
+        let start = rest[..i]
+            .rfind(|c: char| !c.is_alphanumeric())
+            .map_or(0, |j| j + 1);
+        let end = i + 4 + rest[i + 4..].find(')').unwrap_or(0);
+        let node = rest[i + 4..end].parse::<usize>().ok().and_then(|n| {
+            Some(match &rest[start..i] {
+                "Ident" => format!("{:?}", o.idents.vec()[n]),
+                "Field" => format!("{:?}", o.fields.vec()[n]),
+                "FuncType" => format!("{:?}", o.ftypes.vec()[n]),
+                "Spec" => format!("{:?}", o.specs.vec()[n]),
+                "FuncDecl" => format!("{:?}", o.fdecls.vec()[n]),
+                "AssignStmt" => format!("{:?}", o.a_stmts.vec()[n]),
+                "LabeledStmt" => format!("{:?}", o.l_stmts.vec()[n]),
+                "Entity" => {
+                    let e = &o.entities.vec()[n];
+                    format!("{:?} {} at {}", e.kind, e.name, e.pos(o))
+                }
+                "Scope" => "scope".to_owned(),
+                _ => return None,
+            })
+        });
+        match node {
+            Some(node) => {
+                out.push_str(&rest[..start]);
+                out.push_str(&expand_keys(o, &node));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[..i + 4]);
+                rest = &rest[i + 4..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Checks that a reparsed file is the same as the file parsed from scratch.
+fn assert_same_as_full_parse(parsed: &fe::ParsedFile, path: &str) {
+    let src = parsed.source();
+    let mut fs = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &mut fe::ErrorList::new();
+    let (_, f) = fe::parse_file(o, &mut fs, el, path, src, false);
+
+    let dumps = (
+        parsed.file().map(|f| dump(parsed.objects(), f)),
+        f.map(|f| dump(o, &f)),
+    );
+    if dumps.0 != dumps.1 {
+        panic!(
+            "reparse differs from parse of {}:\n{}\n{:?}\n{:?}",
+            path, src, dumps.0, dumps.1
+        );
+    }
+    let (a, b) = (
+        parsed.fset().iter().next().unwrap(),
+        fs.iter().next().unwrap(),
+    );
+    assert_eq!(a.size(), b.size());
+    assert_eq!(a.line_count(), b.line_count(), "{}", src);
+    for l in 1..a.line_count() {
+        assert_eq!(a.line_start(l), b.line_start(l));
+    }
+    assert_eq!(format!("{}", parsed.errors()), format!("{}", el), "{}", src);
+}
+
+#[test]
+fn test_unicode_source() {
+    let parse = |src: &str| {
+        let mut fs = fe::FileSet::new();
+        let o = &mut fe::AstObjects::new();
+        let el = &fe::ErrorList::new();
+        let file = fe::parse_file(o, &mut fs, el, "/a.go", src, false).1;
+        let idents: Vec<String> = o.idents.iter().map(|i| i.name.clone()).collect();
+        let errors: Vec<String> = el.borrow().iter().map(|e| e.to_string()).collect();
+        (file.is_some(), idents, errors)
+    };
+
+    let src = "package main\n\nfunc main() {\n\tπ := 3.14\n\tαβγ2 := π * 2\n\t_ = αβγ2\n}\n";
+    let (ok, idents, errors) = parse(src);
+    assert!(ok && errors.is_empty(), "{:?}", errors);
+    assert!(idents.contains(&"π".to_owned()));
+    assert!(idents.contains(&"αβγ2".to_owned()));
+
+    let src = "package main\n\ntype T struct {\n\t日本語 string\n}\n\nvar t = T{日本語: \"x\"}\n";
+    let (ok, idents, errors) = parse(src);
+    assert!(ok && errors.is_empty(), "{:?}", errors);
+    assert_eq!(idents.iter().filter(|i| *i == "日本語").count(), 2);
+
+    // the bytes that are not UTF-8 are read as U+FFFD
+    let bytes = b"package main\n\nvar s = \"a\xffb\"\nvar x = 1 \xff 2\nvar y = 2\n";
+    let src = String::from_utf8_lossy(bytes);
+    let (ok, idents, errors) = parse(&src);
+    assert!(ok);
+    assert!(idents.contains(&"y".to_owned()));
+    assert_eq!(
+        errors,
+        [
+            "/a.go:3:11: illegal UTF-8 encoding\n",
+            "/a.go:4:11: illegal UTF-8 encoding\n"
+        ]
+    );
+}
+
+#[cfg(feature = "serde_borsh")]
+#[test]
+fn test_ast_cache() {
+    let path = "/a.go";
+    let src = r#"package main
+
+import "fmt"
+
+// T is a pair
+type T struct{ a, b int }
+
+func (t *T) sum() int { return t.a + t.b }
+
+func main() {
+	t := &T{a: 1, b: 'é'}
+L:
+	for i := range []rune("ab") {
+		switch {
+		case i > 0:
+			break L
+		}
+	}
+	fmt.Println(t.sum(), 1.5i, `raw`)
+}
+"#;
+    let mut fset = fe::FileSet::new();
+    let o = &mut fe::AstObjects::new();
+    let el = &fe::ErrorList::new();
+    let file = fe::parse_file(o, &mut fset, el, path, src, false)
+        .1
+        .unwrap();
+
+    let bytes = file.serialize_with(o).unwrap();
+    let (file2, o2) = fe::ast::File::deserialize_with(&bytes).unwrap();
+    assert_eq!(dump(o, &file), dump(&o2, &file2));
+    assert_eq!(
+        fe::printer::print_file(&file, o),
+        fe::printer::print_file(&file2, &o2)
+    );
+    // the scopes link to the entities by their keys
+    let names = |o: &fe::AstObjects, f: &fe::ast::File| {
+        let mut names: Vec<String> = o.scopes[f.scope]
+            .entities
+            .iter()
+            .map(|(name, e)| format!("{} {:?}", name, o.entities[*e].kind))
+            .collect();
+        names.sort();
+        names
+    };
+    assert!(!names(o, &file).is_empty());
+    assert_eq!(names(o, &file), names(&o2, &file2));
+
+    let mut stale = bytes.clone();
+    stale[0] ^= 1;
+    match fe::ast::File::deserialize_with(&stale) {
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+        Ok(_) => panic!("loaded a cache of another version"),
+    }
+    assert!(fe::ast::File::deserialize_with(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_reparse() {
+    let src = r#"package main
+
+import "fmt"
+
+const N = 3
+
+type T struct{ next *T }
+
+func f(t *T) int {
+	for i := range g() {
+		_ = i
+	}
+	return N
+}
+
+func g() []int { return make([]int, N) }
+
+func h() { fmt.Println(f(nil)) }
+"#;
+    let path = "./main.go";
+    let parsed = fe::ParsedFile::parse(path, src);
+    assert_same_as_full_parse(&parsed, path);
+
+    // only f is parsed again
+    let at = src.find("return N").unwrap();
+    let parsed = parsed.reparse(&[fe::TextEdit::new(at..at + 6, "return 2 *")]);
+    assert_same_as_full_parse(&parsed, path);
+    assert_eq!(parsed.reused_decls(), 5);
+
+    // g is declared before f now, and then not declared
+    let at = parsed.source().find("func f").unwrap();
+    let parsed = parsed.reparse(&[fe::TextEdit::new(at..at, "func g() {}\n\n")]);
+    assert_same_as_full_parse(&parsed, path);
+    let at = parsed.source().find("func g() {}").unwrap();
+    let parsed = parsed.reparse(&[fe::TextEdit::new(at..at + 11, "var x = 1")]);
+    assert_same_as_full_parse(&parsed, path);
+
+    // an unterminated comment and its end
+    let at = parsed.source().find("const").unwrap();
+    let parsed = parsed.reparse(&[fe::TextEdit::new(at..at, "/*")]);
+    assert_same_as_full_parse(&parsed, path);
+    assert_eq!(parsed.file().unwrap().decls.len(), 1);
+    let at = parsed.source().find("func h").unwrap();
+    let parsed = parsed.reparse(&[fe::TextEdit::new(at..at, "*/")]);
+    assert_same_as_full_parse(&parsed, path);
+
+    // two edits at once
+    let (a, b) = (
+        parsed.source().find("N = 3").unwrap(),
+        parsed.source().find("Println").unwrap(),
+    );
+    let parsed = parsed.reparse(&[
+        fe::TextEdit::new(b..b + 7, "Printf"),
+        fe::TextEdit::new(a..a + 1, "M"),
+    ]);
+    assert_same_as_full_parse(&parsed, path);
+}
+
+// A xorshift generator, for random edits that are the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n.max(1) as u64) as usize
+    }
+
+    fn offset(&mut self, src: &str) -> usize {
+        let mut at = self.below(src.len() + 1);
+        while !src.is_char_boundary(at) {
+            at -= 1;
+        }
+        at
+    }
+
+    fn edit(&mut self, src: &str) -> fe::TextEdit {
+        const SNIPPETS: &[&str] = &[
+            "x",
+            " ",
+            "\n",
+            "{",
+            "}",
+            "(",
+            ")",
+            "\"",
+            "`",
+            "/*",
+            "*/",
+            "//",
+            ";",
+            ",",
+            "0",
+            "é",
+            ":=",
+            "x.",
+            "func",
+            "\nfunc ",
+            "range ",
+            "func f() {}\n",
+            "var v = 1\n",
+            "type T int\n",
+            "import \"fmt\"\n",
+            "const (\n",
+        ];
+        let at = self.offset(src);
+        match self.below(3) {
+            0 => fe::TextEdit::new(at..at, SNIPPETS[self.below(SNIPPETS.len())]),
+            1 => {
+                let mut end = (at + self.below(16)).min(src.len());
+                while !src.is_char_boundary(end) {
+                    end -= 1;
+                }
+                fe::TextEdit::new(at..end, "")
+            }
+            _ => {
+                let from = self.offset(src);
+                let mut to = (from + self.below(40)).min(src.len());
+                while !src.is_char_boundary(to) {
+                    to -= 1;
+                }
+                fe::TextEdit::new(at..at, &src[from..to])
+            }
+        }
+    }
+}
+
+fn corpus_files(dir: &str, files: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let p = path.to_str().unwrap().to_owned();
+        if path.is_dir() {
+            corpus_files(&p, files);
+        } else if p.ends_with(".go") || p.ends_with(".gos") {
+            files.push(p);
+        }
+    }
+}
+
+#[test]
+fn test_reparse_random_edits() {
+    let mut files = vec![];
+    corpus_files("./../std", &mut files);
+    corpus_files("./../engine/tests/group1", &mut files);
+    files.sort();
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut reused = 0;
+    for path in files.iter() {
+        let src = fs::read_to_string(path).unwrap();
+        if src.len() > 64 * 1024 {
+            continue;
+        }
+        let mut parsed = fe::ParsedFile::parse(path, &src);
+        for _ in 0..8 {
+            let src = parsed.source().to_owned();
+            let edit = rng.edit(&src);
+            let mut edits = vec![edit.clone()];
+            if rng.below(4) == 0 {
+                let other = rng.edit(&src);
+                let r = &other.range;
+                if r.end < edit.range.start || r.start > edit.range.end {
+                    edits.push(other);
+                }
+            }
+            parsed = parsed.reparse(&edits);
+            assert_same_as_full_parse(&parsed, path);
+            reused += parsed.reused_decls();
+
+            // and undo it, as an editor would
+            if edits.len() == 1 && rng.below(2) == 0 {
+                let r = &edit.range;
+                let undo = fe::TextEdit::new(r.start..r.start + edit.text.len(), &src[r.clone()]);
+                parsed = parsed.reparse(&[undo]);
+                assert_same_as_full_parse(&parsed, path);
+                assert_eq!(parsed.source(), src);
+                reused += parsed.reused_decls();
+            }
+        }
+    }
+    assert!(reused > 0);
+}
@@ -21,6 +21,10 @@ pub fn derive_ffi_implement(input: proc_macro::TokenStream) -> proc_macro::Token
                 self.dispatch(ctx, args)
             }
 
+            fn decls(&self) -> Vec<go_vm::FfiFuncDecl> {
+                Self::auto_gen_ffi_decls()
+            }
+
             #[cfg(feature = "async")]
             fn async_call(
                 &self,
@@ -18,6 +18,7 @@ use syn::{
 const TYPE_ERR_MSG: &str = "unexpected return type";
 const FFI_FUNC_PREFIX: &str = "ffi_";
 const FFI_ASYNC_FUNC_PREFIX: &str = "ffi_async_";
+const GO_SIG_ATTR: &str = "go_sig";
 
 macro_rules! return_type_panic {
     () => {
@@ -31,6 +32,8 @@ enum FfiReturnType {
     OneVal(bool),
     MultipleVal(Vec<bool>),
     Vec,
+    /// A `FfiResult`
+    Built,
     AlreadyBoxed,
 }
 
@@ -66,9 +69,11 @@ pub fn ffi_impl_implement(
     }
     let type_name = get_last_segment(&impl_block.self_ty).unwrap().ident;
 
+    let decls = gen_decls_method(ffi_methods.iter().chain(async_ffi_methods.iter()));
     let mut methods: Vec<ImplItem> = vec![
         gen_dispatch_method(&impl_block.self_ty, ffi_methods, false),
         gen_dispatch_method(&impl_block.self_ty, async_ffi_methods, true),
+        decls,
         gen_new_method(&type_name, &new_method),
         gen_id_method(&type_name, &args),
         gen_register_method(),
@@ -77,6 +82,12 @@ pub fn ffi_impl_implement(
     .map(|x| (ImplItem::Method(x)))
     .collect();
 
+    // the go_sig attributes are only read by this macro
+    for item in output_block.items.iter_mut() {
+        if let ImplItem::Method(method) = item {
+            method.attrs.retain(|a| !a.path.is_ident(GO_SIG_ATTR));
+        }
+    }
     output_block.items.append(&mut methods);
     output_block.to_tokens(&mut output);
     output.into()
@@ -138,6 +149,8 @@ fn gen_dispatch_method(
             }
             let mut args: Punctuated<Expr, Token![,]> = Punctuated::new();
             let mut param_count = 0;
+            // the handler reads the arguments itself, and checks their count
+            let mut takes_args = false;
             for (i, farg) in method.sig.inputs.iter().enumerate() {
                 let arg_name: &str = &get_last_segment(&fn_arg_as_pat_type(farg).ty)
                     .unwrap()
@@ -152,6 +165,11 @@ fn gen_dispatch_method(
                             panic!("'FfiCtx' should be the first argument")
                         }
                     }
+                    "FfiArgs" => {
+                        args.push_value(parse_quote! {ffi_args});
+                        args.push_punct(Token![,](Span::call_site()));
+                        takes_args = true;
+                    }
                     "GosValue" => {
                         args.push_value(parse_quote! {arg_iter.next().unwrap()});
                         args.push_punct(Token![,](Span::call_site()));
@@ -170,11 +188,19 @@ fn gen_dispatch_method(
             let name = method.sig.ident.to_string();
             let short_name = name.strip_prefix(FFI_FUNC_PREFIX).unwrap();
             let wrapper = gen_wrapper_block(self_ty, &method, &args, is_async);
-            if is_async {
+            if takes_args {
+                parse_quote! {
+                    #short_name => {
+                        let ffi_args = go_vm::FfiArgs::new(ctx.func_name, arg_iter.collect());
+                        #wrapper
+                    },
+                }
+            } else if is_async {
                 parse_quote! {
                     #short_name => {
                         if arg_count != #count_lit {
-                            Box::pin(async move { Err("FFI: bad argument count".to_owned().into()) })
+                            let err = go_vm::FfiError::arity(ctx.func_name, #count_lit, arg_count);
+                            Box::pin(async move { Err(err.into()) })
                         } else {
                             #wrapper
                         }
@@ -184,7 +210,7 @@ fn gen_dispatch_method(
                 parse_quote! {
                     #short_name => {
                         if arg_count != #count_lit {
-                            Err("FFI: bad argument count".to_owned().into())
+                            Err(go_vm::FfiError::arity(ctx.func_name, #count_lit, arg_count).into())
                         } else {
                             #wrapper
                         }
@@ -199,6 +225,120 @@ fn gen_dispatch_method(
     dispatch_method
 }
 
+/// Generates the declarations of the methods with a `#[go_sig = "func(...)"]`
+/// attribute, with the types the handler converts its parameters and results to.
+fn gen_decls_method<'a>(ffis: impl Iterator<Item = &'a &'a ImplItemMethod>) -> ImplItemMethod {
+    let mut decls: Punctuated<Expr, Token![,]> = Punctuated::new();
+    for method in ffis {
+        let sig = method.attrs.iter().find_map(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(nv)) if nv.path.is_ident(GO_SIG_ATTR) => match nv.lit {
+                Lit::Str(s) => Some(s),
+                _ => panic!("go_sig should be a string"),
+            },
+            _ => None,
+        });
+        let sig = match sig {
+            Some(s) => s,
+            None => continue,
+        };
+        let name = method.sig.ident.to_string();
+        let short_name = name.strip_prefix(FFI_FUNC_PREFIX).unwrap();
+        let param_names: Vec<String> = method
+            .sig
+            .inputs
+            .iter()
+            .map(|farg| {
+                get_last_segment(&fn_arg_as_pat_type(farg).ty)
+                    .unwrap()
+                    .ident
+                    .to_string()
+            })
+            .filter(|name| name != "FfiCtx")
+            .collect();
+        let params = match param_names.iter().any(|name| name == "FfiArgs") {
+            // the types of the arguments are checked as the handler reads them
+            true => quote! {
+                go_vm::FfiSignature::parse(#sig).map_or(vec![], |s| vec![None; s.params.len()])
+            },
+            false => {
+                let types: Punctuated<TokenStream, Token![,]> =
+                    param_names.iter().map(|name| value_type_of(name)).collect();
+                quote!(vec![#types])
+            }
+        };
+        let results = match handler_result_types(&method.sig.output) {
+            Some(types) => {
+                let types: Punctuated<TokenStream, Token![,]> =
+                    types.iter().map(|name| value_type_of(name)).collect();
+                quote!(Some(vec![#types]))
+            }
+            None => quote!(None),
+        };
+        decls.push(parse_quote! {
+            go_vm::FfiFuncDecl {
+                name: #short_name,
+                signature: #sig,
+                params: #params,
+                results: #results,
+            }
+        });
+    }
+    parse_quote! {
+        pub fn auto_gen_ffi_decls() -> Vec<go_vm::FfiFuncDecl> {
+            vec![#decls]
+        }
+    }
+}
+
+/// The names of the result types of a handler, `None` if it returns a `Vec`
+fn handler_result_types(rt: &ReturnType) -> Option<Vec<String>> {
+    let t = match rt {
+        ReturnType::Default => return Some(vec![]),
+        ReturnType::Type(_, t) => &**t,
+    };
+    let t = match get_last_segment(t) {
+        Some(seg) if seg.ident == "RuntimeResult" => get_type_arg_type(&seg.arguments),
+        _ => t.clone(),
+    };
+    match &t {
+        Type::Tuple(tt) => Some(
+            tt.elems
+                .iter()
+                .map(|x| get_last_segment(x).unwrap().ident.to_string())
+                .collect(),
+        ),
+        _ => {
+            let name = get_last_segment(&t).unwrap().ident.to_string();
+            match name.as_str() {
+                "Vec" | "Pin" | "FfiResult" => None,
+                _ => Some(vec![name]),
+            }
+        }
+    }
+}
+
+/// The value type of a primitive parameter or result, `None` for `GosValue`
+fn value_type_of(rust_type: &str) -> TokenStream {
+    let vt = match rust_type {
+        "bool" => quote!(Bool),
+        "isize" => quote!(Int),
+        "i8" => quote!(Int8),
+        "i16" => quote!(Int16),
+        "i32" => quote!(Int32),
+        "i64" => quote!(Int64),
+        "usize" => quote!(Uint),
+        "u8" => quote!(Uint8),
+        "u16" => quote!(Uint16),
+        "u32" => quote!(Uint32),
+        "u64" => quote!(Uint64),
+        "f32" => quote!(Float32),
+        "f64" => quote!(Float64),
+        "String" => quote!(String),
+        _ => return quote!(None),
+    };
+    quote!(Some(go_vm::types::ValueType::#vt))
+}
+
 fn gen_new_method(type_name: &Ident, new_method: &Option<ImplItemMethod>) -> ImplItemMethod {
     let new_ident = Ident::new("auto_gen_ffi_new", Span::call_site());
 
@@ -320,6 +460,16 @@ fn gen_wrapper_block(
                     Ok(vec![#ret])
                 }}
             }
+            (false, true, FfiReturnType::Built) => {
+                parse_quote! {{
+                    #self_ty::#callee(#args).map(|r| r.into_vec())
+                }}
+            }
+            (false, false, FfiReturnType::Built) => {
+                parse_quote! {{
+                    Ok(#self_ty::#callee(#args).into_vec())
+                }}
+            }
             (false, _, FfiReturnType::Vec) => panic!("non-async func cannot return a vec"),
             (false, _, _) => panic!("unsupported return type"),
             (true, _, _) => unreachable!(),
@@ -336,6 +486,7 @@ fn get_return_type_attributes(rt: &ReturnType) -> (bool, FfiReturnType) {
                 let type_name = seg.ident.to_string();
                 match type_name.as_str() {
                     "GosValue" => (false, FfiReturnType::OneVal(false)), // todo: futher validation
+                    "FfiResult" => (false, FfiReturnType::Built),
                     _ if is_primitive(&type_name) => (false, FfiReturnType::OneVal(true)),
                     "Pin" => (false, FfiReturnType::AlreadyBoxed), // todo: futher validation
                     "RuntimeResult" => {
@@ -347,6 +498,7 @@ fn get_return_type_attributes(rt: &ReturnType) -> (bool, FfiReturnType) {
                                     "GosValue" => (true, FfiReturnType::OneVal(false)),
                                     _ if is_primitive(&name) => (true, FfiReturnType::OneVal(true)),
                                     "Vec" => (true, FfiReturnType::Vec), // todo: futher validation
+                                    "FfiResult" => (true, FfiReturnType::Built),
                                     _ => return_type_panic!(),
                                 }
                             }
@@ -5,7 +5,6 @@
 package fmt
 
 import (
-	"errors"
 	"internal/fmtsort"
 	"io"
 	"os"
@@ -31,8 +30,17 @@ const (
 	badPrecString     = "%!(BADPREC)"
 	noVerbString      = "%!(NOVERB)"
 	invReflectString  = "<invalid reflect.Value>"
+	cycleString       = "<stringer cycle>"
 )
 
+// maxMethodDepth bounds the nesting of the formatting methods, Format, GoString,
+// Error and String, so that one formatting its own receiver ends instead of
+// recursing forever.
+const maxMethodDepth = 64
+
+// methodDepth is the number of formatting methods being called.
+var methodDepth int
+
 // State represents the printer state passed to custom formatters.
 // It provides access to the io.Writer interface plus information about
 // the flags and options for the operand's format specifier.
@@ -123,6 +131,10 @@ type pp struct {
 	panicking bool
 	// erroring is set when printing an error string to guard against calling handleMethods.
 	erroring bool
+	// wrapErrs is set when the format string may contain a %w verb.
+	wrapErrs bool
+	// wrappedErr records the target of the %w verb.
+	wrappedErr error
 }
 
 var ppFree = sync.Pool{
@@ -134,6 +146,7 @@ func newPrinter() *pp {
 	p := ppFree.Get().(*pp)
 	p.panicking = false
 	p.erroring = false
+	p.wrapErrs = false
 	p.fmt.init(&p.buf)
 	return p
 }
@@ -153,6 +166,7 @@ func (p *pp) free() {
 	p.buf = p.buf[:0]
 	p.arg = nil
 	p.value = reflect.Value{}
+	p.wrappedErr = nil
 	ppFree.Put(p)
 }
 
@@ -217,12 +231,6 @@ func Sprintf(format string, a ...interface{}) string {
 	return s
 }
 
-// Errorf formats according to a format specifier and returns the string
-// as a value that satisfies error.
-func Errorf(format string, a ...interface{}) error {
-	return errors.New(Sprintf(format, a...))
-}
-
 // These routines do not take a format string
 
 // Fprint formats using the default formats for its operands and writes to w.
@@ -572,14 +580,48 @@ func (p *pp) catchPanic(arg interface{}, verb rune, method string) {
 	}
 }
 
+// enterMethod reports whether a formatting method may be called, and prints
+// the cycle marker instead if the calls nest too deep.
+func (p *pp) enterMethod() bool {
+	if methodDepth >= maxMethodDepth {
+		p.fmt.fmtS(cycleString)
+		return false
+	}
+	methodDepth++
+	return true
+}
+
+func (p *pp) leaveMethod() {
+	methodDepth--
+}
+
 func (p *pp) handleMethods(verb rune) (handled bool) {
 	if p.erroring {
 		return
 	}
+	if verb == 'w' {
+		// It is invalid to use %w other than with Errorf, more than once,
+		// or with a non-error arg.
+		err, ok := p.arg.(error)
+		if !ok || !p.wrapErrs || p.wrappedErr != nil {
+			p.wrappedErr = nil
+			p.wrapErrs = false
+			p.badVerb(verb)
+			return true
+		}
+		p.wrappedErr = err
+		// If the arg is a Formatter, pass 'v' as the verb to it.
+		verb = 'v'
+	}
+
 	// Is it a Formatter?
 	if formatter, ok := p.arg.(Formatter); ok {
 		handled = true
 		defer p.catchPanic(p.arg, verb, "Format")
+		if !p.enterMethod() {
+			return
+		}
+		defer p.leaveMethod()
 		formatter.Format(p, verb)
 		return
 	}
@@ -589,6 +631,10 @@ func (p *pp) handleMethods(verb rune) (handled bool) {
 		if stringer, ok := p.arg.(GoStringer); ok {
 			handled = true
 			defer p.catchPanic(p.arg, verb, "GoString")
+			if !p.enterMethod() {
+				return
+			}
+			defer p.leaveMethod()
 			// Print the result of GoString unadorned.
 			p.fmt.fmtS(stringer.GoString())
 			return
@@ -607,12 +653,20 @@ func (p *pp) handleMethods(verb rune) (handled bool) {
 			case error:
 				handled = true
 				defer p.catchPanic(p.arg, verb, "Error")
+				if !p.enterMethod() {
+					return
+				}
+				defer p.leaveMethod()
 				p.fmtString(v.Error(), verb)
 				return
 
 			case Stringer:
 				handled = true
 				defer p.catchPanic(p.arg, verb, "String")
+				if !p.enterMethod() {
+					return
+				}
+				defer p.leaveMethod()
 				p.fmtString(v.String(), verb)
 				return
 			}
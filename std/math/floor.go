@@ -11,6 +11,10 @@ package math
 //	Floor(±Inf) = ±Inf
 //	Floor(NaN) = NaN
 func Floor(x float64) float64 {
+	return native.floor(x)
+}
+
+func floor(x float64) float64 {
 	if x == 0 || IsNaN(x) || IsInf(x, 0) {
 		return x
 	}
@@ -32,7 +36,11 @@ func Floor(x float64) float64 {
 //	Ceil(±Inf) = ±Inf
 //	Ceil(NaN) = NaN
 func Ceil(x float64) float64 {
-	return -Floor(-x)
+	return native.ceil(x)
+}
+
+func ceil(x float64) float64 {
+	return -floor(-x)
 }
 
 // Trunc returns the integer value of x.
@@ -42,6 +50,10 @@ func Ceil(x float64) float64 {
 //	Trunc(±Inf) = ±Inf
 //	Trunc(NaN) = NaN
 func Trunc(x float64) float64 {
+	return native.trunc(x)
+}
+
+func trunc(x float64) float64 {
 	if x == 0 || IsNaN(x) || IsInf(x, 0) {
 		return x
 	}
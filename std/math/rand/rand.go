@@ -42,9 +42,7 @@ type Source64 interface {
 // Unlike the default Source used by top-level functions, this source is not
 // safe for concurrent use by multiple goroutines.
 func NewSource(seed int64) Source {
-	var rng rngSource
-	rng.Seed(seed)
-	return &rng
+	return &rngSource{native.new_source(seed)}
 }
 
 // A Rand is a source of random numbers.
@@ -285,7 +283,9 @@ func read(p []byte, int63 func() int64, readVal *int64, readPos *int8) (n int, e
  * Top-level convenience functions
  */
 
-var globalRand = New(&lockedSource{src: NewSource(1).(Source64)})
+// goscript: set in init, package vars are initialized before the native
+// generator is available
+var globalRand *Rand
 
 // Seed uses the provided seed value to initialize the default Source to a
 // deterministic state. If Seed is not called, the generator behaves as
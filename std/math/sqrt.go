@@ -89,8 +89,11 @@ package math
 //	Sqrt(±0) = ±0
 //	Sqrt(x < 0) = NaN
 //	Sqrt(NaN) = NaN
-
 func Sqrt(x float64) float64 {
+	return native.sqrt(x)
+}
+
+func sqrt(x float64) float64 {
 	// special cases
 	switch {
 	case x == 0 || IsNaN(x) || IsInf(x, 1):
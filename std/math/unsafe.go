@@ -44,6 +44,12 @@ type ffiBits interface {
 	f32_from_bits(b uint32) float32
 	f64_to_bits(f float64) uint64
 	f64_from_bits(b uint64) float64
+
+	// exact operations, the results don't depend on the implementation
+	sqrt(x float64) float64
+	floor(x float64) float64
+	ceil(x float64) float64
+	trunc(x float64) float64
 }
 
 // Float32bits returns the IEEE 754 binary representation of f,
@@ -381,7 +381,7 @@ func (t reflectType) Size() uintptr {
 }
 
 func (t reflectType) String() string {
-	return t.kind.String()
+	return native.type_string(t.typePtr)
 }
 
 func (t reflectType) Kind() Kind {
@@ -417,11 +417,18 @@ func (t reflectType) IsVariadic() bool {
 }
 
 func (t reflectType) Elem() Type {
-	panic("not implemented")
+	ptyp, kind := native.type_elem(t.typePtr)
+	return reflectType{typePtr: ptyp, kind: Kind(kind)}
 }
 
 func (t reflectType) Field(i int) StructField {
-	panic("not implemented")
+	name, ptyp, kind, embedded := native.type_field(t.typePtr, i)
+	return StructField{
+		Name:      name,
+		Type:      reflectType{typePtr: ptyp, kind: Kind(kind)},
+		Index:     []int{i},
+		Anonymous: embedded,
+	}
 }
 
 func (t reflectType) FieldByIndex(index []int) StructField {
@@ -449,7 +456,7 @@ func (t reflectType) Len() int {
 }
 
 func (t reflectType) NumField() int {
-	panic("not implemented")
+	return native.type_num_field(t.typePtr)
 }
 
 func (t reflectType) NumIn() int {
@@ -467,7 +474,11 @@ func (t reflectType) Out(i int) Type {
 // TypeOf returns the reflection Type that represents the dynamic type of i.
 // If i is a nil interface value, TypeOf returns nil.
 func TypeOf(i interface{}) Type {
-	return ValueOf(i).Type()
+	v := ValueOf(i)
+	if !v.IsValid() {
+		return nil
+	}
+	return v.Type()
 }
 
 // PtrTo returns the pointer type with element t.
@@ -19,6 +19,7 @@ type ffiReflect interface {
 	type_of(p unsafe.Pointer) (unsafe.Pointer, uint)
 
 	bool_val(p unsafe.Pointer) bool
+	string_val(p unsafe.Pointer) string
 	int_val(p unsafe.Pointer) int64
 	uint_val(p unsafe.Pointer) uint64
 	float_val(p unsafe.Pointer) float64
@@ -29,13 +30,17 @@ type ffiReflect interface {
 	index(p unsafe.Pointer, i int) unsafe.Pointer
 	is_nil(p unsafe.Pointer) bool
 	len(p unsafe.Pointer) int
+	pointer(p unsafe.Pointer) uintptr
 
+	map_keys(p unsafe.Pointer) []unsafe.Pointer
 	map_range_init(p unsafe.Pointer) unsafe.Pointer
 	map_range_next(p unsafe.Pointer) bool
 	map_range_key(p unsafe.Pointer) unsafe.Pointer
 	map_range_value(p unsafe.Pointer) unsafe.Pointer
 
 	can_addr(p unsafe.Pointer) bool
+	can_interface(p unsafe.Pointer) bool
+	to_interface(p unsafe.Pointer) interface{}
 	can_set(p unsafe.Pointer) bool
 	set(p unsafe.Pointer, v unsafe.Pointer)
 	set_bool(p unsafe.Pointer, b bool)
@@ -48,6 +53,11 @@ type ffiReflect interface {
 	set_string(p unsafe.Pointer, x string)
 
 	swap(slice interface{}, i int, j int)
+
+	type_string(t unsafe.Pointer) string
+	type_elem(t unsafe.Pointer) (unsafe.Pointer, uint)
+	type_num_field(t unsafe.Pointer) int
+	type_field(t unsafe.Pointer, i int) (string, unsafe.Pointer, uint, bool)
 }
 
 // Value is the reflection interface to a Go value.
@@ -93,7 +103,7 @@ func (v Value) Bool() bool {
 }
 
 // Bytes returns v's underlying value.
-// It panics if v's underlying value is not a slice of bytes.
+// It panics if v's underlying value is not a slice of bytes or a string.
 func (v Value) Bytes() []byte {
 	return native.bytes_val(v.ptr)
 }
@@ -216,7 +226,7 @@ func (v Value) Int() int64 {
 
 // CanInterface reports whether Interface can be used without panicking.
 func (v Value) CanInterface() bool {
-	return false
+	return native.can_interface(v.ptr)
 }
 
 // Interface returns v's current value as an interface{}.
@@ -225,7 +235,7 @@ func (v Value) CanInterface() bool {
 // It panics if the Value was obtained by accessing
 // unexported struct fields.
 func (v Value) Interface() (i interface{}) {
-	panic("not implemented")
+	return native.to_interface(v.ptr)
 }
 
 // InterfaceData returns the interface v's value as a uintptr pair.
@@ -279,7 +289,12 @@ func (v Value) MapIndex(key Value) Value {
 // It panics if v's Kind is not Map.
 // It returns an empty slice if v represents a nil map.
 func (v Value) MapKeys() []Value {
-	panic("not implemented")
+	ptrs := native.map_keys(v.ptr)
+	keys := make([]Value, len(ptrs))
+	for i, p := range ptrs {
+		keys[i] = valuePtrToValue(p)
+	}
+	return keys
 }
 
 // A MapIter is an iterator for ranging over a map.
@@ -382,7 +397,7 @@ func (v Value) OverflowUint(x uint64) bool {
 // Unlike in Go, there is nothing much you can do with uintptr
 // other than using it as a handle or printing it out
 func (v Value) Pointer() uintptr {
-	return uintptr(v.ptr)
+	return native.pointer(v.ptr)
 }
 
 // Recv receives and returns a value from the channel v.
@@ -501,7 +516,13 @@ func (v Value) Slice3(i, j, k int) Value {
 // The fmt package treats Values specially. It does not call their String
 // method implicitly but instead prints the concrete values they hold.
 func (v Value) String() string {
-	panic("not implemented")
+	switch k := v.Kind(); k {
+	case Invalid:
+		return "<invalid Value>"
+	case String:
+		return native.string_val(v.ptr)
+	}
+	return "<" + v.Type().String() + " Value>"
 }
 
 // TryRecv attempts to receive a value from the channel v but will not block.
@@ -696,6 +717,9 @@ func (v Value) Convert(t Type) Value {
 }
 
 func valuePtrToValue(pval unsafe.Pointer) Value {
+	if pval == nil {
+		return Value{}
+	}
 	ptyp, kind := native.type_of(pval)
 	typ := reflectType{typePtr: ptyp, kind: Kind(kind)}
 	return Value{ptr: pval, typ: typ}
@@ -8,13 +8,8 @@ package strconv
 // It accepts 1, t, T, TRUE, true, True, 0, f, F, FALSE, false, False.
 // Any other value returns an error.
 func ParseBool(str string) (bool, error) {
-	switch str {
-	case "1", "t", "T", "true", "TRUE", "True":
-		return true, nil
-	case "0", "f", "F", "false", "FALSE", "False":
-		return false, nil
-	}
-	return false, syntaxError("ParseBool", str)
+	b, code := native.parse_bool(str)
+	return b, codeError("ParseBool", str, code)
 }
 
 // FormatBool returns "true" or "false" according to the value of b.
@@ -531,9 +531,6 @@ func atof64(s string) (f float64, err error) {
 // away from the largest floating point number of the given size,
 // ParseFloat returns f = ±Inf, err.Err = ErrRange.
 func ParseFloat(s string, bitSize int) (float64, error) {
-	if bitSize == 32 {
-		f, err := atof32(s)
-		return float64(f), err
-	}
-	return atof64(s)
+	f, code := native.parse_float(s, bitSize)
+	return f, codeError(fnParseFloat, s, code)
 }
@@ -53,85 +53,15 @@ func ParseUint(s string, base int, bitSize int) (uint64, error) {
 	if len(s) == 0 {
 		return 0, syntaxError(fnParseUint, s)
 	}
-
-	s0 := s
-	switch {
-	case 2 <= base && base <= 36:
-		// valid base; nothing to do
-
-	case base == 0:
-		// Look for octal, hex prefix.
-		switch {
-		case s[0] == '0' && len(s) > 1 && (s[1] == 'x' || s[1] == 'X'):
-			if len(s) < 3 {
-				return 0, syntaxError(fnParseUint, s0)
-			}
-			base = 16
-			s = s[2:]
-		case s[0] == '0':
-			base = 8
-			s = s[1:]
-		default:
-			base = 10
-		}
-
-	default:
-		return 0, baseError(fnParseUint, s0, base)
-	}
-
-	if bitSize == 0 {
-		bitSize = int(IntSize)
-	} else if bitSize < 0 || bitSize > 64 {
-		return 0, bitSizeError(fnParseUint, s0, bitSize)
-	}
-
-	// Cutoff is the smallest number such that cutoff*base > maxUint64.
-	// Use compile-time constants for common cases.
-	var cutoff uint64
-	switch base {
-	case 10:
-		cutoff = maxUint64/10 + 1
-	case 16:
-		cutoff = maxUint64/16 + 1
-	default:
-		cutoff = maxUint64/uint64(base) + 1
+	if base != 0 && (base < 2 || base > 36) {
+		return 0, baseError(fnParseUint, s, base)
 	}
-
-	maxVal := uint64(1)<<uint(bitSize) - 1
-
-	var n uint64
-	for _, c := range []byte(s) {
-		var d byte
-		switch {
-		case '0' <= c && c <= '9':
-			d = c - '0'
-		case 'a' <= c && c <= 'z':
-			d = c - 'a' + 10
-		case 'A' <= c && c <= 'Z':
-			d = c - 'A' + 10
-		default:
-			return 0, syntaxError(fnParseUint, s0)
-		}
-
-		if d >= byte(base) {
-			return 0, syntaxError(fnParseUint, s0)
-		}
-
-		if n >= cutoff {
-			// n*base overflows
-			return maxVal, rangeError(fnParseUint, s0)
-		}
-		n *= uint64(base)
-
-		n1 := n + uint64(d)
-		if n1 < n || n1 > maxVal {
-			// n+v overflows
-			return maxVal, rangeError(fnParseUint, s0)
-		}
-		n = n1
+	if bitSize < 0 || bitSize > 64 {
+		return 0, bitSizeError(fnParseUint, s, bitSize)
 	}
 
-	return n, nil
+	n, code := native.parse_uint(s, base, bitSize)
+	return n, codeError(fnParseUint, s, code)
 }
 
 // ParseInt interprets a string s in the given base (0, 2 to 36) and
@@ -160,78 +90,21 @@ func ParseInt(s string, base int, bitSize int) (i int64, err error) {
 	if len(s) == 0 {
 		return 0, syntaxError(fnParseInt, s)
 	}
-
-	// Pick off leading sign.
-	s0 := s
-	neg := false
-	if s[0] == '+' {
-		s = s[1:]
-	} else if s[0] == '-' {
-		neg = true
-		s = s[1:]
-	}
-
-	// Convert unsigned and check range.
-	var un uint64
-	un, err = ParseUint(s, base, bitSize)
-	if err != nil && err.(*NumError).Err != ErrRange {
-		err.(*NumError).Func = fnParseInt
-		err.(*NumError).Num = s0
-		return 0, err
+	if base != 0 && (base < 2 || base > 36) {
+		return 0, baseError(fnParseInt, s, base)
 	}
-
-	if bitSize == 0 {
-		bitSize = int(IntSize)
+	if bitSize < 0 || bitSize > 64 {
+		return 0, bitSizeError(fnParseInt, s, bitSize)
 	}
 
-	cutoff := uint64(1 << uint(bitSize-1))
-	if !neg && un >= cutoff {
-		return int64(cutoff - 1), rangeError(fnParseInt, s0)
-	}
-	if neg && un > cutoff {
-		return -int64(cutoff), rangeError(fnParseInt, s0)
-	}
-	n := int64(un)
-	if neg {
-		n = -n
-	}
-	return n, nil
+	n, code := native.parse_int(s, base, bitSize)
+	return n, codeError(fnParseInt, s, code)
 }
 
 // Atoi is equivalent to ParseInt(s, 10, 0), converted to type int.
 func Atoi(s string) (int, error) {
 	const fnAtoi = "Atoi"
 
-	sLen := len(s)
-	if intSize == 32 && (0 < sLen && sLen < 10) ||
-		intSize == 64 && (0 < sLen && sLen < 19) {
-		// Fast path for small integers that fit int type.
-		s0 := s
-		if s[0] == '-' || s[0] == '+' {
-			s = s[1:]
-			if len(s) < 1 {
-				return 0, &NumError{fnAtoi, s0, ErrSyntax}
-			}
-		}
-
-		n := 0
-		for _, ch := range []byte(s) {
-			ch -= '0'
-			if ch > 9 {
-				return 0, &NumError{fnAtoi, s0, ErrSyntax}
-			}
-			n = n*10 + int(ch)
-		}
-		if s0[0] == '-' {
-			n = -n
-		}
-		return n, nil
-	}
-
-	// Slow path for invalid or big integers.
-	i64, err := ParseInt(s, 10, 0)
-	if nerr, ok := err.(*NumError); ok {
-		nerr.Func = fnAtoi
-	}
-	return int(i64), err
+	n, code := native.parse_int(s, 10, 0)
+	return int(n), codeError(fnAtoi, s, code)
 }
@@ -43,7 +43,13 @@ var float64info = floatInfo{52, 11, -1023}
 // The special precision -1 uses the smallest number of digits
 // necessary such that ParseFloat will return f exactly.
 func FormatFloat(f float64, fmt byte, prec, bitSize int) string {
-	return string(genericFtoa(make([]byte, 0, max(prec+4, 24)), f, fmt, prec, bitSize))
+	if fmt == 'b' {
+		return string(genericFtoa(make([]byte, 0, max(prec+4, 24)), f, fmt, prec, bitSize))
+	}
+	if bitSize != 32 && bitSize != 64 {
+		panic("strconv: illegal AppendFloat/FormatFloat bitSize")
+	}
+	return native.format_float(f, fmt, prec, bitSize)
 }
 
 // AppendFloat appends the string form of the floating-point number f,
@@ -12,22 +12,20 @@ const fastSmalls = true // enable fast path for small integers
 // for 2 <= base <= 36. The result uses the lower-case letters 'a' to 'z'
 // for digit values >= 10.
 func FormatUint(i uint64, base int) string {
-	if fastSmalls && i < nSmalls && base == 10 {
-		return small(int(i))
+	if base < 2 || base > len(digits) {
+		panic("strconv: illegal AppendInt/FormatInt base")
 	}
-	_, s := formatBits(nil, i, base, false, false)
-	return s
+	return native.format_uint(i, base)
 }
 
 // FormatInt returns the string representation of i in the given base,
 // for 2 <= base <= 36. The result uses the lower-case letters 'a' to 'z'
 // for digit values >= 10.
 func FormatInt(i int64, base int) string {
-	if fastSmalls && 0 <= i && i < nSmalls && base == 10 {
-		return small(int(i))
+	if base < 2 || base > len(digits) {
+		panic("strconv: illegal AppendInt/FormatInt base")
 	}
-	_, s := formatBits(nil, uint64(i), base, i < 0, false)
-	return s
+	return native.format_int(i, base)
 }
 
 // Itoa is equivalent to FormatInt(int64(i), 10).
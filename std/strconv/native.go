@@ -0,0 +1,43 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+package strconv
+
+var native ffiStrconv
+
+func init() {
+	native = ffi(ffiStrconv, "strconv")
+}
+
+// ffiStrconv is implemented in Rust, see engine/src/std/strconv.rs
+//
+// The parsing functions return an error code instead of an error,
+// which codeError turns into a *NumError.
+type ffiStrconv interface {
+	parse_bool(s string) (bool, int)
+	parse_uint(s string, base int, bitSize int) (uint64, int)
+	parse_int(s string, base int, bitSize int) (int64, int)
+	parse_float(s string, bitSize int) (float64, int)
+	format_int(i int64, base int) string
+	format_uint(u uint64, base int) string
+	format_float(f float64, fmt byte, prec, bitSize int) string
+	quote(s string) string
+	unquote(s string) (string, bool)
+}
+
+const (
+	codeOK = iota
+	codeSyntax
+	codeRange
+)
+
+func codeError(fn, str string, code int) error {
+	switch code {
+	case codeSyntax:
+		return syntaxError(fn, str)
+	case codeRange:
+		return rangeError(fn, str)
+	}
+	return nil
+}
@@ -4,17 +4,14 @@
 
 package strings
 
-import (
-	"unicode/utf8"
-	"unsafe"
-)
+import "unsafe"
 
 // A Builder is used to efficiently build a string using Write methods.
 // It minimizes memory copying. The zero value is ready to use.
 // Do not copy a non-zero Builder.
 type Builder struct {
-	addr *Builder // of receiver, to detect copies by value
-	buf  []byte
+	addr *Builder      // of receiver, to detect copies by value
+	buf  unsafe.Pointer // the native buffer, made by the first write
 }
 
 func (b *Builder) copyCheck() {
@@ -23,20 +20,36 @@ func (b *Builder) copyCheck() {
 	} else if b.addr != b {
 		panic("strings: illegal use of non-zero Builder copied by value")
 	}
+	if b.buf == nil {
+		b.buf = native.new_builder()
+	}
 }
 
 // String returns the accumulated string.
 func (b *Builder) String() string {
-	return *(*string)(unsafe.Pointer(&b.buf))
+	if b.buf == nil {
+		return ""
+	}
+	return native.builder_string(b.buf)
 }
 
 // Len returns the number of accumulated bytes; b.Len() == len(b.String()).
-func (b *Builder) Len() int { return len(b.buf) }
+func (b *Builder) Len() int {
+	if b.buf == nil {
+		return 0
+	}
+	return native.builder_len(b.buf)
+}
 
 // Cap returns the capacity of the builder's underlying byte slice. It is the
 // total space allocated for the string being built and includes any bytes
 // already written.
-func (b *Builder) Cap() int { return cap(b.buf) }
+func (b *Builder) Cap() int {
+	if b.buf == nil {
+		return 0
+	}
+	return native.builder_cap(b.buf)
+}
 
 // Reset resets the Builder to be empty.
 func (b *Builder) Reset() {
@@ -44,14 +57,6 @@ func (b *Builder) Reset() {
 	b.buf = nil
 }
 
-// grow copies the buffer to a new, larger buffer so that there are at least n
-// bytes of capacity beyond len(b.buf).
-func (b *Builder) grow(n int) {
-	buf := make([]byte, len(b.buf), 2*cap(b.buf)+n)
-	copy(buf, b.buf)
-	b.buf = buf
-}
-
 // Grow grows b's capacity, if necessary, to guarantee space for
 // another n bytes. After Grow(n), at least n bytes can be written to b
 // without another allocation. If n is negative, Grow panics.
@@ -60,16 +65,14 @@ func (b *Builder) Grow(n int) {
 	if n < 0 {
 		panic("strings.Builder.Grow: negative count")
 	}
-	if cap(b.buf)-len(b.buf) < n {
-		b.grow(n)
-	}
+	native.builder_grow(b.buf, n)
 }
 
 // Write appends the contents of p to b's buffer.
 // Write always returns len(p), nil.
 func (b *Builder) Write(p []byte) (int, error) {
 	b.copyCheck()
-	b.buf = append(b.buf, p...)
+	native.builder_write_bytes(b.buf, p)
 	return len(p), nil
 }
 
@@ -77,7 +80,7 @@ func (b *Builder) Write(p []byte) (int, error) {
 // The returned error is always nil.
 func (b *Builder) WriteByte(c byte) error {
 	b.copyCheck()
-	b.buf = append(b.buf, c)
+	native.builder_write_bytes(b.buf, []byte{c})
 	return nil
 }
 
@@ -85,23 +88,15 @@ func (b *Builder) WriteByte(c byte) error {
 // It returns the length of r and a nil error.
 func (b *Builder) WriteRune(r rune) (int, error) {
 	b.copyCheck()
-	if r < utf8.RuneSelf {
-		b.buf = append(b.buf, byte(r))
-		return 1, nil
-	}
-	l := len(b.buf)
-	if cap(b.buf)-l < utf8.UTFMax {
-		b.grow(utf8.UTFMax)
-	}
-	n := utf8.EncodeRune(b.buf[l:l+utf8.UTFMax], r)
-	b.buf = b.buf[:l+n]
-	return n, nil
+	s := string(r)
+	native.builder_write_string(b.buf, s)
+	return len(s), nil
 }
 
 // WriteString appends the contents of s to b's buffer.
 // It returns the length of s and a nil error.
 func (b *Builder) WriteString(s string) (int, error) {
 	b.copyCheck()
-	b.buf = append(b.buf, s...)
+	native.builder_write_string(b.buf, s)
 	return len(s), nil
 }
@@ -0,0 +1,35 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+package strings
+
+import "unsafe"
+
+var native ffiStrings
+
+func init() {
+	native = ffi(ffiStrings, "strings")
+}
+
+// ffiStrings is implemented in Rust, see engine/src/std/strings.rs
+type ffiStrings interface {
+	index(s, substr string) int
+	has_prefix(s, prefix string) bool
+	has_suffix(s, suffix string) bool
+	split(s, sep string) []string
+	join(a []string, sep string) string
+	replace(s, old, new string, n int) string
+	repeat(s string, count int) string
+	to_upper(s string) string
+	to_lower(s string) string
+	trim_space(s string) string
+
+	new_builder() unsafe.Pointer
+	builder_write_string(b unsafe.Pointer, s string)
+	builder_write_bytes(b unsafe.Pointer, p []byte)
+	builder_string(b unsafe.Pointer) string
+	builder_len(b unsafe.Pointer) int
+	builder_cap(b unsafe.Pointer) int
+	builder_grow(b unsafe.Pointer, n int)
+}
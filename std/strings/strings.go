@@ -305,7 +305,9 @@ func SplitAfterN(s, sep string, n int) []string {
 // and sep are empty, Split returns an empty slice.
 //
 // It is equivalent to SplitN with a count of -1.
-func Split(s, sep string) []string { return genSplit(s, sep, 0, -1) }
+func Split(s, sep string) []string {
+	return native.split(s, sep)
+}
 
 // SplitAfter slices s into all substrings after each instance of sep and
 // returns a slice of those substrings.
@@ -423,35 +425,17 @@ func FieldsFunc(s string, f func(rune) bool) []string {
 // Join concatenates the elements of a to create a single string. The separator string
 // sep is placed between elements in the resulting string.
 func Join(a []string, sep string) string {
-	switch len(a) {
-	case 0:
-		return ""
-	case 1:
-		return a[0]
-	}
-	n := len(sep) * (len(a) - 1)
-	for i := 0; i < len(a); i++ {
-		n += len(a[i])
-	}
-
-	var b Builder
-	b.Grow(n)
-	b.WriteString(a[0])
-	for _, s := range a[1:] {
-		b.WriteString(sep)
-		b.WriteString(s)
-	}
-	return b.String()
+	return native.join(a, sep)
 }
 
 // HasPrefix tests whether the string s begins with prefix.
 func HasPrefix(s, prefix string) bool {
-	return len(s) >= len(prefix) && s[0:len(prefix)] == prefix
+	return native.has_prefix(s, prefix)
 }
 
 // HasSuffix tests whether the string s ends with suffix.
 func HasSuffix(s, suffix string) bool {
-	return len(s) >= len(suffix) && s[len(s)-len(suffix):] == suffix
+	return native.has_suffix(s, suffix)
 }
 
 // Map returns a copy of the string s with all its characters modified
@@ -534,80 +518,17 @@ func Repeat(s string, count int) string {
 	} else if len(s)*count/count != len(s) {
 		panic("strings: Repeat count causes overflow")
 	}
-
-	n := len(s) * count
-	var b Builder
-	b.Grow(n)
-	b.WriteString(s)
-	for b.Len() < n {
-		if b.Len() <= n/2 {
-			b.WriteString(b.String())
-		} else {
-			b.WriteString(b.String()[:n-b.Len()])
-			break
-		}
-	}
-	return b.String()
+	return native.repeat(s, count)
 }
 
 // ToUpper returns a copy of the string s with all Unicode letters mapped to their upper case.
 func ToUpper(s string) string {
-	isASCII, hasLower := true, false
-	for i := 0; i < len(s); i++ {
-		c := s[i]
-		if c >= utf8.RuneSelf {
-			isASCII = false
-			break
-		}
-		hasLower = hasLower || (c >= 'a' && c <= 'z')
-	}
-
-	if isASCII { // optimize for ASCII-only strings.
-		if !hasLower {
-			return s
-		}
-		var b Builder
-		b.Grow(len(s))
-		for i := 0; i < len(s); i++ {
-			c := s[i]
-			if c >= 'a' && c <= 'z' {
-				c -= 'a' - 'A'
-			}
-			b.WriteByte(c)
-		}
-		return b.String()
-	}
-	return Map(unicode.ToUpper, s)
+	return native.to_upper(s)
 }
 
 // ToLower returns a copy of the string s with all Unicode letters mapped to their lower case.
 func ToLower(s string) string {
-	isASCII, hasUpper := true, false
-	for i := 0; i < len(s); i++ {
-		c := s[i]
-		if c >= utf8.RuneSelf {
-			isASCII = false
-			break
-		}
-		hasUpper = hasUpper || (c >= 'A' && c <= 'Z')
-	}
-
-	if isASCII { // optimize for ASCII-only strings.
-		if !hasUpper {
-			return s
-		}
-		var b Builder
-		b.Grow(len(s))
-		for i := 0; i < len(s); i++ {
-			c := s[i]
-			if c >= 'A' && c <= 'Z' {
-				c += 'a' - 'A'
-			}
-			b.WriteByte(c)
-		}
-		return b.String()
-	}
-	return Map(unicode.ToLower, s)
+	return native.to_lower(s)
 }
 
 // ToTitle returns a copy of the string s with all Unicode letters mapped to their title case.
@@ -818,7 +739,7 @@ func TrimRight(s string, cutset string) string {
 // TrimSpace returns a slice of the string s, with all leading
 // and trailing white space removed, as defined by Unicode.
 func TrimSpace(s string) string {
-	return TrimFunc(s, unicode.IsSpace)
+	return native.trim_space(s)
 }
 
 // TrimPrefix returns s without the provided leading prefix string.
@@ -849,34 +770,7 @@ func Replace(s, old, new string, n int) string {
 	if old == new || n == 0 {
 		return s // avoid allocation
 	}
-
-	// Compute number of replacements.
-	if m := Count(s, old); m == 0 {
-		return s // avoid allocation
-	} else if n < 0 || m < n {
-		n = m
-	}
-
-	// Apply replacements to buffer.
-	t := make([]byte, len(s)+n*(len(new)-len(old)))
-	w := 0
-	start := 0
-	for i := 0; i < n; i++ {
-		j := start
-		if len(old) == 0 {
-			if i > 0 {
-				_, wid := utf8.DecodeRuneInString(s[start:])
-				j += wid
-			}
-		} else {
-			j += Index(s[start:], old)
-		}
-		w += copy(t[w:], s[start:j])
-		w += copy(t[w:], new)
-		start = j + len(old)
-	}
-	w += copy(t[w:], s[start:])
-	return string(t[0:w])
+	return native.replace(s, old, new, n)
 }
 
 // ReplaceAll returns a copy of the string s with all
@@ -945,86 +839,7 @@ func EqualFold(s, t string) bool {
 
 // Index returns the index of the first instance of substr in s, or -1 if substr is not present in s.
 func Index(s, substr string) int {
-	n := len(substr)
-	switch {
-	case n == 0:
-		return 0
-	case n == 1:
-		return IndexByte(s, substr[0])
-	case n == len(s):
-		if substr == s {
-			return 0
-		}
-		return -1
-	case n > len(s):
-		return -1
-		// goscript: disabled for now
-		/*
-			case n <= bytealg.MaxLen:
-				// Use brute force when s and substr both are small
-				if len(s) <= bytealg.MaxBruteForce {
-					return bytealg.IndexString(s, substr)
-				}
-				c0 := substr[0]
-				c1 := substr[1]
-				i := 0
-				t := len(s) - n + 1
-				fails := 0
-				for i < t {
-					if s[i] != c0 {
-						// IndexByte is faster than bytealg.IndexString, so use it as long as
-						// we're not getting lots of false positives.
-						o := IndexByte(s[i:t], c0)
-						if o < 0 {
-							return -1
-						}
-						i += o
-					}
-					if s[i+1] == c1 && s[i:i+n] == substr {
-						return i
-					}
-					fails++
-					i++
-					// Switch to bytealg.IndexString when IndexByte produces too many false positives.
-					if fails > bytealg.Cutover(i) {
-						r := bytealg.IndexString(s[i:], substr)
-						if r >= 0 {
-							return r + i
-						}
-						return -1
-					}
-				}
-				return -1
-		*/
-	}
-	c0 := substr[0]
-	c1 := substr[1]
-	i := 0
-	t := len(s) - n + 1
-	fails := 0
-	for i < t {
-		if s[i] != c0 {
-			o := IndexByte(s[i:t], c0)
-			if o < 0 {
-				return -1
-			}
-			i += o
-		}
-		if s[i+1] == c1 && s[i:i+n] == substr {
-			return i
-		}
-		i++
-		fails++
-		if fails >= 4+i>>4 && i < t {
-			// See comment in ../bytes/bytes_generic.go.
-			j := indexRabinKarp(s[i:], substr)
-			if j < 0 {
-				return -1
-			}
-			return i + j
-		}
-	}
-	return -1
+	return native.index(s, substr)
 }
 
 func indexRabinKarp(s, substr string) int {
@@ -6,7 +6,10 @@ package time
 
 // Sleep pauses the current goroutine for at least the duration d.
 // A negative or zero duration causes Sleep to return immediately.
-func Sleep(d Duration)
+// The other goroutines keep running while it sleeps.
+func Sleep(d Duration) {
+	native.async_sleep(int64(d))
+}
 
 // Interface to timers implemented in package runtime.
 // Must be in sync with ../runtime/time.go:/^type timer
@@ -36,13 +39,46 @@ func when(d Duration) int64 {
 	return t
 }
 
-// goscript: todo
-func startTimer(*runtimeTimer) {
+// goscript: a timer is a goroutine sleeping until it fires, i is 1 while
+// it's active, and seq tells it whether it's been stopped or reset since.
+// Like any goroutine, an active timer keeps the program running after main
+// returns, unless the engine has a goroutine grace period.
+func startTimer(r *runtimeTimer) {
+	r.i = 1
+	r.seq++
+	go runTimer(r, r.seq)
+}
+
+func stopTimer(r *runtimeTimer) bool {
+	active := r.i == 1
+	r.i = 0
+	r.seq++
+	return active
 }
 
-// goscript: todo
-func stopTimer(*runtimeTimer) bool {
-	return true
+// The longest a stopped timer's goroutine sleeps before noticing it
+const timerSlice = Millisecond
+
+func runTimer(r *runtimeTimer, seq uintptr) {
+	for r.seq == seq {
+		d := Duration(r.when - runtimeNano())
+		if d > 0 {
+			if d > timerSlice {
+				d = timerSlice
+			}
+			Sleep(d)
+			continue
+		}
+		if r.period > 0 {
+			r.when += r.period
+		} else {
+			r.i = 0
+		}
+		r.f(r.arg, seq)
+		if r.period <= 0 {
+			return
+		}
+	}
 }
 
 // The Timer type represents a single event.
@@ -1067,15 +1067,21 @@ func daysIn(m Month, year int) int {
 	return int(daysBefore[m] - daysBefore[m-1])
 }
 
-// goscript: todo
+type ffiTime interface {
+	now() (sec int64, nsec int32, mono int64)
+	runtime_nano() int64
+	async_sleep(d int64)
+}
+
+var native ffiTime
+
 func now() (sec int64, nsec int32, mono int64) {
-	return 0, 0, 0
+	return native.now()
 }
 
 // runtimeNano returns the current value of the runtime clock in nanoseconds.
-// goscript: todo
 func runtimeNano() int64 {
-	return 0
+	return native.runtime_nano()
 }
 
 // Monotonic times are reported as offsets from startNano.
@@ -1084,7 +1090,13 @@ func runtimeNano() int64 {
 // which appears to have a default resolution of 15ms),
 // we avoid ever reporting a monotonic time of 0.
 // (Callers may want to use 0 as "time not set".)
-var startNano int64 = runtimeNano() - 1
+var startNano int64
+
+// goscript: set here rather than by initializers, which may run before native is set
+func init() {
+	native = ffi(ffiTime, "time")
+	startNano = runtimeNano() - 1
+}
 
 // Now returns the current local time.
 func Now() Time {
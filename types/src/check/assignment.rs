@@ -308,10 +308,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             format!("wrong number of return values (want {}, got {})", ll, count),
                         )
                     } else {
-                        self.error(
-                            rhs[0].pos(self.ast_objs),
-                            format!("cannot initialize {} variables with {} values", ll, count),
-                        )
+                        self.assign_mismatch(rhs, ll, count)
                     }
                     return;
                 }
@@ -352,10 +349,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             | UnpackResult::Nothing(_) => match result.rhs_count() {
                 (count, std::cmp::Ordering::Greater) | (count, std::cmp::Ordering::Less) => {
                     result.use_(self, 0, fctx);
-                    self.error(
-                        rhs[0].pos(self.ast_objs),
-                        format!("cannot assign {} values to {} variables", count, ll),
-                    );
+                    self.assign_mismatch(rhs, ll, count);
                     return;
                 }
                 _ => {
@@ -378,6 +372,34 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         }
     }
 
+    /// Reports that `var_count` variables are assigned `val_count` values, naming
+    /// the function when the values are the results of a call.
+    fn assign_mismatch(&self, rhs: &[Expr], var_count: usize, val_count: usize) {
+        let measure = |n: usize, unit: &str| match n {
+            1 => format!("1 {}", unit),
+            _ => format!("{} {}s", n, unit),
+        };
+        let vars = measure(var_count, "variable");
+        let vals = measure(val_count, "value");
+        let call = match rhs {
+            [e] => match Checker::<S>::unparen(e) {
+                Expr::Call(call) => Some(call),
+                _ => None,
+            },
+            _ => None,
+        };
+        let msg = match call {
+            Some(call) => format!(
+                "assignment mismatch: {} but {} returns {}",
+                vars,
+                self.new_dis(&call.func),
+                vals
+            ),
+            None => format!("assignment mismatch: {} but {}", vars, vals),
+        };
+        self.error(rhs[0].pos(self.ast_objs), msg);
+    }
+
     pub fn short_var_decl(
         &mut self,
         lhs: &Vec<Expr>,
@@ -334,7 +334,26 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             _ => {
                 let pos = self.ast_ident(e.sel).pos;
                 let msg = match &result {
-                    LookupResult::Ambiguous(_) => format!("ambiguous selector {}", sel_name),
+                    LookupResult::Ambiguous(paths) => {
+                        let ed = self.new_dis(x.expr.as_ref().unwrap()).to_string();
+                        let full: Vec<String> = paths
+                            .iter()
+                            .map(|p| {
+                                let names = lookup::embedded_names(x.typ.unwrap(), p, self.tc_objs);
+                                let mut path = vec![ed.clone()];
+                                path.extend(names);
+                                path.push(sel_name.clone());
+                                path.join(".")
+                            })
+                            .collect();
+                        match full.len() {
+                            2 => format!(
+                                "ambiguous selector {}.{} ({} and {})",
+                                ed, sel_name, full[0], full[1]
+                            ),
+                            _ => format!("ambiguous selector {}.{}", ed, sel_name),
+                        }
+                    }
                     LookupResult::NotFound => {
                         let ed = self.new_dis(x.expr.as_ref().unwrap());
                         let td = self.new_td_o(&x.typ);
@@ -70,6 +70,12 @@ pub struct TypeInfo {
     /// only in the Defs map, and identifiers denoting packages in
     /// qualified identifiers are collected in the Uses map.
     pub types: Map<NodeId, TypeAndValue>,
+    /// 'const_spans' maps constant expressions to their source spans, so that
+    /// tools can find the folded value of the expression at a position, the
+    /// values themselves are in 'types'. An expression that is evaluated more
+    /// than once (the implicit repetition of an iota expression in a const
+    /// declaration) keeps the value of its last evaluation.
+    pub const_spans: Map<NodeId, (Pos, Pos)>,
     /// 'defs' maps identifiers to the objects they define (including
     /// package names, dots "." of dot-imports, and blank "_" identifiers).
     /// For identifiers that do not denote objects (e.g., the package name
@@ -132,6 +138,7 @@ impl TypeInfo {
     pub fn new() -> TypeInfo {
         TypeInfo {
             types: Map::new(),
+            const_spans: Map::new(),
             defs: Map::new(),
             uses: Map::new(),
             implicits: Map::new(),
@@ -182,8 +189,6 @@ pub type RcIfaceInfo = Rc<IfaceInfo>;
 pub struct FilesContext<'a, S: SourceRead> {
     // package files
     pub files: &'a Vec<ast::File>,
-    // positions of unused dot-imported packages for each file scope
-    pub unused_dot_imports: Map<ScopeKey, Map<PackageKey, Pos>>,
     // maps package scope type names(LangObj::TypeName) to associated
     // non-blank, non-interface methods(LangObj::Func)
     pub methods: Map<ObjKey, Vec<ObjKey>>,
@@ -249,7 +254,6 @@ impl<S: SourceRead> FilesContext<'_, S> {
     pub fn new(files: &Vec<ast::File>) -> FilesContext<'_, S> {
         FilesContext {
             files: files,
-            unused_dot_imports: Map::new(),
             methods: Map::new(),
             ifaces: Map::new(),
             untyped: Map::new(),
@@ -269,16 +273,6 @@ impl<S: SourceRead> FilesContext<'_, S> {
         }
     }
 
-    pub fn add_unused_dot_import(&mut self, scope: &ScopeKey, pkg: &PackageKey, pos: Pos) {
-        if !self.unused_dot_imports.contains_key(scope) {
-            self.unused_dot_imports.insert(*scope, Map::new());
-        }
-        self.unused_dot_imports
-            .get_mut(scope)
-            .unwrap()
-            .insert(*pkg, pos);
-    }
-
     pub fn remember_untyped(&mut self, e: &Expr, ex_info: ExprInfo) {
         self.untyped.insert(e.id(), ex_info);
     }
@@ -334,6 +328,10 @@ impl TypeInfo {
         self.types.insert(id, TypeAndValue::new(mode, typ));
     }
 
+    pub fn record_const_span(&mut self, e: &Expr, objs: &AstObjects) {
+        self.const_spans.insert(e.id(), (e.pos(objs), e.end(objs)));
+    }
+
     pub fn record_builtin_type(&mut self, mode: &OperandMode, e: &Expr, sig: TypeKey) {
         let mut expr = e;
         // expr must be a (possibly parenthesized) identifier denoting a built-in
@@ -433,7 +431,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         self.package_objects(fctx);
         fctx.process_delayed(0, &mut self);
         self.init_order();
-        self.unused_imports(fctx);
+        self.unused_imports();
         self.record_untyped(fctx);
 
         std::mem::swap(&mut self.result.ast_files, &mut files);
@@ -477,31 +475,41 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         )
     }
 
-    /// check files' package name
+    /// check files' package name, all the files of a package must agree on it
     fn check_files_pkg_name(&mut self, files: &Vec<ast::File>) -> Result<(), ()> {
-        let mut pkg_name: Option<String> = None;
+        let mut first: Option<&ast::File> = None;
         for f in files.iter() {
             let ident = &self.ast_objs.idents[f.name];
-            if pkg_name.is_none() {
-                if ident.name == "_" {
-                    self.error(ident.pos, "invalid package name _".to_owned());
-                    return Err(());
-                } else {
-                    pkg_name = Some(ident.name.clone());
+            match first {
+                None => {
+                    if ident.name == "_" {
+                        self.error(ident.pos, "invalid package name _".to_owned());
+                        return Err(());
+                    }
+                    first = Some(f);
+                }
+                Some(first) => {
+                    let first_name = &self.ast_objs.idents[first.name].name;
+                    if &ident.name != first_name {
+                        let file_name = |pos| self.fset.file(pos).map_or("", |x| x.name());
+                        self.error(
+                            f.package,
+                            format!(
+                                "found packages {} ({}) and {} ({}) in {}",
+                                first_name,
+                                file_name(first.package),
+                                ident.name,
+                                file_name(f.package),
+                                self.tc_objs.pkgs[self.pkg].path()
+                            ),
+                        );
+                        return Err(());
+                    }
                 }
-            } else if &ident.name != pkg_name.as_ref().unwrap() {
-                self.error(
-                    f.package,
-                    format!(
-                        "package {}; expected {}",
-                        ident.name,
-                        pkg_name.as_ref().unwrap()
-                    ),
-                );
-                return Err(());
             }
         }
-        self.tc_objs.pkgs[self.pkg].set_name(pkg_name.unwrap());
+        let name = self.ast_objs.idents[first.unwrap().name].name.clone();
+        self.tc_objs.pkgs[self.pkg].set_name(name);
         Ok(())
     }
 
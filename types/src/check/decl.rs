@@ -275,20 +275,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             return false; // cycle is permitted
         }
 
-        // report error
-        let pos = lobj.pos();
-        self.error(
-            pos,
-            format!("illegal cycle in declaration of {}", lobj.name()),
-        );
-        for o in cycle {
-            if o == self.tc_objs.universe().indir() {
-                continue;
-            }
-            self.error(pos, format!("\t{} refers to", self.lobj(*o).name()));
-        }
-        self.error(pos, format!("\t{} refers to", lobj.name()));
-
+        self.report_decl_cycle(cycle);
         true
     }
 
@@ -600,11 +600,32 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             return;
         }
 
-        // spec: "The right operand in a shift expression must have unsigned
-        // integer type or be an untyped constant representable by a value of
-        // type uint."
+        // check that constants are representable by uint, but don't convert them
+        if let OperandMode::Constant(yv) = &y.mode {
+            // a good error message for negative shift counts
+            let yval = yv.to_int();
+            if yval.is_int() && yval.sign() < 0 {
+                let yd = self.new_dis(y);
+                self.invalid_op(yd.pos(), &format!("negative shift count {}", yd));
+                x.mode = OperandMode::Invalid;
+                return;
+            }
+            // before the check below, which is true for untyped integers as well
+            if typ::is_untyped(y.typ.unwrap(), self.tc_objs) {
+                let mut yc = y.clone();
+                self.representable(&mut yc, self.basic_type(BasicType::Uint));
+                if yc.invalid() {
+                    x.mode = OperandMode::Invalid;
+                    return;
+                }
+            }
+        }
+
+        // spec: "The right operand in a shift expression must have integer type
+        // or be an untyped constant representable by a value of type uint."
+        let o = &self.tc_objs;
         let ytval = self.otype(y.typ.unwrap());
-        if ytval.is_unsigned(o) {
+        if ytval.is_integer(o) {
             //ok
         } else if ytval.is_untyped(o) {
             self.convert_untyped(y, self.basic_type(BasicType::Uint), fctx);
@@ -614,10 +635,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
         } else {
             let yd = self.new_dis(y);
-            self.error(
-                yd.pos(),
-                format!("shift count {} must be unsigned integer", yd),
-            );
+            self.error(yd.pos(), format!("shift count {} must be integer", yd));
             x.mode = OperandMode::Invalid;
             return;
         }
@@ -628,10 +646,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 let yval = yv.to_int();
                 if !yval.is_int() {
                     let yd = self.new_dis(y);
-                    self.invalid_op(
-                        yd.pos(),
-                        &format!("shift count {} must be unsigned integer", yd),
-                    );
+                    self.invalid_op(yd.pos(), &format!("shift count {} must be integer", yd));
                     x.mode = OperandMode::Invalid;
                     return;
                 }
@@ -693,17 +708,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
         }
 
-        // constant rhs must be >= 0
-        if let OperandMode::Constant(v) = &y.mode {
-            if v.sign() < 0 {
-                let yd = self.new_dis(y);
-                self.invalid_op(
-                    yd.pos(),
-                    &format!("shift count {} must not be negative", yd),
-                );
-            }
-        }
-
         if !typ::is_integer(x.typ.unwrap(), self.tc_objs) {
             let xd = self.new_dis(x);
             self.invalid_op(xd.pos(), &format!("shifted operand {} must be integer", xd));
@@ -958,6 +962,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
 
         let kind = self.raw_internal(x, e, hint, fctx);
 
+        if let OperandMode::Constant(_) = &x.mode {
+            self.result.record_const_span(e, self.ast_objs);
+        }
+
         let ty = match &x.mode {
             OperandMode::Invalid => self.invalid_type(),
             OperandMode::NoValue => *self.tc_objs.universe().no_value_tuple(),
@@ -1586,15 +1594,10 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             self.error(
                 pos.unwrap_or_else(|| dx.pos()),
                 format!(
-                    "{} cannot have dynamic type {} ({} {})",
+                    "{} cannot have dynamic type {} {}",
                     dx,
                     self.new_dis(&t),
-                    if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    },
-                    self.lobj(method).name()
+                    lookup::missing_method_reason(t, method, wrong_type, self.tc_objs)
                 ),
             );
         }
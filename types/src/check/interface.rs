@@ -447,7 +447,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             let mi_ref = set.get(id.as_ref()).unwrap();
             let md = self.new_dis(mi_ref);
             self.error(pos, format!("{} redeclared", md));
-            let mpos = mi_ref.pos(self.tc_objs, self.ast_objs);
+            let mpos = alt.pos(self.tc_objs, self.ast_objs);
             if mpos > 0 {
                 // We use "other" rather than "previous" here because
                 // the first declaration seen may not be textually
@@ -155,7 +155,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             if !lobj.entity_type().label_used() {
                 self.soft_error(
                     lobj.pos(),
-                    format!("label {} declared but not used", lobj.name()),
+                    format!("label {} defined and not used", lobj.name()),
                 );
             }
         }
@@ -273,9 +273,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                                         for elem in elems.into_iter() {
                                             self.declare(file_scope, None, elem, 0);
                                         }
-                                        // add position to set of dot-import positions for this file
-                                        // (this is only needed for "imported but not used" errors)
-                                        fctx.add_unused_dot_import(&file_scope, &imp, spec_pos);
                                     } else {
                                         // declare imported package object in file scope
                                         self.declare(file_scope, None, pkg_name_obj, 0);
@@ -540,9 +537,9 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         fctx.methods.clear();
     }
 
-    /// unused_imports checks for unused imports.
-    pub fn unused_imports(&mut self, fctx: &mut FilesContext<S>) {
-        // check use of regular imported packages
+    /// unused_imports checks for unused imports. Blank imports and dot-imports
+    /// are never reported, the package is imported for its init or its names.
+    pub fn unused_imports(&mut self) {
         let pkg_scope = self.scope(*self.package(self.pkg).scope());
         for s in pkg_scope.children().iter() {
             for (_, okey) in self.scope(*s).elems() {
@@ -554,12 +551,16 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                             if obj_val.name() == base {
                                 self.soft_error(
                                     obj_val.pos(),
-                                    format!("{} imported but not used", path),
+                                    format!("imported and not used: \"{}\"", path),
                                 );
                             } else {
                                 self.soft_error(
                                     obj_val.pos(),
-                                    format!("{} imported but not used as {}", path, base),
+                                    format!(
+                                        "imported and not used: \"{}\" as {}",
+                                        path,
+                                        obj_val.name()
+                                    ),
                                 );
                             }
                         }
@@ -568,15 +569,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                 }
             }
         }
-        // check use of dot-imported packages
-        for (_, imports) in fctx.unused_dot_imports.iter() {
-            for (pkey, pos) in imports.iter() {
-                self.soft_error(
-                    *pos,
-                    format!("{} imported but not used", self.package(*pkey).path()),
-                );
-            }
-        }
     }
 
     /// arity_match checks that the lhs and rhs of a const or var decl
@@ -690,7 +682,17 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             .chars()
             .find(|&x| !x.is_ascii_graphic() || x.is_whitespace() || illegal_chars.contains(&x))
         {
-            return Err(format!("invalid character: {}", c));
+            return Err(format!("invalid character: {}", c.escape_default()));
+        }
+        // a local import may start with "./" or "../", after that
+        // the path has to name a package rather than a directory
+        let is_dots = |x: &&str| *x == "." || *x == "..";
+        let mut rest = result.split('/').skip_while(is_dots).peekable();
+        if rest.peek().is_none() {
+            return Err(format!("{} segment", result.rsplit('/').next().unwrap()));
+        }
+        if let Some(seg) = rest.find(is_dots) {
+            return Err(format!("{} segment", seg));
         }
         Ok(result)
     }
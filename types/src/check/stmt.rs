@@ -67,7 +67,7 @@ impl GoVal {
                     _ => GoVal::Invalid,
                 },
             },
-            Value::Float(_) => match v.num_as_f64() {
+            Value::Rat(_) | Value::Float(_) => match v.num_as_f64() {
                 (f, true) => GoVal::Float64(f),
                 _ => GoVal::Invalid,
             },
@@ -179,7 +179,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         unused.sort_by(|a, b| a.pos().cmp(&b.pos()));
 
         for lo in unused.iter() {
-            self.soft_error(lo.pos(), format!("{} declared but not used", lo.name()));
+            self.soft_error(lo.pos(), format!("declared but not used: {}", lo.name()));
         }
         for skey in sval.children().iter() {
             // Don't go inside function literal scopes a second time;
@@ -821,7 +821,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
                     if !used {
                         let ident = self.ast_ident(lhs.unwrap());
                         let (pos, name) = (ident.pos, &ident.name);
-                        self.soft_error(pos, format!("{} declared but not used", name));
+                        self.soft_error(pos, format!("declared but not used: {}", name));
                     }
                 }
 
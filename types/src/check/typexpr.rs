@@ -43,7 +43,7 @@ impl<'a, S: SourceRead> Checker<'a, S> {
         // Note that we cannot use check.lookup here because the returned scope
         // may be different from obj.parent(). See also Scope.lookup_parent doc.
         let name = &self.ast_ident(ikey).name;
-        if let Some((skey, okey)) =
+        if let Some((_, okey)) =
             Scope::lookup_parent(&self.octx.scope.unwrap(), name, self.octx.pos, self.tc_objs)
         {
             self.result.record_use(ikey, okey);
@@ -57,7 +57,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             // informative "not a type/value" error that this function's caller
             // will issue
             let lobj = self.lobj(okey);
-            let pkg = lobj.pkg();
             let mut otype = lobj.typ();
             if otype.is_none() || (lobj.entity_type().is_type_name() && want_type) {
                 self.obj_decl(okey, def, fctx);
@@ -66,17 +65,6 @@ impl<'a, S: SourceRead> Checker<'a, S> {
             }
             debug_assert!(otype.is_some());
 
-            // The object may be dot-imported: If so, remove its package from
-            // the map of unused dot imports for the respective file scope.
-            // (This code is only needed for dot-imports. Without them,
-            // we only have to mark variables, see Var case below).
-            if pkg.is_some() && pkg != Some(self.pkg) {
-                fctx.unused_dot_imports
-                    .get_mut(&skey)
-                    .unwrap()
-                    .remove(&pkg.unwrap());
-            }
-
             let lobj = self.lobj(okey);
             let invalid_type = self.invalid_type();
             match lobj.entity_type() {
@@ -222,25 +222,47 @@ impl<'a, S: SourceRead> Checker<'a, S> {
     // has_cycle reports whether obj appears in path or not.
     // If it does, and report is set, it also reports a cycle error.
     pub fn has_cycle(&self, okey: ObjKey, path: &[ObjKey], report: bool) -> bool {
-        if let Some((i, _)) = path.iter().enumerate().find(|(_, &x)| x == okey) {
+        if let Some(i) = path.iter().position(|&x| x == okey) {
             if report {
-                let obj_val = self.lobj(okey);
-                self.error(
-                    obj_val.pos(),
-                    format!("illegal cycle in declaration of {}", obj_val.name()),
-                );
-                // print cycle
-                for o in path[i..].iter() {
-                    let oval = self.lobj(*o);
-                    self.error(oval.pos(), format!("\t{} refers to", oval.name()));
-                }
-                self.error(obj_val.pos(), format!("\t{}", obj_val.name()));
+                self.report_decl_cycle(&path[i..]);
             }
             return true;
         }
         false
     }
 
+    /// Reports the cycle of declarations in which each object refers to the next
+    /// one and the last to the first, the indirections of the path left out.
+    /// The message has the whole cycle, and a note at the declaration of every
+    /// object says what it refers to. A cycle of types only is a recursive type
+    /// of infinite size.
+    pub fn report_decl_cycle(&self, cycle: &[ObjKey]) {
+        let indir = *self.tc_objs.universe().indir();
+        let objs: Vec<ObjKey> = cycle.iter().filter(|&&o| o != indir).copied().collect();
+        let name = |o: &ObjKey| self.lobj(*o).name().as_str();
+        let path: Vec<&str> = objs.iter().chain(objs.first()).map(name).collect();
+        let first = self.lobj(objs[0]);
+        let types_only = objs
+            .iter()
+            .all(|o| self.lobj(*o).entity_type() == &obj::EntityType::TypeName);
+        let what = match types_only {
+            true => "invalid recursive type",
+            false => "illegal cycle in declaration of",
+        };
+        self.error(
+            first.pos(),
+            format!("{} {} ({})", what, first.name(), path.join(" → ")),
+        );
+        if objs.len() == 1 {
+            self.error(first.pos(), format!("\t{} refers to itself", first.name()));
+            return;
+        }
+        for (i, o) in objs.iter().enumerate() {
+            let note = format!("\t{} refers to {}", path[i], path[i + 1]);
+            self.error(self.lobj(*o).pos(), note);
+        }
+    }
+
     pub fn comma_ok_type(
         tc_objs: &mut TCObjects,
         pos: usize,
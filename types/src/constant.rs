@@ -71,7 +71,10 @@ impl fmt::Display for Value {
             }
             Value::Rat(r) => {
                 //f.write_str("rat: ")?;
-                r.fmt(f)
+                match rat_to_f64(r) {
+                    Some(x) if !r.is_integer() => x.fmt(f),
+                    _ => r.fmt(f),
+                }
             }
             Value::Float(s) => {
                 //f.write_str("float: ")?;
@@ -112,8 +115,14 @@ impl Value {
             Token::FLOAT(flit) => float_from_literal(flit.as_str()),
             Token::IMAG(imlit) => {
                 let s = imlit.as_str();
-                let v = float_from_literal(&s[..(s.len() - 1)]);
-                if let Value::Float(_) = &v {
+                let s = &s[..(s.len() - 1)];
+                // 0b1i is an integer with a prefix, but 09i is decimal
+                let v = match s.get(..2).map(|p| p.to_ascii_lowercase()).as_deref() {
+                    Some("0b") | Some("0o") => int_from_literal(s).to_float(),
+                    Some("0x") if !s.contains(['p', 'P']) => int_from_literal(s).to_float(),
+                    _ => float_from_literal(s),
+                };
+                if let Value::Float(_) | Value::Rat(_) = &v {
                     Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
                 } else {
                     Value::Unknown
@@ -148,7 +157,12 @@ impl Value {
             |val: &Value, btype: BasicType, rounded: Option<&mut Value>| -> bool {
                 match val.to_float() {
                     Value::Float(f) => match btype {
-                        BasicType::Float64 => true,
+                        BasicType::Float64 => {
+                            if let Some(r) = rounded {
+                                *r = Value::Float(f);
+                            }
+                            !f.is_infinite()
+                        }
                         BasicType::Float32 => {
                             let f32_ = *f as f32;
                             let ok = !f32_.is_infinite();
@@ -790,28 +804,84 @@ fn short_quote_str(s: &str, max: usize) -> String {
 }
 
 fn int_from_literal(lit: &str) -> Value {
-    let result = if lit.starts_with("0x") {
-        BigInt::from_str_radix(&lit[2..], 16)
-    } else if lit.starts_with("0o") {
-        BigInt::from_str_radix(&lit[2..], 8)
-    } else if lit.starts_with("0b") {
-        BigInt::from_str_radix(&lit[2..], 2)
-    } else {
-        BigInt::from_str_radix(lit, 10)
+    let lit = lit.replace('_', "");
+    let (digits, radix) = match lit.get(..2).map(|p| p.to_ascii_lowercase()).as_deref() {
+        Some("0x") => (&lit[2..], 16),
+        Some("0o") => (&lit[2..], 8),
+        Some("0b") => (&lit[2..], 2),
+        // the legacy octal literals, 0777
+        _ if lit.len() > 1 && lit.starts_with('0') => (&lit[1..], 8),
+        _ => (&lit[..], 10),
     };
-    match result {
+    match BigInt::from_str_radix(digits, radix) {
         Ok(i) => Value::Int(i),
         Err(_) => Value::Unknown,
     }
 }
 
+/// Like go/constant, a float literal is kept as an exact rational unless its
+/// exponent is too large for that to be practical, so that constant arithmetic
+/// such as `(1<<62 + 1) * 1.0` doesn't lose precision.
 fn float_from_literal(lit: &str) -> Value {
+    let lit = lit.replace('_', "");
+    if lit.starts_with("0x") || lit.starts_with("0X") {
+        return hex_float_from_literal(&lit[2..]);
+    }
+    if let Some(r) = rat_from_decimal_literal(&lit) {
+        return Value::Rat(r);
+    }
     match lit.parse::<f64>() {
         Ok(f) => Value::with_f64(f),
         Err(_) => Value::Unknown,
     }
 }
 
+// go/constant keeps rationals with binary exponents below 4096, about 10^1233
+const MAX_RAT_DECIMAL_EXP: i64 = 1233;
+
+fn rat_from_decimal_literal(lit: &str) -> Option<BigRational> {
+    let (mantissa, exp) = match lit.find(['e', 'E']) {
+        Some(i) => (&lit[..i], lit[i + 1..].parse::<i64>().ok()?),
+        None => (lit, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let exp = exp.checked_sub(frac.len() as i64)?;
+    if exp.abs() > MAX_RAT_DECIMAL_EXP {
+        return None;
+    }
+    let m = BigInt::from_str_radix(&format!("{}{}", int, frac), 10).ok()?;
+    let ten = BigInt::from(10);
+    Some(if exp >= 0 {
+        BigRational::from_integer(m * ten.pow(exp as u32))
+    } else {
+        BigRational::new(m, ten.pow((-exp) as u32))
+    })
+}
+
+// The mantissa in hex digits and the binary exponent after the 'p', 1.8p3
+fn hex_float_from_literal(lit: &str) -> Value {
+    let (mantissa, exp) = match lit.find(['p', 'P']) {
+        Some(i) => (&lit[..i], lit[i + 1..].parse::<i32>()),
+        None => (lit, Ok(0)),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let m = BigInt::from_str_radix(&format!("{}{}", int, frac), 16);
+    match (m, exp) {
+        (Ok(m), Ok(exp)) => {
+            // each hex digit after the point is 4 bits
+            let exp = exp - 4 * frac.len() as i32;
+            let two = BigInt::from(2);
+            let r = if exp >= 0 {
+                BigRational::from_integer(m * two.pow(exp as u32))
+            } else {
+                BigRational::new(m, two.pow((-exp) as u32))
+            };
+            Value::Rat(r)
+        }
+        _ => Value::Unknown,
+    }
+}
+
 fn shorten_with_ellipsis(s: String, max: usize) -> String {
     if s.len() <= max {
         s
@@ -823,10 +893,32 @@ fn shorten_with_ellipsis(s: String, max: usize) -> String {
     }
 }
 
+/// The nearest f64 to r, or None if it's out of the range of f64
 fn rat_to_f64(r: &BigRational) -> Option<f64> {
-    match (r.numer().to_f64(), r.denom().to_f64()) {
-        (Some(n), Some(d)) => Some(n / d),
-        _ => None,
+    let (n, d) = (r.numer().magnitude(), r.denom().magnitude());
+    if n.bits() == 0 {
+        return Some(0.0);
+    }
+    // Scales n/d so the integer part has 63 or 64 bits, what's left of the
+    // fraction only matters as a sticky bit for the rounding of the conversion.
+    let shift = 63 + d.bits() as i64 - n.bits() as i64;
+    let (n, d) = match shift >= 0 {
+        true => (n << shift as usize, d.clone()),
+        false => (n.clone(), d << (-shift) as usize),
+    };
+    let q = (&n / &d).to_u64()?;
+    let sticky = (&n % &d).bits() != 0;
+    let mut f = (q | sticky as u64) as f64;
+    // in steps, the powers of two themselves must not overflow
+    let mut exp = -shift;
+    while exp != 0 {
+        let step = exp.clamp(-1000, 1000);
+        f *= 2f64.powi(step as i32);
+        exp -= step;
+    }
+    match f.is_infinite() {
+        true => None,
+        false => Some(if r.is_negative() { -f } else { f }),
     }
 }
 
@@ -104,29 +104,58 @@ impl<'a, S: SourceRead> Importer<'a, S> {
 
         match self.reader.canonicalize_import(key) {
             Ok((path, import_path)) => match self.pkgs.get(&import_path) {
+                Some(&pkg) if !self.all_results.contains_key(&pkg) => {
+                    self.error(format!("import cycle not allowed: {}", self.cycle(pkg)))
+                }
                 Some(key) => Ok(*key),
                 None => {
                     let pkg = self.tc_objs.new_package(import_path.clone());
-                    self.pkgs.insert(import_path, pkg);
-                    let files = self.parse_path(&path)?;
-                    Checker::new(
-                        self.tc_objs,
-                        self.ast_objs,
-                        self.fset,
-                        self.errors,
-                        self.pkgs,
-                        self.all_results,
-                        pkg,
-                        self.trace_config,
-                        self.reader,
-                    )
-                    .check(files)
+                    self.pkgs.insert(import_path.clone(), pkg);
+                    let result = self.parse_path(&path).and_then(|files| {
+                        Checker::new(
+                            self.tc_objs,
+                            self.ast_objs,
+                            self.fset,
+                            self.errors,
+                            self.pkgs,
+                            self.all_results,
+                            pkg,
+                            self.trace_config,
+                            self.reader,
+                        )
+                        .check(files)
+                    });
+                    if result.is_err() {
+                        // so that it's not taken for a package being checked
+                        self.pkgs.remove(&import_path);
+                    }
+                    result
                 }
             },
             Err(e) => self.error(format!("canonicalize import error: {}", e)),
         }
     }
 
+    /// The chain of imports from pkg back to itself. The packages being checked are
+    /// the ones without a result, they are created in the order they are imported,
+    /// so sorted by key they are the chain of imports that leads to this one.
+    fn cycle(&self, pkg: PackageKey) -> String {
+        let mut checking: Vec<PackageKey> = self
+            .pkgs
+            .values()
+            .filter(|k| !self.all_results.contains_key(k))
+            .copied()
+            .collect();
+        checking.sort();
+        let start = checking.iter().position(|&k| k == pkg).unwrap_or(0);
+        checking[start..]
+            .iter()
+            .chain(std::iter::once(&pkg))
+            .map(|&k| self.tc_objs.pkgs[k].path().as_str())
+            .collect::<Vec<&str>>()
+            .join(" -> ")
+    }
+
     fn parse_path(&mut self, path: &Path) -> Result<Vec<ast::File>, ()> {
         match read_content(path, self.reader) {
             Ok(contents) => {
@@ -24,10 +24,13 @@ use std::fmt::Write;
 
 macro_rules! lookup_on_found {
     ($indices:ident, $i:ident, $target:expr, $et:ident, $indirect:ident, $found:expr) => {
-        $indices = concat_vec($et.indices.clone(), $i);
+        let found = concat_vec($et.indices.clone(), $i).unwrap();
         if $target.is_some() || $et.multiples {
-            return LookupResult::Ambiguous($indices.unwrap());
+            let mut paths: Vec<Vec<usize>> = $indices.take().into_iter().collect();
+            paths.push(found);
+            return LookupResult::Ambiguous(paths);
         }
+        $indices = Some(found);
         *$target = Some($found);
         $indirect = $et.indirect;
     };
@@ -38,9 +41,10 @@ macro_rules! lookup_on_found {
 pub enum LookupResult {
     /// valid entry
     Entry(ObjKey, Vec<usize>, bool),
-    /// the index sequence points to an ambiguous entry
+    /// the index sequences point to ambiguous entries
     /// (the same name appeared more than once at the same embedding level).
-    Ambiguous(Vec<usize>),
+    /// There's only one if the entries are reached through the same embedded type.
+    Ambiguous(Vec<Vec<usize>>),
     /// a method with a pointer receiver type was found
     /// but there was no pointer on the path from the actual receiver type to
     /// the method's formal receiver base type, nor was the receiver addressable.
@@ -278,6 +282,26 @@ pub fn try_deref(t: TypeKey, objs: &TCObjects) -> (TypeKey, bool) {
     }
 }
 
+/// embedded_names returns the names of the embedded fields on the path of an entry
+/// found by lookup_field_or_method, the last index being the entry itself.
+pub fn embedded_names(t: TypeKey, indices: &[usize], objs: &TCObjects) -> Vec<String> {
+    let mut t = t;
+    let mut names = vec![];
+    for &i in indices[..indices.len().saturating_sub(1)].iter() {
+        let (base, _) = try_deref(typ::underlying_type(t, objs), objs);
+        let base = typ::underlying_type(base, objs);
+        match objs.types[base].try_as_struct() {
+            Some(detail) => {
+                let field = &objs.lobjs[detail.fields()[i]];
+                names.push(field.name().clone());
+                t = field.typ().unwrap();
+            }
+            None => break,
+        }
+    }
+    names
+}
+
 /// deref_struct_ptr dereferences typ if it is a (named or unnamed) pointer to a
 /// (named or unnamed) struct and returns its base. Otherwise it returns typ.   
 pub fn deref_struct_ptr(t: TypeKey, objs: &TCObjects) -> TypeKey {
@@ -403,6 +427,40 @@ pub fn missing_method<S: SourceRead>(
     None
 }
 
+/// missing_method_reason explains in the shape gc uses why 't' doesn't
+/// implement 'intf', given the method reported by missing_method, e.g.
+/// "(missing method Draw)", "(method Draw has pointer receiver)" or
+/// "(wrong type for method Draw: have Draw(int), want Draw(context.Context) error)".
+pub fn missing_method_reason(t: TypeKey, m: ObjKey, wrong_type: bool, objs: &TCObjects) -> String {
+    let mval = &objs.lobjs[m];
+    match lookup_field_or_method(t, false, mval.pkg(), mval.name(), objs) {
+        LookupResult::Entry(okey, _, _) if wrong_type => format!(
+            "(wrong type for method {}: have {}, want {})",
+            mval.name(),
+            MethodStr(okey, objs),
+            MethodStr(m, objs)
+        ),
+        LookupResult::BadMethodReceiver => {
+            format!("(method {} has pointer receiver)", mval.name())
+        }
+        _ => format!("(missing method {})", mval.name()),
+    }
+}
+
+/// Formats a method as its name followed by its signature
+struct MethodStr<'a>(ObjKey, &'a TCObjects);
+
+impl<'a> fmt::Display for MethodStr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let obj = &self.1.lobjs[self.0];
+        f.write_str(obj.name())?;
+        match obj.typ() {
+            Some(t) => typ::fmt_signature(t, f, self.1),
+            None => Ok(()),
+        }
+    }
+}
+
 fn lookup_field_or_method_impl(
     tkey: TypeKey,
     addressable: bool,
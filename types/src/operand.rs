@@ -14,7 +14,8 @@ use crate::SourceRead;
 
 use super::check::{Checker, FilesContext};
 use super::constant;
-use super::lookup::missing_method;
+use super::display::type_str;
+use super::lookup::{missing_method, missing_method_reason};
 use super::objects::{TCObjects, TypeKey};
 use super::typ;
 use super::typ::{fmt_type, BasicType, Type};
@@ -195,12 +196,13 @@ impl Operand {
             if let Some((m, wrong_type)) = missing_method(k_right, ut_key_left, true, checker, fctx)
             {
                 if let Some(re) = reason {
-                    let msg = if wrong_type {
-                        "wrong type for method"
-                    } else {
-                        "missing method"
-                    };
-                    *re = format!("{} {}", msg, checker.tc_objs.lobjs[m].name());
+                    let objs = &checker.tc_objs;
+                    *re = format!(
+                        "{} does not implement {} {}",
+                        type_str(&k_right, objs),
+                        type_str(&k_left, objs),
+                        missing_method_reason(k_right, m, wrong_type, objs)
+                    );
                 }
                 return false;
             }
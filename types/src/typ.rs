@@ -1309,8 +1309,11 @@ fn fmt_signature_impl(
 ) -> fmt::Result {
     let sig = &objs.types[t].try_as_signature().unwrap();
     fmt_tuple(sig.params(), sig.variadic(), f, visited, &objs)?;
-    f.write_char(' ')?;
     let results = &objs.types[sig.results()].try_as_tuple().unwrap();
+    if results.vars().is_empty() {
+        return Ok(());
+    }
+    f.write_char(' ')?;
     if results.vars().len() == 1 {
         let obj = &objs.lobjs[results.vars()[0]];
         if obj.name().is_empty() {
@@ -168,7 +168,8 @@ fn parse_error(s: &str, line: usize) -> io::Result<Vec<ErrInfo>> {
     Ok(texts)
 }
 
-fn test_file(path: &str, trace: bool) {
+/// Checks the errors of the file against its ERROR comments, and returns them
+fn test_file(path: &str, trace: bool) -> fe::ErrorList {
     dbg!(path);
     let pkgs = &mut Map::new();
     let config = types::TraceConfig {
@@ -178,7 +179,8 @@ fn test_file(path: &str, trace: bool) {
     let reader = FsReader::new(None, None);
     let fs = &mut fe::FileSet::new();
     let asto = &mut fe::AstObjects::new();
-    let el = &mut fe::ErrorList::new();
+    let mut errors = fe::ErrorList::new();
+    let el = &mut errors;
     let tco = &mut types::TCObjects::new();
     let results = &mut Map::new();
 
@@ -227,8 +229,8 @@ fn test_file(path: &str, trace: bool) {
             }
         }
     }
+    errors
 }
-
 fn parse_comment_errors<P>(path: P) -> io::Result<Map<usize, Vec<ErrInfo>>>
 where
     P: AsRef<Path>,
@@ -278,6 +280,7 @@ fn test_auto() {
     test_file("./tests/data/cycles3.gos", trace);
     test_file("./tests/data/cycles4.gos", trace);
     test_file("./tests/data/cycles5.gos", trace);
+    test_file("./tests/data/cycles6.gos", trace);
     test_file("./tests/data/decls0.src", trace);
     test_file("./tests/data/decls1.src", trace);
     test_file("./tests/data/decls2", trace);
@@ -289,12 +292,14 @@ fn test_auto() {
     test_file("./tests/data/expr2.src", trace);
     test_file("./tests/data/expr3.src", trace);
     test_file("./tests/data/gotos.src", trace);
+    test_file("./tests/data/implements.src", trace);
     test_file("./tests/data/importdecl0", trace);
     test_file("./tests/data/importdecl1", trace);
 
     test_file("./tests/data/init0.src", trace);
     test_file("./tests/data/init1.src", trace);
     test_file("./tests/data/init2.src", trace);
+    test_file("./tests/data/init3.src", trace);
 
     test_file("./tests/data/issues.src", trace);
     test_file("./tests/data/labels.src", trace);
@@ -309,3 +314,34 @@ fn test_auto() {
 fn test_temp() {
     test_file("./tests/data/temp.gos", true);
 }
+
+#[test]
+fn test_recursive_types() {
+    let el = test_file("./tests/data/cycles6.gos", false);
+    let cycles: Vec<String> = el
+        .iter()
+        .filter(|e| e.msg.starts_with("invalid recursive type"))
+        .map(|e| {
+            let notes = e
+                .notes
+                .iter()
+                .map(|n| format!("\n\t{}:{}: {}", n.pos.line, n.pos.column, n.label));
+            format!(
+                "{}:{}: {}{}",
+                e.pos.line,
+                e.pos.column,
+                e.msg,
+                notes.collect::<String>()
+            )
+        })
+        .collect();
+    assert_eq!(
+        cycles,
+        [
+            "9:2: invalid recursive type A (A → B → C → A)\n\t9:2: A refers to B\n\t10:2: B refers to C\n\t11:2: C refers to A",
+            "16:2: invalid recursive type D (D → E → D)\n\t16:2: D refers to E\n\t17:2: E refers to D",
+            "20:6: invalid recursive type F (F → F)\n\t20:6: F refers to itself",
+            "24:2: invalid recursive type I (I → J → I)\n\t24:2: I refers to J\n\t25:2: J refers to I",
+        ]
+    );
+}
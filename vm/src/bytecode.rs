@@ -2,12 +2,13 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::intern::StringPool;
 use crate::value::*;
 #[cfg(feature = "serde_borsh")]
 use borsh::{maybestd::io::Result, maybestd::io::Write, BorshDeserialize, BorshSerialize};
 #[cfg(feature = "serde_borsh")]
 use go_parser::PiggyVecKey;
-use go_parser::{piggy_key_type, PiggyVec};
+use go_parser::{piggy_key_type, Map, PiggyVec};
 
 #[cfg(feature = "serde_borsh")]
 macro_rules! impl_borsh_for_key {
@@ -49,6 +50,8 @@ pub struct VMObjects {
     pub functions: FunctionObjs,
     pub packages: PackageObjs,
     pub prim_meta: PrimitiveMeta,
+    /// The names of the named types, like `main.T`, for the messages of runtime errors
+    pub type_names: Map<MetadataKey, String>,
     pub(crate) arr_slice_caller: Box<ArrCaller>,
 }
 
@@ -62,6 +65,7 @@ impl VMObjects {
             functions: PiggyVec::with_capacity(CAP),
             packages: PiggyVec::with_capacity(CAP),
             prim_meta,
+            type_names: Map::new(),
             arr_slice_caller: Box::new(ArrCaller::new()),
         }
     }
@@ -77,6 +81,7 @@ impl VMObjects {
             functions,
             packages,
             prim_meta,
+            type_names: Map::new(),
             arr_slice_caller: Box::new(ArrCaller::new()),
         }
     }
@@ -87,7 +92,14 @@ impl BorshSerialize for VMObjects {
     fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
         self.metas.serialize(writer)?;
         self.functions.serialize(writer)?;
-        self.packages.serialize(writer)
+        self.packages.serialize(writer)?;
+        let mut names: Vec<(MetadataKey, String)> = self
+            .type_names
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        names.sort_by_key(|(k, _)| k.as_usize());
+        names.serialize(writer)
     }
 }
 
@@ -97,7 +109,10 @@ impl BorshDeserialize for VMObjects {
         let metas = MetadataObjs::deserialize_reader(reader)?.into();
         let functions = FunctionObjs::deserialize_reader(reader)?.into();
         let packages = PackageObjs::deserialize_reader(reader)?.into();
-        Ok(Self::with_components(metas, functions, packages))
+        let names = Vec::<(MetadataKey, String)>::deserialize_reader(reader)?;
+        let mut objs = Self::with_components(metas, functions, packages);
+        objs.type_names = names.into_iter().collect();
+        Ok(objs)
     }
 }
 
@@ -113,6 +128,9 @@ pub struct Bytecode {
     pub main_pkg: PackageKey,
     /// Optional, for debug info
     pub file_set: Option<go_parser::FileSet>,
+    /// Filled by `intern_strings`
+    #[cfg_attr(feature = "serde_borsh", borsh_skip)]
+    pub strings: StringPool,
 }
 
 impl Bytecode {
@@ -140,6 +158,7 @@ impl Bytecode {
             entry,
             main_pkg,
             file_set,
+            strings: StringPool::default(),
         }
     }
 
@@ -163,6 +182,7 @@ impl Bytecode {
             entry,
             main_pkg,
             file_set,
+            strings: StringPool::default(),
         }
     }
 }
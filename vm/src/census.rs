@@ -0,0 +1,232 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A census of the objects a program keeps alive between runs, for the hosts to
+//! find out what a call leaves behind. The objects reachable from the package vars
+//! are traced the way the collector traces them, and counted by their class, and
+//! by the instruction that allocated them if the runs recorded it in `AllocSites`.
+//!
+//! Only the objects that can be shared are counted, the values held directly by a
+//! var, a field or an element are part of what holds them.
+
+use crate::bytecode::{Bytecode, FunctionKey};
+use crate::dispatcher::{ArrCaller, ElemType};
+use crate::gc::{holds_values, value_targets, GcWeak, Node, Target};
+use crate::instruction::{OpIndex, ValueType};
+use crate::objects::*;
+use crate::value::{GosValue, RCount};
+use go_parser::{Map, PiggyVecKey};
+use std::cell::{Cell, RefCell};
+use std::mem::{size_of, size_of_val};
+
+/// How many sites are recorded before the ones of the objects gone are dropped
+/// for the first time
+const MIN_PRUNE: usize = 1024;
+
+/// Where the structs, maps, closures and arrays of values, the objects tracked
+/// by the collector, were allocated. It's kept by the host and given to every run in
+/// `RunOptions::alloc_sites`, so that the objects outliving a run are attributed
+/// too. Recording slows the runs down, it's meant for tests.
+pub struct AllocSites {
+    /// The instruction being executed, if any
+    current: Cell<Option<(FunctionKey, OpIndex)>>,
+    /// The sites keyed by the addresses of the objects
+    sites: RefCell<Map<usize, (GcWeak, FunctionKey, OpIndex)>>,
+    /// The number of sites that has the ones of the objects gone dropped
+    prune_at: Cell<usize>,
+}
+
+impl AllocSites {
+    pub fn new() -> AllocSites {
+        AllocSites {
+            current: Cell::new(None),
+            sites: RefCell::new(Map::new()),
+            prune_at: Cell::new(MIN_PRUNE),
+        }
+    }
+
+    /// Attributes the objects allocated from now on to the instruction at `pc`
+    #[inline]
+    pub(crate) fn enter(&self, func: FunctionKey, pc: OpIndex) {
+        self.current.set(Some((func, pc)));
+    }
+
+    /// Stops attributing, no instruction is being executed
+    pub(crate) fn leave(&self) {
+        self.current.set(None);
+    }
+
+    pub(crate) fn record(&self, w: &GcWeak) {
+        if let Some((func, pc)) = self.current.get() {
+            let mut sites = self.sites.borrow_mut();
+            // an address is only reused once the object there is gone
+            sites.insert(w.addr(), (w.clone(), func, pc));
+            if sites.len() >= self.prune_at.get() {
+                sites.retain(|_, (w, _, _)| w.is_alive());
+                self.prune_at.set((sites.len() * 2).max(MIN_PRUNE));
+            }
+        }
+    }
+
+    fn site(&self, addr: usize) -> Option<(FunctionKey, OpIndex)> {
+        self.sites
+            .borrow()
+            .get(&addr)
+            .filter(|(w, _, _)| w.is_alive())
+            .map(|(_, func, pc)| (*func, *pc))
+    }
+}
+
+impl Default for AllocSites {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The objects of a class allocated at a site, alive at a census
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CensusEntry {
+    /// "struct", "array", "slice", "map", "closure", "interface", "string",
+    /// "channel", "upvalue", or what `UnsafePtr::census_class` says for user data
+    pub class: &'static str,
+    /// The function and the index of the instruction, if it was recorded
+    pub site: Option<(FunctionKey, OpIndex)>,
+    pub objects: usize,
+    /// The bytes of the objects and of the storage they own, roughly
+    pub bytes: usize,
+}
+
+/// The class and the site of the objects counted together, with the function as a
+/// number for sorting by it
+type CensusKey = (&'static str, Option<(usize, OpIndex)>);
+
+/// Counts the objects reachable from the package vars of `code`, by class and site,
+/// sorted by them. It must not be taken while `code` is being run.
+pub fn census(code: &Bytecode, sites: Option<&AllocSites>) -> Vec<CensusEntry> {
+    let mut seen: Map<usize, ()> = Map::new();
+    let mut queue: Vec<Node> = vec![];
+    for pkg in code.objects.packages.iter() {
+        for member in pkg.members() {
+            census_targets(&member, &mut |t| visit(&mut seen, &mut queue, t));
+        }
+    }
+    let mut counts: Map<CensusKey, CensusEntry> = Map::new();
+    while let Some(node) = queue.pop() {
+        trace(&node, &mut |t| visit(&mut seen, &mut queue, t));
+        let (class, bytes) = class_bytes(&node);
+        let site = match &node {
+            Node::Value(v) if is_sited(v.typ()) => sites.and_then(|s| s.site(node.addr())),
+            _ => None,
+        };
+        let key = (class, site.map(|(func, pc)| (func.as_usize(), pc)));
+        let entry = counts.entry(key).or_insert(CensusEntry {
+            class,
+            site,
+            objects: 0,
+            bytes: 0,
+        });
+        entry.objects += 1;
+        entry.bytes += bytes;
+    }
+    let mut entries: Vec<(_, CensusEntry)> = counts.into_iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Queues the object if it's not been seen before
+fn visit(seen: &mut Map<usize, ()>, queue: &mut Vec<Node>, t: Target) {
+    if seen.insert(t.addr(), ()).is_none() {
+        queue.push(t.to_node());
+    }
+}
+
+/// Calls `f` with the objects `node` refers to
+fn trace(node: &Node, f: &mut dyn FnMut(Target)) {
+    if let Node::Value(v) = node {
+        match v.typ() {
+            // the elements are not shared objects
+            ValueType::Array if !holds_values(v) => return,
+            ValueType::Slice if !holds_values(v) => {
+                return census_targets(v.as_slice::<AnyElem>().unwrap().0.array(), f)
+            }
+            ValueType::String | ValueType::Channel => return,
+            _ => {}
+        }
+    }
+    node.children_with(census_targets, f)
+}
+
+/// Like `value_targets`, with the arrays and slices of anything, the strings and
+/// the channels too, which can't be part of a cycle but take memory all the same
+fn census_targets(val: &GosValue, f: &mut dyn FnMut(Target)) {
+    match val.typ() {
+        ValueType::Array | ValueType::Slice | ValueType::String | ValueType::Channel => {
+            if !val.as_addr().is_null() {
+                f(Target::Value(val))
+            }
+        }
+        _ => value_targets(val, f),
+    }
+}
+
+/// If the objects of the type are the ones `AllocSites` records
+fn is_sited(t: ValueType) -> bool {
+    matches!(
+        t,
+        ValueType::Array | ValueType::Struct | ValueType::Closure | ValueType::Map
+    )
+}
+
+fn class_bytes(node: &Node) -> (&'static str, usize) {
+    match node {
+        Node::Value(v) => match v.typ() {
+            ValueType::Struct => (
+                "struct",
+                size_of::<(StructObj, RCount)>()
+                    + v.as_struct().0.borrow_fields().len() * size_of::<GosValue>(),
+            ),
+            ValueType::Array => (
+                "array",
+                size_of::<(GosArrayObj, RCount)>() + v.len() * elem_size(v.t_elem()),
+            ),
+            ValueType::Slice => ("slice", size_of::<(GosSliceObj, RCount)>()),
+            ValueType::Map => (
+                "map",
+                size_of::<(MapObj, RCount)>() + v.len() * 2 * size_of::<GosValue>(),
+            ),
+            ValueType::Closure => {
+                let uvs = match &v.as_closure().unwrap().0 {
+                    ClosureObj::Gos(c) => c.uvs.as_ref().map_or(0, |x| x.len()),
+                    ClosureObj::Ffi(_) => 0,
+                };
+                (
+                    "closure",
+                    size_of::<(ClosureObj, RCount)>() + uvs * size_of::<UpValue>(),
+                )
+            }
+            ValueType::Interface => ("interface", size_of::<InterfaceObj>()),
+            ValueType::String => ("string", size_of::<StringObj>() + v.len()),
+            #[cfg(feature = "async")]
+            ValueType::Channel => (
+                "channel",
+                size_of::<ChannelObj>() + v.cap() * size_of::<GosValue>(),
+            ),
+            _ => unreachable!(),
+        },
+        Node::UpValue(uv) => ("upvalue", size_of_val(&*uv.inner)),
+        Node::UnsafePtr(p) => (p.census_class(), size_of_val(&**p)),
+    }
+}
+
+/// The bytes of an element of an array of `t_elem`
+fn elem_size(t_elem: ValueType) -> usize {
+    match ArrCaller::get_elem_type(t_elem) {
+        ElemType::ElemType8 => 1,
+        ElemType::ElemType16 => 2,
+        ElemType::ElemType32 => 4,
+        ElemType::ElemType64 => 8,
+        ElemType::ElemTypeWord => size_of::<usize>(),
+        ElemType::ElemTypeGos => size_of::<GosElem>(),
+    }
+}
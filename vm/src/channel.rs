@@ -2,12 +2,31 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use super::gc::GcContainer;
 use super::instruction::*;
 use super::value::*;
 use futures_lite::future;
 use std::cell::RefCell;
+use std::fmt::Debug;
 use std::rc::Rc;
 
+pub use async_channel::TryRecvError;
+
+/// The receiving end of a channel fed by the host program, from outside the VM.
+/// Scripts can only receive from it, and only the host closes it.
+pub trait HostChannel: Debug {
+    /// Like `async_channel::Receiver::try_recv`, values are created with `gcc`.
+    fn try_recv(&self, gcc: &GcContainer) -> Result<GosValue, TryRecvError>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cap(&self) -> usize;
+}
+
 #[derive(Clone, Debug)]
 pub enum RendezvousState {
     NotReady,
@@ -24,6 +43,7 @@ pub enum Channel {
     ),
     // Cloning Channel needs to return the same channel, hence the Rc
     Rendezvous(Rc<RefCell<RendezvousState>>),
+    Host(Rc<dyn HostChannel>),
 }
 
 impl Channel {
@@ -41,6 +61,7 @@ impl Channel {
         match self {
             Channel::Bounded(s, _) => s.len(),
             Channel::Rendezvous(_) => 0,
+            Channel::Host(h) => h.len(),
         }
     }
 
@@ -49,16 +70,26 @@ impl Channel {
         match self {
             Channel::Bounded(s, _) => s.capacity().unwrap(),
             Channel::Rendezvous(_) => 0,
+            Channel::Host(h) => h.cap(),
         }
     }
 
+    /// Returns false if the channel is already closed
     #[inline]
-    pub fn close(&self) {
+    pub fn close(&self) -> bool {
         match self {
-            Channel::Bounded(s, _) => {
-                s.close();
+            Channel::Bounded(s, _) => s.close(),
+            Channel::Rendezvous(state) => {
+                let mut state = state.borrow_mut();
+                match &*state {
+                    RendezvousState::Closed => false,
+                    _ => {
+                        *state = RendezvousState::Closed;
+                        true
+                    }
+                }
             }
-            Channel::Rendezvous(state) => *state.borrow_mut() = RendezvousState::Closed,
+            Channel::Host(_) => true,
         }
     }
 
@@ -78,10 +109,11 @@ impl Channel {
                     RendezvousState::Closed => Err(async_channel::TrySendError::Closed(v)),
                 }
             }
+            Channel::Host(_) => Err(async_channel::TrySendError::Closed(v)),
         }
     }
 
-    pub fn try_recv(&self) -> Result<GosValue, async_channel::TryRecvError> {
+    pub fn try_recv(&self, gcc: &GcContainer) -> Result<GosValue, async_channel::TryRecvError> {
         match self {
             Channel::Bounded(_, r) => r.try_recv(),
             Channel::Rendezvous(state) => {
@@ -106,6 +138,7 @@ impl Channel {
                     RendezvousState::Closed => Err(async_channel::TryRecvError::Closed),
                 }
             }
+            Channel::Host(h) => h.try_recv(gcc),
         }
     }
 
@@ -120,17 +153,17 @@ impl Channel {
                         future::yield_now().await;
                     }
                     async_channel::TrySendError::Closed(_) => {
-                        return Err("channel closed!".to_owned().into());
+                        return Err("send on closed channel".to_owned().into());
                     }
                 },
             }
         }
     }
 
-    pub async fn recv(&self) -> Option<GosValue> {
+    pub async fn recv(&self, gcc: &GcContainer) -> Option<GosValue> {
         //dbg!(self);
         loop {
-            match self.try_recv() {
+            match self.try_recv(gcc) {
                 Ok(v) => return Some(v),
                 Err(e) => match e {
                     async_channel::TryRecvError::Empty => {
@@ -167,34 +200,40 @@ impl Selector {
         }
     }
 
-    pub async fn select(&self) -> RuntimeResult<(usize, Option<GosValue>)> {
+    pub async fn select(&self, gcc: &GcContainer) -> RuntimeResult<(usize, Option<GosValue>)> {
         let count = self.comms.len();
-        let rand_start = fastrand::usize(0..count);
+        // a select with only a default case has nothing to try
+        let rand_start = if count > 0 {
+            fastrand::usize(0..count)
+        } else {
+            0
+        };
         loop {
             for i in 0..count {
                 let index = (i + rand_start) % count;
                 let entry = &self.comms[index];
+                // a nil channel is never ready
+                let chan = match entry.chan.as_channel() {
+                    Some(c) => &c.chan,
+                    None => continue,
+                };
                 match &entry.typ {
-                    SelectCommType::Send(val) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_send(val.clone()) {
-                            Ok(_) => return Ok((index, None)),
-                            Err(e) => match e {
-                                async_channel::TrySendError::Full(_) => {}
-                                async_channel::TrySendError::Closed(_) => {
-                                    return Err("channel closed!".to_owned().into());
-                                }
-                            },
-                        }
-                    }
-                    SelectCommType::Recv(_, _) => {
-                        match entry.chan.as_non_nil_channel()?.chan.try_recv() {
-                            Ok(v) => return Ok((index, Some(v))),
-                            Err(e) => match e {
-                                async_channel::TryRecvError::Empty => {}
-                                async_channel::TryRecvError::Closed => return Ok((index, None)),
-                            },
-                        }
-                    }
+                    SelectCommType::Send(val) => match chan.try_send(val.clone()) {
+                        Ok(_) => return Ok((index, None)),
+                        Err(e) => match e {
+                            async_channel::TrySendError::Full(_) => {}
+                            async_channel::TrySendError::Closed(_) => {
+                                return Err("send on closed channel".to_owned().into());
+                            }
+                        },
+                    },
+                    SelectCommType::Recv(_, _) => match chan.try_recv(gcc) {
+                        Ok(v) => return Ok((index, Some(v))),
+                        Err(e) => match e {
+                            async_channel::TryRecvError::Empty => {}
+                            async_channel::TryRecvError::Closed => return Ok((index, None)),
+                        },
+                    },
                 }
             }
 
@@ -24,6 +24,13 @@ pub(crate) trait Dispatcher {
 
     fn slice_copy_semantic(&self, vdata: &ValueData) -> ValueData;
 
+    /// A slice with the same bounds as `slice`, but into the array `f` maps its array to
+    fn slice_map_array(
+        &self,
+        slice: &GosValue,
+        f: &mut dyn FnMut(&GosValue) -> RuntimeResult<GosValue>,
+    ) -> RuntimeResult<GosValue>;
+
     // you cannot just dispatch the default fn hash, as it makes this trait not object-safe
     fn array_hash(&self, val: &GosValue, state: &mut dyn Hasher);
 
@@ -37,15 +44,24 @@ pub(crate) trait Dispatcher {
 
     fn array_len(&self, val: &GosValue) -> usize;
 
+    /// `zero` makes the zero values for the part of the array beyond its elements,
+    /// when the result is longer than the array, see `SliceObj::with_cap`
     fn slice_slice(
         &self,
         slice: &GosValue,
         begin: isize,
         end: isize,
         max: isize,
+        zero: &dyn Fn() -> GosValue,
     ) -> RuntimeResult<GosValue>;
 
-    fn slice_array(&self, arr: GosValue, begin: isize, end: isize) -> RuntimeResult<GosValue>;
+    fn slice_array(
+        &self,
+        arr: GosValue,
+        begin: isize,
+        end: isize,
+        max: isize,
+    ) -> RuntimeResult<GosValue>;
 
     fn slice_append(
         &self,
@@ -123,7 +139,7 @@ macro_rules! define_dispatcher {
 
             #[inline]
             fn array_copy_semantic(&self, vdata: &ValueData, gcc: &GcContainer) -> ValueData {
-                ValueData::new_array::<$elem>(vdata.as_array::<$elem>().0.clone(), gcc)
+                ValueData::new_array::<$elem>(vdata.as_array::<$elem>().0.copy_semantic(gcc), gcc)
             }
 
             #[inline]
@@ -134,6 +150,17 @@ macro_rules! define_dispatcher {
                 }
             }
 
+            #[inline]
+            fn slice_map_array(
+                &self,
+                slice: &GosValue,
+                f: &mut dyn FnMut(&GosValue) -> RuntimeResult<GosValue>,
+            ) -> RuntimeResult<GosValue> {
+                let s = &slice.as_slice::<$elem>().unwrap().0;
+                let array = f(s.array())?;
+                Ok(GosValue::new_slice(s.with_same_bounds(array), self.typ))
+            }
+
             #[inline]
             fn array_hash(&self, val: &GosValue, state: &mut dyn Hasher) {
                 val.as_array::<$elem>().0.dyn_hash(state);
@@ -179,14 +206,18 @@ macro_rules! define_dispatcher {
                 begin: isize,
                 end: isize,
                 max: isize,
+                zero: &dyn Fn() -> GosValue,
             ) -> RuntimeResult<GosValue> {
-                Ok(GosValue::new_slice(
-                    slice
-                        .as_non_nil_slice::<$elem>()?
-                        .0
-                        .slice(begin, end, max)?,
-                    slice.t_elem,
-                ))
+                match slice.as_slice::<$elem>() {
+                    Some(s) => {
+                        let result = s.0.slice(begin, end, max)?;
+                        result.fill_zeros(zero);
+                        Ok(GosValue::new_slice(result, slice.t_elem))
+                    }
+                    // a nil slice can only be sliced to itself
+                    None => SliceObj::<$elem>::check_indices(0, 0, 0, begin, end, max, "capacity")
+                        .map(|_| slice.clone()),
+                }
             }
 
             #[inline]
@@ -195,9 +226,10 @@ macro_rules! define_dispatcher {
                 arr: GosValue,
                 begin: isize,
                 end: isize,
+                max: isize,
             ) -> RuntimeResult<GosValue> {
                 Ok(GosValue::new_slice::<$elem>(
-                    SliceObj::with_array(arr, begin, end)?,
+                    SliceObj::with_array(arr, begin, end, max)?,
                     self.typ,
                 ))
             }
@@ -212,24 +244,34 @@ macro_rules! define_dispatcher {
                 let a = this.as_slice::<$elem>();
                 let b = other.as_slice::<$elem>();
                 match b {
-                    Some(y) => match a {
-                        Some(x) => {
-                            let mut to = x.0.clone();
-                            to.append(&y.0);
-                            Ok(GosValue::new_slice(to, other.t_elem()))
+                    Some(y) if y.0.len() > 0 => {
+                        let (len, cap) = a.map_or((0, 0), |x| (x.0.len(), x.0.cap()));
+                        let new_len = len + y.0.len();
+                        match a {
+                            Some(x) if new_len <= cap => {
+                                let mut to = x.0.clone();
+                                to.append(&y.0);
+                                Ok(GosValue::new_slice(to, other.t_elem()))
+                            }
+                            // it doesn't fit, the elements are copied to a new array
+                            _ => {
+                                let new_cap = grown_cap(cap, new_len);
+                                let mut data = Vec::with_capacity(new_cap);
+                                if let Some(x) = a {
+                                    data.extend_from_slice(&x.0.as_rust_slice());
+                                }
+                                data.extend_from_slice(&y.0.as_rust_slice());
+                                let arr = ArrayObj::<$elem>::with_raw_data(data);
+                                let slice = SliceObj::<$elem>::with_cap(
+                                    GosValue::new_array(arr, other.t_elem(), gcc),
+                                    new_len,
+                                    new_cap,
+                                );
+                                Ok(GosValue::new_slice(slice, other.t_elem()))
+                            }
                         }
-                        None => {
-                            let data = y.0.as_rust_slice().to_vec();
-                            let arr = ArrayObj::<$elem>::with_raw_data(data);
-                            let slice = SliceObj::<$elem>::with_array(
-                                GosValue::new_array(arr, other.t_elem(), gcc),
-                                0,
-                                -1,
-                            )?;
-                            Ok(GosValue::new_slice(slice, other.t_elem()))
-                        }
-                    },
-                    None => Ok(this),
+                    }
+                    _ => Ok(this),
                 }
             }
 
@@ -269,7 +311,20 @@ macro_rules! define_dispatcher {
                 val: &GosValue,
             ) -> RuntimeResult<SliceEnumIter<'static, AnyElem>> {
                 let rust_slice = match val.typ() {
-                    ValueType::Slice => val.as_non_nil_slice::<$elem>()?.0.as_rust_slice(),
+                    ValueType::Slice => match val.as_slice::<$elem>() {
+                        Some(s) => s.0.as_rust_slice(),
+                        // ranging over a nil slice is ranging over an empty one
+                        None => {
+                            let empty: &[$elem] = &[];
+                            let iter: SliceEnumIter<'_, $elem> = empty.iter().enumerate();
+                            return Ok(unsafe {
+                                std::mem::transmute::<
+                                    SliceEnumIter<'_, $elem>,
+                                    SliceEnumIter<'static, AnyElem>,
+                                >(iter)
+                            });
+                        }
+                    },
                     ValueType::Array => val.as_array::<$elem>().0.as_rust_slice(),
                     _ => unreachable!(),
                 };
@@ -356,7 +411,7 @@ impl ArrCaller {
     #[inline]
     pub fn get_slow(t: ValueType) -> Box<dyn Dispatcher> {
         match t {
-            ValueType::Int8 | ValueType::Uint8 => Box::new(Dispatcher8::new(t)),
+            ValueType::Bool | ValueType::Int8 | ValueType::Uint8 => Box::new(Dispatcher8::new(t)),
             ValueType::Int16 | ValueType::Uint16 => Box::new(Dispatcher16::new(t)),
             ValueType::Int32 | ValueType::Uint32 | ValueType::Float32 => {
                 Box::new(Dispatcher32::new(t))
@@ -368,7 +423,7 @@ impl ArrCaller {
             | ValueType::Uint
             | ValueType::UintPtr
             | ValueType::Function
-            | ValueType::Package => Box::new(Dispatcher64::new(t)),
+            | ValueType::Package => Box::new(DispatcherWord::new(t)),
             _ => Box::new(DispatcherGos::new(t)),
         }
     }
@@ -3,11 +3,14 @@
 // license that can be found in the LICENSE file.
 
 use crate::bytecode::*;
+#[cfg(feature = "async")]
+use crate::channel::{Channel, HostChannel};
 use crate::dispatcher::ArrCaller;
 use crate::gc::GcContainer;
 use crate::stack::Stack;
 use crate::value::*;
 use crate::value::{GosValue, RuntimeResult};
+use crate::vm::StdOutSink;
 #[cfg(feature = "async")]
 use futures_lite::future::Future;
 use go_parser::Map;
@@ -23,9 +26,27 @@ pub struct FfiCtx<'a> {
     pub stack: &'a mut Stack,
     pub gcc: &'a GcContainer,
     pub(crate) array_slice_caller: &'a ArrCaller,
+    pub(crate) std_out: Option<&'a StdOutSink>,
 }
 
 impl<'a> FfiCtx<'a> {
+    /// Calls `f` with a FfiCtx that is not in a running VM, for the host to make
+    /// and read the values passed to and returned by `call`.
+    pub fn with_objects<R>(vm_objs: &VMObjects, f: impl FnOnce(&mut FfiCtx) -> R) -> R {
+        let mut stack = Stack::new();
+        let gcc = GcContainer::new();
+        let mut ctx = FfiCtx {
+            func_name: "",
+            vm_objs,
+            user_data: None,
+            stack: &mut stack,
+            gcc: &gcc,
+            array_slice_caller: &vm_objs.arr_slice_caller,
+            std_out: None,
+        };
+        f(&mut ctx)
+    }
+
     #[inline]
     pub fn new_nil(t: ValueType) -> GosValue {
         GosValue::new_nil(t)
@@ -71,6 +92,12 @@ impl<'a> FfiCtx<'a> {
         GosValue::with_str(s)
     }
 
+    /// A string of a copy of `bytes`, which don't have to be valid UTF-8
+    #[inline]
+    pub fn new_string_from_bytes(bytes: &[u8]) -> GosValue {
+        GosValue::new_string(StringObj::with_bytes(bytes))
+    }
+
     #[inline]
     pub fn new_unsafe_ptr(p: Rc<dyn UnsafePtr>) -> GosValue {
         GosValue::new_unsafe_ptr(p)
@@ -81,6 +108,12 @@ impl<'a> FfiCtx<'a> {
         GosValue::new_struct(StructObj::new(fields), self.gcc)
     }
 
+    /// Like `new_struct`, for values made outside of FFI calls, e.g. by a `HostChannel`.
+    #[inline]
+    pub fn new_struct_in(fields: Vec<GosValue>, gcc: &GcContainer) -> GosValue {
+        GosValue::new_struct(StructObj::new(fields), gcc)
+    }
+
     #[inline]
     pub fn new_array(&self, member: Vec<GosValue>, t_elem: ValueType) -> GosValue {
         GosValue::array_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
@@ -95,6 +128,11 @@ impl<'a> FfiCtx<'a> {
         GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), t_elem)
     }
 
+    #[inline]
+    pub fn new_slice(&self, member: Vec<GosValue>, t_elem: ValueType) -> GosValue {
+        GosValue::slice_with_data(member, self.array_slice_caller.get(t_elem), self.gcc)
+    }
+
     #[inline]
     pub fn new_map(&self, m: Map<GosValue, GosValue>) -> GosValue {
         GosValue::map_with_data(m, self.gcc)
@@ -130,9 +168,41 @@ impl<'a> FfiCtx<'a> {
 
     #[inline]
     pub fn new_empty_interface(&self, underlying: GosValue, meta: Meta) -> GosValue {
+        Self::empty_interface_of(underlying, meta)
+    }
+
+    /// Like `new_empty_interface`, for values made outside of FFI calls.
+    #[inline]
+    pub fn empty_interface_of(underlying: GosValue, meta: Meta) -> GosValue {
         GosValue::new_interface(InterfaceObj::with_value(underlying, Some((meta, vec![]))))
     }
 
+    /// A copy of `val`, like assigning it in script.
+    #[inline]
+    pub fn copy_value(&self, val: &GosValue) -> GosValue {
+        val.copy_semantic(self.gcc)
+    }
+
+    /// An interface holding a copy of `val`, like assigning it in script.
+    /// `metas` are (Interface meta, value meta).
+    #[inline]
+    pub fn copy_to_interface(&self, val: &GosValue, metas: (&Meta, Meta)) -> GosValue {
+        self.new_interface(val.copy_semantic(self.gcc), Some(metas))
+    }
+
+    /// An empty interface holding a copy of `val`, like converting it in script.
+    #[inline]
+    pub fn copy_to_empty_interface(&self, val: &GosValue, meta: Meta) -> GosValue {
+        Self::empty_interface_of(val.copy_semantic(self.gcc), meta)
+    }
+
+    /// A channel fed by the host, `recv_zero` is the zero value of its element type.
+    #[cfg(feature = "async")]
+    #[inline]
+    pub fn new_host_channel(chan: Rc<dyn HostChannel>, recv_zero: GosValue) -> GosValue {
+        GosValue::channel_with_chan(Channel::Host(chan), recv_zero)
+    }
+
     #[inline]
     pub fn deref_pointer(&self, ptr: &GosValue) -> RuntimeResult<GosValue> {
         ptr.as_non_nil_pointer()?
@@ -144,6 +214,24 @@ impl<'a> FfiCtx<'a> {
         m.zero(&self.vm_objs.metas, self.gcc)
     }
 
+    /// The std out of the run, if it has one of its own, see `RunOptions::std_out`
+    #[inline]
+    pub fn std_out(&self) -> Option<&StdOutSink> {
+        self.std_out
+    }
+
+    /// Returns the elements of an array, whatever the element type is
+    #[inline]
+    pub fn array_elems(val: &GosValue) -> Vec<GosValue> {
+        val.caller_slow().array_get_vec(val)
+    }
+
+    /// Returns the elements of a slice, whatever the element type is, or None if it's nil
+    #[inline]
+    pub fn slice_elems(val: &GosValue) -> Option<Vec<GosValue>> {
+        val.caller_slow().slice_get_vec(val)
+    }
+
     #[inline]
     pub fn slice_as_rust_slice<T>(val: &GosValue) -> RuntimeResult<Ref<[T]>>
     where
@@ -179,6 +267,153 @@ impl<'a> FfiCtx<'a> {
     }
 }
 
+/// The arguments of a native function, read as the types they are expected to be.
+/// A wrong count or type is an error that becomes a panic in the Go code, instead
+/// of a Rust panic.
+pub struct FfiArgs<'a> {
+    func: &'a str,
+    args: Vec<GosValue>,
+}
+
+impl<'a> FfiArgs<'a> {
+    pub fn new(func: &'a str, args: Vec<GosValue>) -> FfiArgs<'a> {
+        FfiArgs { func, args }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Fails unless there are exactly `count` arguments
+    pub fn expect_len(&self, count: usize) -> Result<(), FfiError> {
+        match self.args.len() == count {
+            true => Ok(()),
+            false => Err(FfiError::arity(self.func, count, self.args.len())),
+        }
+    }
+
+    /// The argument at `i`, whatever its type is
+    pub fn get(&self, i: usize) -> Result<&GosValue, FfiError> {
+        self.args.get(i).ok_or_else(|| {
+            let msg = format!("argument {} missing, got {}", i, self.args.len());
+            FfiError::new(self.func, msg)
+        })
+    }
+
+    /// The argument at `i`, which has to be of type `t`
+    pub fn get_typed(&self, i: usize, t: ValueType) -> Result<&GosValue, FfiError> {
+        let val = self.get(i)?;
+        match val.typ() == t {
+            true => Ok(val),
+            false => {
+                let msg = format!("argument {} is {}, expected {}", i, val.typ(), t);
+                Err(FfiError::new(self.func, msg))
+            }
+        }
+    }
+
+    pub fn get_bool(&self, i: usize) -> Result<bool, FfiError> {
+        self.get_typed(i, ValueType::Bool).map(|v| *v.as_bool())
+    }
+
+    pub fn get_int(&self, i: usize) -> Result<isize, FfiError> {
+        self.get_typed(i, ValueType::Int).map(|v| *v.as_int())
+    }
+
+    pub fn get_int64(&self, i: usize) -> Result<i64, FfiError> {
+        self.get_typed(i, ValueType::Int64).map(|v| *v.as_int64())
+    }
+
+    pub fn get_uint(&self, i: usize) -> Result<usize, FfiError> {
+        self.get_typed(i, ValueType::Uint).map(|v| *v.as_uint())
+    }
+
+    pub fn get_uint64(&self, i: usize) -> Result<u64, FfiError> {
+        self.get_typed(i, ValueType::Uint64).map(|v| *v.as_uint64())
+    }
+
+    pub fn get_float64(&self, i: usize) -> Result<f64, FfiError> {
+        self.get_typed(i, ValueType::Float64)
+            .map(|v| v.as_float64().into_inner())
+    }
+
+    pub fn get_complex128(&self, i: usize) -> Result<Complex128, FfiError> {
+        self.get_typed(i, ValueType::Complex128)
+            .map(|v| *v.as_complex128())
+    }
+
+    pub fn get_str(&self, i: usize) -> Result<String, FfiError> {
+        self.get_typed(i, ValueType::String)
+            .map(|v| v.as_string().as_str().to_owned())
+    }
+
+    /// The bytes of a `[]byte`, none for a nil slice
+    pub fn get_slice_of_u8(&self, i: usize) -> Result<Vec<u8>, FfiError> {
+        let val = self.get_typed(i, ValueType::Slice)?;
+        if val.t_elem() != ValueType::Uint8 {
+            let msg = format!("argument {} is []{}, expected []Uint8", i, val.t_elem());
+            return Err(FfiError::new(self.func, msg));
+        }
+        Ok(val
+            .as_slice::<Elem8>()
+            .map_or(vec![], |s| s.0.as_raw_slice::<u8>().to_vec()))
+    }
+
+    /// The value in the interface at `i`, `None` if the interface is nil
+    pub fn get_interface(&self, i: usize) -> Result<Option<&InterfaceObj>, FfiError> {
+        self.get_typed(i, ValueType::Interface)
+            .map(|v| v.as_interface())
+    }
+
+    pub fn into_vec(self) -> Vec<GosValue> {
+        self.args
+    }
+}
+
+/// The results of a native function, pushed in the order of its signature
+#[derive(Default)]
+pub struct FfiResult(Vec<GosValue>);
+
+impl FfiResult {
+    pub fn new() -> FfiResult {
+        FfiResult(vec![])
+    }
+
+    pub fn push(mut self, val: impl Into<GosValue>) -> FfiResult {
+        self.0.push(val.into());
+        self
+    }
+
+    /// Pushes the error as a string, empty for no error. The std packages turn
+    /// it into an `error` on the Go side, with `errors.New` unless it's empty.
+    pub fn push_err<E: std::fmt::Display>(self, err: Option<E>) -> FfiResult {
+        let msg = err.map(|e| e.to_string()).unwrap_or_default();
+        self.push(FfiCtx::new_string(&msg))
+    }
+
+    /// Pushes the value of `result`, or `zero` for an error, followed by the error
+    pub fn push_result<T, E>(self, result: Result<T, E>, zero: GosValue) -> FfiResult
+    where
+        T: Into<GosValue>,
+        E: std::fmt::Display,
+    {
+        match result {
+            Ok(v) => self.push(v).push_err(None::<E>),
+            Err(e) => self.push(zero).push_err(Some(e)),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<GosValue> {
+        self.0
+    }
+}
+
 /// A FFI Object implemented in Rust for Goscript to call
 pub trait Ffi {
     fn call(&self, ctx: &mut FfiCtx, params: Vec<GosValue>) -> RuntimeResult<Vec<GosValue>>;
@@ -189,6 +424,13 @@ pub trait Ffi {
         ctx: &mut FfiCtx,
         params: Vec<GosValue>,
     ) -> Pin<Box<dyn Future<Output = RuntimeResult<Vec<GosValue>>> + '_>>;
+
+    /// The declarations of the functions, they are checked against the handlers
+    /// when the FFI is registered, and against the Go interface when a package
+    /// creates it. FFIs that don't declare anything are not checked.
+    fn decls(&self) -> Vec<FfiFuncDecl> {
+        vec![]
+    }
 }
 
 impl std::fmt::Debug for dyn Ffi {
@@ -197,8 +439,11 @@ impl std::fmt::Debug for dyn Ffi {
     }
 }
 
+/// A registered ffi and the signatures of its methods, by their names
+type FfiEntry = (Rc<dyn Ffi>, Map<&'static str, FfiSignature>);
+
 pub struct FfiFactory {
-    registry: Map<&'static str, Rc<dyn Ffi>>,
+    registry: Map<&'static str, FfiEntry>,
     /// Down-casting only works for 'static types,
     /// so we just use the good old pointers
     user_data: Option<usize>,
@@ -219,17 +464,53 @@ impl FfiFactory {
         }
     }
 
+    /// Panics if `try_register` fails
     pub fn register(&mut self, name: &'static str, proto: Rc<dyn Ffi>) {
-        assert!(self.registry.insert(name, proto).is_none());
+        if let Err(e) = self.try_register(name, proto) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Registers the FFI after checking its declarations: the signatures must
+    /// parse and agree with the parameter and result types of the handlers.
+    pub fn try_register(&mut self, name: &'static str, proto: Rc<dyn Ffi>) -> Result<(), FfiError> {
+        if self.registry.contains_key(name) {
+            return Err(FfiError::new(name, "already registered".to_owned()));
+        }
+        let mut sigs = Map::new();
+        for decl in proto.decls() {
+            let func = format!("{}.{}", name, decl.name);
+            let sig = FfiSignature::parse(decl.signature)
+                .map_err(|e| FfiError::new(&func, format!("bad signature: {}", e)))?;
+            sig.check_handler(&decl)
+                .map_err(|e| FfiError::new(&func, e))?;
+            if sigs.insert(decl.name, sig).is_some() {
+                return Err(FfiError::new(&func, "declared twice".to_owned()));
+            }
+        }
+        self.registry.insert(name, (proto, sigs));
+        Ok(())
     }
 
     pub(crate) fn user_data(&self) -> Option<usize> {
         self.user_data
     }
 
-    pub(crate) fn create(&self, name: &str) -> RuntimeResult<Rc<dyn Ffi>> {
+    /// Creates the FFI for a Go interface, the interface has to match the
+    /// declarations of the FFI if it has any.
+    pub(crate) fn create(
+        &self,
+        name: &str,
+        iface: &Meta,
+        metas: &MetadataObjs,
+    ) -> RuntimeResult<Rc<dyn Ffi>> {
         match self.registry.get(name) {
-            Some(proto) => Ok(proto.clone()),
+            Some((proto, sigs)) => {
+                if !sigs.is_empty() {
+                    check_iface(name, sigs, iface, metas).map_err(|e| e.to_string())?;
+                }
+                Ok(proto.clone())
+            }
             None => Err(format!("FFI named {} not found", name).into()),
         }
     }
@@ -241,6 +522,366 @@ impl std::fmt::Debug for FfiFactory {
     }
 }
 
+/// The declaration of a native function: its Go signature, and the types the
+/// Rust handler converts the arguments and results to. `#[ffi_impl]` generates
+/// them for the methods with a `#[go_sig = "..."]` attribute.
+#[derive(Clone, Debug)]
+pub struct FfiFuncDecl {
+    pub name: &'static str,
+    /// The Go signature, e.g. "func(s string, n int) ([]byte, error)"
+    pub signature: &'static str,
+    /// `None` for the `GosValue` parameters, which can be of any type
+    pub params: Vec<Option<ValueType>>,
+    /// `None` if the handler returns a `Vec<GosValue>`
+    pub results: Option<Vec<Option<ValueType>>>,
+}
+
+/// The value types of a Go signature, `None` for the named types that can't
+/// be resolved without the Go code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FfiSignature {
+    pub params: Vec<Option<ValueType>>,
+    pub results: Vec<Option<ValueType>>,
+}
+
+impl FfiSignature {
+    pub fn parse(sig: &str) -> Result<FfiSignature, String> {
+        let mut p = SigParser {
+            tokens: sig_tokens(sig),
+            pos: 0,
+        };
+        p.expect("func")?;
+        let params = p.params()?;
+        let results = match p.peek() {
+            None => vec![],
+            Some("(") => p.params()?,
+            Some(_) => vec![p.typ()?],
+        };
+        match p.peek() {
+            None => Ok(FfiSignature { params, results }),
+            Some(t) => Err(format!("unexpected '{}'", t)),
+        }
+    }
+
+    fn check_handler(&self, decl: &FfiFuncDecl) -> Result<(), String> {
+        check_types("parameter", &self.params, &decl.params, "the handler")?;
+        match &decl.results {
+            Some(results) => check_types("result", &self.results, results, "the handler"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks the types of a Go signature against the ones of the Rust handler or
+/// the ones in the Go code, the types unknown to either side are not checked.
+fn check_types(
+    what: &str,
+    sig: &[Option<ValueType>],
+    other: &[Option<ValueType>],
+    other_name: &str,
+) -> Result<(), String> {
+    if sig.len() != other.len() {
+        return Err(format!(
+            "{} {}s in the signature, {} in {}",
+            sig.len(),
+            what,
+            other.len(),
+            other_name
+        ));
+    }
+    for (i, (s, o)) in sig.iter().zip(other.iter()).enumerate() {
+        if let (Some(s), Some(o)) = (s, o) {
+            if s != o {
+                return Err(format!(
+                    "{} {} is {} in the signature, {} in {}",
+                    what, i, s, o, other_name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_iface(
+    name: &str,
+    sigs: &Map<&'static str, FfiSignature>,
+    iface: &Meta,
+    metas: &MetadataObjs,
+) -> Result<(), FfiError> {
+    let types = |ms: &[Meta]| -> Vec<Option<ValueType>> {
+        ms.iter().map(|m| Some(m.value_type(metas))).collect()
+    };
+    for method in metas[iface.key].as_interface().infos() {
+        let func = format!("{}.{}", name, method.name);
+        let sig = sigs
+            .get(method.name.as_str())
+            .ok_or_else(|| FfiError::new(&func, "not declared by the FFI".to_owned()))?;
+        let go_sig = metas[method.meta.key].as_signature();
+        check_types(
+            "parameter",
+            &sig.params,
+            &types(&go_sig.params),
+            "the Go code",
+        )
+        .and_then(|_| {
+            check_types(
+                "result",
+                &sig.results,
+                &types(&go_sig.results),
+                "the Go code",
+            )
+        })
+        .map_err(|e| FfiError::new(&func, e))?;
+    }
+    Ok(())
+}
+
+/// A native function that doesn't agree with its declaration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FfiError {
+    /// The function, qualified with the FFI name when it's known
+    pub func: String,
+    pub msg: String,
+}
+
+impl FfiError {
+    fn new(func: &str, msg: String) -> FfiError {
+        FfiError {
+            func: func.to_owned(),
+            msg,
+        }
+    }
+
+    /// The function takes `expected` arguments but is called with `got`
+    pub fn arity(func: &str, expected: usize, got: usize) -> FfiError {
+        let msg = format!("takes {} arguments, got {}", expected, got);
+        FfiError::new(func, msg)
+    }
+}
+
+impl std::fmt::Display for FfiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ffi function '{}': {}", self.func, self.msg)
+    }
+}
+
+impl std::error::Error for FfiError {}
+
+impl From<FfiError> for RuntimeError {
+    fn from(e: FfiError) -> Self {
+        e.to_string().into()
+    }
+}
+
+fn sig_tokens(s: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut t = c.to_string();
+        if c.is_alphanumeric() || c == '_' {
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                t.push(c);
+                chars.next();
+            }
+        } else if c == '.' && chars.peek() == Some(&'.') {
+            chars.next();
+            chars.next();
+            t = "...".to_owned();
+        } else if c == '<' && chars.peek() == Some(&'-') {
+            chars.next();
+            t = "<-".to_owned();
+        }
+        tokens.push(t);
+    }
+    tokens
+}
+
+/// Parses just enough of the Go type syntax to tell the value types
+struct SigParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl SigParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|x| x.as_str())
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        self.pos += 1;
+        self.tokens
+            .get(self.pos - 1)
+            .map(|x| x.as_str())
+            .ok_or_else(|| "unexpected end".to_owned())
+    }
+
+    fn expect(&mut self, t: &str) -> Result<(), String> {
+        match self.next()? {
+            x if x == t => Ok(()),
+            x => Err(format!("expected '{}', found '{}'", t, x)),
+        }
+    }
+
+    fn is_name(&self, i: usize) -> bool {
+        self.tokens[i]
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_')
+    }
+
+    /// A parenthesized parameter or result list, with or without names
+    fn params(&mut self) -> Result<Vec<Option<ValueType>>, String> {
+        self.expect("(")?;
+        // (type, named, is a plain identifier)
+        let mut entries = vec![];
+        while self.peek() != Some(")") {
+            let start = self.pos;
+            let mut t = self.typ()?;
+            let plain = self.pos == start + 1 && self.is_name(start);
+            let named = !matches!(self.peek(), Some(",") | Some(")"));
+            if named {
+                t = self.typ()?;
+            }
+            entries.push((t, named, plain));
+            if self.peek() == Some(",") {
+                self.next()?;
+            }
+        }
+        self.next()?;
+        if entries.iter().any(|x| x.1) {
+            // in "a, b int" a takes the type of b
+            let mut next_type = None;
+            for e in entries.iter_mut().rev() {
+                if e.1 {
+                    next_type = Some(e.0);
+                } else if !e.2 {
+                    return Err("mixed named and unnamed parameters".to_owned());
+                } else {
+                    e.0 = next_type.ok_or_else(|| "missing parameter type".to_owned())?;
+                }
+            }
+        }
+        Ok(entries.into_iter().map(|x| x.0).collect())
+    }
+
+    fn typ(&mut self) -> Result<Option<ValueType>, String> {
+        let t = match self.next()? {
+            "[" => {
+                if self.peek() == Some("]") {
+                    self.next()?;
+                    self.typ()?;
+                    ValueType::Slice
+                } else {
+                    self.next()?;
+                    self.expect("]")?;
+                    self.typ()?;
+                    ValueType::Array
+                }
+            }
+            "..." => {
+                self.typ()?;
+                ValueType::Slice
+            }
+            "*" => {
+                self.typ()?;
+                ValueType::Pointer
+            }
+            "map" => {
+                self.expect("[")?;
+                self.typ()?;
+                self.expect("]")?;
+                self.typ()?;
+                ValueType::Map
+            }
+            "chan" => {
+                if self.peek() == Some("<-") {
+                    self.next()?;
+                }
+                self.typ()?;
+                ValueType::Channel
+            }
+            "<-" => {
+                self.expect("chan")?;
+                self.typ()?;
+                ValueType::Channel
+            }
+            "func" => {
+                self.params()?;
+                match self.peek() {
+                    Some("(") => {
+                        self.params()?;
+                    }
+                    Some(",") | Some(")") | Some("]") | None => {}
+                    Some(_) => {
+                        self.typ()?;
+                    }
+                }
+                ValueType::Closure
+            }
+            x @ ("interface" | "struct") => {
+                let t = if x == "interface" {
+                    ValueType::Interface
+                } else {
+                    ValueType::Struct
+                };
+                self.expect("{")?;
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next()? {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {}
+                    }
+                }
+                t
+            }
+            "(" => {
+                let t = self.typ()?;
+                self.expect(")")?;
+                return Ok(t);
+            }
+            name => {
+                let name = name.to_owned();
+                if self.peek() == Some(".") {
+                    self.next()?;
+                    let sel = self.next()?;
+                    return Ok(
+                        (name == "unsafe" && sel == "Pointer").then_some(ValueType::UnsafePtr)
+                    );
+                }
+                match name.as_str() {
+                    "bool" => ValueType::Bool,
+                    "int" => ValueType::Int,
+                    "int8" => ValueType::Int8,
+                    "int16" => ValueType::Int16,
+                    "int32" | "rune" => ValueType::Int32,
+                    "int64" => ValueType::Int64,
+                    "uint" => ValueType::Uint,
+                    "uintptr" => ValueType::UintPtr,
+                    "uint8" | "byte" => ValueType::Uint8,
+                    "uint16" => ValueType::Uint16,
+                    "uint32" => ValueType::Uint32,
+                    "uint64" => ValueType::Uint64,
+                    "float32" => ValueType::Float32,
+                    "float64" => ValueType::Float64,
+                    "complex64" => ValueType::Complex64,
+                    "complex128" => ValueType::Complex128,
+                    "string" => ValueType::String,
+                    "error" | "any" => ValueType::Interface,
+                    _ if self.is_name(self.pos - 1) => return Ok(None),
+                    _ => return Err(format!("unexpected '{}'", name)),
+                }
+            }
+        };
+        Ok(Some(t))
+    }
+}
+
 /// Used by CodeGen, so that CodeGen can share the API provided by FFI
 pub struct CodeGenVMCtx {
     vm_objs: VMObjects,
@@ -269,6 +910,7 @@ impl CodeGenVMCtx {
             stack: &mut self.dummy_stack,
             gcc: &&self.dummy_gcc,
             array_slice_caller: &self.caller,
+            std_out: None,
         }
     }
 
@@ -340,3 +982,50 @@ impl CodeGenVMCtx {
         GosValue::new_closure_static(func, up_ptrs, meta)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_signature() {
+        use ValueType::*;
+        let parse = |s| FfiSignature::parse(s).map(|x| (x.params, x.results));
+        assert_eq!(
+            parse("func(s string, n int) ([]byte, error)"),
+            Ok((
+                vec![Some(String), Some(Int)],
+                vec![Some(Slice), Some(Interface)]
+            ))
+        );
+        assert_eq!(
+            parse("func(p unsafe.Pointer) (unsafe.Pointer, uint)"),
+            Ok((vec![Some(UnsafePtr)], vec![Some(UnsafePtr), Some(Uint)]))
+        );
+        assert_eq!(
+            parse("func(a, b int, k Kind) (ok bool)"),
+            Ok((vec![Some(Int), Some(Int), None], vec![Some(Bool)]))
+        );
+        assert_eq!(
+            parse("func(map[string]int, [4]byte, *T, func(int) bool, ...interface{})"),
+            Ok((
+                vec![
+                    Some(Map),
+                    Some(Array),
+                    Some(Pointer),
+                    Some(Closure),
+                    Some(Slice)
+                ],
+                vec![]
+            ))
+        );
+        assert_eq!(
+            parse("func(c <-chan int, s struct{ x, y int }) time.Duration"),
+            Ok((vec![Some(Channel), Some(Struct)], vec![None]))
+        );
+        assert!(parse("func(s string").is_err());
+        assert!(parse("fn(s string)").is_err());
+        assert!(parse("func(a, []int, c int)").is_err());
+        assert!(parse("func() int int").is_err());
+    }
+}
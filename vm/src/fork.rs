@@ -0,0 +1,222 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::bytecode::{Bytecode, PackageObjs, VMObjects};
+use crate::dispatcher::{ArrCaller, ElemType};
+use crate::gc::GcContainer;
+use crate::instruction::ValueType;
+use crate::objects::*;
+use crate::value::{GosValue, RuntimeResult};
+use go_parser::{Map, PiggyVec};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Forker makes deep copies of object graphs. Every object reachable from the
+/// values it's given is copied only once, so the copies keep the sharing of the
+/// originals, e.g. two slices into the same array are copied as two slices into
+/// the same new array, and cycles are copied as cycles.
+pub(crate) struct Forker {
+    gcc: GcContainer,
+    // copies of the objects, keyed by the addresses of the originals
+    copies: Map<usize, GosValue>,
+    up_values: Map<usize, UpValue>,
+}
+
+impl Forker {
+    pub(crate) fn new() -> Forker {
+        Forker {
+            gcc: GcContainer::new(),
+            copies: Map::new(),
+            up_values: Map::new(),
+        }
+    }
+
+    pub(crate) fn fork_value(&mut self, val: &GosValue) -> RuntimeResult<GosValue> {
+        if val.is_nil() {
+            return Ok(val.clone());
+        }
+        let addr = match val.typ() {
+            ValueType::Array
+            | ValueType::Struct
+            | ValueType::Slice
+            | ValueType::Map
+            | ValueType::Pointer
+            | ValueType::UnsafePtr
+            | ValueType::Closure
+            | ValueType::Interface
+            | ValueType::Channel => val.as_addr() as usize,
+            // the rest are either copied by value or immutable
+            _ => return Ok(val.clone()),
+        };
+        if let Some(copy) = self.copies.get(&addr) {
+            return Ok(copy.clone());
+        }
+        match val.typ() {
+            ValueType::Array => {
+                let caller = val.caller_slow();
+                if !matches!(
+                    ArrCaller::get_elem_type(val.t_elem()),
+                    ElemType::ElemTypeGos
+                ) {
+                    let copy = val.copy_semantic(&self.gcc);
+                    self.copies.insert(addr, copy.clone());
+                    return Ok(copy);
+                }
+                let elems = caller.array_get_vec(val);
+                // register the copy before copying the elements, they may refer to the array
+                let copy = caller.array_with_data(elems.clone(), &self.gcc);
+                self.copies.insert(addr, copy.clone());
+                for (i, elem) in elems.iter().enumerate() {
+                    caller.array_set(&copy, &self.fork_value(elem)?, i)?;
+                }
+                Ok(copy)
+            }
+            ValueType::Struct => {
+                let fields = val.as_struct().0.borrow_fields().clone();
+                let copy = GosValue::new_struct(StructObj::new(fields.clone()), &self.gcc);
+                self.copies.insert(addr, copy.clone());
+                for (i, field) in fields.iter().enumerate() {
+                    let field = self.fork_value(field)?;
+                    copy.as_struct().0.borrow_fields_mut()[i] = field;
+                }
+                Ok(copy)
+            }
+            ValueType::Slice => {
+                let caller = val.caller_slow();
+                let copy = caller.slice_map_array(val, &mut |array| self.fork_value(array))?;
+                self.copies.insert(addr, copy.clone());
+                Ok(copy)
+            }
+            ValueType::Map => {
+                let copy = GosValue::new_map(&self.gcc);
+                self.copies.insert(addr, copy.clone());
+                let entries: Vec<(GosValue, GosValue)> = val
+                    .as_map()
+                    .unwrap()
+                    .0
+                    .borrow_data()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                for (k, v) in entries.iter() {
+                    let (k, v) = (self.fork_value(k)?, self.fork_value(v)?);
+                    copy.as_map().unwrap().0.insert(k, v);
+                }
+                Ok(copy)
+            }
+            ValueType::Pointer => {
+                let copy = GosValue::new_pointer(self.fork_pointer(val.as_pointer().unwrap())?);
+                self.copies.insert(addr, copy.clone());
+                Ok(copy)
+            }
+            ValueType::UnsafePtr => {
+                let ptr = val.as_unsafe_ptr().unwrap().ptr();
+                if let Some(handle) = ptr.as_any().downcast_ref::<PointerHandle>() {
+                    let p = GosValue::new_pointer(self.fork_pointer(handle.ptr())?);
+                    let copy = PointerHandle::new(&p);
+                    self.copies.insert(addr, copy.clone());
+                    return Ok(copy);
+                }
+                match ptr.clone_for_fork() {
+                    Some(p) => {
+                        let copy = GosValue::new_unsafe_ptr(p);
+                        self.copies.insert(addr, copy.clone());
+                        Ok(copy)
+                    }
+                    None => Err(format!("cannot fork a value of {}", ptr.type_name()).into()),
+                }
+            }
+            ValueType::Closure => match &val.as_closure().unwrap().0 {
+                ClosureObj::Gos(gos) => {
+                    let mut cls = gos.clone();
+                    if let Some(uvs) = &gos.uvs {
+                        let mut copies = Map::new();
+                        for (i, uv) in uvs.iter() {
+                            copies.insert(*i, self.fork_up_value(uv)?);
+                        }
+                        cls.uvs = Some(copies);
+                    }
+                    if let Some(recv) = &gos.recv {
+                        cls.recv = Some(self.fork_value(recv)?);
+                    }
+                    let copy = GosValue::new_closure(ClosureObj::Gos(cls), &self.gcc);
+                    self.copies.insert(addr, copy.clone());
+                    Ok(copy)
+                }
+                // FFI functions are shared
+                ClosureObj::Ffi(_) => Ok(val.clone()),
+            },
+            ValueType::Interface => match val.as_interface().unwrap() {
+                InterfaceObj::Gos(v, meta) => {
+                    let iface = InterfaceObj::Gos(self.fork_value(v)?, meta.clone());
+                    let copy = GosValue::new_interface(iface);
+                    self.copies.insert(addr, copy.clone());
+                    Ok(copy)
+                }
+                // FFI objects are shared
+                InterfaceObj::Ffi(_) => Ok(val.clone()),
+            },
+            ValueType::Channel => Err("cannot fork a channel".to_owned().into()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn fork_pointer(&mut self, ptr: &PointerObj) -> RuntimeResult<PointerObj> {
+        Ok(match ptr {
+            PointerObj::UpVal(uv) => PointerObj::UpVal(self.fork_up_value(uv)?),
+            PointerObj::SliceMember(s, i) => PointerObj::SliceMember(self.fork_value(s)?, *i),
+            PointerObj::StructField(s, i) => PointerObj::StructField(self.fork_value(s)?, *i),
+            // the package members are copied along with the packages
+            PointerObj::PkgMember(p, i) => PointerObj::PkgMember(*p, *i),
+        })
+    }
+
+    fn fork_up_value(&mut self, uv: &UpValue) -> RuntimeResult<UpValue> {
+        let addr = Rc::as_ptr(&uv.inner) as usize;
+        if let Some(copy) = self.up_values.get(&addr) {
+            return Ok(copy.clone());
+        }
+        let state = uv.inner.borrow().clone();
+        let copy = UpValue {
+            inner: Rc::new(RefCell::new(state.clone())),
+        };
+        self.up_values.insert(addr, copy.clone());
+        if let UpValueState::Closed(v) = state {
+            copy.close(self.fork_value(&v)?);
+        }
+        Ok(copy)
+    }
+}
+
+impl Bytecode {
+    /// Returns a copy of the Bytecode to be run independently of the original.
+    /// The package level vars and everything reachable from them are deeply copied,
+    /// the code and the types are cloned as they are.
+    /// It fails if there is a channel or a user data that doesn't support
+    /// `UnsafePtr::clone_for_fork` among the copied values.
+    pub fn fork(&self) -> RuntimeResult<Bytecode> {
+        let mut forker = Forker::new();
+        let mut packages: PackageObjs = PiggyVec::with_capacity(self.objects.packages.vec().len());
+        for pkg in self.objects.packages.vec().iter() {
+            packages.insert(pkg.fork(&mut forker)?);
+        }
+        Ok(Bytecode {
+            objects: VMObjects {
+                metas: self.objects.metas.clone(),
+                functions: self.objects.functions.clone(),
+                packages,
+                prim_meta: self.objects.prim_meta.clone(),
+                type_names: self.objects.type_names.clone(),
+                arr_slice_caller: Box::new(ArrCaller::new()),
+            },
+            consts: self.consts.clone(),
+            ifaces: self.ifaces.clone(),
+            indices: self.indices.clone(),
+            entry: self.entry,
+            main_pkg: self.main_pkg,
+            file_set: self.file_set.clone(),
+            strings: self.strings.clone(),
+        })
+    }
+}
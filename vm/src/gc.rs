@@ -2,49 +2,630 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+//! Reference counting frees everything but the cycles, which are what this
+//! module is for. The arrays, structs, maps and closures, the objects that can
+//! be part of a cycle, are tracked with weak references when they're created.
+//!
+//! The collection is incremental, so that it never stops the program for long.
+//! A cycle starts once enough bytes have been allocated in tracked objects since
+//! the last one ended, and takes the tracked objects at that point as candidates.
+//! It proceeds in slices run by `GcContainer::step` at the yield points of the
+//! goroutines, each one handling candidates until it has traced its share of
+//! objects. The share is counted in objects rather than time, so that what a slice
+//! does doesn't depend on the clock: `SLICE_OBJECTS` for `DEFAULT_GC_MAX_PAUSE`,
+//! and proportionally fewer for a shorter max pause.
+//!
+//! A candidate is handled with trial deletion over its group, the objects reachable
+//! from it. The references the group holds to its own members are subtracted from
+//! their strong counts, the members with any reference left are held from outside,
+//! and everything reachable from them is alive. The rest is garbage only referenced
+//! by itself, whose containers are cleared to free it.
+//!
+//! No write barrier is needed, because nothing runs between tracing a group and
+//! clearing its garbage, a group is always handled within a single slice. What the
+//! program does between slices can only make a group look different, not wrong:
+//! - an object held by anything the group didn't trace, a goroutine stack, a global,
+//!   a value in the hands of the host, has references left and is alive;
+//! - an object too far from the candidate to be traced within the slice is left
+//!   untraced, its references count as coming from outside, which is safe too;
+//! - garbage made after its objects were handled is found by the next cycle,
+//!   objects created during a cycle are only candidates of the next one.
+//!
+//! The price is that a container with more elements than a slice can trace is never
+//! traced by the slices, its cycles are only collected by `GcContainer::collect`.
+
+use super::census::AllocSites;
+use super::dispatcher::{ArrCaller, ElemType};
 use super::instruction::ValueType;
 use super::objects::*;
-use super::value::{GosValue, RCQueue, RCount, IRC};
-use std::cell::Ref;
-use std::cell::RefCell;
-use std::convert::TryFrom;
+use super::value::{GosValue, RCount, IRC};
+use go_parser::Map;
+use std::cell::{Cell, RefCell};
+use std::mem::{size_of, ManuallyDrop};
 use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// The max pause of `RunOptions` if it's not set
+pub const DEFAULT_GC_MAX_PAUSE: Duration = Duration::from_millis(1);
+
+/// How many objects a slice traces at most
+pub const SLICE_OBJECTS: usize = 4096;
+
+/// The fewest bytes allocated in tracked objects that start a cycle, it takes as
+/// many as there were left alive by the previous one if that's more
+const MIN_DEBT: usize = 256 * 1024;
+
+/// What the collector has done so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// The cycles that have been completed
+    pub cycles: usize,
+    /// The slices run at the yield points of the goroutines
+    pub slices: usize,
+    /// The longest slice
+    pub max_pause: Duration,
+    /// The most objects traced by a slice, `SLICE_OBJECTS` at most
+    pub max_slice_objects: usize,
+    /// The time spent in the slices and in `GcContainer::collect`
+    pub total_time: Duration,
+    /// The objects found to be garbage, only referenced by cycles
+    pub freed: usize,
+    /// The most objects tracked at the start of a cycle
+    pub max_tracked: usize,
+}
 
 pub struct GcContainer {
-    inner: Rc<RefCell<Vec<GcWeak>>>,
+    /// The objects created since the current cycle started, and those it left alive
+    inner: RefCell<Vec<GcWeak>>,
+    /// The candidates of the current cycle yet to be handled
+    pending: RefCell<Vec<GcWeak>>,
+    /// Stored in the RCount of the objects traced by the current cycle
+    epoch: Cell<IRC>,
+    /// The bytes allocated in tracked objects since the last cycle ended
+    debt: Cell<usize>,
+    /// The debt that starts the next cycle
+    target: Cell<usize>,
+    /// The bytes of the objects left alive by the current cycle so far
+    survived: Cell<usize>,
+    /// How many objects a slice traces
+    slice_objects: usize,
+    stats: Cell<GcStats>,
+    alloc_sites: Option<Rc<AllocSites>>,
 }
 
 impl GcContainer {
     pub fn new() -> GcContainer {
+        Self::with_max_pause(DEFAULT_GC_MAX_PAUSE)
+    }
+
+    /// A container whose slices trace as many objects as fit in `max_pause`,
+    /// see `slice_objects`
+    pub fn with_max_pause(max_pause: Duration) -> GcContainer {
         GcContainer {
-            inner: Rc::new(RefCell::new(Vec::new())),
+            inner: RefCell::new(Vec::new()),
+            pending: RefCell::new(Vec::new()),
+            epoch: Cell::new(0),
+            debt: Cell::new(0),
+            target: Cell::new(MIN_DEBT),
+            survived: Cell::new(0),
+            slice_objects: slice_objects(max_pause),
+            stats: Cell::new(GcStats::default()),
+            alloc_sites: None,
         }
     }
 
+    /// Records where the tracked objects are allocated in `sites`
+    pub(crate) fn set_alloc_sites(&mut self, sites: Option<Rc<AllocSites>>) {
+        self.alloc_sites = sites;
+    }
+
+    #[inline]
+    pub(crate) fn alloc_sites(&self) -> Option<&AllocSites> {
+        self.alloc_sites.as_deref()
+    }
+
     pub fn add_array(&self, arr: &Rc<(GosArrayObj, RCount)>) {
-        self.add_weak(GcWeak::new_array(arr))
+        let bytes = arr.0.len() * size_of::<GosElem>();
+        self.add_weak(GcWeak::new_array(arr), bytes)
     }
 
     pub fn add_closure(&self, cls: &Rc<(ClosureObj, RCount)>) {
-        self.add_weak(GcWeak::new_closure(cls))
+        let bytes = match &cls.0 {
+            ClosureObj::Gos(c) => c.uvs.as_ref().map_or(0, |x| x.len() * size_of::<UpValue>()),
+            ClosureObj::Ffi(_) => 0,
+        };
+        self.add_weak(GcWeak::new_closure(cls), bytes)
     }
 
     pub fn add_map(&self, m: &Rc<(MapObj, RCount)>) {
-        self.add_weak(GcWeak::new_map(m))
+        let bytes = m.0.len() * 2 * size_of::<GosValue>();
+        self.add_weak(GcWeak::new_map(m), bytes)
     }
 
     pub fn add_struct(&self, s: &Rc<(StructObj, RCount)>) {
-        self.add_weak(GcWeak::new_struct(s))
+        let bytes = s.0.borrow_fields().len() * size_of::<GosValue>();
+        self.add_weak(GcWeak::new_struct(s), bytes)
     }
 
     #[inline]
-    pub(crate) fn add_weak(&self, w: GcWeak) {
+    pub(crate) fn add_weak(&self, w: GcWeak, bytes: usize) {
+        self.debt.set(self.debt.get() + w.size() + bytes);
+        if let Some(sites) = &self.alloc_sites {
+            sites.record(&w);
+        }
         self.inner.borrow_mut().push(w);
     }
 
-    fn borrow_data(&self) -> Ref<Vec<GcWeak>> {
-        self.inner.borrow()
+    pub fn stats(&self) -> GcStats {
+        self.stats.get()
+    }
+
+    /// Runs a slice of the current cycle, or starts a new one if enough has been
+    /// allocated since the last. It's called at the yield points of the goroutines.
+    pub fn step(&self) {
+        if self.pending.borrow().is_empty() {
+            if self.debt.get() < self.target.get() {
+                return;
+            }
+            self.start_cycle();
+        }
+        let start = Instant::now();
+        let mut budget = Budget::new(self.slice_objects);
+        let freed = self.run(&mut budget);
+        let elapsed = start.elapsed();
+        let mut stats = self.stats.get();
+        stats.slices += 1;
+        stats.max_pause = stats.max_pause.max(elapsed);
+        stats.max_slice_objects = stats.max_slice_objects.max(budget.spent);
+        stats.total_time += elapsed;
+        stats.freed += freed;
+        self.stats.set(stats);
+    }
+
+    /// Finishes the current cycle and runs a whole new one, without any bound on
+    /// the pause. It's called when the run ends.
+    pub fn collect(&self) {
+        let start = Instant::now();
+        let unbounded = || Budget::new(usize::MAX);
+        let mut freed = 0;
+        if !self.pending.borrow().is_empty() {
+            freed += self.run(&mut unbounded());
+        }
+        self.start_cycle();
+        freed += self.run(&mut unbounded());
+        let mut stats = self.stats.get();
+        stats.total_time += start.elapsed();
+        stats.freed += freed;
+        self.stats.set(stats);
+    }
+
+    fn start_cycle(&self) {
+        let candidates = std::mem::take(&mut *self.inner.borrow_mut());
+        let mut stats = self.stats.get();
+        stats.max_tracked = stats.max_tracked.max(candidates.len());
+        self.stats.set(stats);
+        *self.pending.borrow_mut() = candidates;
+        // 0 is the RCount of new objects
+        let epoch = match self.epoch.get().wrapping_add(1) {
+            0 => 1,
+            e => e,
+        };
+        self.epoch.set(epoch);
+        self.survived.set(0);
+    }
+
+    fn end_cycle(&self) {
+        self.debt.set(0);
+        self.target.set(self.survived.get().max(MIN_DEBT));
+        let mut stats = self.stats.get();
+        stats.cycles += 1;
+        self.stats.set(stats);
     }
+
+    /// Handles candidates until the budget runs out or the cycle ends, and returns
+    /// how many objects were cleared.
+    fn run(&self, budget: &mut Budget) -> usize {
+        let epoch = self.epoch.get();
+        let mut freed = 0;
+        while !budget.exhausted() {
+            let w = match self.pending.borrow_mut().pop() {
+                Some(w) => w,
+                None => {
+                    self.end_cycle();
+                    break;
+                }
+            };
+            let val = match w.upgrade() {
+                Some(v) => v,
+                None => {
+                    budget.spend(1);
+                    continue;
+                }
+            };
+            // traced already if it's been reached from another candidate
+            if val.rc() != epoch {
+                if budget.fits(width(&val)) {
+                    freed += self.collect_group(val, epoch, budget);
+                } else if budget.spent > 0 {
+                    // the next slice may have room for it
+                    self.pending.borrow_mut().push(w);
+                    break;
+                }
+                // else it's too big for any slice, only `collect` can trace it
+            }
+            if let Some(val) = w.upgrade() {
+                let survived = self.survived.get() + w.size() + elems_size(&val);
+                self.survived.set(survived);
+                self.inner.borrow_mut().push(w);
+            }
+        }
+        freed
+    }
+
+    /// Traces the objects reachable from `seed` within the budget, and clears the
+    /// ones that are only referenced by each other.
+    fn collect_group(&self, seed: GosValue, epoch: IRC, budget: &mut Budget) -> usize {
+        let mut group = Group::default();
+        group.add(Node::Value(seed));
+        let mut next = 0;
+        while next < group.nodes.len() && !budget.exhausted() {
+            let node = &group.nodes[next];
+            // a node left untraced is held from outside as far as the group knows
+            if !budget.fits(node.width()) {
+                next += 1;
+                continue;
+            }
+            if let Node::Value(v) = node {
+                if is_tracked(v.typ()) {
+                    if v.rc() == epoch {
+                        next += 1;
+                        continue;
+                    }
+                    v.set_rc(epoch);
+                }
+            }
+            budget.spend(1 + node.width());
+            // the objects already in the group are not cloned again
+            let mut children: Vec<(usize, Option<Node>)> = vec![];
+            node.children(&mut |t: Target| {
+                let key = t.addr();
+                let node = (!group.index.contains_key(&key)).then(|| t.to_node());
+                children.push((key, node));
+            });
+            let begin = group.edges.len();
+            for (key, node) in children.into_iter() {
+                let i = match group.index.get(&key) {
+                    Some(i) => *i,
+                    None => group.add(node.unwrap()),
+                };
+                group.edges.push(i);
+            }
+            group.traced[next] = Some((begin, group.edges.len()));
+            next += 1;
+        }
+        group.collect()
+    }
+}
+
+/// How many objects a slice traces for the max pause: `SLICE_OBJECTS` for
+/// `DEFAULT_GC_MAX_PAUSE` or longer, proportionally fewer for a shorter one
+fn slice_objects(max_pause: Duration) -> usize {
+    let objects = SLICE_OBJECTS as u128 * max_pause.as_nanos() / DEFAULT_GC_MAX_PAUSE.as_nanos();
+    objects.clamp(1, SLICE_OBJECTS as u128) as usize
+}
+
+impl Default for GcContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How much a slice may still trace
+struct Budget {
+    objects: usize,
+    spent: usize,
+}
+
+impl Budget {
+    fn new(objects: usize) -> Budget {
+        Budget { objects, spent: 0 }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.objects == 0
+    }
+
+    /// If an object and the `width` objects it refers to can be traced
+    fn fits(&self, width: usize) -> bool {
+        width < self.objects
+    }
+
+    fn spend(&mut self, objects: usize) {
+        self.spent += objects;
+        self.objects = self.objects.saturating_sub(objects);
+    }
+}
+
+/// The objects reachable from a candidate, and the references between them
+#[derive(Default)]
+struct Group {
+    nodes: Vec<Node>,
+    index: Map<usize, usize>,
+    /// The range in `edges` of the children of each node, if it has been traced
+    traced: Vec<Option<(usize, usize)>>,
+    edges: Vec<usize>,
+}
+
+impl Group {
+    fn add(&mut self, node: Node) -> usize {
+        let i = self.nodes.len();
+        self.index.insert(node.addr(), i);
+        self.nodes.push(node);
+        self.traced.push(None);
+        i
+    }
+
+    /// Clears the traced nodes not reachable from outside of the group,
+    /// and returns how many there were.
+    fn collect(self) -> usize {
+        let mut internal = vec![0; self.nodes.len()];
+        for i in self.edges.iter() {
+            internal[*i] += 1;
+        }
+        // each node is held once by the group
+        let mut alive: Vec<bool> = self
+            .nodes
+            .iter()
+            .zip(internal.iter())
+            .map(|(n, i)| n.strong_count() != 1 + i)
+            .collect();
+        let mut queue: Vec<usize> = (0..alive.len()).filter(|i| alive[*i]).collect();
+        while let Some(i) = queue.pop() {
+            if let Some((begin, end)) = self.traced[i] {
+                for j in self.edges[begin..end].iter() {
+                    if !alive[*j] {
+                        alive[*j] = true;
+                        queue.push(*j);
+                    }
+                }
+            }
+        }
+        let mut freed = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !alive[i] && self.traced[i].is_some() {
+                node.break_cycle();
+                freed += 1;
+            }
+        }
+        freed
+    }
+}
+
+/// An object that can be shared, each is held once by its group
+#[derive(Clone)]
+pub(crate) enum Node {
+    Value(GosValue),
+    UpValue(UpValue),
+    UnsafePtr(Rc<dyn UnsafePtr>),
+}
+
+impl Node {
+    pub(crate) fn addr(&self) -> usize {
+        match self {
+            Self::Value(v) => Target::Value(v).addr(),
+            Self::UpValue(uv) => Target::UpValue(uv).addr(),
+            Self::UnsafePtr(p) => Target::UnsafePtr(p).addr(),
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Self::Value(v) => width(v),
+            _ => 1,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::Value(v) => match v.typ() {
+                ValueType::Array => rc_strong_count(v.as_gos_array()),
+                ValueType::Struct => rc_strong_count(v.as_struct()),
+                ValueType::Slice => rc_strong_count(v.as_gos_slice().unwrap()),
+                ValueType::Closure => rc_strong_count(v.as_closure().unwrap()),
+                ValueType::Map => rc_strong_count(v.as_map().unwrap()),
+                ValueType::Interface => rc_strong_count(v.as_interface().unwrap()),
+                _ => unreachable!(),
+            },
+            Self::UpValue(uv) => Rc::strong_count(&uv.inner),
+            Self::UnsafePtr(p) => Rc::strong_count(p),
+        }
+    }
+
+    fn children(&self, f: &mut dyn FnMut(Target)) {
+        self.children_with(value_targets, f)
+    }
+
+    /// Calls `targets` with the values the object holds, the arrays and slices must
+    /// hold GosValues
+    pub(crate) fn children_with(&self, targets: TargetsFn, f: &mut dyn FnMut(Target)) {
+        match self {
+            Self::Value(v) => match v.typ() {
+                ValueType::Array => {
+                    for elem in v.as_gos_array().0.borrow_data().iter() {
+                        targets(&elem.borrow(), f);
+                    }
+                }
+                ValueType::Struct => {
+                    for field in v.as_struct().0.borrow_fields().iter() {
+                        targets(field, f);
+                    }
+                }
+                ValueType::Slice => targets(v.as_gos_slice().unwrap().0.array(), f),
+                ValueType::Closure => {
+                    if let ClosureObj::Gos(cls) = &v.as_closure().unwrap().0 {
+                        if let Some(uvs) = &cls.uvs {
+                            for uv in uvs.values() {
+                                f(Target::UpValue(uv));
+                            }
+                        }
+                        if let Some(recv) = &cls.recv {
+                            targets(recv, f);
+                        }
+                    }
+                }
+                ValueType::Map => {
+                    for (k, v) in v.as_map().unwrap().0.borrow_data().iter() {
+                        targets(k, f);
+                        targets(v, f);
+                    }
+                }
+                ValueType::Interface => {
+                    if let InterfaceObj::Gos(v, _) = v.as_interface().unwrap() {
+                        targets(v, f);
+                    }
+                }
+                _ => unreachable!(),
+            },
+            Self::UpValue(uv) => {
+                if let UpValueState::Closed(v) = &*uv.inner.borrow() {
+                    targets(v, f);
+                }
+            }
+            Self::UnsafePtr(p) => p.gc_refs(&mut |v| targets(v, f)),
+        }
+    }
+
+    /// Drops what the object refers to, it's only called on garbage
+    fn break_cycle(&self) {
+        match self {
+            Self::Value(v) => match v.typ() {
+                ValueType::Array => v.as_gos_array().0.borrow_data_mut().clear(),
+                ValueType::Struct => v.as_struct().0.borrow_fields_mut().clear(),
+                ValueType::Map => v.as_map().unwrap().0.borrow_data_mut().clear(),
+                // they're immutable, what they refer to is cleared
+                _ => {}
+            },
+            Self::UpValue(uv) => {
+                let nil = match &*uv.inner.borrow() {
+                    UpValueState::Closed(v) if v.typ() == ValueType::Slice => {
+                        GosValue::new_nil_slice(v.t_elem())
+                    }
+                    UpValueState::Closed(v) if v.nilable() => GosValue::new_nil(v.typ()),
+                    _ => return,
+                };
+                uv.close(nil);
+            }
+            Self::UnsafePtr(p) => {
+                if p.can_make_cycle() {
+                    p.break_cycle();
+                }
+            }
+        }
+    }
+}
+
+/// A shared object found while tracing
+pub(crate) enum Target<'a> {
+    Value(&'a GosValue),
+    UpValue(&'a UpValue),
+    UnsafePtr(&'a Rc<dyn UnsafePtr>),
+}
+
+impl<'a> Target<'a> {
+    pub(crate) fn addr(&self) -> usize {
+        match self {
+            Self::Value(v) => v.as_addr() as usize,
+            Self::UpValue(uv) => Rc::as_ptr(&uv.inner) as *const () as usize,
+            Self::UnsafePtr(p) => Rc::as_ptr(p) as *const () as usize,
+        }
+    }
+
+    pub(crate) fn to_node(&self) -> Node {
+        match self {
+            Self::Value(v) => Node::Value((*v).clone()),
+            Self::UpValue(uv) => Node::UpValue((*uv).clone()),
+            Self::UnsafePtr(p) => Node::UnsafePtr((*p).clone()),
+        }
+    }
+}
+
+/// Finds the shared objects a value refers to, see `value_targets`
+pub(crate) type TargetsFn = fn(&GosValue, &mut dyn FnMut(Target));
+
+/// Calls `f` with the shared objects `val` refers to: itself if it's one of them,
+/// or those a pointer points into, pointers aren't shared.
+pub(crate) fn value_targets(val: &GosValue, f: &mut dyn FnMut(Target)) {
+    match val.typ() {
+        ValueType::Array | ValueType::Slice if !holds_values(val) => {}
+        ValueType::Array
+        | ValueType::Struct
+        | ValueType::Slice
+        | ValueType::Closure
+        | ValueType::Map
+        | ValueType::Interface
+            if !val.as_addr().is_null() =>
+        {
+            f(Target::Value(val))
+        }
+        ValueType::Pointer => match val.as_pointer() {
+            Some(PointerObj::UpVal(uv)) => f(Target::UpValue(uv)),
+            Some(PointerObj::SliceMember(s, _)) | Some(PointerObj::StructField(s, _)) => {
+                value_targets(s, f)
+            }
+            _ => {}
+        },
+        ValueType::UnsafePtr => {
+            if let Some(p) = val.as_unsafe_ptr() {
+                f(Target::UnsafePtr(p.as_rc()))
+            }
+        }
+        _ => {}
+    }
+}
+
+/// If the elements of an array or a slice are GosValues
+#[inline]
+pub(crate) fn holds_values(val: &GosValue) -> bool {
+    matches!(
+        ArrCaller::get_elem_type(val.t_elem()),
+        ElemType::ElemTypeGos
+    )
+}
+
+/// How many references an object holds, roughly
+fn width(val: &GosValue) -> usize {
+    match val.typ() {
+        ValueType::Array => val.as_gos_array().0.len(),
+        ValueType::Struct => val.as_struct().0.borrow_fields().len(),
+        ValueType::Map => val.as_map().map_or(0, |m| m.0.len() * 2),
+        ValueType::Closure => match val.as_closure() {
+            Some((ClosureObj::Gos(cls), _)) => cls.uvs.as_ref().map_or(0, |x| x.len()) + 1,
+            _ => 0,
+        },
+        _ => 1,
+    }
+}
+
+/// The bytes held by a tracked object besides itself, roughly
+fn elems_size(val: &GosValue) -> usize {
+    width(val) * size_of::<GosValue>()
+}
+
+/// The strong count of the Rc `r` is the content of
+#[inline]
+fn rc_strong_count<T>(r: &T) -> usize {
+    // `r` points into an Rc, which isn't dropped here
+    let rc = ManuallyDrop::new(unsafe { Rc::from_raw(r as *const T) });
+    Rc::strong_count(&rc)
+}
+
+/// If the objects of the type are tracked by `GcContainer`
+#[inline]
+fn is_tracked(t: ValueType) -> bool {
+    matches!(
+        t,
+        ValueType::Array | ValueType::Struct | ValueType::Closure | ValueType::Map
+    )
 }
 
 #[derive(Clone)]
@@ -79,164 +660,170 @@ impl GcWeak {
         GcWeak::Struct(Rc::downgrade(s))
     }
 
-    fn to_gosv(&self) -> Option<GosValue> {
+    /// The address of the object, the one `GosValue::as_addr` gives
+    pub(crate) fn addr(&self) -> usize {
         match &self {
-            GcWeak::Array(w) => w.upgrade().map(|v| {
-                v.1.set(i32::try_from(w.strong_count()).unwrap() - 1);
-                GosValue::from_gos_array(v)
-            }),
-            GcWeak::Closure(w) => w.upgrade().map(|v| {
-                v.1.set(i32::try_from(w.strong_count()).unwrap() - 1);
-                GosValue::from_closure(Some(v))
-            }),
-            GcWeak::Map(w) => w.upgrade().map(|v| {
-                v.1.set(i32::try_from(w.strong_count()).unwrap() - 1);
-                GosValue::from_map(Some(v))
-            }),
-            GcWeak::Struct(w) => w.upgrade().map(|v| {
-                v.1.set(i32::try_from(w.strong_count()).unwrap() - 1);
-                GosValue::from_struct(v)
-            }),
-        }
-    }
-}
-
-fn children_ref_sub_one(val: &GosValue) {
-    match val.typ() {
-        ValueType::Array => val
-            .as_gos_array()
-            .0
-            .borrow_data_mut()
-            .iter()
-            .for_each(|obj| obj.ref_sub_one()),
-        ValueType::Closure => match val.as_closure() {
-            Some(cls) => cls.0.ref_sub_one(),
-            None => {}
-        },
-        ValueType::Map => match val.as_map() {
-            Some(m) => m.0.borrow_data().iter().for_each(|(k, v)| {
-                k.ref_sub_one();
-                v.ref_sub_one();
-            }),
-            None => {}
-        },
-        ValueType::Struct => val
-            .as_struct()
-            .0
-            .borrow_fields()
-            .iter()
-            .for_each(|obj| obj.ref_sub_one()),
-        _ => unreachable!(),
-    };
-}
-
-fn children_mark_dirty(val: &GosValue, queue: &mut RCQueue) {
-    match val.typ() {
-        ValueType::Array => val
-            .as_gos_array()
-            .0
-            .borrow_data_mut()
-            .iter()
-            .for_each(|obj| obj.mark_dirty(queue)),
-        ValueType::Closure => match val.as_closure() {
-            Some(cls) => cls.0.mark_dirty(queue),
-            None => {}
-        },
-        ValueType::Map => match val.as_map() {
-            Some(m) => m.0.borrow_data().iter().for_each(|(k, v)| {
-                k.mark_dirty(queue);
-                v.mark_dirty(queue);
-            }),
-            None => {}
-        },
-        ValueType::Struct => val
-            .as_struct()
-            .0
-            .borrow_fields()
-            .iter()
-            .for_each(|obj| obj.mark_dirty(queue)),
-        _ => unreachable!(),
-    };
-}
-
-/// break_cycle clears all values held by containers
-fn break_cycle(val: &GosValue) {
-    match val.typ() {
-        ValueType::Array => val.as_gos_array().0.borrow_data_mut().clear(),
-        ValueType::Map => match val.as_map() {
-            Some(m) => m.0.borrow_data_mut().clear(),
-            None => {}
-        },
-        ValueType::Struct => val.as_struct().0.borrow_fields_mut().clear(),
-        ValueType::Closure => {}
-        _ => unreachable!(),
-    };
-}
-
-/// put the non-zero-rc on the left, and the others on the right
-fn partition_to_scan(to_scan: &mut Vec<GosValue>) -> usize {
-    let len = to_scan.len();
-    if len == 0 {
-        return 0;
+            GcWeak::Array(w) => w.as_ptr() as usize,
+            GcWeak::Closure(w) => w.as_ptr() as usize,
+            GcWeak::Map(w) => w.as_ptr() as usize,
+            GcWeak::Struct(w) => w.as_ptr() as usize,
+        }
     }
-    let mut p0 = 0;
-    let mut p1 = len - 1;
-    loop {
-        while p0 < len - 1 && to_scan[p0].rc() > 0 {
-            p0 += 1;
+
+    pub(crate) fn is_alive(&self) -> bool {
+        match &self {
+            GcWeak::Array(w) => w.strong_count() > 0,
+            GcWeak::Closure(w) => w.strong_count() > 0,
+            GcWeak::Map(w) => w.strong_count() > 0,
+            GcWeak::Struct(w) => w.strong_count() > 0,
         }
-        while p1 > 1 && to_scan[p1].rc() <= 0 {
-            p1 -= 1;
+    }
+
+    fn upgrade(&self) -> Option<GosValue> {
+        match &self {
+            GcWeak::Array(w) => w.upgrade().map(GosValue::from_gos_array),
+            GcWeak::Closure(w) => w.upgrade().map(|v| GosValue::from_closure(Some(v))),
+            GcWeak::Map(w) => w.upgrade().map(|v| GosValue::from_map(Some(v))),
+            GcWeak::Struct(w) => w.upgrade().map(GosValue::from_struct),
         }
-        if p0 >= p1 {
-            break;
+    }
+
+    /// The bytes of the object itself
+    fn size(&self) -> usize {
+        match &self {
+            GcWeak::Array(_) => size_of::<(GosArrayObj, RCount)>(),
+            GcWeak::Closure(_) => size_of::<(ClosureObj, RCount)>(),
+            GcWeak::Map(_) => size_of::<(MapObj, RCount)>(),
+            GcWeak::Struct(_) => size_of::<(StructObj, RCount)>(),
         }
-        to_scan.swap(p0, p1);
     }
-    p0
 }
 
-pub(crate) fn collect(objs: &GcContainer) {
-    let mut to_scan: Vec<GosValue> = objs
-        .borrow_data()
-        .iter()
-        .filter_map(|o| o.to_gosv())
-        .collect();
-    //print!("objs before GC: {}\n", to_scan.len());
-    for v in to_scan.iter() {
-        children_ref_sub_one(v);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_struct(gcc: &GcContainer, fields: usize) -> GosValue {
+        let fields = vec![GosValue::new_nil(ValueType::Interface); fields];
+        GosValue::new_struct(StructObj::new(fields), gcc)
     }
 
-    let boundary = partition_to_scan(&mut to_scan);
-    for i in boundary..to_scan.len() {
-        to_scan[i].set_rc(-(i as IRC));
+    fn set_field(s: &GosValue, i: usize, val: &GosValue) {
+        s.as_struct().0.borrow_fields_mut()[i] = val.clone();
     }
 
-    let mut queue: RCQueue = RCQueue::new();
-    for i in 0..boundary {
-        children_mark_dirty(&to_scan[i], &mut queue);
+    fn tracked(gcc: &GcContainer) -> usize {
+        gcc.inner.borrow().len()
     }
 
-    loop {
-        if let Some(i) = queue.pop_front() {
-            let obj = &to_scan[(-i) as usize];
-            obj.set_rc(666);
-            children_mark_dirty(&obj, &mut queue);
-        } else {
-            break;
-        }
+    #[test]
+    fn test_cycles() {
+        let gcc = GcContainer::new();
+        let a = new_struct(&gcc, 1);
+        let b = new_struct(&gcc, 1);
+        set_field(&a, 0, &b);
+        set_field(&b, 0, &a);
+        let c = new_struct(&gcc, 1);
+        set_field(&c, 0, &c);
+        drop((a, b));
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (2, 1));
+        // held from outside, and what's reachable from it
+        assert!(c.as_struct().0.borrow_fields()[0].as_addr() == c.as_addr());
+        drop(c);
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (3, 0));
     }
 
-    for obj in to_scan.into_iter() {
-        if obj.rc() <= 0 {
-            break_cycle(&obj);
+    #[test]
+    fn test_shared_objects() {
+        // a cycle through an interface and a pointer to a field, which are not tracked
+        let gcc = GcContainer::new();
+        let s = new_struct(&gcc, 2);
+        let iface = GosValue::new_interface(InterfaceObj::Gos(s.clone(), None));
+        set_field(&s, 0, &iface);
+        set_field(
+            &s,
+            1,
+            &GosValue::new_pointer(PointerObj::StructField(s.clone(), 0)),
+        );
+        drop(s);
+        // held by nothing but an interface
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (0, 1));
+        drop(iface);
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (2, 0));
+
+        // a cycle through a closed up value
+        let s = new_struct(&gcc, 1);
+        let uv = UpValue::new_closed(s.clone());
+        set_field(&s, 0, &GosValue::new_pointer(PointerObj::UpVal(uv.clone())));
+        drop(s);
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (2, 1));
+        let weak = uv.downgrade();
+        drop(uv);
+        gcc.collect();
+        assert_eq!((gcc.stats().freed, tracked(&gcc)), (4, 0));
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_slices() {
+        // a ring longer than a slice can trace at once
+        let gcc = GcContainer::new();
+        let ring: Vec<GosValue> = (0..100).map(|_| new_struct(&gcc, 1)).collect();
+        for (i, s) in ring.iter().enumerate() {
+            set_field(s, 0, &ring[(i + 1) % ring.len()]);
         }
+        let head = ring[0].clone();
+        drop(ring);
+        gcc.start_cycle();
+        let mut freed = 0;
+        while !gcc.pending.borrow().is_empty() {
+            freed += gcc.run(&mut Budget::new(10));
+        }
+        // parts of the ring are traced by different slices, each one sees the rest
+        // of it as referenced from outside
+        assert_eq!((freed, tracked(&gcc)), (0, 100));
+
+        // garbage made after it's been traced is left to the next cycle
+        gcc.start_cycle();
+        freed += gcc.run(&mut Budget::new(200));
+        assert_eq!(gcc.pending.borrow().len(), 99);
+        drop(head);
+        freed += gcc.run(&mut Budget::new(1000));
+        assert!(gcc.pending.borrow().is_empty());
+        assert_eq!((freed, tracked(&gcc)), (0, 100));
+        gcc.start_cycle();
+        freed += gcc.run(&mut Budget::new(1000));
+        assert_eq!((freed, tracked(&gcc)), (100, 0));
     }
 
-    let _result: Vec<GosValue> = objs
-        .borrow_data()
-        .iter()
-        .filter_map(|o| o.to_gosv())
-        .collect();
-    //print!("objs left after GC: {}\n", result.len());
+    #[test]
+    fn test_slice_objects() {
+        let ms = Duration::from_millis(1);
+        assert_eq!(slice_objects(ms), SLICE_OBJECTS);
+        assert_eq!(slice_objects(ms / 4), SLICE_OBJECTS / 4);
+        assert_eq!(slice_objects(ms * 100), SLICE_OBJECTS);
+        assert_eq!(slice_objects(Duration::ZERO), 1);
+
+        // a slice never traces more than its share, whatever the objects refer to
+        let gcc = GcContainer::with_max_pause(ms / 64);
+        let objs: Vec<GosValue> = (0..100).map(|_| new_struct(&gcc, 3)).collect();
+        for (i, s) in objs.iter().enumerate() {
+            for j in 0..3 {
+                set_field(s, j, &objs[(i * 7 + j * 13) % objs.len()]);
+            }
+        }
+        drop(objs);
+        gcc.start_cycle();
+        while !gcc.pending.borrow().is_empty() {
+            gcc.step();
+        }
+        let stats = gcc.stats();
+        assert!(stats.slices > 1, "{:?}", stats);
+        assert!(stats.max_slice_objects <= SLICE_OBJECTS / 64, "{:?}", stats);
+    }
 }
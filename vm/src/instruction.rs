@@ -14,6 +14,11 @@ use std::fmt::Debug;
 
 pub type OpIndex = i32;
 
+/// The largest index, count or jump distance an operand can hold, codegen
+/// reports an error for programs that would need more. `OpIndex::MAX` marks an
+/// unused operand.
+pub const MAX_OPERAND: usize = (OpIndex::MAX - 1) as usize;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Opcode {
@@ -36,6 +41,7 @@ pub enum Opcode {
     STORE_POINTER,
     LOAD_UP_VALUE,
     STORE_UP_VALUE,
+    CLOSE_UP_VALUE, // s0 is a local, the closures made after it capture a new one
 
     // arithmetic, logical, ref, arrow
     ADD,            // +
@@ -49,6 +55,7 @@ pub enum Opcode {
     AND_NOT,        // $^
     SHL,            // <<
     SHR,            // >>
+    CONCAT,         // + of more than two strings, s1 of them from s0 on
     ADD_ASSIGN,     // +
     SUB_ASSIGN,     // -
     MUL_ASSIGN,     // *
@@ -62,6 +69,10 @@ pub enum Opcode {
     SHR_ASSIGN,     // >>
     INC,            //++
     DEC,            //--
+    ADD_CHECKED,    // + panics on integer overflow
+    SUB_CHECKED,    // - panics on integer overflow
+    MUL_CHECKED,    // * panics on integer overflow
+    SHL_CHECKED,    // << panics on integer overflow
     UNARY_SUB,      // -
     UNARY_XOR,      // ^
     NOT,            // !
@@ -99,6 +110,7 @@ pub enum Opcode {
     BIND_METHOD,
     BIND_I_METHOD,
     CAST,
+    CAST_CHECKED, // integer conversions that panic if the value changes
     TYPE_ASSERT,
     TYPE,
 
@@ -122,6 +134,11 @@ pub enum Opcode {
     RECOVER, // for built-in function recover
     ASSERT,  // for built-in function assert
     FFI,     // for FFI
+
+    // the specialized forms of hot instructions, see `tiering`, they are never emitted
+    // by the code generator
+    BIND_I_METHOD_CACHED,
+    CALL_CACHED,
 }
 
 impl fmt::Display for Opcode {
@@ -195,6 +212,11 @@ impl ValueType {
         self <= &Self::Package
     }
 
+    #[inline]
+    pub fn integer(&self) -> bool {
+        self >= &Self::Int && self <= &Self::Uint64
+    }
+
     #[inline]
     pub fn comparable(&self) -> bool {
         self <= &Self::Pointer
@@ -280,6 +302,7 @@ impl Instruction {
                 Opcode::STORE_POINTER => 0,
                 Opcode::LOAD_UP_VALUE => cur.d,
                 Opcode::STORE_UP_VALUE => 0,
+                Opcode::CLOSE_UP_VALUE => 0,
                 Opcode::ADD => cur.d,
                 Opcode::SUB => cur.d,
                 Opcode::MUL => cur.d,
@@ -291,6 +314,7 @@ impl Instruction {
                 Opcode::AND_NOT => cur.d,
                 Opcode::SHL => cur.d,
                 Opcode::SHR => cur.d,
+                Opcode::CONCAT => cur.d,
                 Opcode::ADD_ASSIGN => 0,
                 Opcode::SUB_ASSIGN => 0,
                 Opcode::MUL_ASSIGN => 0,
@@ -304,6 +328,10 @@ impl Instruction {
                 Opcode::SHR_ASSIGN => 0,
                 Opcode::INC => 0,
                 Opcode::DEC => 0,
+                Opcode::ADD_CHECKED => cur.d,
+                Opcode::SUB_CHECKED => cur.d,
+                Opcode::MUL_CHECKED => cur.d,
+                Opcode::SHL_CHECKED => cur.d,
                 Opcode::UNARY_SUB => cur.d,
                 Opcode::UNARY_XOR => cur.d,
                 Opcode::NOT => cur.d,
@@ -334,7 +362,7 @@ impl Instruction {
                 Opcode::SELECT => {
                     let begin = i + 1;
                     i += cur.s0 as usize;
-                    instructions[begin..i].iter().fold(0, |acc, x| {
+                    instructions[begin..=i].iter().fold(0, |acc, x| {
                         let val = match x.t0 {
                             ValueType::FlagC => x.s1,
                             ValueType::FlagD => x.s1 + 1,
@@ -352,11 +380,16 @@ impl Instruction {
                 Opcode::BIND_METHOD => cur.d,
                 Opcode::BIND_I_METHOD => cur.d,
                 Opcode::CAST => cur.d,
+                Opcode::CAST_CHECKED => cur.d,
                 Opcode::TYPE_ASSERT => match cur.t1 {
                     ValueType::FlagB => {
                         i += 1;
                         instructions[i].d
                     }
+                    ValueType::FlagC => {
+                        i += 1;
+                        cur.d
+                    }
                     _ => cur.d,
                 },
                 Opcode::TYPE => match cur.t0 {
@@ -369,8 +402,9 @@ impl Instruction {
                     cur.d
                 }
                 Opcode::CLOSURE => cur.d,
+                // s1 is the number of elements, only the metadata follows
                 Opcode::LITERAL => {
-                    i += 1 + cur.s1 as usize;
+                    i += 1;
                     cur.d
                 }
                 Opcode::NEW => cur.d,
@@ -393,6 +427,8 @@ impl Instruction {
                 Opcode::RECOVER => cur.d,
                 Opcode::ASSERT => 0,
                 Opcode::FFI => cur.d,
+                Opcode::BIND_I_METHOD_CACHED => cur.d,
+                Opcode::CALL_CACHED => 0,
             };
             result = std::cmp::max(result, index);
             i += 1;
@@ -0,0 +1,90 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::bytecode::Bytecode;
+use crate::instruction::ValueType;
+use crate::value::GosValue;
+use go_parser::Map;
+
+/// The strings not longer than this are interned by default
+pub const DEFAULT_MAX_INTERNED_LEN: usize = 32;
+
+/// StringPool holds the interned strings of a Bytecode.
+///
+/// An interned string caches its hash, and two interned strings from the same
+/// pool are equal only if they are the same object, which makes them cheap to
+/// use as map keys and switch labels. Strings made at runtime are not interned,
+/// except that a map key is replaced with its interned copy if there is one.
+/// Interning never changes the semantics: an interned string and a non-interned
+/// one with the same content are equal and have the same hash.
+#[derive(Clone, Debug, Default)]
+pub struct StringPool {
+    max_len: usize,
+    strings: Map<String, GosValue>,
+}
+
+impl StringPool {
+    #[inline]
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns the interned copy of `val` if it's a string in the pool,
+    /// otherwise returns `val`.
+    #[inline]
+    pub fn get(&self, val: GosValue) -> GosValue {
+        if self.strings.is_empty() || val.typ() != ValueType::String {
+            return val;
+        }
+        let s = val.as_string();
+        if s.is_interned() || s.len() > self.max_len {
+            return val;
+        }
+        let found = self.strings.get(&*s.as_str()).cloned();
+        found.unwrap_or(val)
+    }
+
+    fn intern(&mut self, val: &GosValue) {
+        let s = val.as_string();
+        if s.len() > self.max_len {
+            return;
+        }
+        let key = s.as_str().to_string();
+        self.strings.entry(key).or_insert_with(|| {
+            s.set_interned();
+            val.clone()
+        });
+    }
+}
+
+impl Bytecode {
+    /// Interns the string constants not longer than `max_len`, which include
+    /// all the string literals used as map keys and switch case labels.
+    /// `Engine` does it after compiling, a deserialized Bytecode needs to do it
+    /// again for the strings to be interned.
+    pub fn intern_strings(&mut self, max_len: usize) {
+        let mut pool = StringPool {
+            max_len,
+            strings: Map::new(),
+        };
+        if max_len > 0 {
+            for c in self.consts.iter() {
+                if c.typ() == ValueType::String {
+                    pool.intern(c);
+                }
+            }
+        }
+        self.strings = pool;
+    }
+}
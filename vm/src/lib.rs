@@ -9,6 +9,8 @@
 //! - `btree_map`: Make it use BTreeMap instead of HashMap
 //! - `instruction_pos`: Add instruction position to bytecode for debugging
 //! - `serde_borsh`: Serde support for bytecode using Borsh
+//! - `stack_check`: Check the types of the operands of the instructions as they run,
+//!   to catch the instructions emitted with the wrong types
 
 mod instruction;
 #[macro_use]
@@ -20,11 +22,19 @@ mod objects;
 mod dispatcher;
 mod bytecode;
 mod ffi;
+mod fork;
+mod intern;
 mod stack;
+#[cfg(feature = "stack_check")]
+mod stack_check;
+mod tiering;
+mod tuple;
 mod value;
 mod vm;
 
+pub mod census;
 pub mod gc;
+pub mod value_ops;
 pub mod types {
     pub use crate::value::*;
 }
@@ -34,14 +44,23 @@ pub mod parser {
 }
 
 pub use {
+    census::{census, AllocSites, CensusEntry},
     ffi::*,
+    gc::{GcStats, DEFAULT_GC_MAX_PAUSE, SLICE_OBJECTS},
     go_parser::{Map, MapIter},
     go_pmacro::{ffi_impl, Ffi, UnsafePtr},
+    intern::{StringPool, DEFAULT_MAX_INTERNED_LEN},
+    tuple::{ResultTuple, TupleDesc, TupleError},
     value::Bytecode,
-    vm::run,
-    vm::PanicData,
+    vm::{
+        call, run, run_entry, run_with, BlockReason, GoroutineData, GrowthCount, GrowthStats,
+        IdleHook, PanicData, PanicKind, RunOptions, RunResult, StdOutSink, DEFAULT_FIBER_POOL_SIZE,
+    },
 };
 
+#[cfg(feature = "async")]
+pub use channel::{HostChannel, TryRecvError};
+
 pub struct CallStackDisplay<'a> {
     panic_data: &'a PanicData,
     bc: &'a Bytecode,
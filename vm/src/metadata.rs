@@ -5,7 +5,7 @@
 use crate::bytecode::{FunctionKey, MetadataKey, MetadataObjs, VMObjects};
 use crate::gc::GcContainer;
 use crate::instruction::{OpIndex, ValueType};
-use crate::objects::{IfaceBinding, StructObj};
+use crate::objects::{Binding4Runtime, IfaceBinding, StructObj};
 use crate::value::ArrCaller;
 use crate::value::GosValue;
 #[cfg(feature = "serde_borsh")]
@@ -25,7 +25,7 @@ pub enum ChannelType {
     SendRecv,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PrimitiveMeta {
     pub mbool: Meta,
     pub mint: Meta,
@@ -241,7 +241,7 @@ impl Meta {
                 MetadataType::Int16 => 0i16.into(),
                 MetadataType::Int32 => 0i32.into(),
                 MetadataType::Int64 => 0i64.into(),
-                MetadataType::Uint => 0isize.into(),
+                MetadataType::Uint => 0usize.into(),
                 MetadataType::UintPtr => GosValue::new_uint_ptr(0),
                 MetadataType::Uint8 => 0u8.into(),
                 MetadataType::Uint16 => 0u16.into(),
@@ -261,8 +261,14 @@ impl Meta {
                 }
                 MetadataType::Slice(m) => GosValue::new_nil_slice(m.value_type(mobjs)),
                 MetadataType::Struct(f) => {
-                    let field_zeros: Vec<GosValue> =
-                        f.fields.iter().map(|x| x.meta.zero(mobjs, gcc)).collect();
+                    // the promoted fields are in the embedded ones, through a pointer
+                    // they may be of the struct itself
+                    let field_zeros: Vec<GosValue> = f
+                        .fields
+                        .iter()
+                        .filter(|x| x.embedded_indices.is_none())
+                        .map(|x| x.meta.zero(mobjs, gcc))
+                        .collect();
                     let struct_val = StructObj::new(field_zeros);
                     GosValue::new_struct(struct_val, gcc)
                 }
@@ -326,7 +332,7 @@ impl Meta {
     }
 
     /// Depth-first search for method by name
-    fn get_iface_binding(&self, name: &String, metas: &MetadataObjs) -> Option<IfaceBinding> {
+    fn get_iface_binding(&self, name: &str, metas: &MetadataObjs) -> Option<IfaceBinding> {
         match &metas[self.key] {
             MetadataType::Named(m, underlying) => match m.mapping.get(name) {
                 Some(&i) => Some(IfaceBinding::Struct(m.members[i as usize].clone(), None)),
@@ -336,15 +342,17 @@ impl Meta {
                 .try_index_by_name(name)
                 .map(|x| IfaceBinding::Iface(x, None)),
             MetadataType::Struct(fields) => {
-                for (i, f) in fields.fields.iter().enumerate() {
+                let declared = fields.fields.iter().enumerate();
+                for (i, f) in declared.filter(|(_, f)| f.embedded && f.embedded_indices.is_none()) {
                     if let Some(mut re) = f.meta.get_iface_binding(name, metas) {
                         let indices = match &mut re {
                             IfaceBinding::Struct(_, indices) | IfaceBinding::Iface(_, indices) => {
                                 indices
                             }
                         };
+                        // the path runs from the outermost field in
                         if let Some(x) = indices {
-                            x.push(i as OpIndex)
+                            x.insert(0, i as OpIndex)
                         } else {
                             *indices = Some(vec![i as OpIndex]);
                         }
@@ -357,6 +365,22 @@ impl Meta {
         }
     }
 
+    /// Whether the path of embedded fields `indices` goes through a pointer, which
+    /// puts the pointer receiver methods promoted along it in the method set of a value
+    fn embeds_pointer(&self, indices: &[OpIndex], metas: &MetadataObjs) -> bool {
+        let mut cur = *self;
+        for &i in indices.iter() {
+            cur = match &metas[cur.underlying(metas).key] {
+                MetadataType::Struct(fields) => fields.fields[i as usize].meta,
+                _ => return false,
+            };
+            if cur.ptr_depth > 0 {
+                return true;
+            }
+        }
+        false
+    }
+
     #[inline]
     pub fn get_method(&self, index: OpIndex, metas: &MetadataObjs) -> Rc<RefCell<MethodDesc>> {
         let k = self.recv_meta_key();
@@ -368,7 +392,162 @@ impl Meta {
     }
 
     pub fn identical(&self, other: &Self, metas: &MetadataObjs) -> bool {
-        (self.key == other.key) || metas[self.key].identical(&metas[other.key], metas)
+        self.ptr_depth == other.ptr_depth
+            && ((self.key == other.key) || metas[self.key].identical(&metas[other.key], metas))
+    }
+
+    /// Whether `==` is defined on the type, and its values can be map keys. Only
+    /// the values an interface holds are looked at at run time, the checker takes
+    /// care of the rest.
+    pub fn comparable(&self, metas: &MetadataObjs) -> bool {
+        if self.ptr_depth > 0 {
+            return true;
+        }
+        match self.mtype_unwraped(metas) {
+            MetadataType::Slice(_) | MetadataType::Map(_, _) | MetadataType::Signature(_) => false,
+            MetadataType::Array(elem, _) => elem.comparable(metas),
+            MetadataType::Struct(fields) => fields.infos().iter().all(|f| f.meta.comparable(metas)),
+            _ => true,
+        }
+    }
+
+    /// The type the way Go writes it, like `*main.T` or `map[string]int`, with the
+    /// names of the named types from `objs.type_names`
+    pub fn type_string(&self, objs: &VMObjects) -> String {
+        let mut s = "*".repeat(self.ptr_depth as usize);
+        let metas = &objs.metas;
+        let join = |ms: &mut dyn Iterator<Item = String>| ms.collect::<Vec<_>>().join(", ");
+        let sig = |sig: &SigMetadata| {
+            let mut params: Vec<String> = sig.params.iter().map(|x| x.type_string(objs)).collect();
+            if let Some((_, elem)) = &sig.variadic {
+                *params.last_mut().unwrap() = format!("...{}", elem.type_string(objs));
+            }
+            let results = join(&mut sig.results.iter().map(|x| x.type_string(objs)));
+            match sig.results.len() {
+                0 => format!("({})", params.join(", ")),
+                1 => format!("({}) {}", params.join(", "), results),
+                _ => format!("({}) ({})", params.join(", "), results),
+            }
+        };
+        let t = match &metas[self.key] {
+            MetadataType::Bool => "bool".to_owned(),
+            MetadataType::Int => "int".to_owned(),
+            MetadataType::Int8 => "int8".to_owned(),
+            MetadataType::Int16 => "int16".to_owned(),
+            MetadataType::Int32 => "int32".to_owned(),
+            MetadataType::Int64 => "int64".to_owned(),
+            MetadataType::Uint => "uint".to_owned(),
+            MetadataType::UintPtr => "uintptr".to_owned(),
+            MetadataType::Uint8 => "uint8".to_owned(),
+            MetadataType::Uint16 => "uint16".to_owned(),
+            MetadataType::Uint32 => "uint32".to_owned(),
+            MetadataType::Uint64 => "uint64".to_owned(),
+            MetadataType::Float32 => "float32".to_owned(),
+            MetadataType::Float64 => "float64".to_owned(),
+            MetadataType::Complex64 => "complex64".to_owned(),
+            MetadataType::Complex128 => "complex128".to_owned(),
+            MetadataType::UnsafePtr => "unsafe.Pointer".to_owned(),
+            MetadataType::Str => "string".to_owned(),
+            MetadataType::Array(m, size) => format!("[{}]{}", size, m.type_string(objs)),
+            MetadataType::Slice(m) => format!("[]{}", m.type_string(objs)),
+            MetadataType::Map(k, v) => {
+                format!("map[{}]{}", k.type_string(objs), v.type_string(objs))
+            }
+            MetadataType::Channel(typ, m) => {
+                let dir = match typ {
+                    ChannelType::Send => "chan<- ",
+                    ChannelType::Recv => "<-chan ",
+                    ChannelType::SendRecv => "chan ",
+                };
+                format!("{}{}", dir, m.type_string(objs))
+            }
+            MetadataType::Struct(fields) if fields.infos().is_empty() => "struct {}".to_owned(),
+            MetadataType::Struct(fields) => {
+                let fields = fields.infos().iter().map(|f| match f.embedded {
+                    true => f.meta.type_string(objs),
+                    false => format!("{} {}", f.name, f.meta.type_string(objs)),
+                });
+                format!("struct {{ {} }}", fields.collect::<Vec<_>>().join("; "))
+            }
+            MetadataType::Interface(fields) if fields.infos().is_empty() => {
+                "interface {}".to_owned()
+            }
+            MetadataType::Interface(fields) => {
+                let methods = fields
+                    .infos()
+                    .iter()
+                    .map(|f| format!("{}{}", f.name, sig(metas[f.meta.key].as_signature())));
+                format!("interface {{ {} }}", methods.collect::<Vec<_>>().join("; "))
+            }
+            MetadataType::Signature(s) => format!("func{}", sig(s)),
+            // not the underlying type without the name, which could be recursive
+            MetadataType::Named(_, _) => match objs.type_names.get(&self.key) {
+                Some(name) => name.clone(),
+                None => "<named type>".to_owned(),
+            },
+            MetadataType::None => "nil".to_owned(),
+        };
+        s.push_str(&t);
+        s
+    }
+
+    /// The bindings of the methods of the interface `self` to the ones of the
+    /// dynamic type `typ`, `None` if `typ` doesn't implement it. Like in the
+    /// method sets of Go, a value doesn't have the methods with pointer receivers.
+    pub fn iface_bindings_of(&self, typ: &Meta, objs: &VMObjects) -> Option<Vec<Binding4Runtime>> {
+        self.iface_bindings_or_missing(typ, objs).ok()
+    }
+
+    /// Like `iface_bindings_of`, with the name of the first method `typ` doesn't
+    /// have if it doesn't implement the interface
+    pub fn iface_bindings_or_missing<'a>(
+        &self,
+        typ: &Meta,
+        objs: &'a VMObjects,
+    ) -> Result<Vec<Binding4Runtime>, &'a str> {
+        let metas = &objs.metas;
+        let methods = match &metas[self.underlying(metas).key] {
+            MetadataType::Interface(fields) => fields.infos(),
+            _ => return Err(""),
+        };
+        if methods.is_empty() {
+            return Ok(vec![]);
+        }
+        let pointee_is_iface = matches!(typ.mtype_unwraped(metas), MetadataType::Interface(_));
+        if typ.ptr_depth > 1 || (typ.ptr_depth == 1 && pointee_is_iface) {
+            return Err(&methods[0].name);
+        }
+        methods
+            .iter()
+            .map(|m| {
+                let binding = match typ.get_iface_binding(&m.name, metas) {
+                    Some(IfaceBinding::Struct(desc, indices)) => {
+                        let desc = desc.borrow();
+                        match desc.func {
+                            // a value only has the pointer receiver methods reached
+                            // through an embedded pointer
+                            Some(func)
+                                if !(desc.pointer_recv
+                                    && typ.ptr_depth == 0
+                                    && !indices
+                                        .as_ref()
+                                        .is_some_and(|x| typ.embeds_pointer(x, metas))) =>
+                            {
+                                let sig = metas[objs.functions[func].meta.key].as_signature();
+                                sig.identical_params(metas[m.meta.key].as_signature(), metas)
+                                    .then(|| {
+                                        Binding4Runtime::Struct(func, desc.pointer_recv, indices)
+                                    })
+                            }
+                            _ => None,
+                        }
+                    }
+                    Some(b) => Some(b.into()),
+                    None => None,
+                };
+                binding.ok_or(m.name.as_str())
+            })
+            .collect()
     }
 
     pub fn bind_with_iface(
@@ -397,6 +576,8 @@ pub struct FieldInfo {
     pub name: String,
     pub tag: Option<String>,
     //pub exported: bool,
+    /// Whether the field is embedded, only the methods of those are promoted
+    pub embedded: bool,
     pub embedded_indices: Option<Vec<usize>>,
 }
 
@@ -525,6 +706,8 @@ impl Fields {
         for (i, f) in self.fields.iter().enumerate() {
             let other_f = &other.fields[i];
             let ok = f.name == other_f.name
+                && f.tag == other_f.tag
+                && f.embedded == other_f.embedded
                 && f.embedded_indices == other_f.embedded_indices
                 && f.meta.identical(&other_f.meta, metas);
             if !ok {
@@ -533,6 +716,21 @@ impl Fields {
         }
         true
     }
+
+    /// Like `identical`, for the methods of interfaces, which are a set
+    /// and so can be in any order
+    pub fn identical_methods(&self, other: &Self, metas: &MetadataObjs) -> bool {
+        if self.fields.len() != other.fields.len() {
+            return false;
+        }
+        self.fields.iter().all(|f| {
+            other
+                .fields
+                .iter()
+                .find(|x| x.name == f.name)
+                .is_some_and(|x| f.meta.identical(&x.meta, metas))
+        })
+    }
 }
 
 #[cfg_attr(feature = "serde_borsh", derive(BorshDeserialize, BorshSerialize))]
@@ -610,13 +808,15 @@ impl SigMetadata {
     }
 
     pub fn identical(&self, other: &Self, metas: &MetadataObjs) -> bool {
-        if !match (&self.recv, &other.recv) {
-            (None, None) => true,
-            (Some(a), Some(b)) => a.identical(b, metas),
+        match (&self.recv, &other.recv) {
+            (None, None) => self.identical_params(other, metas),
+            (Some(a), Some(b)) => a.identical(b, metas) && self.identical_params(other, metas),
             _ => false,
-        } {
-            return false;
         }
+    }
+
+    /// Like `identical`, without comparing the receivers.
+    pub fn identical_params(&self, other: &Self, metas: &MetadataObjs) -> bool {
         if self.params.len() != other.params.len() {
             return false;
         }
@@ -765,6 +965,8 @@ impl MetadataType {
             (Self::Int16, Self::Int16) => true,
             (Self::Int32, Self::Int32) => true,
             (Self::Int64, Self::Int64) => true,
+            (Self::Uint, Self::Uint) => true,
+            (Self::UintPtr, Self::UintPtr) => true,
             (Self::Uint8, Self::Uint8) => true,
             (Self::Uint16, Self::Uint16) => true,
             (Self::Uint32, Self::Uint32) => true,
@@ -773,6 +975,7 @@ impl MetadataType {
             (Self::Float64, Self::Float64) => true,
             (Self::Complex64, Self::Complex64) => true,
             (Self::Complex128, Self::Complex128) => true,
+            (Self::UnsafePtr, Self::UnsafePtr) => true,
             (Self::Str, Self::Str) => true,
             (Self::Struct(a), Self::Struct(b)) => a.identical(b, metas),
             (Self::Signature(a), Self::Signature(b)) => a.identical(b, metas),
@@ -783,12 +986,188 @@ impl MetadataType {
             (Self::Map(ak, av), Self::Map(bk, bv)) => {
                 ak.identical(bk, metas) && av.identical(bv, metas)
             }
-            (Self::Interface(a), Self::Interface(b)) => a.identical(b, metas),
+            (Self::Interface(a), Self::Interface(b)) => a.identical_methods(b, metas),
             (Self::Channel(at, avt), Self::Channel(bt, bvt)) => {
                 at == bt && avt.identical(bvt, metas)
             }
-            (Self::Named(_, a), Self::Named(_, b)) => a.identical(b, metas),
+            // a named type is only identical to itself, which has the same key, and
+            // as every recursive type goes through a named one, this doesn't loop
             _ => false,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn field(meta: Meta, name: &str, tag: Option<&str>) -> FieldInfo {
+        FieldInfo {
+            meta,
+            name: name.to_owned(),
+            tag: tag.map(|x| x.to_owned()),
+            embedded: false,
+            embedded_indices: None,
+        }
+    }
+
+    // a new key for every type, so that identical can't go by the keys
+    fn new_type(t: MetadataType, metas: &mut MetadataObjs) -> Meta {
+        Meta::with_type(t, metas)
+    }
+
+    #[test]
+    fn test_identical_basic() {
+        let objs = &mut VMObjects::new();
+        let metas = &mut objs.metas;
+        let kinds = [
+            MetadataType::Uint,
+            MetadataType::UintPtr,
+            MetadataType::UnsafePtr,
+            MetadataType::Str,
+        ];
+        for k in kinds.iter() {
+            let (a, b) = (new_type(k.clone(), metas), new_type(k.clone(), metas));
+            assert!(a.identical(&b, metas), "{:?}", k);
+        }
+        let (i, u) = (objs.prim_meta.mint, objs.prim_meta.muint);
+        assert!(!i.identical(&u, metas));
+        // pointers have to be just as deep
+        assert!(i.ptr_to().identical(&i.ptr_to(), metas));
+        assert!(!i.ptr_to().identical(&i, metas));
+        assert!(!i.ptr_to().ptr_to().identical(&i.ptr_to(), metas));
+    }
+
+    #[test]
+    fn test_identical_composite() {
+        let objs = &mut VMObjects::new();
+        let (i, s) = (objs.prim_meta.mint, objs.prim_meta.mstr);
+        let metas = &mut objs.metas;
+        let (a, b, c) = (
+            Meta::new_slice(i, metas),
+            Meta::new_slice(i, metas),
+            Meta::new_slice(s, metas),
+        );
+        assert!(a.identical(&b, metas));
+        assert!(!a.identical(&c, metas));
+
+        let (a, b, c) = (
+            Meta::new_array(i, 3, metas),
+            Meta::new_array(i, 3, metas),
+            Meta::new_array(i, 4, metas),
+        );
+        assert!(a.identical(&b, metas));
+        assert!(!a.identical(&c, metas));
+
+        let (a, b, c) = (
+            Meta::new_map(s, i, metas),
+            Meta::new_map(s, i, metas),
+            Meta::new_map(i, s, metas),
+        );
+        assert!(a.identical(&b, metas));
+        assert!(!a.identical(&c, metas));
+
+        let (a, b, c) = (
+            Meta::new_channel(ChannelType::Recv, i, metas),
+            Meta::new_channel(ChannelType::Recv, i, metas),
+            Meta::new_channel(ChannelType::SendRecv, i, metas),
+        );
+        assert!(a.identical(&b, metas));
+        assert!(!a.identical(&c, metas));
+    }
+
+    #[test]
+    fn test_identical_signature() {
+        let objs = &mut VMObjects::new();
+        let (i, s) = (objs.prim_meta.mint, objs.prim_meta.mstr);
+        let metas = &mut objs.metas;
+        let a = Meta::new_sig(None, vec![i, s], vec![i], None, metas);
+        let b = Meta::new_sig(None, vec![i, s], vec![i], None, metas);
+        assert!(a.identical(&b, metas));
+        let c = Meta::new_sig(None, vec![s, i], vec![i], None, metas);
+        assert!(!a.identical(&c, metas));
+        let d = Meta::new_sig(None, vec![i, s], vec![], None, metas);
+        assert!(!a.identical(&d, metas));
+
+        let ss = Meta::new_slice(s, metas);
+        let e = Meta::new_sig(None, vec![i, ss], vec![i], Some((ss, s)), metas);
+        let f = Meta::new_sig(None, vec![i, ss], vec![i], Some((ss, s)), metas);
+        let g = Meta::new_sig(None, vec![i, ss], vec![i], None, metas);
+        assert!(e.identical(&f, metas));
+        assert!(!e.identical(&g, metas));
+    }
+
+    #[test]
+    fn test_identical_struct_and_interface() {
+        let objs = &mut VMObjects::new();
+        let (i, s) = (objs.prim_meta.mint, objs.prim_meta.mstr);
+        let st = |fields: Vec<FieldInfo>, metas: &mut MetadataObjs| {
+            new_type(MetadataType::Struct(Fields::new(fields)), metas)
+        };
+        let metas = &mut objs.metas;
+        let a = st(
+            vec![field(i, "a", None), field(s, "b", Some("json"))],
+            metas,
+        );
+        let b = st(
+            vec![field(i, "a", None), field(s, "b", Some("json"))],
+            metas,
+        );
+        assert!(a.identical(&b, metas));
+        // the names, the tags and the order count
+        let c = st(
+            vec![field(i, "a", None), field(s, "c", Some("json"))],
+            metas,
+        );
+        let d = st(vec![field(i, "a", None), field(s, "b", None)], metas);
+        let e = st(
+            vec![field(s, "b", Some("json")), field(i, "a", None)],
+            metas,
+        );
+        assert!(!a.identical(&c, metas));
+        assert!(!a.identical(&d, metas));
+        assert!(!a.identical(&e, metas));
+
+        let m1 = Meta::new_sig(None, vec![], vec![i], None, metas);
+        let m2 = Meta::new_sig(None, vec![s], vec![], None, metas);
+        let iface = |methods: Vec<FieldInfo>, metas: &mut MetadataObjs| {
+            Meta::new_interface(Fields::new(methods), metas)
+        };
+        let x = iface(vec![field(m1, "Len", None), field(m2, "Set", None)], metas);
+        let y = iface(vec![field(m2, "Set", None), field(m1, "Len", None)], metas);
+        let z = iface(vec![field(m1, "Len", None), field(m1, "Set", None)], metas);
+        let w = iface(vec![field(m1, "Len", None)], metas);
+        // the methods are a set
+        assert!(x.identical(&y, metas));
+        assert!(!x.identical(&z, metas));
+        assert!(!x.identical(&w, metas));
+    }
+
+    #[test]
+    fn test_identical_named() {
+        let objs = &mut VMObjects::new();
+        let i = objs.prim_meta.mint;
+        let metas = &mut objs.metas;
+        let a = Meta::new_named(i, metas);
+        let b = Meta::new_named(i, metas);
+        assert!(a.identical(&a, metas));
+        assert!(!a.identical(&b, metas));
+        assert!(!a.identical(&i, metas));
+
+        // type node struct { next *node }, twice
+        let recursive = |metas: &mut MetadataObjs| {
+            let named = Meta::new_named(i, metas);
+            let fields = Fields::new(vec![field(named.ptr_to(), "next", None)]);
+            let underlying = new_type(MetadataType::Struct(fields), metas);
+            metas[named.key] = MetadataType::Named(Methods::new(), underlying);
+            (named, underlying)
+        };
+        let (n1, u1) = recursive(metas);
+        let (n2, u2) = recursive(metas);
+        assert!(n1.identical(&n1, metas));
+        assert!(!n1.identical(&n2, metas));
+        assert!(!u1.identical(&u2, metas));
+        let u3 = new_type(metas[u1.key].clone(), metas);
+        assert!(u1.identical(&u3, metas));
+    }
+}
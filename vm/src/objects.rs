@@ -7,11 +7,13 @@
 #[cfg(feature = "async")]
 use crate::channel::Channel;
 use crate::ffi::Ffi;
+use crate::fork::Forker;
 use crate::gc::GcContainer;
 use crate::instruction::{Instruction, OpIndex, ValueType};
 use crate::metadata::*;
 use crate::stack::Stack;
 use crate::value::*;
+use crate::value_ops;
 
 #[cfg(feature = "serde_borsh")]
 use borsh::{
@@ -55,6 +57,29 @@ impl MapObj {
         }
     }
 
+    /// A map with room for `cap` entries before it has to rehash, for `make(map[K]V, cap)`
+    #[inline]
+    pub fn with_capacity(cap: usize) -> MapObj {
+        #[cfg(not(feature = "btree_map"))]
+        let data = Map::with_capacity(cap);
+        #[cfg(feature = "btree_map")]
+        let data = {
+            let _ = cap;
+            Map::new()
+        };
+        Self::with_data(data)
+    }
+
+    /// The number of entries the map can hold without rehashing,
+    /// a btree map never rehashes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        #[cfg(not(feature = "btree_map"))]
+        return self.borrow_data().capacity();
+        #[cfg(feature = "btree_map")]
+        return usize::MAX;
+    }
+
     #[inline]
     pub fn insert(&self, key: GosValue, val: GosValue) -> Option<GosValue> {
         self.borrow_data_mut().insert(key, val)
@@ -130,6 +155,15 @@ impl Display for MapObj {
 // ----------------------------------------------------------------------------
 // ArrayObj
 
+/// The panic of an index not less than `len`, a negative one was cast to usize
+fn index_out_of_range(i: usize, len: usize) -> RuntimeError {
+    match i as isize {
+        i if i < 0 => format!("index out of range [{}]", i),
+        i => format!("index out of range [{}] with length {}", i, len),
+    }
+    .into()
+}
+
 /// Element is used to store GosValue in Typed containers to save memomry
 pub trait Element: Clone + Hash + Debug {
     fn from_value(val: GosValue) -> Self;
@@ -145,6 +179,11 @@ pub trait Element: Clone + Hash + Debug {
     fn copy_or_clone_slice(dst: &mut [Self], src: &[Self]) {
         dst.clone_from_slice(src)
     }
+
+    /// A copy that doesn't share the arrays and structs in it, see `GosValue::copy_semantic`
+    fn copy_semantic(&self, _gcc: &GcContainer) -> Self {
+        self.clone()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
@@ -153,16 +192,6 @@ pub struct GosElem {
 }
 
 impl GosElem {
-    /// for gc
-    pub fn ref_sub_one(&self) {
-        self.cell.borrow().ref_sub_one();
-    }
-
-    /// for gc
-    pub fn mark_dirty(&self, queue: &mut RCQueue) {
-        self.cell.borrow().mark_dirty(queue);
-    }
-
     pub fn borrow(&self) -> Ref<GosValue> {
         self.cell.borrow()
     }
@@ -192,6 +221,13 @@ impl Element for GosElem {
         }
     }
 
+    #[inline]
+    fn copy_semantic(&self, gcc: &GcContainer) -> Self {
+        GosElem {
+            cell: RefCell::new(self.cell.borrow().copy_semantic(gcc)),
+        }
+    }
+
     #[inline]
     fn into_value(self, t: ValueType) -> GosValue {
         let v = self.cell.into_inner();
@@ -372,6 +408,16 @@ where
         }
     }
 
+    /// A copy of the array that doesn't share the arrays and structs in it
+    pub fn copy_semantic(&self, gcc: &GcContainer) -> ArrayObj<T> {
+        let data = self
+            .borrow_data()
+            .iter()
+            .map(|x| x.copy_semantic(gcc))
+            .collect();
+        Self::with_raw_data(data)
+    }
+
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.borrow_data().len()
@@ -405,7 +451,7 @@ where
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned().into());
+            return Err(index_out_of_range(i, self.len()));
         }
         Ok(self.borrow_data()[i].clone().into_value(t))
     }
@@ -413,7 +459,7 @@ where
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
         if i >= self.len() {
-            return Err(format!("index {} out of range", i).to_owned().into());
+            return Err(index_out_of_range(i, self.len()));
         }
         Ok(self.borrow_data()[i].set_value(&val))
     }
@@ -516,6 +562,26 @@ where
 // ----------------------------------------------------------------------------
 // SliceObj
 
+/// The capacity of the new array of an `append` that needs `new_len` elements and
+/// doesn't fit in `old_cap`, the way the Go runtime grows slices: the capacity is
+/// doubled below 256 elements, and grows by a factor going down to 1.25 above.
+/// Unlike Go it's not rounded up to the size classes of an allocator.
+pub(crate) fn grown_cap(old_cap: usize, new_len: usize) -> usize {
+    const THRESHOLD: usize = 256;
+    let double_cap = old_cap + old_cap;
+    if new_len > double_cap {
+        return new_len;
+    }
+    if old_cap < THRESHOLD {
+        return double_cap;
+    }
+    let mut cap = old_cap;
+    while cap < new_len {
+        cap += (cap + 3 * THRESHOLD) >> 2;
+    }
+    cap
+}
+
 #[derive(Clone)]
 pub struct SliceObj<T> {
     array: GosValue,
@@ -523,6 +589,8 @@ pub struct SliceObj<T> {
     end: Cell<usize>,
     // This is not capacity, but rather the max index that can be sliced.
     cap_end: Cell<usize>,
+    // Only used by strings, the cached hash of an interned string, 0 if not interned.
+    interned: Cell<u64>,
     phantom: PhantomData<T>,
 }
 
@@ -532,18 +600,49 @@ impl<T> SliceObj<T>
 where
     T: Element,
 {
-    pub fn with_array(arr: GosValue, begin: isize, end: isize) -> RuntimeResult<SliceObj<T>> {
+    pub fn with_array(
+        arr: GosValue,
+        begin: isize,
+        end: isize,
+        max: isize,
+    ) -> RuntimeResult<SliceObj<T>> {
         let len = arr.as_array::<T>().0.len();
-        let (bi, ei, cap) = SliceObj::<T>::check_indices(0, len, len, begin, end, -1)?;
+        let (bi, ei, cap) = SliceObj::<T>::check_indices(0, len, len, begin, end, max, "capacity")?;
         Ok(SliceObj {
             begin: Cell::from(bi),
             end: Cell::from(ei),
             cap_end: Cell::from(cap),
             array: arr,
+            interned: Cell::new(0),
             phantom: PhantomData,
         })
     }
 
+    /// A slice of the first `len` elements of `arr` with the capacity `cap`. The array
+    /// may hold less than `cap` elements, the rest are zero values added by
+    /// `fill_zeros` when the slice is resliced beyond them.
+    pub(crate) fn with_cap(arr: GosValue, len: usize, cap: usize) -> SliceObj<T> {
+        debug_assert!(len <= arr.as_array::<T>().0.len() && len <= cap);
+        SliceObj {
+            begin: Cell::from(0),
+            end: Cell::from(len),
+            cap_end: Cell::from(cap),
+            array: arr,
+            interned: Cell::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a slice with the same bounds into another array
+    #[inline]
+    pub(crate) fn with_same_bounds(&self, array: GosValue) -> SliceObj<T> {
+        SliceObj {
+            array,
+            interned: Cell::new(0),
+            ..self.clone()
+        }
+    }
+
     /// Get a reference to the slice obj's array.
     #[must_use]
     #[inline]
@@ -600,10 +699,14 @@ where
         self.array.as_addr() == other.array.as_addr()
     }
 
-    /// get_array_equivalent returns the underlying array and mapped index
+    /// get_array_equivalent returns the underlying array and mapped index,
+    /// the index is checked against the length, not the array it shares
     #[inline]
-    pub fn get_array_equivalent(&self, i: usize) -> (&GosValue, usize) {
-        (self.array(), self.begin() + i)
+    pub fn get_array_equivalent(&self, i: usize) -> RuntimeResult<(&GosValue, usize)> {
+        if i >= self.len() {
+            return Err(index_out_of_range(i, self.len()));
+        }
+        Ok((self.array(), self.begin() + i))
     }
 
     #[inline(always)]
@@ -613,18 +716,24 @@ where
 
     #[inline(always)]
     pub fn get(&self, i: usize, t: ValueType) -> RuntimeResult<GosValue> {
+        if i >= self.len() {
+            return Err(index_out_of_range(i, self.len()));
+        }
         self.array_obj().get(self.begin() + i, t)
     }
 
     #[inline(always)]
     pub fn set(&self, i: usize, val: &GosValue) -> RuntimeResult<()> {
-        self.array_obj().set(i, val)
+        if i >= self.len() {
+            return Err(index_out_of_range(i, self.len()));
+        }
+        self.array_obj().set(self.begin() + i, val)
     }
 
     #[inline]
     pub fn push(&mut self, val: GosValue) {
         let mut data = self.borrow_all_data_mut();
-        if data.len() == self.len() {
+        if data.len() == self.end() {
             data.push(T::from_value(val))
         } else {
             data[self.end()] = T::from_value(val);
@@ -636,27 +745,30 @@ where
         }
     }
 
+    /// Writes the elements of `other` after the end of the slice, in its array, which
+    /// the caller has checked has the capacity.
     #[inline]
     pub fn append(&mut self, other: &SliceObj<T>) {
+        let end = self.end();
+        let new_end = end + other.len();
+        // they may be where they go, or be borrowed with the array
+        let copied = self
+            .sharing_with(other)
+            .then(|| other.as_rust_slice().to_vec());
         let mut data = self.borrow_all_data_mut();
-        let new_end = self.end() + other.len();
-        let after_end_len = data.len() - self.end();
-        let sharing = self.sharing_with(other);
-        if after_end_len <= other.len() {
-            data.truncate(self.end());
-            if !sharing {
-                data.extend_from_slice(&other.as_rust_slice());
-            } else {
-                data.extend_from_within(other.range());
-            }
-        } else {
-            if !sharing {
-                T::copy_or_clone_slice(&mut data[self.end()..new_end], &other.as_rust_slice());
-            } else {
-                let cloned = data[other.range()].to_vec();
-                T::copy_or_clone_slice(&mut data[self.end()..new_end], &cloned);
+        let other_ref;
+        let src: &[T] = match &copied {
+            Some(v) => v,
+            None => {
+                other_ref = other.as_rust_slice();
+                &other_ref
             }
-        }
+        };
+        // the elements the array already holds after the end are overwritten,
+        // the rest are added
+        let overwritten = data.len().min(new_end) - end;
+        T::copy_or_clone_slice(&mut data[end..end + overwritten], &src[..overwritten]);
+        data.extend_from_slice(&src[overwritten..]);
         drop(data);
         *self.end.get_mut() = new_end;
         if self.cap_end.get() < self.end.get() {
@@ -688,6 +800,17 @@ where
 
     #[inline]
     pub fn slice(&self, begin: isize, end: isize, max: isize) -> RuntimeResult<SliceObj<T>> {
+        self.slice_bounded(begin, end, max, "capacity")
+    }
+
+    #[inline]
+    fn slice_bounded(
+        &self,
+        begin: isize,
+        end: isize,
+        max: isize,
+        bound: &str,
+    ) -> RuntimeResult<SliceObj<T>> {
         let (bi, ei, cap) = SliceObj::<T>::check_indices(
             self.begin(),
             self.len(),
@@ -695,23 +818,35 @@ where
             begin,
             end,
             max,
+            bound,
         )?;
         Ok(SliceObj {
             begin: Cell::from(bi),
             end: Cell::from(ei),
             cap_end: Cell::from(cap),
             array: self.array.clone(),
+            interned: Cell::new(0),
             phantom: PhantomData,
         })
     }
 
+    /// Adds zero values to the array up to the end of the slice, if the array is shorter,
+    /// see `with_cap`.
+    #[inline]
+    pub(crate) fn fill_zeros(&self, zero: &dyn Fn() -> GosValue) {
+        let mut data = self.borrow_all_data_mut();
+        if data.len() < self.end() {
+            data.resize_with(self.end(), || T::from_value(zero()));
+        }
+    }
+
     #[inline]
     pub fn swap(&self, i: usize, j: usize) -> RuntimeResult<()> {
         let len = self.len();
         if i >= len {
-            Err(format!("index {} out of range", i).into())
+            Err(index_out_of_range(i, len))
         } else if j >= len {
-            Err(format!("index {} out of range", j).into())
+            Err(index_out_of_range(j, len))
         } else {
             self.borrow_all_data_mut()
                 .swap(i + self.begin(), j + self.begin());
@@ -729,41 +864,49 @@ where
         self.array_obj().borrow_data()
     }
 
+    /// Checks `[begin:end:max]` against a slice from `this_begin`, of `this_len`
+    /// elements and with its capacity ending at `this_cap_end`, an `end` or `max` of -1
+    /// is left out. Returns the new bounds in the array, the errors are the ones of Go,
+    /// in which `bound` is what `this_cap_end` is, "capacity" or "length".
     #[inline]
-    fn check_indices(
+    pub(crate) fn check_indices(
         this_begin: usize,
         this_len: usize,
-        this_cap: usize,
+        this_cap_end: usize,
         begin: isize,
         end: isize,
         max: isize,
+        bound: &str,
     ) -> RuntimeResult<(usize, usize, usize)> {
-        let bi = this_begin + begin as usize;
-        if bi > this_cap {
-            return Err(format!("index {} out of range", begin).to_owned().into());
-        }
-
-        let cap = if max < 0 {
-            this_cap
-        } else {
-            let val = max as usize;
-            if val > this_cap {
-                return Err(format!("index {} out of range", max).to_owned().into());
+        let cap = (this_cap_end - this_begin) as isize;
+        let three = max != -1;
+        let out_of_range = |s: String| Err(format!("slice bounds out of range {}", s).into());
+        let max = if three {
+            if max < 0 || max > cap {
+                return out_of_range(format!("[::{}] with {} {}", max, bound, cap));
             }
-            val
-        };
-
-        let ei = if end < 0 {
-            this_len
+            max
         } else {
-            let val = this_begin + end as usize;
-            if val < bi || val > cap {
-                return Err(format!("index {} out of range", end).to_owned().into());
+            cap
+        };
+        let end = match end {
+            -1 if !three => this_len as isize,
+            _ if end < 0 || end > max => {
+                return match three {
+                    true => out_of_range(format!("[:{}:{}]", end, max)),
+                    false => out_of_range(format!("[:{}] with {} {}", end, bound, cap)),
+                };
             }
-            val
+            _ => end,
         };
-
-        Ok((bi, ei, cap))
+        if begin < 0 || begin > end {
+            return match three {
+                true => out_of_range(format!("[{}:{}:]", begin, end)),
+                false => out_of_range(format!("[{}:{}]", begin, end)),
+            };
+        }
+        let at = |i: isize| this_begin + i as usize;
+        Ok((at(begin), at(end), at(max)))
     }
 }
 
@@ -797,23 +940,67 @@ pub type SliceEnumIter<'a, T> = std::iter::Enumerate<SliceIter<'a, T>>;
 // ----------------------------------------------------------------------------
 // StringObj
 
-pub type StringIter<'a> = std::str::Chars<'a>;
+/// The runes of a string with their byte offsets, like `for i, r := range s` of Go,
+/// a byte that doesn't start a valid UTF-8 sequence is a U+FFFD of its own
+pub struct StringIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
 
-pub type StringEnumIter<'a> = std::iter::Enumerate<StringIter<'a>>;
+impl<'a> StringIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> StringIter<'a> {
+        StringIter { bytes, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for StringIter<'a> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let rest = &self.bytes[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let head = &rest[..rest.len().min(4)];
+        let valid = match std::str::from_utf8(head) {
+            Ok(s) => s,
+            Err(e) => unsafe { std::str::from_utf8_unchecked(&head[..e.valid_up_to()]) },
+        };
+        let (c, len) = match valid.chars().next() {
+            Some(c) => (c, c.len_utf8()),
+            None => (char::REPLACEMENT_CHARACTER, 1),
+        };
+        let offset = self.offset;
+        self.offset += len;
+        Some((offset, c))
+    }
+}
 
 pub type StringObj = SliceObj<Elem8>;
 
 impl StringObj {
     #[inline]
     pub fn with_str(s: &str) -> StringObj {
-        let buf: Vec<Elem8> = unsafe { std::mem::transmute(s.as_bytes().to_vec()) };
+        Self::with_bytes(s.as_bytes())
+    }
+
+    /// A string of a copy of `bytes`, which don't have to be valid UTF-8
+    #[inline]
+    pub fn with_bytes(bytes: &[u8]) -> StringObj {
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(bytes.to_vec()) };
         Self::with_buf(buf)
     }
 
     #[inline]
     fn with_buf(buf: Vec<Elem8>) -> StringObj {
         let arr = GosValue::new_non_gc_array(ArrayObj::with_raw_data(buf), ValueType::Uint8);
-        SliceObj::with_array(arr, 0, -1).unwrap()
+        SliceObj::with_array(arr, 0, -1, -1).unwrap()
+    }
+
+    /// `s[begin:end]` of a string, an `end` of -1 is left out
+    #[inline]
+    pub fn substring(&self, begin: isize, end: isize) -> RuntimeResult<StringObj> {
+        self.slice_bounded(begin, end, -1, "length")
     }
 
     /// It's safe because strings are readonly
@@ -824,6 +1011,11 @@ impl StringObj {
         unsafe { std::mem::transmute(self.as_rust_slice()) }
     }
 
+    #[inline]
+    pub fn as_bytes(&self) -> Ref<'_, [u8]> {
+        unsafe { std::mem::transmute(self.as_rust_slice()) }
+    }
+
     #[inline]
     pub fn index(&self, i: usize) -> RuntimeResult<GosValue> {
         self.get(i, ValueType::Uint8)
@@ -836,10 +1028,62 @@ impl StringObj {
 
     #[inline]
     pub fn add(&self, other: &StringObj) -> StringObj {
-        let mut buf = self.as_rust_slice().to_vec();
-        buf.extend_from_slice(&other.as_rust_slice());
+        Self::concat([self, other])
+    }
+
+    /// The parts joined in one allocation, copying their bytes once
+    pub fn concat<'a>(parts: impl IntoIterator<Item = &'a StringObj> + Clone) -> StringObj {
+        let len = parts.clone().into_iter().map(|x| x.len()).sum();
+        let mut buf: Vec<u8> = Vec::with_capacity(len);
+        for part in parts {
+            buf.extend_from_slice(&part.as_bytes());
+        }
+        let buf: Vec<Elem8> = unsafe { std::mem::transmute(buf) };
         Self::with_buf(buf)
     }
+
+    #[inline]
+    pub fn is_interned(&self) -> bool {
+        self.interned.get() != 0
+    }
+
+    /// Marks the string as interned by caching its hash, see `StringPool`
+    #[inline]
+    pub(crate) fn set_interned(&self) {
+        self.interned.set(self.content_hash());
+    }
+
+    /// A hash of the content, cached if the string is interned.
+    /// It's never 0, so that 0 can mean "not interned".
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        match self.interned.get() {
+            0 => {
+                // FNV-1a, the strings worth hashing fast are short
+                let mut h: u64 = 0xcbf29ce484222325;
+                for b in self.as_str().as_bytes() {
+                    h ^= *b as u64;
+                    h = h.wrapping_mul(0x100000001b3);
+                }
+                h | 1
+            }
+            h => h,
+        }
+    }
+
+    /// Interned strings of the same pool are equal only if they are the same
+    /// object, the hashes rule out most of the rest without comparing the bytes.
+    #[inline]
+    pub fn str_eq(&self, other: &StringObj) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let (a, b) = (self.interned.get(), other.interned.get());
+        if a != 0 && b != 0 && a != b {
+            return false;
+        }
+        *self.as_str() == *other.as_str()
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -857,6 +1101,16 @@ impl StructObj {
         }
     }
 
+    /// A copy of the struct that doesn't share the arrays and structs in it
+    pub fn copy_semantic(&self, gcc: &GcContainer) -> StructObj {
+        StructObj::new(
+            self.borrow_fields()
+                .iter()
+                .map(|x| x.copy_semantic(gcc))
+                .collect(),
+        )
+    }
+
     #[inline]
     pub fn borrow_fields(&self) -> Ref<Vec<GosValue>> {
         self.fields.borrow()
@@ -1000,22 +1254,6 @@ impl InterfaceObj {
             None => false,
         }
     }
-
-    /// for gc
-    pub fn ref_sub_one(&self) {
-        match self {
-            Self::Gos(v, _) => v.ref_sub_one(),
-            _ => {}
-        };
-    }
-
-    /// for gc
-    pub fn mark_dirty(&self, queue: &mut RCQueue) {
-        match self {
-            Self::Gos(v, _) => v.mark_dirty(queue),
-            _ => {}
-        };
-    }
 }
 
 impl Eq for InterfaceObj {}
@@ -1023,11 +1261,7 @@ impl Eq for InterfaceObj {}
 impl PartialEq for InterfaceObj {
     #[inline]
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Gos(x, _), Self::Gos(y, _)) => x == y,
-            (Self::Ffi(x), Self::Ffi(y)) => Rc::ptr_eq(&x.ffi_obj, &y.ffi_obj),
-            _ => false,
-        }
+        value_ops::iface_equal(self, other)
     }
 }
 
@@ -1039,31 +1273,16 @@ impl PartialOrd for InterfaceObj {
 }
 
 impl Ord for InterfaceObj {
+    #[inline]
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Self::Gos(x, _), Self::Gos(y, _)) => {
-                let xt = x.typ();
-                let yt = y.typ();
-                if xt == yt {
-                    x.cmp(y)
-                } else {
-                    xt.cmp(&yt)
-                }
-            }
-            (Self::Ffi(x), Self::Ffi(y)) => Rc::as_ptr(&x.ffi_obj).cmp(&Rc::as_ptr(&y.ffi_obj)),
-            (Self::Gos(_, _), Self::Ffi(_)) => Ordering::Greater,
-            (Self::Ffi(_), Self::Gos(_, _)) => Ordering::Less,
-        }
+        value_ops::iface_compare(self, other)
     }
 }
 
 impl Hash for InterfaceObj {
     #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
-        match self {
-            Self::Gos(v, _) => v.hash(state),
-            Self::Ffi(ffi) => Rc::as_ptr(&ffi.ffi_obj).hash(state),
-        }
+        value_ops::iface_hash(self, state)
     }
 }
 
@@ -1111,8 +1330,9 @@ impl ChannelObj {
         self.chan.cap()
     }
 
+    /// Returns false if the channel is already closed
     #[inline]
-    pub fn close(&self) {
+    pub fn close(&self) -> bool {
         self.chan.close()
     }
 
@@ -1120,8 +1340,8 @@ impl ChannelObj {
         self.chan.send(v).await
     }
 
-    pub async fn recv(&self) -> Option<GosValue> {
-        self.chan.recv().await
+    pub async fn recv(&self, gcc: &GcContainer) -> Option<GosValue> {
+        self.chan.recv(gcc).await
     }
 }
 
@@ -1166,7 +1386,7 @@ impl PointerObj {
         i: OpIndex,
         caller: &Box<dyn Dispatcher>,
     ) -> RuntimeResult<PointerObj> {
-        let slice = GosValue::slice_array(val, 0, -1, caller)?;
+        let slice = GosValue::slice_array(val, 0, -1, -1, caller)?;
         // todo: index check!
         Ok(PointerObj::SliceMember(slice, i))
     }
@@ -1241,34 +1461,6 @@ impl PointerObj {
         Ok(())
     }
 
-    /// for gc
-    pub(crate) fn ref_sub_one(&self) {
-        match &self {
-            PointerObj::UpVal(uv) => uv.ref_sub_one(),
-            PointerObj::SliceMember(s, _) => {
-                let rc = &s.as_gos_slice().unwrap().1;
-                rc.set(rc.get() - 1);
-            }
-            PointerObj::StructField(s, _) => {
-                let rc = &s.as_struct().1;
-                rc.set(rc.get() - 1);
-            }
-            _ => {}
-        };
-    }
-
-    /// for gc
-    pub(crate) fn mark_dirty(&self, queue: &mut RCQueue) {
-        match &self {
-            PointerObj::UpVal(uv) => uv.mark_dirty(queue),
-            PointerObj::SliceMember(s, _) => {
-                rcount_mark_and_queue(&s.as_gos_slice().unwrap().1, queue)
-            }
-            PointerObj::StructField(s, _) => rcount_mark_and_queue(&s.as_struct().1, queue),
-            _ => {}
-        };
-    }
-
     #[inline]
     fn order(&self) -> usize {
         match self {
@@ -1364,11 +1556,9 @@ pub trait UnsafePtr {
         panic!("implement your own eq for your type");
     }
 
-    /// for gc
-    fn ref_sub_one(&self) {}
-
-    /// for gc
-    fn mark_dirty(&self, _: &mut RCQueue) {}
+    /// Calls `f` with the values the user data holds, so that GC can find the cycles
+    /// they're part of
+    fn gc_refs(&self, _: &mut dyn FnMut(&GosValue)) {}
 
     /// Returns true if the user data can make reference cycles, so that GC can
     fn can_make_cycle(&self) -> bool {
@@ -1377,6 +1567,22 @@ pub trait UnsafePtr {
 
     /// If can_make_cycle returns true, implement this to break cycle
     fn break_cycle(&self) {}
+
+    /// Returns an independent copy of the user data for `Bytecode::fork`,
+    /// the fork fails if it returns None
+    fn clone_for_fork(&self) -> Option<Rc<dyn UnsafePtr>> {
+        None
+    }
+
+    /// For error messages
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// The class the user data is counted in by a heap census, see `census`
+    fn census_class(&self) -> &'static str {
+        self.type_name()
+    }
 }
 
 impl std::fmt::Debug for dyn UnsafePtr {
@@ -1405,14 +1611,8 @@ impl UnsafePtr for PointerHandle {
         }
     }
 
-    /// for gc
-    fn ref_sub_one(&self) {
-        self.ptr.ref_sub_one()
-    }
-
-    /// for gc
-    fn mark_dirty(&self, q: &mut RCQueue) {
-        self.ptr.mark_dirty(q)
+    fn gc_refs(&self, f: &mut dyn FnMut(&GosValue)) {
+        f(&GosValue::new_pointer(self.ptr.clone()))
     }
 }
 
@@ -1448,6 +1648,11 @@ impl UnsafePtrObj {
         &*self.ptr
     }
 
+    #[inline]
+    pub(crate) fn as_rc(&self) -> &Rc<dyn UnsafePtr> {
+        &self.ptr
+    }
+
     pub fn as_rust_ptr(&self) -> *const dyn UnsafePtr {
         &*self.ptr
     }
@@ -1638,22 +1843,6 @@ impl UpValue {
             }
         }
     }
-
-    /// for gc
-    pub fn ref_sub_one(&self) {
-        let state: &UpValueState = &self.inner.borrow();
-        if let UpValueState::Closed(uvs) = state {
-            uvs.ref_sub_one()
-        }
-    }
-
-    /// for gc
-    pub fn mark_dirty(&self, queue: &mut RCQueue) {
-        let state: &UpValueState = &self.inner.borrow();
-        if let UpValueState::Closed(uvs) = state {
-            uvs.mark_dirty(queue)
-        }
-    }
 }
 
 impl Eq for UpValue {}
@@ -1822,40 +2011,6 @@ impl ClosureObj {
             _ => unreachable!(),
         }
     }
-
-    /// for gc
-    pub fn ref_sub_one(&self) {
-        match self {
-            Self::Gos(obj) => {
-                if let Some(uvs) = &obj.uvs {
-                    for (_, v) in uvs.iter() {
-                        v.ref_sub_one()
-                    }
-                }
-                if let Some(recv) = &obj.recv {
-                    recv.ref_sub_one()
-                }
-            }
-            Self::Ffi(_) => {}
-        }
-    }
-
-    /// for gc
-    pub fn mark_dirty(&self, queue: &mut RCQueue) {
-        match self {
-            Self::Gos(obj) => {
-                if let Some(uvs) = &obj.uvs {
-                    for (_, v) in uvs.iter() {
-                        v.mark_dirty(queue)
-                    }
-                }
-                if let Some(recv) = &obj.recv {
-                    recv.mark_dirty(queue)
-                }
-            }
-            Self::Ffi(_) => {}
-        }
-    }
 }
 
 // ----------------------------------------------------------------------------
@@ -1870,7 +2025,10 @@ pub struct PackageObj {
     member_indices: Map<String, OpIndex>,
     init_funcs: Vec<GosValue>,
     // maps func_member_index of the constructor to pkg_member_index
-    var_mapping: RefCell<Option<Map<OpIndex, OpIndex>>>,
+    var_mapping: Map<OpIndex, OpIndex>,
+    // zero values of the vars, to restore before re-initializing
+    var_zeros: Vec<(OpIndex, GosValue)>,
+    inited: Cell<bool>,
 }
 
 impl PackageObj {
@@ -1880,7 +2038,9 @@ impl PackageObj {
             members: vec![],
             member_indices: Map::new(),
             init_funcs: vec![],
-            var_mapping: RefCell::new(Some(Map::new())),
+            var_mapping: Map::new(),
+            var_zeros: vec![],
+            inited: Cell::new(false),
         }
     }
 
@@ -1895,13 +2055,17 @@ impl PackageObj {
         index as OpIndex
     }
 
+    /// Adds a package level var, `val` and `zero` should be two different
+    /// instances of the zero value of the var's type.
+    pub fn add_var_member(&mut self, name: String, val: GosValue, zero: GosValue) -> OpIndex {
+        let index = self.add_member(name, val);
+        self.var_zeros.push((index, zero));
+        index
+    }
+
     pub fn add_var_mapping(&mut self, name: String, fn_index: OpIndex) -> OpIndex {
         let index = *self.member_index(&name).unwrap();
-        self.var_mapping
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .insert(fn_index, index);
+        self.var_mapping.insert(fn_index, index);
         index
     }
 
@@ -1918,7 +2082,45 @@ impl PackageObj {
     }
 
     pub fn inited(&self) -> bool {
-        self.var_mapping.borrow().is_none()
+        self.inited.get()
+    }
+
+    /// Marks the package as not initialized, so that its vars are re-initialized
+    /// and its init functions are run again the next time it's imported.
+    pub fn reset(&self) {
+        self.inited.set(false);
+    }
+
+    pub(crate) fn restore_var_zeros(&self, gcc: &GcContainer) {
+        for (i, zero) in self.var_zeros.iter() {
+            *self.member_mut(*i) = zero.copy_semantic(gcc);
+        }
+    }
+
+    /// The current values of the vars, in the order of their declaration
+    pub fn vars(&self) -> Vec<GosValue> {
+        self.var_zeros
+            .iter()
+            .map(|(i, _)| self.member(*i).clone())
+            .collect()
+    }
+
+    /// Exchanges the values of the vars with `vals`, which is in the order of `vars`,
+    /// so that several sets of vars can take turns in the package.
+    pub fn swap_vars(&self, vals: &mut [GosValue]) {
+        for ((i, _), v) in self.var_zeros.iter().zip(vals.iter_mut()) {
+            std::mem::swap(&mut *self.member_mut(*i), v);
+        }
+    }
+
+    /// The position in `vars` of the member with the given index, if it's a var
+    pub fn var_position(&self, i: OpIndex) -> Option<usize> {
+        self.var_zeros.iter().position(|(j, _)| *j == i)
+    }
+
+    /// The imports, consts, vars and funcs
+    pub(crate) fn members(&self) -> impl Iterator<Item = Ref<'_, GosValue>> {
+        self.members.iter().map(|m| m.borrow())
     }
 
     #[inline]
@@ -1936,15 +2138,31 @@ impl PackageObj {
         self.init_funcs.get(i as usize)
     }
 
+    /// Returns a copy of the package with the members deeply copied by `forker`.
+    pub(crate) fn fork(&self, forker: &mut Forker) -> RuntimeResult<PackageObj> {
+        let members = self
+            .members
+            .iter()
+            .map(|m| forker.fork_value(&m.borrow()).map(RefCell::new))
+            .collect::<RuntimeResult<_>>()?;
+        Ok(PackageObj {
+            name: self.name.clone(),
+            members,
+            member_indices: self.member_indices.clone(),
+            init_funcs: self.init_funcs.clone(),
+            var_mapping: self.var_mapping.clone(),
+            var_zeros: self.var_zeros.clone(),
+            inited: self.inited.clone(),
+        })
+    }
+
     #[inline]
     pub fn init_vars(&self, vals: Vec<GosValue>) {
-        let mut borrow = self.var_mapping.borrow_mut();
-        let mapping = borrow.as_ref().unwrap();
         for (i, v) in vals.into_iter().enumerate() {
-            let vi = mapping[&(i as OpIndex)];
+            let vi = self.var_mapping[&(i as OpIndex)];
             *self.member_mut(vi) = v;
         }
-        *borrow = None;
+        self.inited.set(true);
     }
 }
 
@@ -1960,7 +2178,9 @@ impl BorshSerialize for PackageObj {
         members.serialize(writer)?;
         self.member_indices.serialize(writer)?;
         self.init_funcs.serialize(writer)?;
-        self.var_mapping.borrow().serialize(writer)
+        self.var_mapping.serialize(writer)?;
+        self.var_zeros.serialize(writer)?;
+        self.inited.get().serialize(writer)
     }
 }
 
@@ -1974,14 +2194,17 @@ impl BorshDeserialize for PackageObj {
             .collect();
         let member_indices = Map::<String, OpIndex>::deserialize_reader(reader)?;
         let init_funcs = Vec::<GosValue>::deserialize_reader(reader)?;
-        let var_mapping =
-            RefCell::new(Option::<Map<OpIndex, OpIndex>>::deserialize_reader(reader)?);
+        let var_mapping = Map::<OpIndex, OpIndex>::deserialize_reader(reader)?;
+        let var_zeros = Vec::<(OpIndex, GosValue)>::deserialize_reader(reader)?;
+        let inited = Cell::new(bool::deserialize_reader(reader)?);
         Ok(PackageObj {
             name,
             members,
             member_indices,
             init_funcs,
             var_mapping,
+            var_zeros,
+            inited,
         })
     }
 }
@@ -2,12 +2,16 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::gc::GcContainer;
 use crate::value::*;
+use crate::value_ops;
 
 const DEFAULT_CAPACITY: usize = 256;
 
 pub struct Stack {
     vec: Vec<GosValue>,
+    /// The slots from it on have never been written, see `reset`
+    used: usize,
 }
 
 impl Stack {
@@ -15,16 +19,45 @@ impl Stack {
     pub fn new() -> Stack {
         Stack {
             vec: vec![GosValue::new_nil(ValueType::Void); DEFAULT_CAPACITY],
+            used: 0,
         }
     }
 
     #[inline]
     pub fn with_vec(v: Vec<GosValue>) -> Stack {
-        let mut s = Stack { vec: v };
+        let used = v.len();
+        let mut s = Stack { vec: v, used };
         s.set_min_size(DEFAULT_CAPACITY);
         s
     }
 
+    /// A stack without any slots, for a goroutine that hasn't started yet
+    #[inline]
+    pub(crate) fn unallocated() -> Stack {
+        Stack {
+            vec: Vec::new(),
+            used: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Drops the values left on the stack, so that it can be reused for another
+    /// goroutine. Only the slots that have been used are cleared.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.clear(0, self.used as OpIndex);
+        self.used = 0;
+    }
+
     #[inline]
     pub fn get(&self, index: OpIndex) -> &GosValue {
         unsafe { self.vec.get_unchecked(index as usize) }
@@ -50,39 +83,63 @@ impl Stack {
     }
 
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn read_and_op(
         &self,
         lhs: &ValueData,
         t: ValueType,
+        rhs_t: ValueType,
         op: Opcode,
         rhs: OpIndex,
         sb: OpIndex,
         consts: &[GosValue],
-    ) -> GosValue {
+    ) -> RuntimeResult<GosValue> {
         let d = match op {
             Opcode::INC => lhs.inc(t),
             Opcode::DEC => lhs.dec(t),
             Opcode::ADD => lhs.binary_op_add(self.read(rhs, sb, consts).data(), t),
             Opcode::SUB => lhs.binary_op_sub(self.read(rhs, sb, consts).data(), t),
             Opcode::MUL => lhs.binary_op_mul(self.read(rhs, sb, consts).data(), t),
-            Opcode::QUO => lhs.binary_op_quo(self.read(rhs, sb, consts).data(), t),
-            Opcode::REM => lhs.binary_op_rem(self.read(rhs, sb, consts).data(), t),
+            Opcode::QUO => lhs.binary_op_quo(self.read(rhs, sb, consts).data(), t)?,
+            Opcode::REM => lhs.binary_op_rem(self.read(rhs, sb, consts).data(), t)?,
             Opcode::AND => lhs.binary_op_and(self.read(rhs, sb, consts).data(), t),
             Opcode::OR => lhs.binary_op_or(self.read(rhs, sb, consts).data(), t),
             Opcode::XOR => lhs.binary_op_xor(self.read(rhs, sb, consts).data(), t),
             Opcode::AND_NOT => lhs.binary_op_and_not(self.read(rhs, sb, consts).data(), t),
-            Opcode::SHL => lhs.binary_op_shl(self.read(rhs, sb, consts).data().as_uint32(), t),
-            Opcode::SHR => lhs.binary_op_shr(self.read(rhs, sb, consts).data().as_uint32(), t),
+            Opcode::SHL => {
+                let count = self.read(rhs, sb, consts).data().shift_count(rhs_t)?;
+                lhs.binary_op_shl(&count, t)
+            }
+            Opcode::SHR => {
+                let count = self.read(rhs, sb, consts).data().shift_count(rhs_t)?;
+                lhs.binary_op_shr(&count, t)
+            }
+            Opcode::ADD_CHECKED => {
+                lhs.binary_op_add_checked(self.read(rhs, sb, consts).data(), t)?
+            }
+            Opcode::SUB_CHECKED => {
+                lhs.binary_op_sub_checked(self.read(rhs, sb, consts).data(), t)?
+            }
+            Opcode::MUL_CHECKED => {
+                lhs.binary_op_mul_checked(self.read(rhs, sb, consts).data(), t)?
+            }
+            Opcode::SHL_CHECKED => {
+                let count = self.read(rhs, sb, consts).data().shift_count(rhs_t)?;
+                lhs.binary_op_shl_checked(&count, t)?
+            }
             _ => {
                 dbg!(op);
                 unreachable!();
             }
         };
-        GosValue::new(t, d)
+        Ok(GosValue::new(t, d))
     }
 
+    /// Makes sure the slots below `size` exist, all the writes to the stack
+    /// are to slots made sure of with it.
     #[inline]
     pub fn set_min_size(&mut self, size: usize) {
+        self.used = self.used.max(size);
         if size > self.vec.len() {
             self.vec.resize(size, GosValue::new_nil(ValueType::Void))
         }
@@ -96,6 +153,25 @@ impl Stack {
         self.vec[begin..new_len].swap_with_slice(&mut vec);
     }
 
+    /// Copies `vals` to the stack starting at `index`, it doesn't allocate
+    /// unless the stack has to grow
+    #[inline]
+    pub fn set_from_slice(&mut self, index: OpIndex, vals: &[GosValue]) {
+        let begin = index as usize;
+        let end = begin + vals.len();
+        self.set_min_size(end);
+        self.vec[begin..end].clone_from_slice(vals);
+    }
+
+    /// Drops the values in [begin, end), like `move_vec` but without
+    /// allocating a Vec for them
+    #[inline]
+    pub fn clear(&mut self, begin: OpIndex, end: OpIndex) {
+        for v in self.vec[begin as usize..end as usize].iter_mut() {
+            *v = GosValue::new_nil(ValueType::Void);
+        }
+    }
+
     #[inline]
     pub fn move_vec(&mut self, begin: OpIndex, end: OpIndex) -> Vec<GosValue> {
         let b = begin as usize;
@@ -138,33 +214,64 @@ impl Stack {
 
 impl std::fmt::Debug for Stack {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:#?}", &self.vec[..16])
+        write!(f, "{:#?}", &self.vec[..self.vec.len().min(16)])
     }
 }
 
+/// A map being ranged over. The keys are the ones it had when the range started,
+/// the entries deleted since are skipped and the ones added are not visited, which
+/// is one of the orders Go allows. The values are kept for the keys that can't be
+/// looked up, the ones with NaN in them, which can't be deleted or set either.
+struct MapRange {
+    map: GosValue,
+    entries: std::vec::IntoIter<(GosValue, GosValue)>,
+}
+
+/// An iterator over a slice, an array or a string, with the value it borrows from,
+/// which may be a temporary that nothing else keeps alive.
+struct Ranged<I> {
+    _target: GosValue,
+    iter: I,
+}
+
 /// store iterators for Opcode::RANGE
 pub struct RangeStack {
-    maps: Vec<GosMapIter<'static>>,
-    slices: Vec<SliceEnumIter<'static, AnyElem>>,
-    strings: Vec<StringEnumIter<'static>>,
+    maps: Vec<MapRange>,
+    slices: Vec<Ranged<SliceEnumIter<'static, AnyElem>>>,
+    strings: Vec<Ranged<StringIter<'static>>>,
+    /// The state of the generator that shuffles the keys of the maps, if they are
+    /// shuffled, see `RunOptions::map_order_seed`
+    map_order: Option<u64>,
 }
 
 impl RangeStack {
-    pub fn new() -> RangeStack {
+    pub fn new(map_order_seed: Option<u64>) -> RangeStack {
         RangeStack {
             maps: vec![],
             slices: vec![],
             strings: vec![],
+            map_order: map_order_seed,
         }
     }
 
+    /// splitmix64, which works with any seed
+    fn next_random(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
     /// range_init creates iters and transmute them to 'static, then save them on stacks.
-    /// it's safe because they are held only during 'ranging', which can never be longer
-    /// than their real lifetime
+    /// it's safe because they are saved along with the value they iterate over, so the
+    /// value lives as long as they do
     ///
     /// But it's not rust-safe just go-safe. because the Ref is dropped inside the transmute.
     /// that means if you write to the container we are ranging, it'll not be stopped by
     /// the borrow checker. Which is not safe to Rust, but it's exactly what Go does.
+    /// Maps are the exception, their keys are copied because a map may rehash while
+    /// it's ranged over.
     pub(crate) fn range_init(
         &mut self,
         target: &GosValue,
@@ -173,18 +280,41 @@ impl RangeStack {
     ) -> RuntimeResult<()> {
         match typ {
             ValueType::Map => {
-                let map = target.as_non_nil_map()?.0.borrow_data();
-                let iter = unsafe { std::mem::transmute(map.iter()) };
-                self.maps.push(iter);
+                // ranging over a nil map is ranging over an empty one
+                let mut entries: Vec<(GosValue, GosValue)> = target.as_map().map_or(vec![], |m| {
+                    let data = m.0.borrow_data();
+                    data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+                });
+                if let Some(state) = &mut self.map_order {
+                    // sorted first so that a seed gives the same order whatever the
+                    // map stores them in
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    for i in (1..entries.len()).rev() {
+                        let j = (Self::next_random(state) % (i as u64 + 1)) as usize;
+                        entries.swap(i, j);
+                    }
+                }
+                self.maps.push(MapRange {
+                    map: target.clone(),
+                    entries: entries.into_iter(),
+                });
             }
             ValueType::Array | ValueType::Slice => {
                 let iter = arr_caller.array_slice_iter(&target)?;
-                self.slices.push(iter);
+                self.slices.push(Ranged {
+                    _target: target.clone(),
+                    iter,
+                });
             }
             ValueType::String => {
+                let bytes = target.as_string().as_bytes();
+                let iter = StringIter::new(&bytes);
                 let iter =
-                    unsafe { std::mem::transmute(target.as_string().as_str().chars().enumerate()) };
-                self.strings.push(iter);
+                    unsafe { std::mem::transmute::<StringIter<'_>, StringIter<'static>>(iter) };
+                self.strings.push(Ranged {
+                    _target: target.clone(),
+                    iter,
+                });
             }
             _ => unreachable!(),
         }
@@ -198,24 +328,31 @@ impl RangeStack {
         stack: &mut Stack,
         index_key: OpIndex,
         index_val: OpIndex,
+        gcc: &GcContainer,
     ) -> bool {
         match typ {
-            ValueType::Map => match self.maps.last_mut().unwrap().next() {
-                Some((k, v)) => {
-                    stack.set(index_key, k.clone());
-                    stack.set(index_val, v.clone());
-                    false
-                }
-                None => {
-                    self.maps.pop();
-                    true
+            ValueType::Map => {
+                let range = self.maps.last_mut().unwrap();
+                for (k, v) in range.entries.by_ref() {
+                    let v = match value_ops::equal(&k, &k) {
+                        true => range.map.as_map().and_then(|m| m.0.get(&k)),
+                        false => Some(v),
+                    };
+                    if let Some(v) = v {
+                        // the variables are copies, like assigned ones
+                        stack.set(index_key, k.copy_semantic(gcc));
+                        stack.set(index_val, v.copy_semantic(gcc));
+                        return false;
+                    }
                 }
-            },
+                self.maps.pop();
+                true
+            }
             ValueType::Array | ValueType::Slice => {
-                match arr_caller.array_slice_next(self.slices.last_mut().unwrap()) {
+                match arr_caller.array_slice_next(&mut self.slices.last_mut().unwrap().iter) {
                     Some((k, v)) => {
                         stack.set(index_key, (k as isize).into());
-                        stack.set(index_val, v);
+                        stack.set(index_val, v.copy_semantic(gcc));
                         false
                     }
                     None => {
@@ -224,10 +361,11 @@ impl RangeStack {
                     }
                 }
             }
-            ValueType::String => match self.strings.last_mut().unwrap().next() {
+            ValueType::String => match self.strings.last_mut().unwrap().iter.next() {
                 Some((k, v)) => {
                     stack.set(index_key, (k as isize).into());
-                    stack.set(index_val, (v as isize).into());
+                    // a rune
+                    stack.set(index_val, (v as i32).into());
                     false
                 }
                 None => {
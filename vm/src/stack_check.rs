@@ -0,0 +1,236 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The runtime counterpart of the `emit_check` feature of the code generator.
+//! Before a typed instruction runs, the values it reads are checked against the
+//! types it expects them to be of, and the run stops with a `PanicKind::StackCheck`
+//! panic on the first mismatch, so that an instruction emitted with the wrong
+//! ValueType is caught where it misreads a slot rather than wherever the misread
+//! value eventually crashes.
+//! A value carries the ValueType its writer gave it, that's the tag checked, the
+//! instruction that wrote each slot last is kept on the side for the diagnostic.
+
+use crate::stack::Stack;
+use crate::value::*;
+
+pub(crate) struct StackCheck {
+    /// The function and the pc of the instruction that wrote each slot last
+    writers: Vec<Option<(FunctionKey, OpIndex)>>,
+}
+
+impl StackCheck {
+    pub fn new() -> StackCheck {
+        StackCheck { writers: vec![] }
+    }
+
+    /// The operands `inst` reads and the types they have to be of
+    fn expected(inst: &Instruction) -> Vec<(&'static str, OpIndex, ValueType)> {
+        let (t0, t1) = (inst.t0, inst.t1);
+        match inst.op0 {
+            Opcode::ADD
+            | Opcode::SUB
+            | Opcode::MUL
+            | Opcode::QUO
+            | Opcode::REM
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR
+            | Opcode::AND_NOT
+            | Opcode::ADD_CHECKED
+            | Opcode::SUB_CHECKED
+            | Opcode::MUL_CHECKED => vec![("s0", inst.s0, t0), ("s1", inst.s1, t0)],
+            Opcode::SHL | Opcode::SHR | Opcode::SHL_CHECKED => {
+                vec![("s0", inst.s0, t0), ("s1", inst.s1, t1)]
+            }
+            Opcode::ADD_ASSIGN
+            | Opcode::SUB_ASSIGN
+            | Opcode::MUL_ASSIGN
+            | Opcode::QUO_ASSIGN
+            | Opcode::REM_ASSIGN
+            | Opcode::AND_ASSIGN
+            | Opcode::OR_ASSIGN
+            | Opcode::XOR_ASSIGN
+            | Opcode::AND_NOT_ASSIGN => vec![("d", inst.d, t0), ("s0", inst.s0, t0)],
+            Opcode::SHL_ASSIGN | Opcode::SHR_ASSIGN => {
+                vec![("d", inst.d, t0), ("s0", inst.s0, t1)]
+            }
+            Opcode::INC | Opcode::DEC => vec![("d", inst.d, t0)],
+            Opcode::UNARY_SUB | Opcode::UNARY_XOR | Opcode::NOT => vec![("s0", inst.s0, t0)],
+            // only the comparisons of copyable values depend on the types
+            Opcode::EQL if t0.copyable() && t0 == t1 => {
+                vec![("s0", inst.s0, t0), ("s1", inst.s1, t1)]
+            }
+            Opcode::NEQ | Opcode::LSS | Opcode::GTR | Opcode::LEQ | Opcode::GEQ
+                if t0.copyable() =>
+            {
+                vec![("s0", inst.s0, t0), ("s1", inst.s1, t0)]
+            }
+            Opcode::CAST | Opcode::CAST_CHECKED if t1 != ValueType::Void => {
+                vec![("s0", inst.s0, t1)]
+            }
+            // the bases of the fields and the entries, and the targets of the calls
+            Opcode::LOAD_STRUCT => vec![("s0", inst.s0, ValueType::Struct)],
+            Opcode::STORE_STRUCT => vec![("d", inst.d, ValueType::Struct)],
+            Opcode::LOAD_MAP => vec![("s0", inst.s0, ValueType::Map)],
+            Opcode::STORE_MAP => vec![("d", inst.d, ValueType::Map)],
+            Opcode::LOAD_POINTER => vec![("s0", inst.s0, ValueType::Pointer)],
+            Opcode::STORE_POINTER => vec![("d", inst.d, ValueType::Pointer)],
+            Opcode::CALL => vec![("d", inst.d, ValueType::Closure)],
+            // CALL_CACHED is given either the closure or the receiver
+            Opcode::BIND_I_METHOD | Opcode::BIND_I_METHOD_CACHED => {
+                vec![("s0", inst.s0, ValueType::Interface)]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether `op` writes the local at its `d`
+    fn writes_d(op: Opcode) -> bool {
+        matches!(
+            op,
+            Opcode::DUPLICATE
+                | Opcode::LOAD_SLICE
+                | Opcode::LOAD_ARRAY
+                | Opcode::LOAD_MAP
+                | Opcode::LOAD_STRUCT
+                | Opcode::LOAD_EMBEDDED
+                | Opcode::LOAD_PKG
+                | Opcode::LOAD_POINTER
+                | Opcode::LOAD_UP_VALUE
+                | Opcode::ADD
+                | Opcode::SUB
+                | Opcode::MUL
+                | Opcode::QUO
+                | Opcode::REM
+                | Opcode::AND
+                | Opcode::OR
+                | Opcode::XOR
+                | Opcode::AND_NOT
+                | Opcode::SHL
+                | Opcode::SHR
+                | Opcode::CONCAT
+                | Opcode::ADD_ASSIGN
+                | Opcode::SUB_ASSIGN
+                | Opcode::MUL_ASSIGN
+                | Opcode::QUO_ASSIGN
+                | Opcode::REM_ASSIGN
+                | Opcode::AND_ASSIGN
+                | Opcode::OR_ASSIGN
+                | Opcode::XOR_ASSIGN
+                | Opcode::AND_NOT_ASSIGN
+                | Opcode::SHL_ASSIGN
+                | Opcode::SHR_ASSIGN
+                | Opcode::INC
+                | Opcode::DEC
+                | Opcode::ADD_CHECKED
+                | Opcode::SUB_CHECKED
+                | Opcode::MUL_CHECKED
+                | Opcode::SHL_CHECKED
+                | Opcode::UNARY_SUB
+                | Opcode::UNARY_XOR
+                | Opcode::NOT
+                | Opcode::EQL
+                | Opcode::NEQ
+                | Opcode::LSS
+                | Opcode::GTR
+                | Opcode::LEQ
+                | Opcode::GEQ
+                | Opcode::REF
+                | Opcode::REF_UPVALUE
+                | Opcode::REF_SLICE_MEMBER
+                | Opcode::REF_STRUCT_FIELD
+                | Opcode::REF_EMBEDDED
+                | Opcode::REF_PKG_MEMBER
+                | Opcode::BIND_METHOD
+                | Opcode::BIND_I_METHOD
+                | Opcode::BIND_I_METHOD_CACHED
+                | Opcode::CAST
+                | Opcode::CAST_CHECKED
+                | Opcode::TYPE_ASSERT
+                | Opcode::SLICE
+                | Opcode::CLOSURE
+                | Opcode::LITERAL
+                | Opcode::NEW
+                | Opcode::MAKE
+                | Opcode::COMPLEX
+                | Opcode::REAL
+                | Opcode::IMAG
+                | Opcode::LEN
+                | Opcode::CAP
+                | Opcode::APPEND
+                | Opcode::RECOVER
+        )
+    }
+
+    /// Checks `inst`, at `pc` of `func`, before it runs, then records it as the
+    /// writer of its `d` if it writes one. The error describes the operands of the
+    /// wrong types.
+    pub fn check(
+        &mut self,
+        func: FunctionKey,
+        pc: OpIndex,
+        inst: &Instruction,
+        stack: &Stack,
+        sb: OpIndex,
+        code: &Bytecode,
+    ) -> Result<(), String> {
+        let mismatches: Vec<String> = Self::expected(inst)
+            .into_iter()
+            .filter_map(|(name, i, want)| {
+                let got = stack.read(i, sb, &code.consts).typ();
+                (got != want).then(|| {
+                    format!(
+                        "{} is {:?}, expected {:?}, {}",
+                        name,
+                        got,
+                        want,
+                        self.writer(i, sb, func, code)
+                    )
+                })
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "stack type mismatch in function {:?} at pc {}{}: {} {:?}/{:?}: {}",
+                func,
+                pc,
+                position(code, func, pc).map_or(String::new(), |p| format!(" ({})", p)),
+                inst.op0,
+                inst.t0,
+                inst.t1,
+                mismatches.join("; ")
+            ));
+        }
+        if Self::writes_d(inst.op0) && inst.d >= 0 {
+            let slot = (sb + inst.d) as usize;
+            if slot >= self.writers.len() {
+                self.writers.resize(slot + 1, None);
+            }
+            self.writers[slot] = Some((func, pc));
+        }
+        Ok(())
+    }
+
+    fn writer(&self, i: OpIndex, sb: OpIndex, func: FunctionKey, code: &Bytecode) -> String {
+        if i < 0 {
+            return "a constant".to_owned();
+        }
+        match self.writers.get((sb + i) as usize).copied().flatten() {
+            Some((f, pc)) => {
+                let pos = position(code, f, pc).map_or(String::new(), |p| format!(" ({})", p));
+                if f == func {
+                    format!("last written at pc {}{}", pc, pos)
+                } else {
+                    format!("last written at pc {} of function {:?}{}", pc, f, pos)
+                }
+            }
+            None => "not written by an instruction".to_owned(),
+        }
+    }
+}
+
+fn position(code: &Bytecode, func: FunctionKey, pc: OpIndex) -> Option<go_parser::FilePos> {
+    let pos = (*code.objects.functions[func].pos.get(pc as usize)?)?;
+    code.file_set.as_ref()?.position(pos as usize)
+}
@@ -0,0 +1,391 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Tiered execution, turned on by `RunOptions::tiering_threshold`.
+//! The calls of every function are counted, and once a function has been called
+//! the threshold times it's promoted: a copy of its code is made, with the
+//! instructions the profile gathered so far says are worth it replaced by
+//! specialized forms, and the function runs the copy from then on.
+//!
+//! The only specialization is the inline cache of interface method calls. A
+//! BIND_I_METHOD that has only seen one dynamic type, and the CALL of the method it
+//! binds, become BIND_I_METHOD_CACHED and CALL_CACHED. When the interface holds the
+//! cached type, the bind leaves the receiver in the register instead of a closure
+//! allocated for it, and the call calls the cached method with it. Otherwise the
+//! bind falls back to the generic binding, and the call sees a closure and calls it
+//! the generic way, so a wrong guess only costs the time it would have saved.
+//!
+//! The rewrites are one for one, a promoted function has the same length and the
+//! same jumps as the original, so that a frame that was running the original code
+//! continues in the copy. Such a frame may have bound a method the generic way
+//! before a CALL_CACHED, which is why the call checks what it's given.
+
+use crate::value::*;
+use go_parser::PiggyVecKey;
+use std::cell::{Cell, OnceCell, RefCell};
+
+/// The specialized code of a promoted function
+pub(crate) struct Promoted {
+    pub code: Vec<Instruction>,
+    /// The inline caches, at the pcs of both of the instructions of a cached call
+    pub caches: Vec<Option<InlineCache>>,
+}
+
+/// The dynamic type and the method a cached interface call expects
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InlineCache {
+    pub meta: Meta,
+    pub func: FunctionKey,
+}
+
+/// What a BIND_I_METHOD has bound so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Site {
+    Unseen,
+    Mono(InlineCache),
+    Mega,
+}
+
+pub(crate) struct Tiering {
+    threshold: u32,
+    calls: Vec<Cell<u32>>,
+    /// The sites of the functions not promoted yet, by pc
+    sites: RefCell<Vec<Vec<Site>>>,
+    promoted: Vec<OnceCell<Promoted>>,
+}
+
+impl Tiering {
+    pub fn new(threshold: u32, functions: &FunctionObjs) -> Tiering {
+        let count = functions.vec().len();
+        Tiering {
+            threshold: threshold.max(1),
+            calls: (0..count).map(|_| Cell::new(0)).collect(),
+            sites: RefCell::new(vec![vec![]; count]),
+            promoted: (0..count).map(|_| OnceCell::new()).collect(),
+        }
+    }
+
+    /// The code `func` runs, the specialized one once it's promoted
+    #[inline]
+    pub fn code<'a>(
+        tiering: Option<&'a Tiering>,
+        key: FunctionKey,
+        func: &'a FunctionObj,
+    ) -> &'a Vec<Instruction> {
+        match tiering.and_then(|t| t.promoted[key.as_usize()].get()) {
+            Some(p) => &p.code,
+            None => &func.code,
+        }
+    }
+
+    /// The cache of the cached instruction at `pc` of `key`
+    #[inline]
+    pub fn cache(&self, key: FunctionKey, pc: OpIndex) -> InlineCache {
+        self.promoted[key.as_usize()].get().unwrap().caches[pc as usize].unwrap()
+    }
+
+    /// Counts a call of `func`, and promotes it when the count reaches the threshold.
+    #[inline]
+    pub fn count_call(&self, key: FunctionKey, func: &FunctionObj) {
+        let count = &self.calls[key.as_usize()];
+        let n = count.get().saturating_add(1);
+        count.set(n);
+        if n == self.threshold {
+            self.promote(key, func);
+        }
+    }
+
+    /// Records what the BIND_I_METHOD at `pc` of `key` binds for `iface`.
+    pub fn record_bind(&self, key: FunctionKey, pc: OpIndex, iface: &InterfaceObj, index: usize) {
+        if self.promoted[key.as_usize()].get().is_some() {
+            return;
+        }
+        let seen = match iface {
+            InterfaceObj::Gos(_, Some((meta, bindings))) => match &bindings[index] {
+                // the methods of embedded fields are reached through them, they aren't cached
+                Binding4Runtime::Struct(func, _, None) => Some(InlineCache {
+                    meta: *meta,
+                    func: *func,
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut sites = self.sites.borrow_mut();
+        let sites = &mut sites[key.as_usize()];
+        let pc = pc as usize;
+        if sites.len() <= pc {
+            sites.resize(pc + 1, Site::Unseen);
+        }
+        sites[pc] = match (sites[pc], seen) {
+            (Site::Unseen, Some(c)) => Site::Mono(c),
+            (Site::Mono(c0), Some(c)) if c0 == c => Site::Mono(c),
+            _ => Site::Mega,
+        };
+    }
+
+    fn promote(&self, key: FunctionKey, func: &FunctionObj) {
+        let sites = std::mem::take(&mut self.sites.borrow_mut()[key.as_usize()]);
+        let promoted = specialize(&func.code, first_register(func), &sites);
+        debug_assert_eq!(
+            verify(&func.code, first_register(func), &promoted),
+            Ok(()),
+            "{:?}",
+            key
+        );
+        let _ = self.promoted[key.as_usize()].set(promoted);
+    }
+}
+
+/// The registers, the temporaries of expressions, come after the variables
+fn first_register(func: &FunctionObj) -> OpIndex {
+    func.ret_count() + func.param_count() + func.local_count()
+}
+
+fn specialize(code: &[Instruction], first_reg: OpIndex, sites: &[Site]) -> Promoted {
+    let mut result = Promoted {
+        code: code.to_vec(),
+        caches: vec![None; code.len()],
+    };
+    for (i, site) in sites.iter().enumerate() {
+        if let Site::Mono(cache) = site {
+            if let Some(j) = paired_call(code, i, first_reg) {
+                result.code[i].op0 = Opcode::BIND_I_METHOD_CACHED;
+                result.code[j].op0 = Opcode::CALL_CACHED;
+                result.caches[i] = Some(*cache);
+                result.caches[j] = Some(*cache);
+            }
+        }
+    }
+    result
+}
+
+/// The CALL of the method the BIND_I_METHOD at `i` binds, if it can leave the
+/// receiver there instead: the method is bound to a register and called with a
+/// receiver slot right after, the register isn't used in between, and the control
+/// doesn't leave the call expression, or enter it, in between.
+fn paired_call(code: &[Instruction], i: usize, first_reg: OpIndex) -> Option<usize> {
+    let bind = &code[i];
+    let r = bind.d;
+    if bind.op0 != Opcode::BIND_I_METHOD || r < first_reg {
+        return None;
+    }
+    // the operands that aren't indices may only make it miss a pair
+    let j = i
+        + 1
+        + code[i + 1..]
+            .iter()
+            .position(|x| x.d == r || x.s0 == r || x.s1 == r)?;
+    let call = &code[j];
+    if call.op0 != Opcode::CALL
+        || call.d != r
+        || call.t0 != ValueType::FlagA
+        || call.t1 != ValueType::FlagA
+    {
+        return None;
+    }
+    let inside = |pc: usize| i < pc && pc < j;
+    for (k, x) in code.iter().enumerate() {
+        match x.op0 {
+            Opcode::JUMP | Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {
+                // the jumps of && and || stay in the expression
+                let target = k as OpIndex + 1 + x.d;
+                if inside(k) != (inside(target as usize) || target as usize == j) {
+                    return None;
+                }
+            }
+            Opcode::RETURN
+            | Opcode::SWITCH
+            | Opcode::SELECT
+            | Opcode::RANGE_INIT
+            | Opcode::RANGE
+            | Opcode::LOAD_INIT_FUNC
+            | Opcode::IMPORT
+                if inside(k) =>
+            {
+                return None
+            }
+            _ => {}
+        }
+    }
+    Some(j)
+}
+
+/// Checks that `promoted` is a valid specialization of `code`: only the pairs of
+/// instructions `paired_call` accepts are cached, with the same cache, and
+/// everything else is left as it is.
+pub(crate) fn verify(
+    code: &[Instruction],
+    first_reg: OpIndex,
+    promoted: &Promoted,
+) -> Result<(), String> {
+    if promoted.code.len() != code.len() || promoted.caches.len() != code.len() {
+        return Err("the length of the code changed".to_owned());
+    }
+    let same_operands = |a: &Instruction, b: &Instruction| {
+        (a.op1, a.t0, a.t1, a.d, a.s0, a.s1) == (b.op1, b.t0, b.t1, b.d, b.s0, b.s1)
+    };
+    let mut paired = vec![false; code.len()];
+    for (pc, (a, b)) in code.iter().zip(promoted.code.iter()).enumerate() {
+        if !same_operands(a, b) {
+            return Err(format!("the operands changed at pc {}", pc));
+        }
+        let cache = promoted.caches[pc];
+        match (a.op0, b.op0) {
+            (Opcode::BIND_I_METHOD_CACHED | Opcode::CALL_CACHED, _) => {
+                return Err(format!(
+                    "a specialized instruction in the original at pc {}",
+                    pc
+                ))
+            }
+            (Opcode::BIND_I_METHOD, Opcode::BIND_I_METHOD_CACHED) => {
+                let j = paired_call(code, pc, first_reg)
+                    .ok_or_else(|| format!("no call for the cached bind at pc {}", pc))?;
+                if promoted.code[j].op0 != Opcode::CALL_CACHED
+                    || cache.is_none()
+                    || promoted.caches[j] != cache
+                {
+                    return Err(format!(
+                        "the call of the cached bind at pc {} isn't cached with it",
+                        pc
+                    ));
+                }
+                paired[j] = true;
+            }
+            (Opcode::CALL, Opcode::CALL_CACHED) => {
+                if !paired[pc] {
+                    return Err(format!("no cached bind for the cached call at pc {}", pc));
+                }
+            }
+            (x, y) if x == y => {
+                if cache.is_some() {
+                    return Err(format!("a cache for the generic instruction at pc {}", pc));
+                }
+            }
+            (x, y) => return Err(format!("{} is rewritten to {} at pc {}", x, y, pc)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inst(
+        op0: Opcode,
+        t0: ValueType,
+        t1: ValueType,
+        d: OpIndex,
+        s0: OpIndex,
+        s1: OpIndex,
+    ) -> Instruction {
+        Instruction {
+            op0,
+            op1: Opcode::VOID,
+            t0,
+            t1,
+            d,
+            s0,
+            s1,
+        }
+    }
+
+    fn op(op0: Opcode, d: OpIndex, s0: OpIndex, s1: OpIndex) -> Instruction {
+        inst(op0, ValueType::Void, ValueType::Void, d, s0, s1)
+    }
+
+    fn call(d: OpIndex, sb: OpIndex) -> Instruction {
+        inst(
+            Opcode::CALL,
+            ValueType::FlagA,
+            ValueType::FlagA,
+            d,
+            sb,
+            OpIndex::MAX,
+        )
+    }
+
+    fn mono() -> Site {
+        Site::Mono(InlineCache {
+            meta: Meta::new(MetadataKey::from(7), 0, false),
+            func: FunctionKey::from(3),
+        })
+    }
+
+    // `x.M(a && b)` with the local x at 0 and a, b at 1, 2, then `return`
+    fn code() -> Vec<Instruction> {
+        vec![
+            op(Opcode::BIND_I_METHOD, 3, 0, 0),
+            op(Opcode::DUPLICATE, 6, 1, OpIndex::MAX),
+            op(Opcode::JUMP_IF_NOT, 1, 6, OpIndex::MAX),
+            op(Opcode::DUPLICATE, 6, 2, OpIndex::MAX),
+            call(3, 4),
+            op(Opcode::RETURN, OpIndex::MAX, OpIndex::MAX, OpIndex::MAX),
+        ]
+    }
+
+    #[test]
+    fn test_specialize() {
+        let code = code();
+        let sites = vec![mono()];
+        let p = specialize(&code, 3, &sites);
+        assert_eq!(p.code[0].op0, Opcode::BIND_I_METHOD_CACHED);
+        assert_eq!(p.code[4].op0, Opcode::CALL_CACHED);
+        assert_eq!(verify(&code, 3, &p), Ok(()));
+
+        // seen more than one type, or bound to a variable
+        let p = specialize(&code, 3, &[Site::Mega]);
+        assert!(p.caches.iter().all(|c| c.is_none()));
+        assert_eq!(verify(&code, 3, &p), Ok(()));
+        let p = specialize(&code, 4, &sites);
+        assert!(p.caches.iter().all(|c| c.is_none()));
+
+        // the register is read before the call
+        let mut read = code.clone();
+        read[3].s0 = 3;
+        assert!(specialize(&read, 3, &sites)
+            .caches
+            .iter()
+            .all(|c| c.is_none()));
+
+        // a jump from outside into the arguments
+        let mut jump_in = code.clone();
+        jump_in.push(op(Opcode::JUMP, -5, OpIndex::MAX, OpIndex::MAX));
+        assert!(specialize(&jump_in, 3, &sites)
+            .caches
+            .iter()
+            .all(|c| c.is_none()));
+    }
+
+    #[test]
+    fn test_verify() {
+        let code = code();
+        let good = specialize(&code, 3, &[mono()]);
+
+        let mut p = specialize(&code, 3, &[mono()]);
+        p.code[4].op0 = Opcode::CALL;
+        assert!(verify(&code, 3, &p).is_err());
+
+        let mut p = specialize(&code, 3, &[]);
+        p.code[4].op0 = Opcode::CALL_CACHED;
+        p.caches[4] = good.caches[4];
+        assert!(verify(&code, 3, &p).is_err());
+
+        let mut p = specialize(&code, 3, &[mono()]);
+        p.caches[4] = None;
+        assert!(verify(&code, 3, &p).is_err());
+
+        let mut p = specialize(&code, 3, &[mono()]);
+        p.code[1].s0 = 2;
+        assert!(verify(&code, 3, &p).is_err());
+
+        let mut p = specialize(&code, 3, &[mono()]);
+        p.code.pop();
+        assert!(verify(&code, 3, &p).is_err());
+
+        // the pair is only valid where the register is a temporary
+        assert!(verify(&code, 4, &good).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! On the stack the results of a call are just consecutive slots. Wherever they
+//! cross a boundary of the VM, from an FFI handler or back to the host, they are
+//! a `ResultTuple` instead, which can only be made by checking the values against
+//! the `TupleDesc` of the declared results, so that a handler returning too few
+//! values can't corrupt the stack of its caller.
+
+use crate::value::*;
+
+/// The ValueTypes of the declared results of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TupleDesc {
+    types: Vec<ValueType>,
+}
+
+impl TupleDesc {
+    pub fn results_of(sig: &SigMetadata, metas: &MetadataObjs) -> TupleDesc {
+        TupleDesc {
+            types: sig.results.iter().map(|m| m.value_type(metas)).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Makes a tuple of `vals` if they agree with the declaration. `func` is the
+    /// function, and `provider` what produced the values, for the error.
+    pub fn check(
+        &self,
+        func: &str,
+        provider: &str,
+        vals: Vec<GosValue>,
+    ) -> Result<ResultTuple, TupleError> {
+        if vals.len() != self.types.len() {
+            return Err(TupleError::new(
+                func,
+                format!(
+                    "returns {}, {} provided {}",
+                    count(self.types.len()),
+                    provider,
+                    vals.len()
+                ),
+            ));
+        }
+        for (i, (t, v)) in self.types.iter().zip(vals.iter()).enumerate() {
+            if v.typ() != *t {
+                return Err(TupleError::new(
+                    func,
+                    format!(
+                        "returns {} as result {}, {} provided {}",
+                        t,
+                        i,
+                        provider,
+                        v.typ()
+                    ),
+                ));
+            }
+        }
+        Ok(ResultTuple { vals })
+    }
+}
+
+fn count(n: usize) -> String {
+    match n {
+        1 => "1 value".to_owned(),
+        _ => format!("{} values", n),
+    }
+}
+
+/// Results checked against a `TupleDesc`
+#[derive(Debug)]
+pub struct ResultTuple {
+    vals: Vec<GosValue>,
+}
+
+impl ResultTuple {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vals.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.vals.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, GosValue> {
+        self.vals.iter()
+    }
+
+    #[inline]
+    pub fn into_vec(self) -> Vec<GosValue> {
+        self.vals
+    }
+}
+
+/// Results that don't agree with the declaration of the function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TupleError {
+    pub func: String,
+    pub msg: String,
+}
+
+impl TupleError {
+    fn new(func: &str, msg: String) -> TupleError {
+        TupleError {
+            func: func.to_owned(),
+            msg,
+        }
+    }
+}
+
+impl std::fmt::Display for TupleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "function {} {}", self.func, self.msg)
+    }
+}
+
+impl std::error::Error for TupleError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let desc = TupleDesc {
+            types: vec![ValueType::Int, ValueType::String],
+        };
+        let err = desc.check("f", "handler", vec![1isize.into()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function f returns 2 values, handler provided 1"
+        );
+        let vals = vec![1isize.into(), 2isize.into(), 3isize.into()];
+        let err = desc.check("f", "handler", vals).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function f returns 2 values, handler provided 3"
+        );
+        let err = desc
+            .check("f", "handler", vec![1isize.into(), 2isize.into()])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function f returns String as result 1, handler provided Int"
+        );
+        let vals = vec![1isize.into(), GosValue::with_str("s")];
+        assert_eq!(desc.check("f", "handler", vals).unwrap().len(), 2);
+
+        let desc = TupleDesc {
+            types: vec![ValueType::Int],
+        };
+        let err = desc.check("g", "the call", vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function g returns 1 value, the call provided 0"
+        );
+    }
+}
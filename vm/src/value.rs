@@ -12,6 +12,7 @@ use crate::gc::GcContainer;
 pub use crate::instruction::*;
 pub use crate::metadata::*;
 pub use crate::objects::*;
+use crate::value_ops;
 #[cfg(feature = "serde_borsh")]
 use borsh::{
     maybestd::io::Result as BorshResult, maybestd::io::Write as BorshWrite, BorshDeserialize,
@@ -23,7 +24,6 @@ use std::io::{Error, ErrorKind};
 use ordered_float;
 use std::cell::Cell;
 use std::cmp::Ordering;
-use std::collections::VecDeque;
 use std::convert::From;
 use std::fmt::{self, Debug, Display, Write};
 use std::hash::{Hash, Hasher};
@@ -36,24 +36,6 @@ pub type F32 = ordered_float::OrderedFloat<f32>;
 pub type F64 = ordered_float::OrderedFloat<f64>;
 pub type IRC = i32;
 pub type RCount = Cell<IRC>;
-pub type RCQueue = VecDeque<IRC>;
-
-#[inline]
-pub(crate) fn rcount_mark_and_queue(rc: &RCount, queue: &mut RCQueue) {
-    let i = rc.get();
-    if i <= 0 {
-        queue.push_back(i);
-        rc.set(1);
-    }
-}
-
-fn ref_ptr_eq<T>(x: Option<&T>, y: Option<&T>) -> bool {
-    match (x, y) {
-        (Some(a), Some(b)) => a as *const T == b as *const T,
-        (None, None) => true,
-        _ => false,
-    }
-}
 
 macro_rules! nil_err_str {
     () => {
@@ -85,6 +67,49 @@ macro_rules! union_shift {
     };
 }
 
+macro_rules! union_div {
+    ($a:ident, $b:ident, $name:tt, $op:ident) => {
+        match $b.$name {
+            0 => Err(divide_by_zero_err()),
+            b => Ok(ValueData {
+                $name: $a.$name.$op(b),
+            }),
+        }
+    };
+}
+
+macro_rules! union_shr {
+    ($a:ident, $b:ident, $name:tt, signed) => {
+        ValueData {
+            // the sign bit fills the result once the count reaches the width
+            $name: $a.$name >> (*$b).min(std::mem::size_of_val(&$a.$name) as u32 * 8 - 1),
+        }
+    };
+    ($a:ident, $b:ident, $name:tt, unsigned) => {
+        union_shift!($a, $b, $name, checked_shr)
+    };
+}
+
+macro_rules! union_op_checked {
+    ($t:ident, $a:ident, $b:ident, $name:tt, $op:ident, $desc:expr, $sym:expr) => {
+        match $a.$name.$op($b.$name) {
+            Some(v) => Ok(ValueData { $name: v }),
+            None => Err(overflow_err($t, $desc, &$a.$name, $sym, &$b.$name)),
+        }
+    };
+}
+
+macro_rules! union_shl_checked {
+    ($t:ident, $a:ident, $b:ident, $name:tt) => {{
+        let v = $a.$name.checked_shl(*$b).unwrap_or(0);
+        if v.checked_shr(*$b).unwrap_or(0) == $a.$name {
+            Ok(ValueData { $name: v })
+        } else {
+            Err(overflow_err($t, "left shift", &$a.$name, "<<", $b))
+        }
+    }};
+}
+
 macro_rules! union_cmp {
     ($a:ident, $b:ident, $name:tt, $op:tt) => {
         $a.$name $op $b.$name
@@ -113,6 +138,46 @@ macro_rules! binary_op_int_float_str {
     };
 }
 
+macro_rules! binary_op_int_checked {
+    ($t:ident, $a:ident, $b:ident, $op:ident, $desc:expr, $sym:expr) => {
+        match $t {
+            ValueType::Int => union_op_checked!($t, $a, $b, int, $op, $desc, $sym),
+            ValueType::Int8 => union_op_checked!($t, $a, $b, int8, $op, $desc, $sym),
+            ValueType::Int16 => union_op_checked!($t, $a, $b, int16, $op, $desc, $sym),
+            ValueType::Int32 => union_op_checked!($t, $a, $b, int32, $op, $desc, $sym),
+            ValueType::Int64 => union_op_checked!($t, $a, $b, int64, $op, $desc, $sym),
+            ValueType::Uint => union_op_checked!($t, $a, $b, uint, $op, $desc, $sym),
+            ValueType::UintPtr => union_op_checked!($t, $a, $b, uint_ptr, $op, $desc, $sym),
+            ValueType::Uint8 => union_op_checked!($t, $a, $b, uint8, $op, $desc, $sym),
+            ValueType::Uint16 => union_op_checked!($t, $a, $b, uint16, $op, $desc, $sym),
+            ValueType::Uint32 => union_op_checked!($t, $a, $b, uint32, $op, $desc, $sym),
+            ValueType::Uint64 => union_op_checked!($t, $a, $b, uint64, $op, $desc, $sym),
+            _ => unreachable!(),
+        }
+    };
+}
+
+macro_rules! div_int_float {
+    ($t:ident, $a:ident, $b:ident, $op:ident, $float_op:tt) => {
+        match $t {
+            ValueType::Int => union_div!($a, $b, int, $op),
+            ValueType::Int8 => union_div!($a, $b, int8, $op),
+            ValueType::Int16 => union_div!($a, $b, int16, $op),
+            ValueType::Int32 => union_div!($a, $b, int32, $op),
+            ValueType::Int64 => union_div!($a, $b, int64, $op),
+            ValueType::Uint => union_div!($a, $b, uint, $op),
+            ValueType::UintPtr => union_div!($a, $b, uint_ptr, $op),
+            ValueType::Uint8 => union_div!($a, $b, uint8, $op),
+            ValueType::Uint16 => union_div!($a, $b, uint16, $op),
+            ValueType::Uint32 => union_div!($a, $b, uint32, $op),
+            ValueType::Uint64 => union_div!($a, $b, uint64, $op),
+            ValueType::Float32 => Ok(union_op!($a, $b, float32, $float_op)),
+            ValueType::Float64 => Ok(union_op!($a, $b, float64, $float_op)),
+            _ => unreachable!(),
+        }
+    };
+}
+
 macro_rules! binary_op_int_no_wrap {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -147,8 +212,9 @@ macro_rules! cmp_bool_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            // IEEE 754 rather than the total order of OrderedFloat, NaN is unordered
+            ValueType::Float32 => $a.float32.0 $op $b.float32.0,
+            ValueType::Float64 => $a.float64.0 $op $b.float64.0,
             _ => unreachable!(),
         }
     };
@@ -168,8 +234,9 @@ macro_rules! cmp_int_float {
             ValueType::Uint16 => union_cmp!($a, $b, uint16, $op),
             ValueType::Uint32 => union_cmp!($a, $b, uint32, $op),
             ValueType::Uint64 => union_cmp!($a, $b, uint64, $op),
-            ValueType::Float32 => union_cmp!($a, $b, float32, $op),
-            ValueType::Float64 => union_cmp!($a, $b, float64, $op),
+            // IEEE 754 rather than the total order of OrderedFloat, NaN is unordered
+            ValueType::Float32 => $a.float32.0 $op $b.float32.0,
+            ValueType::Float64 => $a.float64.0 $op $b.float64.0,
             _ => unreachable!(),
         }
     };
@@ -194,6 +261,44 @@ macro_rules! shift_int {
     };
 }
 
+macro_rules! shr_int {
+    ($t:ident, $a:ident, $b:ident) => {
+        match $t {
+            ValueType::Int => union_shr!($a, $b, int, signed),
+            ValueType::Int8 => union_shr!($a, $b, int8, signed),
+            ValueType::Int16 => union_shr!($a, $b, int16, signed),
+            ValueType::Int32 => union_shr!($a, $b, int32, signed),
+            ValueType::Int64 => union_shr!($a, $b, int64, signed),
+            ValueType::Uint => union_shr!($a, $b, uint, unsigned),
+            ValueType::UintPtr => union_shr!($a, $b, uint_ptr, unsigned),
+            ValueType::Uint8 => union_shr!($a, $b, uint8, unsigned),
+            ValueType::Uint16 => union_shr!($a, $b, uint16, unsigned),
+            ValueType::Uint32 => union_shr!($a, $b, uint32, unsigned),
+            ValueType::Uint64 => union_shr!($a, $b, uint64, unsigned),
+            _ => unreachable!(),
+        }
+    };
+}
+
+macro_rules! shl_int_checked {
+    ($t:ident, $a:ident, $b:ident) => {
+        match $t {
+            ValueType::Int => union_shl_checked!($t, $a, $b, int),
+            ValueType::Int8 => union_shl_checked!($t, $a, $b, int8),
+            ValueType::Int16 => union_shl_checked!($t, $a, $b, int16),
+            ValueType::Int32 => union_shl_checked!($t, $a, $b, int32),
+            ValueType::Int64 => union_shl_checked!($t, $a, $b, int64),
+            ValueType::Uint => union_shl_checked!($t, $a, $b, uint),
+            ValueType::UintPtr => union_shl_checked!($t, $a, $b, uint_ptr),
+            ValueType::Uint8 => union_shl_checked!($t, $a, $b, uint8),
+            ValueType::Uint16 => union_shl_checked!($t, $a, $b, uint16),
+            ValueType::Uint32 => union_shl_checked!($t, $a, $b, uint32),
+            ValueType::Uint64 => union_shl_checked!($t, $a, $b, uint64),
+            _ => unreachable!(),
+        }
+    };
+}
+
 macro_rules! convert_to_int {
     ($val:expr, $vt:expr, $d_type:tt, $typ:tt) => {{
         unsafe {
@@ -269,6 +374,33 @@ impl std::convert::From<String> for RuntimeError {
 
 pub type RuntimeResult<T> = result::Result<T, RuntimeError>;
 
+/// The Go name of a basic type, used in runtime error messages
+fn type_name(t: ValueType) -> String {
+    t.to_string().to_lowercase()
+}
+
+fn divide_by_zero_err() -> RuntimeError {
+    "integer divide by zero".to_owned().into()
+}
+
+fn overflow_err(
+    t: ValueType,
+    desc: &str,
+    a: &dyn Display,
+    sym: &str,
+    b: &dyn Display,
+) -> RuntimeError {
+    format!(
+        "integer overflow: {} {} of {} {} {}",
+        type_name(t),
+        desc,
+        a,
+        sym,
+        b
+    )
+    .into()
+}
+
 pub(crate) type OptionBox<T> = Option<Box<T>>;
 
 pub(crate) type OptionRc<T> = Option<Rc<T>>;
@@ -683,22 +815,63 @@ impl ValueData {
         v
     }
 
+    /// Like cast_copyable, but fails if an integer conversion changes the value
+    #[inline]
+    pub(crate) fn cast_copyable_checked(
+        &self,
+        from: ValueType,
+        to: ValueType,
+    ) -> RuntimeResult<ValueData> {
+        let v = self.cast_copyable(from, to);
+        match (self.as_i128(from), v.as_i128(to)) {
+            (Some(a), Some(b)) if a != b => Err(format!(
+                "integer overflow: conversion of {} {} to {}",
+                type_name(from),
+                a,
+                type_name(to)
+            )
+            .into()),
+            _ => Ok(v),
+        }
+    }
+
+    #[inline]
+    fn as_i128(&self, t: ValueType) -> Option<i128> {
+        unsafe {
+            match t {
+                ValueType::Int => Some(self.int as i128),
+                ValueType::Int8 => Some(self.int8 as i128),
+                ValueType::Int16 => Some(self.int16 as i128),
+                ValueType::Int32 => Some(self.int32 as i128),
+                ValueType::Int64 => Some(self.int64 as i128),
+                ValueType::Uint => Some(self.uint as i128),
+                ValueType::UintPtr => Some(self.uint_ptr as i128),
+                ValueType::Uint8 => Some(self.uint8 as i128),
+                ValueType::Uint16 => Some(self.uint16 as i128),
+                ValueType::Uint32 => Some(self.uint32 as i128),
+                ValueType::Uint64 => Some(self.uint64 as i128),
+                _ => None,
+            }
+        }
+    }
+
     #[inline]
     pub(crate) fn unary_negate(&self, t: ValueType) -> ValueData {
         let mut v = unsafe { self.copy_non_ptr() };
         match t {
-            ValueType::Int => v.int = -unsafe { self.int },
-            ValueType::Int8 => v.int8 = -unsafe { self.int8 },
-            ValueType::Int16 => v.int16 = -unsafe { self.int16 },
-            ValueType::Int32 => v.int32 = -unsafe { self.int32 },
-            ValueType::Int64 => v.int64 = -unsafe { self.int64 },
+            ValueType::Int => v.int = unsafe { self.int }.wrapping_neg(),
+            ValueType::Int8 => v.int8 = unsafe { self.int8 }.wrapping_neg(),
+            ValueType::Int16 => v.int16 = unsafe { self.int16 }.wrapping_neg(),
+            ValueType::Int32 => v.int32 = unsafe { self.int32 }.wrapping_neg(),
+            ValueType::Int64 => v.int64 = unsafe { self.int64 }.wrapping_neg(),
             ValueType::Float32 => v.float32 = -unsafe { self.float32 },
             ValueType::Float64 => v.float64 = -unsafe { self.float64 },
-            ValueType::Uint => v.uint = unsafe { (!0) ^ self.uint } + 1,
-            ValueType::Uint8 => v.uint8 = unsafe { (!0) ^ self.uint8 } + 1,
-            ValueType::Uint16 => v.uint16 = unsafe { (!0) ^ self.uint16 } + 1,
-            ValueType::Uint32 => v.uint32 = unsafe { (!0) ^ self.uint32 } + 1,
-            ValueType::Uint64 => v.uint64 = unsafe { (!0) ^ self.uint64 } + 1,
+            ValueType::Uint => v.uint = unsafe { self.uint }.wrapping_neg(),
+            ValueType::UintPtr => v.uint_ptr = unsafe { self.uint_ptr }.wrapping_neg(),
+            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 }.wrapping_neg(),
+            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 }.wrapping_neg(),
+            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 }.wrapping_neg(),
+            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 }.wrapping_neg(),
             _ => unreachable!(),
         };
         v
@@ -735,18 +908,19 @@ impl ValueData {
     pub(crate) fn inc(&self, t: ValueType) -> ValueData {
         let mut v = unsafe { self.copy_non_ptr() };
         match t {
-            ValueType::Int => v.int = unsafe { self.int } + 1,
-            ValueType::Int8 => v.int8 = unsafe { self.int8 } + 1,
-            ValueType::Int16 => v.int16 = unsafe { self.int16 } + 1,
-            ValueType::Int32 => v.int32 = unsafe { self.int32 } + 1,
-            ValueType::Int64 => v.int64 = unsafe { self.int64 } + 1,
+            ValueType::Int => v.int = unsafe { self.int }.wrapping_add(1),
+            ValueType::Int8 => v.int8 = unsafe { self.int8 }.wrapping_add(1),
+            ValueType::Int16 => v.int16 = unsafe { self.int16 }.wrapping_add(1),
+            ValueType::Int32 => v.int32 = unsafe { self.int32 }.wrapping_add(1),
+            ValueType::Int64 => v.int64 = unsafe { self.int64 }.wrapping_add(1),
             ValueType::Float32 => v.float32 = unsafe { self.float32 } + 1.0,
             ValueType::Float64 => v.float64 = unsafe { self.float64 } + 1.0,
-            ValueType::Uint => v.uint = unsafe { self.uint } + 1,
-            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 } + 1,
-            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 } + 1,
-            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 } + 1,
-            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 } + 1,
+            ValueType::Uint => v.uint = unsafe { self.uint }.wrapping_add(1),
+            ValueType::UintPtr => v.uint_ptr = unsafe { self.uint_ptr }.wrapping_add(1),
+            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 }.wrapping_add(1),
+            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 }.wrapping_add(1),
+            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 }.wrapping_add(1),
+            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 }.wrapping_add(1),
             _ => unreachable!(),
         };
         v
@@ -756,18 +930,19 @@ impl ValueData {
     pub(crate) fn dec(&self, t: ValueType) -> ValueData {
         let mut v = unsafe { self.copy_non_ptr() };
         match t {
-            ValueType::Int => v.int = unsafe { self.int } - 1,
-            ValueType::Int8 => v.int8 = unsafe { self.int8 } - 1,
-            ValueType::Int16 => v.int16 = unsafe { self.int16 } - 1,
-            ValueType::Int32 => v.int32 = unsafe { self.int32 } - 1,
-            ValueType::Int64 => v.int64 = unsafe { self.int64 } - 1,
+            ValueType::Int => v.int = unsafe { self.int }.wrapping_sub(1),
+            ValueType::Int8 => v.int8 = unsafe { self.int8 }.wrapping_sub(1),
+            ValueType::Int16 => v.int16 = unsafe { self.int16 }.wrapping_sub(1),
+            ValueType::Int32 => v.int32 = unsafe { self.int32 }.wrapping_sub(1),
+            ValueType::Int64 => v.int64 = unsafe { self.int64 }.wrapping_sub(1),
             ValueType::Float32 => v.float32 = unsafe { self.float32 } - 1.0,
             ValueType::Float64 => v.float64 = unsafe { self.float64 } - 1.0,
-            ValueType::Uint => v.uint = unsafe { self.uint } - 1,
-            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 } - 1,
-            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 } - 1,
-            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 } - 1,
-            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 } - 1,
+            ValueType::Uint => v.uint = unsafe { self.uint }.wrapping_sub(1),
+            ValueType::UintPtr => v.uint_ptr = unsafe { self.uint_ptr }.wrapping_sub(1),
+            ValueType::Uint8 => v.uint8 = unsafe { self.uint8 }.wrapping_sub(1),
+            ValueType::Uint16 => v.uint16 = unsafe { self.uint16 }.wrapping_sub(1),
+            ValueType::Uint32 => v.uint32 = unsafe { self.uint32 }.wrapping_sub(1),
+            ValueType::Uint64 => v.uint64 = unsafe { self.uint64 }.wrapping_sub(1),
             _ => unreachable!(),
         };
         v
@@ -788,14 +963,16 @@ impl ValueData {
         unsafe { binary_op_int_float_str!(t, self, b, *) }
     }
 
+    /// Integer division panics on a zero divisor, and wraps around like the other
+    /// operators: the most negative value divided by -1 is itself.
     #[inline]
-    pub(crate) fn binary_op_quo(&self, b: &ValueData, t: ValueType) -> ValueData {
-        unsafe { binary_op_int_float_str!(t, self, b, /) }
+    pub(crate) fn binary_op_quo(&self, b: &ValueData, t: ValueType) -> RuntimeResult<ValueData> {
+        unsafe { div_int_float!(t, self, b, wrapping_div, /) }
     }
 
     #[inline]
-    pub(crate) fn binary_op_rem(&self, b: &ValueData, t: ValueType) -> ValueData {
-        unsafe { binary_op_int_no_wrap!(t, self, b, %) }
+    pub(crate) fn binary_op_rem(&self, b: &ValueData, t: ValueType) -> RuntimeResult<ValueData> {
+        unsafe { div_int_float!(t, self, b, wrapping_rem, %) }
     }
 
     #[inline]
@@ -820,7 +997,49 @@ impl ValueData {
 
     #[inline]
     pub(crate) fn binary_op_shr(&self, b: &u32, t: ValueType) -> ValueData {
-        unsafe { shift_int!(t, self, b, checked_shr) }
+        unsafe { shr_int!(t, self, b) }
+    }
+
+    /// The count of a shift, of any integer type. A negative one panics, the ones
+    /// not fitting in u32 are way past the width of any type and shift all the bits out.
+    #[inline]
+    pub(crate) fn shift_count(&self, t: ValueType) -> RuntimeResult<u32> {
+        match self.as_i128(t).unwrap() {
+            n if n < 0 => Err("negative shift amount".to_owned().into()),
+            n => Ok(n.min(u32::MAX as i128) as u32),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn binary_op_add_checked(
+        &self,
+        b: &ValueData,
+        t: ValueType,
+    ) -> RuntimeResult<ValueData> {
+        unsafe { binary_op_int_checked!(t, self, b, checked_add, "addition", "+") }
+    }
+
+    #[inline]
+    pub(crate) fn binary_op_sub_checked(
+        &self,
+        b: &ValueData,
+        t: ValueType,
+    ) -> RuntimeResult<ValueData> {
+        unsafe { binary_op_int_checked!(t, self, b, checked_sub, "subtraction", "-") }
+    }
+
+    #[inline]
+    pub(crate) fn binary_op_mul_checked(
+        &self,
+        b: &ValueData,
+        t: ValueType,
+    ) -> RuntimeResult<ValueData> {
+        unsafe { binary_op_int_checked!(t, self, b, checked_mul, "multiplication", "*") }
+    }
+
+    #[inline]
+    pub(crate) fn binary_op_shl_checked(&self, b: &u32, t: ValueType) -> RuntimeResult<ValueData> {
+        unsafe { shl_int_checked!(t, self, b) }
     }
 
     #[inline]
@@ -864,12 +1083,16 @@ impl ValueData {
 
     #[inline]
     pub(crate) fn compare_eql(&self, b: &ValueData, t: ValueType) -> bool {
+        if t == ValueType::Complex64 {
+            let (x, y) = (self.as_complex64(), b.as_complex64());
+            return x.r.0 == y.r.0 && x.i.0 == y.i.0;
+        }
         unsafe { cmp_bool_int_float!(t, self, b, ==) }
     }
 
     #[inline]
     pub(crate) fn compare_neq(&self, b: &ValueData, t: ValueType) -> bool {
-        unsafe { cmp_bool_int_float!(t, self, b, !=) }
+        !self.compare_eql(b, t)
     }
 
     #[inline]
@@ -1218,20 +1441,13 @@ impl ValueData {
     #[inline]
     fn copy_semantic(&self, t: ValueType, t_elem: ValueType, gcc: &GcContainer) -> ValueData {
         match t {
-            _ if t != ValueType::Array
-                && t != ValueType::Struct
-                && t != ValueType::Slice
-                && t != ValueType::Map =>
-            {
+            // maps are reference types, a copy refers to the same map
+            _ if t != ValueType::Array && t != ValueType::Struct && t != ValueType::Slice => {
                 self.clone(t)
             }
             ValueType::Array => ArrCaller::get_slow(t_elem).array_copy_semantic(self, gcc),
-            ValueType::Struct => ValueData::new_struct(StructObj::clone(&self.as_struct().0), gcc),
+            ValueType::Struct => ValueData::new_struct(self.as_struct().0.copy_semantic(gcc), gcc),
             ValueType::Slice => ArrCaller::get_slow(t_elem).slice_copy_semantic(self),
-            ValueType::Map => match self.as_map() {
-                Some(m) => ValueData::new_map(m.0.clone(), gcc),
-                None => ValueData::new_nil(t),
-            },
             _ => unreachable!(),
         }
     }
@@ -1536,7 +1752,8 @@ impl GosValue {
         gcc: &GcContainer,
     ) -> GosValue {
         let arr = GosValue::array_with_size(size, cap, val, caller, gcc);
-        GosValue::slice_array(arr, 0, size as isize, caller).unwrap()
+        let t_elem = caller.typ();
+        GosValue::new_slice(SliceObj::<AnyElem>::with_cap(arr, size, cap), t_elem)
     }
 
     #[inline]
@@ -1548,7 +1765,7 @@ impl GosValue {
         assert!(caller.typ() != ValueType::Void);
         let len = data.len();
         let arr = GosValue::array_with_data(data, caller, gcc);
-        GosValue::slice_array(arr, 0, len as isize, caller).unwrap()
+        GosValue::slice_array(arr, 0, len as isize, -1, caller).unwrap()
     }
 
     #[inline]
@@ -1556,9 +1773,16 @@ impl GosValue {
         arr: GosValue,
         begin: isize,
         end: isize,
+        max: isize,
         caller: &Box<dyn Dispatcher>,
     ) -> RuntimeResult<GosValue> {
-        caller.slice_array(arr, begin, end)
+        caller.slice_array(arr, begin, end, max)
+    }
+
+    #[inline]
+    pub(crate) fn map_with_capacity(cap: usize, gcc: &GcContainer) -> GosValue {
+        let data = ValueData::new_map(MapObj::with_capacity(cap), gcc);
+        GosValue::new(ValueType::Map, data)
     }
 
     #[inline]
@@ -1976,10 +2200,9 @@ impl GosValue {
 
     #[inline]
     pub(crate) fn slice_array_equivalent(&self, index: usize) -> RuntimeResult<(&GosValue, usize)> {
-        Ok(self
-            .as_non_nil_slice::<AnyElem>()?
+        self.as_non_nil_slice::<AnyElem>()?
             .0
-            .get_array_equivalent(index))
+            .get_array_equivalent(index)
     }
 
     pub fn slice_swap(&self, i: usize, j: usize) -> RuntimeResult<()> {
@@ -2045,13 +2268,8 @@ impl GosValue {
     }
 
     #[inline]
-    pub fn slice_string(
-        s: &GosValue,
-        begin: isize,
-        end: isize,
-        max: isize,
-    ) -> RuntimeResult<GosValue> {
-        Ok(GosValue::new_string(s.as_string().slice(begin, end, max)?))
+    pub fn slice_string(s: &GosValue, begin: isize, end: isize) -> RuntimeResult<GosValue> {
+        Ok(GosValue::new_string(s.as_string().substring(begin, end)?))
     }
 
     #[inline]
@@ -2088,61 +2306,6 @@ impl GosValue {
         }
     }
 
-    /// for gc
-    pub fn ref_sub_one(&self) {
-        match &self.typ {
-            ValueType::Pointer => {
-                self.as_pointer().map(|p| p.ref_sub_one());
-            }
-            ValueType::UnsafePtr => {
-                self.as_unsafe_ptr().map(|p| p.ptr().ref_sub_one());
-            }
-            ValueType::Interface => {
-                self.as_interface().map(|x| x.ref_sub_one());
-            }
-            ValueType::Array => self.as_gos_array().1.set(self.as_gos_array().1.get() - 1),
-            ValueType::Struct => self.as_struct().1.set(self.as_struct().1.get() - 1),
-            ValueType::Closure => {
-                self.as_closure().map(|x| x.1.set(x.1.get() - 1));
-            }
-            ValueType::Slice => {
-                self.as_gos_slice().map(|x| x.0.array().ref_sub_one());
-            }
-            ValueType::Map => {
-                self.as_map().map(|x| x.1.set(x.1.get() - 1));
-            }
-            _ => {}
-        };
-    }
-
-    /// for gc
-    pub(crate) fn mark_dirty(&self, queue: &mut RCQueue) {
-        match &self.typ {
-            ValueType::Array => rcount_mark_and_queue(&self.as_gos_array().1, queue),
-            ValueType::Pointer => {
-                self.as_pointer().map(|x| x.mark_dirty(queue));
-            }
-            ValueType::UnsafePtr => {
-                self.as_unsafe_ptr().map(|x| x.ptr().mark_dirty(queue));
-            }
-            ValueType::Closure => {
-                self.as_closure()
-                    .map(|x| rcount_mark_and_queue(&x.1, queue));
-            }
-            ValueType::Slice => {
-                self.as_gos_slice().map(|x| x.0.array().mark_dirty(queue));
-            }
-            ValueType::Map => {
-                self.as_map().map(|x| rcount_mark_and_queue(&x.1, queue));
-            }
-            ValueType::Interface => {
-                self.as_interface().map(|x| x.mark_dirty(queue));
-            }
-            ValueType::Struct => rcount_mark_and_queue(&self.as_struct().1, queue),
-            _ => {}
-        };
-    }
-
     #[inline]
     pub(crate) fn rc(&self) -> IRC {
         self.data.rc(self.typ).unwrap().get()
@@ -2202,121 +2365,18 @@ impl Clone for GosValue {
 
 impl Eq for GosValue {}
 
+/// Equality, hashing and ordering are all in value_ops, see the contract there
 impl PartialEq for GosValue {
     #[inline]
     fn eq(&self, b: &GosValue) -> bool {
-        match (self.typ, b.typ) {
-            (ValueType::Bool, ValueType::Bool) => self.as_bool().eq(b.as_bool()),
-            (ValueType::Int, ValueType::Int) => self.as_int().eq(b.as_int()),
-            (ValueType::Int8, ValueType::Int8) => self.as_int8().eq(b.as_int8()),
-            (ValueType::Int16, ValueType::Int16) => self.as_int16().eq(b.as_int16()),
-            (ValueType::Int32, ValueType::Int32) => self.as_int32().eq(b.as_int32()),
-            (ValueType::Int64, ValueType::Int64) => self.as_int64().eq(b.as_int64()),
-            (ValueType::Uint, ValueType::Uint) => self.as_uint().eq(b.as_uint()),
-            (ValueType::UintPtr, ValueType::UintPtr) => self.as_uint_ptr().eq(b.as_uint_ptr()),
-            (ValueType::Uint8, ValueType::Uint8) => self.as_uint8().eq(b.as_uint8()),
-            (ValueType::Uint16, ValueType::Uint16) => self.as_uint16().eq(b.as_uint16()),
-            (ValueType::Uint32, ValueType::Uint32) => self.as_uint32().eq(b.as_uint32()),
-            (ValueType::Uint64, ValueType::Uint64) => self.as_uint64().eq(b.as_uint64()),
-            (ValueType::Float32, ValueType::Float32) => self.as_float32().eq(b.as_float32()),
-            (ValueType::Float64, ValueType::Float64) => self.as_float64().eq(b.as_float64()),
-            (ValueType::Complex64, ValueType::Complex64) => {
-                let x = self.as_complex64();
-                let y = b.as_complex64();
-                x.r == y.r && x.i == y.i
-            }
-            (ValueType::Function, ValueType::Function) => self.as_function().eq(b.as_function()),
-            (ValueType::Package, ValueType::Package) => self.as_package().eq(b.as_package()),
-            (ValueType::Metadata, ValueType::Metadata) => self.as_metadata().eq(b.as_metadata()),
-            (ValueType::Complex128, ValueType::Complex128) => {
-                let x = self.as_complex128();
-                let y = b.as_complex128();
-                x.r == y.r && x.i == y.i
-            }
-            (ValueType::String, ValueType::String) => {
-                *self.as_string().as_str() == *b.as_string().as_str()
-            }
-            (ValueType::Array, ValueType::Array) => {
-                self.caller_slow().array_eq(self.data(), b.data())
-            }
-            (ValueType::Struct, ValueType::Struct) => {
-                StructObj::eq(&self.as_struct().0, &b.as_struct().0)
-            }
-            (ValueType::Pointer, ValueType::Pointer) => self.as_pointer() == b.as_pointer(),
-            (ValueType::UnsafePtr, ValueType::UnsafePtr) => {
-                self.as_unsafe_ptr() == b.as_unsafe_ptr()
-            }
-            (ValueType::Closure, ValueType::Closure) => {
-                ref_ptr_eq(self.as_closure(), b.as_closure())
-            }
-            #[cfg(feature = "async")]
-            (ValueType::Channel, ValueType::Channel) => {
-                ref_ptr_eq(self.as_channel(), b.as_channel())
-            }
-            (ValueType::Interface, ValueType::Interface) => {
-                match (self.as_interface(), b.as_interface()) {
-                    (Some(a), Some(b)) => a.eq(b),
-                    (None, None) => true,
-                    _ => false,
-                }
-            }
-            (_, ValueType::Void) => self.is_nil(),
-            (ValueType::Void, _) => b.is_nil(),
-            (ValueType::Interface, _) => self
-                .as_interface()
-                .map_or(b.is_nil(), |x| x.equals_value(b)),
-            (_, ValueType::Interface) => b
-                .as_interface()
-                .map_or(self.is_nil(), |x| x.equals_value(self)),
-            _ => false,
-        }
+        value_ops::equal(self, b)
     }
 }
 
 impl Hash for GosValue {
+    #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
-        match &self.typ {
-            ValueType::Bool => self.as_bool().hash(state),
-            ValueType::Int => self.as_int().hash(state),
-            ValueType::Int8 => self.as_int8().hash(state),
-            ValueType::Int16 => self.as_int16().hash(state),
-            ValueType::Int32 => self.as_int32().hash(state),
-            ValueType::Int64 => self.as_int64().hash(state),
-            ValueType::Uint => self.as_uint().hash(state),
-            ValueType::UintPtr => self.as_uint_ptr().hash(state),
-            ValueType::Uint8 => self.as_uint8().hash(state),
-            ValueType::Uint16 => self.as_uint16().hash(state),
-            ValueType::Uint32 => self.as_uint32().hash(state),
-            ValueType::Uint64 => self.as_uint64().hash(state),
-            ValueType::Float32 => self.as_float32().hash(state),
-            ValueType::Float64 => self.as_float64().hash(state),
-            ValueType::Complex64 => self.as_uint64().hash(state),
-            ValueType::Function => self.as_function().hash(state),
-            ValueType::Package => self.as_package().hash(state),
-            ValueType::Metadata => self.as_metadata().hash(state),
-            ValueType::String => self.as_string().as_str().hash(state),
-            ValueType::Array => self.caller_slow().array_hash(self, state),
-            ValueType::Complex128 => {
-                let c = self.as_complex128();
-                c.r.hash(state);
-                c.i.hash(state);
-            }
-            ValueType::Struct => {
-                self.as_struct().0.hash(state);
-            }
-            ValueType::Pointer => match self.as_pointer() {
-                Some(p) => PointerObj::hash(&p, state),
-                None => 0.hash(state),
-            },
-            ValueType::Interface => match self.as_interface() {
-                Some(iface) => iface.hash(state),
-                None => 0.hash(state),
-            },
-            _ => {
-                dbg!(self.typ);
-                unreachable!();
-            }
-        }
+        value_ops::hash(self, state)
     }
 }
 
@@ -2327,62 +2387,11 @@ impl PartialOrd for GosValue {
     }
 }
 
+// For when used as a BTreeMap key
 impl Ord for GosValue {
+    #[inline]
     fn cmp(&self, b: &Self) -> Ordering {
-        match (self.typ, b.typ) {
-            (ValueType::Bool, ValueType::Bool) => self.as_bool().cmp(b.as_bool()),
-            (ValueType::Int, ValueType::Int) => self.as_int().cmp(b.as_int()),
-            (ValueType::Int8, ValueType::Int8) => self.as_int8().cmp(b.as_int8()),
-            (ValueType::Int16, ValueType::Int16) => self.as_int16().cmp(b.as_int16()),
-            (ValueType::Int32, ValueType::Int32) => self.as_int32().cmp(b.as_int32()),
-            (ValueType::Int64, ValueType::Int64) => self.as_int64().cmp(b.as_int64()),
-            (ValueType::Uint, ValueType::Uint) => self.as_uint().cmp(b.as_uint()),
-            (ValueType::UintPtr, ValueType::UintPtr) => self.as_uint_ptr().cmp(b.as_uint_ptr()),
-            (ValueType::Uint8, ValueType::Uint8) => self.as_uint8().cmp(b.as_uint8()),
-            (ValueType::Uint16, ValueType::Uint16) => self.as_uint16().cmp(b.as_uint16()),
-            (ValueType::Uint32, ValueType::Uint32) => self.as_uint32().cmp(b.as_uint32()),
-            (ValueType::Uint64, ValueType::Uint64) => self.as_uint64().cmp(b.as_uint64()),
-            (ValueType::Float32, ValueType::Float32) => self.as_float32().cmp(b.as_float32()),
-            (ValueType::Float64, ValueType::Float64) => self.as_float64().cmp(b.as_float64()),
-            (ValueType::Complex128, ValueType::Complex128) => {
-                let left = self.as_complex128();
-                let right = self.as_complex128();
-                left.r.cmp(&right.r).then(left.i.cmp(&right.i))
-            }
-            (ValueType::Function, ValueType::Function) => self.as_uint64().cmp(b.as_uint64()),
-            (ValueType::Package, ValueType::Package) => self.as_uint64().cmp(b.as_uint64()),
-            (ValueType::Metadata, ValueType::Metadata) => self.as_metadata().cmp(b.as_metadata()),
-            (ValueType::String, ValueType::String) => {
-                self.as_string().as_str().cmp(&b.as_string().as_str())
-            }
-            (ValueType::Array, ValueType::Array) => {
-                self.caller_slow().array_cmp(self.data(), b.data())
-            }
-            (ValueType::Complex64, ValueType::Complex64) => {
-                let left = self.as_complex64();
-                let right = self.as_complex64();
-                left.r.cmp(&right.r).then(left.i.cmp(&right.i))
-            }
-            (ValueType::Struct, ValueType::Struct) => self.as_struct().0.cmp(&b.as_struct().0),
-            (ValueType::Pointer, ValueType::Pointer) => match (self.as_pointer(), b.as_pointer()) {
-                (Some(a), Some(b)) => a.cmp(b),
-                (None, None) => Ordering::Equal,
-                (Some(_), None) => Ordering::Greater,
-                (None, Some(_)) => Ordering::Less,
-            },
-            (ValueType::Interface, ValueType::Interface) => {
-                match (self.as_interface(), b.as_interface()) {
-                    (Some(a), Some(b)) => a.cmp(b),
-                    (None, None) => Ordering::Equal,
-                    (Some(_), None) => Ordering::Greater,
-                    (None, Some(_)) => Ordering::Less,
-                }
-            }
-            _ => {
-                dbg!(self.typ(), b.typ());
-                unreachable!()
-            }
-        }
+        value_ops::compare(self, b)
     }
 }
 
@@ -2657,7 +2666,7 @@ impl GosValue {
                             dummy_gcc,
                             reader,
                         )?;
-                        GosValue::slice_array(array, 0, -1, &caller).unwrap()
+                        GosValue::slice_array(array, 0, -1, -1, &caller).unwrap()
                     }
                 }
             }
@@ -3128,4 +3137,39 @@ mod test {
         //let s2 = s.clone().into_string();
         //dbg!(s2);
     }
+
+    #[test]
+    fn test_interned_string() {
+        use std::collections::hash_map::DefaultHasher;
+        let hash = |v: &GosValue| {
+            let mut h = DefaultHasher::new();
+            v.hash(&mut h);
+            h.finish()
+        };
+        let interned = GosValue::with_str("key");
+        interned.as_string().set_interned();
+        let other = GosValue::with_str("key");
+        let sliced = GosValue::new_string(
+            GosValue::with_str("a key")
+                .as_string()
+                .slice(2, -1, -1)
+                .unwrap(),
+        );
+        let added = GosValue::new_string(
+            GosValue::with_str("ke")
+                .as_string()
+                .add(GosValue::with_str("y").as_string()),
+        );
+        assert!(interned.as_string().is_interned());
+        for v in [&other, &sliced, &added] {
+            assert!(!v.as_string().is_interned());
+            assert_eq!(&interned, v);
+            assert_eq!(v, &interned);
+            assert_eq!(hash(&interned), hash(v));
+        }
+        let diff = GosValue::with_str("kez");
+        diff.as_string().set_interned();
+        assert_ne!(interned, diff);
+        assert_ne!(other, diff);
+    }
 }
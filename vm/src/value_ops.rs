@@ -0,0 +1,692 @@
+// Copyright 2022 The Goscript Authors. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Equality, hashing and ordering of GosValues.
+//!
+//! They are all here so that they can't disagree. The `PartialEq`, `Hash` and
+//! `Ord` of GosValue and InterfaceObj, the comparison instructions, the maps and
+//! the switch statements all go through this module, and the tests at the end of
+//! this file check the following contract on random values:
+//!
+//! - `equal(a, b)` implies `hash(a) == hash(b)`.
+//! - `try_order(a, b)` is `Some` for the integers, the floats other than NaN and
+//!   the strings, and `None` for the rest, which Go doesn't order. When it's
+//!   `Some`, it's `Some(Ordering::Equal)` exactly when `equal(a, b)`, and it's the
+//!   reverse of `try_order(b, a)`. `compare` extends it to a total order.
+//! - Values don't carry their named types. A value of a named type compares,
+//!   hashes and orders as a value of its underlying type. The named type only
+//!   shows through an interface: two interfaces are equal if they hold values of
+//!   the same dynamic type, and the values are equal.
+//! - Floats follow IEEE 754, like in Go. NaN is equal to nothing, not even
+//!   itself, and it's not ordered. +0 and -0 are equal and hash the same. So a
+//!   map can hold any number of NaN keys, and none of them can be looked up.
+//!   Complex numbers compare part by part, and so do arrays and structs.
+//! - nil is equal to the nil of any type, and to an interface holding nothing,
+//!   but not to an interface holding a nil pointer.
+//! - An interface compared with a value of another type is equal to it if it
+//!   holds an equal value. The code generator only emits this where Go converts
+//!   the value to the interface, whose dynamic type it knows to be right.
+//!   These mixed comparisons and the ones with the untyped nil are only ever
+//!   done by `==`, the keys of a map or the tags of a switch are all of one type,
+//!   so they are the exceptions to `compare` being consistent with `equal`.
+//! - An interface can hold a value of a type that's not comparable, a slice, a
+//!   map or a function, or a struct or an array with one in it. Go panics when
+//!   two of them of the same dynamic type are compared, and when one is hashed as
+//!   a map key. `equal` and `hash` can't fail, `try_equal` and `unhashable` find
+//!   these values for the VM to panic on first.
+
+use crate::metadata::Meta;
+use crate::objects::InterfaceObj;
+use crate::value::*;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// The type `v` is compared as: that of the value an interface holds, with the
+/// metadata of the interface if it has any, `ValueType::Void` for a nil
+/// interface, and the type of `v` itself otherwise.
+pub fn type_of(v: &GosValue) -> (ValueType, Option<Meta>) {
+    match v.typ() {
+        ValueType::Interface => match v.as_interface() {
+            Some(InterfaceObj::Gos(v, info)) => (v.typ(), info.as_ref().map(|x| x.0)),
+            Some(InterfaceObj::Ffi(ffi)) => (ValueType::Interface, Some(ffi.meta)),
+            None => (ValueType::Void, None),
+        },
+        t => (t, None),
+    }
+}
+
+/// Whether `a == b` in Go
+pub fn equal(a: &GosValue, b: &GosValue) -> bool {
+    match (a.typ(), b.typ()) {
+        (ValueType::Interface, ValueType::Interface) => {
+            match (a.as_interface(), b.as_interface()) {
+                (Some(x), Some(y)) => iface_equal(x, y),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        (_, ValueType::Void) => a.is_nil(),
+        (ValueType::Void, _) => b.is_nil(),
+        // an interface compared with a value of a type that implements it
+        (ValueType::Interface, _) => a
+            .as_interface()
+            .and_then(|x| x.underlying_value())
+            .is_some_and(|x| equal(x, b)),
+        (_, ValueType::Interface) => b
+            .as_interface()
+            .and_then(|x| x.underlying_value())
+            .is_some_and(|x| equal(a, x)),
+        (t, u) if t != u => false,
+        (t, _) => match t {
+            ValueType::Float32 => a.as_float32().0 == b.as_float32().0,
+            ValueType::Float64 => a.as_float64().0 == b.as_float64().0,
+            ValueType::Complex64 => {
+                let (x, y) = (a.as_complex64(), b.as_complex64());
+                x.r.0 == y.r.0 && x.i.0 == y.i.0
+            }
+            ValueType::Complex128 => {
+                let (x, y) = (a.as_complex128(), b.as_complex128());
+                x.r.0 == y.r.0 && x.i.0 == y.i.0
+            }
+            ValueType::Function => a.as_function() == b.as_function(),
+            ValueType::Package => a.as_package() == b.as_package(),
+            ValueType::Metadata => a.as_metadata() == b.as_metadata(),
+            _ if t.copyable() => a.data().compare_eql(b.data(), t),
+            ValueType::String => a.as_string().str_eq(b.as_string()),
+            ValueType::Array if a.t_elem() != b.t_elem() => false,
+            ValueType::Array if has_float_elems(a) => {
+                let (x, y) = (elems(a), elems(b));
+                x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| equal(x, y))
+            }
+            ValueType::Array => a.caller_slow().array_eq(a.data(), b.data()),
+            ValueType::Struct => {
+                let (x, y) = (&a.as_struct().0, &b.as_struct().0);
+                let (x, y) = (x.borrow_fields(), y.borrow_fields());
+                x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| equal(x, y))
+            }
+            ValueType::Pointer => a.as_pointer() == b.as_pointer(),
+            ValueType::UnsafePtr => a.as_unsafe_ptr() == b.as_unsafe_ptr(),
+            ValueType::Closure => addr(a) == addr(b),
+            #[cfg(feature = "async")]
+            ValueType::Channel => addr(a) == addr(b),
+            _ => false,
+        },
+    }
+}
+
+pub(crate) fn iface_equal(x: &InterfaceObj, y: &InterfaceObj) -> bool {
+    match (x, y) {
+        (InterfaceObj::Gos(v, vinfo), InterfaceObj::Gos(w, winfo)) => {
+            let same_type = match (vinfo, winfo) {
+                (Some((m, _)), Some((n, _))) => m.key == n.key && m.ptr_depth == n.ptr_depth,
+                // without the type info, the values have to tell
+                _ => true,
+            };
+            same_type && equal(v, w)
+        }
+        (InterfaceObj::Ffi(x), InterfaceObj::Ffi(y)) => Rc::ptr_eq(&x.ffi_obj, &y.ffi_obj),
+        _ => false,
+    }
+}
+
+/// `equal(a, b)`, or the dynamic type of the interfaces holding values of a type
+/// that's not comparable it finds in `a` and `b` and in their fields and elements.
+/// Like in Go, the fields and the elements are compared in order and the ones
+/// after the first unequal pair aren't looked at.
+pub fn try_equal(a: &GosValue, b: &GosValue, metas: &MetadataObjs) -> Result<bool, Meta> {
+    match (a.typ(), b.typ()) {
+        (ValueType::Interface, ValueType::Interface) => {
+            match (a.as_interface(), b.as_interface()) {
+                (
+                    Some(InterfaceObj::Gos(v, Some((m, _)))),
+                    Some(InterfaceObj::Gos(w, Some((n, _)))),
+                ) if m.key == n.key && m.ptr_depth == n.ptr_depth => match m.comparable(metas) {
+                    true => try_equal(v, w, metas),
+                    false => Err(*m),
+                },
+                _ => Ok(equal(a, b)),
+            }
+        }
+        (ValueType::Interface, t) if t != ValueType::Void => match a.as_interface() {
+            Some(InterfaceObj::Gos(v, _)) => try_equal(v, b, metas),
+            _ => Ok(false),
+        },
+        (t, ValueType::Interface) if t != ValueType::Void => match b.as_interface() {
+            Some(InterfaceObj::Gos(w, _)) => try_equal(a, w, metas),
+            _ => Ok(false),
+        },
+        (ValueType::Struct, ValueType::Struct) => {
+            let (x, y) = (&a.as_struct().0, &b.as_struct().0);
+            let (x, y) = (x.borrow_fields(), y.borrow_fields());
+            try_equal_all(x.iter().zip(y.iter()), metas)
+        }
+        (ValueType::Array, ValueType::Array) if may_hold_iface(a) && a.t_elem() == b.t_elem() => {
+            let (x, y) = (elems(a), elems(b));
+            try_equal_all(x.iter().zip(y.iter()), metas)
+        }
+        _ => Ok(equal(a, b)),
+    }
+}
+
+fn try_equal_all<'a>(
+    pairs: impl Iterator<Item = (&'a GosValue, &'a GosValue)>,
+    metas: &MetadataObjs,
+) -> Result<bool, Meta> {
+    for (x, y) in pairs {
+        if !try_equal(x, y, metas)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The dynamic type of the first interface holding a value of a type that's not
+/// comparable in `v` or in its fields and elements, `None` if `v` can be hashed
+pub fn unhashable(v: &GosValue, metas: &MetadataObjs) -> Option<Meta> {
+    match v.typ() {
+        ValueType::Interface => match v.as_interface() {
+            Some(InterfaceObj::Gos(v, Some((m, _)))) => match m.comparable(metas) {
+                true => unhashable(v, metas),
+                false => Some(*m),
+            },
+            _ => None,
+        },
+        ValueType::Struct => v
+            .as_struct()
+            .0
+            .borrow_fields()
+            .iter()
+            .find_map(|f| unhashable(f, metas)),
+        ValueType::Array if may_hold_iface(v) => elems(v).iter().find_map(|e| unhashable(e, metas)),
+        _ => None,
+    }
+}
+
+/// Feeds `v` to `state`, the same way for the values that are `equal`
+pub fn hash<H: Hasher>(v: &GosValue, state: &mut H) {
+    match v.typ() {
+        ValueType::Void => hash_nil(state),
+        ValueType::Bool => v.as_bool().hash(state),
+        ValueType::Int => v.as_int().hash(state),
+        ValueType::Int8 => v.as_int8().hash(state),
+        ValueType::Int16 => v.as_int16().hash(state),
+        ValueType::Int32 => v.as_int32().hash(state),
+        ValueType::Int64 => v.as_int64().hash(state),
+        ValueType::Uint => v.as_uint().hash(state),
+        ValueType::UintPtr => v.as_uint_ptr().hash(state),
+        ValueType::Uint8 => v.as_uint8().hash(state),
+        ValueType::Uint16 => v.as_uint16().hash(state),
+        ValueType::Uint32 => v.as_uint32().hash(state),
+        ValueType::Uint64 => v.as_uint64().hash(state),
+        ValueType::Float32 => hash_float(v.as_float32().0 as f64, state),
+        ValueType::Float64 => hash_float(v.as_float64().0, state),
+        ValueType::Complex64 => {
+            let c = v.as_complex64();
+            hash_float(c.r.0 as f64, state);
+            hash_float(c.i.0 as f64, state);
+        }
+        ValueType::Complex128 => {
+            let c = v.as_complex128();
+            hash_float(c.r.0, state);
+            hash_float(c.i.0, state);
+        }
+        ValueType::Function => v.as_function().hash(state),
+        ValueType::Package => v.as_package().hash(state),
+        ValueType::Metadata => v.as_metadata().hash(state),
+        ValueType::String => state.write_u64(v.as_string().content_hash()),
+        ValueType::Array if has_float_elems(v) => {
+            for e in elems(v).iter() {
+                hash(e, state);
+            }
+        }
+        ValueType::Array => v.caller_slow().array_hash(v, state),
+        ValueType::Struct => {
+            for f in v.as_struct().0.borrow_fields().iter() {
+                hash(f, state);
+            }
+        }
+        ValueType::Pointer => match v.as_pointer() {
+            Some(p) => p.hash(state),
+            None => hash_nil(state),
+        },
+        // the unsafe pointers are compared by the objects they point to
+        ValueType::UnsafePtr => match v.as_unsafe_ptr() {
+            Some(_) => 1.hash(state),
+            None => hash_nil(state),
+        },
+        ValueType::Interface => match v.as_interface() {
+            Some(iface) => iface_hash(iface, state),
+            None => hash_nil(state),
+        },
+        ValueType::Closure | ValueType::Channel => match addr(v) {
+            0 => hash_nil(state),
+            a => a.hash(state),
+        },
+        _ if v.is_nil() => hash_nil(state),
+        t => unreachable!("hash of unhashable type {:?}", t),
+    }
+}
+
+/// An interface hashes as the value it holds, so that it hashes the same as the
+/// value when they are equal
+pub(crate) fn iface_hash<H: Hasher>(iface: &InterfaceObj, state: &mut H) {
+    match iface {
+        InterfaceObj::Gos(v, _) => hash(v, state),
+        InterfaceObj::Ffi(ffi) => Rc::as_ptr(&ffi.ffi_obj).hash(state),
+    }
+}
+
+/// The order of `a` and `b` in Go, `None` if they are not ordered
+pub fn try_order(a: &GosValue, b: &GosValue) -> Option<Ordering> {
+    let t = a.typ();
+    if t != b.typ() {
+        return None;
+    }
+    match t {
+        ValueType::Float32 => a.as_float32().0.partial_cmp(&b.as_float32().0),
+        ValueType::Float64 => a.as_float64().0.partial_cmp(&b.as_float64().0),
+        _ if t.integer() => {
+            let (x, y) = (a.data(), b.data());
+            Some(if x.compare_lss(y, t) {
+                Ordering::Less
+            } else if x.compare_eql(y, t) {
+                Ordering::Equal
+            } else {
+                Ordering::Greater
+            })
+        }
+        ValueType::String => Some(a.as_string().as_str().cmp(&b.as_string().as_str())),
+        _ => None,
+    }
+}
+
+/// A total order of all the values, for the containers that need one like the
+/// maps with the `btree_map` feature. It's `try_order` where that's defined, and
+/// it's consistent with `equal` but for NaN, which it puts after the other floats
+/// and takes as equal to itself.
+pub fn compare(a: &GosValue, b: &GosValue) -> Ordering {
+    if let Some(order) = try_order(a, b) {
+        return order;
+    }
+    let (t, u) = (a.typ(), b.typ());
+    if t != u {
+        return t.cmp(&u);
+    }
+    match t {
+        ValueType::Void => Ordering::Equal,
+        ValueType::Bool => a.as_bool().cmp(b.as_bool()),
+        // NaN, try_order covers the other floats
+        ValueType::Float32 => a.as_float32().cmp(b.as_float32()),
+        ValueType::Float64 => a.as_float64().cmp(b.as_float64()),
+        ValueType::Complex64 => {
+            let (x, y) = (a.as_complex64(), b.as_complex64());
+            cmp_float(x.r.0 as f64, y.r.0 as f64).then(cmp_float(x.i.0 as f64, y.i.0 as f64))
+        }
+        ValueType::Complex128 => {
+            let (x, y) = (a.as_complex128(), b.as_complex128());
+            cmp_float(x.r.0, y.r.0).then(cmp_float(x.i.0, y.i.0))
+        }
+        ValueType::Function => a.as_function().cmp(b.as_function()),
+        ValueType::Package => a.as_package().cmp(b.as_package()),
+        ValueType::Metadata => a.as_metadata().cmp(b.as_metadata()),
+        ValueType::Array if a.t_elem() != b.t_elem() => a.t_elem().cmp(&b.t_elem()),
+        ValueType::Array if has_float_elems(a) => {
+            let (x, y) = (elems(a), elems(b));
+            x.len().cmp(&y.len()).then_with(|| {
+                x.iter()
+                    .zip(y.iter())
+                    .map(|(x, y)| compare(x, y))
+                    .find(|o| *o != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        ValueType::Array => a.caller_slow().array_cmp(a.data(), b.data()),
+        ValueType::Struct => {
+            let (x, y) = (&a.as_struct().0, &b.as_struct().0);
+            let (x, y) = (x.borrow_fields(), y.borrow_fields());
+            x.iter()
+                .zip(y.iter())
+                .map(|(x, y)| compare(x, y))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        }
+        ValueType::Pointer => a.as_pointer().cmp(&b.as_pointer()),
+        ValueType::Interface => match (a.as_interface(), b.as_interface()) {
+            (Some(x), Some(y)) => iface_compare(x, y),
+            (None, None) => Ordering::Equal,
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+        },
+        _ => addr(a).cmp(&addr(b)),
+    }
+}
+
+pub(crate) fn iface_compare(x: &InterfaceObj, y: &InterfaceObj) -> Ordering {
+    match (x, y) {
+        (InterfaceObj::Gos(v, vinfo), InterfaceObj::Gos(w, winfo)) => {
+            let types = match (vinfo, winfo) {
+                (Some((m, _)), Some((n, _))) => (m.key, m.ptr_depth).cmp(&(n.key, n.ptr_depth)),
+                _ => Ordering::Equal,
+            };
+            types.then_with(|| compare(v, w))
+        }
+        (InterfaceObj::Ffi(x), InterfaceObj::Ffi(y)) => Rc::as_ptr(&x.ffi_obj)
+            .cast::<()>()
+            .cmp(&Rc::as_ptr(&y.ffi_obj).cast::<()>()),
+        (InterfaceObj::Gos(_, _), InterfaceObj::Ffi(_)) => Ordering::Greater,
+        (InterfaceObj::Ffi(_), InterfaceObj::Gos(_, _)) => Ordering::Less,
+    }
+}
+
+#[inline]
+fn hash_nil<H: Hasher>(state: &mut H) {
+    0.hash(state)
+}
+
+/// +0 and -0 hash the same, NaN hashes to anything as it's equal to nothing
+#[inline]
+fn hash_float<H: Hasher>(f: f64, state: &mut H) {
+    let f = if f == 0.0 { 0.0 } else { f };
+    state.write_u64(f.to_bits())
+}
+
+/// The total order of the floats `compare` uses
+#[inline]
+fn cmp_float(x: f64, y: f64) -> Ordering {
+    F64::from(x).cmp(&F64::from(y))
+}
+
+/// The arrays of floats are compared element by element, the other arrays of
+/// the same types are equal only if they are bitwise equal
+#[inline]
+fn has_float_elems(v: &GosValue) -> bool {
+    matches!(
+        v.t_elem(),
+        ValueType::Float32 | ValueType::Float64 | ValueType::Complex64
+    )
+}
+
+/// Whether the elements of an array can be or have interfaces in them
+#[inline]
+fn may_hold_iface(v: &GosValue) -> bool {
+    matches!(
+        v.t_elem(),
+        ValueType::Interface | ValueType::Struct | ValueType::Array
+    )
+}
+
+#[inline]
+fn elems(v: &GosValue) -> Vec<GosValue> {
+    v.caller_slow().array_get_vec(v)
+}
+
+/// The address of the object a reference type points to, 0 for nil
+fn addr(v: &GosValue) -> usize {
+    match v.typ() {
+        ValueType::Closure => v.as_closure().map_or(0, |x| x as *const _ as usize),
+        #[cfg(feature = "async")]
+        ValueType::Channel => v.as_channel().map_or(0, |x| x as *const _ as usize),
+        ValueType::Slice => v.as_gos_slice().map_or(0, |x| x as *const _ as usize),
+        ValueType::Map => v.as_map().map_or(0, |x| x as *const _ as usize),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gc::GcContainer;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+
+    fn hash_of(v: &GosValue) -> u64 {
+        let mut h = DefaultHasher::new();
+        hash(v, &mut h);
+        h.finish()
+    }
+
+    /// Random values of a handful of types, drawn from small pools so that the
+    /// equal ones come up often. There's no generator of Go values to test the
+    /// VM against, the pools have the corner cases of the contract instead.
+    struct Gen {
+        seed: u64,
+        gcc: GcContainer,
+        /// An unnamed type and a named type with it as the underlying type
+        metas: [Meta; 2],
+    }
+
+    impl Gen {
+        fn new(seed: u64) -> Gen {
+            let mut metas = MetadataObjs::with_capacity(2);
+            let unnamed = Meta::with_type(MetadataType::Float64, &mut metas);
+            let named = Meta::new_named(unnamed, &mut metas);
+            Gen {
+                seed,
+                gcc: GcContainer::new(),
+                metas: [unnamed, named],
+            }
+        }
+
+        /// xorshift64
+        fn below(&mut self, n: usize) -> usize {
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            (self.seed % n as u64) as usize
+        }
+
+        fn float(&mut self) -> f64 {
+            [0.0, -0.0, 1.0, -1.5, f64::NAN, f64::INFINITY][self.below(6)]
+        }
+
+        fn scalar(&mut self) -> GosValue {
+            match self.below(9) {
+                0 => GosValue::from(self.below(2) == 0),
+                1 => GosValue::from(self.below(4) as isize - 2),
+                2 => GosValue::from(self.below(3) as u8),
+                3 => GosValue::new_float32((self.float() as f32).into()),
+                4 | 5 => GosValue::new_float64(self.float().into()),
+                6 => GosValue::new_complex128(self.float().into(), self.float().into()),
+                7 => GosValue::new_complex64(
+                    (self.float() as f32).into(),
+                    (self.float() as f32).into(),
+                ),
+                _ => self.string(),
+            }
+        }
+
+        /// Equal strings of different kinds: fresh, sliced and concatenated
+        fn string(&mut self) -> GosValue {
+            let s = ["", "a", "b", "ab"][self.below(4)];
+            match self.below(3) {
+                0 => GosValue::with_str(s),
+                1 => GosValue::new_string(
+                    GosValue::with_str(&format!("x{}", s))
+                        .as_string()
+                        .slice(1, -1, -1)
+                        .unwrap(),
+                ),
+                _ => GosValue::new_string(
+                    GosValue::with_str(s)
+                        .as_string()
+                        .add(GosValue::with_str("").as_string()),
+                ),
+            }
+        }
+
+        fn value(&mut self, depth: usize) -> GosValue {
+            match if depth == 0 { 0 } else { self.below(6) } {
+                0 | 1 => self.scalar(),
+                2 => {
+                    let t = [ValueType::Int, ValueType::Float64][self.below(2)];
+                    let data = (0..2)
+                        .map(|_| match t {
+                            ValueType::Int => GosValue::from(self.below(2) as isize),
+                            _ => GosValue::new_float64(self.float().into()),
+                        })
+                        .collect();
+                    GosValue::array_with_data(data, &ArrCaller::get_slow(t), &self.gcc)
+                }
+                3 => {
+                    let fields = vec![self.value(depth - 1), self.value(depth - 1)];
+                    GosValue::new_struct(StructObj::new(fields), &self.gcc)
+                }
+                4 => match self.below(3) {
+                    0 => GosValue::new_nil(ValueType::Interface),
+                    _ => {
+                        // interfaces don't hold interfaces
+                        let mut val = self.value(depth - 1);
+                        while val.typ() == ValueType::Interface {
+                            val = self.value(depth - 1);
+                        }
+                        let meta = self.metas[self.below(2)];
+                        let obj = InterfaceObj::with_value(val, Some((meta, vec![])));
+                        GosValue::new_interface(obj)
+                    }
+                },
+                _ => GosValue::new_nil(ValueType::Pointer),
+            }
+        }
+    }
+
+    fn ordered(v: &GosValue) -> bool {
+        match v.typ() {
+            ValueType::Float32 => !v.as_float32().0.is_nan(),
+            ValueType::Float64 => !v.as_float64().0.is_nan(),
+            t => t.integer() || t == ValueType::String,
+        }
+    }
+
+    #[test]
+    fn test_contract() {
+        let mut gen = Gen::new(0x9e3779b97f4a7c15);
+        let mut vals: Vec<GosValue> = (0..300).map(|_| gen.value(2)).collect();
+        vals.push(GosValue::new_nil(ValueType::Void));
+        let mut equal_pairs = 0;
+        for a in vals.iter() {
+            for b in vals.iter() {
+                let eq = equal(a, b);
+                assert_eq!(eq, equal(b, a), "{:?} {:?}", a, b);
+                assert_eq!(eq, a == b);
+                if eq {
+                    equal_pairs += 1;
+                    assert_eq!(hash_of(a), hash_of(b), "{:?} {:?}", a, b);
+                    if a.typ() == b.typ() {
+                        assert_eq!(compare(a, b), Ordering::Equal, "{:?} {:?}", a, b);
+                    }
+                }
+                let order = try_order(a, b);
+                let expect_order = a.typ() == b.typ() && ordered(a) && ordered(b);
+                assert_eq!(order.is_some(), expect_order, "{:?} {:?}", a, b);
+                assert_eq!(order, try_order(b, a).map(Ordering::reverse));
+                if let Some(o) = order {
+                    assert_eq!(o == Ordering::Equal, eq, "{:?} {:?}", a, b);
+                    assert_eq!(o, compare(a, b));
+                }
+                assert_eq!(compare(a, b), compare(b, a).reverse(), "{:?} {:?}", a, b);
+            }
+        }
+        // the pools are small enough for the equal pairs not to be only the diagonal
+        assert!(equal_pairs > 2 * vals.len());
+
+        let mut sorted = vals.clone();
+        sorted.sort_by(compare);
+        for w in sorted.windows(2) {
+            assert_ne!(compare(&w[0], &w[1]), Ordering::Greater);
+        }
+        for _ in 0..20000 {
+            let (a, b, c) = (
+                &vals[gen.below(vals.len())],
+                &vals[gen.below(vals.len())],
+                &vals[gen.below(vals.len())],
+            );
+            if compare(a, b) != Ordering::Greater && compare(b, c) != Ordering::Greater {
+                assert_ne!(compare(a, c), Ordering::Greater, "{:?} {:?} {:?}", a, b, c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_go_rules() {
+        let gen = Gen::new(1);
+        let nan = GosValue::new_float64(f64::NAN.into());
+        let zero = GosValue::new_float64(0.0.into());
+        let neg_zero = GosValue::new_float64((-0.0).into());
+        assert!(!equal(&nan, &nan));
+        assert_eq!(try_order(&nan, &nan), None);
+        assert!(equal(&zero, &neg_zero));
+        assert_eq!(hash_of(&zero), hash_of(&neg_zero));
+        let nan_c = GosValue::new_complex64(f32::NAN.into(), 0.0.into());
+        assert!(!equal(&nan_c, &nan_c));
+
+        let mut map = HashMap::new();
+        for i in 0..3 {
+            map.insert(nan.clone(), i);
+            map.insert(neg_zero.clone(), i);
+            map.insert(zero.clone(), i);
+        }
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get(&nan), None);
+        assert_eq!(map.get(&zero), Some(&2));
+
+        // the same value is equal through the same named type only
+        let iface = |v: &GosValue, m: Meta| {
+            GosValue::new_interface(InterfaceObj::with_value(v.clone(), Some((m, vec![]))))
+        };
+        let one = GosValue::new_float64(1.0.into());
+        let [unnamed, named] = gen.metas;
+        assert!(equal(&iface(&one, named), &iface(&one, named)));
+        assert!(!equal(&iface(&one, named), &iface(&one, unnamed)));
+        assert!(equal(&iface(&one, named), &one));
+        assert!(!equal(&iface(&nan, named), &iface(&nan, named)));
+        assert!(equal(&iface(&zero, named), &iface(&neg_zero, named)));
+
+        // nil
+        let nil = GosValue::new_nil(ValueType::Void);
+        let nil_iface = GosValue::new_nil(ValueType::Interface);
+        let nil_ptr = GosValue::new_nil(ValueType::Pointer);
+        assert!(equal(&nil, &nil_ptr));
+        assert!(equal(&nil, &nil_iface));
+        assert!(!equal(&nil_iface, &nil_ptr));
+        assert!(!equal(&iface(&nil_ptr, unnamed), &nil));
+        assert!(!equal(&iface(&nil_ptr, unnamed), &nil_iface));
+        assert_eq!(hash_of(&nil_iface), hash_of(&nil_ptr));
+    }
+
+    #[test]
+    fn test_uncomparable() {
+        let gcc = GcContainer::new();
+        let mut metas = MetadataObjs::with_capacity(3);
+        let int = Meta::with_type(MetadataType::Int, &mut metas);
+        let slice_meta = Meta::with_type(MetadataType::Slice(int), &mut metas);
+        let iface = |v: GosValue, m: Meta| {
+            GosValue::new_interface(InterfaceObj::with_value(v, Some((m, vec![]))))
+        };
+        let slice = || {
+            let data = vec![GosValue::from(1isize)];
+            iface(
+                GosValue::slice_with_data(data, &ArrCaller::get_slow(ValueType::Int), &gcc),
+                slice_meta,
+            )
+        };
+        let one = iface(GosValue::from(1isize), int);
+        let strukt = |a: &GosValue, b: &GosValue| {
+            GosValue::new_struct(StructObj::new(vec![a.clone(), b.clone()]), &gcc)
+        };
+
+        assert_eq!(try_equal(&slice(), &slice(), &metas), Err(slice_meta));
+        assert_eq!(try_equal(&slice(), &one, &metas), Ok(false));
+        assert_eq!(try_equal(&one, &one, &metas), Ok(true));
+        let (a, b) = (strukt(&one, &slice()), strukt(&one, &slice()));
+        assert_eq!(try_equal(&a, &b, &metas), Err(slice_meta));
+        // the fields after the first unequal ones aren't compared
+        let c = strukt(&GosValue::from(2isize), &slice());
+        assert_eq!(try_equal(&a, &c, &metas), Ok(false));
+
+        assert_eq!(unhashable(&one, &metas), None);
+        assert_eq!(unhashable(&slice(), &metas), Some(slice_meta));
+        assert_eq!(unhashable(&a, &metas), Some(slice_meta));
+    }
+}
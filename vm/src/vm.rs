@@ -2,15 +2,24 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
+use crate::census::AllocSites;
 use crate::ffi::{FfiCtx, FfiFactory};
-use crate::gc::{collect, GcContainer};
+use crate::gc::{GcContainer, GcStats, DEFAULT_GC_MAX_PAUSE};
 use crate::objects::ClosureObj;
 use crate::stack::{RangeStack, Stack};
+#[cfg(feature = "stack_check")]
+use crate::stack_check::StackCheck;
+use crate::tiering::Tiering;
+use crate::tuple::TupleDesc;
 use crate::value::*;
+use crate::value_ops;
 use go_parser::Map;
 use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "async")]
 use crate::channel;
@@ -18,6 +27,8 @@ use crate::channel;
 use async_executor::LocalExecutor;
 #[cfg(feature = "async")]
 use futures_lite::future;
+#[cfg(feature = "async")]
+use std::time::Instant;
 
 // restore stack_ref after drop to allow code in block call yield
 macro_rules! restore_stack_ref {
@@ -27,9 +38,12 @@ macro_rules! restore_stack_ref {
     }};
 }
 
+/// The panic of calling a nil func value or a method of a nil interface
+const NIL_DEREF: &str = "invalid memory address or nil pointer dereference";
+
 macro_rules! go_panic {
-    ($panic:ident, $msg:expr, $frame:ident, $code:ident) => {{
-        let mut data = PanicData::new($msg);
+    ($panic:ident, $kind:expr, $msg:expr, $frame:ident, $code:ident) => {{
+        let mut data = PanicData::new($kind, $msg);
         data.call_stack.push(($frame.func(), $frame.pc - 1));
         $panic = Some(data);
         $frame.pc = $code.len() as OpIndex - 1;
@@ -40,7 +54,7 @@ macro_rules! go_panic_str {
     ($panic:ident, $msg:expr, $frame:ident, $code:ident) => {{
         let str_val = GosValue::with_str($msg);
         let iface = GosValue::empty_iface_with_val(str_val);
-        go_panic!($panic, iface, $frame, $code);
+        go_panic!($panic, PanicKind::Runtime, iface, $frame, $code);
     }};
 }
 
@@ -81,10 +95,19 @@ macro_rules! binary_op {
 }
 
 macro_rules! binary_op_assign {
+    ($stack:ident, $op:tt, $inst:expr, $sb:expr, $consts:expr) => {{
+        let right = unsafe { $stack.read($inst.s0, $sb, $consts).data().copy_non_ptr() };
+        let d = $stack.get_mut($inst.d + $sb);
+        // the whole value is replaced so the string a += replaces is dropped
+        *d = GosValue::new($inst.t0, d.data().$op(&right, $inst.t0));
+    }};
+}
+
+macro_rules! checked_binary_op_assign {
     ($stack:ident, $op:tt, $inst:expr, $sb:expr, $consts:expr) => {{
         let right = unsafe { $stack.read($inst.s0, $sb, $consts).data().copy_non_ptr() };
         let d = $stack.get_data_mut($inst.d + $sb);
-        *d = d.$op(&right, $inst.t0);
+        d.$op(&right, $inst.t0).map(|vdata| *d = vdata)
     }};
 }
 
@@ -93,13 +116,14 @@ macro_rules! shift_op {
         let right = $stack
             .read($inst.s1, $sb, $consts)
             .data()
-            .cast_copyable($inst.t1, ValueType::Uint32);
-        let vdata = $stack
-            .read($inst.s0, $sb, $consts)
-            .data()
-            .$op(right.as_uint32(), $inst.t0);
-        let val = GosValue::new($inst.t0, vdata);
-        $stack.set($inst.d + $sb, val);
+            .shift_count($inst.t1);
+        right.map(|count| {
+            let vdata = $stack
+                .read($inst.s0, $sb, $consts)
+                .data()
+                .$op(&count, $inst.t0);
+            $stack.set($inst.d + $sb, GosValue::new($inst.t0, vdata));
+        })
     }};
 }
 
@@ -108,9 +132,21 @@ macro_rules! shift_op_assign {
         let right = $stack
             .read($inst.s0, $sb, $consts)
             .data()
-            .cast_copyable($inst.t1, ValueType::Uint32);
-        let d = $stack.get_data_mut($inst.d + $sb);
-        *d = d.$op(right.as_uint32(), $inst.t0);
+            .shift_count($inst.t1);
+        right.map(|count| {
+            let d = $stack.get_data_mut($inst.d + $sb);
+            *d = d.$op(&count, $inst.t0);
+        })
+    }};
+}
+
+macro_rules! checked_binary_op {
+    ($stack:expr, $op:tt, $inst:expr, $sb:expr, $consts:expr) => {{
+        let result = $stack
+            .read($inst.s0, $sb, $consts)
+            .data()
+            .$op($stack.read($inst.s1, $sb, $consts).data(), $inst.t0);
+        result.map(|vdata| $stack.set($inst.d + $sb, GosValue::new($inst.t0, vdata)))
     }};
 }
 
@@ -124,30 +160,260 @@ macro_rules! unary_op {
 
 /// Entry point
 pub fn run(code: &Bytecode, ffi: &FfiFactory) -> Option<PanicData> {
-    let gcc = GcContainer::new();
+    run_entry(code, ffi, code.entry)
+}
+
+/// Runs `code` starting from `entry` instead of the default entry function,
+/// for Bytecode compiled with more than one entry.
+pub fn run_entry(code: &Bytecode, ffi: &FfiFactory, entry: FunctionKey) -> Option<PanicData> {
+    run_with(code, ffi, entry, &RunOptions::default()).panic
+}
+
+/// Called when all goroutines are blocked, see `RunOptions::idle_hook`
+pub type IdleHook = Rc<dyn Fn() -> bool>;
+
+/// Receives what a run writes to its std out, see `RunOptions::std_out`
+pub type StdOutSink = Rc<RefCell<dyn std::io::Write>>;
+
+/// Options of `run_with`
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// How long the other goroutines may keep running after the main goroutine returns.
+    /// With `None` they run until none of them can make progress.
+    pub grace_period: Option<Duration>,
+    /// Count the slice growths and map rehashes per instruction, see `GrowthStats`
+    pub growth_stats: bool,
+    /// Called instead of ending the run when no goroutine can make progress, for
+    /// hosts that feed channels from outside the VM. It should wait until the host
+    /// might have sent something and return true, or return false if it never will.
+    pub idle_hook: Option<IdleHook>,
+    /// Setting it from any thread stops the run at the next time slice of a goroutine,
+    /// with a `PanicKind::Interrupted` panic.
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// Stops the run with a `PanicKind::InstructionLimit` panic once its goroutines
+    /// have executed more instructions, counted at the end of their time slices.
+    pub instruction_limit: Option<u64>,
+    /// How many stacks of finished goroutines are kept for the new ones to reuse,
+    /// `DEFAULT_FIBER_POOL_SIZE` with `None`, `Some(0)` turns the reuse off.
+    pub fiber_pool_size: Option<usize>,
+    /// How long a slice of the cycle collection may stop a goroutine at one of its
+    /// yield points, `DEFAULT_GC_MAX_PAUSE` with `None`
+    pub gc_max_pause: Option<Duration>,
+    /// Promote a function to specialized code once it has been called this many
+    /// times, see `tiering`. Off with `None`.
+    pub tiering_threshold: Option<u32>,
+    /// Record in it where the objects are allocated, for `census` to attribute them
+    pub alloc_sites: Option<Rc<AllocSites>>,
+    /// Shuffle the order in which `range` visits the entries of maps with this seed,
+    /// to catch the programs that depend on it. A seed gives the same order for the
+    /// same keys. With `None` the order is the one the entries are stored in.
+    pub map_order_seed: Option<u64>,
+    /// Receives what the run writes to the std out instead of the writer shared by
+    /// all the runs, the FFIs read it with `FfiCtx::std_out`
+    pub std_out: Option<StdOutSink>,
+}
+
+impl std::fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("grace_period", &self.grace_period)
+            .field("growth_stats", &self.growth_stats)
+            .field("idle_hook", &self.idle_hook.is_some())
+            .field("interrupt", &self.interrupt)
+            .field("instruction_limit", &self.instruction_limit)
+            .field("fiber_pool_size", &self.fiber_pool_size)
+            .field("gc_max_pause", &self.gc_max_pause)
+            .field("tiering_threshold", &self.tiering_threshold)
+            .field("alloc_sites", &self.alloc_sites.is_some())
+            .field("map_order_seed", &self.map_order_seed)
+            .field("std_out", &self.std_out.is_some())
+            .finish()
+    }
+}
+
+/// How often the storage of slices and maps had to grow at an instruction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GrowthCount {
+    /// `append`s that didn't fit in the capacity and copied the slice to a new array
+    pub slice_growths: usize,
+    /// map assignments that made the map rehash its entries
+    pub map_rehashes: usize,
+}
+
+/// The growth counts of the instructions where any growth happened,
+/// keyed by the function and the index of the instruction in it
+pub type GrowthStats = Map<(FunctionKey, OpIndex), GrowthCount>;
+
+/// The outcome of `run_with`
+#[derive(Debug)]
+pub struct RunResult {
+    pub panic: Option<PanicData>,
+    /// The goroutines still alive when the run ended, ordered by id,
+    /// always empty if the run ended with a panic
+    pub leaked_goroutines: Vec<GoroutineData>,
+    /// Empty unless `RunOptions::growth_stats` is on
+    pub growth_stats: GrowthStats,
+    /// What the cycle collection did, including the collection at the end of the run
+    pub gc_stats: GcStats,
+}
+
+/// Runs `code` starting from `entry` and reports the goroutines left behind.
+pub fn run_with(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    entry: FunctionKey,
+    options: &RunOptions,
+) -> RunResult {
+    let cls = ClosureObj::gos_from_func(entry, &code.objects.functions, None);
+    run_closure(code, ffi, cls, vec![], 0, options).0
+}
+
+/// Calls `func`, a function or a closure of `code`, with `args` and returns the results.
+/// It's meant for calling into a program that has been run, so that the packages
+/// are initialized. The goroutines started by the call run like in `run_with`.
+pub fn call(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    func: &GosValue,
+    args: Vec<GosValue>,
+    options: &RunOptions,
+) -> std::result::Result<Vec<GosValue>, PanicData> {
+    let new_panic = |msg: &str| {
+        PanicData::new(
+            PanicKind::Runtime,
+            GosValue::empty_iface_with_val(GosValue::with_str(msg)),
+        )
+    };
+    let cls = match func.typ() {
+        ValueType::Closure => match func.as_closure() {
+            Some((ClosureObj::Gos(cls), _)) => cls.clone(),
+            Some((ClosureObj::Ffi(_), _)) => return Err(new_panic("cannot call a FFI function")),
+            None => return Err(new_panic(NIL_DEREF)),
+        },
+        ValueType::Function => {
+            match ClosureObj::gos_from_func(*func.as_function(), &code.objects.functions, None) {
+                ClosureObj::Gos(cls) => cls,
+                ClosureObj::Ffi(_) => unreachable!(),
+            }
+        }
+        _ => return Err(new_panic("not a function")),
+    };
+    let fobj = &code.objects.functions[cls.func];
+    let mut vec = fobj.ret_zeros.clone();
+    let ret_count = vec.len();
+    if let Some(r) = &cls.recv {
+        vec.push(r.clone());
+    }
+    vec.extend(args);
+    if vec.len() != ret_count + fobj.param_count() as usize {
+        return Err(new_panic("wrong number of arguments"));
+    }
+    let (result, results) = run_closure(code, ffi, ClosureObj::Gos(cls), vec, ret_count, options);
+    match result.panic {
+        Some(p) => Err(p),
+        None => Ok(results),
+    }
+}
+
+/// Runs `cls` as the main goroutine with `args` at the bottom of its stack,
+/// and returns the `ret_count` results left there.
+fn run_closure(
+    code: &Bytecode,
+    ffi: &FfiFactory,
+    cls: ClosureObj,
+    args: Vec<GosValue>,
+    ret_count: usize,
+    options: &RunOptions,
+) -> (RunResult, Vec<GosValue>) {
+    let mut gcc = GcContainer::with_max_pause(options.gc_max_pause.unwrap_or(DEFAULT_GC_MAX_PAUSE));
+    gcc.set_alloc_sites(options.alloc_sites.clone());
     let panic_data = Rc::new(RefCell::new(None));
+    let first_frame = CallFrame::with_closure(cls, 0);
 
     #[cfg(not(feature = "async"))]
-    {
-        let ctx = Context::new(code, &gcc, ffi, panic_data.clone());
-        let first_frame = ctx.new_entry_frame(code.entry);
-        Fiber::new(ctx, Stack::new(), first_frame).main_loop();
-    }
+    let (ctx, stack) = {
+        let ctx = Context::new(code, &gcc, ffi, panic_data.clone(), options);
+        let mut fiber = Fiber::new(ctx.clone(), args, first_frame, None);
+        fiber.main_loop();
+        (ctx, fiber.stack)
+    };
     #[cfg(feature = "async")]
-    {
+    let (ctx, stack) = {
         let exec = Rc::new(LocalExecutor::new());
-        let ctx = Context::new(exec.clone(), code, &gcc, ffi, panic_data.clone());
-        let entry = ctx.new_entry_frame(code.entry);
-        ctx.spawn_fiber(Stack::new(), entry);
+        let ctx = Context::new(exec.clone(), code, &gcc, ffi, panic_data.clone(), options);
+        let stack = ctx.spawn_fiber(args, first_frame, None);
+        let mut main_returned: Option<Instant> = None;
+        let (mut last_progress, mut stalled_ticks) = (0, 0);
         future::block_on(async {
             loop {
-                if !exec.try_tick() {
+                {
+                    let goroutines = ctx.goroutines.borrow();
+                    // the running goroutines stop by themselves, with their call stacks
+                    if goroutines.all_blocked() && ctx.interrupted() {
+                        drop(goroutines);
+                        ctx.interrupt(vec![], 0);
+                        break;
+                    }
+                    if let Some(grace) = options.grace_period {
+                        if !goroutines.live.contains_key(&0) {
+                            let since = *main_returned.get_or_insert_with(Instant::now);
+                            if since.elapsed() >= grace {
+                                break;
+                            }
+                        }
+                    }
+                    // Blocked goroutines keep polling their channels, once every one
+                    // of them has failed to get anywhere no one ever will.
+                    if goroutines.progress != last_progress || !goroutines.all_blocked() {
+                        last_progress = goroutines.progress;
+                        stalled_ticks = 0;
+                    } else {
+                        stalled_ticks += 1;
+                        if stalled_ticks > 2 * goroutines.live.len() {
+                            match &options.idle_hook {
+                                Some(hook) if hook() => stalled_ticks = 0,
+                                _ => break,
+                            }
+                        }
+                    }
+                }
+                if !exec.try_tick() || panic_data.borrow().is_some() {
                     break;
                 }
             }
         });
+        (ctx, stack)
+    };
+    let panic = panic_data.replace(None);
+    let leaked_goroutines = match panic {
+        Some(_) => vec![],
+        None => {
+            let mut leaked: Vec<GoroutineData> =
+                ctx.goroutines.borrow().live.values().cloned().collect();
+            leaked.sort_by_key(|g| g.id);
+            leaked
+        }
+    };
+    let growth_stats = ctx
+        .growth_stats
+        .as_ref()
+        .map_or_else(Map::new, |g| g.take());
+    let results = stack.borrow_mut().move_vec(0, ret_count as OpIndex);
+    // the goroutines left behind go with the context
+    drop((ctx, stack));
+    if let Some(sites) = gcc.alloc_sites() {
+        sites.leave();
     }
-    panic_data.replace(None)
+    gcc.collect();
+    (
+        RunResult {
+            panic,
+            leaked_goroutines,
+            growth_stats,
+            gc_stats: gcc.stats(),
+        },
+        results,
+    )
 }
 
 #[derive(Clone, Debug)]
@@ -166,6 +432,8 @@ struct CallFrame {
     referred_by: Option<Map<OpIndex, Referers>>,
 
     defer_stack: Option<Vec<DeferredCall>>,
+    // recover only stops a panic when called by a deferred function itself
+    is_deferred: bool,
 }
 
 impl CallFrame {
@@ -177,28 +445,76 @@ impl CallFrame {
             var_ptrs: None,
             referred_by: None,
             defer_stack: None,
+            is_deferred: false,
         }
     }
 
-    fn add_referred_by(&mut self, index: OpIndex, typ: ValueType, uv: &UpValue) {
-        if self.referred_by.is_none() {
-            self.referred_by = Some(Map::new());
+    // the local vars referred to by closures become up values
+    fn init_var_ptrs(&mut self, func: &FunctionObj, stack: &Rc<RefCell<Stack>>) {
+        let uvs = match &self.closure.as_gos().uvs {
+            Some(uvs) => uvs,
+            None => return,
+        };
+        let mut ptrs: Vec<UpValue> = Vec::with_capacity(func.up_ptrs.len());
+        for (i, p) in func.up_ptrs.iter().enumerate() {
+            ptrs.push(if p.is_local {
+                // local pointers
+                let uv = UpValue::new(
+                    p.clone_with_stack(Rc::downgrade(stack), self.stack_base as OpIndex),
+                );
+                add_referred_by(&mut self.referred_by, p.index, p.typ, &uv);
+                uv
+            } else {
+                uvs[&i].clone()
+            });
         }
-        let map = self.referred_by.as_mut().unwrap();
-        let weak = uv.downgrade();
-        match map.get_mut(&index) {
-            Some(v) => {
-                debug_assert!(v.typ == typ);
-                v.weaks.push(weak);
+        self.var_ptrs = Some(ptrs);
+    }
+
+    fn add_referred_by(&mut self, index: OpIndex, typ: ValueType, uv: &UpValue) {
+        add_referred_by(&mut self.referred_by, index, typ, uv)
+    }
+
+    // an up value of this frame that still points to the local var
+    fn open_up_value(&self, index: OpIndex) -> Option<UpValue> {
+        let referrers = self.referred_by.as_ref()?.get(&index)?;
+        referrers
+            .weaks
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .find(|uv| uv.is_open())
+    }
+
+    // the up values pointing to the local var are closed, so the closures and pointers
+    // made from then on refer to a new var, like the one of the next iteration of the
+    // loop whose body declares it
+    fn close_local(
+        &mut self,
+        index: OpIndex,
+        func: &FunctionObj,
+        stack: &Stack,
+        stack_rc: &Rc<RefCell<Stack>>,
+    ) {
+        let referrers = match self.referred_by.as_mut().and_then(|m| m.remove(&index)) {
+            Some(r) => r,
+            None => return,
+        };
+        let val = stack.get(self.stack_base + index);
+        for weak in referrers.weaks.iter() {
+            if let Some(uv) = weak.upgrade() {
+                if uv.is_open() {
+                    uv.close(val.clone());
+                }
             }
-            None => {
-                map.insert(
-                    index,
-                    Referers {
-                        typ: typ,
-                        weaks: vec![weak],
-                    },
-                );
+        }
+        // the pointers taken by this frame itself
+        if let Some(ptrs) = &mut self.var_ptrs {
+            for (p, ptr) in func.up_ptrs.iter().zip(ptrs.iter_mut()) {
+                if p.is_local && p.index == index {
+                    *ptr =
+                        UpValue::new(p.clone_with_stack(Rc::downgrade(stack_rc), self.stack_base));
+                    add_referred_by(&mut self.referred_by, index, p.typ, ptr);
+                }
             }
         }
     }
@@ -232,6 +548,31 @@ impl CallFrame {
     }
 }
 
+fn add_referred_by(
+    referred_by: &mut Option<Map<OpIndex, Referers>>,
+    index: OpIndex,
+    typ: ValueType,
+    uv: &UpValue,
+) {
+    let map = referred_by.get_or_insert_with(Map::new);
+    let weak = uv.downgrade();
+    match map.get_mut(&index) {
+        Some(v) => {
+            debug_assert!(v.typ == typ);
+            v.weaks.push(weak);
+        }
+        None => {
+            map.insert(
+                index,
+                Referers {
+                    typ: typ,
+                    weaks: vec![weak],
+                },
+            );
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct DeferredCall {
     frame: CallFrame,
@@ -244,21 +585,138 @@ enum Result {
     End,
 }
 
+/// The origin of a panic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicKind {
+    /// Raised by the VM itself, e.g. index out of range or nil dereference
+    Runtime,
+    /// Raised by a call to the builtin `panic`
+    Explicit,
+    /// An error returned by an FFI function
+    Ffi,
+    /// The run was stopped with `RunOptions::interrupt`, it can't be recovered
+    Interrupted,
+    /// The run exceeded `RunOptions::instruction_limit`, it can't be recovered
+    InstructionLimit,
+    /// With the `stack_check` feature, an instruction was about to read a value of
+    /// the wrong type, it can't be recovered
+    StackCheck,
+}
+
 #[derive(Debug)]
 pub struct PanicData {
+    pub kind: PanicKind,
     pub msg: GosValue,
     pub call_stack: Vec<(FunctionKey, OpIndex)>,
+    /// The id of the goroutine in which the panic happened, 0 is the main goroutine
+    pub goroutine: usize,
 }
 
 impl PanicData {
-    fn new(m: GosValue) -> PanicData {
+    fn new(kind: PanicKind, m: GosValue) -> PanicData {
         PanicData {
+            kind,
             msg: m,
             call_stack: vec![],
+            goroutine: 0,
         }
     }
 }
 
+/// What a suspended goroutine is waiting for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockReason {
+    Send,
+    Recv,
+    Select,
+}
+
+impl std::fmt::Display for BlockReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Send => "chan send",
+            Self::Recv => "chan receive",
+            Self::Select => "select",
+        })
+    }
+}
+
+/// The state of a goroutine as of the last time it was suspended
+#[derive(Clone, Debug)]
+pub struct GoroutineData {
+    /// 0 is the main goroutine
+    pub id: usize,
+    /// The go statement that started it, `None` for the main goroutine
+    pub spawned_at: Option<(FunctionKey, OpIndex)>,
+    /// `None` if it's runnable
+    pub blocked_on: Option<BlockReason>,
+    /// Where it's suspended
+    pub position: (FunctionKey, OpIndex),
+}
+
+/// Bookkeeping of the goroutines, shared by all the fibers
+#[derive(Default)]
+struct Goroutines {
+    next_id: usize,
+    live: Map<usize, GoroutineData>,
+    // bumped whenever a goroutine gets something done
+    progress: usize,
+}
+
+impl Goroutines {
+    fn add(
+        &mut self,
+        spawned_at: Option<(FunctionKey, OpIndex)>,
+        position: (FunctionKey, OpIndex),
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let data = GoroutineData {
+            id,
+            spawned_at,
+            blocked_on: None,
+            position,
+        };
+        self.live.insert(id, data);
+        self.progress += 1;
+        id
+    }
+
+    fn remove(&mut self, id: usize) {
+        self.live.remove(&id);
+        self.progress += 1;
+    }
+
+    #[cfg(feature = "async")]
+    fn suspend(
+        &mut self,
+        id: usize,
+        reason: Option<BlockReason>,
+        position: (FunctionKey, OpIndex),
+    ) {
+        if let Some(g) = self.live.get_mut(&id) {
+            if reason.is_none() {
+                self.progress += 1;
+            }
+            g.blocked_on = reason;
+            g.position = position;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn resume(&mut self, id: usize) {
+        if let Some(g) = self.live.get_mut(&id) {
+            g.blocked_on = None;
+            self.progress += 1;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn all_blocked(&self) -> bool {
+        self.live.values().all(|g| g.blocked_on.is_some())
+    }
+}
+
 #[derive(Clone)]
 struct Context<'a> {
     #[cfg(feature = "async")]
@@ -267,7 +725,15 @@ struct Context<'a> {
     gcc: &'a GcContainer,
     ffi_factory: &'a FfiFactory,
     panic_data: Rc<RefCell<Option<PanicData>>>,
-    next_id: Cell<usize>,
+    goroutines: Rc<RefCell<Goroutines>>,
+    growth_stats: Option<Rc<RefCell<GrowthStats>>>,
+    interrupt: Option<Arc<AtomicBool>>,
+    instructions: Rc<Cell<u64>>,
+    instruction_limit: Option<u64>,
+    fiber_pool: Rc<RefCell<FiberPool>>,
+    tiering: Option<Rc<Tiering>>,
+    map_order_seed: Option<u64>,
+    std_out: Option<StdOutSink>,
 }
 
 impl<'a> Context<'a> {
@@ -277,6 +743,7 @@ impl<'a> Context<'a> {
         gcc: &'a GcContainer,
         ffi_factory: &'a FfiFactory,
         panic_data: Rc<RefCell<Option<PanicData>>>,
+        options: &RunOptions,
     ) -> Context<'a> {
         Context {
             #[cfg(feature = "async")]
@@ -285,25 +752,126 @@ impl<'a> Context<'a> {
             gcc,
             ffi_factory,
             panic_data,
-            next_id: Cell::new(0),
+            goroutines: Rc::new(RefCell::new(Goroutines::default())),
+            growth_stats: options.growth_stats.then(Default::default),
+            interrupt: options.interrupt.clone(),
+            instructions: Rc::new(Cell::new(0)),
+            instruction_limit: options.instruction_limit,
+            fiber_pool: Rc::new(RefCell::new(FiberPool::new(
+                options.fiber_pool_size.unwrap_or(DEFAULT_FIBER_POOL_SIZE),
+            ))),
+            tiering: options
+                .tiering_threshold
+                .map(|n| Rc::new(Tiering::new(n, &code.objects.functions))),
+            map_order_seed: options.map_order_seed,
+            std_out: options.std_out.clone(),
         }
     }
 
-    fn new_entry_frame(&self, entry: FunctionKey) -> CallFrame {
-        let cls = ClosureObj::gos_from_func(entry, &self.code.objects.functions, None);
-        CallFrame::with_closure(cls, 0)
+    fn interrupted(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|f| f.load(AtomicOrdering::Relaxed))
+    }
+
+    /// Adds the instructions of a time slice, returns whether the limit is exceeded.
+    fn count_instructions(&self, count: u64) -> bool {
+        let total = self.instructions.get() + count;
+        self.instructions.set(total);
+        self.instruction_limit.is_some_and(|limit| total > limit)
+    }
+
+    fn interrupt(&self, call_stack: Vec<(FunctionKey, OpIndex)>, goroutine: usize) {
+        self.stop(PanicKind::Interrupted, "interrupted", call_stack, goroutine);
+    }
+
+    /// Ends the run with an unrecoverable panic, unless it's already panicking.
+    fn stop(
+        &self,
+        kind: PanicKind,
+        msg: &str,
+        call_stack: Vec<(FunctionKey, OpIndex)>,
+        goroutine: usize,
+    ) {
+        let mut data = self.panic_data.borrow_mut();
+        if data.is_none() {
+            let msg = GosValue::empty_iface_with_val(GosValue::with_str(msg));
+            let mut p = PanicData::new(kind, msg);
+            p.call_stack = call_stack;
+            p.goroutine = goroutine;
+            *data = Some(p);
+        }
     }
 
+    /// The stack of the main goroutine is returned for its results, the ones of
+    /// the others go back to the pool when they return.
     #[cfg(feature = "async")]
-    fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame) {
-        let mut f = Fiber::new(self.clone(), stack, first_frame);
+    fn spawn_fiber(
+        &self,
+        args: Vec<GosValue>,
+        first_frame: CallFrame,
+        spawned_at: Option<(FunctionKey, OpIndex)>,
+    ) -> Rc<RefCell<Stack>> {
+        let mut f = Fiber::new(self.clone(), args, first_frame, spawned_at);
+        let stack = f.stack.clone();
+        let recycle = spawned_at.is_some();
         self.exec
             .spawn(async move {
                 // let parent fiber go first
                 future::yield_now().await;
                 f.main_loop().await;
+                if recycle {
+                    f.recycle();
+                }
             })
             .detach();
+        stack
+    }
+}
+
+/// How many stacks of finished goroutines are kept by default, see `RunOptions::fiber_pool_size`
+pub const DEFAULT_FIBER_POOL_SIZE: usize = 64;
+
+/// The stacks that have grown past this many slots are not kept
+#[cfg(feature = "async")]
+const MAX_POOLED_STACK_LEN: usize = 4096;
+
+/// The stacks and frame Vecs of the goroutines that have returned, for the new
+/// ones, so that a short lived goroutine doesn't have to allocate its own.
+/// Without async there is only the main goroutine, it only takes from the pool.
+#[cfg_attr(not(feature = "async"), allow(dead_code))]
+struct FiberPool {
+    free: Vec<(Stack, Vec<CallFrame>)>,
+    limit: usize,
+}
+
+impl FiberPool {
+    fn new(limit: usize) -> FiberPool {
+        FiberPool {
+            free: vec![],
+            limit,
+        }
+    }
+
+    /// A stack and frame Vec with nothing left in them, reused if there are any
+    fn take(&mut self) -> (Stack, Vec<CallFrame>) {
+        match self.free.pop() {
+            Some((mut stack, frames)) => {
+                stack.reset();
+                (stack, frames)
+            }
+            None => (Stack::new(), vec![]),
+        }
+    }
+
+    /// Keeps them unless the pool is full or the stack has grown too big to hold on to.
+    /// The stack is only cleared when it's taken again.
+    #[cfg(feature = "async")]
+    fn put(&mut self, stack: Stack, mut frames: Vec<CallFrame>) {
+        if self.free.len() < self.limit && stack.len() <= MAX_POOLED_STACK_LEN {
+            frames.clear();
+            self.free.push((stack, frames));
+        }
     }
 }
 
@@ -313,6 +881,11 @@ struct Fiber<'a> {
     frames: Vec<CallFrame>,
     context: Context<'a>,
     _id: usize,
+    /// The args and the first frame until the fiber starts running, its stack is
+    /// only taken from the pool then.
+    pending: Option<(Vec<GosValue>, CallFrame)>,
+    #[cfg(feature = "stack_check")]
+    stack_check: StackCheck,
 }
 
 impl<'a> Fiber<'a> {
@@ -320,20 +893,62 @@ impl<'a> Fiber<'a> {
         self._id
     }
 
-    fn new(context: Context<'a>, stack: Stack, first_frame: CallFrame) -> Fiber<'a> {
-        let _id = context.next_id.get();
-        context.next_id.set(_id + 1);
+    fn new(
+        context: Context<'a>,
+        args: Vec<GosValue>,
+        first_frame: CallFrame,
+        spawned_at: Option<(FunctionKey, OpIndex)>,
+    ) -> Fiber<'a> {
+        let _id = context
+            .goroutines
+            .borrow_mut()
+            .add(spawned_at, (first_frame.func(), 0));
         Fiber {
-            stack: Rc::new(RefCell::new(stack)),
-            rstack: RangeStack::new(),
-            frames: vec![first_frame],
+            stack: Rc::new(RefCell::new(Stack::unallocated())),
+            rstack: RangeStack::new(context.map_order_seed),
+            frames: vec![],
             context,
             _id,
+            pending: Some((args, first_frame)),
+            #[cfg(feature = "stack_check")]
+            stack_check: StackCheck::new(),
+        }
+    }
+
+    /// Sets up the first frame on a stack from the pool, the first time the fiber runs.
+    fn start(&mut self) {
+        let (args, mut first_frame) = match self.pending.take() {
+            Some(p) => p,
+            None => return,
+        };
+        let (mut stack, mut frames) = self.context.fiber_pool.borrow_mut().take();
+        let func = &self.context.code.objects.functions[first_frame.func()];
+        stack.set_min_size(func.max_write_index as usize + 1);
+        stack.set_vec(0, args);
+        *self.stack.borrow_mut() = stack;
+        // it's done by CALL for the other frames
+        if first_frame.var_ptrs.is_none() {
+            first_frame.init_var_ptrs(func, &self.stack);
+        }
+        frames.push(first_frame);
+        self.frames = frames;
+    }
+
+    /// Gives the stack and frames back to the pool, once the goroutine has returned
+    /// and no up value refers to its stack anymore.
+    #[cfg(feature = "async")]
+    fn recycle(&mut self) {
+        if !self.frames.is_empty() {
+            return;
         }
+        let stack = std::mem::replace(&mut *self.stack.borrow_mut(), Stack::unallocated());
+        let frames = std::mem::take(&mut self.frames);
+        self.context.fiber_pool.borrow_mut().put(stack, frames);
     }
 
     #[cfg_attr(feature = "async", go_pmacro::async_fn)]
     fn main_loop(&mut self) {
+        self.start();
         let ctx = &self.context;
         let gcc = ctx.gcc;
         let objs: &VMObjects = &ctx.code.objects;
@@ -342,6 +957,10 @@ impl<'a> Fiber<'a> {
         let prim_meta: &PrimitiveMeta = &objs.prim_meta;
         let ifaces = &ctx.code.ifaces;
         let indices = &ctx.code.indices;
+        let strings = &ctx.code.strings;
+        let growth_stats = ctx.growth_stats.as_deref();
+        let tiering = ctx.tiering.as_deref();
+        let alloc_sites = gcc.alloc_sites();
         let mut frame_height = self.frames.len();
         let fr = self.frames.last().unwrap();
         let mut func = &objs.functions[fr.func()];
@@ -350,11 +969,12 @@ impl<'a> Fiber<'a> {
         let mut stack_mut_ref = self.stack.borrow_mut();
         let mut stack: &mut Stack = &mut stack_mut_ref;
         // allocate local variables
-        stack.set_vec(func.param_count(), func.local_zeros.clone());
+        stack.set_from_slice(func.ret_count() + func.param_count(), &func.local_zeros);
 
-        let mut code = &func.code;
+        let mut code = Tiering::code(tiering, fr.func(), func);
 
         let mut total_inst = 0;
+        let mut counted_inst = 0;
         //let mut stats: Map<Opcode, usize> = Map::new();
         loop {
             let mut frame = self.frames.last_mut().unwrap();
@@ -367,6 +987,24 @@ impl<'a> Fiber<'a> {
                 total_inst += 1;
                 //stats.entry(*inst).and_modify(|e| *e += 1).or_insert(1);
                 frame.pc += 1;
+                if let Some(sites) = alloc_sites {
+                    sites.enter(frame.func(), frame.pc - 1);
+                }
+                #[cfg(feature = "stack_check")]
+                if let Err(msg) =
+                    self.stack_check
+                        .check(frame.func(), frame.pc - 1, inst, stack, sb, ctx.code)
+                {
+                    let call_stack = self
+                        .frames
+                        .iter()
+                        .rev()
+                        .map(|f| (f.func(), f.pc - 1))
+                        .collect();
+                    ctx.stop(PanicKind::StackCheck, &msg, call_stack, self._id);
+                    result = Result::End;
+                    break;
+                }
                 //dbg!(inst);
                 match inst_op {
                     // desc: local
@@ -402,21 +1040,24 @@ impl<'a> Fiber<'a> {
                             Ok((array, i)) => match inst.op1 {
                                 Opcode::VOID => {
                                     let val = stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                    let result = array.caller(caller).array_set(&array, &val, i);
+                                    let result = array.caller(caller).array_set(array, &val, i);
                                     panic_if_err!(result, panic, frame, code);
                                 }
-                                _ => match array.caller(caller).array_get(&array, i) {
+                                _ => match array.caller(caller).array_get(array, i) {
                                     Ok(old) => {
-                                        let val = stack.read_and_op(
-                                            old.data(),
-                                            inst.t0,
-                                            inst.op1,
-                                            inst.s1,
-                                            sb,
-                                            &consts,
-                                        );
-                                        let result =
-                                            array.caller(caller).array_set(&array, &val, i);
+                                        let result = stack
+                                            .read_and_op(
+                                                old.data(),
+                                                inst.t0,
+                                                inst.t1,
+                                                inst.op1,
+                                                inst.s1,
+                                                sb,
+                                                &consts,
+                                            )
+                                            .and_then(|val| {
+                                                array.caller(caller).array_set(array, &val, i)
+                                            });
                                         panic_if_err!(result, panic, frame, code);
                                     }
                                     Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -450,16 +1091,19 @@ impl<'a> Fiber<'a> {
                             }
                             _ => match array.caller(caller).array_get(&array, index) {
                                 Ok(old) => {
-                                    let val = stack.read_and_op(
-                                        old.data(),
-                                        inst.t0,
-                                        inst.op1,
-                                        inst.s1,
-                                        sb,
-                                        &consts,
-                                    );
-                                    let result =
-                                        array.caller(caller).array_set(&array, &val, index);
+                                    let result = stack
+                                        .read_and_op(
+                                            old.data(),
+                                            inst.t0,
+                                            inst.t1,
+                                            inst.op1,
+                                            inst.s1,
+                                            sb,
+                                            &consts,
+                                        )
+                                        .and_then(|val| {
+                                            array.caller(caller).array_set(&array, &val, index)
+                                        });
                                     panic_if_err!(result, panic, frame, code);
                                 }
                                 Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -476,6 +1120,10 @@ impl<'a> Fiber<'a> {
                         frame.pc += 1;
                         let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
+                        if let Err(e) = check_hashable(key, objs) {
+                            go_panic_str!(panic, e.as_str(), frame, code);
+                            continue;
+                        }
                         let val = match map.as_map() {
                             Some(map) => map.0.get(&key),
                             None => None,
@@ -500,30 +1148,50 @@ impl<'a> Fiber<'a> {
                         match dest.as_non_nil_map() {
                             Ok(map) => {
                                 let key = stack.read(inst.s0, sb, consts);
-                                match inst.op1 {
+                                if let Err(e) = check_hashable(key, objs) {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                                let val = match inst.op1 {
                                     Opcode::VOID => {
-                                        let val =
-                                            stack.read(inst.s1, sb, consts).copy_semantic(gcc);
-                                        map.0.insert(key.clone(), val);
+                                        Ok(stack.read(inst.s1, sb, consts).copy_semantic(gcc))
                                     }
                                     _ => {
                                         let old = match map.0.get(&key) {
                                             Some(v) => v,
                                             None => stack.read(inst_ex.s0, sb, consts).clone(),
                                         };
-                                        let val = stack.read_and_op(
+                                        stack.read_and_op(
                                             old.data(),
                                             inst.t0,
+                                            inst.t1,
                                             inst.op1,
                                             inst.s1,
                                             sb,
                                             &consts,
-                                        );
-                                        map.0.insert(key.clone(), val);
+                                        )
                                     }
+                                };
+                                match val {
+                                    Ok(val) => {
+                                        let cap = growth_stats.map(|_| map.0.capacity());
+                                        // the key is copied like the value, a struct
+                                        // key changed later must not change in the map
+                                        map.0.insert(strings.get(key.copy_semantic(gcc)), val);
+                                        if let (Some(stats), Some(cap)) = (growth_stats, cap) {
+                                            if map.0.capacity() > cap {
+                                                count_growth(stats, frame, 2, |c| {
+                                                    c.map_rehashes += 1
+                                                });
+                                            }
+                                        }
+                                    }
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            Err(_) => {
+                                go_panic_str!(panic, "assignment to entry in nil map", frame, code)
+                            }
                         }
                     }
                     // desc: local
@@ -547,15 +1215,18 @@ impl<'a> Fiber<'a> {
                             _ => {
                                 let old =
                                     &mut dest.as_struct().0.borrow_fields_mut()[inst.s0 as usize];
-                                let val = stack.read_and_op(
+                                match stack.read_and_op(
                                     old.data(),
                                     inst.t0,
+                                    inst.t1,
                                     inst.op1,
                                     inst.s1,
                                     sb,
                                     &consts,
-                                );
-                                *old = val;
+                                ) {
+                                    Ok(val) => *old = val,
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
@@ -596,16 +1267,19 @@ impl<'a> Fiber<'a> {
                                     s.as_struct().0.borrow_fields_mut()[index] = val;
                                 }
                                 _ => {
-                                    let old = &s.as_struct().0.borrow_fields()[index as usize];
-                                    let val = stack.read_and_op(
+                                    let old = &mut s.as_struct().0.borrow_fields_mut()[index];
+                                    match stack.read_and_op(
                                         old.data(),
                                         inst.t0,
+                                        inst.t1,
                                         inst.op1,
                                         inst.s1,
                                         sb,
                                         &consts,
-                                    );
-                                    s.as_struct().0.borrow_fields_mut()[index as usize] = val;
+                                    ) {
+                                        Ok(val) => *old = val,
+                                        Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                    }
                                 }
                             },
                             Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -636,15 +1310,18 @@ impl<'a> Fiber<'a> {
                             }
                             _ => {
                                 let mut old = pkg.member_mut(index);
-                                let val = stack.read_and_op(
+                                match stack.read_and_op(
                                     old.data(),
                                     inst.t0,
+                                    inst.t1,
                                     inst.op1,
                                     inst.s1,
                                     sb,
                                     &consts,
-                                );
-                                *old = val;
+                                ) {
+                                    Ok(val) => *old = val,
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
@@ -672,11 +1349,12 @@ impl<'a> Fiber<'a> {
                                     stack.read_and_op(
                                         old.data(),
                                         inst.t0,
+                                        inst.t1,
                                         inst.op1,
                                         inst.s0,
                                         sb,
                                         &consts,
-                                    )
+                                    )?
                                 }
                             };
                             match p {
@@ -709,6 +1387,11 @@ impl<'a> Fiber<'a> {
                         let val = uvs[inst.s0 as usize].value(stack).into_owned();
                         stack.set(inst.d + sb, val);
                     }
+                    // s0: local
+                    Opcode::CLOSE_UP_VALUE => {
+                        let func = frame.func_obj(objs);
+                        frame.close_local(inst.s0, func, stack, &self.stack);
+                    }
                     Opcode::STORE_UP_VALUE => {
                         let uvs = frame.var_ptrs.as_ref().unwrap();
                         let uv = &uvs[inst.d as usize];
@@ -719,42 +1402,77 @@ impl<'a> Fiber<'a> {
                             }
                             _ => {
                                 let old = uv.value(stack);
-                                let val = stack.read_and_op(
+                                match stack.read_and_op(
                                     old.data(),
                                     inst.t0,
+                                    inst.t1,
                                     inst.op1,
                                     inst.s0,
                                     sb,
                                     &consts,
-                                );
-                                uv.set_value(val, stack);
+                                ) {
+                                    Ok(val) => uv.set_value(val, stack),
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
                             }
                         }
                     }
                     Opcode::ADD => binary_op!(stack, binary_op_add, inst, sb, consts),
                     Opcode::SUB => binary_op!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL => binary_op!(stack, binary_op_mul, inst, sb, consts),
-                    Opcode::QUO => binary_op!(stack, binary_op_quo, inst, sb, consts),
-                    Opcode::REM => binary_op!(stack, binary_op_rem, inst, sb, consts),
+                    Opcode::QUO => {
+                        let result = checked_binary_op!(stack, binary_op_quo, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::REM => {
+                        let result = checked_binary_op!(stack, binary_op_rem, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::AND => binary_op!(stack, binary_op_and, inst, sb, consts),
                     Opcode::OR => binary_op!(stack, binary_op_or, inst, sb, consts),
                     Opcode::XOR => binary_op!(stack, binary_op_xor, inst, sb, consts),
                     Opcode::AND_NOT => binary_op!(stack, binary_op_and_not, inst, sb, consts),
-                    Opcode::SHL => shift_op!(stack, binary_op_shl, inst, sb, consts),
-                    Opcode::SHR => shift_op!(stack, binary_op_shr, inst, sb, consts),
+                    Opcode::SHL => {
+                        let result = shift_op!(stack, binary_op_shl, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SHR => {
+                        let result = shift_op!(stack, binary_op_shr, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::CONCAT => {
+                        let begin = inst.s0 + sb;
+                        let parts = (begin..begin + inst.s1).map(|i| stack.get(i).as_string());
+                        let val = GosValue::new_string(StringObj::concat(parts));
+                        stack.set(inst.d + sb, val);
+                    }
                     Opcode::ADD_ASSIGN => binary_op_assign!(stack, binary_op_add, inst, sb, consts),
                     Opcode::SUB_ASSIGN => binary_op_assign!(stack, binary_op_sub, inst, sb, consts),
                     Opcode::MUL_ASSIGN => binary_op_assign!(stack, binary_op_mul, inst, sb, consts),
-                    Opcode::QUO_ASSIGN => binary_op_assign!(stack, binary_op_quo, inst, sb, consts),
-                    Opcode::REM_ASSIGN => binary_op_assign!(stack, binary_op_rem, inst, sb, consts),
+                    Opcode::QUO_ASSIGN => {
+                        let result =
+                            checked_binary_op_assign!(stack, binary_op_quo, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::REM_ASSIGN => {
+                        let result =
+                            checked_binary_op_assign!(stack, binary_op_rem, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::AND_ASSIGN => binary_op_assign!(stack, binary_op_and, inst, sb, consts),
                     Opcode::OR_ASSIGN => binary_op_assign!(stack, binary_op_or, inst, sb, consts),
                     Opcode::XOR_ASSIGN => binary_op_assign!(stack, binary_op_xor, inst, sb, consts),
                     Opcode::AND_NOT_ASSIGN => {
                         binary_op_assign!(stack, binary_op_and_not, inst, sb, consts)
                     }
-                    Opcode::SHL_ASSIGN => shift_op_assign!(stack, binary_op_shl, inst, sb, consts),
-                    Opcode::SHR_ASSIGN => shift_op_assign!(stack, binary_op_shr, inst, sb, consts),
+                    Opcode::SHL_ASSIGN => {
+                        let result = shift_op_assign!(stack, binary_op_shl, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SHR_ASSIGN => {
+                        let result = shift_op_assign!(stack, binary_op_shr, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::INC => unsafe {
                         let v = stack.get_mut(inst.d + sb).data_mut();
                         *v = v.inc(inst.t0);
@@ -763,6 +1481,33 @@ impl<'a> Fiber<'a> {
                         let v = stack.get_mut(inst.d + sb).data_mut();
                         *v = v.dec(inst.t0);
                     },
+                    Opcode::ADD_CHECKED => {
+                        let result =
+                            checked_binary_op!(stack, binary_op_add_checked, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SUB_CHECKED => {
+                        let result =
+                            checked_binary_op!(stack, binary_op_sub_checked, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::MUL_CHECKED => {
+                        let result =
+                            checked_binary_op!(stack, binary_op_mul_checked, inst, sb, consts);
+                        panic_if_err!(result, panic, frame, code);
+                    }
+                    Opcode::SHL_CHECKED => {
+                        let right = stack.read(inst.s1, sb, consts).data().shift_count(inst.t1);
+                        let result = right
+                            .and_then(|count| {
+                                stack
+                                    .read(inst.s0, sb, consts)
+                                    .data()
+                                    .binary_op_shl_checked(&count, inst.t0)
+                            })
+                            .map(|vdata| stack.set(inst.d + sb, GosValue::new(inst.t0, vdata)));
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::UNARY_SUB => unary_op!(stack, unary_negate, inst, sb, consts),
                     Opcode::UNARY_XOR => unary_op!(stack, unary_xor, inst, sb, consts),
                     Opcode::NOT => unary_op!(stack, logical_not, inst, sb, consts),
@@ -770,21 +1515,27 @@ impl<'a> Fiber<'a> {
                         let a = stack.read(inst.s0, sb, consts);
                         let b = stack.read(inst.s1, sb, consts);
                         let eq = if inst.t0.copyable() && inst.t0 == inst.t1 {
-                            a.data().compare_eql(b.data(), inst.t0)
+                            Ok(a.data().compare_eql(b.data(), inst.t0))
                         } else {
-                            a.eq(b)
+                            equal_or_err(a, b, objs)
                         };
-                        stack.set(inst.d + sb, eq.into());
+                        match eq {
+                            Ok(eq) => stack.set(inst.d + sb, eq.into()),
+                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                        }
                     }
                     Opcode::NEQ => {
                         let a = stack.read(inst.s0, sb, consts);
                         let b = stack.read(inst.s1, sb, consts);
-                        let neq = if inst.t0.copyable() {
-                            a.data().compare_neq(b.data(), inst.t0)
+                        let eq = if inst.t0.copyable() && inst.t0 == inst.t1 {
+                            Ok(a.data().compare_eql(b.data(), inst.t0))
                         } else {
-                            !a.eq(b)
+                            equal_or_err(a, b, objs)
                         };
-                        stack.set(inst.d + sb, neq.into());
+                        match eq {
+                            Ok(eq) => stack.set(inst.d + sb, (!eq).into()),
+                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                        }
                     }
                     Opcode::LSS => {
                         let a = stack.read(inst.s0, sb, consts);
@@ -792,7 +1543,7 @@ impl<'a> Fiber<'a> {
                         let lss = if inst.t0.copyable() {
                             a.data().compare_lss(b.data(), inst.t0)
                         } else {
-                            a.cmp(b) == Ordering::Less
+                            value_ops::try_order(a, b) == Some(Ordering::Less)
                         };
                         stack.set(inst.d + sb, lss.into());
                     }
@@ -802,7 +1553,7 @@ impl<'a> Fiber<'a> {
                         let gtr = if inst.t0.copyable() {
                             a.data().compare_gtr(b.data(), inst.t0)
                         } else {
-                            a.cmp(b) == Ordering::Greater
+                            value_ops::try_order(a, b) == Some(Ordering::Greater)
                         };
                         stack.set(inst.d + sb, gtr.into());
                     }
@@ -812,7 +1563,10 @@ impl<'a> Fiber<'a> {
                         let leq = if inst.t0.copyable() {
                             a.data().compare_leq(b.data(), inst.t0)
                         } else {
-                            a.cmp(b) != Ordering::Greater
+                            matches!(
+                                value_ops::try_order(a, b),
+                                Some(Ordering::Less | Ordering::Equal)
+                            )
                         };
                         stack.set(inst.d + sb, leq.into());
                     }
@@ -822,7 +1576,10 @@ impl<'a> Fiber<'a> {
                         let geq = if inst.t0.copyable() {
                             a.data().compare_geq(b.data(), inst.t0)
                         } else {
-                            a.cmp(b) != Ordering::Less
+                            matches!(
+                                value_ops::try_order(a, b),
+                                Some(Ordering::Greater | Ordering::Equal)
+                            )
                         };
                         stack.set(inst.d + sb, geq.into());
                     }
@@ -896,6 +1653,11 @@ impl<'a> Fiber<'a> {
                         let chan = stack.read(inst.s0, sb, consts).as_channel().cloned();
                         let val = stack.read(inst.s1, sb, consts).clone();
                         drop(stack_mut_ref);
+                        gcc.step();
+                        let pos = (frame.func(), frame.pc - 1);
+                        ctx.goroutines
+                            .borrow_mut()
+                            .suspend(self._id, Some(BlockReason::Send), pos);
                         let re = match chan {
                             Some(c) => c.send(&val).await,
                             None => loop {
@@ -903,17 +1665,24 @@ impl<'a> Fiber<'a> {
                             },
                         };
                         restore_stack_ref!(self, stack, stack_mut_ref);
+                        ctx.goroutines.borrow_mut().resume(self._id);
                         panic_if_err!(re, panic, frame, code);
                     }
                     #[cfg(not(feature = "async"))]
                     Opcode::RECV => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
                     Opcode::RECV => {
+                        let pos = (frame.func(), frame.pc - 1);
+                        ctx.goroutines
+                            .borrow_mut()
+                            .suspend(self._id, Some(BlockReason::Recv), pos);
                         match stack.read(inst.s0, sb, consts).as_channel().cloned() {
                             Some(chan) => {
                                 drop(stack_mut_ref);
-                                let val = chan.recv().await;
+                                gcc.step();
+                                let val = chan.recv(gcc).await;
                                 restore_stack_ref!(self, stack, stack_mut_ref);
+                                ctx.goroutines.borrow_mut().resume(self._id);
                                 let (unwrapped, ok) = unwrap_recv_val!(chan, val, gcc);
                                 stack.set(inst.d + sb, unwrapped);
                                 if inst.t1 == ValueType::FlagB {
@@ -926,61 +1695,70 @@ impl<'a> Fiber<'a> {
                         };
                     }
                     Opcode::PACK_VARIADIC => {
-                        let v = stack.move_vec(inst.s0 + sb, inst.s1 + sb);
-                        let val = GosValue::slice_with_data(v, caller.get(inst.t0), gcc);
+                        // no arguments are a nil slice, like in Go
+                        let val = match inst.s0 == inst.s1 {
+                            true => GosValue::new_nil_slice(inst.t0),
+                            false => {
+                                let v = stack.move_vec(inst.s0 + sb, inst.s1 + sb);
+                                GosValue::slice_with_data(v, caller.get(inst.t0), gcc)
+                            }
+                        };
                         stack.set(inst.d + sb, val);
                     }
                     // t0: call style
-                    // d: closure
+                    // d: closure, or the receiver BIND_I_METHOD_CACHED left for CALL_CACHED
                     // s0: next stack base
-                    Opcode::CALL => {
+                    Opcode::CALL | Opcode::CALL_CACHED => {
                         let call_style = inst.t0;
-                        let cls = stack
-                            .read(inst.d, sb, consts)
-                            .as_closure()
-                            .unwrap()
-                            .0
-                            .clone();
-                        let next_sb = sb + inst.s0;
-                        match &cls {
-                            ClosureObj::Gos(gosc) => {
-                                let next_func = &objs.functions[gosc.func];
-                                let mut returns_recv = next_func.ret_zeros.clone();
-                                if let Some(r) = &gosc.recv {
-                                    // push receiver on stack as the first parameter
-                                    // don't call copy_semantic because BIND_METHOD did it already
-                                    returns_recv.push(r.clone());
+                        let target = stack.read(inst.d, sb, consts);
+                        let cls = if inst_op == Opcode::CALL_CACHED
+                            && target.typ() != ValueType::Closure
+                        {
+                            // the inline cache hit, it's the receiver of the cached method
+                            let cache = tiering.unwrap().cache(frame.func(), frame.pc - 1);
+                            ClosureObj::gos_from_func(
+                                cache.func,
+                                &objs.functions,
+                                Some(target.clone()),
+                            )
+                        } else {
+                            match target.as_closure() {
+                                Some((cls, _)) => cls.clone(),
+                                None => {
+                                    go_panic_str!(panic, NIL_DEREF, frame, code);
+                                    continue;
                                 }
-                                stack.set_min_size(
-                                    (next_sb + next_func.max_write_index + 1) as usize,
-                                );
-                                stack.set_vec(next_sb, returns_recv);
                             }
-                            _ => {}
-                        }
-                        let mut nframe = CallFrame::with_closure(cls.clone(), next_sb);
-
+                        };
+                        let next_sb = sb + inst.s0;
                         match cls {
                             ClosureObj::Gos(gosc) => {
                                 let nfunc = &objs.functions[gosc.func];
-                                if let Some(uvs) = gosc.uvs {
-                                    let mut ptrs: Vec<UpValue> =
-                                        Vec::with_capacity(nfunc.up_ptrs.len());
-                                    for (i, p) in nfunc.up_ptrs.iter().enumerate() {
-                                        ptrs.push(if p.is_local {
-                                            // local pointers
-                                            let uv = UpValue::new(p.clone_with_stack(
-                                                Rc::downgrade(&self.stack),
-                                                nframe.stack_base as OpIndex,
-                                            ));
-                                            nframe.add_referred_by(p.index, p.typ, &uv);
-                                            uv
-                                        } else {
-                                            uvs[&i].clone()
-                                        });
+                                if let Some(t) = tiering {
+                                    t.count_call(gosc.func, nfunc);
+                                }
+                                // the frame is in the same stack as the caller's, right
+                                // after its operands, nothing is allocated unless it grows
+                                stack.set_min_size((next_sb + nfunc.max_write_index + 1) as usize);
+                                stack.set_from_slice(next_sb, &nfunc.ret_zeros);
+                                if let Some(r) = &gosc.recv {
+                                    let recv_index = next_sb + nfunc.ret_count();
+                                    if inst.t1 != ValueType::FlagA {
+                                        // a method value called as a func value, the args
+                                        // are where the receiver goes
+                                        let end = recv_index + nfunc.param_count() - 1;
+                                        for i in (recv_index..end).rev() {
+                                            let arg = stack.get(i).clone();
+                                            stack.set(i + 1, arg);
+                                        }
                                     }
-                                    nframe.var_ptrs = Some(ptrs);
+                                    // push receiver on stack as the first parameter
+                                    // don't call copy_semantic because BIND_METHOD did it already
+                                    stack.set(recv_index, r.clone());
                                 }
+                                let mut nframe =
+                                    CallFrame::with_closure(ClosureObj::Gos(gosc), next_sb);
+                                nframe.init_var_ptrs(nfunc, &self.stack);
                                 match call_style {
                                     ValueType::FlagA => {
                                         // default call
@@ -989,7 +1767,7 @@ impl<'a> Fiber<'a> {
                                         frame = self.frames.last_mut().unwrap();
                                         func = nfunc;
                                         sb = frame.stack_base;
-                                        code = &func.code;
+                                        code = Tiering::code(tiering, frame.func(), func);
                                         //dbg!("default", &code);
                                     }
                                     #[cfg(not(feature = "async"))]
@@ -1002,9 +1780,9 @@ impl<'a> Fiber<'a> {
                                             + nfunc.ret_count()
                                             + nfunc.param_count() as OpIndex;
                                         let vec = stack.move_vec(begin, end);
-                                        let nstack = Stack::with_vec(vec);
                                         nframe.stack_base = 0;
-                                        self.context.spawn_fiber(nstack, nframe);
+                                        let spawned_at = (frame.func(), frame.pc - 1);
+                                        ctx.spawn_fiber(vec, nframe, Some(spawned_at));
                                     }
                                     ValueType::FlagC => {
                                         // deferred
@@ -1013,6 +1791,7 @@ impl<'a> Fiber<'a> {
                                             + nfunc.ret_count()
                                             + nfunc.param_count() as OpIndex;
                                         let vec = stack.move_vec(begin, end);
+                                        nframe.is_deferred = true;
                                         let deferred = DeferredCall {
                                             frame: nframe,
                                             vec: vec,
@@ -1024,37 +1803,65 @@ impl<'a> Fiber<'a> {
                             }
                             ClosureObj::Ffi(ffic) => {
                                 let sig = objs.metas[ffic.meta.key].as_signature();
-                                let result_begin = nframe.stack_base;
-                                let param_begin = result_begin + 1 + sig.results.len() as OpIndex;
+                                let result_begin = next_sb;
+                                // the closure is bound to an interface, the slot left
+                                // for the receiver, if any, isn't used
+                                let recv_slot = (inst.t1 == ValueType::FlagA) as OpIndex;
+                                let param_begin =
+                                    result_begin + recv_slot + sig.results.len() as OpIndex;
                                 let end = param_begin + sig.params.len() as OpIndex;
                                 let params = stack.move_vec(param_begin, end);
                                 // release stack so that code in ffi can yield
                                 drop(stack_mut_ref);
-                                let returns = {
+                                if ffic.is_async {
+                                    gcc.step();
+                                }
+                                // the returns, or the future of an async call
+                                let call = {
+                                    let mut stack_ref = self.stack.borrow_mut();
                                     let mut ctx = FfiCtx {
                                         func_name: &ffic.func_name,
                                         vm_objs: objs,
                                         user_data: ctx.ffi_factory.user_data(),
-                                        stack: &mut self.stack.borrow_mut(),
+                                        stack: &mut stack_ref,
                                         gcc,
                                         array_slice_caller: caller,
+                                        std_out: ctx.std_out.as_ref(),
                                     };
                                     if !ffic.is_async {
-                                        ffic.ffi.call(&mut ctx, params)
+                                        Ok(ffic.ffi.call(&mut ctx, params))
                                     } else {
                                         #[cfg(not(feature = "async"))]
                                         {
-                                            Err("Async features disabled".to_owned().into())
+                                            Ok(Err("Async features disabled".to_owned().into()))
                                         }
                                         #[cfg(feature = "async")]
-                                        ffic.ffi.async_call(&mut ctx, params).await
+                                        {
+                                            Err(ffic.ffi.async_call(&mut ctx, params))
+                                        }
                                     }
                                 };
+                                // the stack is released before waiting, other goroutines
+                                // may write to it through up-values while the call waits
+                                #[cfg(feature = "async")]
+                                let returns = match call {
+                                    Ok(returns) => returns,
+                                    Err(fut) => fut.await,
+                                };
+                                #[cfg(not(feature = "async"))]
+                                let returns =
+                                    call.unwrap_or_else(|e: std::convert::Infallible| match e {});
                                 restore_stack_ref!(self, stack, stack_mut_ref);
+                                let returns = returns.and_then(|result| {
+                                    TupleDesc::results_of(sig, &objs.metas)
+                                        .check(&ffic.func_name, "handler", result)
+                                        .map_err(|e| e.to_string().into())
+                                });
                                 match returns {
-                                    Ok(result) => stack.set_vec(result_begin, result),
+                                    Ok(result) => stack.set_vec(result_begin, result.into_vec()),
                                     Err(e) => {
                                         go_panic_str!(panic, e.as_str(), frame, code);
+                                        panic.as_mut().unwrap().kind = PanicKind::Ffi;
                                     }
                                 }
                             }
@@ -1095,6 +1902,10 @@ impl<'a> Fiber<'a> {
                                         + cur_func.ret_count()
                                         + cur_func.param_count()
                                         + cur_func.local_count();
+                                    let dfunc = call.frame.func_obj(objs);
+                                    stack.set_min_size(
+                                        (new_sb + dfunc.max_write_index + 1) as usize,
+                                    );
                                     stack.set_vec(new_sb, call.vec);
                                     let nframe = call.frame;
 
@@ -1105,10 +1916,10 @@ impl<'a> Fiber<'a> {
                                     let fkey = frame.func();
                                     func = &objs.functions[fkey];
                                     sb = frame.stack_base;
-                                    code = &func.code;
+                                    code = Tiering::code(tiering, fkey, func);
                                     //dbg!("deferred", &code);
                                     let index = new_sb + call_vec_len;
-                                    stack.set_vec(index, func.local_zeros.clone());
+                                    stack.set_from_slice(index, &func.local_zeros);
                                     continue;
                                 }
                                 true
@@ -1128,7 +1939,7 @@ impl<'a> Fiber<'a> {
                             let func = frame.func_obj(objs);
                             let begin = sb + func.ret_count() as OpIndex;
                             let end = begin + func.param_count() + func.local_count();
-                            stack.move_vec(begin, end);
+                            stack.clear(begin, end);
                         }
 
                         // We used to need this to make the compiler happy:
@@ -1136,8 +1947,6 @@ impl<'a> Fiber<'a> {
                         self.frames.pop();
                         frame_height -= 1;
                         if self.frames.is_empty() {
-                            dbg!(total_inst);
-
                             result = Result::End;
                             break;
                         }
@@ -1145,7 +1954,7 @@ impl<'a> Fiber<'a> {
                         sb = frame.stack_base;
                         // restore func, consts, code
                         func = &objs.functions[frame.func()];
-                        code = &func.code;
+                        code = Tiering::code(tiering, frame.func(), func);
 
                         if let Some(p) = &mut panic {
                             p.call_stack.push((frame.func(), frame.pc - 1));
@@ -1168,14 +1977,24 @@ impl<'a> Fiber<'a> {
                         let a = stack.read(inst.s0, sb, consts);
                         let b = stack.read(inst.s1, sb, consts);
                         let ok = if t.copyable() {
-                            a.data().compare_eql(b.data(), t)
+                            Ok(a.data().compare_eql(b.data(), t))
                         } else if t != ValueType::Metadata {
-                            a.eq(&b)
+                            equal_or_err(a, b, objs)
                         } else {
-                            a.as_metadata().identical(b.as_metadata(), &objs.metas)
+                            let (a, b) = (a.as_metadata(), b.as_metadata());
+                            Ok(match b.mtype_unwraped(&objs.metas) {
+                                // the case of a type switch is an interface the dynamic
+                                // type may implement
+                                MetadataType::Interface(_) => {
+                                    *a != prim_meta.none && b.iface_bindings_of(a, objs).is_some()
+                                }
+                                _ => a.identical(b, &objs.metas),
+                            })
                         };
-                        if ok {
-                            frame.pc += inst.d;
+                        match ok {
+                            Ok(true) => frame.pc += inst.d,
+                            Ok(false) => {}
+                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                         }
                     }
                     #[cfg(not(feature = "async"))]
@@ -1207,8 +2026,16 @@ impl<'a> Fiber<'a> {
                         let selector = channel::Selector::new(comms, default_offset);
 
                         drop(stack_mut_ref);
-                        let re = selector.select().await;
+                        gcc.step();
+                        let pos = (frame.func(), frame.pc - 1);
+                        ctx.goroutines.borrow_mut().suspend(
+                            self._id,
+                            Some(BlockReason::Select),
+                            pos,
+                        );
+                        let re = selector.select(gcc).await;
                         restore_stack_ref!(self, stack, stack_mut_ref);
+                        ctx.goroutines.borrow_mut().resume(self._id);
 
                         match re {
                             Ok((i, val)) => {
@@ -1258,6 +2085,7 @@ impl<'a> Fiber<'a> {
                             stack,
                             inst.d + sb,
                             inst.s1 + sb,
+                            gcc,
                         ) {
                             frame.pc += inst.s0;
                         }
@@ -1289,16 +2117,73 @@ impl<'a> Fiber<'a> {
                         );
                     }
                     Opcode::BIND_I_METHOD => {
-                        match stack.read(inst.s0, sb, consts).as_non_nil_interface() {
-                            Ok(iface) => {
+                        // the method of a nil interface is bound before it's called, like
+                        // the method value, which is where Go panics
+                        match stack.read(inst.s0, sb, consts).as_interface() {
+                            Some(iface) => {
+                                if let Some(t) = tiering {
+                                    t.record_bind(
+                                        frame.func(),
+                                        frame.pc - 1,
+                                        iface,
+                                        inst.s1 as usize,
+                                    );
+                                }
                                 match bind_iface_method(iface, inst.s1 as usize, stack, objs, gcc) {
                                     Ok(cls) => stack.set(inst.d + sb, cls),
                                     Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                                 }
                             }
-                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                            None => go_panic_str!(panic, NIL_DEREF, frame, code),
+                        }
+                    }
+                    // Leaves the receiver for the CALL_CACHED, if the interface holds the
+                    // cached type, a closure bound the generic way otherwise
+                    Opcode::BIND_I_METHOD_CACHED => {
+                        let cache = tiering.unwrap().cache(frame.func(), frame.pc - 1);
+                        match stack.read(inst.s0, sb, consts).as_interface() {
+                            Some(iface) => {
+                                let index = inst.s1 as usize;
+                                let hit = match iface {
+                                    InterfaceObj::Gos(obj, Some((meta, bindings)))
+                                        if *meta == cache.meta =>
+                                    {
+                                        match &bindings[index] {
+                                            Binding4Runtime::Struct(f, ptr_recv, None)
+                                                if *f == cache.func =>
+                                            {
+                                                Some((obj, *ptr_recv))
+                                            }
+                                            _ => None,
+                                        }
+                                    }
+                                    _ => None,
+                                };
+                                let result = match hit {
+                                    // the CALL_CACHED would take a func value for the closure
+                                    Some((obj, ptr_recv)) if obj.typ() != ValueType::Closure => {
+                                        cast_receiver(obj.copy_semantic(gcc), ptr_recv, stack, objs)
+                                    }
+                                    _ => bind_iface_method(iface, index, stack, objs, gcc),
+                                };
+                                match result {
+                                    Ok(val) => stack.set(inst.d + sb, val),
+                                    Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
+                                }
+                            }
+                            None => go_panic_str!(panic, NIL_DEREF, frame, code),
                         }
                     }
+                    // desc: local
+                    // s0: integer value
+                    Opcode::CAST_CHECKED => {
+                        let result = stack
+                            .read(inst.s0, sb, consts)
+                            .data()
+                            .cast_copyable_checked(inst.t1, inst.t0)
+                            .map(|vdata| stack.set(inst.d + sb, GosValue::new(inst.t0, vdata)));
+                        panic_if_err!(result, panic, frame, code);
+                    }
                     Opcode::CAST => {
                         let from_type = inst.t1;
                         let to_type = inst.t0;
@@ -1315,6 +2200,26 @@ impl<'a> Fiber<'a> {
                                     .read(inst.s0, sb, consts)
                                     .cast_copyable(from_type, to_type),
                             },
+                            ValueType::Complex64 | ValueType::Complex128 => {
+                                let from = stack.read(inst.s0, sb, consts);
+                                let (r, i) = match from_type {
+                                    ValueType::Complex64 => {
+                                        let c = from.as_complex64();
+                                        (c.r.into_inner() as f64, c.i.into_inner() as f64)
+                                    }
+                                    _ => {
+                                        let c = from.as_complex128();
+                                        (c.r.into_inner(), c.i.into_inner())
+                                    }
+                                };
+                                match to_type {
+                                    ValueType::Complex64 => GosValue::new_complex64(
+                                        (r as f32).into(),
+                                        (i as f32).into(),
+                                    ),
+                                    _ => GosValue::new_complex128(r.into(), i.into()),
+                                }
+                            }
                             _ if to_type.copyable() => stack
                                 .read(inst.s0, sb, consts)
                                 .cast_copyable(from_type, to_type),
@@ -1345,7 +2250,11 @@ impl<'a> Fiber<'a> {
                                     }
                                     ValueType::Uint8 => {
                                         match stack.read(inst.s0, sb, consts).as_slice::<Elem8>() {
-                                            Some(slice) => GosValue::new_string(slice.0.clone()),
+                                            Some(slice) => {
+                                                GosValue::new_string(StringObj::with_bytes(
+                                                    &slice.0.as_raw_slice::<u8>(),
+                                                ))
+                                            }
                                             None => GosValue::with_str(""),
                                         }
                                     }
@@ -1362,10 +2271,8 @@ impl<'a> Fiber<'a> {
                                 let from = stack.read(inst.s0, sb, consts).as_string();
                                 match inst.op1_as_t() {
                                     ValueType::Int32 => {
-                                        let data = from
-                                            .as_str()
-                                            .chars()
-                                            .map(|x| (x as i32).into())
+                                        let data = StringIter::new(&from.as_bytes())
+                                            .map(|(_, x)| (x as i32).into())
                                             .collect();
                                         GosValue::slice_with_data(
                                             data,
@@ -1373,9 +2280,10 @@ impl<'a> Fiber<'a> {
                                             gcc,
                                         )
                                     }
-                                    ValueType::Uint8 => {
-                                        GosValue::new_slice(from.clone(), ValueType::Uint8)
-                                    }
+                                    ValueType::Uint8 => GosValue::new_slice(
+                                        StringObj::with_bytes(&from.as_bytes()),
+                                        ValueType::Uint8,
+                                    ),
                                     _ => unreachable!(),
                                 }
                             }
@@ -1429,15 +2337,35 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::TYPE_ASSERT => {
                         let val = stack.read(inst.s0, sb, consts);
-                        match type_assert(val, cst(consts, inst.s1), gcc, &objs.metas) {
-                            Ok((val, ok)) => {
-                                stack.set(inst.d + sb, val);
-                                if inst.t1 == ValueType::FlagB {
+                        let want = cst(consts, inst.s1);
+                        match type_assert(val, want, gcc, objs) {
+                            Ok((result, ok)) => match inst.t1 {
+                                // with comma-ok
+                                ValueType::FlagB => {
+                                    stack.set(inst.d + sb, result);
                                     let inst_ex = &code[frame.pc as usize];
                                     frame.pc += 1;
                                     stack.set(inst_ex.d + sb, ok.into());
                                 }
-                            }
+                                // without, the next instruction has the static type of
+                                // the value for the message of the panic
+                                ValueType::FlagC => {
+                                    let inst_ex = &code[frame.pc as usize];
+                                    frame.pc += 1;
+                                    if ok {
+                                        stack.set(inst.d + sb, result);
+                                    } else {
+                                        let msg = type_assert_error(
+                                            val,
+                                            want.as_metadata(),
+                                            cst(consts, inst_ex.s0).as_metadata(),
+                                            objs,
+                                        );
+                                        go_panic_str!(panic, &msg, frame, code);
+                                    }
+                                }
+                                _ => stack.set(inst.d + sb, result),
+                            },
                             Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                         }
                     }
@@ -1468,8 +2396,12 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::IMPORT => {
                         let pkey = *stack.read(inst.s0, sb, consts).as_package();
-                        if objs.packages[pkey].inited() {
+                        let pkg = &objs.packages[pkey];
+                        if pkg.inited() {
                             frame.pc += inst.d
+                        } else {
+                            // the package may have been reset after a previous run
+                            pkg.restore_var_zeros(gcc);
                         }
                     }
                     Opcode::SLICE => {
@@ -1480,11 +2412,18 @@ impl<'a> Fiber<'a> {
                         let end = *stack.read(inst_ex.s0, sb, consts).as_int();
                         let max = *stack.read(inst_ex.s1, sb, consts).as_int();
                         let result = match inst.t0 {
-                            ValueType::Slice => s.caller(caller).slice_slice(s, begin, end, max),
-                            ValueType::String => GosValue::slice_string(s, begin, end, max),
-                            ValueType::Array => {
-                                GosValue::slice_array(s.clone(), begin, end, caller.get(inst.t1))
+                            ValueType::Slice => {
+                                let zero = || stack.read(inst_ex.d, sb, consts).copy_semantic(gcc);
+                                s.caller(caller).slice_slice(s, begin, end, max, &zero)
                             }
+                            ValueType::String => GosValue::slice_string(s, begin, end),
+                            ValueType::Array => GosValue::slice_array(
+                                s.clone(),
+                                begin,
+                                end,
+                                max,
+                                caller.get(inst.t1),
+                            ),
                             _ => unreachable!(),
                         };
 
@@ -1503,20 +2442,39 @@ impl<'a> Fiber<'a> {
                                     // We used to need this to make the compiler happy:
                                     //drop(frame);
                                     for (_, uv) in uvs.iter_mut() {
-                                        let r: &mut UpValueState = &mut uv.inner.borrow_mut();
-                                        if let UpValueState::Open(d) = r {
-                                            // get frame index, and add_referred_by
-                                            for i in 1..frame_height {
-                                                let index = frame_height - i;
-                                                if self.frames[index].func() == d.func {
-                                                    let upframe = &mut self.frames[index];
-                                                    d.stack = Rc::downgrade(&self.stack);
-                                                    d.stack_base = upframe.stack_base as OpIndex;
-                                                    upframe.add_referred_by(d.index, d.typ, uv);
-                                                    // if not found, the upvalue is already closed, nothing to be done
-                                                    break;
+                                        let shared = {
+                                            let r: &mut UpValueState = &mut uv.inner.borrow_mut();
+                                            match r {
+                                                UpValueState::Open(d) => {
+                                                    // get frame index, and add_referred_by
+                                                    let mut shared = None;
+                                                    for i in 1..=frame_height {
+                                                        let index = frame_height - i;
+                                                        if self.frames[index].func() == d.func {
+                                                            let upframe = &mut self.frames[index];
+                                                            // closures of the same frame share the up value,
+                                                            // so that they still do after it's closed
+                                                            shared = upframe.open_up_value(d.index);
+                                                            if shared.is_none() {
+                                                                d.stack =
+                                                                    Rc::downgrade(&self.stack);
+                                                                d.stack_base =
+                                                                    upframe.stack_base as OpIndex;
+                                                                upframe.add_referred_by(
+                                                                    d.index, d.typ, uv,
+                                                                );
+                                                            }
+                                                            // if not found, the upvalue is already closed, nothing to be done
+                                                            break;
+                                                        }
+                                                    }
+                                                    shared
                                                 }
+                                                UpValueState::Closed(_) => None,
                                             }
+                                        };
+                                        if let Some(shared) = shared {
+                                            *uv = shared;
                                         }
                                     }
                                     frame = self.frames.last_mut().unwrap();
@@ -1533,9 +2491,11 @@ impl<'a> Fiber<'a> {
 
                         let begin = inst.s0 + sb;
                         let count = inst.s1;
-                        let build_val = |m: &Meta| {
+                        // an array is as long as its type says, with the elements not
+                        // in the literal zero
+                        let build_val = |m: &Meta, len: usize| {
                             let zero_val = m.zero(&objs.metas, gcc);
-                            let mut val = vec![];
+                            let mut val = Vec::with_capacity(len);
                             let mut cur_index = -1;
                             for i in 0..count {
                                 let index = *stack.get(begin + i * 2).as_int32();
@@ -1550,26 +2510,36 @@ impl<'a> Fiber<'a> {
                                     val.push(elem);
                                 } else if gap > 0 {
                                     for _ in 0..gap {
-                                        val.push(zero_val.clone());
+                                        val.push(zero_val.copy_semantic(gcc));
                                     }
                                     val.push(elem);
                                 } else {
                                     val[cur_index as usize] = elem;
                                 }
                             }
+                            while val.len() < len {
+                                val.push(zero_val.copy_semantic(gcc));
+                            }
                             (val, zero_val.typ())
                         };
                         let new_val = match &objs.metas[md.key] {
                             MetadataType::Slice(m) => {
-                                let (val, typ) = build_val(m);
+                                let (val, typ) = build_val(m, 0);
                                 GosValue::slice_with_data(val, caller.get(typ), gcc)
                             }
-                            MetadataType::Array(m, _) => {
-                                let (val, typ) = build_val(m);
+                            MetadataType::Array(m, size) => {
+                                let (val, typ) = build_val(m, *size);
                                 GosValue::array_with_data(val, caller.get(typ), gcc)
                             }
                             MetadataType::Map(_, _) => {
-                                let map_val = GosValue::new_map(gcc);
+                                let key_err = (0..count).find_map(|i| {
+                                    check_hashable(stack.get(begin + i * 2), objs).err()
+                                });
+                                if let Some(e) = key_err {
+                                    go_panic_str!(panic, e.as_str(), frame, code);
+                                    continue;
+                                }
+                                let map_val = GosValue::map_with_capacity(count as usize, gcc);
                                 let map = map_val.as_map().unwrap();
                                 for i in 0..count {
                                     let k = stack.get(begin + i * 2).clone();
@@ -1603,7 +2573,7 @@ impl<'a> Fiber<'a> {
                         let md = stack.read(inst.s0, sb, consts).as_metadata();
                         let val = match md.mtype_unwraped(&objs.metas) {
                             MetadataType::Slice(vmeta) => {
-                                let (cap, len) = match inst.t0 {
+                                let (len, cap) = match inst.t0 {
                                     // 3 args
                                     ValueType::FlagC => {
                                         let inst_ex = &code[frame.pc as usize];
@@ -1630,7 +2600,18 @@ impl<'a> Fiber<'a> {
                                     gcc,
                                 )
                             }
-                            MetadataType::Map(_, _) => GosValue::new_map(gcc),
+                            MetadataType::Map(_, _) => {
+                                let cap = match inst.t0 {
+                                    // 2 args
+                                    ValueType::FlagB => {
+                                        *stack.read(inst.s1, sb, consts).as_int() as usize
+                                    }
+                                    // 1 arg
+                                    ValueType::FlagA => 0,
+                                    _ => unreachable!(),
+                                };
+                                GosValue::map_with_capacity(cap, gcc)
+                            }
                             #[cfg(not(feature = "async"))]
                             MetadataType::Channel(_, _) => {
                                 go_panic_no_async!(panic, frame, code);
@@ -1714,9 +2695,15 @@ impl<'a> Fiber<'a> {
                                 ArrayObj::with_raw_data(s.as_rust_slice().to_vec()),
                                 ValueType::Uint8,
                             );
-                            GosValue::slice_array(arr, 0, -1, caller.get(ValueType::Uint8)).unwrap()
+                            GosValue::slice_array(arr, 0, -1, -1, caller.get(ValueType::Uint8))
+                                .unwrap()
                         };
 
+                        if let Some(stats) = growth_stats {
+                            if b.len() > 0 && a.len() + b.len() > a.cap() {
+                                count_growth(stats, frame, 1, |c| c.slice_growths += 1);
+                            }
+                        }
                         match caller.get(inst.t1).slice_append(a, b, gcc) {
                             Ok(slice) => stack.set(inst.d + sb, slice),
                             Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
@@ -1740,26 +2727,38 @@ impl<'a> Fiber<'a> {
                     Opcode::DELETE => {
                         let map = stack.read(inst.s0, sb, consts);
                         let key = stack.read(inst.s1, sb, consts);
-                        match map.as_map() {
-                            Some(m) => m.0.delete(key),
-                            None => {}
+                        match check_hashable(key, objs) {
+                            Ok(()) => {
+                                if let Some(m) = map.as_map() {
+                                    m.0.delete(key)
+                                }
+                            }
+                            Err(e) => go_panic_str!(panic, e.as_str(), frame, code),
                         }
                     }
                     #[cfg(not(feature = "async"))]
                     Opcode::CLOSE => go_panic_no_async!(panic, frame, code),
                     #[cfg(feature = "async")]
                     Opcode::CLOSE => match stack.read(inst.s0, sb, consts).as_channel() {
-                        Some(c) => c.close(),
-                        None => {}
+                        Some(c) => {
+                            if !c.close() {
+                                go_panic_str!(panic, "close of closed channel", frame, code);
+                            }
+                        }
+                        None => go_panic_str!(panic, "close of nil channel", frame, code),
                     },
                     Opcode::PANIC => {
                         let val = stack.read(inst.s0, sb, consts).clone();
-                        go_panic!(panic, val, frame, code);
+                        go_panic!(panic, PanicKind::Explicit, val, frame, code);
                     }
                     Opcode::RECOVER => {
-                        let p = panic.take();
-                        let val = p.map_or(GosValue::new_nil(ValueType::Void), |x| {
-                            GosValue::new_interface(InterfaceObj::with_value(x.msg, None))
+                        let p = if frame.is_deferred {
+                            panic.take()
+                        } else {
+                            None
+                        };
+                        let val = p.map_or(GosValue::new_nil(ValueType::Interface), |x| {
+                            recovered_value(x.msg, prim_meta)
                         });
                         stack.set(inst.d + sb, val);
                     }
@@ -1774,13 +2773,15 @@ impl<'a> Fiber<'a> {
                             let itype = stack.read(inst.s0, sb, consts);
                             let name = stack.read(inst.s1, sb, consts);
                             let name_str = name.as_string().as_str();
-                            match self.context.ffi_factory.create(&name_str) {
-                                Ok(v) => {
-                                    let meta = itype.as_metadata().underlying(&objs.metas).clone();
-                                    GosValue::new_interface(InterfaceObj::Ffi(UnderlyingFfi::new(
-                                        v, meta,
-                                    )))
-                                }
+                            let meta = itype.as_metadata().underlying(&objs.metas).clone();
+                            match self
+                                .context
+                                .ffi_factory
+                                .create(&name_str, &meta, &objs.metas)
+                            {
+                                Ok(v) => GosValue::new_interface(InterfaceObj::Ffi(
+                                    UnderlyingFfi::new(v, meta),
+                                )),
                                 Err(e) => {
                                     go_panic_str!(panic, e.as_str(), frame, code);
                                     continue;
@@ -1794,30 +2795,66 @@ impl<'a> Fiber<'a> {
             } //yield unit
             match result {
                 Result::End => {
-                    *ctx.panic_data.borrow_mut() = panic.take();
+                    if let Some(mut p) = panic.take() {
+                        // Only the first unrecovered panic is reported, the program
+                        // stops running right after it.
+                        let mut data = ctx.panic_data.borrow_mut();
+                        if data.is_none() {
+                            p.goroutine = self._id;
+                            *data = Some(p);
+                        }
+                    }
                     break;
                 }
                 Result::Continue => {
+                    let over_limit = ctx.count_instructions(total_inst - counted_inst);
+                    counted_inst = total_inst;
+                    if over_limit || ctx.interrupted() {
+                        let call_stack = self
+                            .frames
+                            .iter()
+                            .rev()
+                            .map(|f| (f.func(), (f.pc - 1).max(0)))
+                            .collect();
+                        if over_limit {
+                            let msg = "instruction limit exceeded";
+                            ctx.stop(PanicKind::InstructionLimit, msg, call_stack, self._id);
+                        } else {
+                            ctx.interrupt(call_stack, self._id);
+                        }
+                        break;
+                    }
                     drop(stack_mut_ref);
+                    gcc.step();
                     #[cfg(feature = "async")]
-                    future::yield_now().await;
+                    {
+                        let frame = self.frames.last().unwrap();
+                        ctx.goroutines.borrow_mut().suspend(
+                            self._id,
+                            None,
+                            (frame.func(), frame.pc),
+                        );
+                        future::yield_now().await;
+                    }
                     restore_stack_ref!(self, stack, stack_mut_ref);
                 }
             };
         } //loop
 
-        collect(gcc);
+        ctx.goroutines.borrow_mut().remove(self._id);
+        gcc.step();
     }
 }
 
+// the code points that aren't valid runes convert to U+FFFD
 #[inline]
 fn char_from_u32(u: u32) -> char {
-    unsafe { char::from_u32_unchecked(u) }
+    char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER)
 }
 
 #[inline]
 fn char_from_i32(i: i32) -> char {
-    unsafe { char::from_u32_unchecked(i as u32) }
+    char_from_u32(i as u32)
 }
 
 #[inline]
@@ -1825,22 +2862,111 @@ fn deref_value(v: &GosValue, stack: &Stack, objs: &VMObjects) -> RuntimeResult<G
     v.as_non_nil_pointer()?.deref(stack, &objs.packages)
 }
 
+/// Counts a growth at the instruction `back` instructions before the pc of `frame`
+#[inline]
+fn count_growth(
+    stats: &RefCell<GrowthStats>,
+    frame: &CallFrame,
+    back: OpIndex,
+    f: impl FnOnce(&mut GrowthCount),
+) {
+    f(stats
+        .borrow_mut()
+        .entry((frame.func(), frame.pc - back))
+        .or_default());
+}
+
 #[inline(always)]
 fn cst(consts: &Vec<GosValue>, i: OpIndex) -> &GosValue {
     &consts[(-i - 1) as usize]
 }
 
+/// The value `recover` returns for a panic with `msg`. The messages of runtime
+/// errors are made without type info, which they get here, as strings.
+fn recovered_value(msg: GosValue, prim_meta: &PrimitiveMeta) -> GosValue {
+    if let Some(iface) = msg.as_interface() {
+        if let InterfaceObj::Gos(v, None) = iface as &InterfaceObj {
+            if v.typ() == ValueType::String {
+                let obj = InterfaceObj::with_value(v.clone(), Some((prim_meta.mstr, vec![])));
+                return GosValue::new_interface(obj);
+            }
+        }
+    }
+    msg
+}
+
+/// `a == b`, Go panics if they are interfaces holding values of a type that's not comparable
+fn equal_or_err(a: &GosValue, b: &GosValue, objs: &VMObjects) -> RuntimeResult<bool> {
+    value_ops::try_equal(a, b, &objs.metas)
+        .map_err(|m| format!("comparing uncomparable type {}", m.type_string(objs)).into())
+}
+
+/// Go panics on a map key it can't hash, before looking it up
+fn check_hashable(key: &GosValue, objs: &VMObjects) -> RuntimeResult<()> {
+    match value_ops::unhashable(key, &objs.metas) {
+        Some(m) => Err(format!("hash of unhashable type {}", m.type_string(objs)).into()),
+        None => Ok(()),
+    }
+}
+
+/// The message of the panic of a failed `x.(T)`, the way Go puts it, `iface` is the
+/// static type of `x`
+fn type_assert_error(val: &GosValue, want: &Meta, iface: &Meta, objs: &VMObjects) -> String {
+    let metas = &objs.metas;
+    let to_iface = matches!(want.mtype_unwraped(metas), MetadataType::Interface(_));
+    let dynamic = val.as_interface().and_then(|x| match x as &InterfaceObj {
+        InterfaceObj::Gos(_, Some((meta, _))) => Some(*meta),
+        _ => None,
+    });
+    let want_str = want.type_string(objs);
+    match dynamic {
+        Some(meta) if to_iface => {
+            let missing = want
+                .iface_bindings_or_missing(&meta, objs)
+                .err()
+                .unwrap_or("");
+            format!(
+                "interface conversion: {} is not {}: missing method {}",
+                meta.type_string(objs),
+                want_str,
+                missing
+            )
+        }
+        Some(meta) => format!(
+            "interface conversion: {} is {}, not {}",
+            iface.type_string(objs),
+            meta.type_string(objs),
+            want_str
+        ),
+        None if to_iface => format!("interface conversion: interface is nil, not {}", want_str),
+        None => format!(
+            "interface conversion: {} is nil, not {}",
+            iface.type_string(objs),
+            want_str
+        ),
+    }
+}
+
 #[inline]
 fn type_assert(
     val: &GosValue,
     want_meta: &GosValue,
     gcc: &GcContainer,
-    metas: &MetadataObjs,
+    objs: &VMObjects,
 ) -> RuntimeResult<(GosValue, bool)> {
+    let metas = &objs.metas;
     let want_meta = want_meta.as_metadata();
+    let to_iface = matches!(want_meta.mtype_unwraped(metas), MetadataType::Interface(_));
     match val.as_interface() {
         Some(iface) => match &iface as &InterfaceObj {
             InterfaceObj::Gos(v, mb) => match mb {
+                Some((meta, _)) if to_iface => match want_meta.iface_bindings_of(meta, objs) {
+                    Some(binding) => {
+                        let obj = InterfaceObj::with_value(v.clone(), Some((*meta, binding)));
+                        Ok((GosValue::new_interface(obj), true))
+                    }
+                    None => Ok((want_meta.zero(metas, gcc), false)),
+                },
                 Some((meta, _)) => {
                     if want_meta.identical(meta, metas) {
                         Ok((v.copy_semantic(gcc), true))
@@ -1896,6 +3022,9 @@ fn get_embeded(
         cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
     }
     for &i in indices.iter() {
+        if cur_val.typ() == ValueType::Pointer {
+            cur_val = cur_val.as_non_nil_pointer()?.deref(stack, pkgs)?;
+        }
         let s = &cur_val.as_struct().0;
         let v = s.borrow_fields()[i as usize].clone();
         cur_val = v;
@@ -1937,6 +3066,20 @@ fn bind_iface_method(
                 Binding4Runtime::Struct(func, ptr_recv, indices) => {
                     let obj = match indices {
                         None => obj.copy_semantic(gcc),
+                        // a pointer receiver gets the embedded field itself, not a copy
+                        Some(inds) if *ptr_recv => {
+                            let (last, path) = inds.split_last().unwrap();
+                            let mut parent = get_embeded(obj.clone(), path, stack, &objs.packages)?;
+                            if parent.typ() == ValueType::Pointer {
+                                parent = deref_value(&parent, stack, objs)?;
+                            }
+                            let field =
+                                parent.as_struct().0.borrow_fields()[*last as usize].clone();
+                            match field.typ() {
+                                ValueType::Pointer => field,
+                                _ => GosValue::new_pointer(PointerObj::StructField(parent, *last)),
+                            }
+                        }
                         Some(inds) => get_embeded(obj.clone(), inds, stack, &objs.packages)?
                             .copy_semantic(gcc),
                     };
@@ -1968,3 +3111,38 @@ fn bind_iface_method(
         }
     }
 }
+
+#[cfg(all(test, feature = "async"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fiber_pool() {
+        let mut pool = FiberPool::new(2);
+        let (mut stack, frames) = pool.take();
+        assert_eq!(stack.len(), 256);
+        stack.set_min_size(8);
+        stack.set(5, GosValue::with_str("left over"));
+        pool.put(stack, frames);
+        // the reused stack has nothing left of the goroutine before
+        let (stack, frames) = pool.take();
+        assert!(pool.free.is_empty());
+        assert_eq!(stack.get(5).typ(), ValueType::Void);
+        pool.put(stack, frames);
+
+        for _ in 0..3 {
+            pool.put(Stack::new(), vec![]);
+        }
+        assert_eq!(pool.free.len(), 2);
+
+        let mut pool = FiberPool::new(2);
+        let mut big = Stack::new();
+        big.set_min_size(MAX_POOLED_STACK_LEN + 1);
+        pool.put(big, vec![]);
+        assert!(pool.free.is_empty());
+
+        let mut pool = FiberPool::new(0);
+        pool.put(Stack::new(), vec![]);
+        assert!(pool.free.is_empty());
+    }
+}